@@ -0,0 +1,43 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use farcaster_core::crypto::ProveCrossGroupDleq;
+use farcaster_core::swap::btcxmr::KeyManager;
+
+fn dleq_proof(c: &mut Criterion) {
+    let mut key_manager = KeyManager::new([1; 32], 1).unwrap();
+    let (spend, encryption_key, proof) = key_manager.generate_proof().unwrap();
+
+    c.bench_function("dleq generate", |b| {
+        b.iter(|| key_manager.generate_proof().unwrap())
+    });
+    c.bench_function("dleq verify", |b| {
+        b.iter(|| {
+            key_manager
+                .verify_proof(&spend, &encryption_key, proof.clone())
+                .unwrap()
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = dleq_proof
+}
+criterion_main!(benches);
@@ -0,0 +1,78 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+
+use farcaster_core::consensus::{self, deserialize, serialize};
+use farcaster_core::swap::btcxmr::Deal;
+
+fn deal(c: &mut Criterion) {
+    let bytes = hex::decode(include_str!("../tests/vectors/deal.hex").trim()).unwrap();
+    let deal: Deal = deserialize(&bytes).unwrap();
+
+    c.bench_function("deal encode", |b| b.iter(|| serialize(&deal)));
+    c.bench_function("deal decode", |b| {
+        b.iter(|| deserialize::<Deal>(&bytes).unwrap())
+    });
+    let encoded = deal.to_string();
+    c.bench_function("deal parse", |b| {
+        b.iter(|| encoded.parse::<Deal>().unwrap())
+    });
+}
+
+fn psbt(c: &mut Criterion) {
+    let unsigned_tx = bitcoin::Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: bitcoin::Script::default(),
+            sequence: 0,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: 100_000,
+            script_pubkey: bitcoin::Script::default(),
+        }],
+    };
+    let psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).unwrap();
+    let bytes = bitcoin::consensus::serialize(&psbt);
+
+    c.bench_function("psbt encode", |b| {
+        b.iter(|| bitcoin::consensus::serialize(&psbt))
+    });
+    c.bench_function("psbt decode", |b| {
+        b.iter(|| bitcoin::consensus::deserialize::<PartiallySignedTransaction>(&bytes).unwrap())
+    });
+    c.bench_function("psbt canonical bytes", |b| {
+        b.iter(|| {
+            let bytes = consensus::CanonicalBytes::as_canonical_bytes(&psbt);
+            <PartiallySignedTransaction as consensus::CanonicalBytes>::from_canonical_bytes(&bytes)
+                .unwrap()
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = deal, psbt
+}
+criterion_main!(benches);
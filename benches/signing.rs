@@ -0,0 +1,130 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use bitcoin::blockdata::opcodes;
+use bitcoin::blockdata::script::Builder;
+use bitcoin::blockdata::transaction::{EcdsaSighashType, OutPoint, TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::Txid;
+
+use farcaster_core::bitcoin::segwitv0::SigningSession;
+use farcaster_core::crypto::context::{CryptoContext, NonceProvider};
+
+const INPUTS: u32 = 10;
+
+// A partial transaction spending `INPUTS` outputs locked to the public key
+fn psbt(pubkey: &PublicKey) -> PartiallySignedTransaction {
+    let script = Builder::new()
+        .push_slice(&pubkey.serialize())
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .into_script();
+    let unsigned_tx = bitcoin::Transaction {
+        version: 2,
+        lock_time: 0,
+        input: (0..INPUTS)
+            .map(|vout| TxIn {
+                previous_output: OutPoint::new(Txid::from_inner([1; 32]), vout),
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: vec![TxOut {
+            value: 100_000 * INPUTS as u64,
+            script_pubkey: script.to_v0_p2wsh(),
+        }],
+    };
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).unwrap();
+    for input in psbt.inputs.iter_mut() {
+        input.witness_utxo = Some(TxOut {
+            value: 100_000,
+            script_pubkey: script.to_v0_p2wsh(),
+        });
+        input.witness_script = Some(script.clone());
+        input.sighash_type = Some(EcdsaSighashType::All.into());
+    }
+    psbt
+}
+
+fn signing(c: &mut Criterion) {
+    let secp = Secp256k1::new();
+    let secret_key = SecretKey::from_slice(&[1; 32]).unwrap();
+    let pubkey = PublicKey::from_secret_key(&secp, &secret_key);
+    let psbt = psbt(&pubkey);
+    let ctx = CryptoContext::with_nonce_provider(NonceProvider::deterministic());
+
+    c.bench_function("sign input rfc6979", |b| {
+        b.iter(|| {
+            SigningSession::new(&psbt)
+                .sign_input(&secp, 0, &secret_key)
+                .unwrap()
+        })
+    });
+    c.bench_function("sign input deterministic", |b| {
+        b.iter(|| {
+            SigningSession::new(&psbt)
+                .sign_input(ctx.ecdsa(), 0, &secret_key)
+                .unwrap()
+        })
+    });
+    c.bench_function("sign all inputs", |b| {
+        b.iter(|| {
+            SigningSession::new(&psbt)
+                .sign_all(&secp, &secret_key)
+                .unwrap()
+        })
+    });
+
+    let signatures: Vec<_> = SigningSession::new(&psbt)
+        .sign_all(&secp, &secret_key)
+        .unwrap()
+        .into_iter()
+        .map(|sig| (sig, pubkey))
+        .collect();
+    c.bench_function("verify all inputs", |b| {
+        b.iter(|| {
+            SigningSession::new(&psbt)
+                .verify_all(&secp, &signatures)
+                .unwrap()
+        })
+    });
+    c.bench_function("combine signed psbt", |b| {
+        let mut signed = psbt.clone();
+        for (input, (sig, pubkey)) in signed.inputs.iter_mut().zip(signatures.iter()) {
+            input.partial_sigs.insert(
+                bitcoin::PublicKey::new(*pubkey),
+                bitcoin::EcdsaSig::sighash_all(*sig),
+            );
+        }
+        b.iter_batched(
+            || psbt.clone(),
+            |mut unsigned| unsigned.combine(signed.clone()).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = signing
+}
+criterion_main!(benches);
@@ -0,0 +1,345 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Random generators of the protocol types for property-based testing, available with the
+//! `test-utils` feature. Downstream crates can use the [`Arbitrary`] implementations to fuzz their
+//! own handling of deals, messages, and fee strategies with [`quickcheck`].
+//!
+//! Generic types are implemented for their concrete Bitcoin-Monero instantiation defined in
+//! [`crate::swap::btcxmr`].
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use inet2_addr::InetSocketAddr;
+use quickcheck::{Arbitrary, Gen};
+
+use crate::bitcoin::fee::SatPerKvB;
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::blockchain::{AccordantAmount, Blockchain, FeePolitics, FeeStrategy, Network};
+use crate::crypto::{KeccakCommitment, SharedKeyId, TaggedElement};
+use crate::protocol::message::{Abort, CommitAliceParameters, CommitBobParameters};
+use crate::protocol::ArbitratingParameters;
+use crate::role::{SwapRole, TradeRole};
+use crate::swap::SwapId;
+use crate::trade::{Deal, DealExpiry, DealId, DealParameters, DealTerms, Version};
+use crate::Uuid;
+
+fn choose<T: Copy>(g: &mut Gen, values: &[T]) -> T {
+    *g.choose(values).expect("values is not empty")
+}
+
+impl Arbitrary for Uuid {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self(uuid::Uuid::from_bytes(Arbitrary::arbitrary(g)))
+    }
+}
+
+impl Arbitrary for DealId {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self(Arbitrary::arbitrary(g))
+    }
+}
+
+impl Arbitrary for SwapId {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self(Arbitrary::arbitrary(g))
+    }
+}
+
+impl Arbitrary for Network {
+    fn arbitrary(g: &mut Gen) -> Self {
+        choose(g, &[Network::Mainnet, Network::Testnet, Network::Local])
+    }
+}
+
+impl Arbitrary for Blockchain {
+    fn arbitrary(g: &mut Gen) -> Self {
+        choose(g, &[Blockchain::Bitcoin, Blockchain::Monero])
+    }
+}
+
+impl Arbitrary for SwapRole {
+    fn arbitrary(g: &mut Gen) -> Self {
+        choose(g, &[SwapRole::Alice, SwapRole::Bob])
+    }
+}
+
+impl Arbitrary for FeePolitics {
+    fn arbitrary(g: &mut Gen) -> Self {
+        choose(
+            g,
+            &[
+                FeePolitics::MakerPays,
+                FeePolitics::TakerPays,
+                FeePolitics::Split,
+            ],
+        )
+    }
+}
+
+impl Arbitrary for TradeRole {
+    fn arbitrary(g: &mut Gen) -> Self {
+        choose(g, &[TradeRole::Maker, TradeRole::Taker])
+    }
+}
+
+impl Arbitrary for CSVTimelock {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self::new(Arbitrary::arbitrary(g))
+    }
+}
+
+impl Arbitrary for SatPerKvB {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self::from_sat(Arbitrary::arbitrary(g))
+    }
+}
+
+impl Arbitrary for AccordantAmount {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self::from_atomic_units(Arbitrary::arbitrary(g))
+    }
+}
+
+impl<T> Arbitrary for FeeStrategy<T>
+where
+    T: Arbitrary,
+{
+    #[cfg(not(feature = "fee_range"))]
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self::Fixed(T::arbitrary(g))
+    }
+
+    #[cfg(feature = "fee_range")]
+    fn arbitrary(g: &mut Gen) -> Self {
+        if bool::arbitrary(g) {
+            Self::Fixed(T::arbitrary(g))
+        } else {
+            Self::Range {
+                min_inc: T::arbitrary(g),
+                max_inc: T::arbitrary(g),
+            }
+        }
+    }
+}
+
+impl Arbitrary for KeccakCommitment {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self::from(<[u8; 32]>::arbitrary(g))
+    }
+}
+
+impl Arbitrary for SharedKeyId {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self::new(Arbitrary::arbitrary(g))
+    }
+}
+
+impl<T, E> Arbitrary for TaggedElement<T, E>
+where
+    T: Arbitrary,
+    E: Arbitrary,
+{
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self::new(T::arbitrary(g), E::arbitrary(g))
+    }
+}
+
+impl Arbitrary for Version {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self::new(Arbitrary::arbitrary(g))
+    }
+}
+
+fn arbitrary_bitcoin_amount(g: &mut Gen) -> bitcoin::Amount {
+    bitcoin::Amount::from_sat(u64::arbitrary(g) % (bitcoin::Amount::MAX_MONEY.as_sat() + 1))
+}
+
+fn arbitrary_public_key(g: &mut Gen) -> PublicKey {
+    loop {
+        if let Ok(secret) = SecretKey::from_slice(&<[u8; 32]>::arbitrary(g)) {
+            return PublicKey::from_secret_key(&Secp256k1::new(), &secret);
+        }
+    }
+}
+
+impl Arbitrary for DealParameters<bitcoin::Amount, AccordantAmount, CSVTimelock, SatPerKvB> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self {
+            uuid: Arbitrary::arbitrary(g),
+            network: Arbitrary::arbitrary(g),
+            arbitrating_blockchain: Arbitrary::arbitrary(g),
+            accordant_blockchain: Arbitrary::arbitrary(g),
+            arbitrating_amount: arbitrary_bitcoin_amount(g),
+            accordant_amount: Arbitrary::arbitrary(g),
+            cancel_timelock: Arbitrary::arbitrary(g),
+            punish_timelock: Arbitrary::arbitrary(g),
+            fee_strategy: Arbitrary::arbitrary(g),
+            maker_role: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for DealExpiry {
+    fn arbitrary(g: &mut Gen) -> Self {
+        if bool::arbitrary(g) {
+            Self::Height(Arbitrary::arbitrary(g))
+        } else {
+            Self::Timestamp(Arbitrary::arbitrary(g))
+        }
+    }
+}
+
+impl Arbitrary for DealTerms<AccordantAmount> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self {
+            min_accordant_amount: Arbitrary::arbitrary(g),
+            expiry: Arbitrary::arbitrary(g),
+            fee_politics: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for Deal<bitcoin::Amount, AccordantAmount, CSVTimelock, SatPerKvB> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let version: Version = Arbitrary::arbitrary(g);
+        // Terms are only encoded since version 2
+        let terms = match version.to_u16() {
+            v if v >= 2 => Arbitrary::arbitrary(g),
+            _ => DealTerms::default(),
+        };
+        Self {
+            version,
+            terms,
+            parameters: Arbitrary::arbitrary(g),
+            node_id: arbitrary_public_key(g),
+            peer_address: InetSocketAddr::socket(
+                IpAddr::V4(Ipv4Addr::from(u32::arbitrary(g))),
+                // A zero port does not survive the uniform encoding of the address
+                u16::arbitrary(g).max(1),
+            ),
+        }
+    }
+}
+
+impl Arbitrary for ArbitratingParameters<bitcoin::Amount, CSVTimelock, SatPerKvB> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self {
+            arbitrating_amount: arbitrary_bitcoin_amount(g),
+            cancel_timelock: Arbitrary::arbitrary(g),
+            punish_timelock: Arbitrary::arbitrary(g),
+            fee_strategy: Arbitrary::arbitrary(g),
+            fee_politics: Arbitrary::arbitrary(g),
+            maker_role: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl<C> Arbitrary for CommitAliceParameters<C>
+where
+    C: Arbitrary,
+{
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self {
+            swap_id: Arbitrary::arbitrary(g),
+            buy: C::arbitrary(g),
+            cancel: C::arbitrary(g),
+            refund: C::arbitrary(g),
+            punish: C::arbitrary(g),
+            adaptor: C::arbitrary(g),
+            extra_arbitrating_keys: Arbitrary::arbitrary(g),
+            arbitrating_shared_keys: Arbitrary::arbitrary(g),
+            spend: C::arbitrary(g),
+            extra_accordant_keys: Arbitrary::arbitrary(g),
+            accordant_shared_keys: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl<C> Arbitrary for CommitBobParameters<C>
+where
+    C: Arbitrary,
+{
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self {
+            swap_id: Arbitrary::arbitrary(g),
+            buy: C::arbitrary(g),
+            cancel: C::arbitrary(g),
+            refund: C::arbitrary(g),
+            adaptor: C::arbitrary(g),
+            extra_arbitrating_keys: Arbitrary::arbitrary(g),
+            arbitrating_shared_keys: Arbitrary::arbitrary(g),
+            spend: C::arbitrary(g),
+            extra_accordant_keys: Arbitrary::arbitrary(g),
+            accordant_shared_keys: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for Abort {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self {
+            swap_id: Arbitrary::arbitrary(g),
+            error_body: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::consensus::{deserialize, serialize, Decodable, Encodable};
+    use crate::swap::btcxmr;
+
+    use quickcheck::quickcheck;
+
+    fn roundtrip<T: Encodable + Decodable>(value: T) -> bool {
+        let bytes = serialize(&value);
+        deserialize::<T>(&bytes).map_or(false, |decoded| serialize(&decoded) == bytes)
+    }
+
+    quickcheck! {
+        fn roundtrip_deal_parameters(params: btcxmr::DealParameters) -> bool {
+            roundtrip(params)
+        }
+
+        fn roundtrip_deal(deal: btcxmr::Deal) -> bool {
+            roundtrip(deal)
+        }
+
+        fn roundtrip_fee_strategy(strategy: FeeStrategy<SatPerKvB>) -> bool {
+            roundtrip(strategy)
+        }
+
+        fn roundtrip_commit_alice(msg: CommitAliceParameters<KeccakCommitment>) -> bool {
+            roundtrip(msg)
+        }
+
+        fn roundtrip_commit_bob(msg: CommitBobParameters<KeccakCommitment>) -> bool {
+            roundtrip(msg)
+        }
+
+        fn roundtrip_abort(msg: Abort) -> bool {
+            roundtrip(msg)
+        }
+
+        fn roundtrip_accordant_amount(amount: AccordantAmount) -> bool {
+            roundtrip(amount)
+        }
+    }
+}
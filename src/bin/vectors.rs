@@ -0,0 +1,42 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Write the canonical test vectors of the Bitcoin-Monero swap to JSON files.
+//!
+//! ```text
+//! farcaster-vectors [DIR]
+//! ```
+//!
+//! The files are written in `DIR`, `vectors` by default.
+
+use farcaster_core::swap::btcxmr::vectors;
+
+use std::process;
+
+fn main() {
+    let dir = std::env::args().nth(1).unwrap_or_else(|| "vectors".into());
+    match vectors::write_to(&dir) {
+        Ok(paths) => {
+            for path in paths {
+                println!("{}", path.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to write the vectors in {}: {}", dir, e);
+            process::exit(1);
+        }
+    }
+}
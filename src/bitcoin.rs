@@ -26,17 +26,27 @@ use ecdsa_fun::adaptor::EncryptedSignature;
 use crate::blockchain::Network;
 use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
 
-pub(crate) mod address;
+pub mod address;
 pub(crate) mod amount;
+pub mod anchor;
+pub mod cashaddr;
 pub mod fee;
+pub mod hardware;
+pub mod metadata;
+pub mod network;
 #[cfg(feature = "experimental")]
 #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
 pub mod segwitv0;
+pub mod sighash;
+pub mod tag;
 #[cfg(all(feature = "experimental", feature = "taproot"))]
 #[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
 pub mod taproot;
+#[cfg(test)]
+pub(crate) mod test_helpers;
 pub mod timelock;
 pub mod transaction;
+pub mod truc;
 
 /// Bitcoin blockchain using SegWit version 0 transaction outputs and ECDSA cryptography. This type
 /// is experimental because it uses ECDSA Adaptor Signatures that are not ready for production.
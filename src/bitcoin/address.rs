@@ -14,11 +14,48 @@
 // License along with this library; if not, write to the Free Software
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
 
+//! Bitcoin addresses used as swap destinations and their validation. The bitcoins of a swap can
+//! only be paid to the SegWit outputs the transactions are sized for: P2WPKH, P2WSH, and P2TR.
+
 use crate::consensus::{self, CanonicalBytes};
-use bitcoin::Address;
+use bitcoin::util::address::AddressType;
+use bitcoin::{Address, Network};
+use thiserror::Error;
 
 use std::str::{self, FromStr};
 
+/// Address types accepted as swap destinations.
+pub const SUPPORTED_DESTINATION_TYPES: [AddressType; 3] =
+    [AddressType::P2wpkh, AddressType::P2wsh, AddressType::P2tr];
+
+/// Errors when validating a destination address.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DestinationError {
+    /// The address is not valid for the swap network.
+    #[error("Address {address} is not valid for network {network}")]
+    NetworkMismatch { address: Address, network: Network },
+    /// The address type is not supported as a swap destination.
+    #[error("Address {0} is not of a supported type")]
+    UnsupportedType(Address),
+}
+
+/// Validate that the address belongs to the network and is one of the
+/// [`SUPPORTED_DESTINATION_TYPES`].
+pub fn validate_destination(address: &Address, network: Network) -> Result<(), DestinationError> {
+    address
+        .is_valid_for_network(network)
+        .then(|| ())
+        .ok_or_else(|| DestinationError::NetworkMismatch {
+            address: address.clone(),
+            network,
+        })?;
+    address
+        .address_type()
+        .filter(|address_type| SUPPORTED_DESTINATION_TYPES.contains(address_type))
+        .map(|_| ())
+        .ok_or_else(|| DestinationError::UnsupportedType(address.clone()))
+}
+
 impl CanonicalBytes for Address {
     fn as_canonical_bytes(&self) -> Vec<u8> {
         self.to_string().into()
@@ -32,3 +69,44 @@ impl CanonicalBytes for Address {
             .map_err(consensus::Error::new)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use bitcoin::util::taproot::TapBranchHash;
+    use bitcoin::XOnlyPublicKey;
+
+    #[test]
+    fn validate_destination_addresses() {
+        let secp = Secp256k1::new();
+        let key = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let pubkey = bitcoin::PublicKey::new(key);
+
+        let p2wpkh = Address::p2wpkh(&pubkey, Network::Testnet).unwrap();
+        let p2wsh = Address::p2wsh(&p2wpkh.script_pubkey(), Network::Testnet);
+        let p2tr = Address::p2tr(
+            &secp,
+            XOnlyPublicKey::from(key),
+            None::<TapBranchHash>,
+            Network::Testnet,
+        );
+        for address in [&p2wpkh, &p2wsh, &p2tr] {
+            assert!(validate_destination(address, Network::Testnet).is_ok());
+            assert_eq!(
+                validate_destination(address, Network::Bitcoin),
+                Err(DestinationError::NetworkMismatch {
+                    address: address.clone(),
+                    network: Network::Bitcoin,
+                })
+            );
+        }
+
+        let p2pkh = Address::p2pkh(&pubkey, Network::Testnet);
+        assert_eq!(
+            validate_destination(&p2pkh, Network::Testnet),
+            Err(DestinationError::UnsupportedType(p2pkh))
+        );
+    }
+}
@@ -0,0 +1,145 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Anchor outputs, tiny outputs added to a transaction only to let a party bump its fee with a
+//! child-pays-for-parent (CPFP) transaction. Anchors are never consumed by the swap transactions.
+
+use bitcoin::blockdata::opcodes;
+use bitcoin::blockdata::script::{Builder, Instruction, Script};
+use bitcoin::blockdata::transaction::TxOut;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Amount;
+
+/// Value of an ephemeral anchor output, the dust limit of pay-to-anchor outputs.
+pub const EPHEMERAL_ANCHOR_VALUE: u64 = 240;
+
+/// Value of a keyed anchor output, the dust limit of P2WSH outputs.
+pub const KEYED_ANCHOR_VALUE: u64 = 330;
+
+/// Relative timelock after which anyone can spend a keyed anchor, so anchors are eventually
+/// swept from the UTXO set.
+pub const KEYED_ANCHOR_TIMELOCK: i64 = 16;
+
+/// The witness program of a pay-to-anchor output.
+const PAY_TO_ANCHOR_PROGRAM: [u8; 2] = [0x4e, 0x73];
+
+/// An anchor output for fee bumping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnchorOutput {
+    /// A keyless pay-to-anchor output anyone can spend, so either party can bump the fee.
+    Ephemeral,
+    /// A P2WSH output spendable by the key, or by anyone after [`KEYED_ANCHOR_TIMELOCK`] blocks.
+    Keyed(PublicKey),
+}
+
+impl AnchorOutput {
+    /// Return the witness script of the anchor, if any.
+    pub fn witness_script(&self) -> Option<Script> {
+        match self {
+            Self::Ephemeral => None,
+            Self::Keyed(pubkey) => Some(
+                Builder::new()
+                    .push_slice(&pubkey.serialize())
+                    .push_opcode(opcodes::all::OP_CHECKSIG)
+                    .push_opcode(opcodes::all::OP_IFDUP)
+                    .push_opcode(opcodes::all::OP_NOTIF)
+                    .push_int(KEYED_ANCHOR_TIMELOCK)
+                    .push_opcode(opcodes::all::OP_CSV)
+                    .push_opcode(opcodes::all::OP_ENDIF)
+                    .into_script(),
+            ),
+        }
+    }
+
+    /// Return the output script of the anchor.
+    pub fn script_pubkey(&self) -> Script {
+        match self.witness_script() {
+            None => Builder::new()
+                .push_int(1)
+                .push_slice(&PAY_TO_ANCHOR_PROGRAM)
+                .into_script(),
+            Some(script) => script.to_v0_p2wsh(),
+        }
+    }
+
+    /// Return the value of the anchor output.
+    pub fn amount(&self) -> Amount {
+        match self {
+            Self::Ephemeral => Amount::from_sat(EPHEMERAL_ANCHOR_VALUE),
+            Self::Keyed(_) => Amount::from_sat(KEYED_ANCHOR_VALUE),
+        }
+    }
+
+    /// Return the transaction output of the anchor.
+    pub fn tx_out(&self) -> TxOut {
+        TxOut {
+            value: self.amount().as_sat(),
+            script_pubkey: self.script_pubkey(),
+        }
+    }
+
+    /// Return the anchor the output pays to, if any. Keyed anchors are only recognized with their
+    /// witness script.
+    pub fn from_output(txout: &TxOut, witness_script: Option<&Script>) -> Option<Self> {
+        let anchor = match witness_script {
+            None => Self::Ephemeral,
+            Some(script) => match script.instructions().next() {
+                Some(Ok(Instruction::PushBytes(bytes))) => {
+                    Self::Keyed(PublicKey::from_slice(bytes).ok()?)
+                }
+                _ => return None,
+            },
+        };
+        (anchor.tx_out() == *txout && anchor.witness_script().as_ref() == witness_script)
+            .then(|| anchor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    #[test]
+    fn recognize_anchor_outputs() {
+        let ephemeral = AnchorOutput::Ephemeral;
+        assert_eq!(
+            ephemeral.script_pubkey().as_bytes(),
+            &[0x51, 0x02, 0x4e, 0x73]
+        );
+        assert_eq!(
+            AnchorOutput::from_output(&ephemeral.tx_out(), None),
+            Some(ephemeral)
+        );
+
+        let secret = SecretKey::from_slice(&[1; 32]).unwrap();
+        let keyed = AnchorOutput::Keyed(PublicKey::from_secret_key(&Secp256k1::new(), &secret));
+        let script = keyed.witness_script().unwrap();
+        assert!(keyed.script_pubkey().is_v0_p2wsh());
+        assert_eq!(
+            AnchorOutput::from_output(&keyed.tx_out(), Some(&script)),
+            Some(keyed)
+        );
+        // Keyed anchors need their witness script
+        assert_eq!(AnchorOutput::from_output(&keyed.tx_out(), None), None);
+
+        // Outputs holding more than an anchor are not anchors
+        let mut txout = ephemeral.tx_out();
+        txout.value += 1;
+        assert_eq!(AnchorOutput::from_output(&txout, None), None);
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! [CashAddr][cashaddr] encoding of Bitcoin Cash addresses. Bitcoin Cash has no SegWit, so swap
+//! outputs are paid to legacy `P2PKH` and `P2SH` scripts and displayed with this format.
+//!
+//! [cashaddr]: https://github.com/bitcoincashorg/bitcoincash.org/blob/master/spec/cashaddr.md
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::hashes::Hash;
+use bitcoin::{PubkeyHash, ScriptHash};
+use thiserror::Error;
+
+use crate::blockchain::Network;
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Errors when parsing a CashAddr address.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The prefix is missing or unknown
+    #[error("The prefix is missing or unknown")]
+    InvalidPrefix,
+    /// The address mixes upper and lower case characters
+    #[error("The address mixes upper and lower case characters")]
+    MixedCase,
+    /// The address contains a character outside of the charset
+    #[error("The address contains a character outside of the charset")]
+    InvalidCharacter,
+    /// The checksum does not match
+    #[error("The checksum does not match")]
+    InvalidChecksum,
+    /// The payload is not a 160 bits P2PKH or P2SH hash
+    #[error("The payload is not a 160 bits P2PKH or P2SH hash")]
+    InvalidPayload,
+}
+
+/// The type of script an address pays to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressType {
+    /// Pay to public key hash.
+    P2pkh,
+    /// Pay to script hash.
+    P2sh,
+}
+
+/// A Bitcoin Cash address with its network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CashAddr {
+    /// The network of the address, selects the prefix.
+    pub network: Network,
+    /// The type of script paid to.
+    pub address_type: AddressType,
+    /// The 160 bits hash of the public key or the script.
+    pub hash: [u8; 20],
+}
+
+impl CashAddr {
+    /// Create the address paying to `script` hash.
+    pub fn p2sh(script: &Script, network: Network) -> Self {
+        Self {
+            network,
+            address_type: AddressType::P2sh,
+            hash: ScriptHash::hash(script.as_bytes()).into_inner(),
+        }
+    }
+
+    /// Parse the address paid by a `P2PKH` or `P2SH` output script, return `None` for any other
+    /// script.
+    pub fn from_script(script: &Script, network: Network) -> Option<Self> {
+        let (address_type, hash) = if script.is_p2pkh() {
+            (AddressType::P2pkh, &script.as_bytes()[3..23])
+        } else if script.is_p2sh() {
+            (AddressType::P2sh, &script.as_bytes()[2..22])
+        } else {
+            return None;
+        };
+        let mut bytes = [0u8; 20];
+        bytes.copy_from_slice(hash);
+        Some(Self {
+            network,
+            address_type,
+            hash: bytes,
+        })
+    }
+
+    /// Return the output script paying to this address.
+    pub fn script_pubkey(&self) -> Script {
+        match self.address_type {
+            AddressType::P2pkh => Script::new_p2pkh(&PubkeyHash::from_inner(self.hash)),
+            AddressType::P2sh => Script::new_p2sh(&ScriptHash::from_inner(self.hash)),
+        }
+    }
+
+    /// Return the human readable prefix used on `network`.
+    pub fn prefix(network: Network) -> &'static str {
+        match network {
+            Network::Mainnet => "bitcoincash",
+            Network::Testnet => "bchtest",
+            Network::Local => "bchreg",
+        }
+    }
+}
+
+fn polymod(values: impl Iterator<Item = u8>) -> u64 {
+    let mut c: u64 = 1;
+    for d in values {
+        let c0 = (c >> 35) as u8;
+        c = ((c & 0x07_ffff_ffff) << 5) ^ u64::from(d);
+        for (bit, generator) in [
+            0x98_f2bc_8e61,
+            0x79_b76d_99e2,
+            0xf3_3e5f_b3c4,
+            0xae_2eab_e2a8,
+            0x1e_4f43_e470,
+        ]
+        .iter()
+        .enumerate()
+        {
+            if c0 & (1 << bit) != 0 {
+                c ^= generator;
+            }
+        }
+    }
+    c ^ 1
+}
+
+fn checksum_input<'a>(prefix: &'a str, payload: &'a [u8]) -> impl Iterator<Item = u8> + 'a {
+    prefix
+        .bytes()
+        .map(|b| b & 0x1f)
+        .chain(std::iter::once(0))
+        .chain(payload.iter().copied())
+}
+
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let max = (1 << to) - 1;
+    let mut ret = Vec::new();
+    for value in data {
+        acc = (acc << from) | u32::from(*value);
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            ret.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            ret.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max) != 0 {
+        return None;
+    }
+    Some(ret)
+}
+
+impl fmt::Display for CashAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let prefix = Self::prefix(self.network);
+        let version = match self.address_type {
+            AddressType::P2pkh => 0u8,
+            AddressType::P2sh => 8u8,
+        };
+        let mut data = vec![version];
+        data.extend_from_slice(&self.hash);
+        let mut payload = convert_bits(&data, 8, 5, true).expect("padding is enabled");
+        let checksum = polymod(checksum_input(prefix, &payload).chain([0u8; 8].iter().copied()));
+        payload.extend((0..8).map(|i| ((checksum >> (5 * (7 - i))) & 0x1f) as u8));
+        let encoded: String = payload
+            .iter()
+            .map(|d| CHARSET[*d as usize] as char)
+            .collect();
+        write!(f, "{}:{}", prefix, encoded)
+    }
+}
+
+impl FromStr for CashAddr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(Error::MixedCase);
+        }
+        let s = s.to_ascii_lowercase();
+        let (prefix, encoded) = s.split_once(':').ok_or(Error::InvalidPrefix)?;
+        let network = [Network::Mainnet, Network::Testnet, Network::Local]
+            .into_iter()
+            .find(|n| Self::prefix(*n) == prefix)
+            .ok_or(Error::InvalidPrefix)?;
+
+        let payload = encoded
+            .bytes()
+            .map(|b| {
+                CHARSET
+                    .iter()
+                    .position(|c| *c == b)
+                    .map(|p| p as u8)
+                    .ok_or(Error::InvalidCharacter)
+            })
+            .collect::<Result<Vec<u8>, Error>>()?;
+        if payload.len() < 8 {
+            return Err(Error::InvalidPayload);
+        }
+        (polymod(checksum_input(prefix, &payload)) == 0)
+            .then(|| ())
+            .ok_or(Error::InvalidChecksum)?;
+
+        let data = convert_bits(&payload[..payload.len() - 8], 5, 8, false)
+            .ok_or(Error::InvalidPayload)?;
+        let address_type = match data.first() {
+            Some(0) => AddressType::P2pkh,
+            Some(8) => AddressType::P2sh,
+            _ => return Err(Error::InvalidPayload),
+        };
+        if data.len() != 21 {
+            return Err(Error::InvalidPayload);
+        }
+        let mut hash = [0u8; 20];
+        hash.copy_from_slice(&data[1..]);
+        Ok(Self {
+            network,
+            address_type,
+            hash,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "76a04053bda0a88bda5177b86a15c3b29f559873";
+
+    fn hash() -> [u8; 20] {
+        let mut hash = [0u8; 20];
+        hash.copy_from_slice(&hex::decode(HASH).unwrap());
+        hash
+    }
+
+    #[test]
+    fn encode_and_parse_addresses() {
+        let vectors = [
+            (
+                "bitcoincash:qpm2qsznhks23z7629mms6s4cwef74vcwvy22gdx6a",
+                Network::Mainnet,
+                AddressType::P2pkh,
+            ),
+            (
+                "bitcoincash:ppm2qsznhks23z7629mms6s4cwef74vcwvn0h829pq",
+                Network::Mainnet,
+                AddressType::P2sh,
+            ),
+            (
+                "bchtest:qpm2qsznhks23z7629mms6s4cwef74vcwvqcw003ap",
+                Network::Testnet,
+                AddressType::P2pkh,
+            ),
+        ];
+        for (s, network, address_type) in vectors {
+            let address = CashAddr {
+                network,
+                address_type,
+                hash: hash(),
+            };
+            assert_eq!(address.to_string(), s);
+            assert_eq!(CashAddr::from_str(s), Ok(address));
+            assert_eq!(CashAddr::from_str(&s.to_uppercase()), Ok(address));
+            assert_eq!(
+                CashAddr::from_script(&address.script_pubkey(), network),
+                Some(address)
+            );
+        }
+    }
+
+    #[test]
+    fn reject_invalid_addresses() {
+        let valid = "bitcoincash:qpm2qsznhks23z7629mms6s4cwef74vcwvy22gdx6a";
+        assert_eq!(
+            CashAddr::from_str("bitcoincash:qpm2qsznhks23z7629mms6s4cwef74vcwvy22gdx6c"),
+            Err(Error::InvalidChecksum)
+        );
+        assert_eq!(
+            CashAddr::from_str(&valid.replace("bitcoincash", "bchreg")),
+            Err(Error::InvalidChecksum)
+        );
+        assert_eq!(
+            CashAddr::from_str("litecoin:qpm2qsznhks23z7629mms6s4cwef74vcwvy22gdx6a"),
+            Err(Error::InvalidPrefix)
+        );
+        assert_eq!(
+            CashAddr::from_str("bitcoincash:Qpm2qsznhks23z7629mms6s4cwef74vcwvy22gdx6a"),
+            Err(Error::MixedCase)
+        );
+        assert_eq!(
+            CashAddr::from_str("bitcoincash:bpm2qsznhks23z7629mms6s4cwef74vcwvy22gdx6a"),
+            Err(Error::InvalidCharacter)
+        );
+    }
+}
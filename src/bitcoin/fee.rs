@@ -34,14 +34,14 @@
 //! ```
 
 use bitcoin::blockdata::transaction::TxOut;
-use bitcoin::blockdata::witness::Witness;
 use bitcoin::util::amount::Denomination;
 use bitcoin::util::psbt::PartiallySignedTransaction;
 use bitcoin::Amount;
 
 use crate::bitcoin::transaction;
-use crate::blockchain::{Fee, FeePriority, FeeStrategy, FeeStrategyError};
+use crate::blockchain::{FeePolitics, FeePriority, FeeStrategy, FeeStrategyError};
 use crate::consensus::{self, CanonicalBytes};
+use crate::role::SwapRole;
 
 use std::str::FromStr;
 
@@ -142,7 +142,9 @@ impl FromStr for SatPerKvB {
     }
 }
 
-fn get_available_input_sat(tx: &PartiallySignedTransaction) -> Result<Amount, FeeStrategyError> {
+pub(crate) fn get_available_input_sat(
+    tx: &PartiallySignedTransaction,
+) -> Result<Amount, FeeStrategyError> {
     // Get the available amount on the transaction
     let inputs: Result<Vec<TxOut>, FeeStrategyError> = tx
         .inputs
@@ -154,105 +156,179 @@ fn get_available_input_sat(tx: &PartiallySignedTransaction) -> Result<Amount, Fe
                 .ok_or(FeeStrategyError::MissingInputsMetadata)
         })
         .collect();
-    Ok(Amount::from_sat(
-        inputs?.iter().map(|txout| txout.value).sum(),
-    ))
+    sum_amounts(inputs?.iter().map(|txout| txout.value))
 }
 
-fn upper_bound_simulated_witness() -> Witness {
-    // Simulate 2 signatures for Alice and Bob, and a script
-    //   buy/cancel script is 70 bytes
-    //   refund/punish script is 111 bytes plus a true/false opcode
-    //
-    // We simulate the biggest of the two scripts
-    Witness::from_vec(vec![vec![0; 71], vec![0; 71], vec![0], vec![0; 111]])
+/// Sum satoshi values, failing instead of overflowing.
+fn sum_amounts(mut values: impl Iterator<Item = u64>) -> Result<Amount, FeeStrategyError> {
+    values.try_fold(Amount::ZERO, |acc, value| {
+        acc.checked_add(Amount::from_sat(value))
+            .ok_or(FeeStrategyError::AmountOverflow)
+    })
 }
 
-impl Fee for PartiallySignedTransaction {
-    type FeeUnit = SatPerKvB;
-
-    type Amount = Amount;
-
-    /// Calculates and sets the fees on the given transaction and return the fees set
-    #[allow(unused_variables)]
-    fn set_fee(
-        &mut self,
-        strategy: &FeeStrategy<SatPerKvB>,
-        politic: FeePriority,
-    ) -> Result<Self::Amount, FeeStrategyError> {
-        if self.unsigned_tx.output.len() != 1 {
-            return Err(FeeStrategyError::new(
-                transaction::Error::MultiUTXOUnsuported,
-            ));
-        }
+/// Compute the fee amount for a rate in satoshi per kilo virtual bytes and a transaction size,
+/// failing if the result is not a valid amount.
+fn fee_amount(fee_rate: f64, vsize: f64) -> Result<Amount, FeeStrategyError> {
+    Amount::from_float_in((fee_rate / 1000f64 * vsize).round(), Denomination::Satoshi)
+        .map_err(|_| FeeStrategyError::AmountOverflow)
+}
 
-        let input_sum = get_available_input_sat(self)?;
-
-        // simulate witness data
-        self.unsigned_tx.input[0].witness = upper_bound_simulated_witness();
-        let vsize = self.unsigned_tx.vsize() as f64;
-        // remove witness
-        self.unsigned_tx.input[0].witness = Witness::new();
-
-        let fee_rate = match strategy {
-            FeeStrategy::Fixed(sat_per_kvb) => sat_per_kvb
-                .as_native_unit()
-                .to_float_in(Denomination::Satoshi),
-            #[cfg(feature = "fee_range")]
-            FeeStrategy::Range { min_inc, max_inc } => match politic {
-                FeePriority::Low => min_inc.as_native_unit().to_float_in(Denomination::Satoshi),
-                FeePriority::High => max_inc.as_native_unit().to_float_in(Denomination::Satoshi),
-            },
-        };
-        let fee_amount = fee_rate / 1000f64 * vsize;
-        let fee_amount = Amount::from_sat(fee_amount.round() as u64);
+/// Return the fee for a transaction of `vsize` virtual bytes at the lowest rate allowed by the
+/// strategy. Both swap participants must agree on the estimation, thus the rate cannot depend on
+/// a local fee priority.
+pub(crate) fn estimate_fee(
+    strategy: &FeeStrategy<SatPerKvB>,
+    vsize: usize,
+) -> Result<Amount, FeeStrategyError> {
+    let (min_inc, _) = strategy.bounds();
+    fee_amount(
+        min_inc.as_native_unit().to_float_in(Denomination::Satoshi),
+        vsize as f64,
+    )
+}
 
-        // Apply the fee on the first output
-        self.unsigned_tx.output[0].value = input_sum
-            .checked_sub(fee_amount)
-            .ok_or(FeeStrategyError::NotEnoughAssets)?
-            .as_sat();
+/// The allocation of the happy path fees, `lock` and `buy`, between the swap participants for a
+/// given [`FeePolitics`].
+///
+/// Bob funds the `lock` transaction and Alice receives the output of the `buy` transaction, the
+/// lock output value is adjusted such that each participant bears its share of the fees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FeeAllocation {
+    /// The value of the lock output.
+    pub lock_amount: Amount,
+    /// The value received by Alice on the buy output.
+    pub buy_amount: Amount,
+    /// The value Bob must fund to create the lock output.
+    pub funding_amount: Amount,
+    /// The part of the fees borne by Alice.
+    pub alice_fee: Amount,
+    /// The part of the fees borne by Bob.
+    pub bob_fee: Amount,
+}
 
-        // Return the fee amount set in native blockchain asset unit
-        Ok(fee_amount)
+impl FeeAllocation {
+    /// Allocate the `lock_fee` and `buy_fee` on top of the `arbitrating_amount` negotiated in the
+    /// deal.
+    pub fn new(
+        arbitrating_amount: Amount,
+        lock_fee: Amount,
+        buy_fee: Amount,
+        politics: FeePolitics,
+        maker_role: SwapRole,
+    ) -> Result<Self, FeeStrategyError> {
+        let total_fee = lock_fee
+            .checked_add(buy_fee)
+            .ok_or(FeeStrategyError::AmountOverflow)?;
+        let (alice_fee, bob_fee) = politics.split_fee(maker_role, total_fee.as_sat());
+        let (alice_fee, bob_fee) = (Amount::from_sat(alice_fee), Amount::from_sat(bob_fee));
+        let buy_amount = arbitrating_amount
+            .checked_sub(alice_fee)
+            .ok_or(FeeStrategyError::NotEnoughAssets)?;
+        let lock_amount = buy_amount
+            .checked_add(buy_fee)
+            .ok_or(FeeStrategyError::AmountOverflow)?;
+        let funding_amount = lock_amount
+            .checked_add(lock_fee)
+            .ok_or(FeeStrategyError::AmountOverflow)?;
+        Ok(Self {
+            lock_amount,
+            buy_amount,
+            funding_amount,
+            alice_fee,
+            bob_fee,
+        })
     }
+}
 
-    /// Validates that the fees for the given transaction are set accordingly to the strategy
-    fn validate_fee(&self, strategy: &FeeStrategy<SatPerKvB>) -> Result<bool, FeeStrategyError> {
-        if self.unsigned_tx.output.len() != 1 {
-            return Err(FeeStrategyError::new(
-                transaction::Error::MultiUTXOUnsuported,
-            ));
-        }
+/// Set the fee on the first output of `psbt` for a final transaction of `vsize` virtual bytes and
+/// return the fee set. Outputs other than the first one have fixed values, fails if the first
+/// output falls below its dust limit.
+#[allow(unused_variables)]
+pub(crate) fn apply_fee(
+    psbt: &mut PartiallySignedTransaction,
+    strategy: &FeeStrategy<SatPerKvB>,
+    politic: FeePriority,
+    vsize: usize,
+) -> Result<Amount, FeeStrategyError> {
+    if psbt.unsigned_tx.output.is_empty() {
+        return Err(FeeStrategyError::new(
+            transaction::Error::MultiUTXOUnsuported,
+        ));
+    }
 
-        let input_sum = get_available_input_sat(self)?.as_sat();
-        let output_sum = self.unsigned_tx.output[0].value;
-        let effective_fee = input_sum
-            .checked_sub(output_sum)
-            .ok_or(FeeStrategyError::AmountOfFeeTooHigh)?;
-
-        // simulate witness data
-        let mut tx = self.unsigned_tx.clone();
-        tx.input[0].witness = upper_bound_simulated_witness();
-        let vsize = tx.vsize() as f64;
-
-        match strategy {
-            FeeStrategy::Fixed(sat_per_kvb) => {
-                let fee_rate = sat_per_kvb
-                    .as_native_unit()
-                    .to_float_in(Denomination::Satoshi);
-                let fee_amount = fee_rate / 1000f64 * vsize;
-                Ok(effective_fee == fee_amount.round() as u64)
-            }
-            #[cfg(feature = "fee_range")]
-            FeeStrategy::Range { min_inc, max_inc } => todo!(),
-        }
+    let input_sum = get_available_input_sat(psbt)?;
+    // Outputs other than the first one have fixed values, the fee is applied on the first
+    let fixed_outputs = sum_amounts(psbt.unsigned_tx.output[1..].iter().map(|txout| txout.value))?;
+
+    let fee_rate = match strategy {
+        FeeStrategy::Fixed(sat_per_kvb) => sat_per_kvb
+            .as_native_unit()
+            .to_float_in(Denomination::Satoshi),
+        #[cfg(feature = "fee_range")]
+        FeeStrategy::Range { min_inc, max_inc } => match politic {
+            FeePriority::Low => min_inc.as_native_unit().to_float_in(Denomination::Satoshi),
+            FeePriority::High => max_inc.as_native_unit().to_float_in(Denomination::Satoshi),
+        },
+    };
+    let fee_amount = fee_amount(fee_rate, vsize as f64)?;
+
+    // Apply the fee on the first output
+    psbt.unsigned_tx.output[0].value = input_sum
+        .checked_sub(fixed_outputs)
+        .and_then(|available| available.checked_sub(fee_amount))
+        .ok_or(FeeStrategyError::NotEnoughAssets)?
+        .as_sat();
+
+    // The first output must stay spendable once the fee is taken from it
+    let first = &psbt.unsigned_tx.output[0];
+    if first.value < first.script_pubkey.dust_value().as_sat() {
+        return Err(FeeStrategyError::new(transaction::Error::DustOutput(
+            first.value,
+        )));
+    }
+
+    // Return the fee amount set in native blockchain asset unit
+    Ok(fee_amount)
+}
+
+/// Validate that the fee of `psbt` follows the strategy for a final transaction of `vsize`
+/// virtual bytes.
+pub(crate) fn check_fee(
+    psbt: &PartiallySignedTransaction,
+    strategy: &FeeStrategy<SatPerKvB>,
+    vsize: usize,
+) -> Result<bool, FeeStrategyError> {
+    if psbt.unsigned_tx.output.is_empty() {
+        return Err(FeeStrategyError::new(
+            transaction::Error::MultiUTXOUnsuported,
+        ));
     }
+
+    let input_sum = get_available_input_sat(psbt)?;
+    let output_sum = sum_amounts(psbt.unsigned_tx.output.iter().map(|txout| txout.value))?;
+    let effective_fee = input_sum
+        .checked_sub(output_sum)
+        .ok_or(FeeStrategyError::AmountOfFeeTooHigh)?;
+
+    // A fixed strategy has equal bounds and requires an exact match
+    let (min_inc, max_inc) = strategy.bounds();
+    let bound = |rate: &SatPerKvB| {
+        fee_amount(
+            rate.as_native_unit().to_float_in(Denomination::Satoshi),
+            vsize as f64,
+        )
+    };
+    Ok(effective_fee >= bound(min_inc)? && effective_fee <= bound(max_inc)?)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bitcoin::segwitv0::BuyTx;
+    use crate::blockchain::Fee;
+    use crate::transaction::Transaction;
+    use bitcoin::blockdata::witness::Witness;
 
     #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     struct SerdeTest {
@@ -280,6 +356,167 @@ mod tests {
         }
     }
 
+    #[test]
+    fn allocate_fees_with_politics() {
+        let alloc = |politics, maker_role| {
+            FeeAllocation::new(
+                Amount::from_sat(100_000),
+                Amount::from_sat(301),
+                Amount::from_sat(500),
+                politics,
+                maker_role,
+            )
+            .unwrap()
+        };
+
+        // Alice is the maker and pays everything
+        let alice_pays = alloc(FeePolitics::MakerPays, SwapRole::Alice);
+        assert_eq!(alice_pays, alloc(FeePolitics::TakerPays, SwapRole::Bob));
+        assert_eq!(alice_pays.buy_amount, Amount::from_sat(99_199));
+        assert_eq!(alice_pays.lock_amount, Amount::from_sat(99_699));
+        assert_eq!(alice_pays.funding_amount, Amount::from_sat(100_000));
+        assert_eq!(alice_pays.bob_fee, Amount::ZERO);
+
+        // Bob is the maker and pays everything
+        let bob_pays = alloc(FeePolitics::MakerPays, SwapRole::Bob);
+        assert_eq!(bob_pays.buy_amount, Amount::from_sat(100_000));
+        assert_eq!(bob_pays.lock_amount, Amount::from_sat(100_500));
+        assert_eq!(bob_pays.funding_amount, Amount::from_sat(100_801));
+        assert_eq!(bob_pays.alice_fee, Amount::ZERO);
+
+        // Bob pays the odd unit
+        let split = alloc(FeePolitics::Split, SwapRole::Alice);
+        assert_eq!(split.alice_fee, Amount::from_sat(400));
+        assert_eq!(split.bob_fee, Amount::from_sat(401));
+        assert_eq!(split.buy_amount, Amount::from_sat(99_600));
+        assert_eq!(split.funding_amount, Amount::from_sat(100_401));
+
+        // In every case the fees are covered exactly
+        for a in [alice_pays, bob_pays, split] {
+            assert_eq!(a.alice_fee + a.bob_fee, Amount::from_sat(801));
+            assert_eq!(a.funding_amount - a.buy_amount, Amount::from_sat(801));
+        }
+
+        assert!(FeeAllocation::new(
+            Amount::from_sat(100),
+            Amount::from_sat(301),
+            Amount::from_sat(500),
+            FeePolitics::TakerPays,
+            SwapRole::Bob,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn set_and_validate_fee_on_multiple_outputs() {
+        use bitcoin::blockdata::transaction::{OutPoint, TxIn};
+
+        let tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![
+                TxOut {
+                    value: 0,
+                    script_pubkey: bitcoin::Script::default(),
+                },
+                TxOut {
+                    value: 20_000,
+                    script_pubkey: bitcoin::Script::default(),
+                },
+            ],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 100_000,
+            script_pubkey: bitcoin::Script::default(),
+        });
+        let mut buy = BuyTx::new(psbt);
+
+        let strategy = FeeStrategy::Fixed(SatPerKvB::from_sat(1_000));
+        let fee = buy.set_fee(&strategy, FeePriority::Low).unwrap();
+        let outputs = &buy.as_partial().unsigned_tx.output;
+        assert_eq!(outputs[0].value, 100_000 - 20_000 - fee.as_sat());
+        assert_eq!(outputs[1].value, 20_000);
+        assert!(buy.validate_fee(&strategy).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "fee_range")]
+    fn validate_fee_within_range() {
+        use crate::blockchain::FeeEstimator;
+        use bitcoin::blockdata::transaction::{OutPoint, TxIn};
+
+        struct Estimation(u64);
+
+        impl FeeEstimator<SatPerKvB> for Estimation {
+            fn estimate_fee_rate(&self) -> Result<SatPerKvB, FeeStrategyError> {
+                Ok(SatPerKvB::from_sat(self.0))
+            }
+        }
+
+        let tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 0,
+                script_pubkey: bitcoin::Script::default(),
+            }],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 100_000,
+            script_pubkey: bitcoin::Script::default(),
+        });
+        let mut psbt = BuyTx::new(psbt);
+
+        let strategy = FeeStrategy::Range {
+            min_inc: SatPerKvB::from_sat(1_000),
+            max_inc: SatPerKvB::from_sat(3_000),
+        };
+        let low = psbt.set_fee(&strategy, FeePriority::Low).unwrap();
+        assert!(psbt.validate_fee(&strategy).unwrap());
+        let high = psbt.set_fee(&strategy, FeePriority::High).unwrap();
+        assert!(psbt.validate_fee(&strategy).unwrap());
+
+        // Any rate resolved within the bounds is valid
+        let fee = psbt
+            .set_fee_with_estimator(&strategy, &Estimation(2_000))
+            .unwrap();
+        assert!(fee > low && fee < high);
+        assert!(psbt.validate_fee(&strategy).unwrap());
+        // Estimations outside the bounds are clamped
+        let fee = psbt
+            .set_fee_with_estimator(&strategy, &Estimation(10_000))
+            .unwrap();
+        assert_eq!(fee, high);
+
+        // Fees outside the bounds are rejected
+        psbt.set_fee(
+            &FeeStrategy::Fixed(SatPerKvB::from_sat(500)),
+            FeePriority::Low,
+        )
+        .unwrap();
+        assert!(!psbt.validate_fee(&strategy).unwrap());
+        psbt.set_fee(
+            &FeeStrategy::Fixed(SatPerKvB::from_sat(4_000)),
+            FeePriority::Low,
+        )
+        .unwrap();
+        assert!(!psbt.validate_fee(&strategy).unwrap());
+    }
+
     #[test]
     fn display_sats_per_vbyte() {
         let fee_rate = SatPerKvB::from_sat(100);
@@ -298,7 +535,7 @@ mod tests {
     #[test]
     fn deserialize_fee_rate_in_yaml() {
         let s = "---\nfee: 10 satoshi/kvB\n";
-        let fee_rate = serde_yaml::from_str(&s).expect("Decode fee rate from yaml");
+        let fee_rate = serde_yaml::from_str(s).expect("Decode fee rate from yaml");
         assert_eq!(
             SerdeTest {
                 fee: SatPerKvB::from_sat(10)
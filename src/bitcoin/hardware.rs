@@ -0,0 +1,291 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Hardware wallet compatible partially signed transactions. Hardware signers, e.g. Ledger or
+//! Trezor devices, refuse to sign inputs they cannot fully verify: they need the derivation path
+//! of their keys to recognize the inputs and the change they own, and the full previous
+//! transaction of SegWit version 0 inputs to protect against the fee attack on their value.
+//!
+//! [`Tx::to_hardware_psbt`] exports a copy of the partial transaction enriched with the fields
+//! listed in a [`HardwareEnrichment`], the transaction used by the swap is left untouched:
+//!
+//! - the [`BIP-32`][bip-32] key origins of the keys committed in the input and output scripts,
+//! - the previous transactions as `non_witness_utxo`,
+//! - the keys that must sign each swap script, under the [`PSBT_PROPRIETARY_PREFIX`] prefix with
+//!   the [`SUBTYPE_REQUIRED_SIGNER`] subtype, one entry per key with an empty value.
+//!
+//! The script code set as the `witness_script` of P2WPKH inputs to compute their sighash is not
+//! part of [`BIP-174`][bip-174] and rejected by firmwares, it is removed from the export.
+//!
+//! [bip-32]: https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki
+//! [bip-174]: https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki
+
+use std::collections::BTreeMap;
+
+use bitcoin::blockdata::script::{Instruction, Script};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::util::bip32::KeySource;
+use bitcoin::util::psbt::raw::ProprietaryKey;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::{Transaction, Txid};
+
+use crate::bitcoin::metadata::PSBT_PROPRIETARY_PREFIX;
+use crate::bitcoin::transaction::{SubTransaction, Tx};
+use crate::transaction::Error as FError;
+
+/// Subtype of the proprietary input entries listing the keys that must sign the input.
+pub const SUBTYPE_REQUIRED_SIGNER: u8 = 0x10;
+
+/// The data known by the wallet and missing from the swap transactions for a hardware signer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HardwareEnrichment {
+    previous_txs: BTreeMap<Txid, Transaction>,
+    key_origins: BTreeMap<PublicKey, KeySource>,
+}
+
+impl HardwareEnrichment {
+    /// Create an empty enrichment.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a transaction spent by the inputs, e.g. the funding transaction seen on-chain.
+    pub fn with_previous_transaction(mut self, tx: Transaction) -> Self {
+        self.previous_txs.insert(tx.txid(), tx);
+        self
+    }
+
+    /// Add the origin, master fingerprint and derivation path, of a key held by the signer.
+    pub fn with_key_origin(mut self, pubkey: PublicKey, origin: KeySource) -> Self {
+        self.key_origins.insert(pubkey, origin);
+        self
+    }
+}
+
+impl<T> Tx<T>
+where
+    T: SubTransaction,
+{
+    /// Return a copy of the partial transaction enriched for hardware signers, see the [module
+    /// documentation](self). Fails with [`FError::WrongTemplate`] if a previous transaction does
+    /// not create the output described in the `witness_utxo` of the input spending it.
+    pub fn to_hardware_psbt(
+        &self,
+        enrichment: &HardwareEnrichment,
+    ) -> Result<PartiallySignedTransaction, FError> {
+        let mut psbt = self.psbt.clone();
+        for (index, txin) in self.psbt.unsigned_tx.input.iter().enumerate() {
+            let signers = T::required_signers(&self.psbt, index)?;
+            let input = &mut psbt.inputs[index];
+            if let Some(prev) = enrichment.previous_txs.get(&txin.previous_output.txid) {
+                let txout = prev
+                    .output
+                    .get(txin.previous_output.vout as usize)
+                    .ok_or(FError::WrongTemplate("Previous output does not exist"))?;
+                (input.witness_utxo.is_none() || input.witness_utxo.as_ref() == Some(txout))
+                    .then(|| ())
+                    .ok_or(FError::WrongTemplate(
+                        "Previous transaction does not match the spent output",
+                    ))?;
+                input.witness_utxo = Some(txout.clone());
+                input.non_witness_utxo = Some(prev.clone());
+            }
+            let script_pubkey = input
+                .witness_utxo
+                .as_ref()
+                .map(|txout| &txout.script_pubkey);
+            if script_pubkey.map(Script::is_v0_p2wpkh).unwrap_or(false) {
+                input.witness_script = None;
+            }
+            for (pubkey, origin) in enrichment.key_origins.iter() {
+                if script_pubkey
+                    .map(|script| pays_to_key(script, pubkey))
+                    .unwrap_or(false)
+                    || input
+                        .witness_script
+                        .as_ref()
+                        .map(|script| commits_to_key(script, pubkey))
+                        .unwrap_or(false)
+                {
+                    input.bip32_derivation.insert(*pubkey, origin.clone());
+                }
+            }
+            for signer in signers {
+                input.proprietary.insert(
+                    ProprietaryKey {
+                        prefix: PSBT_PROPRIETARY_PREFIX.to_vec(),
+                        subtype: SUBTYPE_REQUIRED_SIGNER,
+                        key: signer.to_bytes(),
+                    },
+                    vec![],
+                );
+            }
+        }
+        for (txout, output) in self
+            .psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .zip(psbt.outputs.iter_mut())
+        {
+            for (pubkey, origin) in enrichment.key_origins.iter() {
+                if pays_to_key(&txout.script_pubkey, pubkey)
+                    || output
+                        .witness_script
+                        .as_ref()
+                        .map(|script| commits_to_key(script, pubkey))
+                        .unwrap_or(false)
+                {
+                    output.bip32_derivation.insert(*pubkey, origin.clone());
+                }
+            }
+        }
+        Ok(psbt)
+    }
+}
+
+/// Return the keys that must sign the input at `index` of a partial transaction exported with
+/// [`Tx::to_hardware_psbt`].
+pub fn required_signers(
+    psbt: &PartiallySignedTransaction,
+    index: usize,
+) -> Vec<bitcoin::PublicKey> {
+    psbt.inputs
+        .get(index)
+        .map(|input| {
+            input
+                .proprietary
+                .keys()
+                .filter(|key| {
+                    key.prefix == PSBT_PROPRIETARY_PREFIX && key.subtype == SUBTYPE_REQUIRED_SIGNER
+                })
+                .filter_map(|key| bitcoin::PublicKey::from_slice(&key.key).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Return true if the output script is a P2WPKH paying to the key.
+fn pays_to_key(script_pubkey: &Script, pubkey: &PublicKey) -> bool {
+    bitcoin::PublicKey::new(*pubkey)
+        .wpubkey_hash()
+        .map(|hash| *script_pubkey == Script::new_v0_p2wpkh(&hash))
+        .unwrap_or(false)
+}
+
+// Return true if the script pushes the key or its hash.
+pub(crate) fn commits_to_key(script: &Script, pubkey: &PublicKey) -> bool {
+    let key = bitcoin::PublicKey::new(*pubkey);
+    let hash = key.pubkey_hash();
+    script.instructions().any(|instruction| match instruction {
+        Ok(Instruction::PushBytes(bytes)) => bytes == key.to_bytes() || bytes == &hash[..],
+        _ => false,
+    })
+}
+
+#[cfg(all(test, feature = "experimental"))]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use bitcoin::blockdata::witness::Witness;
+    use bitcoin::network::constants::Network as BtcNetwork;
+    use bitcoin::util::bip32::{DerivationPath, Fingerprint};
+    use bitcoin::{Address, Amount};
+    use std::str::FromStr;
+
+    use crate::bitcoin::segwitv0::{BuyTx, FundingTx, LockTx};
+    use crate::bitcoin::test_helpers::pubkey;
+    use crate::bitcoin::timelock::CSVTimelock;
+    use crate::blockchain::Network;
+    use crate::script::{DataLock, SwapRoleKeys};
+    use crate::transaction::{Buyable, Fundable, Lockable};
+
+    fn origin(index: u32) -> KeySource {
+        (
+            Fingerprint::from(&[0xde, 0xad, 0xbe, 0xef][..]),
+            DerivationPath::from_str(&format!("m/84'/1'/0'/0/{}", index)).unwrap(),
+        )
+    }
+
+    #[test]
+    fn enrich_lock_and_buy_for_hardware() {
+        let mut funding = FundingTx::initialize(pubkey(1), Network::Local).unwrap();
+        let funding_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: funding.get_address().unwrap().script_pubkey(),
+            }],
+        };
+        funding.update(funding_tx.clone()).unwrap();
+        let data_lock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+        };
+        let lock = LockTx::initialize(&funding, data_lock, Amount::from_sat(100_000)).unwrap();
+
+        let enrichment = HardwareEnrichment::new()
+            .with_previous_transaction(funding_tx.clone())
+            .with_key_origin(pubkey(1), origin(1))
+            .with_key_origin(pubkey(3), origin(3))
+            .with_key_origin(pubkey(4), origin(4));
+        let psbt = lock.to_hardware_psbt(&enrichment).unwrap();
+        let input = &psbt.inputs[0];
+        assert_eq!(input.non_witness_utxo, Some(funding_tx.clone()));
+        assert_eq!(input.witness_script, None);
+        assert_eq!(input.bip32_derivation.get(&pubkey(1)), Some(&origin(1)));
+        assert_eq!(input.bip32_derivation.len(), 1);
+        // The swap transaction keeps its script code
+        assert!(lock.as_psbt().inputs[0].witness_script.is_some());
+
+        let destination =
+            Address::p2wpkh(&bitcoin::PublicKey::new(pubkey(4)), BtcNetwork::Regtest).unwrap();
+        let buy = BuyTx::initialize(&lock, data_lock, destination).unwrap();
+        let psbt = buy.to_hardware_psbt(&enrichment).unwrap();
+        assert_eq!(
+            psbt.inputs[0].bip32_derivation.get(&pubkey(3)),
+            Some(&origin(3))
+        );
+        assert_eq!(
+            psbt.outputs[0].bip32_derivation.get(&pubkey(4)),
+            Some(&origin(4))
+        );
+        assert_eq!(
+            required_signers(&psbt, 0),
+            vec![
+                bitcoin::PublicKey::new(pubkey(2)),
+                bitcoin::PublicKey::new(pubkey(3))
+            ]
+        );
+
+        // The previous transaction must create the spent output
+        let mut psbt = lock.as_psbt().clone();
+        psbt.inputs[0].witness_utxo.as_mut().unwrap().value += 1;
+        assert!(matches!(
+            LockTx::new(psbt).to_hardware_psbt(&enrichment),
+            Err(FError::WrongTemplate(_))
+        ));
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! User metadata attached to a [`Tx`](crate::bitcoin::transaction::Tx) and preserved in the
+//! proprietary key-value pairs of its partially signed transaction, see [`BIP-174`][bip-174].
+//! External signers and persistence layers receiving the PSBT see what the transaction is for:
+//! its label, when it was created, the role of the participant, and the swap it belongs to.
+//!
+//! Entries are stored under the [`PSBT_PROPRIETARY_PREFIX`] prefix, one subtype per field, and
+//! do not change the transaction id. Values are consensus encoded.
+//!
+//! [bip-174]: https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki
+
+use std::collections::BTreeMap;
+
+use bitcoin::util::psbt::raw::ProprietaryKey;
+
+use crate::consensus::{self, deserialize, serialize};
+use crate::role::SwapRole;
+use crate::swap::SwapId;
+use crate::transaction::TxLabel;
+
+/// Prefix of the proprietary keys holding the metadata.
+pub const PSBT_PROPRIETARY_PREFIX: &[u8] = b"farcaster";
+
+const SUBTYPE_LABEL: u8 = 0x00;
+const SUBTYPE_CREATED_AT: u8 = 0x01;
+const SUBTYPE_ROLE: u8 = 0x02;
+const SUBTYPE_SWAP_ID: u8 = 0x03;
+const SUBTYPE_USER: u8 = 0xff;
+
+/// Metadata describing a transaction of a swap, all fields are optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxMetadata {
+    /// The label of the transaction in the swap.
+    pub label: Option<TxLabel>,
+    /// When the transaction has been created, in seconds since the Unix epoch.
+    pub created_at: Option<u64>,
+    /// The role of the participant who created the transaction.
+    pub role: Option<SwapRole>,
+    /// The swap the transaction belongs to.
+    pub swap_id: Option<SwapId>,
+    /// Arbitrary user entries.
+    pub user: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl TxMetadata {
+    /// Return true if no metadata is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Write the metadata in the proprietary entries, replacing the metadata already present.
+    /// Other proprietary entries are left untouched.
+    pub fn write_to(&self, proprietary: &mut BTreeMap<ProprietaryKey, Vec<u8>>) {
+        proprietary.retain(|key, _| key.prefix != PSBT_PROPRIETARY_PREFIX);
+        let mut insert = |subtype, key, value| {
+            proprietary.insert(
+                ProprietaryKey {
+                    prefix: PSBT_PROPRIETARY_PREFIX.to_vec(),
+                    subtype,
+                    key,
+                },
+                value,
+            );
+        };
+        if let Some(label) = self.label {
+            insert(SUBTYPE_LABEL, vec![], serialize(&label));
+        }
+        if let Some(created_at) = self.created_at {
+            insert(SUBTYPE_CREATED_AT, vec![], serialize(&created_at));
+        }
+        if let Some(role) = self.role {
+            insert(SUBTYPE_ROLE, vec![], serialize(&role));
+        }
+        if let Some(swap_id) = self.swap_id {
+            insert(SUBTYPE_SWAP_ID, vec![], serialize(&swap_id));
+        }
+        for (key, value) in &self.user {
+            insert(SUBTYPE_USER, key.clone(), value.clone());
+        }
+    }
+
+    /// Read the metadata from the proprietary entries. Fails if a known entry cannot be decoded,
+    /// entries with an unknown subtype are ignored.
+    pub fn read_from(
+        proprietary: &BTreeMap<ProprietaryKey, Vec<u8>>,
+    ) -> Result<Self, consensus::Error> {
+        let mut metadata = Self::default();
+        for (key, value) in proprietary
+            .iter()
+            .filter(|(key, _)| key.prefix == PSBT_PROPRIETARY_PREFIX)
+        {
+            match key.subtype {
+                SUBTYPE_LABEL => metadata.label = Some(deserialize(value)?),
+                SUBTYPE_CREATED_AT => metadata.created_at = Some(deserialize(value)?),
+                SUBTYPE_ROLE => metadata.role = Some(deserialize(value)?),
+                SUBTYPE_SWAP_ID => metadata.swap_id = Some(deserialize(value)?),
+                SUBTYPE_USER => {
+                    metadata.user.insert(key.key.clone(), value.clone());
+                }
+                _ => (),
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::consensus::encode;
+    use bitcoin::util::psbt::PartiallySignedTransaction;
+
+    use crate::Uuid;
+
+    #[test]
+    fn metadata_round_trip_through_psbt() {
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        })
+        .unwrap();
+        let other = ProprietaryKey {
+            prefix: b"other".to_vec(),
+            subtype: 0,
+            key: vec![],
+        };
+        psbt.proprietary.insert(other.clone(), vec![1]);
+
+        let mut metadata = TxMetadata {
+            label: Some(TxLabel::Cancel),
+            created_at: Some(1_650_000_000),
+            role: Some(SwapRole::Bob),
+            swap_id: Some(SwapId(Uuid::new())),
+            user: BTreeMap::new(),
+        };
+        metadata
+            .user
+            .insert(b"note".to_vec(), b"second attempt".to_vec());
+        metadata.write_to(&mut psbt.proprietary);
+
+        let bytes = encode::serialize(&psbt);
+        let psbt: PartiallySignedTransaction = encode::deserialize(&bytes).unwrap();
+        assert_eq!(TxMetadata::read_from(&psbt.proprietary).unwrap(), metadata);
+
+        // Writing replaces the metadata and keeps foreign entries
+        let mut proprietary = psbt.proprietary;
+        TxMetadata::default().write_to(&mut proprietary);
+        assert!(TxMetadata::read_from(&proprietary).unwrap().is_empty());
+        assert_eq!(proprietary.get(&other), Some(&vec![1]));
+    }
+}
@@ -0,0 +1,314 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Network parameters of Bitcoin and of custom Bitcoin-like chains: forks, custom signets, and
+//! private test networks. The parameters select the address encoding, the P2P magic and the
+//! default port, while the consensus rules and the swap transactions stay the ones of the
+//! [`base`](NetworkParams::base) network.
+//!
+//! ```
+//! use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+//! use bitcoin::util::address::Payload;
+//! use farcaster_core::bitcoin::network::NetworkParams;
+//!
+//! let secret = SecretKey::from_slice(&[1; 32]).unwrap();
+//! let pubkey = bitcoin::PublicKey::new(PublicKey::from_secret_key(&Secp256k1::new(), &secret));
+//! let params = NetworkParams::custom("mychain", bitcoin::Network::Regtest)
+//!     .with_bech32_hrp("mcrt")
+//!     .with_magic(0x0b110907)
+//!     .with_default_port(19444);
+//! let address = params.address(Payload::p2wpkh(&pubkey).unwrap());
+//! let encoded = params.format_address(&address);
+//! assert!(encoded.starts_with("mcrt1q"));
+//! assert_eq!(params.parse_address(&encoded).unwrap(), address);
+//! ```
+
+use std::fmt;
+
+use bitcoin::bech32::{self, FromBase32};
+use bitcoin::hashes::Hash;
+use bitcoin::util::address::{self, AddressEncoding, Payload, WitnessVersion};
+use bitcoin::util::base58;
+use bitcoin::{Address, PubkeyHash, ScriptHash};
+use thiserror::Error;
+
+use crate::bitcoin::address::{validate_destination, DestinationError};
+use crate::blockchain::Network;
+
+/// Errors when parsing an address with custom network parameters.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The address is malformed.
+    #[error("Invalid address: {0}")]
+    Address(#[from] address::Error),
+    /// The bech32 human readable part or the base58 version byte is not the one of the network.
+    #[error("The address prefix does not match network {0}")]
+    PrefixMismatch(String),
+    /// The address is not a valid swap destination.
+    #[error("Invalid destination: {0}")]
+    Destination(#[from] DestinationError),
+}
+
+/// The parameters of a Bitcoin network.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NetworkParams {
+    /// The name of the network.
+    pub name: String,
+    /// The standard network the chain follows the consensus rules of, used to tag the addresses.
+    pub base: bitcoin::Network,
+    /// The human readable part of bech32 addresses.
+    pub bech32_hrp: String,
+    /// The base58 version byte of P2PKH addresses.
+    pub p2pkh_prefix: u8,
+    /// The base58 version byte of P2SH addresses.
+    pub p2sh_prefix: u8,
+    /// The magic bytes of the P2P messages.
+    pub magic: u32,
+    /// The default P2P port.
+    pub default_port: u16,
+}
+
+impl NetworkParams {
+    /// Return the parameters of a standard network.
+    pub fn standard(network: bitcoin::Network) -> Self {
+        let (bech32_hrp, p2pkh_prefix, p2sh_prefix, default_port) = match network {
+            bitcoin::Network::Bitcoin => ("bc", 0, 5, 8333),
+            bitcoin::Network::Testnet => ("tb", 111, 196, 18333),
+            bitcoin::Network::Signet => ("tb", 111, 196, 38333),
+            bitcoin::Network::Regtest => ("bcrt", 111, 196, 18444),
+        };
+        Self {
+            name: network.to_string(),
+            base: network,
+            bech32_hrp: bech32_hrp.into(),
+            p2pkh_prefix,
+            p2sh_prefix,
+            magic: network.magic(),
+            default_port,
+        }
+    }
+
+    /// Create the parameters of a custom network, starting from the parameters of its `base`
+    /// network.
+    pub fn custom(name: impl Into<String>, base: bitcoin::Network) -> Self {
+        Self {
+            name: name.into(),
+            ..Self::standard(base)
+        }
+    }
+
+    /// Set the human readable part of bech32 addresses.
+    pub fn with_bech32_hrp(mut self, hrp: impl Into<String>) -> Self {
+        self.bech32_hrp = hrp.into().to_lowercase();
+        self
+    }
+
+    /// Set the base58 version bytes of P2PKH and P2SH addresses.
+    pub fn with_base58_prefixes(mut self, p2pkh_prefix: u8, p2sh_prefix: u8) -> Self {
+        self.p2pkh_prefix = p2pkh_prefix;
+        self.p2sh_prefix = p2sh_prefix;
+        self
+    }
+
+    /// Set the magic bytes of the P2P messages.
+    pub fn with_magic(mut self, magic: u32) -> Self {
+        self.magic = magic;
+        self
+    }
+
+    /// Set the default P2P port.
+    pub fn with_default_port(mut self, port: u16) -> Self {
+        self.default_port = port;
+        self
+    }
+
+    /// Return the Farcaster network of the chain.
+    pub fn network(&self) -> Network {
+        self.base.into()
+    }
+
+    /// Return the address paying to `payload` on this network.
+    pub fn address(&self, payload: Payload) -> Address {
+        Address {
+            payload,
+            network: self.base,
+        }
+    }
+
+    /// Encode the address with the prefixes of this network.
+    pub fn format_address(&self, address: &Address) -> String {
+        AddressEncoding {
+            payload: &address.payload,
+            p2pkh_prefix: self.p2pkh_prefix,
+            p2sh_prefix: self.p2sh_prefix,
+            bech32_hrp: &self.bech32_hrp,
+        }
+        .to_string()
+    }
+
+    /// Parse an address encoded with the prefixes of this network. The address is tagged with
+    /// the [`base`](Self::base) network.
+    pub fn parse_address(&self, s: &str) -> Result<Address, Error> {
+        let payload = match s.rfind('1') {
+            Some(sep) if s[..sep].eq_ignore_ascii_case(&self.bech32_hrp) => {
+                self.parse_witness_program(s)?
+            }
+            _ => self.parse_base58(s)?,
+        };
+        Ok(self.address(payload))
+    }
+
+    /// Parse an address with the prefixes of this network and validate it is a supported swap
+    /// destination, see [`validate_destination`].
+    pub fn parse_destination(&self, s: &str) -> Result<Address, Error> {
+        let address = self.parse_address(s)?;
+        validate_destination(&address, self.base)?;
+        Ok(address)
+    }
+
+    fn parse_witness_program(&self, s: &str) -> Result<Payload, Error> {
+        let (_, data, variant) = bech32::decode(s).map_err(address::Error::Bech32)?;
+        let (version, program) = data
+            .split_first()
+            .ok_or(address::Error::EmptyBech32Payload)?;
+        let version = WitnessVersion::from_u5(*version)?;
+        let program = Vec::<u8>::from_base32(program).map_err(address::Error::Bech32)?;
+        if program.len() < 2 || program.len() > 40 {
+            return Err(address::Error::InvalidWitnessProgramLength(program.len()).into());
+        }
+        if version == WitnessVersion::V0 && program.len() != 20 && program.len() != 32 {
+            return Err(address::Error::InvalidSegwitV0ProgramLength(program.len()).into());
+        }
+        if version.bech32_variant() != variant {
+            return Err(address::Error::InvalidBech32Variant {
+                expected: version.bech32_variant(),
+                found: variant,
+            }
+            .into());
+        }
+        Ok(Payload::WitnessProgram { version, program })
+    }
+
+    fn parse_base58(&self, s: &str) -> Result<Payload, Error> {
+        let data = base58::from_check(s).map_err(address::Error::Base58)?;
+        if data.len() != 21 {
+            return Err(address::Error::Base58(base58::Error::InvalidLength(data.len())).into());
+        }
+        match data[0] {
+            prefix if prefix == self.p2pkh_prefix => Ok(Payload::PubkeyHash(
+                PubkeyHash::from_slice(&data[1..]).expect("20 bytes hash"),
+            )),
+            prefix if prefix == self.p2sh_prefix => Ok(Payload::ScriptHash(
+                ScriptHash::from_slice(&data[1..]).expect("20 bytes hash"),
+            )),
+            _ => Err(Error::PrefixMismatch(self.name.clone())),
+        }
+    }
+}
+
+impl From<bitcoin::Network> for NetworkParams {
+    fn from(network: bitcoin::Network) -> Self {
+        Self::standard(network)
+    }
+}
+
+impl From<Network> for NetworkParams {
+    fn from(network: Network) -> Self {
+        Self::standard(network.into())
+    }
+}
+
+impl fmt::Display for NetworkParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.base)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use std::str::FromStr;
+
+    fn pubkey() -> bitcoin::PublicKey {
+        bitcoin::PublicKey::new(PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1; 32]).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn standard_params_match_bitcoin_encoding() {
+        for network in [
+            bitcoin::Network::Bitcoin,
+            bitcoin::Network::Testnet,
+            bitcoin::Network::Signet,
+            bitcoin::Network::Regtest,
+        ] {
+            let params = NetworkParams::standard(network);
+            for address in [
+                Address::p2pkh(&pubkey(), network),
+                Address::p2shwpkh(&pubkey(), network).unwrap(),
+                Address::p2wpkh(&pubkey(), network).unwrap(),
+            ] {
+                let encoded = params.format_address(&address);
+                assert_eq!(encoded, address.to_string());
+                assert_eq!(params.parse_address(&encoded).unwrap(), address);
+            }
+        }
+    }
+
+    #[test]
+    fn custom_params_encode_addresses() {
+        let params = NetworkParams::custom("mychain", bitcoin::Network::Regtest)
+            .with_bech32_hrp("MCRT")
+            .with_base58_prefixes(50, 55)
+            .with_magic(0x0b110907)
+            .with_default_port(19444);
+        assert_eq!(params.network(), Network::Local);
+        assert_eq!(params.bech32_hrp, "mcrt");
+
+        let address = params.address(Payload::p2wpkh(&pubkey()).unwrap());
+        let encoded = params.format_address(&address);
+        assert!(encoded.starts_with("mcrt1q"));
+        assert_eq!(params.parse_address(&encoded).unwrap(), address);
+        assert_eq!(
+            params.parse_address(&encoded.to_uppercase()).unwrap(),
+            address
+        );
+        assert!(params.parse_destination(&encoded).is_ok());
+
+        let address = params.address(Payload::p2pkh(&pubkey()));
+        let encoded = params.format_address(&address);
+        assert_ne!(encoded, address.to_string());
+        assert_eq!(params.parse_address(&encoded).unwrap(), address);
+        // Legacy addresses are not swap destinations
+        assert!(matches!(
+            params.parse_destination(&encoded),
+            Err(Error::Destination(DestinationError::UnsupportedType(_)))
+        ));
+
+        // Addresses of other networks are rejected
+        let regtest = Address::p2wpkh(&pubkey(), bitcoin::Network::Regtest).unwrap();
+        assert!(params.parse_address(&regtest.to_string()).is_err());
+        let testnet = Address::from_str("mrCDrCybB6J1vRfbwM5hemdJz73FwDBC8r").unwrap();
+        assert_eq!(
+            params.parse_address(&testnet.to_string()),
+            Err(Error::PrefixMismatch("mychain".into()))
+        );
+    }
+}
@@ -21,35 +21,80 @@ use std::fmt::{self, Debug};
 use std::str::FromStr;
 
 use crate::bitcoin::segwitv0::{
-    buy::Buy, cancel::Cancel, funding::Funding, lock::Lock, punish::Punish, refund::Refund,
+    buy::Buy, cancel::Cancel, collaborative::CollaborativeCancel, funding::Funding, lock::Lock,
+    punish::Punish, refund::Refund, surplus::Surplus, sweep::Sweep,
 };
 use crate::bitcoin::transaction::TxInRef;
-use crate::bitcoin::transaction::{MetadataOutput, Tx};
+use crate::bitcoin::transaction::{MetadataOutput, SubTransaction, TemplateParams, Tx};
 use crate::bitcoin::{Bitcoin, BitcoinSegwitV0, Btc, Strategy};
 
+use crate::bitcoin::fee::{self, FeeAllocation, SatPerKvB};
 use crate::bitcoin::timelock::CSVTimelock;
-use crate::blockchain::Transactions;
+use crate::blockchain::{FeeStrategy, FeeStrategyError, Transactions};
 use crate::consensus::{self, CanonicalBytes};
+use crate::crypto::context::EcdsaSigner;
 use crate::crypto::{DeriveKeys, SharedKeyId};
+use crate::protocol::ArbitratingParameters;
 use crate::role::SwapRole;
 use crate::script::{DataLock, DataPunishableLock, ScriptPath, SwapRoleKeys};
 
 use bitcoin::blockdata::opcodes;
-use bitcoin::blockdata::script::{Builder, Instruction, Script};
-use bitcoin::blockdata::transaction::EcdsaSighashType;
+use bitcoin::blockdata::script::{Builder, Instruction, Instructions, Script};
+use bitcoin::blockdata::transaction::{EcdsaSighashType, OutPoint, TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
 use bitcoin::hashes::sha256d::Hash as Sha256dHash;
-use bitcoin::secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1, SecretKey, Signing};
+use bitcoin::secp256k1::{
+    ecdsa::Signature, All, Message, PublicKey, Secp256k1, SecretKey, Signing,
+};
 use bitcoin::util::psbt::PartiallySignedTransaction;
 use bitcoin::util::sighash::SighashCache;
+use bitcoin::Amount;
 
 use ecdsa_fun::adaptor::EncryptedSignature;
+#[cfg(not(feature = "global-context"))]
+use once_cell::sync::OnceCell;
 
+pub mod airgap;
+pub mod batch;
+pub mod builder;
 mod buy;
 mod cancel;
+mod collaborative;
 pub mod funding;
 mod lock;
 mod punish;
 mod refund;
+pub mod setup;
+pub mod signing;
+mod surplus;
+mod sweep;
+pub mod watchtower;
+pub mod witness;
+
+pub use collaborative::CollaborativeSplit;
+pub use lock::LockOutputType;
+pub use punish::{PunishBurn, PunishPolicy, PunishShare, MAX_BASIS_POINTS, NUMS_POINT};
+pub use signing::SigningSession;
+
+// Upper bounds of the witness items weight, each item is prefixed with its length. See
+// `witness` for the witness layout of each transaction template.
+
+/// Weight of a signature item, low-S DER encoded with its sighash flag.
+pub const SIGNATURE_ITEM_WEIGHT: usize = 1 + 72;
+/// Weight of a compressed public key item.
+pub const PUBKEY_ITEM_WEIGHT: usize = 1 + 33;
+/// Weight of an empty item, e.g. `OP_FALSE`.
+pub const EMPTY_ITEM_WEIGHT: usize = 1;
+/// Weight of an `OP_TRUE` item.
+pub const TRUE_ITEM_WEIGHT: usize = 2;
+/// Weight of the swap lock script item.
+pub const COOP_LOCK_ITEM_WEIGHT: usize = 1 + 70;
+/// Weight of the punishable lock script item, the relative timelock is pushed on at most four
+/// bytes.
+pub const PUNISH_LOCK_ITEM_WEIGHT: usize = 1 + 114;
+/// Weight of the punishable lock script item with a recovery key, the 1-of-2 multisig of Bob's and
+/// the recovery keys adds the recovery key and two opcodes.
+pub const RECOVERY_PUNISH_LOCK_ITEM_WEIGHT: usize = PUNISH_LOCK_ITEM_WEIGHT + 34 + 2;
 
 /// Spend the lock output and reveal the first secret.
 pub type BuyTx = Tx<Buy>;
@@ -57,6 +102,10 @@ pub type BuyTx = Tx<Buy>;
 /// Cancel the buy transaction and allow refund or punish transaction.
 pub type CancelTx = Tx<Cancel>;
 
+/// Unwind the swap cooperatively from the lock, following a split agreed upon by both
+/// participants.
+pub type CollaborativeCancelTx = Tx<CollaborativeCancel>;
+
 /// Funding the swap creating a SegWit v0 output.
 pub type FundingTx = Funding;
 
@@ -69,6 +118,12 @@ pub type PunishTx = Tx<Punish>;
 /// Spend the cancel output and reveal the second secret.
 pub type RefundTx = Tx<Refund>;
 
+/// Return the surplus of an overfunded funding output to the funder before locking the funds.
+pub type SurplusTx = Tx<Surplus>;
+
+/// Sweep completed swap outputs to a long-term wallet address in a single transaction.
+pub type SweepTx = Tx<Sweep>;
+
 /// Inner type for the implementation of SegWit version 0 transactions and ECDSA cryptography.
 #[derive(Clone, Debug, Copy, Eq, PartialEq)]
 pub struct SegwitV0;
@@ -192,21 +247,37 @@ pub struct PunishLock {
     alice: PublicKey,
     bob: PublicKey,
     punish: PublicKey,
+    recovery: Option<PublicKey>,
 }
 
 impl PunishLock {
+    /// Return the punishable lock script. Without recovery key the successful path requires
+    /// Alice's and Bob's signatures, with a recovery key it requires Alice's signature and a
+    /// 1-of-2 multisig of Bob's and the recovery keys. Alice's signature is always required so
+    /// she can recover Bob's adaptor secret from any refund seen on-chain.
     pub fn script(data: DataPunishableLock<CSVTimelock, PublicKey>) -> Script {
         let DataPunishableLock {
             timelock,
             success: SwapRoleKeys { alice, bob },
             failure,
+            recovery,
         } = data;
-        Builder::new()
+        let builder = Builder::new()
             .push_opcode(opcodes::all::OP_IF)
             .push_key(&bitcoin::util::key::PublicKey::new(alice))
-            .push_opcode(opcodes::all::OP_CHECKSIGVERIFY)
-            .push_key(&bitcoin::util::key::PublicKey::new(bob))
-            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .push_opcode(opcodes::all::OP_CHECKSIGVERIFY);
+        let builder = match recovery {
+            None => builder
+                .push_key(&bitcoin::util::key::PublicKey::new(bob))
+                .push_opcode(opcodes::all::OP_CHECKSIG),
+            Some(recovery) => builder
+                .push_int(1)
+                .push_key(&bitcoin::util::key::PublicKey::new(bob))
+                .push_key(&bitcoin::util::key::PublicKey::new(recovery))
+                .push_int(2)
+                .push_opcode(opcodes::all::OP_CHECKMULTISIG),
+        };
+        builder
             .push_opcode(opcodes::all::OP_ELSE)
             .push_int(timelock.as_u32().into())
             .push_opcode(opcodes::all::OP_CSV)
@@ -235,47 +306,39 @@ impl PunishLock {
                 _ => Err(Error::WrongTemplate("Missing IF opcode")),
             })?;
         // Alice pubkey
-        let bytes = ints
-            .next() // Option<Result<Inst., Err>>
-            .ok_or(Error::MissingPublicKey) // Result<Result, Inst., Err>, FErr>
-            .map_or_else(
-                // Pass the error through
-                Err,
-                |v| match v {
-                    Ok(Instruction::PushBytes(b)) => Ok(b),
-                    // Error in the script
-                    Err(e) => Err(Error::new(e)),
-                    // Not a push bytes, not a pubkey
-                    _ => Err(Error::MissingPublicKey),
-                },
-            )?;
-        let alice = PublicKey::from_slice(bytes).map_err(Error::new)?;
-        // Checksig verify
-        ints.next()
-            .ok_or(Error::WrongTemplate("Missing opcode"))
-            .map_or_else(Err, |v| match v {
-                Ok(Instruction::Op(all::OP_CHECKSIGVERIFY)) => Ok(()),
-                Err(e) => Err(Error::new(e)),
-                _ => Err(Error::WrongTemplate("Missing CHECKSIGVERIFY opcode")),
-            })?;
-        // Bob pubkey
-        let bytes = ints
-            .next()
-            .ok_or(Error::MissingPublicKey)
-            .map_or_else(Err, |v| match v {
-                Ok(Instruction::PushBytes(b)) => Ok(b),
-                Err(e) => Err(Error::new(e)),
-                _ => Err(Error::MissingPublicKey),
-            })?;
-        let bob = PublicKey::from_slice(bytes).map_err(Error::new)?;
-        // Checksig
-        ints.next()
-            .ok_or(Error::WrongTemplate("Missing opcode"))
-            .map_or_else(Err, |v| match v {
-                Ok(Instruction::Op(all::OP_CHECKSIG)) => Ok(()),
-                Err(e) => Err(Error::new(e)),
-                _ => Err(Error::WrongTemplate("Missing CHECKSIG opcode")),
-            })?;
+        let alice = next_pubkey(&mut ints)?;
+        next_opcode(
+            &mut ints,
+            all::OP_CHECKSIGVERIFY,
+            "Missing CHECKSIGVERIFY opcode",
+        )?;
+        let (bob, recovery) = match ints.next() {
+            // Threshold of the recovery multisig
+            Some(Ok(Instruction::Op(all::OP_PUSHNUM_1))) => {
+                let bob = next_pubkey(&mut ints)?;
+                let recovery = next_pubkey(&mut ints)?;
+                next_opcode(&mut ints, all::OP_PUSHNUM_2, "Missing multisig key count")?;
+                next_opcode(
+                    &mut ints,
+                    all::OP_CHECKMULTISIG,
+                    "Missing CHECKMULTISIG opcode",
+                )?;
+                (bob, Some(recovery))
+            }
+            // Bob pubkey
+            v => {
+                let bytes = v
+                    .ok_or(Error::MissingPublicKey)
+                    .map_or_else(Err, |v| match v {
+                        Ok(Instruction::PushBytes(b)) => Ok(b),
+                        Err(e) => Err(Error::new(e)),
+                        _ => Err(Error::MissingPublicKey),
+                    })?;
+                let bob = PublicKey::from_slice(bytes).map_err(Error::new)?;
+                next_opcode(&mut ints, all::OP_CHECKSIG, "Missing CHECKSIG opcode")?;
+                (bob, None)
+            }
+        };
         // Else opcode
         ints.next()
             .ok_or(Error::WrongTemplate("Missing opcode"))
@@ -337,7 +400,17 @@ impl PunishLock {
             };
         }
 
-        Ok(Self { alice, bob, punish })
+        Ok(Self {
+            alice,
+            bob,
+            punish,
+            recovery,
+        })
+    }
+
+    /// Return the recovery key of the successful path, if any.
+    pub fn recovery_key(&self) -> Option<&PublicKey> {
+        self.recovery.as_ref()
     }
 
     pub fn get_pubkey(&self, swap_role: SwapRole, script_path: ScriptPath) -> Option<&PublicKey> {
@@ -354,6 +427,28 @@ impl PunishLock {
     }
 }
 
+fn next_pubkey(ints: &mut Instructions) -> Result<PublicKey, crate::transaction::Error> {
+    use crate::transaction::Error;
+    match ints.next() {
+        Some(Ok(Instruction::PushBytes(b))) => PublicKey::from_slice(b).map_err(Error::new),
+        Some(Err(e)) => Err(Error::new(e)),
+        _ => Err(Error::MissingPublicKey),
+    }
+}
+
+fn next_opcode(
+    ints: &mut Instructions,
+    opcode: opcodes::All,
+    missing: &'static str,
+) -> Result<(), crate::transaction::Error> {
+    use crate::transaction::Error;
+    match ints.next() {
+        Some(Ok(Instruction::Op(op))) if op == opcode => Ok(()),
+        Some(Err(e)) => Err(Error::new(e)),
+        _ => Err(Error::WrongTemplate(missing)),
+    }
+}
+
 //impl Arbitrating for Bitcoin<SegwitV0> {}
 
 impl TryFrom<Btc> for Bitcoin<SegwitV0> {
@@ -377,6 +472,7 @@ impl Transactions for Bitcoin<SegwitV0> {
     type Ms = Sha256dHash;
     type Pk = PublicKey;
     type Si = Signature;
+    type FeeUnit = SatPerKvB;
 
     type Funding = Funding;
     type Lock = Tx<Lock>;
@@ -384,6 +480,104 @@ impl Transactions for Bitcoin<SegwitV0> {
     type Cancel = Tx<Cancel>;
     type Refund = Tx<Refund>;
     type Punish = Tx<Punish>;
+
+    /// Without fee politics the lock output holds the arbitrating amount: Bob pays the `lock` fee
+    /// when funding and Alice pays the `buy` fee. Otherwise fees are estimated at the lowest rate
+    /// of the strategy and allocated with [`FeeAllocation`]; a buy fee above the estimation is
+    /// borne by Alice.
+    fn lock_amount(
+        params: &ArbitratingParameters<Amount, CSVTimelock, SatPerKvB>,
+        buy_destination: &bitcoin::Address,
+    ) -> Result<Amount, FeeStrategyError> {
+        let politics = match params.fee_politics {
+            Some(politics) => politics,
+            None => return Ok(params.arbitrating_amount),
+        };
+        // The lock output is a P2WSH output, its script length is fixed
+        let lock_script = Script::new_v0_p2wsh(&Default::default());
+        let lock_fee = estimate_fee::<Lock>(&params.fee_strategy, lock_script)?;
+        let buy_fee = estimate_fee::<Buy>(&params.fee_strategy, buy_destination.script_pubkey())?;
+        FeeAllocation::new(
+            params.arbitrating_amount,
+            lock_fee,
+            buy_fee,
+            politics,
+            params.maker_role,
+        )
+        .map(|allocation| allocation.lock_amount)
+    }
+
+    fn funding_amount(
+        params: &ArbitratingParameters<Amount, CSVTimelock, SatPerKvB>,
+        buy_destination: &bitcoin::Address,
+    ) -> Result<Amount, FeeStrategyError> {
+        let lock_script = Script::new_v0_p2wsh(&Default::default());
+        let lock_fee = estimate_fee::<Lock>(&params.fee_strategy, lock_script)?;
+        Self::lock_amount(params, buy_destination)?
+            .checked_add(lock_fee)
+            .ok_or(FeeStrategyError::AmountOfFeeTooHigh)
+    }
+
+    fn verify_buy_template(
+        buy: &Tx<Buy>,
+        lock: &Tx<Lock>,
+        destination_target: &bitcoin::Address,
+        fee_strategy: &FeeStrategy<SatPerKvB>,
+    ) -> Result<(), crate::transaction::Error> {
+        buy.verify_template_with(&TemplateParams::buy(
+            lock,
+            destination_target,
+            *fee_strategy,
+        )?)
+    }
+
+    fn verify_cancel_template(
+        cancel: &Tx<Cancel>,
+        lock: &Tx<Lock>,
+        data_lock: DataLock<CSVTimelock, PublicKey>,
+        punish_lock: DataPunishableLock<CSVTimelock, PublicKey>,
+        fee_strategy: &FeeStrategy<SatPerKvB>,
+    ) -> Result<(), crate::transaction::Error> {
+        let params = TemplateParams::cancel(lock, data_lock, punish_lock, *fee_strategy)?;
+        cancel.verify_template_with(&params)
+    }
+
+    fn verify_refund_template(
+        refund: &Tx<Refund>,
+        cancel: &Tx<Cancel>,
+        refund_target: &bitcoin::Address,
+        fee_strategy: &FeeStrategy<SatPerKvB>,
+    ) -> Result<(), crate::transaction::Error> {
+        refund.verify_template_with(&TemplateParams::refund(
+            cancel,
+            refund_target,
+            *fee_strategy,
+        )?)
+    }
+}
+
+/// Estimate the fee of a sub-transaction with one input and one output paying to `script_pubkey`.
+fn estimate_fee<T: SubTransaction>(
+    strategy: &FeeStrategy<SatPerKvB>,
+    script_pubkey: Script,
+) -> Result<Amount, FeeStrategyError> {
+    let unsigned_tx = bitcoin::Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Script::default(),
+            sequence: 0,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: 0,
+            script_pubkey,
+        }],
+    };
+    let psbt =
+        PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(FeeStrategyError::new)?;
+    fee::estimate_fee(strategy, Tx::<T>::new(psbt).estimated_vsize())
 }
 
 impl DeriveKeys for Bitcoin<SegwitV0> {
@@ -447,54 +641,78 @@ impl CanonicalBytes for EncryptedSignature {
 pub fn signature_hash(
     txin: TxInRef,
     script: &Script,
-    value: u64,
+    value: Amount,
     sighash_type: EcdsaSighashType,
 ) -> Sha256dHash {
     SighashCache::new(txin.transaction)
-        .segwit_signature_hash(txin.index, script, value, sighash_type)
+        .segwit_signature_hash(txin.index, script, value.as_sat(), sighash_type)
         .expect("encoding works")
         .as_hash()
 }
 
-/// Computes the [`BIP-143`][bip-143] compliant signature for the given input.
+/// Computes the [`BIP-143`][bip-143] compliant signature for the given input. The nonces are
+/// generated by the signer, RFC6979 nonces with a secp256k1 context, or the nonces injected in an
+/// ECDSA engine, see [`EcdsaSigner`].
 ///
 /// [bip-143]: https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
-pub fn sign_input<C>(
-    context: &mut Secp256k1<C>,
+pub fn sign_input<S>(
+    signer: &S,
     txin: TxInRef,
     script: &Script,
-    value: u64,
+    value: Amount,
     sighash_type: EcdsaSighashType,
     secret_key: &bitcoin::secp256k1::SecretKey,
 ) -> Result<Signature, bitcoin::secp256k1::Error>
 where
-    C: Signing,
+    S: EcdsaSigner + ?Sized,
 {
     // Computes sighash.
     let sighash = signature_hash(txin, script, value, sighash_type);
     // Makes signature.
     let msg = Message::from_slice(&sighash[..])?;
-    let mut sig = context.sign_ecdsa(&msg, secret_key);
-    sig.normalize_s();
-    Ok(sig)
+    Ok(signer.sign_low_s(&msg, secret_key))
 }
 
 /// Computes the [`BIP-143`][bip-143] compliant signature for the given hash.
 /// Assumes that the hash is correctly computed.
 ///
 /// [bip-143]: https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
-pub fn sign_hash(
+pub fn sign_hash<C: Signing>(
+    secp: &Secp256k1<C>,
     sighash: Sha256dHash,
     secret_key: &bitcoin::secp256k1::SecretKey,
 ) -> Result<Signature, bitcoin::secp256k1::Error> {
-    let context = Secp256k1::new();
     // Makes signature.
     let msg = Message::from_slice(&sighash[..])?;
-    let mut sig = context.sign_ecdsa(&msg, secret_key);
+    let mut sig = secp.sign_ecdsa(&msg, secret_key);
     sig.normalize_s();
     Ok(sig)
 }
 
+/// Return the secp256k1 context verifying the signatures found in the witnesses of transactions
+/// seen on-chain when no context is provided, it is created once and shared.
+pub(crate) fn verification_context() -> &'static Secp256k1<All> {
+    #[cfg(feature = "global-context")]
+    return bitcoin::secp256k1::SECP256K1;
+    #[cfg(not(feature = "global-context"))]
+    {
+        static CONTEXT: OnceCell<Secp256k1<All>> = OnceCell::new();
+        CONTEXT.get_or_init(Secp256k1::new)
+    }
+}
+
+/// Return the `SIGHASH_ALL` signatures found anywhere in the witness stacks of a transaction, in
+/// input and stack order. Unlike the template aware `extract_witness_signature` functions, e.g.
+/// [`BuyTx::extract_witness_signature`], no assumption is made on the position of the
+/// signatures, so non-standard witnesses from the counterparty can still be searched.
+pub fn witness_signatures(tx: &bitcoin::Transaction) -> Vec<Signature> {
+    tx.input
+        .iter()
+        .flat_map(|txin| txin.witness.iter())
+        .filter_map(|item| buy::extract_sighash_all_signature(item).ok())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,309 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Signing flow for minimal offline signers without PSBT support. A [`SigningRequest`] lists,
+//! for each input, the sighash to sign with the public key expected to sign it; the signer
+//! returns one raw signature per entry, 64-byte compact or DER encoded with or without the
+//! trailing sighash type byte. Signatures are validated against the sighash and inserted in the
+//! `partial_sigs` of the partial transaction with [`SigningRequest::import`].
+
+use std::io;
+
+use bitcoin::blockdata::transaction::EcdsaSighashType;
+use bitcoin::hashes::sha256d::Hash as Sha256dHash;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1, Verification};
+use bitcoin::util::ecdsa::EcdsaSig;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::Txid;
+
+use crate::bitcoin::hardware::commits_to_key;
+use crate::bitcoin::segwitv0::SigningSession;
+use crate::bitcoin::transaction::{check_low_s, parse_der_signature, Error};
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::transaction::Error as FError;
+
+/// A sighash to sign with the key of an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SighashRequest {
+    /// The index of the input in the transaction.
+    pub index: u32,
+    /// The sighash to sign.
+    pub sighash: Sha256dHash,
+    /// The public key expected to sign the sighash.
+    pub pubkey: PublicKey,
+    /// The sighash type committed in the sighash.
+    pub sighash_type: EcdsaSighashType,
+}
+
+/// The sighashes of a transaction to sign with an offline signer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningRequest {
+    /// The id of the transaction the sighashes commit to.
+    pub txid: Txid,
+    /// The sighashes to sign, in input order.
+    pub requests: Vec<SighashRequest>,
+}
+
+impl SigningRequest {
+    /// Export the sighashes of the inputs committing to one of the public keys in their
+    /// `witness_script` and not signed yet by that key. Fails with [`FError::MissingPublicKey`]
+    /// if no input is left to sign with the keys.
+    pub fn export(
+        psbt: &PartiallySignedTransaction,
+        pubkeys: &[PublicKey],
+    ) -> Result<Self, FError> {
+        let mut session = SigningSession::new(psbt);
+        let mut requests = vec![];
+        for (index, input) in psbt.inputs.iter().enumerate() {
+            let script = input
+                .witness_script
+                .as_ref()
+                .ok_or(FError::MissingWitness)?;
+            let sighash_type = input
+                .ecdsa_hash_ty()
+                .map_err(|_| FError::from(Error::MissingSigHashType))?;
+            for pubkey in pubkeys.iter().filter(|pubkey| {
+                commits_to_key(script, pubkey)
+                    && !input
+                        .partial_sigs
+                        .contains_key(&bitcoin::PublicKey::new(**pubkey))
+            }) {
+                requests.push(SighashRequest {
+                    index: index as u32,
+                    sighash: session.input_hash(index)?,
+                    pubkey: *pubkey,
+                    sighash_type,
+                });
+            }
+        }
+        (!requests.is_empty())
+            .then(|| ())
+            .ok_or(FError::MissingPublicKey)?;
+        Ok(Self {
+            txid: psbt.unsigned_tx.txid(),
+            requests,
+        })
+    }
+
+    /// Validate the raw signatures returned by the signer, one per request in order, and insert
+    /// them in the partial transaction. The partial transaction is left untouched if any
+    /// signature is invalid.
+    ///
+    /// Fails with [`FError::MissingSignature`] if the number of signatures does not match,
+    /// [`FError::WrongTemplate`] if the request was not exported from this transaction, and
+    /// [`FError::NonCanonicalSignature`] if a signature cannot be parsed, see
+    /// [`parse_raw_signature`].
+    pub fn import<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        psbt: &mut PartiallySignedTransaction,
+        signatures: &[Vec<u8>],
+    ) -> Result<(), FError> {
+        (signatures.len() == self.requests.len())
+            .then(|| ())
+            .ok_or(FError::MissingSignature)?;
+        (psbt.unsigned_tx.txid() == self.txid)
+            .then(|| ())
+            .ok_or(FError::WrongTemplate(
+                "The signing request is not for this transaction",
+            ))?;
+        let mut session = SigningSession::new(psbt);
+        let mut sigs = Vec::with_capacity(signatures.len());
+        for (request, bytes) in self.requests.iter().zip(signatures) {
+            // The prevouts are committed in the txid but not the spent values and scripts
+            (session.input_hash(request.index as usize)? == request.sighash)
+                .then(|| ())
+                .ok_or(FError::WrongTemplate(
+                    "The sighash does not match the transaction",
+                ))?;
+            let sig = parse_raw_signature(bytes, request.sighash_type)?;
+            let msg = Message::from_slice(&request.sighash[..]).map_err(Error::from)?;
+            secp.verify_ecdsa(&msg, &sig, &request.pubkey)
+                .map_err(Error::from)?;
+            sigs.push(sig);
+        }
+        for (request, sig) in self.requests.iter().zip(sigs) {
+            psbt.inputs[request.index as usize].partial_sigs.insert(
+                bitcoin::PublicKey::new(request.pubkey),
+                EcdsaSig {
+                    sig,
+                    hash_ty: request.sighash_type,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Parse a raw signature returned by an offline signer: strictly DER encoded, DER encoded
+/// followed by the sighash type byte as in a witness, or 64-byte compact encoded. The signature
+/// must be in its low-S form. Fails with [`FError::NonCanonicalSignature`] otherwise, or if the
+/// trailing sighash type byte does not match `sighash_type`.
+pub fn parse_raw_signature(
+    bytes: &[u8],
+    sighash_type: EcdsaSighashType,
+) -> Result<Signature, FError> {
+    if let Ok(sig) = parse_der_signature(bytes) {
+        return Ok(sig);
+    }
+    if bytes.len() == 64 {
+        let sig = Signature::from_compact(bytes).map_err(|_| FError::NonCanonicalSignature)?;
+        check_low_s(&sig)?;
+        return Ok(sig);
+    }
+    match bytes.split_last() {
+        Some((&flag, der)) if flag as u32 == sighash_type.to_u32() => parse_der_signature(der),
+        _ => Err(FError::NonCanonicalSignature),
+    }
+}
+
+impl Encodable for SighashRequest {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.index.consensus_encode(writer)?;
+        len += self.sighash.into_inner().consensus_encode(writer)?;
+        len += self.pubkey.serialize().consensus_encode(writer)?;
+        Ok(len + (self.sighash_type.to_u32() as u8).consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for SighashRequest {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            index: u32::consensus_decode(d)?,
+            sighash: Sha256dHash::from_inner(<[u8; 32]>::consensus_decode(d)?),
+            pubkey: PublicKey::from_canonical_bytes(&<[u8; 33]>::consensus_decode(d)?)?,
+            sighash_type: EcdsaSighashType::from_standard(u8::consensus_decode(d)? as u32)
+                .map_err(|_| consensus::Error::ParseFailed("Unknown sighash type"))?,
+        })
+    }
+}
+
+impl_strict_encoding!(SighashRequest);
+
+impl Encodable for SigningRequest {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let len = self.txid.into_inner().consensus_encode(writer)?;
+        Ok(len + self.requests.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for SigningRequest {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            txid: Txid::from_inner(<[u8; 32]>::consensus_decode(d)?),
+            requests: Vec::<SighashRequest>::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(SigningRequest);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::script::Script;
+    use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use bitcoin::blockdata::witness::Witness;
+    use bitcoin::secp256k1::SecretKey;
+
+    fn multi_input_psbt(pubkey: &PublicKey) -> PartiallySignedTransaction {
+        let script = Script::new_p2pkh(&bitcoin::PublicKey::new(*pubkey).pubkey_hash());
+        let tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: (0..3)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(Txid::from_inner([1; 32]), vout),
+                    script_sig: Script::default(),
+                    sequence: 0xffffffff,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: 29_000,
+                script_pubkey: Script::default(),
+            }],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        for input in psbt.inputs.iter_mut() {
+            input.witness_utxo = Some(TxOut {
+                value: 10_000,
+                script_pubkey: Script::default(),
+            });
+            input.witness_script = Some(script.clone());
+        }
+        psbt
+    }
+
+    #[test]
+    fn export_sighashes_and_import_raw_signatures() {
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[3; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&secp, &secret);
+        let other = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[4; 32]).unwrap());
+        let mut psbt = multi_input_psbt(&pubkey);
+
+        // Only the committed key is asked to sign
+        let request = SigningRequest::export(&psbt, &[pubkey, other]).unwrap();
+        assert_eq!(request.requests.len(), 3);
+        assert!(request.requests.iter().all(|r| r.pubkey == pubkey));
+        assert!(SigningRequest::export(&psbt, &[other]).is_err());
+        assert_eq!(
+            consensus::deserialize::<SigningRequest>(&consensus::serialize(&request)).unwrap(),
+            request
+        );
+
+        // Sign offline and return one signature per encoding
+        let sigs: Vec<Signature> = request
+            .requests
+            .iter()
+            .map(|r| secp.sign_ecdsa(&Message::from_slice(&r.sighash[..]).unwrap(), &secret))
+            .collect();
+        let mut flagged = sigs[2].serialize_der().to_vec();
+        flagged.push(EcdsaSighashType::All as u8);
+        let raw = vec![
+            sigs[0].serialize_compact().to_vec(),
+            sigs[1].serialize_der().to_vec(),
+            flagged,
+        ];
+
+        // Signatures swapped between inputs are rejected and nothing is inserted
+        let swapped = vec![raw[1].clone(), raw[0].clone(), raw[2].clone()];
+        assert!(request.import(&secp, &mut psbt, &swapped).is_err());
+        assert!(request.import(&secp, &mut psbt, &raw[..2]).is_err());
+        assert!(psbt
+            .inputs
+            .iter()
+            .all(|input| input.partial_sigs.is_empty()));
+
+        request.import(&secp, &mut psbt, &raw).unwrap();
+        for (input, sig) in psbt.inputs.iter().zip(sigs) {
+            assert_eq!(
+                input.partial_sigs[&bitcoin::PublicKey::new(pubkey)],
+                EcdsaSig::sighash_all(sig)
+            );
+        }
+        // Nothing left to sign
+        assert!(SigningRequest::export(&psbt, &[pubkey]).is_err());
+
+        // A trailing sighash type byte must match the request
+        let mut wrong = raw[1].clone();
+        wrong.push(EcdsaSighashType::None as u8);
+        assert!(parse_raw_signature(&wrong, EcdsaSighashType::All).is_err());
+    }
+}
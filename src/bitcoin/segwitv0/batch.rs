@@ -0,0 +1,277 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Transaction creating the lock outputs of several concurrent swaps at once. A market maker
+//! running many swaps as Bob can lock them with a single transaction funded by its wallet instead
+//! of one funding and one lock transaction per swap.
+//!
+//! [`BatchFunding`] lists the lock outputs, one per swap, and emits the outputs of the
+//! transaction. The wallet adds its inputs and change after them, the inputs must all be SegWit
+//! inputs for the transaction id to be known before signing. Once the transaction is built,
+//! [`BatchFunding::lock_output`] returns the lock output of a swap, identified by its index and
+//! verified against its script, to build the cancel and buy transactions with
+//! [`Tx::initialize_from_output`](crate::bitcoin::transaction::Tx).
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{OutPoint, TxOut};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Amount, Transaction};
+
+use crate::script::DataLock;
+use crate::swap::SwapId;
+use crate::transaction::Error as FError;
+
+use crate::bitcoin::segwitv0::{CoopLock, LockOutputType};
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::transaction::{Error, MetadataOutput};
+
+/// The lock output of a swap in a [`BatchFunding`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchOutput {
+    /// The swap locked by the output.
+    pub swap_id: SwapId,
+    /// The value of the lock output.
+    pub amount: Amount,
+    /// The type of the lock output.
+    pub output_type: LockOutputType,
+    script: Script,
+}
+
+impl BatchOutput {
+    /// Return the witness script of the lock output.
+    pub fn witness_script(&self) -> &Script {
+        &self.script
+    }
+
+    /// Return the transaction output locking the swap.
+    pub fn tx_out(&self) -> TxOut {
+        TxOut {
+            value: self.amount.as_sat(),
+            script_pubkey: self.output_type.script_pubkey(&self.script),
+        }
+    }
+}
+
+/// Builder of a transaction locking several swaps, the lock output of the n-th added swap is the
+/// n-th output of the transaction.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchFunding {
+    outputs: Vec<BatchOutput>,
+}
+
+impl BatchFunding {
+    /// Create an empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the P2WSH lock output of a swap and return its index, see
+    /// [`BatchFunding::add_swap_with_output_type`].
+    pub fn add_swap(
+        &mut self,
+        swap_id: SwapId,
+        lock: DataLock<CSVTimelock, PublicKey>,
+        amount: Amount,
+    ) -> Result<usize, FError> {
+        self.add_swap_with_output_type(swap_id, lock, amount, LockOutputType::P2wsh)
+    }
+
+    /// Add the lock output of a swap with the given type of output and return its index. Fails if
+    /// the swap is already part of the batch or if the amount is below the dust limit.
+    pub fn add_swap_with_output_type(
+        &mut self,
+        swap_id: SwapId,
+        lock: DataLock<CSVTimelock, PublicKey>,
+        amount: Amount,
+        output_type: LockOutputType,
+    ) -> Result<usize, FError> {
+        self.output(swap_id)
+            .is_none()
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Swap is already part of the batch"))?;
+        let output = BatchOutput {
+            swap_id,
+            amount,
+            output_type,
+            script: CoopLock::script(lock),
+        };
+        let txout = output.tx_out();
+        if txout.value < txout.script_pubkey.dust_value().as_sat() {
+            return Err(Error::DustOutput(txout.value).into());
+        }
+        self.outputs.push(output);
+        Ok(self.outputs.len() - 1)
+    }
+
+    /// Return the lock outputs of the batch, in the order of the transaction outputs.
+    pub fn outputs(&self) -> &[BatchOutput] {
+        &self.outputs
+    }
+
+    /// Return the index and the lock output of a swap, if part of the batch.
+    pub fn output(&self, swap_id: SwapId) -> Option<(usize, &BatchOutput)> {
+        self.outputs
+            .iter()
+            .enumerate()
+            .find(|(_, output)| output.swap_id == swap_id)
+    }
+
+    /// Return the total value locked by the batch.
+    pub fn total_amount(&self) -> Amount {
+        Amount::from_sat(
+            self.outputs
+                .iter()
+                .map(|output| output.amount.as_sat())
+                .sum(),
+        )
+    }
+
+    /// Return the unsigned transaction without inputs holding the lock outputs, to be completed
+    /// with the inputs and change of the wallet.
+    pub fn unsigned_tx(&self) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: self.outputs.iter().map(BatchOutput::tx_out).collect(),
+        }
+    }
+
+    /// Verify that the transaction holds all the lock outputs of the batch at their index.
+    pub fn verify(&self, tx: &Transaction) -> Result<(), FError> {
+        self.outputs
+            .iter()
+            .try_for_each(|output| self.lock_output(tx, output.swap_id).map(|_| ()))
+    }
+
+    /// Return the lock output of a swap in the transaction completed by the wallet. Fails with
+    /// [`FError::MissingUTXO`] if the swap is not part of the batch and with
+    /// [`FError::WrongTemplate`] if the output at its index does not lock the swap.
+    pub fn lock_output(&self, tx: &Transaction, swap_id: SwapId) -> Result<MetadataOutput, FError> {
+        let (index, output) = self.output(swap_id).ok_or(FError::MissingUTXO)?;
+        let tx_out = output.tx_out();
+        (tx.output.get(index) == Some(&tx_out))
+            .then(|| ())
+            .ok_or(FError::WrongTemplate(
+                "Batch output does not match the swap lock",
+            ))?;
+        Ok(MetadataOutput {
+            out_point: OutPoint::new(tx.txid(), index as u32),
+            tx_out,
+            script_pubkey: Some(output.script.clone()),
+            redeem_script: output.output_type.redeem_script(&output.script),
+            wtxid: Some(tx.wtxid()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::TxIn;
+    use bitcoin::blockdata::witness::Witness;
+    use bitcoin::hashes::Hash;
+    use bitcoin::network::constants::Network;
+    use bitcoin::{Address, Txid};
+
+    use crate::bitcoin::segwitv0::{BuyTx, CancelTx};
+    use crate::bitcoin::test_helpers::pubkey;
+    use crate::script::{DataPunishableLock, SwapRoleKeys};
+    use crate::transaction::Transaction as _;
+    use crate::Uuid;
+
+    fn lock(alice: u8, bob: u8) -> DataLock<CSVTimelock, PublicKey> {
+        DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(alice), pubkey(bob)),
+            failure: SwapRoleKeys::new(pubkey(alice), pubkey(bob)),
+        }
+    }
+
+    #[test]
+    fn lock_several_swaps_at_once() {
+        let (first, second) = (SwapId(Uuid::new()), SwapId(Uuid::new()));
+        let mut batch = BatchFunding::new();
+        assert_eq!(
+            batch
+                .add_swap(first, lock(1, 2), Amount::from_sat(100_000))
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            batch
+                .add_swap_with_output_type(
+                    second,
+                    lock(3, 4),
+                    Amount::from_sat(50_000),
+                    LockOutputType::P2shP2wsh
+                )
+                .unwrap(),
+            1
+        );
+        assert!(batch
+            .add_swap(first, lock(1, 2), Amount::from_sat(100_000))
+            .is_err());
+        assert!(batch
+            .add_swap(SwapId(Uuid::new()), lock(5, 6), Amount::from_sat(100))
+            .is_err());
+        assert_eq!(batch.total_amount(), Amount::from_sat(150_000));
+
+        // The wallet adds its input and change
+        let mut tx = batch.unsigned_tx();
+        tx.input.push(TxIn {
+            previous_output: OutPoint::new(Txid::from_inner([1; 32]), 0),
+            script_sig: Script::default(),
+            sequence: 0xffffffff,
+            witness: Witness::new(),
+        });
+        tx.output.push(TxOut {
+            value: 10_000,
+            script_pubkey: Script::default(),
+        });
+        assert!(batch.verify(&tx).is_ok());
+
+        let output = batch.lock_output(&tx, second).unwrap();
+        assert_eq!(output.out_point, OutPoint::new(tx.txid(), 1));
+        assert!(output.redeem_script.is_some());
+        let cancel_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(3), pubkey(4)),
+            failure: pubkey(3),
+            recovery: None,
+        };
+        let cancel =
+            CancelTx::initialize_from_output(output.clone(), lock(3, 4), cancel_lock).unwrap();
+        assert_eq!(cancel.based_on(), output);
+        let destination =
+            Address::p2wpkh(&bitcoin::PublicKey::new(pubkey(7)), Network::Regtest).unwrap();
+        let buy = BuyTx::initialize_from_output(output, destination).unwrap();
+        assert_eq!(buy.output_amount(), Amount::from_sat(50_000));
+
+        // Outputs reordered by the wallet are rejected
+        tx.output.swap(0, 1);
+        assert!(matches!(
+            batch.lock_output(&tx, second),
+            Err(FError::WrongTemplate(_))
+        ));
+        assert!(batch.verify(&tx).is_err());
+        assert!(matches!(
+            batch.lock_output(&tx, SwapId(Uuid::new())),
+            Err(FError::MissingUTXO)
+        ));
+    }
+}
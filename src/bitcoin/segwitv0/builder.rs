@@ -0,0 +1,728 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Fluent builders for the [`CancelTx`] and [`PunishTx`] transactions. Mandatory fields are
+//! tracked in the builder type, `build` is only available once all of them are set, so a missing
+//! field is a compile-time error.
+
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Address;
+
+use crate::bitcoin::anchor::AnchorOutput;
+use crate::bitcoin::fee::SatPerKvB;
+use crate::bitcoin::segwitv0::{CancelTx, CoopLock, LockTx, PunishPolicy, PunishTx};
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::transaction::{SubTransaction, Tx};
+use crate::blockchain::{Fee, FeePriority, FeeStrategy};
+use crate::script::{DataLock, DataPunishableLock, SwapRoleKeys};
+use crate::transaction::{Cancelable, Error as FError, Linkable};
+
+/// Marker for a mandatory field not set yet.
+#[derive(Debug, Clone, Copy)]
+pub struct Missing;
+
+fn apply_fee<T: SubTransaction>(
+    tx: &mut Tx<T>,
+    fee: Option<(FeeStrategy<SatPerKvB>, FeePriority)>,
+) -> Result<(), FError> {
+    if let Some((strategy, politic)) = fee {
+        tx.set_fee(&strategy, politic).map_err(FError::new)?;
+    }
+    Ok(())
+}
+
+/// Builder for the [`CancelTx`] transaction, created with [`CancelTx::builder`].
+#[derive(Debug, Clone)]
+pub struct CancelBuilder<L, K, T, P> {
+    lock: L,
+    cancel_keys: K,
+    timelock: T,
+    punish_lock: P,
+    fee: Option<(FeeStrategy<SatPerKvB>, FeePriority)>,
+    truc: Option<AnchorOutput>,
+}
+
+impl CancelTx {
+    /// Start building a cancel transaction.
+    pub fn builder() -> CancelBuilder<Missing, Missing, Missing, Missing> {
+        CancelBuilder {
+            lock: Missing,
+            cancel_keys: Missing,
+            timelock: Missing,
+            punish_lock: Missing,
+            fee: None,
+            truc: None,
+        }
+    }
+}
+
+impl<L, K, T, P> CancelBuilder<L, K, T, P> {
+    /// Set the lock transaction the cancel spends.
+    pub fn lock(self, lock: &LockTx) -> CancelBuilder<&LockTx, K, T, P> {
+        CancelBuilder {
+            lock,
+            cancel_keys: self.cancel_keys,
+            timelock: self.timelock,
+            punish_lock: self.punish_lock,
+            fee: self.fee,
+            truc: self.truc,
+        }
+    }
+
+    /// Set Alice's and Bob's keys of the lock output, the keys signing the cancel.
+    pub fn cancel_keys(
+        self,
+        alice: PublicKey,
+        bob: PublicKey,
+    ) -> CancelBuilder<L, SwapRoleKeys<PublicKey>, T, P> {
+        CancelBuilder {
+            lock: self.lock,
+            cancel_keys: SwapRoleKeys::new(alice, bob),
+            timelock: self.timelock,
+            punish_lock: self.punish_lock,
+            fee: self.fee,
+            truc: self.truc,
+        }
+    }
+
+    /// Set the cancel timelock, relative to the lock confirmation.
+    pub fn timelock(self, timelock: CSVTimelock) -> CancelBuilder<L, K, CSVTimelock, P> {
+        CancelBuilder {
+            lock: self.lock,
+            cancel_keys: self.cancel_keys,
+            timelock,
+            punish_lock: self.punish_lock,
+            fee: self.fee,
+            truc: self.truc,
+        }
+    }
+
+    /// Set the punishable lock of the cancel output.
+    pub fn punish_lock(
+        self,
+        punish_lock: DataPunishableLock<CSVTimelock, PublicKey>,
+    ) -> CancelBuilder<L, K, T, DataPunishableLock<CSVTimelock, PublicKey>> {
+        CancelBuilder {
+            lock: self.lock,
+            cancel_keys: self.cancel_keys,
+            timelock: self.timelock,
+            punish_lock,
+            fee: self.fee,
+            truc: self.truc,
+        }
+    }
+
+    /// Set the fee strategy applied on the transaction, no fee is set by default.
+    pub fn fee(mut self, strategy: FeeStrategy<SatPerKvB>, politic: FeePriority) -> Self {
+        self.fee = Some((strategy, politic));
+        self
+    }
+
+    /// Build a TRUC transaction with the given anchor for package relay, see [`Tx::make_truc`].
+    pub fn truc(mut self, anchor: AnchorOutput) -> Self {
+        self.truc = Some(anchor);
+        self
+    }
+}
+
+impl
+    CancelBuilder<
+        &LockTx,
+        SwapRoleKeys<PublicKey>,
+        CSVTimelock,
+        DataPunishableLock<CSVTimelock, PublicKey>,
+    >
+{
+    /// Build the cancel transaction.
+    ///
+    /// Fails with [`FError::WrongTemplate`] if the cancel keys do not match the lock output.
+    pub fn build(self) -> Result<CancelTx, FError> {
+        let data_lock = DataLock {
+            timelock: self.timelock,
+            success: self.cancel_keys,
+            // Not part of the lock output script
+            failure: self.cancel_keys,
+        };
+        (self.lock.get_consumable_output()?.script_pubkey == Some(CoopLock::script(data_lock)))
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Cancel keys do not match the lock"))?;
+
+        let mut cancel = CancelTx::initialize(self.lock, data_lock, self.punish_lock)?;
+        if let Some(anchor) = self.truc {
+            cancel.make_truc(anchor)?;
+        }
+        apply_fee(&mut cancel, self.fee)?;
+        Ok(cancel)
+    }
+}
+
+/// Builder for the [`PunishTx`] transaction, created with [`PunishTx::builder`].
+#[derive(Debug, Clone)]
+pub struct PunishBuilder<C, P, D> {
+    cancel: C,
+    punish_lock: P,
+    destination: D,
+    policy: PunishPolicy,
+    fee: Option<(FeeStrategy<SatPerKvB>, FeePriority)>,
+    truc: Option<AnchorOutput>,
+}
+
+impl PunishTx {
+    /// Start building a punish transaction.
+    pub fn builder() -> PunishBuilder<Missing, Missing, Missing> {
+        PunishBuilder {
+            cancel: Missing,
+            punish_lock: Missing,
+            destination: Missing,
+            policy: PunishPolicy::new(),
+            fee: None,
+            truc: None,
+        }
+    }
+}
+
+impl<C, P, D> PunishBuilder<C, P, D> {
+    /// Set the cancel transaction the punish spends.
+    pub fn cancel(self, cancel: &CancelTx) -> PunishBuilder<&CancelTx, P, D> {
+        PunishBuilder {
+            cancel,
+            punish_lock: self.punish_lock,
+            destination: self.destination,
+            policy: self.policy,
+            fee: self.fee,
+            truc: self.truc,
+        }
+    }
+
+    /// Set the punishable lock of the cancel output.
+    pub fn punish_lock(
+        self,
+        punish_lock: DataPunishableLock<CSVTimelock, PublicKey>,
+    ) -> PunishBuilder<C, DataPunishableLock<CSVTimelock, PublicKey>, D> {
+        PunishBuilder {
+            cancel: self.cancel,
+            punish_lock,
+            destination: self.destination,
+            policy: self.policy,
+            fee: self.fee,
+            truc: self.truc,
+        }
+    }
+
+    /// Set the address receiving the punished funds.
+    pub fn destination(self, destination: Address) -> PunishBuilder<C, P, Address> {
+        PunishBuilder {
+            cancel: self.cancel,
+            punish_lock: self.punish_lock,
+            destination,
+            policy: self.policy,
+            fee: self.fee,
+            truc: self.truc,
+        }
+    }
+
+    /// Set the policy splitting the punished funds, the destination receives everything by
+    /// default.
+    pub fn policy(mut self, policy: PunishPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Set the fee strategy applied on the transaction, no fee is set by default.
+    pub fn fee(mut self, strategy: FeeStrategy<SatPerKvB>, politic: FeePriority) -> Self {
+        self.fee = Some((strategy, politic));
+        self
+    }
+
+    /// Build a TRUC transaction with the given anchor for package relay, see [`Tx::make_truc`].
+    pub fn truc(mut self, anchor: AnchorOutput) -> Self {
+        self.truc = Some(anchor);
+        self
+    }
+}
+
+impl PunishBuilder<&CancelTx, DataPunishableLock<CSVTimelock, PublicKey>, Address> {
+    /// Build the punish transaction.
+    pub fn build(self) -> Result<PunishTx, FError> {
+        let mut punish = PunishTx::initialize_with_policy(
+            self.cancel,
+            self.punish_lock,
+            self.destination,
+            &self.policy,
+        )?;
+        if let Some(anchor) = self.truc {
+            punish.make_truc(anchor)?;
+        }
+        apply_fee(&mut punish, self.fee)?;
+        Ok(punish)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use bitcoin::blockdata::witness::Witness;
+    use bitcoin::network::constants::Network as BtcNetwork;
+    use bitcoin::Amount;
+
+    use crate::bitcoin::segwitv0::{sign_hash, FundingTx};
+    use crate::bitcoin::test_helpers::{pubkey, secp, secret};
+    use crate::blockchain::Network;
+    use crate::script::ScriptPath;
+    use crate::transaction::{
+        Broadcastable, Chainable, Finalizable, Fundable, Lockable, Transaction, Witnessable,
+    };
+
+    fn lock(data_lock: DataLock<CSVTimelock, PublicKey>) -> LockTx {
+        let mut funding = FundingTx::initialize(pubkey(1), Network::Local).unwrap();
+        let funding_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: funding.get_address().unwrap().script_pubkey(),
+            }],
+        };
+        funding.update(funding_tx).unwrap();
+        LockTx::initialize(&funding, data_lock, Amount::from_sat(100_000)).unwrap()
+    }
+
+    #[test]
+    fn build_cancel_and_punish() {
+        let data_lock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(20),
+            success: SwapRoleKeys::new(pubkey(4), pubkey(5)),
+            failure: pubkey(6),
+            recovery: None,
+        };
+        let lock = lock(data_lock);
+        let fee = FeeStrategy::Fixed(SatPerKvB::from_sat(1000));
+
+        let cancel = CancelTx::builder()
+            .lock(&lock)
+            .cancel_keys(pubkey(2), pubkey(3))
+            .timelock(CSVTimelock::new(10))
+            .punish_lock(punish_lock)
+            .build()
+            .unwrap();
+        assert!(cancel.is_build_on_top_of(&lock).is_ok());
+        assert!(cancel.verify_template(data_lock, punish_lock).is_ok());
+
+        // Keys not matching the lock are rejected
+        assert!(matches!(
+            CancelTx::builder()
+                .cancel_keys(pubkey(3), pubkey(2))
+                .lock(&lock)
+                .punish_lock(punish_lock)
+                .timelock(CSVTimelock::new(10))
+                .build(),
+            Err(FError::WrongTemplate(_))
+        ));
+
+        let destination =
+            Address::p2wpkh(&bitcoin::PublicKey::new(pubkey(7)), BtcNetwork::Regtest).unwrap();
+        let punish = PunishTx::builder()
+            .cancel(&cancel)
+            .destination(destination.clone())
+            .punish_lock(punish_lock)
+            .fee(fee, FeePriority::Low)
+            .build()
+            .unwrap();
+        assert!(punish.is_build_on_top_of(&cancel).is_ok());
+        assert!(punish.output_amount() < Amount::from_sat(100_000));
+        assert_eq!(
+            punish.as_partial().unsigned_tx.output[0].script_pubkey,
+            destination.script_pubkey()
+        );
+    }
+
+    #[test]
+    fn verify_counterparty_templates() {
+        use crate::bitcoin::segwitv0::RefundTx;
+        use crate::bitcoin::transaction::TemplateParams;
+        use crate::bitcoin::BitcoinSegwitV0;
+        use crate::blockchain::{Fee, Transactions};
+        use crate::transaction::{Error as FError, Refundable};
+
+        let data_lock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(20),
+            success: SwapRoleKeys::new(pubkey(4), pubkey(5)),
+            failure: pubkey(6),
+            recovery: None,
+        };
+        let lock = lock(data_lock);
+        let fee = FeeStrategy::Fixed(SatPerKvB::from_sat(1000));
+
+        let cancel = CancelTx::builder()
+            .lock(&lock)
+            .cancel_keys(pubkey(2), pubkey(3))
+            .timelock(CSVTimelock::new(10))
+            .punish_lock(punish_lock)
+            .fee(fee, FeePriority::Low)
+            .build()
+            .unwrap();
+        let params = TemplateParams::cancel(&lock, data_lock, punish_lock, fee).unwrap();
+        assert!(cancel.verify_template_with(&params).is_ok());
+
+        // Another timelock, destination, or fee is rejected
+        let shorter = DataLock {
+            timelock: CSVTimelock::new(5),
+            ..data_lock
+        };
+        let params = TemplateParams::cancel(&lock, shorter, punish_lock, fee).unwrap();
+        assert!(matches!(
+            cancel.verify_template_with(&params),
+            Err(FError::WrongTemplate(_))
+        ));
+        let other = DataPunishableLock {
+            failure: pubkey(7),
+            ..punish_lock
+        };
+        let params = TemplateParams::cancel(&lock, data_lock, other, fee).unwrap();
+        assert!(cancel.verify_template_with(&params).is_err());
+        let params = TemplateParams::cancel(
+            &lock,
+            data_lock,
+            punish_lock,
+            FeeStrategy::Fixed(SatPerKvB::from_sat(2000)),
+        )
+        .unwrap();
+        assert!(cancel.verify_template_with(&params).is_err());
+
+        let refund_target =
+            Address::p2wpkh(&bitcoin::PublicKey::new(pubkey(8)), BtcNetwork::Regtest).unwrap();
+        let mut refund = RefundTx::initialize(&cancel, refund_target.clone()).unwrap();
+        refund.set_fee(&fee, FeePriority::Low).unwrap();
+        let params = TemplateParams::refund(&cancel, &refund_target, fee).unwrap();
+        assert!(refund.verify_template_with(&params).is_ok());
+
+        // The refund must spend the cancel output
+        let params = TemplateParams::refund(&lock, &refund_target, fee).unwrap();
+        assert!(matches!(
+            refund.verify_template_with(&params),
+            Err(FError::InvalidTransactionChain)
+        ));
+
+        // The output amounts are checked before the fee
+        let params = TemplateParams::refund(&cancel, &refund_target, fee).unwrap();
+        let input = cancel.as_partial().unsigned_tx.output[0].value;
+        let mut dust = RefundTx::new(refund.as_psbt().clone());
+        dust.psbt.unsigned_tx.output[0].value = 100;
+        assert!(matches!(
+            dust.verify_template_with(&params),
+            Err(FError::Other(_))
+        ));
+        let mut overspend = RefundTx::new(refund.as_psbt().clone());
+        overspend.psbt.unsigned_tx.output[0].value = input + 1;
+        assert!(matches!(
+            overspend.verify_template_with(&params),
+            Err(FError::WrongTemplate("Outputs spend more than the input"))
+        ));
+
+        // The same checks are run through the arbitrating blockchain
+        assert!(
+            BitcoinSegwitV0::verify_refund_template(&refund, &cancel, &refund_target, &fee).is_ok()
+        );
+        assert!(BitcoinSegwitV0::verify_cancel_template(
+            &cancel,
+            &lock,
+            data_lock,
+            punish_lock,
+            &fee
+        )
+        .is_ok());
+        assert!(BitcoinSegwitV0::verify_cancel_template(
+            &cancel,
+            &lock,
+            shorter,
+            punish_lock,
+            &fee
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn broadcast_truc_cancel_in_package() {
+        use crate::bitcoin::truc::{BroadcastMode, Package, TRUC_VERSION};
+        use crate::syncer::mock::MockSyncer;
+        use crate::syncer::Syncer;
+        use crate::transaction::TxLabel;
+
+        let data_lock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(20),
+            success: SwapRoleKeys::new(pubkey(4), pubkey(5)),
+            failure: pubkey(6),
+            recovery: None,
+        };
+        let lock = lock(data_lock);
+        let mode = BroadcastMode::Truc;
+        assert!(mode.is_truc(TxLabel::Cancel) && !mode.is_truc(TxLabel::Buy));
+
+        let cancel = CancelTx::builder()
+            .lock(&lock)
+            .cancel_keys(pubkey(2), pubkey(3))
+            .timelock(CSVTimelock::new(10))
+            .punish_lock(punish_lock)
+            .fee(
+                FeeStrategy::Fixed(SatPerKvB::from_sat(1000)),
+                FeePriority::Low,
+            )
+            .truc(AnchorOutput::Ephemeral)
+            .build()
+            .unwrap();
+        assert_eq!(cancel.as_partial().unsigned_tx.version, TRUC_VERSION);
+        assert_eq!(cancel.anchor(), Some(AnchorOutput::Ephemeral));
+        assert!(cancel.verify_template(data_lock, punish_lock).is_ok());
+        assert!(cancel
+            .validate_fee(&FeeStrategy::Fixed(SatPerKvB::from_sat(1000)))
+            .unwrap());
+        // A version 3 cancel must carry its anchor
+        let mut no_anchor = CancelTx::builder()
+            .lock(&lock)
+            .cancel_keys(pubkey(2), pubkey(3))
+            .timelock(CSVTimelock::new(10))
+            .punish_lock(punish_lock)
+            .build()
+            .unwrap();
+        no_anchor.as_partial_mut().unsigned_tx.version = TRUC_VERSION;
+        assert!(matches!(
+            no_anchor.verify_template(data_lock, punish_lock),
+            Err(FError::WrongTemplate(_))
+        ));
+
+        // The punish spends the cancel output, not the anchor
+        let destination =
+            Address::p2wpkh(&bitcoin::PublicKey::new(pubkey(7)), BtcNetwork::Regtest).unwrap();
+        let punish = PunishTx::builder()
+            .cancel(&cancel)
+            .destination(destination)
+            .punish_lock(punish_lock)
+            .build()
+            .unwrap();
+        assert_eq!(
+            punish.as_partial().unsigned_tx.input[0].previous_output,
+            OutPoint::new(cancel.txid(), 0)
+        );
+
+        // A child spending the anchor bumps the cancel fee
+        let parent = cancel.extract();
+        let mut child = bitcoin::Transaction {
+            version: TRUC_VERSION,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(parent.txid(), 1),
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![],
+        };
+        assert!(Package::new(parent.clone(), child.clone()).is_ok());
+        child.version = 2;
+        assert!(Package::new(parent.clone(), child.clone()).is_err());
+        child.version = TRUC_VERSION;
+
+        let package = Package::new(parent.clone(), child.clone()).unwrap();
+        let mut syncer = MockSyncer::new();
+        syncer.fail_broadcasts(1);
+        assert!(syncer
+            .broadcast_package(package.clone().into_transactions())
+            .is_err());
+        assert_eq!(syncer.confirmations(&parent.txid()), None);
+        assert_eq!(
+            syncer
+                .broadcast_package(package.into_transactions())
+                .unwrap(),
+            vec![parent.txid(), child.txid()]
+        );
+    }
+
+    #[test]
+    fn combine_cancel_signatures() {
+        let data_lock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(20),
+            success: SwapRoleKeys::new(pubkey(4), pubkey(5)),
+            failure: pubkey(6),
+            recovery: None,
+        };
+        let lock = lock(data_lock);
+        let build = || {
+            CancelTx::builder()
+                .lock(&lock)
+                .cancel_keys(pubkey(2), pubkey(3))
+                .timelock(CSVTimelock::new(10))
+                .punish_lock(punish_lock)
+                .build()
+        };
+        let sign = |cancel: &mut CancelTx, byte: u8| {
+            let msg = cancel
+                .generate_witness_message(ScriptPath::Failure)
+                .unwrap();
+            let sig = sign_hash(secp(), msg, &secret(byte)).unwrap();
+            cancel.add_witness(pubkey(byte), sig).unwrap();
+        };
+
+        // Each party signs its own copy of the cancel
+        let mut alice_cancel = build().unwrap();
+        let mut bob_cancel = build().unwrap();
+        assert_eq!(alice_cancel.missing_signatures().unwrap(), vec![0]);
+        sign(&mut alice_cancel, 2);
+        sign(&mut bob_cancel, 3);
+        assert_eq!(alice_cancel.missing_signatures().unwrap(), vec![0]);
+
+        let mut combined = build().unwrap();
+        assert_eq!(combined.combine(&alice_cancel).unwrap(), vec![0]);
+        assert!(combined.combine(&bob_cancel).unwrap().is_empty());
+        assert!(combined.finalize().is_ok());
+
+        // The witness template bounds the final size within a few weight units
+        let vsize = combined.extract().vsize();
+        assert!(combined.estimated_vsize() >= vsize);
+        assert!(combined.estimated_vsize() <= vsize + 1);
+
+        // A cancel with an other unsigned transaction is rejected
+        let mut other = CancelTx::initialize(
+            &lock,
+            DataLock {
+                timelock: CSVTimelock::new(11),
+                ..data_lock
+            },
+            punish_lock,
+        )
+        .unwrap();
+        assert!(other.combine(&alice_cancel).is_err());
+        assert_eq!(other.missing_signatures().unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn refund_with_recovery_key() {
+        use crate::bitcoin::segwitv0::{PunishLock, RefundTx};
+        use crate::role::SwapRole;
+        use crate::transaction::Refundable;
+
+        let data_lock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(20),
+            success: SwapRoleKeys::new(pubkey(4), pubkey(5)),
+            failure: pubkey(6),
+            recovery: Some(pubkey(8)),
+        };
+        let script = PunishLock::script(punish_lock);
+        let swaplock = PunishLock::from_script(&script).unwrap();
+        assert_eq!(swaplock.recovery_key(), Some(&pubkey(8)));
+        assert_eq!(
+            swaplock.get_pubkey(SwapRole::Bob, ScriptPath::Success),
+            Some(&pubkey(5))
+        );
+
+        let lock = lock(data_lock);
+        let cancel = CancelTx::builder()
+            .lock(&lock)
+            .cancel_keys(pubkey(2), pubkey(3))
+            .timelock(CSVTimelock::new(10))
+            .punish_lock(punish_lock)
+            .build()
+            .unwrap();
+        assert!(cancel.verify_template(data_lock, punish_lock).is_ok());
+        // The cancel output commits to the recovery key
+        assert!(cancel
+            .verify_template(
+                data_lock,
+                DataPunishableLock {
+                    recovery: None,
+                    ..punish_lock
+                }
+            )
+            .is_err());
+
+        let destination =
+            Address::p2wpkh(&bitcoin::PublicKey::new(pubkey(7)), BtcNetwork::Regtest).unwrap();
+        let mut refund = RefundTx::initialize(&cancel, destination.clone()).unwrap();
+        let sign = |refund: &mut RefundTx, byte: u8| {
+            let msg = refund
+                .generate_witness_message(ScriptPath::Success)
+                .unwrap();
+            let sig = sign_hash(secp(), msg, &secret(byte)).unwrap();
+            refund.add_witness(pubkey(byte), sig).unwrap();
+            sig
+        };
+
+        // Alice and the recovery key are enough to refund when Bob lost his keys
+        let alice_sig = sign(&mut refund, 4);
+        assert_eq!(refund.missing_signatures().unwrap(), vec![0]);
+        assert!(matches!(refund.finalize(), Err(FError::MissingSignature)));
+        sign(&mut refund, 8);
+        assert!(refund.missing_signatures().unwrap().is_empty());
+        assert!(refund.finalize().is_ok());
+
+        let tx = refund.extract();
+        assert_eq!(tx.input[0].witness.len(), 5);
+        assert_eq!(
+            refund.extract_witness_signature(&tx, secp()).unwrap(),
+            alice_sig
+        );
+        let vsize = tx.vsize();
+        assert!(refund.estimated_vsize() >= vsize);
+        assert!(refund.estimated_vsize() <= vsize + 1);
+
+        // Alice's signature is required, Bob and the recovery key cannot refund without her
+        let mut refund = RefundTx::initialize(&cancel, destination).unwrap();
+        sign(&mut refund, 5);
+        sign(&mut refund, 8);
+        assert_eq!(refund.missing_signatures().unwrap(), vec![0]);
+        assert!(matches!(refund.finalize(), Err(FError::MissingSignature)));
+        // Bob's signature is used when available
+        let alice_sig = sign(&mut refund, 4);
+        refund.finalize().unwrap();
+        let tx = refund.extract();
+        assert_eq!(
+            refund.extract_witness_signature(&tx, secp()).unwrap(),
+            alice_sig
+        );
+    }
+}
@@ -14,26 +14,27 @@
 // License along with this library; if not, write to the Free Software
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
 
-use std::marker::PhantomData;
-
-use bitcoin::blockdata::transaction::{TxIn, TxOut};
+use bitcoin::blockdata::transaction::{EcdsaSighashType, TxIn, TxOut};
 use bitcoin::blockdata::witness::Witness;
 use bitcoin::secp256k1::ecdsa::Signature;
-use bitcoin::secp256k1::PublicKey;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, Verification};
 use bitcoin::util::ecdsa::EcdsaSig;
 use bitcoin::util::psbt::PartiallySignedTransaction;
 use bitcoin::Address;
 use bitcoin::Amount;
 use bitcoin::Transaction;
 
+use crate::blockchain::FeeStrategy;
 use crate::role::SwapRole;
-use crate::script;
-use crate::transaction::{Buyable, Error as FError, Lockable};
+use crate::script::{self, ScriptPath};
+use crate::transaction::{Buyable, Error as FError, Linkable, Lockable, Witnessable};
 
-use crate::bitcoin::segwitv0::CoopLock;
+use crate::bitcoin::fee::SatPerKvB;
+use crate::bitcoin::segwitv0::witness::BUY_WITNESS;
 use crate::bitcoin::segwitv0::Sha256dHash;
+use crate::bitcoin::segwitv0::{verification_context, witness_signatures, CoopLock};
 use crate::bitcoin::timelock::CSVTimelock;
-use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, Tx};
+use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, TemplateParams, Tx};
 
 #[derive(Debug)]
 pub struct Buy;
@@ -69,6 +70,42 @@ impl SubTransaction for Buy {
 
         Ok(())
     }
+
+    fn estimated_witness_weight() -> usize {
+        BUY_WITNESS.max_size()
+    }
+
+    fn required_signers(
+        psbt: &PartiallySignedTransaction,
+        index: usize,
+    ) -> Result<Vec<bitcoin::PublicKey>, FError> {
+        let script = psbt.inputs[index]
+            .witness_script
+            .as_ref()
+            .ok_or(FError::MissingWitness)?;
+        let swaplock = CoopLock::from_script(script)?;
+        Ok(vec![
+            bitcoin::PublicKey::new(*swaplock.get_pubkey(SwapRole::Alice)),
+            bitcoin::PublicKey::new(*swaplock.get_pubkey(SwapRole::Bob)),
+        ])
+    }
+}
+
+impl TemplateParams {
+    /// The values a buy transaction must follow: spending the lock output without timelock and
+    /// paying the destination address.
+    pub fn buy(
+        lock: &impl Linkable<MetadataOutput>,
+        destination_target: &Address,
+        fee_strategy: FeeStrategy<SatPerKvB>,
+    ) -> Result<Self, FError> {
+        Ok(Self {
+            prev_output: lock.get_consumable_output()?,
+            sequence: 0,
+            script_pubkey: destination_target.script_pubkey(),
+            fee_strategy,
+        })
+    }
 }
 
 impl
@@ -99,34 +136,7 @@ impl
         _lock: script::DataLock<CSVTimelock, PublicKey>,
         destination_target: Address,
     ) -> Result<Self, FError> {
-        let output_metadata = prev.get_consumable_output()?;
-
-        let unsigned_tx = bitcoin::Transaction {
-            version: 2,
-            lock_time: 0,
-            input: vec![TxIn {
-                previous_output: output_metadata.out_point,
-                script_sig: bitcoin::Script::default(),
-                sequence: 0,
-                witness: Witness::new(),
-            }],
-            output: vec![TxOut {
-                value: output_metadata.tx_out.value,
-                script_pubkey: destination_target.script_pubkey(),
-            }],
-        };
-
-        let mut psbt =
-            PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
-
-        // Set the input witness data and sighash type
-        psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
-        psbt.inputs[0].witness_script = output_metadata.script_pubkey;
-
-        Ok(Tx {
-            psbt,
-            _t: PhantomData,
-        })
+        Self::initialize_from_output(prev.get_consumable_output()?, destination_target)
     }
 
     fn verify_template(&self, destination_target: Address) -> Result<(), FError> {
@@ -157,11 +167,104 @@ impl
         Ok(())
     }
 
-    fn extract_witness(tx: bitcoin::Transaction) -> Signature {
-        let TxIn { witness, .. } = &tx.input[0];
-        let witness_bytes = witness.to_vec();
-        let ecdsa_sig = EcdsaSig::from_slice(witness_bytes[0].as_ref())
-            .expect("Validated transaction on-chain, signature and witness position is correct.");
-        ecdsa_sig.sig
+    fn extract_witness(&self, tx: bitcoin::Transaction) -> Result<Signature, FError> {
+        self.search_witness_signature(&tx, verification_context())
+    }
+}
+
+impl Tx<Buy> {
+    /// Extract the decrypted adaptor signature from the witness of a buy transaction seen
+    /// on-chain. The witness must follow the buy template with Bob's signature, Alice's
+    /// signature, and the cooperative lock script; an error is returned otherwise, e.g. if the
+    /// witness has been malleated.
+    pub fn extract_witness_signature(tx: &bitcoin::Transaction) -> Result<Signature, FError> {
+        let witness = match tx.input.as_slice() {
+            [TxIn { witness, .. }] => witness.to_vec(),
+            _ => return Err(FError::WrongTemplate("Number of inputs is not 1")),
+        };
+        match witness.as_slice() {
+            [bob_sig, _alice_sig, script] => {
+                CoopLock::from_script(&bitcoin::Script::from(script.clone()))?;
+                extract_sighash_all_signature(bob_sig)
+            }
+            [] => Err(FError::MissingWitness),
+            _ => Err(FError::WrongTemplate("Unexpected witness stack")),
+        }
+    }
+
+    /// Search the witness of this buy transaction seen on-chain for Bob's decrypted adaptor
+    /// signature, the signature verifying against Bob's key and the witness message of this buy.
+    /// Unlike [`Self::extract_witness_signature`] no position is assumed, so a non-standard
+    /// witness from Alice, e.g. with swapped signatures or extra elements, is still searched.
+    pub fn search_witness_signature<C: Verification>(
+        &self,
+        tx: &bitcoin::Transaction,
+        secp: &Secp256k1<C>,
+    ) -> Result<Signature, FError> {
+        (tx.txid() == self.txid())
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Transaction is not this buy"))?;
+        let script = self.psbt.inputs[0]
+            .witness_script
+            .as_ref()
+            .ok_or(FError::MissingWitness)?;
+        let bob = *CoopLock::from_script(script)?.get_pubkey(SwapRole::Bob);
+
+        let msg = self.generate_witness_message(ScriptPath::Success)?;
+        let msg = Message::from_slice(&msg[..]).expect("Hash is always ok");
+        let signatures = witness_signatures(tx);
+        if signatures.is_empty() {
+            return Err(FError::MissingWitness);
+        }
+        signatures
+            .into_iter()
+            .find(|sig| secp.verify_ecdsa(&msg, sig, &bob).is_ok())
+            .ok_or(FError::MissingSignature)
+    }
+}
+
+impl Tx<Buy> {
+    /// Create the buy transaction spending the given lock output, e.g. one of the outputs of a
+    /// transaction locking several swaps at once, see
+    /// [`BatchFunding`](crate::bitcoin::segwitv0::batch::BatchFunding).
+    pub fn initialize_from_output(
+        output_metadata: MetadataOutput,
+        destination_target: Address,
+    ) -> Result<Self, FError> {
+        let unsigned_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: output_metadata.out_point,
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: output_metadata.tx_out.value,
+                script_pubkey: destination_target.script_pubkey(),
+            }],
+        };
+
+        let mut psbt =
+            PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
+
+        // Set the input witness data and sighash type
+        psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
+        psbt.inputs[0].witness_script = output_metadata.script_pubkey;
+        psbt.inputs[0].redeem_script = output_metadata.redeem_script;
+
+        Ok(Tx::new(psbt))
+    }
+}
+
+/// Parse a DER encoded signature from a witness element and ensure it commits to the whole
+/// transaction.
+pub(crate) fn extract_sighash_all_signature(bytes: &[u8]) -> Result<Signature, FError> {
+    let ecdsa_sig = EcdsaSig::from_slice(bytes)
+        .map_err(|_| FError::WrongTemplate("Invalid signature in witness"))?;
+    match ecdsa_sig.hash_ty {
+        EcdsaSighashType::All => Ok(ecdsa_sig.sig),
+        _ => Err(FError::WrongTemplate("Signature is not SIGHASH_ALL")),
     }
 }
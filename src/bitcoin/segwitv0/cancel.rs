@@ -14,8 +14,6 @@
 // License along with this library; if not, write to the Free Software
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
 
-use std::marker::PhantomData;
-
 use bitcoin::blockdata::transaction::{TxIn, TxOut};
 use bitcoin::blockdata::witness::Witness;
 use bitcoin::secp256k1::ecdsa::Signature;
@@ -25,14 +23,18 @@ use bitcoin::Address;
 use bitcoin::Amount;
 use bitcoin::Transaction;
 
+use crate::blockchain::FeeStrategy;
 use crate::role::SwapRole;
 use crate::script;
-use crate::transaction::{Cancelable, Error as FError, Lockable};
+use crate::transaction::{Cancelable, Error as FError, Linkable, Lockable};
 
+use crate::bitcoin::fee::SatPerKvB;
+use crate::bitcoin::segwitv0::witness::CANCEL_WITNESS;
 use crate::bitcoin::segwitv0::Sha256dHash;
 use crate::bitcoin::segwitv0::{CoopLock, PunishLock};
 use crate::bitcoin::timelock::CSVTimelock;
-use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, Tx};
+use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, TemplateParams, Tx};
+use crate::bitcoin::truc::TRUC_VERSION;
 
 #[derive(Debug)]
 pub struct Cancel;
@@ -68,6 +70,43 @@ impl SubTransaction for Cancel {
 
         Ok(())
     }
+
+    fn estimated_witness_weight() -> usize {
+        CANCEL_WITNESS.max_size()
+    }
+
+    fn required_signers(
+        psbt: &PartiallySignedTransaction,
+        index: usize,
+    ) -> Result<Vec<bitcoin::PublicKey>, FError> {
+        let script = psbt.inputs[index]
+            .witness_script
+            .as_ref()
+            .ok_or(FError::MissingWitness)?;
+        let swaplock = CoopLock::from_script(script)?;
+        Ok(vec![
+            bitcoin::PublicKey::new(*swaplock.get_pubkey(SwapRole::Alice)),
+            bitcoin::PublicKey::new(*swaplock.get_pubkey(SwapRole::Bob)),
+        ])
+    }
+}
+
+impl TemplateParams {
+    /// The values a cancel transaction must follow: spending the lock output after the cancel
+    /// timelock and paying the punishable lock.
+    pub fn cancel(
+        lock: &impl Linkable<MetadataOutput>,
+        data_lock: script::DataLock<CSVTimelock, PublicKey>,
+        punish_lock: script::DataPunishableLock<CSVTimelock, PublicKey>,
+        fee_strategy: FeeStrategy<SatPerKvB>,
+    ) -> Result<Self, FError> {
+        Ok(Self {
+            prev_output: lock.get_consumable_output()?,
+            sequence: data_lock.timelock.as_u32(),
+            script_pubkey: PunishLock::v0_p2wsh(punish_lock),
+            fee_strategy,
+        })
+    }
 }
 
 impl
@@ -98,38 +137,7 @@ impl
         lock: script::DataLock<CSVTimelock, PublicKey>,
         punish_lock: script::DataPunishableLock<CSVTimelock, PublicKey>,
     ) -> Result<Self, FError> {
-        let script = PunishLock::script(punish_lock);
-        let output_metadata = prev.get_consumable_output()?;
-
-        let unsigned_tx = bitcoin::Transaction {
-            version: 2,
-            lock_time: 0,
-            input: vec![TxIn {
-                previous_output: output_metadata.out_point,
-                script_sig: bitcoin::Script::default(),
-                sequence: lock.timelock.as_u32(),
-                witness: Witness::new(),
-            }],
-            output: vec![TxOut {
-                value: output_metadata.tx_out.value,
-                script_pubkey: script.to_v0_p2wsh(),
-            }],
-        };
-
-        let mut psbt =
-            PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
-
-        // Set the input witness data and sighash type
-        psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
-        psbt.inputs[0].witness_script = output_metadata.script_pubkey;
-
-        // Set the script witness of the output
-        psbt.outputs[0].witness_script = Some(script);
-
-        Ok(Tx {
-            psbt,
-            _t: PhantomData,
-        })
+        Self::initialize_from_output(prev.get_consumable_output()?, lock, punish_lock)
     }
 
     fn verify_template(
@@ -137,18 +145,25 @@ impl
         lock: script::DataLock<CSVTimelock, PublicKey>,
         punish_lock: script::DataPunishableLock<CSVTimelock, PublicKey>,
     ) -> Result<(), FError> {
-        (self.psbt.unsigned_tx.version == 2)
+        // A TRUC cancel is accepted with its anchor, see `Tx::make_truc`
+        let version = self.psbt.unsigned_tx.version;
+        (version == 2 || version == TRUC_VERSION && self.anchor().is_some())
             .then(|| 0)
-            .ok_or(FError::WrongTemplate("Tx version is not 2"))?;
+            .ok_or(FError::WrongTemplate(
+                "Tx version is not 2 nor 3 with an anchor",
+            ))?;
         (self.psbt.unsigned_tx.lock_time == 0)
             .then(|| 0)
             .ok_or(FError::WrongTemplate("LockTime is not set to 0"))?;
         (self.psbt.unsigned_tx.input.len() == 1)
             .then(|| 0)
             .ok_or(FError::WrongTemplate("Number of inputs is not 1"))?;
-        (self.psbt.unsigned_tx.output.len() == 1)
+        let outputs = self.psbt.unsigned_tx.output.len();
+        (outputs == 1 || outputs == 2 && self.anchor().is_some())
             .then(|| 0)
-            .ok_or(FError::WrongTemplate("Number of outputs is not 1"))?;
+            .ok_or(FError::WrongTemplate(
+                "Number of outputs is not 1 plus an optional anchor",
+            ))?;
 
         let txin = &self.psbt.unsigned_tx.input[0];
         (txin.sequence == lock.timelock.as_u32())
@@ -166,3 +181,44 @@ impl
         Ok(())
     }
 }
+
+impl Tx<Cancel> {
+    /// Create the cancel transaction spending the given lock output, e.g. one of the outputs of a
+    /// transaction locking several swaps at once, see
+    /// [`BatchFunding`](crate::bitcoin::segwitv0::batch::BatchFunding).
+    pub fn initialize_from_output(
+        output_metadata: MetadataOutput,
+        lock: script::DataLock<CSVTimelock, PublicKey>,
+        punish_lock: script::DataPunishableLock<CSVTimelock, PublicKey>,
+    ) -> Result<Self, FError> {
+        let script = PunishLock::script(punish_lock);
+
+        let unsigned_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: output_metadata.out_point,
+                script_sig: bitcoin::Script::default(),
+                sequence: lock.timelock.as_u32(),
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: output_metadata.tx_out.value,
+                script_pubkey: script.to_v0_p2wsh(),
+            }],
+        };
+
+        let mut psbt =
+            PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
+
+        // Set the input witness data and sighash type
+        psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
+        psbt.inputs[0].witness_script = output_metadata.script_pubkey;
+        psbt.inputs[0].redeem_script = output_metadata.redeem_script;
+
+        // Set the script witness of the output
+        psbt.outputs[0].witness_script = Some(script);
+
+        Ok(Tx::new(psbt))
+    }
+}
@@ -0,0 +1,289 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+use bitcoin::blockdata::transaction::{TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::Address;
+use bitcoin::Amount;
+
+use crate::role::SwapRole;
+use crate::transaction::{Error as FError, Linkable};
+
+use crate::bitcoin::segwitv0::buy::extract_sighash_all_signature;
+use crate::bitcoin::segwitv0::witness::COLLABORATIVE_CANCEL_WITNESS;
+use crate::bitcoin::segwitv0::{CoopLock, LockTx};
+use crate::bitcoin::transaction::{Error, SubTransaction, Tx};
+
+/// The split of the locked funds agreed upon by both participants to unwind a swap
+/// cooperatively. Bob, who funded the lock, receives what is left after Alice's share and the
+/// fee.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CollaborativeSplit {
+    /// The address receiving Bob's share.
+    pub bob_target: Address,
+    /// The address receiving Alice's share.
+    pub alice_target: Address,
+    /// The share of Alice, e.g. a compensation for the time her funds have been locked. Alice
+    /// has no output if zero.
+    pub alice_amount: Amount,
+    /// The fee paid by the transaction.
+    pub fee: Amount,
+}
+
+#[derive(Debug)]
+pub struct CollaborativeCancel;
+
+impl SubTransaction for CollaborativeCancel {
+    fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
+        let script = psbt.inputs[0]
+            .witness_script
+            .clone()
+            .ok_or(FError::MissingWitness)?;
+
+        let swaplock = CoopLock::from_script(&script)?;
+
+        let alice_sig = *psbt.inputs[0]
+            .partial_sigs
+            .get(&bitcoin::PublicKey::new(
+                *swaplock.get_pubkey(SwapRole::Alice),
+            ))
+            .ok_or(FError::MissingSignature)?;
+
+        let bob_sig = *psbt.inputs[0]
+            .partial_sigs
+            .get(&bitcoin::PublicKey::new(
+                *swaplock.get_pubkey(SwapRole::Bob),
+            ))
+            .ok_or(FError::MissingSignature)?;
+
+        psbt.inputs[0].final_script_witness = Some(Witness::from_vec(vec![
+            bob_sig.to_vec(),
+            alice_sig.to_vec(),
+            script.into_bytes(),
+        ]));
+
+        Ok(())
+    }
+
+    fn estimated_witness_weight() -> usize {
+        COLLABORATIVE_CANCEL_WITNESS.max_size()
+    }
+
+    fn required_signers(
+        psbt: &PartiallySignedTransaction,
+        index: usize,
+    ) -> Result<Vec<bitcoin::PublicKey>, FError> {
+        let script = psbt.inputs[index]
+            .witness_script
+            .as_ref()
+            .ok_or(FError::MissingWitness)?;
+        let swaplock = CoopLock::from_script(script)?;
+        Ok(vec![
+            bitcoin::PublicKey::new(*swaplock.get_pubkey(SwapRole::Alice)),
+            bitcoin::PublicKey::new(*swaplock.get_pubkey(SwapRole::Bob)),
+        ])
+    }
+}
+
+impl Tx<CollaborativeCancel> {
+    /// Spend the lock output through the cooperative path to unwind the swap without waiting
+    /// for the cancel timelock, sending the funds following the agreed split. Bob's output comes
+    /// first, then Alice's if her share is not zero.
+    ///
+    /// Alice must only hand over her signature encrypted with Bob's adaptor key, as for the
+    /// refund: once Bob decrypts it and broadcasts the transaction, Alice recovers Bob's key
+    /// share from the witness, see [`Self::extract_witness_signature`], and with it her locked
+    /// accordant funds.
+    ///
+    /// Returns [`FError::NotEnoughAssets`] if a share is below the dust limit of its address.
+    pub fn initialize(lock: &LockTx, split: &CollaborativeSplit) -> Result<Self, FError> {
+        let output_metadata = lock.get_consumable_output()?;
+
+        let bob_amount = split
+            .alice_amount
+            .checked_add(split.fee)
+            .and_then(|spent| Amount::from_sat(output_metadata.tx_out.value).checked_sub(spent))
+            .ok_or(FError::NotEnoughAssets)?;
+
+        let mut outputs = vec![(split.bob_target.script_pubkey(), bob_amount)];
+        if split.alice_amount != Amount::ZERO {
+            outputs.push((split.alice_target.script_pubkey(), split.alice_amount));
+        }
+        outputs
+            .iter()
+            .all(|(script, amount)| *amount >= script.dust_value())
+            .then(|| ())
+            .ok_or(FError::NotEnoughAssets)?;
+
+        let unsigned_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: output_metadata.out_point,
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: outputs
+                .into_iter()
+                .map(|(script_pubkey, amount)| TxOut {
+                    value: amount.as_sat(),
+                    script_pubkey,
+                })
+                .collect(),
+        };
+
+        let mut psbt =
+            PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
+
+        // Set the input witness data and sighash type
+        psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
+        psbt.inputs[0].witness_script = output_metadata.script_pubkey;
+        psbt.inputs[0].redeem_script = output_metadata.redeem_script;
+
+        Ok(Tx::new(psbt))
+    }
+
+    /// Verify that the transaction proposed by the counter-party spends the lock and follows the
+    /// agreed split.
+    pub fn verify_template(&self, lock: &LockTx, split: &CollaborativeSplit) -> Result<(), FError> {
+        let expected = Self::initialize(lock, split)?;
+        (self.psbt.unsigned_tx == expected.psbt.unsigned_tx)
+            .then(|| ())
+            .ok_or(FError::WrongTemplate(
+                "Transaction does not follow the split",
+            ))?;
+        let input = &self.psbt.inputs[0];
+        let expected_input = &expected.psbt.inputs[0];
+        (input.witness_utxo == expected_input.witness_utxo
+            && input.witness_script == expected_input.witness_script)
+            .then(|| ())
+            .ok_or(FError::InvalidTransactionChain)
+    }
+
+    /// Extract Alice's decrypted adaptor signature from the witness of a collaborative cancel
+    /// seen on-chain.
+    pub fn extract_witness_signature(tx: &bitcoin::Transaction) -> Result<Signature, FError> {
+        let witness = match tx.input.as_slice() {
+            [TxIn { witness, .. }] => witness.to_vec(),
+            _ => return Err(FError::WrongTemplate("Number of inputs is not 1")),
+        };
+        match witness.as_slice() {
+            [_bob_sig, alice_sig, script] => {
+                CoopLock::from_script(&bitcoin::Script::from(script.clone()))?;
+                extract_sighash_all_signature(alice_sig)
+            }
+            [] => Err(FError::MissingWitness),
+            _ => Err(FError::WrongTemplate("Unexpected witness stack")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::OutPoint;
+
+    use crate::bitcoin::segwitv0::{sign_hash, FundingTx};
+    use crate::bitcoin::test_helpers::{address, pubkey, secp, secret};
+    use crate::bitcoin::timelock::CSVTimelock;
+    use crate::blockchain::Network;
+    use crate::script::{DataLock, ScriptPath, SwapRoleKeys};
+    use crate::transaction::{
+        Broadcastable, Chainable, Finalizable, Fundable, Lockable, Transaction, Witnessable,
+    };
+
+    fn lock() -> LockTx {
+        let mut funding = FundingTx::initialize(pubkey(1), Network::Local).unwrap();
+        let funding_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: funding.get_address().unwrap().script_pubkey(),
+            }],
+        };
+        funding.update(funding_tx).unwrap();
+        let data_lock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+        };
+        LockTx::initialize(&funding, data_lock, Amount::from_sat(100_000)).unwrap()
+    }
+
+    #[test]
+    fn unwind_swap_cooperatively() {
+        let lock = lock();
+        let split = CollaborativeSplit {
+            bob_target: address(4),
+            alice_target: address(5),
+            alice_amount: Amount::from_sat(10_000),
+            fee: Amount::from_sat(500),
+        };
+        let mut tx = Tx::<CollaborativeCancel>::initialize(&lock, &split).unwrap();
+        assert!(tx.is_build_on_top_of(&lock).is_ok());
+        assert!(tx.verify_template(&lock, &split).is_ok());
+        assert_eq!(tx.as_partial().unsigned_tx.output[0].value, 89_500);
+
+        let other_split = CollaborativeSplit {
+            alice_amount: Amount::from_sat(20_000),
+            ..split.clone()
+        };
+        assert!(matches!(
+            tx.verify_template(&lock, &other_split),
+            Err(FError::WrongTemplate(_))
+        ));
+
+        let msg = tx.generate_witness_message(ScriptPath::Success).unwrap();
+        let alice_sig = sign_hash(secp(), msg, &secret(2)).unwrap();
+        let bob_sig = sign_hash(secp(), msg, &secret(3)).unwrap();
+        tx.add_witness(pubkey(2), alice_sig).unwrap();
+        assert!(matches!(tx.finalize(), Err(FError::MissingSignature)));
+        tx.add_witness(pubkey(3), bob_sig).unwrap();
+        tx.finalize().unwrap();
+        let signed = tx.extract();
+        assert_eq!(
+            Tx::<CollaborativeCancel>::extract_witness_signature(&signed).unwrap(),
+            alice_sig
+        );
+
+        // Without a share Alice has no output, shares under dust are rejected
+        let bob_only = CollaborativeSplit {
+            alice_amount: Amount::ZERO,
+            ..split.clone()
+        };
+        let tx = Tx::<CollaborativeCancel>::initialize(&lock, &bob_only).unwrap();
+        assert_eq!(tx.as_partial().unsigned_tx.output.len(), 1);
+        let dust = CollaborativeSplit {
+            alice_amount: Amount::from_sat(100),
+            ..split
+        };
+        assert!(matches!(
+            Tx::<CollaborativeCancel>::initialize(&lock, &dust),
+            Err(FError::NotEnoughAssets)
+        ));
+    }
+}
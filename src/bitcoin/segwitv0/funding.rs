@@ -15,16 +15,26 @@
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
 
 //! Implementation for handeling the funding transaction on-chain.
+//!
+//! Funding addresses must never be reused across swaps: a reused address links the swaps
+//! on-chain and the funding detection of one swap would pick up the funding of the other. The
+//! [`FundingAddresses`] book records the address handed out to each swap.
 
+use std::collections::HashMap;
+
+use bitcoin::blockdata::script::Script;
 use bitcoin::blockdata::transaction::{OutPoint, Transaction};
 use bitcoin::network::constants::Network as BtcNetwork;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::Address;
+use bitcoin::{Amount, Denomination};
 
 use crate::blockchain::Network;
 use crate::consensus::{CanonicalBytes, Decodable, Encodable};
+use crate::swap::SwapId;
 use crate::transaction::{Error as FError, Fundable, Linkable};
 
+use crate::bitcoin::tag::SwapTag;
 use crate::bitcoin::transaction::{Error, MetadataOutput};
 
 /// Manages the steps to handle on-chain funding. Receives the public key derived from the key
@@ -36,6 +46,78 @@ pub struct Funding {
     seen_tx: Option<Transaction>,
 }
 
+impl Funding {
+    /// Verifies the value of the funding output seen on-chain against the targeted swap amount.
+    /// The output must hold at least `target_amount` and at most `target_amount` plus `max_fee`,
+    /// the fee budget left for the lock transaction.
+    ///
+    /// Returns [`FError::NotEnoughAssets`] if the output is underfunded and [`FError::Overfunded`]
+    /// if it holds more than expected. In the latter case the excess can be returned to the funder
+    /// with a [`SurplusTx`](crate::bitcoin::segwitv0::SurplusTx) before locking the funds.
+    pub fn verify_funded_amount(
+        &self,
+        target_amount: Amount,
+        max_fee: Amount,
+    ) -> Result<(), FError> {
+        let value = Amount::from_sat(self.get_consumable_output()?.tx_out.value);
+        if value < target_amount {
+            return Err(FError::NotEnoughAssets);
+        }
+        let max_amount = target_amount
+            .checked_add(max_fee)
+            .ok_or(FError::InvalidTargetAmount)?;
+        if value > max_amount {
+            return Err(FError::Overfunded);
+        }
+        Ok(())
+    }
+}
+
+impl Funding {
+    /// Return the swap tag carried by the funding transaction seen on-chain, if any. The external
+    /// wallet adds it with [`SwapTag::tx_out`].
+    pub fn tag(&self) -> Option<SwapTag> {
+        self.seen_tx.as_ref().and_then(SwapTag::from_transaction)
+    }
+
+    /// Return a [`BIP-21`][bip-21] URI requesting exactly `amount` on the funding address,
+    /// labelled with the swap identifier, e.g.
+    /// `bitcoin:bc1q...?amount=0.0015&label=farcaster-swap-<swap id>`.
+    ///
+    /// [bip-21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+    pub fn bip21_uri(&self, amount: Amount, swap_id: SwapId) -> Result<String, FError> {
+        Ok(format!(
+            "bitcoin:{}?{}",
+            self.get_address()?,
+            bip21_params(amount, swap_id)
+        ))
+    }
+
+    /// Return the [`BIP-21`][bip-21] URI of [`Funding::bip21_uri`] with the scheme and the bech32
+    /// address in uppercase, so QR codes can encode them in the more compact alphanumeric mode.
+    /// The parameters are left untouched.
+    ///
+    /// [bip-21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+    pub fn bip21_qr_uri(&self, amount: Amount, swap_id: SwapId) -> Result<String, FError> {
+        Ok(format!(
+            "{}?{}",
+            self.get_address()?.to_qr_uri(),
+            bip21_params(amount, swap_id)
+        ))
+    }
+}
+
+// Amounts are expressed in bitcoins without trailing zeros, labels only contain unreserved
+// characters and do not need to be percent-encoded.
+fn bip21_params(amount: Amount, swap_id: SwapId) -> String {
+    let btc = amount.to_string_in(Denomination::Bitcoin);
+    let btc = match btc.contains('.') {
+        true => btc.trim_end_matches('0').trim_end_matches('.'),
+        false => &btc,
+    };
+    format!("amount={}&label=farcaster-swap-{}", btc, swap_id)
+}
+
 impl Linkable<MetadataOutput> for Funding {
     fn get_consumable_output(&self) -> Result<MetadataOutput, FError> {
         // Create a **COMPRESSED** ECDSA public key.
@@ -75,6 +157,8 @@ impl Linkable<MetadataOutput> for Funding {
                     out_point: OutPoint::new(t.txid(), ix as u32),
                     tx_out: tx_out.clone(),
                     script_pubkey: Some(Address::p2pkh(&pubkey, network).script_pubkey()),
+                    redeem_script: None,
+                    wtxid: Some(t.wtxid()),
                 })
                 .ok_or(FError::MissingUTXO),
             // The transaction has not been see yet, cannot infer the UTXO
@@ -130,6 +214,104 @@ impl Fundable<Transaction, MetadataOutput, Address, PublicKey> for Funding {
     }
 }
 
+/// Whether a funding address was already handed out, see [`FundingAddresses::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressUse {
+    /// The address was never used.
+    Fresh,
+    /// The address is used by the swap.
+    Used(SwapId),
+}
+
+/// The funding addresses handed out to the swaps, by output script. The book is persisted with the
+/// wallet and addresses are never released, even once their swap is over.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FundingAddresses {
+    used: HashMap<Script, SwapId>,
+}
+
+impl FundingAddresses {
+    /// Create an empty book.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return whether the address was already handed out.
+    pub fn check(&self, address: &Address) -> AddressUse {
+        match self.used.get(&address.script_pubkey()) {
+            Some(swap_id) => AddressUse::Used(*swap_id),
+            None => AddressUse::Fresh,
+        }
+    }
+
+    /// Record the funding address of the swap and return it. Registering the same swap twice is
+    /// allowed, fails with [`FError::AddressReuse`] if the address is used by another swap.
+    pub fn register(&mut self, swap_id: SwapId, funding: &Funding) -> Result<Address, FError> {
+        let address = funding.get_address()?;
+        match self.check(&address) {
+            AddressUse::Used(other) if other != swap_id => Err(FError::AddressReuse(other)),
+            _ => {
+                self.used.insert(address.script_pubkey(), swap_id);
+                Ok(address)
+            }
+        }
+    }
+
+    /// Return the swap funded by the transaction, if it pays to a registered address. Fails with
+    /// [`FError::AddressReuse`] if it pays to the addresses of multiple swaps.
+    pub fn funded_swap(&self, tx: &Transaction) -> Result<Option<SwapId>, FError> {
+        let mut swaps = tx
+            .output
+            .iter()
+            .filter_map(|txout| self.used.get(&txout.script_pubkey));
+        match swaps.next() {
+            Some(swap_id) => match swaps.find(|other| *other != swap_id) {
+                Some(other) => Err(FError::AddressReuse(*other)),
+                None => Ok(Some(*swap_id)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Return the number of addresses handed out.
+    pub fn len(&self) -> usize {
+        self.used.len()
+    }
+
+    /// Return true if no address was handed out.
+    pub fn is_empty(&self) -> bool {
+        self.used.is_empty()
+    }
+}
+
+impl Encodable for FundingAddresses {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        // Sorted by script for a deterministic encoding
+        let mut used: Vec<_> = self.used.iter().collect();
+        used.sort_by(|a, b| a.0.cmp(b.0));
+        let mut len = (used.len() as u32).consensus_encode(writer)?;
+        for (script, swap_id) in used {
+            len += script.as_bytes().to_vec().consensus_encode(writer)?;
+            len += swap_id.consensus_encode(writer)?;
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for FundingAddresses {
+    fn consensus_decode<D: std::io::Read>(d: &mut D) -> Result<Self, crate::consensus::Error> {
+        let len = u32::consensus_decode(d)?;
+        let mut used = HashMap::new();
+        for _ in 0..len {
+            let script = Script::from(Vec::<u8>::consensus_decode(d)?);
+            used.insert(script, SwapId::consensus_decode(d)?);
+        }
+        Ok(Self { used })
+    }
+}
+
+impl_strict_encoding!(FundingAddresses);
+
 impl Encodable for Funding {
     fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
         let mut len = self.pubkey.as_canonical_bytes().consensus_encode(writer)?;
@@ -150,3 +332,52 @@ impl Decodable for Funding {
 }
 
 impl_strict_encoding!(Funding);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    #[test]
+    fn funding_bip21_uri() {
+        let pubkey = PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let funding = Funding::initialize(pubkey, Network::Mainnet).unwrap();
+        let address = funding.get_address().unwrap();
+        let swap_id = SwapId::from(uuid::Uuid::from_u128(
+            0x67e55044_10b1_426f_9247_bb680e5fe0c8,
+        ));
+
+        assert_eq!(
+            funding
+                .bip21_uri(Amount::from_sat(150_000), swap_id)
+                .unwrap(),
+            format!(
+                "bitcoin:{}?amount=0.0015&label=farcaster-swap-67e55044-10b1-426f-9247-bb680e5fe0c8",
+                address
+            )
+        );
+        assert_eq!(
+            funding
+                .bip21_qr_uri(Amount::from_btc(2.0).unwrap(), swap_id)
+                .unwrap(),
+            format!(
+                "BITCOIN:{}?amount=2&label=farcaster-swap-67e55044-10b1-426f-9247-bb680e5fe0c8",
+                address.to_string().to_uppercase()
+            )
+        );
+
+        // The address cannot be derived without public key
+        let raw = Funding::raw(bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        })
+        .unwrap();
+        assert!(raw.bip21_uri(Amount::from_sat(1), swap_id).is_err());
+    }
+}
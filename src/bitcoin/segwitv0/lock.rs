@@ -14,8 +14,6 @@
 // License along with this library; if not, write to the Free Software
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
 
-use std::marker::PhantomData;
-
 use bitcoin::blockdata::transaction::{TxIn, TxOut};
 use bitcoin::blockdata::witness::Witness;
 use bitcoin::secp256k1::ecdsa::Signature;
@@ -23,11 +21,13 @@ use bitcoin::secp256k1::PublicKey;
 use bitcoin::util::psbt::PartiallySignedTransaction;
 use bitcoin::Address;
 use bitcoin::Amount;
+use bitcoin::Script;
 use bitcoin::Transaction;
 
 use crate::script;
 use crate::transaction::{Error as FError, Fundable, Lockable};
 
+use crate::bitcoin::segwitv0::witness::KEY_SPEND_WITNESS;
 use crate::bitcoin::segwitv0::CoopLock;
 use crate::bitcoin::segwitv0::Sha256dHash;
 use crate::bitcoin::timelock::CSVTimelock;
@@ -36,6 +36,40 @@ use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, Tx};
 #[derive(Debug)]
 pub struct Lock;
 
+/// The type of output created by the lock transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum LockOutputType {
+    /// A native SegWit v0 P2WSH output, the default.
+    P2wsh,
+    /// A P2WSH output nested in a P2SH output, for wallets not supporting bech32 addresses.
+    P2shP2wsh,
+}
+
+impl Default for LockOutputType {
+    fn default() -> Self {
+        Self::P2wsh
+    }
+}
+
+impl LockOutputType {
+    /// Return the output script locking to the witness `script`.
+    pub fn script_pubkey(&self, script: &Script) -> Script {
+        match self {
+            Self::P2wsh => script.to_v0_p2wsh(),
+            Self::P2shP2wsh => script.to_v0_p2wsh().to_p2sh(),
+        }
+    }
+
+    /// Return the redeem script of the output, if any.
+    pub fn redeem_script(&self, script: &Script) -> Option<Script> {
+        match self {
+            Self::P2wsh => None,
+            Self::P2shP2wsh => Some(script.to_v0_p2wsh()),
+        }
+    }
+}
+
 impl SubTransaction for Lock {
     fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
         let (pubkey, full_sig) = psbt.inputs[0]
@@ -49,6 +83,10 @@ impl SubTransaction for Lock {
         ]));
         Ok(())
     }
+
+    fn estimated_witness_weight() -> usize {
+        KEY_SPEND_WITNESS.max_size()
+    }
 }
 
 impl
@@ -68,9 +106,45 @@ impl
         prev: &impl Fundable<Transaction, MetadataOutput, Address, PublicKey>,
         lock: script::DataLock<CSVTimelock, PublicKey>,
         target_amount: Amount,
+    ) -> Result<Self, FError> {
+        Self::initialize_with_output_type(prev, lock, target_amount, LockOutputType::P2wsh)
+    }
+
+    fn verify_template(
+        &self,
+        lock: script::DataLock<CSVTimelock, PublicKey>,
+    ) -> Result<(), FError> {
+        self.verify_template_with_output_type(lock, LockOutputType::P2wsh)
+    }
+}
+
+impl Tx<Lock> {
+    /// Create the lock transaction with the given type of output, see [`LockOutputType`]. The
+    /// cancel and buy transactions built on top of it spend the output accordingly.
+    pub fn initialize_with_output_type(
+        prev: &impl Fundable<Transaction, MetadataOutput, Address, PublicKey>,
+        lock: script::DataLock<CSVTimelock, PublicKey>,
+        target_amount: Amount,
+        output_type: LockOutputType,
+    ) -> Result<Self, FError> {
+        Self::initialize_from_output(
+            prev.get_consumable_output()?,
+            lock,
+            target_amount,
+            output_type,
+        )
+    }
+
+    /// Create the lock transaction spending the given output, e.g. one of the outputs of a
+    /// transaction funding several swaps at once, see
+    /// [`BatchFunding`](crate::bitcoin::segwitv0::batch::BatchFunding).
+    pub fn initialize_from_output(
+        output_metadata: MetadataOutput,
+        lock: script::DataLock<CSVTimelock, PublicKey>,
+        target_amount: Amount,
+        output_type: LockOutputType,
     ) -> Result<Self, FError> {
         let script = CoopLock::script(lock);
-        let output_metadata = prev.get_consumable_output()?;
 
         if output_metadata.tx_out.value < target_amount.as_sat() {
             return Err(FError::NotEnoughAssets);
@@ -87,7 +161,7 @@ impl
             }],
             output: vec![TxOut {
                 value: target_amount.as_sat(),
-                script_pubkey: script.to_v0_p2wsh(),
+                script_pubkey: output_type.script_pubkey(&script),
             }],
         };
 
@@ -98,18 +172,20 @@ impl
         psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
         psbt.inputs[0].witness_script = output_metadata.script_pubkey;
 
-        // Set the script witness of the output
+        // Set the script witness and redeem script of the output
+        psbt.outputs[0].redeem_script = output_type.redeem_script(&script);
         psbt.outputs[0].witness_script = Some(script);
 
-        Ok(Tx {
-            psbt,
-            _t: PhantomData,
-        })
+        Ok(Tx::new(psbt))
     }
 
-    fn verify_template(
+    /// Verify the template of the lock transaction for the given type of output, see
+    /// [`LockOutputType`]. An optional anchor output and an optional swap tag are accepted after
+    /// the lock output.
+    pub fn verify_template_with_output_type(
         &self,
         lock: script::DataLock<CSVTimelock, PublicKey>,
+        output_type: LockOutputType,
     ) -> Result<(), FError> {
         (self.psbt.unsigned_tx.version == 2)
             .then(|| 0)
@@ -120,9 +196,13 @@ impl
         (self.psbt.unsigned_tx.input.len() == 1)
             .then(|| 0)
             .ok_or(FError::WrongTemplate("Number of inputs is not 1"))?;
-        (self.psbt.unsigned_tx.output.len() == 1)
+        let outputs = self.psbt.unsigned_tx.output.len();
+        let optional = self.anchor().is_some() as usize + self.tag().is_some() as usize;
+        (outputs == 1 + optional && self.auxiliary_outputs() == optional)
             .then(|| 0)
-            .ok_or(FError::WrongTemplate("Number of outputs is not 1"))?;
+            .ok_or(FError::WrongTemplate(
+                "Number of outputs is not 1 plus an optional anchor and tag",
+            ))?;
 
         let txin = &self.psbt.unsigned_tx.input[0];
         (txin.sequence == CSVTimelock::disable())
@@ -130,7 +210,7 @@ impl
             .ok_or(FError::WrongTemplate("Sequence timelock is not disabled"))?;
 
         let txout = &self.psbt.unsigned_tx.output[0];
-        let script_pubkey = CoopLock::v0_p2wsh(lock);
+        let script_pubkey = output_type.script_pubkey(&CoopLock::script(lock));
         (txout.script_pubkey == script_pubkey)
             .then(|| 0)
             .ok_or(FError::WrongTemplate("Script pubkey does not match"))?;
@@ -138,3 +218,188 @@ impl
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::OutPoint;
+    use bitcoin::secp256k1::{Message, Secp256k1};
+
+    use crate::bitcoin::anchor::AnchorOutput;
+    use crate::bitcoin::metadata::TxMetadata;
+    use crate::bitcoin::segwitv0::funding::Funding;
+    use crate::bitcoin::segwitv0::{CancelTx, LockTx};
+    use crate::bitcoin::tag::SwapTag;
+    use crate::bitcoin::test_helpers::{pubkey, secret};
+    use crate::blockchain::Network;
+    use crate::script::{DataPunishableLock, ScriptPath, SwapRoleKeys};
+    use crate::swap::SwapId;
+    use crate::transaction::{
+        Broadcastable, Cancelable, Chainable, Linkable, Transaction as _, TxLabel, Witnessable,
+    };
+    use crate::Uuid;
+
+    fn funding() -> Funding {
+        let mut funding = Funding::initialize(pubkey(1), Network::Local).unwrap();
+        let funding_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: funding.get_address().unwrap().script_pubkey(),
+            }],
+        };
+        funding.update(funding_tx).unwrap();
+        funding
+    }
+
+    #[test]
+    fn spend_nested_segwit_lock() {
+        let datalock = script::DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: SwapRoleKeys::new(pubkey(4), pubkey(5)),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: pubkey(4),
+            recovery: None,
+        };
+
+        let lock = LockTx::initialize_with_output_type(
+            &funding(),
+            datalock,
+            Amount::from_sat(100_000),
+            LockOutputType::P2shP2wsh,
+        )
+        .unwrap();
+        assert!(lock.psbt.unsigned_tx.output[0].script_pubkey.is_p2sh());
+        assert!(lock
+            .verify_template_with_output_type(datalock, LockOutputType::P2shP2wsh)
+            .is_ok());
+        assert!(lock.verify_template(datalock).is_err());
+
+        let mut cancel = CancelTx::initialize(&lock, datalock, punish_lock).unwrap();
+        assert_eq!(
+            cancel.psbt.inputs[0].redeem_script,
+            Some(CoopLock::script(datalock).to_v0_p2wsh())
+        );
+
+        // Sign with the lock keys and finalize the cancel
+        let secp = Secp256k1::new();
+        let msg = cancel
+            .generate_witness_message(ScriptPath::Failure)
+            .unwrap();
+        let msg = Message::from_slice(&msg[..]).unwrap();
+        for byte in [2, 3] {
+            let sig = secp.sign_ecdsa(&msg, &secret(byte));
+            cancel.add_witness(pubkey(byte), sig).unwrap();
+        }
+        let expected_txid = cancel.get_consumable_output().unwrap().out_point.txid;
+        let unsigned_wtxid = cancel.wtxid();
+        assert_eq!(unsigned_wtxid, cancel.extract().wtxid());
+        let tx = cancel.finalize_and_extract().unwrap();
+        // Finalizing changes the witness transaction id but not the transaction id
+        assert_ne!(cancel.wtxid(), unsigned_wtxid);
+        assert_eq!(cancel.wtxid(), tx.wtxid());
+        let output = cancel.get_consumable_output().unwrap();
+        assert_eq!(output.txid(), expected_txid);
+        assert_eq!(output.wtxid, Some(tx.wtxid()));
+        assert!(!tx.input[0].script_sig.is_empty());
+        assert_eq!(tx.input[0].witness.len(), 3);
+        // The txid used to chain the refund and punish includes the script sig
+        assert_eq!(tx.txid(), expected_txid);
+        assert_eq!(cancel.txid(), expected_txid);
+        assert_eq!(cancel.into_transaction(), tx);
+    }
+
+    #[test]
+    fn anchor_is_ignored_downstream() {
+        let datalock = script::DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: SwapRoleKeys::new(pubkey(4), pubkey(5)),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: pubkey(4),
+            recovery: None,
+        };
+
+        let mut lock = LockTx::initialize(&funding(), datalock, Amount::from_sat(99_000)).unwrap();
+        let txid = lock.txid();
+        lock.add_anchor(AnchorOutput::Keyed(pubkey(6))).unwrap();
+        assert_ne!(lock.txid(), txid);
+        assert_eq!(lock.anchor(), Some(AnchorOutput::Keyed(pubkey(6))));
+        assert!(lock.verify_template(datalock).is_ok());
+        // The funding covers a second anchor but the template accepts only one
+        lock.add_anchor(AnchorOutput::Ephemeral).unwrap();
+        assert!(lock.verify_template(datalock).is_err());
+        lock.as_partial_mut().unsigned_tx.output.pop();
+        lock.as_partial_mut().outputs.pop();
+        // The funding cannot cover an anchor when the lock spends all of it
+        assert!(
+            LockTx::initialize(&funding(), datalock, Amount::from_sat(100_000))
+                .unwrap()
+                .add_anchor(AnchorOutput::Ephemeral)
+                .is_err()
+        );
+
+        // The swap tag is accepted along the anchor, only once
+        let tag = SwapTag::new(SwapId(Uuid::new()));
+        lock.add_tag(tag);
+        assert_eq!(lock.tag(), Some(tag));
+        assert_eq!(lock.anchor(), Some(AnchorOutput::Keyed(pubkey(6))));
+        assert!(lock.verify_template(datalock).is_ok());
+        lock.add_tag(tag);
+        assert!(lock.verify_template(datalock).is_err());
+        lock.as_partial_mut().unsigned_tx.output.pop();
+        lock.as_partial_mut().outputs.pop();
+
+        // Metadata does not change the transaction
+        let txid = lock.txid();
+        lock.set_metadata(&TxMetadata {
+            label: Some(TxLabel::Lock),
+            ..Default::default()
+        });
+        assert_eq!(lock.txid(), txid);
+        assert_eq!(lock.metadata().unwrap().label, Some(TxLabel::Lock));
+
+        let output = lock.get_consumable_output().unwrap();
+        assert_eq!(output.out_point, OutPoint::new(lock.txid(), 0));
+        assert_eq!(output.tx_out.value, 99_000);
+        let cancel = CancelTx::initialize(&lock, datalock, punish_lock).unwrap();
+        assert!(cancel.is_build_on_top_of(&lock).is_ok());
+    }
+
+    #[test]
+    fn borrowed_accessors() {
+        let datalock = script::DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: SwapRoleKeys::new(pubkey(4), pubkey(5)),
+        };
+        let mut lock = LockTx::initialize(&funding(), datalock, Amount::from_sat(100_000)).unwrap();
+        let txid = lock.txid();
+        assert_eq!(txid, lock.unsigned_tx().txid());
+        assert_eq!(lock.as_psbt().unsigned_tx.txid(), txid);
+
+        // The cached id is reset when the transaction is modified
+        lock.as_partial_mut().unsigned_tx.lock_time = 1;
+        assert_ne!(lock.txid(), txid);
+        assert_eq!(lock.txid(), lock.unsigned_tx().txid());
+        let txid = lock.txid();
+        lock.add_tag(SwapTag::new(SwapId(Uuid::new())));
+        assert_ne!(lock.txid(), txid);
+        assert_eq!(lock.txid(), lock.unsigned_tx().txid());
+    }
+}
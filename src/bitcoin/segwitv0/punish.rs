@@ -14,9 +14,9 @@
 // License along with this library; if not, write to the Free Software
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
 
-use std::marker::PhantomData;
-
-use bitcoin::blockdata::transaction::{TxIn, TxOut};
+use bitcoin::blockdata::opcodes;
+use bitcoin::blockdata::script::{Builder, Script};
+use bitcoin::blockdata::transaction::{EcdsaSighashType, TxIn, TxOut};
 use bitcoin::blockdata::witness::Witness;
 use bitcoin::secp256k1::ecdsa::Signature;
 use bitcoin::secp256k1::PublicKey;
@@ -28,13 +28,165 @@ use bitcoin::Transaction;
 use crate::role::SwapRole;
 use crate::script;
 use crate::script::ScriptPath;
-use crate::transaction::{Cancelable, Error, Punishable};
+use crate::transaction::{Cancelable, Error, Punishable, Transaction as _};
 
+use crate::bitcoin::segwitv0::witness::PUNISH_WITNESS;
 use crate::bitcoin::segwitv0::PunishLock;
 use crate::bitcoin::segwitv0::Sha256dHash;
 use crate::bitcoin::timelock::CSVTimelock;
 use crate::bitcoin::transaction::{self, MetadataOutput, SubTransaction, Tx};
 
+/// Maximum number of basis points a [`PunishPolicy`] can distribute, i.e. 100%.
+pub const MAX_BASIS_POINTS: u16 = 10_000;
+
+/// The BIP-341 NUMS point `lift_x(SHA256(G))` with an even Y coordinate, a public key nobody
+/// knows the secret key of, used by [`PunishBurn::UnspendableKey`].
+pub const NUMS_POINT: [u8; 33] = [
+    0x02, 0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a,
+    0x5e, 0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a,
+    0xc0,
+];
+
+/// A provably unspendable output burning a share of the punished amount. Burning part of the
+/// punishment lowers the incentive for Alice to induce the failure of the swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum PunishBurn {
+    /// An `OP_RETURN` output, not added to the UTXO set.
+    OpReturn,
+    /// A P2WPKH output paying to the [`NUMS_POINT`].
+    UnspendableKey,
+}
+
+impl PunishBurn {
+    /// Return the output script of the burn.
+    pub fn script_pubkey(&self) -> Script {
+        match self {
+            Self::OpReturn => Builder::new()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .into_script(),
+            Self::UnspendableKey => {
+                let key = bitcoin::PublicKey::from_slice(&NUMS_POINT).expect("valid point");
+                Script::new_v0_p2wpkh(&key.wpubkey_hash().expect("compressed key"))
+            }
+        }
+    }
+}
+
+/// A share of the punished amount sent to a destination, expressed in basis points (1/100th of a
+/// percent) of the cancel output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PunishShare {
+    /// The address receiving the share.
+    pub destination: Address,
+    /// The share of the punished amount in basis points.
+    pub basis_points: u16,
+}
+
+/// Splits the punish output across multiple destinations, e.g. to share the punishment proceeds
+/// with a watchtower service, or to burn a part of it, see [`PunishBurn`]. The punisher always
+/// receives the remaining amount after all the shares are distributed and pays the transaction
+/// fee.
+///
+/// The policy changes the punish template and must be agreed upon before the swap starts: both
+/// parties build the punish from the same policy, see
+/// [`SetupParameters`](crate::bitcoin::segwitv0::setup::SetupParameters), and Bob can check the
+/// outputs with [`Tx::verify_policy`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PunishPolicy {
+    shares: Vec<PunishShare>,
+    burn: Option<(PunishBurn, u16)>,
+}
+
+impl PunishPolicy {
+    /// Create a new policy without any share, the punisher receives the full amount.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a share of `basis_points` sent to `destination`.
+    pub fn with_share(mut self, destination: Address, basis_points: u16) -> Self {
+        self.shares.push(PunishShare {
+            destination,
+            basis_points,
+        });
+        self
+    }
+
+    /// Burn `basis_points` of the punished amount, replacing any previous burn.
+    pub fn with_burn(mut self, burn: PunishBurn, basis_points: u16) -> Self {
+        self.burn = Some((burn, basis_points));
+        self
+    }
+
+    /// Return the list of shares defined in the policy.
+    pub fn shares(&self) -> &[PunishShare] {
+        &self.shares
+    }
+
+    /// Return the burn defined in the policy and its share in basis points, if any.
+    pub fn burn(&self) -> Option<(PunishBurn, u16)> {
+        self.burn
+    }
+
+    /// Validate that the shares and the burn do not distribute more than [`MAX_BASIS_POINTS`].
+    pub fn validate(&self) -> Result<(), transaction::Error> {
+        let total: u32 = self
+            .shares
+            .iter()
+            .map(|s| s.basis_points)
+            .chain(self.burn.map(|(_, basis_points)| basis_points))
+            .map(u32::from)
+            .sum();
+        (total <= MAX_BASIS_POINTS as u32)
+            .then(|| ())
+            .ok_or(transaction::Error::InvalidPunishPolicy)
+    }
+
+    /// Compute the outputs for a punished `amount`, the first output pays the remaining amount to
+    /// `destination_target` and the burn, if any, is the last output. Every output must be above
+    /// the dust limit of its script, burns must not be empty.
+    fn outputs(
+        &self,
+        amount: Amount,
+        destination_target: &Address,
+    ) -> Result<Vec<TxOut>, transaction::Error> {
+        self.validate()?;
+        // Cannot overflow as the basis points are at most `MAX_BASIS_POINTS`
+        let share_of = |basis_points: u16| {
+            (amount.as_sat() as u128 * basis_points as u128 / MAX_BASIS_POINTS as u128) as u64
+        };
+        let shares: Vec<TxOut> = self
+            .shares
+            .iter()
+            .map(|share| (share.destination.script_pubkey(), share.basis_points))
+            .chain(
+                self.burn
+                    .map(|(burn, basis_points)| (burn.script_pubkey(), basis_points)),
+            )
+            .map(|(script_pubkey, basis_points)| TxOut {
+                value: share_of(basis_points),
+                script_pubkey,
+            })
+            .collect();
+        let distributed: u64 = shares.iter().map(|txout| txout.value).sum();
+        let mut outputs = vec![TxOut {
+            value: amount
+                .checked_sub(Amount::from_sat(distributed))
+                .ok_or(transaction::Error::InvalidPunishPolicy)?
+                .as_sat(),
+            script_pubkey: destination_target.script_pubkey(),
+        }];
+        outputs.extend(shares);
+        for txout in outputs.iter() {
+            if txout.value == 0 || txout.value < txout.script_pubkey.dust_value().as_sat() {
+                return Err(transaction::Error::DustOutput(txout.value));
+            }
+        }
+        Ok(outputs)
+    }
+}
+
 #[derive(Debug)]
 pub struct Punish;
 
@@ -63,6 +215,34 @@ impl SubTransaction for Punish {
         ]));
         Ok(())
     }
+
+    fn estimated_witness_weight() -> usize {
+        PUNISH_WITNESS.max_size()
+    }
+
+    fn required_signers(
+        psbt: &PartiallySignedTransaction,
+        index: usize,
+    ) -> Result<Vec<bitcoin::PublicKey>, Error> {
+        let script = psbt.inputs[index]
+            .witness_script
+            .as_ref()
+            .ok_or(Error::MissingWitness)?;
+        let swaplock = PunishLock::from_script(script)?;
+        let key = swaplock
+            .get_pubkey(SwapRole::Alice, ScriptPath::Success)
+            .ok_or(Error::MissingPublicKey)?;
+        Ok(vec![bitcoin::PublicKey::new(*key)])
+    }
+
+    /// Alice can sign the punish with `SIGHASH_ALL|ANYONECANPAY` and add fee inputs when
+    /// broadcasting it, the outputs stay committed.
+    fn accepts_sighash_type(sighash_type: EcdsaSighashType) -> bool {
+        matches!(
+            sighash_type,
+            EcdsaSighashType::All | EcdsaSighashType::AllPlusAnyoneCanPay
+        )
+    }
 }
 
 impl
@@ -117,9 +297,346 @@ impl
         psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
         psbt.inputs[0].witness_script = output_metadata.script_pubkey;
 
-        Ok(Tx {
-            psbt,
-            _t: PhantomData,
-        })
+        Ok(Tx::new(psbt))
+    }
+}
+
+impl Tx<Punish> {
+    /// Creates a new punish transaction splitting the cancel output according to the `policy`.
+    /// The first output pays `destination_target` and receives the remaining amount, thus the
+    /// fee is taken from it when set, other outputs pay the policy shares.
+    pub fn initialize_with_policy(
+        prev: &impl Cancelable<
+            Address,
+            Transaction,
+            PartiallySignedTransaction,
+            MetadataOutput,
+            Amount,
+            CSVTimelock,
+            Sha256dHash,
+            PublicKey,
+            Signature,
+        >,
+        punish_lock: script::DataPunishableLock<CSVTimelock, PublicKey>,
+        destination_target: Address,
+        policy: &PunishPolicy,
+    ) -> Result<Self, Error> {
+        let mut punish = Self::initialize(prev, punish_lock, destination_target.clone())?;
+        let value = Amount::from_sat(
+            punish.psbt.inputs[0]
+                .witness_utxo
+                .as_ref()
+                .ok_or(Error::MissingWitness)?
+                .value,
+        );
+        let outputs = policy.outputs(value, &destination_target)?;
+        let psbt = punish.as_partial_mut();
+        psbt.outputs = vec![Default::default(); outputs.len()];
+        psbt.unsigned_tx.output = outputs;
+        Ok(punish)
+    }
+
+    /// Verify that the outputs of the punish follow the agreed `policy`: the first output pays
+    /// `destination_target`, it carries the fee, and the others pay exactly the shares and the
+    /// burn of the policy. Fails with [`Error::WrongTemplate`] otherwise.
+    pub fn verify_policy(
+        &self,
+        policy: &PunishPolicy,
+        destination_target: &Address,
+    ) -> Result<(), Error> {
+        let value = Amount::from_sat(
+            self.psbt.inputs[0]
+                .witness_utxo
+                .as_ref()
+                .ok_or(Error::MissingWitness)?
+                .value,
+        );
+        let expected = policy.outputs(value, destination_target)?;
+        let outputs = &self.psbt.unsigned_tx.output;
+        (outputs.len() == expected.len()
+            && outputs[0].script_pubkey == expected[0].script_pubkey
+            && outputs[1..] == expected[1..])
+            .then(|| ())
+            .ok_or(Error::WrongTemplate(
+                "Punish outputs do not follow the policy",
+            ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::OutPoint;
+    use bitcoin::util::ecdsa::EcdsaSig;
+
+    use crate::bitcoin::segwitv0::{sign_hash, CancelTx, FundingTx, LockTx};
+    use crate::bitcoin::test_helpers::{address, pubkey, secp, secret};
+    use crate::blockchain::Network as FNetwork;
+    use crate::script::{DataLock, DataPunishableLock, SwapRoleKeys};
+    use crate::transaction::{Broadcastable, Finalizable, Fundable, Lockable, Witnessable};
+
+    #[test]
+    fn split_punish_outputs() {
+        let policy = PunishPolicy::new()
+            .with_share(address(2), 2_000)
+            .with_share(address(3), 500);
+        let outputs = policy
+            .outputs(Amount::from_sat(100_001), &address(1))
+            .unwrap();
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(outputs[0].value, 75_001);
+        assert_eq!(outputs[0].script_pubkey, address(1).script_pubkey());
+        assert_eq!(outputs[1].value, 20_000);
+        assert_eq!(outputs[2].value, 5_000);
+    }
+
+    #[test]
+    fn invalid_punish_policies() {
+        let policy = PunishPolicy::new()
+            .with_share(address(2), 6_000)
+            .with_share(address(3), 5_000);
+        assert!(matches!(
+            policy.validate(),
+            Err(transaction::Error::InvalidPunishPolicy)
+        ));
+
+        let policy = PunishPolicy::new().with_share(address(2), 1);
+        assert!(matches!(
+            policy.outputs(Amount::from_sat(100_000), &address(1)),
+            Err(transaction::Error::DustOutput(10))
+        ));
+
+        let policy = PunishPolicy::new().with_share(address(2), MAX_BASIS_POINTS);
+        assert!(matches!(
+            policy.outputs(Amount::from_sat(100_000), &address(1)),
+            Err(transaction::Error::DustOutput(0))
+        ));
+    }
+
+    #[test]
+    fn burn_part_of_the_punishment() {
+        assert!(PublicKey::from_slice(&NUMS_POINT).is_ok());
+        let policy = PunishPolicy::new()
+            .with_share(address(2), 1_000)
+            .with_burn(PunishBurn::OpReturn, 5_000);
+        let outputs = policy
+            .outputs(Amount::from_sat(100_000), &address(1))
+            .unwrap();
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(outputs[0].value, 40_000);
+        assert_eq!(outputs[2].value, 50_000);
+        assert!(outputs[2].script_pubkey.is_op_return());
+
+        let policy = policy.with_burn(PunishBurn::UnspendableKey, 5_000);
+        let outputs = policy
+            .outputs(Amount::from_sat(100_000), &address(1))
+            .unwrap();
+        assert_eq!(
+            outputs[2].script_pubkey,
+            PunishBurn::UnspendableKey.script_pubkey()
+        );
+        assert!(outputs[2].script_pubkey.is_v0_p2wpkh());
+
+        // Burns count toward the distributed amount and cannot be empty
+        let policy = PunishPolicy::new()
+            .with_share(address(2), 6_000)
+            .with_burn(PunishBurn::OpReturn, 5_000);
+        assert!(matches!(
+            policy.validate(),
+            Err(transaction::Error::InvalidPunishPolicy)
+        ));
+        let policy = PunishPolicy::new().with_burn(PunishBurn::OpReturn, 1);
+        assert!(matches!(
+            policy.outputs(Amount::from_sat(1_000), &address(1)),
+            Err(transaction::Error::DustOutput(0))
+        ));
+    }
+
+    #[test]
+    fn verify_punish_policy() {
+        let (cancel, _) = punish();
+        let keys = SwapRoleKeys::new(pubkey(2), pubkey(3));
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(10),
+            success: keys,
+            failure: pubkey(2),
+            recovery: None,
+        };
+        let policy = PunishPolicy::new().with_burn(PunishBurn::OpReturn, 2_000);
+        let mut punish =
+            Tx::<Punish>::initialize_with_policy(&cancel, punish_lock, address(4), &policy)
+                .unwrap();
+        punish.verify_policy(&policy, &address(4)).unwrap();
+        // The fee is taken from the punisher output only
+        punish.psbt.unsigned_tx.output[0].value -= 1_000;
+        punish.verify_policy(&policy, &address(4)).unwrap();
+
+        assert!(punish
+            .verify_policy(&PunishPolicy::new(), &address(4))
+            .is_err());
+        let other = PunishPolicy::new().with_burn(PunishBurn::UnspendableKey, 2_000);
+        assert!(punish.verify_policy(&other, &address(4)).is_err());
+        assert!(punish.verify_policy(&policy, &address(5)).is_err());
+    }
+
+    #[test]
+    fn reject_dust_punisher_output_after_fee() {
+        use crate::bitcoin::fee::SatPerKvB;
+        use crate::blockchain::{Fee, FeePriority, FeeStrategy, FeeStrategyError};
+
+        let (cancel, _) = punish();
+        let keys = SwapRoleKeys::new(pubkey(2), pubkey(3));
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(10),
+            success: keys,
+            failure: pubkey(2),
+            recovery: None,
+        };
+        // The punisher output is above the dust limit before the fee is set
+        let policy = PunishPolicy::new().with_burn(PunishBurn::OpReturn, 9_960);
+        let mut punish =
+            Tx::<Punish>::initialize_with_policy(&cancel, punish_lock, address(4), &policy)
+                .unwrap();
+        assert_eq!(punish.psbt.unsigned_tx.output[0].value, 400);
+        assert!(matches!(
+            punish.set_fee(
+                &FeeStrategy::Fixed(SatPerKvB::from_sat(1_000)),
+                FeePriority::Low
+            ),
+            Err(FeeStrategyError::Other(_))
+        ));
+    }
+
+    fn punish() -> (CancelTx, Tx<Punish>) {
+        let mut funding = FundingTx::initialize(pubkey(1), FNetwork::Local).unwrap();
+        let funding_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: funding.get_address().unwrap().script_pubkey(),
+            }],
+        };
+        funding.update(funding_tx).unwrap();
+        let keys = SwapRoleKeys::new(pubkey(2), pubkey(3));
+        let data_lock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: keys,
+            failure: keys,
+        };
+        let lock = LockTx::initialize(&funding, data_lock, Amount::from_sat(100_000)).unwrap();
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(10),
+            success: keys,
+            failure: pubkey(2),
+            recovery: None,
+        };
+        let cancel = CancelTx::initialize(&lock, data_lock, punish_lock).unwrap();
+        let punish = Tx::<Punish>::initialize(&cancel, punish_lock, address(4)).unwrap();
+        (cancel, punish)
+    }
+
+    #[test]
+    fn sign_punish_with_anyone_can_pay() {
+        let (mut cancel, mut punish) = punish();
+        assert_eq!(punish.sighash_type().unwrap(), EcdsaSighashType::All);
+        // Only the punish accepts extra inputs
+        assert!(cancel
+            .set_sighash_type(EcdsaSighashType::AllPlusAnyoneCanPay)
+            .is_err());
+        assert!(punish
+            .set_sighash_type(EcdsaSighashType::SinglePlusAnyoneCanPay)
+            .is_err());
+        punish
+            .set_sighash_type(EcdsaSighashType::AllPlusAnyoneCanPay)
+            .unwrap();
+
+        let msg = punish
+            .generate_witness_message(ScriptPath::Failure)
+            .unwrap();
+        let sig = sign_hash(secp(), msg, &secret(2)).unwrap();
+
+        // A signature committing to another sighash type is rejected at finalization
+        let mut other = Tx::<Punish>::new(punish.as_psbt().clone());
+        other.psbt.inputs[0].partial_sigs.insert(
+            bitcoin::PublicKey::new(pubkey(2)),
+            EcdsaSig::sighash_all(sig),
+        );
+        assert!(matches!(other.finalize(), Err(Error::WrongTemplate(_))));
+
+        punish.add_witness(pubkey(2), sig).unwrap();
+        punish.finalize().unwrap();
+        let witness = punish.psbt.inputs[0].final_script_witness.clone().unwrap();
+        assert_eq!(witness.to_vec()[0].last(), Some(&0x81));
+
+        // The signature stays valid once a fee input is added
+        let wtxid = punish.wtxid();
+        punish.as_partial_mut().unsigned_tx.input.push(TxIn {
+            previous_output: OutPoint::new(bitcoin::Txid::default(), 1),
+            script_sig: bitcoin::Script::default(),
+            sequence: 0xffffffff,
+            witness: Witness::new(),
+        });
+        assert_ne!(punish.wtxid(), wtxid);
+        assert_eq!(punish.wtxid(), punish.extract().wtxid());
+        assert_eq!(
+            punish
+                .generate_witness_message(ScriptPath::Failure)
+                .unwrap(),
+            msg
+        );
+    }
+
+    #[test]
+    fn sign_punish_with_replay_protection() {
+        use crate::bitcoin::sighash::{Bip143, SighashAlgorithm};
+        use crate::bitcoincash::BitcoinCash;
+
+        let (_, mut punish) = punish();
+        assert_eq!(punish.fork_id().unwrap(), None);
+        let bip143_msg = punish
+            .generate_witness_message(ScriptPath::Failure)
+            .unwrap();
+
+        let fork_id = BitcoinCash::replay_protection();
+        punish.set_fork_id(fork_id).unwrap();
+        // The replay protection is kept when changing the sighash type
+        punish
+            .set_sighash_type(EcdsaSighashType::AllPlusAnyoneCanPay)
+            .unwrap();
+        assert_eq!(punish.fork_id().unwrap(), Some(fork_id));
+        assert_eq!(
+            punish.sighash_type().unwrap(),
+            EcdsaSighashType::AllPlusAnyoneCanPay
+        );
+        assert_eq!(punish.psbt.inputs[0].sighash_type.unwrap().to_u32(), 0xc1);
+
+        let msg = punish
+            .generate_witness_message(ScriptPath::Failure)
+            .unwrap();
+        assert_eq!(
+            msg,
+            punish
+                .signature_hash_with(&fork_id, EcdsaSighashType::AllPlusAnyoneCanPay)
+                .unwrap()
+        );
+        assert_ne!(
+            msg,
+            punish
+                .signature_hash_with(&Bip143, EcdsaSighashType::AllPlusAnyoneCanPay)
+                .unwrap()
+        );
+        assert_ne!(msg, bip143_msg);
+        assert_eq!(
+            fork_id.sighash_byte(EcdsaSighashType::AllPlusAnyoneCanPay),
+            0xc1
+        );
     }
 }
@@ -14,26 +14,27 @@
 // License along with this library; if not, write to the Free Software
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
 
-use std::marker::PhantomData;
-
 use bitcoin::blockdata::transaction::{TxIn, TxOut};
 use bitcoin::blockdata::witness::Witness;
 use bitcoin::secp256k1::ecdsa::Signature;
-use bitcoin::secp256k1::PublicKey;
-use bitcoin::util::ecdsa::EcdsaSig;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, Verification};
 use bitcoin::util::psbt::PartiallySignedTransaction;
 use bitcoin::Address;
 use bitcoin::Amount;
 use bitcoin::Transaction;
 
+use crate::blockchain::FeeStrategy;
 use crate::role::SwapRole;
 use crate::script::ScriptPath;
-use crate::transaction::{Cancelable, Error as FError, Refundable};
+use crate::transaction::{Cancelable, Error as FError, Linkable, Refundable, Witnessable};
 
-use crate::bitcoin::segwitv0::PunishLock;
+use crate::bitcoin::fee::SatPerKvB;
+use crate::bitcoin::segwitv0::buy::extract_sighash_all_signature;
+use crate::bitcoin::segwitv0::witness::{REFUND_RECOVERY_WITNESS, REFUND_WITNESS};
 use crate::bitcoin::segwitv0::Sha256dHash;
+use crate::bitcoin::segwitv0::{verification_context, PunishLock};
 use crate::bitcoin::timelock::CSVTimelock;
-use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, Tx};
+use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, TemplateParams, Tx};
 
 #[derive(Debug)]
 pub struct Refund;
@@ -47,33 +48,132 @@ impl SubTransaction for Refund {
 
         let swaplock = PunishLock::from_script(&script)?;
 
-        let alice_sig = *psbt.inputs[0]
-            .partial_sigs
-            .get(&bitcoin::PublicKey::new(
-                *swaplock
-                    .get_pubkey(SwapRole::Alice, ScriptPath::Success)
-                    .ok_or(FError::MissingPublicKey)?,
-            ))
-            .ok_or(FError::MissingSignature)?;
-
-        let bob_sig = *psbt.inputs[0]
-            .partial_sigs
-            .get(&bitcoin::PublicKey::new(
-                *swaplock
-                    .get_pubkey(SwapRole::Bob, ScriptPath::Success)
-                    .ok_or(FError::MissingPublicKey)?,
-            ))
-            .ok_or(FError::MissingSignature)?;
-
-        psbt.inputs[0].final_script_witness = Some(Witness::from_vec(vec![
-            bob_sig.to_vec(),
-            alice_sig.to_vec(),
-            vec![1],             // OP_TRUE
-            script.into_bytes(), // cancel script
-        ]));
+        let alice = *swaplock
+            .get_pubkey(SwapRole::Alice, ScriptPath::Success)
+            .ok_or(FError::MissingPublicKey)?;
+        let bob = *swaplock
+            .get_pubkey(SwapRole::Bob, ScriptPath::Success)
+            .ok_or(FError::MissingPublicKey)?;
+        let partial_sigs = &psbt.inputs[0].partial_sigs;
+
+        let witness = match swaplock.recovery_key() {
+            None => {
+                let alice_sig = *partial_sigs
+                    .get(&bitcoin::PublicKey::new(alice))
+                    .ok_or(FError::MissingSignature)?;
+                let bob_sig = *partial_sigs
+                    .get(&bitcoin::PublicKey::new(bob))
+                    .ok_or(FError::MissingSignature)?;
+                vec![
+                    bob_sig.to_vec(),
+                    alice_sig.to_vec(),
+                    vec![1],             // OP_TRUE
+                    script.into_bytes(), // cancel script
+                ]
+            }
+            Some(recovery) => {
+                let alice_sig = *partial_sigs
+                    .get(&bitcoin::PublicKey::new(alice))
+                    .ok_or(FError::MissingSignature)?;
+                // Bob's signature or, if he lost his keys, the recovery key signature
+                let other_sig = *partial_sigs
+                    .get(&bitcoin::PublicKey::new(bob))
+                    .or_else(|| partial_sigs.get(&bitcoin::PublicKey::new(*recovery)))
+                    .ok_or(FError::MissingSignature)?;
+                vec![
+                    vec![], // CHECKMULTISIG pops an extra item
+                    other_sig.to_vec(),
+                    alice_sig.to_vec(),
+                    vec![1],             // OP_TRUE
+                    script.into_bytes(), // cancel script
+                ]
+            }
+        };
+
+        psbt.inputs[0].final_script_witness = Some(Witness::from_vec(witness));
 
         Ok(())
     }
+
+    fn estimated_witness_weight() -> usize {
+        REFUND_WITNESS.max_size()
+    }
+
+    fn estimated_input_weight(psbt: &PartiallySignedTransaction, index: usize) -> usize {
+        let recovery = psbt.inputs[index]
+            .witness_script
+            .as_ref()
+            .and_then(|script| PunishLock::from_script(script).ok())
+            .map_or(false, |swaplock| swaplock.recovery_key().is_some());
+        match recovery {
+            true => REFUND_RECOVERY_WITNESS.max_size(),
+            false => Self::estimated_witness_weight(),
+        }
+    }
+
+    fn required_signers(
+        psbt: &PartiallySignedTransaction,
+        index: usize,
+    ) -> Result<Vec<bitcoin::PublicKey>, FError> {
+        let script = psbt.inputs[index]
+            .witness_script
+            .as_ref()
+            .ok_or(FError::MissingWitness)?;
+        let swaplock = PunishLock::from_script(script)?;
+        let mut signers: Vec<bitcoin::PublicKey> = [SwapRole::Alice, SwapRole::Bob]
+            .iter()
+            .map(|role| {
+                swaplock
+                    .get_pubkey(*role, ScriptPath::Success)
+                    .map(|key| bitcoin::PublicKey::new(*key))
+                    .ok_or(FError::MissingPublicKey)
+            })
+            .collect::<Result<_, _>>()?;
+        signers.extend(
+            swaplock
+                .recovery_key()
+                .map(|key| bitcoin::PublicKey::new(*key)),
+        );
+        Ok(signers)
+    }
+
+    fn signature_threshold(
+        _psbt: &PartiallySignedTransaction,
+        _index: usize,
+    ) -> Result<usize, FError> {
+        // Two signatures with or without the recovery key
+        Ok(2)
+    }
+
+    fn has_required_signatures(
+        psbt: &PartiallySignedTransaction,
+        index: usize,
+    ) -> Result<bool, FError> {
+        // Alice's signature is mandatory, the recovery key only stands in for Bob's key
+        let signers = Self::required_signers(psbt, index)?;
+        let partial_sigs = &psbt.inputs[index].partial_sigs;
+        Ok(partial_sigs.contains_key(&signers[0])
+            && signers[1..]
+                .iter()
+                .any(|key| partial_sigs.contains_key(key)))
+    }
+}
+
+impl TemplateParams {
+    /// The values a refund transaction must follow: spending the cancel output without timelock
+    /// and paying the refund address.
+    pub fn refund(
+        cancel: &impl Linkable<MetadataOutput>,
+        refund_target: &Address,
+        fee_strategy: FeeStrategy<SatPerKvB>,
+    ) -> Result<Self, FError> {
+        Ok(Self {
+            prev_output: cancel.get_consumable_output()?,
+            sequence: 0,
+            script_pubkey: refund_target.script_pubkey(),
+            fee_strategy,
+        })
+    }
 }
 
 impl
@@ -127,10 +227,7 @@ impl
         psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
         psbt.inputs[0].witness_script = output_metadata.script_pubkey;
 
-        Ok(Tx {
-            psbt,
-            _t: PhantomData,
-        })
+        Ok(Tx::new(psbt))
     }
 
     fn verify_template(&self, refund_target: Address) -> Result<(), FError> {
@@ -161,11 +258,56 @@ impl
         Ok(())
     }
 
-    fn extract_witness(tx: bitcoin::Transaction) -> Signature {
-        let TxIn { witness, .. } = &tx.input[0];
-        let witness_bytes = witness.to_vec();
-        let ecdsa_sig = EcdsaSig::from_slice(witness_bytes[1].as_ref())
-            .expect("Validated transaction on-chain, signature and witness position is correct.");
-        ecdsa_sig.sig
+    fn extract_witness(&self, tx: bitcoin::Transaction) -> Result<Signature, FError> {
+        self.extract_witness_signature(&tx, verification_context())
+    }
+}
+
+impl Tx<Refund> {
+    /// Extract Alice's decrypted adaptor signature from the witness of this refund transaction
+    /// seen on-chain. The witness must follow the refund template, with or without recovery key,
+    /// with the `OP_TRUE` branch selector and the punishable lock script of this refund; an error
+    /// is returned otherwise, e.g. if the witness has been malleated. Alice's signature is always
+    /// required on the refund path, it is verified against her key and the witness message of the
+    /// refund with the `secp` context.
+    pub fn extract_witness_signature<C: Verification>(
+        &self,
+        tx: &bitcoin::Transaction,
+        secp: &Secp256k1<C>,
+    ) -> Result<Signature, FError> {
+        (tx.txid() == self.psbt.unsigned_tx.txid())
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Transaction is not this refund"))?;
+        let witness = match tx.input.as_slice() {
+            [TxIn { witness, .. }] => witness.to_vec(),
+            _ => return Err(FError::WrongTemplate("Number of inputs is not 1")),
+        };
+        let (alice_sig, branch, script) = match witness.as_slice() {
+            [_bob_sig, alice_sig, branch, script] => (alice_sig, branch, script),
+            [dummy, _other_sig, alice_sig, branch, script] if dummy.is_empty() => {
+                (alice_sig, branch, script)
+            }
+            [] => return Err(FError::MissingWitness),
+            _ => return Err(FError::WrongTemplate("Unexpected witness stack")),
+        };
+        if branch.as_slice() != [1] {
+            return Err(FError::WrongTemplate(
+                "Witness does not select the refund path",
+            ));
+        }
+        let script = bitcoin::Script::from(script.clone());
+        (self.psbt.inputs[0].witness_script.as_ref() == Some(&script))
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Witness script does not match"))?;
+        let alice = *PunishLock::from_script(&script)?
+            .get_pubkey(SwapRole::Alice, ScriptPath::Success)
+            .ok_or(FError::MissingPublicKey)?;
+
+        let msg = self.generate_witness_message(ScriptPath::Success)?;
+        let msg = Message::from_slice(&msg[..]).expect("Hash is always ok");
+        let sig = extract_sighash_all_signature(alice_sig)?;
+        secp.verify_ecdsa(&msg, &sig, &alice)
+            .map_err(|_| FError::MissingSignature)?;
+        Ok(sig)
     }
 }
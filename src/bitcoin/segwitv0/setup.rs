@@ -0,0 +1,789 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Pre-signing ceremony of the arbitrating transactions. [`ArbitratingSetup`] builds the whole
+//! dependent graph of a swap from the funding transaction:
+//!
+//! ```text
+//! lock -> buy
+//!      -> cancel -> refund
+//!                -> punish
+//! ```
+//!
+//! and orders the signatures that must be exchanged before funds are locked. A transaction is
+//! only broadcastable once it and all its dependents are pre-signed, so no party can lock or
+//! cancel without holding the transactions that get the funds back out:
+//!
+//! 1. Bob sends the [`CoreArbitratingSetup`] with his `cancel` signature,
+//! 2. Alice sends the [`RefundProcedureSignatures`] with her `cancel` signature and her `refund`
+//!    adaptor signature,
+//! 3. Bob sends the [`BuyProcedureSignature`] with his `buy` adaptor signature, then he can sign
+//!    and broadcast the `lock`.
+//!
+//! The `punish` is spent by Alice alone and needs no pre-signature.
+//!
+//! Recording a message with [`ArbitratingSetup::record`] verifies its signatures against the
+//! witness messages of the graph and the keys of the signer, the signatures are then kept, see
+//! [`ArbitratingSetup::signature`].
+//!
+//! [`CoreArbitratingSetup`]: crate::protocol::message::CoreArbitratingSetup
+//! [`RefundProcedureSignatures`]: crate::protocol::message::RefundProcedureSignatures
+//! [`BuyProcedureSignature`]: crate::protocol::message::BuyProcedureSignature
+
+use bitcoin::hashes::sha256d::Hash as Sha256dHash;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{ecdsa::Signature, Message, PublicKey};
+use bitcoin::{Address, Amount, Txid};
+use ecdsa_fun::adaptor::EncryptedSignature;
+use ecdsa_fun::fun::Point;
+
+use crate::bitcoin::anchor::AnchorOutput;
+use crate::bitcoin::fee::SatPerKvB;
+use crate::bitcoin::segwitv0::{
+    BuyTx, CancelTx, FundingTx, LockTx, PunishPolicy, PunishTx, RefundTx,
+};
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::truc::BroadcastMode;
+use crate::blockchain::{Fee, FeePriority, FeeStrategy, FeeStrategyError};
+use crate::crypto::context::CryptoContext;
+use crate::crypto::Error as CryptoError;
+use crate::role::SwapRole;
+use crate::script::{DataLock, DataPunishableLock, ScriptPath, SwapRoleKeys};
+use crate::swap::{self, SwapId};
+use crate::transaction::{Buyable, Cancelable, Lockable, Refundable, TxLabel, Witnessable};
+use crate::Res;
+
+/// The kind of a pre-signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum SignatureKind {
+    /// A regular signature.
+    Regular,
+    /// An adaptor signature, decrypted by the counter-party when broadcasting.
+    Adaptor,
+}
+
+/// A signature a party must produce before the funds are locked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display("{kind} signature of {signer} on {tx}")]
+pub struct PreSignature {
+    /// The transaction signed.
+    pub tx: TxLabel,
+    /// The party producing the signature.
+    pub signer: SwapRole,
+    /// The kind of signature.
+    pub kind: SignatureKind,
+}
+
+/// A pre-signature exchanged during the ceremony.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetupSignature {
+    /// A regular signature.
+    Regular(Signature),
+    /// An adaptor signature.
+    Adaptor(Box<EncryptedSignature>),
+}
+
+impl SetupSignature {
+    /// The kind of the signature.
+    pub fn kind(&self) -> SignatureKind {
+        match self {
+            Self::Regular(_) => SignatureKind::Regular,
+            Self::Adaptor(_) => SignatureKind::Adaptor,
+        }
+    }
+}
+
+const fn presig(tx: TxLabel, signer: SwapRole, kind: SignatureKind) -> PreSignature {
+    PreSignature { tx, signer, kind }
+}
+
+const CORE_ARBITRATING_SETUP_SIGNATURES: [PreSignature; 1] = [presig(
+    TxLabel::Cancel,
+    SwapRole::Bob,
+    SignatureKind::Regular,
+)];
+
+const REFUND_PROCEDURE_SIGNATURES: [PreSignature; 2] = [
+    presig(TxLabel::Cancel, SwapRole::Alice, SignatureKind::Regular),
+    presig(TxLabel::Refund, SwapRole::Alice, SignatureKind::Adaptor),
+];
+
+const BUY_PROCEDURE_SIGNATURE: [PreSignature; 1] =
+    [presig(TxLabel::Buy, SwapRole::Bob, SignatureKind::Adaptor)];
+
+/// The messages of the pre-signing ceremony, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum SetupMessage {
+    /// The [`CoreArbitratingSetup`](crate::protocol::message::CoreArbitratingSetup) message.
+    CoreArbitratingSetup,
+    /// The [`RefundProcedureSignatures`](crate::protocol::message::RefundProcedureSignatures)
+    /// message.
+    RefundProcedureSignatures,
+    /// The [`BuyProcedureSignature`](crate::protocol::message::BuyProcedureSignature) message.
+    BuyProcedureSignature,
+}
+
+impl SetupMessage {
+    /// All the messages of the ceremony, in the order they must be sent.
+    pub const ALL: [SetupMessage; 3] = [
+        Self::CoreArbitratingSetup,
+        Self::RefundProcedureSignatures,
+        Self::BuyProcedureSignature,
+    ];
+
+    /// The party sending the message.
+    pub fn sender(&self) -> SwapRole {
+        match self {
+            Self::CoreArbitratingSetup | Self::BuyProcedureSignature => SwapRole::Bob,
+            Self::RefundProcedureSignatures => SwapRole::Alice,
+        }
+    }
+
+    /// The pre-signatures carried by the message.
+    pub fn signatures(&self) -> &'static [PreSignature] {
+        match self {
+            Self::CoreArbitratingSetup => &CORE_ARBITRATING_SETUP_SIGNATURES,
+            Self::RefundProcedureSignatures => &REFUND_PROCEDURE_SIGNATURES,
+            Self::BuyProcedureSignature => &BUY_PROCEDURE_SIGNATURE,
+        }
+    }
+}
+
+/// The parameters of the arbitrating transactions.
+#[derive(Debug, Clone)]
+pub struct SetupParameters {
+    /// The lock of the funds, spent by the `buy` or the `cancel`.
+    pub lock: DataLock<CSVTimelock, PublicKey>,
+    /// The punishable lock of the `cancel`, spent by the `refund` or the `punish`.
+    pub punish_lock: DataPunishableLock<CSVTimelock, PublicKey>,
+    /// The amount locked.
+    pub target_amount: Amount,
+    /// Alice's address, receiving the `buy` and the `punish`.
+    pub alice_address: Address,
+    /// Bob's address, receiving the `refund`.
+    pub bob_address: Address,
+    /// The fee strategy of the deal.
+    pub fee_strategy: FeeStrategy<SatPerKvB>,
+    /// The fee priority applied on the transactions.
+    pub fee_politic: FeePriority,
+    /// The policy splitting the `punish` output, agreed upon by both parties.
+    pub punish_policy: PunishPolicy,
+    /// Alice's adaptor key, encrypting Bob's `buy` adaptor signature.
+    pub alice_adaptor: PublicKey,
+    /// Bob's adaptor key, encrypting Alice's `refund` adaptor signature.
+    pub bob_adaptor: PublicKey,
+    /// How the transactions are broadcast, agreed upon by both parties. In
+    /// [`BroadcastMode::Truc`] the `cancel` and `punish` are TRUC transactions with an ephemeral
+    /// anchor, see [`Tx::make_truc`](crate::bitcoin::transaction::Tx::make_truc).
+    pub broadcast_mode: BroadcastMode,
+}
+
+/// The arbitrating transactions of a swap and the progress of their pre-signing ceremony.
+#[derive(Debug)]
+pub struct ArbitratingSetup {
+    swap_id: SwapId,
+    lock: LockTx,
+    cancel: CancelTx,
+    refund: RefundTx,
+    buy: BuyTx,
+    punish: PunishTx,
+    data_lock: DataLock<CSVTimelock, PublicKey>,
+    punish_lock: DataPunishableLock<CSVTimelock, PublicKey>,
+    adaptors: SwapRoleKeys<PublicKey>,
+    sent: Vec<SetupMessage>,
+    signatures: Vec<(PreSignature, SetupSignature)>,
+}
+
+impl ArbitratingSetup {
+    /// Build the arbitrating transactions on top of the funding transaction, fees are set on all
+    /// the transactions spending the lock.
+    pub fn new(swap_id: SwapId, funding: &FundingTx, params: SetupParameters) -> Res<Self> {
+        let SetupParameters {
+            lock: data_lock,
+            punish_lock,
+            target_amount,
+            alice_address,
+            bob_address,
+            fee_strategy,
+            fee_politic,
+            punish_policy,
+            alice_adaptor,
+            bob_adaptor,
+            broadcast_mode,
+        } = params;
+
+        let lock = LockTx::initialize(funding, data_lock, target_amount)?;
+        lock.validate_fee(&fee_strategy)?
+            .then(|| ())
+            .ok_or(FeeStrategyError::OutOfStrategy)?;
+        let mut cancel = CancelTx::initialize(&lock, data_lock, punish_lock)?;
+        if broadcast_mode.is_truc(TxLabel::Cancel) {
+            cancel.make_truc(AnchorOutput::Ephemeral)?;
+        }
+        cancel.set_fee(&fee_strategy, fee_politic)?;
+        let mut refund = RefundTx::initialize(&cancel, bob_address)?;
+        refund.set_fee(&fee_strategy, fee_politic)?;
+        let mut buy = BuyTx::initialize(&lock, data_lock, alice_address.clone())?;
+        buy.set_fee(&fee_strategy, fee_politic)?;
+        let mut punish =
+            PunishTx::initialize_with_policy(&cancel, punish_lock, alice_address, &punish_policy)?;
+        if broadcast_mode.is_truc(TxLabel::Punish) {
+            punish.make_truc(AnchorOutput::Ephemeral)?;
+        }
+        punish.set_fee(&fee_strategy, fee_politic)?;
+
+        Ok(Self {
+            swap_id,
+            lock,
+            cancel,
+            refund,
+            buy,
+            punish,
+            data_lock,
+            punish_lock,
+            adaptors: SwapRoleKeys::new(alice_adaptor, bob_adaptor),
+            sent: vec![],
+            signatures: vec![],
+        })
+    }
+
+    /// The swap of the transactions.
+    pub fn swap_id(&self) -> SwapId {
+        self.swap_id
+    }
+
+    /// Return the `lock` transaction.
+    pub fn lock(&self) -> &LockTx {
+        &self.lock
+    }
+
+    /// Return the `cancel` transaction.
+    pub fn cancel(&self) -> &CancelTx {
+        &self.cancel
+    }
+
+    /// Return the `refund` transaction.
+    pub fn refund(&self) -> &RefundTx {
+        &self.refund
+    }
+
+    /// Return the `buy` transaction.
+    pub fn buy(&self) -> &BuyTx {
+        &self.buy
+    }
+
+    /// Return the `punish` transaction.
+    pub fn punish(&self) -> &PunishTx {
+        &self.punish
+    }
+
+    /// The labels of the transactions of the graph, parents first.
+    pub fn labels() -> [TxLabel; 5] {
+        [
+            TxLabel::Lock,
+            TxLabel::Cancel,
+            TxLabel::Refund,
+            TxLabel::Buy,
+            TxLabel::Punish,
+        ]
+    }
+
+    fn unsigned_tx(&self, label: TxLabel) -> Option<&bitcoin::Transaction> {
+        let psbt = match label {
+            TxLabel::Lock => self.lock.as_psbt(),
+            TxLabel::Cancel => self.cancel.as_psbt(),
+            TxLabel::Refund => self.refund.as_psbt(),
+            TxLabel::Buy => self.buy.as_psbt(),
+            TxLabel::Punish => self.punish.as_psbt(),
+            _ => return None,
+        };
+        Some(&psbt.unsigned_tx)
+    }
+
+    /// Return the transaction id of a transaction of the graph.
+    pub fn txid(&self, label: TxLabel) -> Option<Txid> {
+        self.unsigned_tx(label).map(|tx| tx.txid())
+    }
+
+    /// Return the transactions of the graph spending an output of the transaction.
+    pub fn dependents(&self, label: TxLabel) -> Vec<TxLabel> {
+        let txid = match self.txid(label) {
+            Some(txid) => txid,
+            None => return vec![],
+        };
+        Self::labels()
+            .into_iter()
+            .filter(|&other| {
+                self.unsigned_tx(other).map_or(false, |tx| {
+                    tx.input
+                        .iter()
+                        .any(|txin| txin.previous_output.txid == txid)
+                })
+            })
+            .collect()
+    }
+
+    /// All the pre-signatures of the ceremony, in the order they are produced.
+    pub fn required_signatures() -> Vec<PreSignature> {
+        SetupMessage::ALL
+            .iter()
+            .flat_map(|message| message.signatures().iter().copied())
+            .collect()
+    }
+
+    /// The messages the party must send, in order.
+    pub fn messages(role: SwapRole) -> Vec<SetupMessage> {
+        SetupMessage::ALL
+            .into_iter()
+            .filter(|message| message.sender() == role)
+            .collect()
+    }
+
+    /// The next message of the ceremony, if any.
+    pub fn next_message(&self) -> Option<SetupMessage> {
+        SetupMessage::ALL.get(self.sent.len()).copied()
+    }
+
+    /// Record that a message of the ceremony has been sent or received with its `signatures`,
+    /// in the order of [`SetupMessage::signatures`]. Fails with [`swap::Error::InvalidSetup`] if
+    /// the message is not the next one of the ceremony or the signatures are not of the expected
+    /// kinds, and with a [`CryptoError`] if a signature is not valid for the witness message of
+    /// the transaction and the key of the signer. The signatures are verified with the contexts
+    /// of `ctx`.
+    pub fn record(
+        &mut self,
+        ctx: &CryptoContext,
+        message: SetupMessage,
+        signatures: &[SetupSignature],
+    ) -> Res<()> {
+        (self.next_message() == Some(message))
+            .then(|| ())
+            .ok_or(swap::Error::InvalidSetup("Unexpected setup message"))?;
+        let expected = message.signatures();
+        (signatures.len() == expected.len()
+            && expected
+                .iter()
+                .zip(signatures)
+                .all(|(presig, sig)| presig.kind == sig.kind()))
+        .then(|| ())
+        .ok_or(swap::Error::InvalidSetup("Unexpected setup signatures"))?;
+        for (presig, sig) in expected.iter().zip(signatures) {
+            self.verify_signature(ctx, presig, sig)?;
+        }
+        self.sent.push(message);
+        self.signatures
+            .extend(expected.iter().copied().zip(signatures.iter().cloned()));
+        Ok(())
+    }
+
+    /// Return the recorded pre-signature, if its message has been recorded.
+    pub fn signature(&self, presig: &PreSignature) -> Option<&SetupSignature> {
+        self.signatures
+            .iter()
+            .find(|(recorded, _)| recorded == presig)
+            .map(|(_, sig)| sig)
+    }
+
+    /// The witness message and the key signing it for a pre-signature.
+    fn signed_message(&self, presig: &PreSignature) -> Res<(Sha256dHash, PublicKey)> {
+        let role_key = |keys: &SwapRoleKeys<PublicKey>| match presig.signer {
+            SwapRole::Alice => keys.alice,
+            SwapRole::Bob => keys.bob,
+        };
+        Ok(match presig.tx {
+            TxLabel::Cancel => (
+                self.cancel.generate_witness_message(ScriptPath::Failure)?,
+                role_key(&self.data_lock.failure),
+            ),
+            TxLabel::Refund => (
+                self.refund.generate_witness_message(ScriptPath::Success)?,
+                role_key(&self.punish_lock.success),
+            ),
+            TxLabel::Buy => (
+                self.buy.generate_witness_message(ScriptPath::Success)?,
+                role_key(&self.data_lock.success),
+            ),
+            _ => return Err(swap::Error::InvalidSetup("Transaction is not pre-signed").into()),
+        })
+    }
+
+    fn verify_signature(
+        &self,
+        ctx: &CryptoContext,
+        presig: &PreSignature,
+        sig: &SetupSignature,
+    ) -> Res<()> {
+        let (msg, signing_key) = self.signed_message(presig)?;
+        match sig {
+            SetupSignature::Regular(sig) => {
+                let message = Message::from_slice(&msg[..]).expect("Hash is always ok");
+                ctx.secp()
+                    .verify_ecdsa(&message, sig, &signing_key)
+                    .map_err(|_| CryptoError::InvalidSignature)?;
+            }
+            SetupSignature::Adaptor(sig) => {
+                // Encrypted with the adaptor key of the counter-party
+                let encryption_key = match presig.signer {
+                    SwapRole::Alice => self.adaptors.bob,
+                    SwapRole::Bob => self.adaptors.alice,
+                };
+                let verification_key = Point::from_bytes(signing_key.serialize())
+                    .ok_or(CryptoError::InvalidSignature)?;
+                let encryption_key = Point::from_bytes(encryption_key.serialize())
+                    .ok_or(CryptoError::InvalidAdaptorKey)?;
+                ctx.adaptor()
+                    .verify_encrypted_signature(
+                        &verification_key,
+                        &encryption_key,
+                        msg.as_inner(),
+                        sig,
+                    )
+                    .then(|| ())
+                    .ok_or(CryptoError::InvalidEncryptedSignature)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Return true if all the pre-signatures of the transaction have been exchanged.
+    pub fn is_presigned(&self, label: TxLabel) -> bool {
+        let exchanged: Vec<_> = self
+            .sent
+            .iter()
+            .flat_map(|message| message.signatures().iter())
+            .collect();
+        Self::required_signatures()
+            .iter()
+            .filter(|sig| sig.tx == label)
+            .all(|sig| exchanged.contains(&sig))
+    }
+
+    /// Return true if the transaction and all its dependents are pre-signed, i.e. the
+    /// transaction can be signed and broadcast without risking the funds.
+    pub fn is_broadcastable(&self, label: TxLabel) -> bool {
+        self.txid(label).is_some()
+            && self.is_presigned(label)
+            && self
+                .dependents(label)
+                .into_iter()
+                .all(|dependent| self.is_broadcastable(dependent))
+    }
+
+    /// Fails with [`swap::Error::InvalidSetup`] if the transaction is not broadcastable, see
+    /// [`ArbitratingSetup::is_broadcastable`].
+    pub fn ensure_broadcastable(&self, label: TxLabel) -> Res<()> {
+        self.is_broadcastable(label).then(|| ()).ok_or_else(|| {
+            swap::Error::InvalidSetup("Dependent transactions not pre-signed").into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::Witness;
+
+    use ecdsa_fun::adaptor::Adaptor;
+    use ecdsa_fun::fun::{marker::*, Scalar};
+
+    use crate::bitcoin::segwitv0::{estimate_fee, lock::Lock};
+    use crate::bitcoin::test_helpers::{address, pubkey};
+    use crate::blockchain::Network as FNetwork;
+    use crate::crypto::context::{NonceGen, Transcript};
+    use crate::script::SwapRoleKeys;
+    use crate::transaction::Fundable;
+
+    // The secret bytes of the keys signing each pre-signature, see `setup_with`
+    fn signing_byte(presig: &PreSignature) -> u8 {
+        match (presig.tx, presig.signer) {
+            (TxLabel::Cancel, SwapRole::Alice) => 4,
+            (TxLabel::Cancel, SwapRole::Bob) => 5,
+            (TxLabel::Refund, SwapRole::Alice) => 6,
+            (TxLabel::Buy, SwapRole::Bob) => 3,
+            _ => unreachable!(),
+        }
+    }
+
+    fn sign(setup: &ArbitratingSetup, message: SetupMessage) -> Vec<SetupSignature> {
+        message
+            .signatures()
+            .iter()
+            .map(|presig| {
+                let (msg, _) = setup.signed_message(presig).unwrap();
+                let secret = SecretKey::from_slice(&[signing_byte(presig); 32]).unwrap();
+                match presig.kind {
+                    SignatureKind::Regular => SetupSignature::Regular(
+                        Secp256k1::new()
+                            .sign_ecdsa(&Message::from_slice(&msg[..]).unwrap(), &secret),
+                    ),
+                    SignatureKind::Adaptor => {
+                        // Encrypted with the adaptor key of the counter-party, see `setup_with`
+                        let encryption_key = match presig.signer {
+                            SwapRole::Alice => pubkey(12),
+                            SwapRole::Bob => pubkey(11),
+                        };
+                        let signing_key = Scalar::from_slice(&secret[..])
+                            .unwrap()
+                            .mark::<NonZero>()
+                            .unwrap();
+                        let encryption_key = Point::from_bytes(encryption_key.serialize()).unwrap();
+                        SetupSignature::Adaptor(Box::new(
+                            Adaptor::<Transcript, NonceGen>::default().encrypted_sign(
+                                &signing_key,
+                                &encryption_key,
+                                msg.as_inner(),
+                            ),
+                        ))
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn setup_with(timelock: CSVTimelock, broadcast_mode: BroadcastMode) -> ArbitratingSetup {
+        let fee_strategy = FeeStrategy::Fixed(SatPerKvB::from_sat(1000));
+        let lock_fee = estimate_fee::<Lock>(
+            &fee_strategy,
+            bitcoin::Script::new_v0_p2wsh(&Default::default()),
+        )
+        .unwrap();
+        let mut funding = FundingTx::initialize(pubkey(1), FNetwork::Local).unwrap();
+        funding
+            .update(bitcoin::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![TxIn {
+                    previous_output: OutPoint::null(),
+                    script_sig: bitcoin::Script::default(),
+                    sequence: 0,
+                    witness: Witness::new(),
+                }],
+                output: vec![TxOut {
+                    value: 100_000 + lock_fee.as_sat(),
+                    script_pubkey: funding.get_address().unwrap().script_pubkey(),
+                }],
+            })
+            .unwrap();
+        let params = SetupParameters {
+            lock: DataLock {
+                timelock,
+                success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+                failure: SwapRoleKeys::new(pubkey(4), pubkey(5)),
+            },
+            punish_lock: DataPunishableLock {
+                timelock,
+                success: SwapRoleKeys::new(pubkey(6), pubkey(7)),
+                failure: pubkey(8),
+                recovery: None,
+            },
+            target_amount: Amount::from_sat(100_000),
+            alice_address: address(9),
+            bob_address: address(10),
+            fee_strategy,
+            fee_politic: FeePriority::Low,
+            punish_policy: PunishPolicy::new(),
+            alice_adaptor: pubkey(11),
+            bob_adaptor: pubkey(12),
+            broadcast_mode,
+        };
+        ArbitratingSetup::new(crate::Uuid::new().into(), &funding, params).unwrap()
+    }
+
+    fn setup() -> ArbitratingSetup {
+        setup_with(CSVTimelock::new(10), BroadcastMode::Standard)
+    }
+
+    #[test]
+    fn build_dependent_graph() {
+        let setup = setup();
+        assert_eq!(
+            setup.dependents(TxLabel::Lock),
+            vec![TxLabel::Cancel, TxLabel::Buy]
+        );
+        assert_eq!(
+            setup.dependents(TxLabel::Cancel),
+            vec![TxLabel::Refund, TxLabel::Punish]
+        );
+        assert!(setup.dependents(TxLabel::Refund).is_empty());
+        assert!(setup.dependents(TxLabel::AccLock).is_empty());
+        assert_eq!(
+            ArbitratingSetup::messages(SwapRole::Bob),
+            vec![
+                SetupMessage::CoreArbitratingSetup,
+                SetupMessage::BuyProcedureSignature
+            ]
+        );
+        assert_eq!(ArbitratingSetup::required_signatures().len(), 4);
+    }
+
+    #[test]
+    fn broadcast_only_once_dependents_are_presigned() {
+        let mut setup = setup();
+        let ctx = CryptoContext::new();
+        assert!(setup.is_broadcastable(TxLabel::Punish));
+        assert!(!setup.is_broadcastable(TxLabel::Lock));
+        assert!(setup.ensure_broadcastable(TxLabel::Cancel).is_err());
+        // Messages must follow the ceremony order
+        let refund_sigs = sign(&setup, SetupMessage::RefundProcedureSignatures);
+        assert!(setup
+            .record(&ctx, SetupMessage::RefundProcedureSignatures, &refund_sigs)
+            .is_err());
+
+        let core_sigs = sign(&setup, SetupMessage::CoreArbitratingSetup);
+        setup
+            .record(&ctx, SetupMessage::CoreArbitratingSetup, &core_sigs)
+            .unwrap();
+        assert!(!setup.is_broadcastable(TxLabel::Cancel));
+        setup
+            .record(&ctx, SetupMessage::RefundProcedureSignatures, &refund_sigs)
+            .unwrap();
+        assert!(setup.is_broadcastable(TxLabel::Cancel));
+        assert_eq!(
+            setup.signature(&REFUND_PROCEDURE_SIGNATURES[1]),
+            Some(&refund_sigs[1])
+        );
+        // The buy is not pre-signed yet, the lock must not be broadcast
+        assert!(!setup.is_broadcastable(TxLabel::Lock));
+        assert!(setup.signature(&BUY_PROCEDURE_SIGNATURE[0]).is_none());
+        let buy_sigs = sign(&setup, SetupMessage::BuyProcedureSignature);
+        setup
+            .record(&ctx, SetupMessage::BuyProcedureSignature, &buy_sigs)
+            .unwrap();
+        setup.ensure_broadcastable(TxLabel::Lock).unwrap();
+        assert_eq!(setup.next_message(), None);
+    }
+
+    #[test]
+    fn reject_invalid_signatures() {
+        let mut setup = setup();
+        let ctx = CryptoContext::new();
+        let core_sigs = sign(&setup, SetupMessage::CoreArbitratingSetup);
+        // Missing or of the wrong kind
+        assert!(setup
+            .record(&ctx, SetupMessage::CoreArbitratingSetup, &[])
+            .is_err());
+        let buy_sigs = sign(&setup, SetupMessage::BuyProcedureSignature);
+        assert!(setup
+            .record(&ctx, SetupMessage::CoreArbitratingSetup, &buy_sigs)
+            .is_err());
+        // Signed by Alice instead of Bob
+        let alice_sigs = sign(&setup, SetupMessage::RefundProcedureSignatures);
+        assert!(setup
+            .record(&ctx, SetupMessage::CoreArbitratingSetup, &alice_sigs[..1])
+            .is_err());
+        assert_eq!(
+            setup.next_message(),
+            Some(SetupMessage::CoreArbitratingSetup)
+        );
+
+        setup
+            .record(&ctx, SetupMessage::CoreArbitratingSetup, &core_sigs)
+            .unwrap();
+        setup
+            .record(
+                &ctx,
+                SetupMessage::RefundProcedureSignatures,
+                &sign(&setup, SetupMessage::RefundProcedureSignatures),
+            )
+            .unwrap();
+        // Signed over the cancel instead of the buy
+        let (msg, _) = setup
+            .signed_message(&CORE_ARBITRATING_SETUP_SIGNATURES[0])
+            .unwrap();
+        let signing_key = Scalar::from_slice(&[3; 32])
+            .unwrap()
+            .mark::<NonZero>()
+            .unwrap();
+        let encryption_key = Point::from_bytes(pubkey(11).serialize()).unwrap();
+        let wrong_sig = Adaptor::<Transcript, NonceGen>::default().encrypted_sign(
+            &signing_key,
+            &encryption_key,
+            msg.as_inner(),
+        );
+        assert_ne!(
+            SetupSignature::Adaptor(Box::new(wrong_sig.clone())),
+            buy_sigs[0]
+        );
+        assert!(setup
+            .record(
+                &ctx,
+                SetupMessage::BuyProcedureSignature,
+                &[SetupSignature::Adaptor(Box::new(wrong_sig))]
+            )
+            .is_err());
+        setup
+            .record(&ctx, SetupMessage::BuyProcedureSignature, &buy_sigs)
+            .unwrap();
+    }
+
+    #[test]
+    fn time_based_timelocks() {
+        let timelock = CSVTimelock::from_seconds(4 * 3600).unwrap();
+        let setup = setup_with(timelock, BroadcastMode::Standard);
+        // The sequences signal the time-based lock, the punishable lock script pushes it
+        let sequence = |psbt: &bitcoin::util::psbt::PartiallySignedTransaction| {
+            psbt.unsigned_tx.input[0].sequence
+        };
+        assert_eq!(sequence(setup.cancel().as_psbt()), timelock.as_u32());
+        assert_eq!(sequence(setup.punish().as_psbt()), timelock.as_u32());
+        let script = setup.punish().as_psbt().inputs[0]
+            .witness_script
+            .clone()
+            .unwrap();
+        let pushed = bitcoin::blockdata::script::Builder::new()
+            .push_int(timelock.as_u32().into())
+            .into_script();
+        assert!(script
+            .as_bytes()
+            .windows(pushed.len())
+            .any(|window| window == pushed.as_bytes()));
+    }
+
+    #[test]
+    fn truc_broadcast_mode() {
+        use crate::bitcoin::truc::TRUC_VERSION;
+
+        let mut setup = setup_with(CSVTimelock::new(10), BroadcastMode::Truc);
+        let ctx = CryptoContext::new();
+        let version =
+            |psbt: &bitcoin::util::psbt::PartiallySignedTransaction| psbt.unsigned_tx.version;
+        assert_eq!(version(setup.cancel().as_psbt()), TRUC_VERSION);
+        assert_eq!(version(setup.punish().as_psbt()), TRUC_VERSION);
+        assert_eq!(setup.cancel().anchor(), Some(AnchorOutput::Ephemeral));
+        assert_eq!(setup.punish().anchor(), Some(AnchorOutput::Ephemeral));
+        assert_eq!(version(setup.refund().as_psbt()), 2);
+        assert_eq!(version(setup.buy().as_psbt()), 2);
+        setup
+            .cancel()
+            .verify_template(setup.data_lock, setup.punish_lock)
+            .unwrap();
+        assert_eq!(
+            setup.dependents(TxLabel::Cancel),
+            vec![TxLabel::Refund, TxLabel::Punish]
+        );
+
+        for message in SetupMessage::ALL {
+            let sigs = sign(&setup, message);
+            setup.record(&ctx, message, &sigs).unwrap();
+        }
+        setup.ensure_broadcastable(TxLabel::Lock).unwrap();
+    }
+}
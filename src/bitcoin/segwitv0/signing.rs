@@ -0,0 +1,212 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Signing and verification of all the inputs of a transaction through a single
+//! [`SighashCache`]. The [`BIP-143`][bip-143] midstate hashes (prevouts, sequences, and
+//! outputs) are computed once per transaction instead of once per input as with
+//! [`signature_hash`](crate::bitcoin::segwitv0::signature_hash).
+//!
+//! [bip-143]: https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::EcdsaSighashType;
+use bitcoin::hashes::sha256d::Hash as Sha256dHash;
+use bitcoin::secp256k1::{
+    ecdsa::Signature, Message, PublicKey, Secp256k1, SecretKey, Verification,
+};
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::util::sighash::SighashCache;
+use bitcoin::Amount;
+
+use crate::bitcoin::transaction::Error;
+use crate::crypto::context::EcdsaSigner;
+use crate::transaction::Error as FError;
+
+/// A signing session over one partially signed transaction. Sighashes of all inputs are
+/// computed through the same cache, the script and value of each input are taken from the
+/// `witness_script` and `witness_utxo` of the partial transaction.
+pub struct SigningSession<'a> {
+    psbt: &'a PartiallySignedTransaction,
+    cache: SighashCache<&'a bitcoin::Transaction>,
+}
+
+impl<'a> SigningSession<'a> {
+    /// Start a new session on the partial transaction.
+    pub fn new(psbt: &'a PartiallySignedTransaction) -> Self {
+        Self {
+            psbt,
+            cache: SighashCache::new(&psbt.unsigned_tx),
+        }
+    }
+
+    /// Computes the sighash of an input with an explicit script, value, and sighash type.
+    pub fn signature_hash(
+        &mut self,
+        index: usize,
+        script: &Script,
+        value: Amount,
+        sighash_type: EcdsaSighashType,
+    ) -> Sha256dHash {
+        self.cache
+            .segwit_signature_hash(index, script, value.as_sat(), sighash_type)
+            .expect("encoding works")
+            .as_hash()
+    }
+
+    /// Computes the sighash of an input from the metadata of the partial transaction, the
+    /// sighash type defaults to `SIGHASH_ALL` if not set.
+    pub fn input_hash(&mut self, index: usize) -> Result<Sha256dHash, FError> {
+        let input = self.psbt.inputs.get(index).ok_or(FError::MissingWitness)?;
+        let value = input
+            .witness_utxo
+            .as_ref()
+            .ok_or(FError::MissingWitness)?
+            .value;
+        let script = input
+            .witness_script
+            .as_ref()
+            .ok_or(FError::MissingWitness)?;
+        let sighash_type = input
+            .ecdsa_hash_ty()
+            .map_err(|_| FError::from(Error::MissingSigHashType))?;
+        Ok(self.signature_hash(index, script, Amount::from_sat(value), sighash_type))
+    }
+
+    /// Sign an input with a low-S signature, the nonce is generated by the signer, see
+    /// [`EcdsaSigner`].
+    pub fn sign_input<S: EcdsaSigner + ?Sized>(
+        &mut self,
+        signer: &S,
+        index: usize,
+        secret_key: &SecretKey,
+    ) -> Result<Signature, FError> {
+        let msg = Message::from_slice(&self.input_hash(index)?[..]).map_err(Error::from)?;
+        Ok(signer.sign_low_s(&msg, secret_key))
+    }
+
+    /// Sign all the inputs with the same key.
+    pub fn sign_all<S: EcdsaSigner + ?Sized>(
+        &mut self,
+        signer: &S,
+        secret_key: &SecretKey,
+    ) -> Result<Vec<Signature>, FError> {
+        (0..self.psbt.inputs.len())
+            .map(|index| self.sign_input(signer, index, secret_key))
+            .collect()
+    }
+
+    /// Verify the signature of an input.
+    pub fn verify_input<C: Verification>(
+        &mut self,
+        context: &Secp256k1<C>,
+        index: usize,
+        sig: &Signature,
+        pubkey: &PublicKey,
+    ) -> Result<(), FError> {
+        let msg = Message::from_slice(&self.input_hash(index)?[..]).map_err(Error::from)?;
+        context
+            .verify_ecdsa(&msg, sig, pubkey)
+            .map_err(|e| FError::from(Error::from(e)))
+    }
+
+    /// Verify the signatures of all inputs, one signature and public key per input.
+    pub fn verify_all<C: Verification>(
+        &mut self,
+        context: &Secp256k1<C>,
+        signatures: &[(Signature, PublicKey)],
+    ) -> Result<(), FError> {
+        if signatures.len() != self.psbt.inputs.len() {
+            return Err(FError::MissingSignature);
+        }
+        signatures
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, (sig, pubkey))| self.verify_input(context, index, sig, pubkey))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use bitcoin::blockdata::witness::Witness;
+    use bitcoin::hashes::Hash;
+    use bitcoin::Txid;
+
+    use crate::bitcoin::segwitv0::signature_hash;
+    use crate::bitcoin::transaction::TxInRef;
+
+    fn multi_input_psbt(pubkey: &PublicKey) -> PartiallySignedTransaction {
+        let script = bitcoin::Script::new_p2pkh(&bitcoin::PublicKey::new(*pubkey).pubkey_hash());
+        let tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: (0..3)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(Txid::from_inner([1; 32]), vout),
+                    script_sig: Script::default(),
+                    sequence: 0xffffffff,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: 29_000,
+                script_pubkey: Script::default(),
+            }],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        for input in psbt.inputs.iter_mut() {
+            input.witness_utxo = Some(TxOut {
+                value: 10_000,
+                script_pubkey: Script::default(),
+            });
+            input.witness_script = Some(script.clone());
+        }
+        psbt
+    }
+
+    #[test]
+    fn sign_and_verify_all_inputs() {
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[3; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&secp, &secret);
+        let psbt = multi_input_psbt(&pubkey);
+
+        let mut session = SigningSession::new(&psbt);
+        for index in 0..3 {
+            let script = psbt.inputs[index].witness_script.clone().unwrap();
+            assert_eq!(
+                session.input_hash(index).unwrap(),
+                signature_hash(
+                    TxInRef::new(&psbt.unsigned_tx, index),
+                    &script,
+                    Amount::from_sat(10_000),
+                    EcdsaSighashType::All
+                )
+            );
+        }
+
+        let sigs = session.sign_all(&secp, &secret).unwrap();
+        assert_eq!(sigs.len(), 3);
+        let pairs: Vec<_> = sigs.iter().map(|sig| (*sig, pubkey)).collect();
+        assert!(session.verify_all(&secp, &pairs).is_ok());
+
+        // A signature is only valid for its own input
+        assert!(session.verify_input(&secp, 1, &sigs[0], &pubkey).is_err());
+        assert!(session.verify_all(&secp, &pairs[..2]).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+use bitcoin::blockdata::transaction::{TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::Address;
+use bitcoin::Amount;
+
+use crate::transaction::{Error as FError, Linkable};
+
+use crate::bitcoin::segwitv0::funding::Funding;
+use crate::bitcoin::segwitv0::witness::KEY_SPEND_WITNESS;
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::transaction::{Error, SubTransaction, Tx};
+
+#[derive(Debug)]
+pub struct Surplus;
+
+impl SubTransaction for Surplus {
+    fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
+        let (pubkey, full_sig) = psbt.inputs[0]
+            .partial_sigs
+            .iter()
+            .next()
+            .ok_or(FError::MissingSignature)?;
+        psbt.inputs[0].final_script_witness = Some(Witness::from_vec(vec![
+            full_sig.to_vec(),
+            pubkey.to_bytes(),
+        ]));
+        Ok(())
+    }
+
+    fn estimated_witness_weight() -> usize {
+        KEY_SPEND_WITNESS.max_size()
+    }
+}
+
+impl Tx<Surplus> {
+    /// Split an overfunded funding output in two: `amount` is sent back to the funding address
+    /// and the surplus, minus `fee`, is refunded to `refund_target`. Once the transaction is
+    /// mined the funding can be updated with it and the swap continues with the lock
+    /// transaction.
+    ///
+    /// `amount` should include the fee budget of the lock transaction, as the lock spends the
+    /// funding output entirely.
+    ///
+    /// Returns [`FError::NotEnoughAssets`] if the remaining surplus is below the dust limit of
+    /// the refund address.
+    pub fn initialize(
+        funding: &Funding,
+        amount: Amount,
+        refund_target: Address,
+        fee: Amount,
+    ) -> Result<Self, FError> {
+        let output_metadata = funding.get_consumable_output()?;
+
+        let surplus = amount
+            .checked_add(fee)
+            .and_then(|spent| Amount::from_sat(output_metadata.tx_out.value).checked_sub(spent))
+            .ok_or(FError::NotEnoughAssets)?;
+
+        let refund_script = refund_target.script_pubkey();
+        if surplus < refund_script.dust_value() {
+            return Err(FError::NotEnoughAssets);
+        }
+
+        let unsigned_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: output_metadata.out_point,
+                script_sig: bitcoin::Script::default(),
+                sequence: CSVTimelock::disable(),
+                witness: Witness::new(),
+            }],
+            output: vec![
+                TxOut {
+                    value: amount.as_sat(),
+                    script_pubkey: output_metadata.tx_out.script_pubkey.clone(),
+                },
+                TxOut {
+                    value: surplus.as_sat(),
+                    script_pubkey: refund_script,
+                },
+            ],
+        };
+
+        let mut psbt =
+            PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
+
+        // Set the input witness data and sighash type
+        psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
+        psbt.inputs[0].witness_script = output_metadata.script_pubkey;
+
+        Ok(Tx::new(psbt))
+    }
+
+    /// Return the amount refunded to the funder.
+    pub fn surplus_amount(&self) -> Amount {
+        Amount::from_sat(self.psbt.unsigned_tx.output[1].value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::OutPoint;
+
+    use crate::bitcoin::test_helpers::{address, pubkey};
+    use crate::blockchain::Network;
+    use crate::transaction::{Broadcastable, Fundable};
+
+    fn funding_with(value: u64) -> Funding {
+        let pubkey = pubkey(1);
+        let mut funding = Funding::initialize(pubkey, Network::Local).unwrap();
+        let funding_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: funding.get_address().unwrap().script_pubkey(),
+            }],
+        };
+        funding.update(funding_tx).unwrap();
+        funding
+    }
+
+    #[test]
+    fn refund_surplus_of_overfunded_funding() {
+        let target = Amount::from_sat(100_000);
+        let max_fee = Amount::from_sat(1_000);
+        let refund = address(2);
+
+        let mut funding = funding_with(150_000);
+        assert!(matches!(
+            funding.verify_funded_amount(target, max_fee),
+            Err(FError::Overfunded)
+        ));
+
+        let surplus =
+            Tx::<Surplus>::initialize(&funding, target + max_fee, refund, Amount::from_sat(500))
+                .unwrap();
+        assert_eq!(surplus.surplus_amount(), Amount::from_sat(48_500));
+
+        funding.update(surplus.extract()).unwrap();
+        assert!(funding.verify_funded_amount(target, max_fee).is_ok());
+    }
+
+    #[test]
+    fn surplus_under_dust_is_rejected() {
+        let refund = address(2);
+        let funding = funding_with(101_100);
+        assert!(matches!(
+            funding.verify_funded_amount(Amount::from_sat(100_000), Amount::from_sat(1_000)),
+            Err(FError::Overfunded)
+        ));
+        assert!(matches!(
+            Tx::<Surplus>::initialize(
+                &funding,
+                Amount::from_sat(101_000),
+                refund,
+                Amount::from_sat(50)
+            ),
+            Err(FError::NotEnoughAssets)
+        ));
+    }
+}
@@ -0,0 +1,239 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+use bitcoin::blockdata::transaction::{EcdsaSighashType, TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::hashes::sha256d::Hash as Sha256dHash;
+use bitcoin::secp256k1::{ecdsa::Signature, PublicKey};
+use bitcoin::util::ecdsa::EcdsaSig;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::{Address, Amount, Network};
+
+use crate::blockchain::{Fee, FeePriority, FeeStrategy};
+use crate::transaction::{Error as FError, Transaction as _};
+
+use crate::bitcoin::fee::SatPerKvB;
+use crate::bitcoin::segwitv0::signature_hash;
+use crate::bitcoin::segwitv0::witness::KEY_SPEND_WITNESS;
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::transaction::{
+    check_low_s, Error, MetadataOutput, SubTransaction, Tx, TxInRef,
+};
+
+#[derive(Debug)]
+pub struct Sweep;
+
+impl SubTransaction for Sweep {
+    fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
+        for input in psbt.inputs.iter_mut() {
+            let (pubkey, full_sig) = input
+                .partial_sigs
+                .iter()
+                .next()
+                .ok_or(FError::MissingSignature)?;
+            input.final_script_witness = Some(Witness::from_vec(vec![
+                full_sig.to_vec(),
+                pubkey.to_bytes(),
+            ]));
+        }
+        Ok(())
+    }
+
+    fn estimated_witness_weight() -> usize {
+        KEY_SPEND_WITNESS.max_size()
+    }
+}
+
+impl Tx<Sweep> {
+    /// Spend one or more completed swap outputs, e.g. the outputs of the buy or refund
+    /// transactions, to `destination` in a single transaction paying `fee_rate`. Each output
+    /// must be a single-key SegWit v0 output of the key it is given with.
+    ///
+    /// Returns [`FError::WrongTemplate`] if an output is not controlled by its key and
+    /// [`FError::NotEnoughAssets`] if the swept amount after fee is below the dust limit of the
+    /// destination.
+    pub fn initialize(
+        outputs: &[(MetadataOutput, PublicKey)],
+        destination: Address,
+        fee_rate: SatPerKvB,
+    ) -> Result<Self, FError> {
+        if outputs.is_empty() {
+            return Err(FError::MissingUTXO);
+        }
+
+        let mut script_codes = Vec::with_capacity(outputs.len());
+        for (output, pubkey) in outputs {
+            let pubkey = bitcoin::PublicKey::new(*pubkey);
+            // The network does not change the script
+            let address = Address::p2wpkh(&pubkey, Network::Bitcoin).map_err(Error::from)?;
+            if output.tx_out.script_pubkey != address.script_pubkey() {
+                return Err(FError::WrongTemplate(
+                    "Swept output is not controlled by the given key",
+                ));
+            }
+            script_codes.push(Address::p2pkh(&pubkey, Network::Bitcoin).script_pubkey());
+        }
+
+        let unsigned_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: outputs
+                .iter()
+                .map(|(output, _)| TxIn {
+                    previous_output: output.out_point,
+                    script_sig: bitcoin::Script::default(),
+                    sequence: CSVTimelock::disable(),
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: 0,
+                script_pubkey: destination.script_pubkey(),
+            }],
+        };
+
+        let mut psbt =
+            PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
+
+        // Set the inputs witness data
+        for ((input, (output, _)), script_code) in
+            psbt.inputs.iter_mut().zip(outputs.iter()).zip(script_codes)
+        {
+            input.witness_utxo = Some(output.tx_out.clone());
+            input.witness_script = Some(script_code);
+        }
+
+        let mut sweep = Tx::new(psbt);
+        sweep
+            .set_fee(&FeeStrategy::Fixed(fee_rate), FeePriority::Low)
+            .map_err(|_| FError::NotEnoughAssets)?;
+        if sweep.sweep_amount() < destination.script_pubkey().dust_value() {
+            return Err(FError::NotEnoughAssets);
+        }
+
+        Ok(sweep)
+    }
+
+    /// Return the amount received on the destination address.
+    pub fn sweep_amount(&self) -> Amount {
+        Amount::from_sat(self.psbt.unsigned_tx.output[0].value)
+    }
+
+    /// Generate the message to sign for the input at `index`.
+    pub fn generate_input_witness_message(&self, index: usize) -> Result<Sha256dHash, FError> {
+        let input = self.psbt.inputs.get(index).ok_or(FError::MissingUTXO)?;
+        let witness_utxo = input.witness_utxo.as_ref().ok_or(FError::MissingWitness)?;
+        let script = input
+            .witness_script
+            .as_ref()
+            .ok_or(FError::MissingWitness)?;
+        Ok(signature_hash(
+            TxInRef::new(&self.psbt.unsigned_tx, index),
+            script,
+            Amount::from_sat(witness_utxo.value),
+            EcdsaSighashType::All,
+        ))
+    }
+
+    /// Add the signature of `pubkey` for the input at `index`.
+    pub fn add_input_witness(
+        &mut self,
+        index: usize,
+        pubkey: PublicKey,
+        sig: Signature,
+    ) -> Result<(), FError> {
+        check_low_s(&sig)?;
+        let input = self
+            .as_partial_mut()
+            .inputs
+            .get_mut(index)
+            .ok_or(FError::MissingUTXO)?;
+        input
+            .partial_sigs
+            .insert(bitcoin::PublicKey::new(pubkey), EcdsaSig::sighash_all(sig));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::OutPoint;
+    use bitcoin::Txid;
+
+    use crate::bitcoin::segwitv0::{sign_hash, SweepTx};
+    use crate::bitcoin::test_helpers::{address, pubkey, secp, secret};
+    use crate::transaction::{Broadcastable, Finalizable};
+    use bitcoin::hashes::Hash;
+
+    fn output(byte: u8, value: u64) -> (MetadataOutput, PublicKey) {
+        let output = MetadataOutput {
+            out_point: OutPoint::new(Txid::from_inner([byte; 32]), 0),
+            tx_out: TxOut {
+                value,
+                script_pubkey: address(byte).script_pubkey(),
+            },
+            script_pubkey: None,
+            redeem_script: None,
+            wtxid: None,
+        };
+        (output, pubkey(byte))
+    }
+
+    #[test]
+    fn sweep_swap_outputs() {
+        let outputs = vec![output(1, 100_000), output(2, 50_000)];
+        let fee_rate = SatPerKvB::from_sat(2_000);
+        let mut sweep = SweepTx::initialize(&outputs, address(3), fee_rate).unwrap();
+        assert_eq!(sweep.as_partial().unsigned_tx.input.len(), 2);
+
+        for (index, byte) in [1u8, 2].iter().enumerate() {
+            let msg = sweep.generate_input_witness_message(index).unwrap();
+            let sig = sign_hash(secp(), msg, &secret(*byte)).unwrap();
+            sweep.add_input_witness(index, pubkey(*byte), sig).unwrap();
+        }
+        assert!(sweep.missing_signatures().unwrap().is_empty());
+        sweep.finalize().unwrap();
+
+        // The paid fee matches the target rate within the witness size upper bound
+        let tx = sweep.extract();
+        assert!(sweep.estimated_vsize() >= tx.vsize());
+        assert!(sweep.estimated_vsize() <= tx.vsize() + 1);
+        assert!(sweep.validate_fee(&FeeStrategy::Fixed(fee_rate)).unwrap());
+    }
+
+    #[test]
+    fn reject_invalid_sweep() {
+        let destination = address(3);
+        let fee_rate = SatPerKvB::from_sat(2_000);
+        assert!(matches!(
+            SweepTx::initialize(&[], destination.clone(), fee_rate),
+            Err(FError::MissingUTXO)
+        ));
+
+        let (other, _) = output(1, 100_000);
+        assert!(matches!(
+            SweepTx::initialize(&[(other, pubkey(2))], destination.clone(), fee_rate),
+            Err(FError::WrongTemplate(_))
+        ));
+
+        assert!(matches!(
+            SweepTx::initialize(&[output(1, 500)], destination, fee_rate),
+            Err(FError::NotEnoughAssets)
+        ));
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Export of the pre-signed `cancel` and `punish` transactions with their trigger conditions, so
+//! a third-party watchtower can enforce them on the user's behalf if they go offline.
+//!
+//! The [`WatchtowerPackage`] is sealed into an encrypted blob with a key shared only between the
+//! user and its watchtower, see [`crate::crypto::encryption`].
+
+use std::io;
+
+use bitcoin::blockdata::transaction::OutPoint;
+use bitcoin::Transaction;
+use rand::{CryptoRng, RngCore};
+
+use crate::bitcoin::segwitv0::{CancelTx, PunishTx};
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::encryption::{self, SealingKey};
+use crate::swap::SwapId;
+use crate::transaction::{Broadcastable, Error as FError, Transaction as _};
+use crate::Res;
+
+/// Condition under which a watchtower must broadcast a transaction: once `outpoint` is confirmed
+/// the transaction becomes valid after `timelock` blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Trigger {
+    /// The output to watch on-chain, spent by the transaction to broadcast.
+    pub outpoint: OutPoint,
+    /// The relative timelock before the transaction can be mined.
+    pub timelock: CSVTimelock,
+}
+
+impl Trigger {
+    fn from_input(tx: &Transaction) -> Self {
+        Self {
+            outpoint: tx.input[0].previous_output,
+            timelock: CSVTimelock::new(tx.input[0].sequence),
+        }
+    }
+
+    /// Return the first block height at which the transaction can be mined given the height at
+    /// which the watched output has been confirmed. Estimated for time-based timelocks, see
+    /// [`CSVTimelock::as_blocks`].
+    pub fn broadcastable_at(&self, confirmation_height: u32) -> u32 {
+        confirmation_height.saturating_add(self.timelock.as_blocks())
+    }
+}
+
+impl Encodable for Trigger {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let len = bitcoin::consensus::encode::serialize(&self.outpoint).consensus_encode(writer)?;
+        Ok(len
+            + self
+                .timelock
+                .as_canonical_bytes()
+                .consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for Trigger {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            outpoint: bitcoin::consensus::encode::deserialize(unwrap_vec_ref!(d).as_ref())
+                .map_err(consensus::Error::new)?,
+            timelock: CSVTimelock::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+/// The set of fully signed transactions a watchtower holds for a swap and the conditions to
+/// broadcast them. The `cancel` transaction is broadcast once the lock matures, the `punish`
+/// transaction, if present, once the cancel matures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchtowerPackage {
+    /// The swap identifier the package belongs to.
+    pub swap_id: SwapId,
+    /// The fully signed cancel transaction.
+    pub cancel: Transaction,
+    /// The lock output and the cancel timelock.
+    pub cancel_trigger: Trigger,
+    /// The fully signed punish transaction, only available for Alice.
+    pub punish: Option<Transaction>,
+    /// The cancel output and the punish timelock.
+    pub punish_trigger: Option<Trigger>,
+}
+
+impl WatchtowerPackage {
+    /// Create a new package from the finalized `cancel` and, if any, `punish` transactions. The
+    /// triggers are derived from the transaction inputs.
+    pub fn new<U>(swap_id: U, cancel: &CancelTx, punish: Option<&PunishTx>) -> Res<Self>
+    where
+        U: Into<SwapId>,
+    {
+        let is_final = |psbt: &bitcoin::util::psbt::PartiallySignedTransaction| {
+            psbt.inputs[0].final_script_witness.is_some()
+        };
+        if !is_final(cancel.as_partial()) || !punish.map_or(true, |p| is_final(p.as_partial())) {
+            return Err(FError::MissingWitness.into());
+        }
+
+        let cancel = cancel.extract();
+        let punish = punish.map(|p| p.extract());
+        Ok(Self {
+            swap_id: swap_id.into(),
+            cancel_trigger: Trigger::from_input(&cancel),
+            punish_trigger: punish.as_ref().map(Trigger::from_input),
+            cancel,
+            punish,
+        })
+    }
+
+    /// Serialize and encrypt the package into a blob to hand over to a watchtower.
+    pub fn seal<R: RngCore + CryptoRng>(&self, rng: &mut R, key: &SealingKey) -> Vec<u8> {
+        encryption::seal(rng, key, &consensus::serialize(self))
+    }
+
+    /// Decrypt and deserialize a package from a blob created with [`WatchtowerPackage::seal`].
+    pub fn open(key: &SealingKey, blob: &[u8]) -> Res<Self> {
+        let bytes = encryption::open(key, blob)?;
+        Ok(consensus::deserialize(&bytes)?)
+    }
+}
+
+impl Encodable for WatchtowerPackage {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.swap_id.consensus_encode(writer)?;
+        len += self.cancel.as_canonical_bytes().consensus_encode(writer)?;
+        len += self.cancel_trigger.consensus_encode(writer)?;
+        len += self.punish.as_canonical_bytes().consensus_encode(writer)?;
+        Ok(len + self.punish_trigger.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for WatchtowerPackage {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            swap_id: Decodable::consensus_decode(d)?,
+            cancel: Transaction::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            cancel_trigger: Decodable::consensus_decode(d)?,
+            punish: Option::<Transaction>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            punish_trigger: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(WatchtowerPackage);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::{Amount, TxIn, TxOut, Witness};
+
+    use crate::bitcoin::segwitv0::{FundingTx, LockTx};
+    use crate::bitcoin::test_helpers::{address, pubkey};
+    use crate::blockchain::Network;
+    use crate::crypto;
+    use crate::script::{DataLock, DataPunishableLock, SwapRoleKeys};
+    use crate::transaction::{Cancelable, Fundable, Lockable, Punishable};
+    use crate::Uuid;
+
+    fn transactions() -> (CancelTx, PunishTx) {
+        let mut funding = FundingTx::initialize(pubkey(1), Network::Local).unwrap();
+        funding
+            .update(Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![TxIn {
+                    previous_output: OutPoint::null(),
+                    script_sig: bitcoin::Script::default(),
+                    sequence: 0,
+                    witness: Witness::new(),
+                }],
+                output: vec![TxOut {
+                    value: 100_000,
+                    script_pubkey: funding.get_address().unwrap().script_pubkey(),
+                }],
+            })
+            .unwrap();
+        let keys = SwapRoleKeys::new(pubkey(2), pubkey(3));
+        let data_lock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: keys,
+            failure: keys,
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(20),
+            success: keys,
+            failure: pubkey(2),
+            recovery: None,
+        };
+        let lock = LockTx::initialize(&funding, data_lock, Amount::from_sat(100_000)).unwrap();
+        let cancel = CancelTx::initialize(&lock, data_lock, punish_lock).unwrap();
+        let punish = PunishTx::initialize(&cancel, punish_lock, address(4)).unwrap();
+        (cancel, punish)
+    }
+
+    // The package does not check the witnesses, a placeholder marks the inputs as final
+    fn finalize<T>(tx: &mut crate::bitcoin::transaction::Tx<T>)
+    where
+        T: crate::bitcoin::transaction::SubTransaction,
+    {
+        tx.as_partial_mut().inputs[0].final_script_witness = Some(Witness::from_vec(vec![vec![1]]));
+    }
+
+    fn package() -> (CancelTx, PunishTx, WatchtowerPackage) {
+        let (mut cancel, mut punish) = transactions();
+        finalize(&mut cancel);
+        finalize(&mut punish);
+        let package = WatchtowerPackage::new(Uuid::new(), &cancel, Some(&punish)).unwrap();
+        (cancel, punish, package)
+    }
+
+    #[test]
+    fn triggers_follow_the_transaction_inputs() {
+        let (cancel, punish, package) = package();
+        assert_eq!(
+            package.cancel_trigger,
+            Trigger {
+                outpoint: cancel.as_partial().unsigned_tx.input[0].previous_output,
+                timelock: CSVTimelock::new(10),
+            }
+        );
+        assert_eq!(
+            package.punish_trigger,
+            Some(Trigger {
+                outpoint: OutPoint::new(cancel.extract().txid(), 0),
+                timelock: CSVTimelock::new(20),
+            })
+        );
+        assert_eq!(package.punish, Some(punish.extract()));
+
+        // Bob has no punish transaction to hand over
+        let bob = WatchtowerPackage::new(Uuid::new(), &cancel, None).unwrap();
+        assert_eq!(bob.punish, None);
+        assert_eq!(bob.punish_trigger, None);
+    }
+
+    #[test]
+    fn trigger_broadcastable_height() {
+        let (_, _, package) = package();
+        assert_eq!(package.cancel_trigger.broadcastable_at(100), 110);
+        assert_eq!(package.punish_trigger.unwrap().broadcastable_at(110), 130);
+        assert_eq!(package.cancel_trigger.broadcastable_at(u32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn reject_unfinalized_transactions() {
+        let (mut cancel, punish) = transactions();
+        assert!(WatchtowerPackage::new(Uuid::new(), &cancel, None).is_err());
+        finalize(&mut cancel);
+        assert!(WatchtowerPackage::new(Uuid::new(), &cancel, Some(&punish)).is_err());
+    }
+
+    #[test]
+    fn seal_and_open_package() {
+        let (_, _, package) = package();
+        let key = [7u8; 32];
+        let blob = package.seal(&mut rand::thread_rng(), &key);
+        assert_eq!(WatchtowerPackage::open(&key, &blob).unwrap(), package);
+        // A fresh nonce is drawn for each blob
+        assert_ne!(package.seal(&mut rand::thread_rng(), &key), blob);
+
+        assert!(matches!(
+            WatchtowerPackage::open(&[8u8; 32], &blob),
+            Err(crate::Error::Crypto(crypto::Error::InvalidCiphertext))
+        ));
+        let mut tampered = blob.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(WatchtowerPackage::open(&key, &tampered).is_err());
+        assert!(WatchtowerPackage::open(&key, &blob[..blob.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Witness stack layouts of the transaction templates, from bottom to top of the stack. The
+//! layouts let fee estimators and watchtowers reason about the size of the final transactions
+//! without re-deriving the scripts. Sizes are upper bounds in bytes, equal to the weight units of
+//! the witness, each item includes its length prefix.
+
+use crate::bitcoin::segwitv0::{
+    buy::Buy, cancel::Cancel, collaborative::CollaborativeCancel, lock::Lock, punish::Punish,
+    refund::Refund, surplus::Surplus, sweep::Sweep,
+};
+use crate::bitcoin::segwitv0::{
+    COOP_LOCK_ITEM_WEIGHT, EMPTY_ITEM_WEIGHT, PUBKEY_ITEM_WEIGHT, PUNISH_LOCK_ITEM_WEIGHT,
+    RECOVERY_PUNISH_LOCK_ITEM_WEIGHT, SIGNATURE_ITEM_WEIGHT, TRUE_ITEM_WEIGHT,
+};
+use crate::bitcoin::transaction::{SubTransaction, Tx};
+use crate::role::SwapRole;
+use crate::script::ScriptPath;
+
+/// An item of a witness stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum WitnessItem {
+    /// A signature of the party.
+    Signature(SwapRole),
+    /// A signature of any of the keys allowed to spend, e.g. the key of a P2WPKH output.
+    AnySignature,
+    /// The public key of a P2WPKH output.
+    PublicKey,
+    /// An empty item, selecting the `OP_ELSE` branch or popped by `OP_CHECKMULTISIG`.
+    Empty,
+    /// `OP_TRUE`, selecting the `OP_IF` branch.
+    True,
+    /// The witness script of the swap lock, see [`CoopLock`](crate::bitcoin::segwitv0::CoopLock).
+    CoopLockScript,
+    /// The witness script of the punishable lock, see
+    /// [`PunishLock`](crate::bitcoin::segwitv0::PunishLock).
+    PunishLockScript,
+    /// The witness script of the punishable lock with a recovery key.
+    RecoveryPunishLockScript,
+}
+
+impl WitnessItem {
+    /// The maximum size of the item, including its length prefix.
+    pub const fn max_size(&self) -> usize {
+        match self {
+            Self::Signature(_) | Self::AnySignature => SIGNATURE_ITEM_WEIGHT,
+            Self::PublicKey => PUBKEY_ITEM_WEIGHT,
+            Self::Empty => EMPTY_ITEM_WEIGHT,
+            Self::True => TRUE_ITEM_WEIGHT,
+            Self::CoopLockScript => COOP_LOCK_ITEM_WEIGHT,
+            Self::PunishLockScript => PUNISH_LOCK_ITEM_WEIGHT,
+            Self::RecoveryPunishLockScript => RECOVERY_PUNISH_LOCK_ITEM_WEIGHT,
+        }
+    }
+}
+
+/// The witness stack spending an input of a transaction template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WitnessLayout {
+    /// The path of the script spent, `None` for a key spend.
+    pub path: Option<ScriptPath>,
+    /// The items of the stack, from bottom to top.
+    pub items: &'static [WitnessItem],
+}
+
+impl WitnessLayout {
+    /// The maximum size of the witness, the item count and all the items, in bytes. As witness
+    /// bytes weigh one weight unit this is also the maximum weight of the witness.
+    pub fn max_size(&self) -> usize {
+        1 + self.items.iter().map(WitnessItem::max_size).sum::<usize>()
+    }
+}
+
+/// Witness of a P2WPKH key spend, e.g. the `lock` spending the funding output.
+pub const KEY_SPEND_WITNESS: WitnessLayout = WitnessLayout {
+    path: None,
+    items: &[WitnessItem::AnySignature, WitnessItem::PublicKey],
+};
+
+/// Witness of the `buy` spending the multisig path of the swap lock.
+pub const BUY_WITNESS: WitnessLayout = WitnessLayout {
+    path: Some(ScriptPath::Success),
+    items: &[
+        WitnessItem::Signature(SwapRole::Bob),
+        WitnessItem::Signature(SwapRole::Alice),
+        WitnessItem::CoopLockScript,
+    ],
+};
+
+/// Witness of the `cancel` spending the timelock path of the swap lock.
+pub const CANCEL_WITNESS: WitnessLayout = WitnessLayout {
+    path: Some(ScriptPath::Failure),
+    items: &[
+        WitnessItem::Signature(SwapRole::Bob),
+        WitnessItem::Signature(SwapRole::Alice),
+        WitnessItem::CoopLockScript,
+    ],
+};
+
+/// Witness of the `refund` spending the multisig path of the punishable lock.
+pub const REFUND_WITNESS: WitnessLayout = WitnessLayout {
+    path: Some(ScriptPath::Success),
+    items: &[
+        WitnessItem::Signature(SwapRole::Bob),
+        WitnessItem::Signature(SwapRole::Alice),
+        WitnessItem::True,
+        WitnessItem::PunishLockScript,
+    ],
+};
+
+/// Witness of the `refund` spending the multisig path of the punishable lock with a recovery key,
+/// Alice's signature and either Bob's or the recovery key signature.
+pub const REFUND_RECOVERY_WITNESS: WitnessLayout = WitnessLayout {
+    path: Some(ScriptPath::Success),
+    items: &[
+        WitnessItem::Empty,
+        WitnessItem::AnySignature,
+        WitnessItem::Signature(SwapRole::Alice),
+        WitnessItem::True,
+        WitnessItem::RecoveryPunishLockScript,
+    ],
+};
+
+/// Witness of the `punish` spending the timelock path of the punishable lock.
+pub const PUNISH_WITNESS: WitnessLayout = WitnessLayout {
+    path: Some(ScriptPath::Failure),
+    items: &[
+        WitnessItem::Signature(SwapRole::Alice),
+        WitnessItem::Empty,
+        WitnessItem::PunishLockScript,
+    ],
+};
+
+/// Witness of the collaborative `cancel` spending the multisig path of the swap lock.
+pub const COLLABORATIVE_CANCEL_WITNESS: WitnessLayout = BUY_WITNESS;
+
+/// The witness layouts of the inputs of a transaction template, one per spend path.
+pub trait WitnessTemplate {
+    /// The layouts of the witness spending an input of the transaction.
+    const WITNESS_LAYOUTS: &'static [WitnessLayout];
+
+    /// The maximum size of the witness of an input over all the spend paths.
+    fn max_witness_size() -> usize {
+        Self::WITNESS_LAYOUTS
+            .iter()
+            .map(WitnessLayout::max_size)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+impl<T> Tx<T>
+where
+    T: SubTransaction + WitnessTemplate,
+{
+    /// The layouts of the witness spending an input of the transaction, one per spend path.
+    pub fn witness_layouts() -> &'static [WitnessLayout] {
+        T::WITNESS_LAYOUTS
+    }
+
+    /// The maximum size of the witness of an input over all the spend paths.
+    pub fn max_witness_size() -> usize {
+        T::max_witness_size()
+    }
+}
+
+impl WitnessTemplate for Lock {
+    const WITNESS_LAYOUTS: &'static [WitnessLayout] = &[KEY_SPEND_WITNESS];
+}
+
+impl WitnessTemplate for Buy {
+    const WITNESS_LAYOUTS: &'static [WitnessLayout] = &[BUY_WITNESS];
+}
+
+impl WitnessTemplate for Cancel {
+    const WITNESS_LAYOUTS: &'static [WitnessLayout] = &[CANCEL_WITNESS];
+}
+
+impl WitnessTemplate for CollaborativeCancel {
+    const WITNESS_LAYOUTS: &'static [WitnessLayout] = &[COLLABORATIVE_CANCEL_WITNESS];
+}
+
+impl WitnessTemplate for Refund {
+    const WITNESS_LAYOUTS: &'static [WitnessLayout] = &[REFUND_WITNESS, REFUND_RECOVERY_WITNESS];
+}
+
+impl WitnessTemplate for Punish {
+    const WITNESS_LAYOUTS: &'static [WitnessLayout] = &[PUNISH_WITNESS];
+}
+
+impl WitnessTemplate for Surplus {
+    const WITNESS_LAYOUTS: &'static [WitnessLayout] = &[KEY_SPEND_WITNESS];
+}
+
+impl WitnessTemplate for Sweep {
+    const WITNESS_LAYOUTS: &'static [WitnessLayout] = &[KEY_SPEND_WITNESS];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bitcoin::segwitv0::{BuyTx, CancelTx, LockTx, PunishTx, RefundTx};
+
+    #[test]
+    fn layouts_match_fee_estimations() {
+        assert_eq!(KEY_SPEND_WITNESS.max_size(), 1 + 73 + 34);
+        assert_eq!(BUY_WITNESS.max_size(), 1 + 2 * 73 + 71);
+        assert_eq!(PUNISH_WITNESS.max_size(), 1 + 73 + 1 + 115);
+        assert_eq!(REFUND_WITNESS.max_size(), 1 + 2 * 73 + 2 + 115);
+        assert_eq!(REFUND_RECOVERY_WITNESS.max_size(), 1 + 1 + 2 * 73 + 2 + 151);
+
+        assert_eq!(LockTx::max_witness_size(), Lock::estimated_witness_weight());
+        assert_eq!(BuyTx::max_witness_size(), Buy::estimated_witness_weight());
+        assert_eq!(
+            CancelTx::max_witness_size(),
+            Cancel::estimated_witness_weight()
+        );
+        assert_eq!(
+            PunishTx::max_witness_size(),
+            Punish::estimated_witness_weight()
+        );
+        // The recovery path is the largest spend of the refund
+        assert_eq!(
+            RefundTx::max_witness_size(),
+            REFUND_RECOVERY_WITNESS.max_size()
+        );
+        assert_eq!(RefundTx::witness_layouts()[0], REFUND_WITNESS);
+        assert_eq!(
+            RefundTx::witness_layouts()[1].path,
+            Some(ScriptPath::Success)
+        );
+    }
+}
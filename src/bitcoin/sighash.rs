@@ -0,0 +1,453 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Signature hash algorithms for the UTXO based arbitrating blockchains. Transaction templates are
+//! shared between chains, only the way inputs commit to the spent output differs.
+//!
+//! [`Bip143`] is used on Bitcoin for SegWit version 0 inputs. [`ForkId`] is the replay protected
+//! variant used by Bitcoin Cash: the same [`BIP-143`][bip-143] preimage is hashed, but the sighash
+//! type committed in the preimage carries the `SIGHASH_FORKID` flag and the fork identifier. As
+//! Bitcoin Cash has no SegWit the resulting signatures are used in legacy script signatures.
+//!
+//! [bip-143]: https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{EcdsaSighashType, NonStandardSighashType};
+use bitcoin::consensus::encode::Encodable;
+use bitcoin::hashes::sha256d::Hash as Sha256dHash;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::util::sighash::SighashCache;
+use bitcoin::Amount;
+
+use crate::bitcoin::transaction::TxInRef;
+
+/// The `SIGHASH_FORKID` flag used for replay protection.
+pub const SIGHASH_FORKID: u32 = 0x40;
+
+/// The fork identifier used by Bitcoin Cash.
+pub const BITCOIN_CASH_FORK_ID: u32 = 0;
+
+const SIGHASH_ANYONECANPAY: u32 = 0x80;
+const SIGHASH_MASK: u32 = 0x1f;
+
+/// An algorithm computing the message signed by an input.
+pub trait SighashAlgorithm {
+    /// Return the sighash type committed in the preimage for the given type.
+    fn hash_type(&self, sighash_type: EcdsaSighashType) -> u32;
+
+    /// Return the byte appended to signatures produced for the given type.
+    fn sighash_byte(&self, sighash_type: EcdsaSighashType) -> u8 {
+        (self.hash_type(sighash_type) & 0xff) as u8
+    }
+
+    /// Computes the sighash of the input spending an output locked with `script` and holding
+    /// `value`.
+    fn signature_hash(
+        &self,
+        txin: TxInRef,
+        script: &Script,
+        value: Amount,
+        sighash_type: EcdsaSighashType,
+    ) -> Sha256dHash {
+        bip143_signature_hash(txin, script, value, self.hash_type(sighash_type))
+    }
+}
+
+/// The [`BIP-143`][bip-143] sighash algorithm used by SegWit version 0 inputs.
+///
+/// [bip-143]: https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Bip143;
+
+impl SighashAlgorithm for Bip143 {
+    fn hash_type(&self, sighash_type: EcdsaSighashType) -> u32 {
+        sighash_type.to_u32()
+    }
+
+    fn signature_hash(
+        &self,
+        txin: TxInRef,
+        script: &Script,
+        value: Amount,
+        sighash_type: EcdsaSighashType,
+    ) -> Sha256dHash {
+        SighashCache::new(txin.transaction())
+            .segwit_signature_hash(txin.index(), script, value.as_sat(), sighash_type)
+            .expect("encoding works")
+            .as_hash()
+    }
+}
+
+/// The [`BIP-143`][bip-143] sighash algorithm with `SIGHASH_FORKID` replay protection, the
+/// committed sighash type is `sighash | SIGHASH_FORKID | fork_id << 8`.
+///
+/// [bip-143]: https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ForkId {
+    /// The 24 bits fork identifier.
+    pub fork_id: u32,
+}
+
+impl ForkId {
+    /// The replay protection used by Bitcoin Cash.
+    pub fn bitcoin_cash() -> Self {
+        Self {
+            fork_id: BITCOIN_CASH_FORK_ID,
+        }
+    }
+}
+
+impl Default for ForkId {
+    fn default() -> Self {
+        Self::bitcoin_cash()
+    }
+}
+
+impl SighashAlgorithm for ForkId {
+    fn hash_type(&self, sighash_type: EcdsaSighashType) -> u32 {
+        sighash_type.to_u32() | SIGHASH_FORKID | ((self.fork_id & 0x00ff_ffff) << 8)
+    }
+}
+
+/// Split a raw sighash type, as committed in a preimage or set in a partial transaction, into its
+/// standard sighash type and its replay protection, if the `SIGHASH_FORKID` flag is set. Fails if
+/// the standard part is not a standard sighash type.
+pub fn split_hash_type(
+    hash_type: u32,
+) -> Result<(EcdsaSighashType, Option<ForkId>), NonStandardSighashType> {
+    let sighash_type = EcdsaSighashType::from_standard(hash_type & 0xff & !SIGHASH_FORKID)?;
+    let fork_id = (hash_type & SIGHASH_FORKID != 0).then(|| ForkId {
+        fork_id: hash_type >> 8,
+    });
+    Ok((sighash_type, fork_id))
+}
+
+/// Computes the [`BIP-143`][bip-143] sighash committing to the raw `hash_type`.
+///
+/// [bip-143]: https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
+pub fn bip143_signature_hash(
+    txin: TxInRef,
+    script: &Script,
+    value: Amount,
+    hash_type: u32,
+) -> Sha256dHash {
+    let tx = txin.transaction();
+    let index = txin.index();
+    let anyone_can_pay = hash_type & SIGHASH_ANYONECANPAY != 0;
+    let base = hash_type & SIGHASH_MASK;
+    let is_single = base == EcdsaSighashType::Single.to_u32();
+    let is_none = base == EcdsaSighashType::None.to_u32();
+
+    let hash_prevouts = if anyone_can_pay {
+        Sha256dHash::default()
+    } else {
+        let mut engine = Sha256dHash::engine();
+        for input in &tx.input {
+            input
+                .previous_output
+                .consensus_encode(&mut engine)
+                .expect("engines don't error");
+        }
+        Sha256dHash::from_engine(engine)
+    };
+
+    let hash_sequence = if anyone_can_pay || is_single || is_none {
+        Sha256dHash::default()
+    } else {
+        let mut engine = Sha256dHash::engine();
+        for input in &tx.input {
+            input
+                .sequence
+                .consensus_encode(&mut engine)
+                .expect("engines don't error");
+        }
+        Sha256dHash::from_engine(engine)
+    };
+
+    let hash_outputs = if !is_single && !is_none {
+        let mut engine = Sha256dHash::engine();
+        for output in &tx.output {
+            output
+                .consensus_encode(&mut engine)
+                .expect("engines don't error");
+        }
+        Sha256dHash::from_engine(engine)
+    } else if is_single && index < tx.output.len() {
+        let mut engine = Sha256dHash::engine();
+        tx.output[index]
+            .consensus_encode(&mut engine)
+            .expect("engines don't error");
+        Sha256dHash::from_engine(engine)
+    } else {
+        Sha256dHash::default()
+    };
+
+    let input = txin.input();
+    let mut engine = Sha256dHash::engine();
+    (|| -> Result<(), std::io::Error> {
+        tx.version.consensus_encode(&mut engine)?;
+        engine.input(&hash_prevouts[..]);
+        engine.input(&hash_sequence[..]);
+        input.previous_output.consensus_encode(&mut engine)?;
+        script.consensus_encode(&mut engine)?;
+        value.as_sat().consensus_encode(&mut engine)?;
+        input.sequence.consensus_encode(&mut engine)?;
+        engine.input(&hash_outputs[..]);
+        tx.lock_time.consensus_encode(&mut engine)?;
+        hash_type.consensus_encode(&mut engine)?;
+        Ok(())
+    })()
+    .expect("engines don't error");
+    Sha256dHash::from_engine(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use bitcoin::hashes::hex::FromHex;
+    use bitcoin::{Transaction, Txid, Witness};
+
+    fn transaction() -> Transaction {
+        let input = |vout| TxIn {
+            previous_output: OutPoint::new(Default::default(), vout),
+            script_sig: Script::default(),
+            sequence: 0xffff_fffd,
+            witness: Witness::default(),
+        };
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![input(0), input(1)],
+            output: vec![
+                TxOut {
+                    value: 10_000,
+                    script_pubkey: Script::new_op_return(&[1, 2, 3]),
+                },
+                TxOut {
+                    value: 20_000,
+                    script_pubkey: Script::new_op_return(&[4, 5, 6]),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn bip143_preimage_matches_segwit_sighash() {
+        let tx = transaction();
+        let script = Script::new_op_return(&[7]);
+        let value = Amount::from_sat(42_000);
+        for sighash_type in [
+            EcdsaSighashType::All,
+            EcdsaSighashType::None,
+            EcdsaSighashType::Single,
+            EcdsaSighashType::AllPlusAnyoneCanPay,
+            EcdsaSighashType::NonePlusAnyoneCanPay,
+            EcdsaSighashType::SinglePlusAnyoneCanPay,
+        ] {
+            for index in 0..2 {
+                let txin = TxInRef::new(&tx, index);
+                assert_eq!(
+                    bip143_signature_hash(txin, &script, value, sighash_type.to_u32()),
+                    Bip143.signature_hash(txin, &script, value, sighash_type),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn forkid_commits_to_replay_protection() {
+        let tx = transaction();
+        let txin = TxInRef::new(&tx, 0);
+        let script = Script::new_op_return(&[7]);
+        let value = Amount::from_sat(42_000);
+
+        let bch = ForkId::bitcoin_cash();
+        assert_eq!(bch.hash_type(EcdsaSighashType::All), 0x41);
+        assert_eq!(bch.sighash_byte(EcdsaSighashType::All), 0x41);
+        assert_eq!(
+            ForkId { fork_id: 0xab }.hash_type(EcdsaSighashType::All),
+            0xab41
+        );
+
+        let btc_hash = Bip143.signature_hash(txin, &script, value, EcdsaSighashType::All);
+        let bch_hash = bch.signature_hash(txin, &script, value, EcdsaSighashType::All);
+        assert_ne!(btc_hash, bch_hash);
+        assert_eq!(bch_hash, bip143_signature_hash(txin, &script, value, 0x41));
+        assert_ne!(
+            bch_hash,
+            ForkId { fork_id: 1 }.signature_hash(txin, &script, value, EcdsaSighashType::All)
+        );
+    }
+
+    #[test]
+    fn split_raw_hash_types() {
+        assert_eq!(
+            split_hash_type(0x01).unwrap(),
+            (EcdsaSighashType::All, None)
+        );
+        assert_eq!(
+            split_hash_type(0xc3).unwrap(),
+            (
+                EcdsaSighashType::SinglePlusAnyoneCanPay,
+                Some(ForkId::bitcoin_cash())
+            )
+        );
+        assert_eq!(
+            split_hash_type(0xab41).unwrap(),
+            (EcdsaSighashType::All, Some(ForkId { fork_id: 0xab }))
+        );
+        assert!(split_hash_type(0x04).is_err());
+        assert!(split_hash_type(0x44).is_err());
+    }
+
+    fn hex_script(s: &str) -> Script {
+        Script::from(Vec::<u8>::from_hex(s).unwrap())
+    }
+
+    // Vector of the BIP-143 forkid test in the `sv` crate, Bitcoin SV shares the Bitcoin Cash
+    // replay protection
+    #[test]
+    fn forkid_reference_vector() {
+        let p2pkh = hex_script("76a91402b74813b047606b4b3fbdfb1a6e8e053fdb8dab88ac");
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(
+                    Txid::from_hex(
+                        "f671dc000ad12795e86b59b27e0c367d9b026bbd4141c227b9285867a53bb6f7",
+                    )
+                    .unwrap(),
+                    0,
+                ),
+                script_sig: Script::default(),
+                sequence: 0,
+                witness: Witness::default(),
+            }],
+            output: vec![
+                TxOut {
+                    value: 100,
+                    script_pubkey: p2pkh.clone(),
+                },
+                TxOut {
+                    value: 259_899_900,
+                    script_pubkey: p2pkh.clone(),
+                },
+            ],
+        };
+        let hash = ForkId::bitcoin_cash().signature_hash(
+            TxInRef::new(&tx, 0),
+            &p2pkh,
+            Amount::from_sat(260_000_000),
+            EcdsaSighashType::All,
+        );
+        assert_eq!(
+            hash.into_inner().to_vec(),
+            Vec::<u8>::from_hex("1e2121837829018daf3aeadab76f1a542c49a3600ded7bd74323ee74ce0d840c")
+                .unwrap()
+        );
+    }
+
+    // Sighashes of a 2-of-2 multisig spend for all sighash types, computed with the `sv` crate
+    // reference implementation
+    #[test]
+    fn forkid_reference_sighash_types() {
+        let script = hex_script("5221022a1c8b6c7879f4137a1a8dfc0db830bbcc9a7d46bdbbab5e35bbe2fde4e8ed1e2103e0a5b3f202fa48d4f5c0bd73e24461433b5685032e1f3ba78e07b5895a3f58f352ae");
+        let input = |txid: &str, vout, sequence| TxIn {
+            previous_output: OutPoint::new(Txid::from_hex(txid).unwrap(), vout),
+            script_sig: Script::default(),
+            sequence,
+            witness: Witness::default(),
+        };
+        let tx = Transaction {
+            version: 2,
+            lock_time: 650_000,
+            input: vec![
+                input(
+                    "f671dc000ad12795e86b59b27e0c367d9b026bbd4141c227b9285867a53bb6f7",
+                    1,
+                    0xffff_fffd,
+                ),
+                input(
+                    "bf6c1139ea01ca054b8d00aa0a088daaeab4f3b8e111626c6be7d603a9dd8dff",
+                    0,
+                    4,
+                ),
+            ],
+            output: vec![
+                TxOut {
+                    value: 149_000,
+                    script_pubkey: hex_script("76a91402b74813b047606b4b3fbdfb1a6e8e053fdb8dab88ac"),
+                },
+                TxOut {
+                    value: 50_000,
+                    script_pubkey: hex_script("a914748284390f9e263a4b766a75d0633c50426eb87587"),
+                },
+            ],
+        };
+        let vectors = [
+            (
+                EcdsaSighashType::All,
+                "8408ad844dc5a485e1414d627167b4eddf42b4a3aeb45aae68d3eef53437e895",
+                "d285a372c6a88f61250a4878b43c1a20d505c28699a8702eff4f2b079ed83420",
+            ),
+            (
+                EcdsaSighashType::None,
+                "4c3d31e07e16c50f52be6803aab6046c24afff8588e492e51bf78ee766e86a0f",
+                "33befbd354d803746c7d9503e0187191a32ef76fce5eda548d99956154644a6d",
+            ),
+            (
+                EcdsaSighashType::Single,
+                "d1020e63ca8e93b05e8cc867f7056e26f4be8591f11a80495ed9dea9cc06a810",
+                "7825f3c20952280162e942de1828588e8c29be0e8b5c723005a1ad8d8de85cfe",
+            ),
+            (
+                EcdsaSighashType::AllPlusAnyoneCanPay,
+                "797d9fd4110de93f4aed57031be0191eb9f5b4ad960e65fbd8b007ce648ebf48",
+                "ca8516666ea3dd20437f64c2e4ec6f235b57abf95fcbb1c6cc4c6dbf198e5085",
+            ),
+            (
+                EcdsaSighashType::NonePlusAnyoneCanPay,
+                "759c1912859c9012d82e7113b5444b760b8e3d1c2b6bb09d4dfbff1c5f4ddf2d",
+                "5b3488b9f520eae76ca2e8bfdd926f971a6250830489964526ce72ae64a7f1fa",
+            ),
+            (
+                EcdsaSighashType::SinglePlusAnyoneCanPay,
+                "8644411dcef64533df62be314821991019664747116fb1ee37545487ba41b669",
+                "dd1e897dcb2476dbdb98934dde929ab1a4e686720c41491d57e9ab3879253e13",
+            ),
+        ];
+        for (sighash_type, first, second) in vectors {
+            for (index, expected) in [first, second].into_iter().enumerate() {
+                let hash = ForkId::bitcoin_cash().signature_hash(
+                    TxInRef::new(&tx, index),
+                    &script,
+                    Amount::from_sat(200_000),
+                    sighash_type,
+                );
+                assert_eq!(
+                    hash.into_inner().to_vec(),
+                    Vec::<u8>::from_hex(expected).unwrap(),
+                    "{:?} input {}",
+                    sighash_type,
+                    index
+                );
+            }
+        }
+    }
+}
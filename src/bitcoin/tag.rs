@@ -0,0 +1,139 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Opt-in `OP_RETURN` outputs tagging the funding or lock transaction of a swap. The tag carries
+//! a truncated hash of the swap identifier so recovery tooling and block explorers operated by
+//! the user can re-associate on-chain transactions with the locally stored swap checkpoints,
+//! while third parties cannot recover the swap identifier from it.
+//!
+//! Tagging a transaction adds a zero-value output, it must be done before signing and, for the
+//! funding transaction, by the external wallet with [`SwapTag::tx_out`]. The tag output is ignored
+//! by the transactions built on top.
+
+use bitcoin::blockdata::opcodes;
+use bitcoin::blockdata::script::{Builder, Instruction, Script};
+use bitcoin::blockdata::transaction::TxOut;
+
+use crate::crypto::commitment::tagged_hash;
+use crate::swap::SwapId;
+
+/// Magic bytes prefixing the tag in the `OP_RETURN` output.
+pub const SWAP_TAG_MAGIC: [u8; 4] = *b"FCSW";
+
+/// Length of the truncated swap identifier hash.
+pub const SWAP_TAG_LEN: usize = 8;
+
+/// A truncated hash of a swap identifier carried in an `OP_RETURN` output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SwapTag([u8; SWAP_TAG_LEN]);
+
+impl SwapTag {
+    /// Tag of the hash used to derive swap tags.
+    pub const DERIVATION_TAG: &'static [u8] = b"farcaster/swap-tag";
+
+    /// Derive the tag of a swap, the first [`SWAP_TAG_LEN`] bytes of a tagged SHA-256 hash of the
+    /// swap identifier.
+    pub fn new(swap_id: SwapId) -> Self {
+        let hash = tagged_hash(Self::DERIVATION_TAG, swap_id.0.as_ref().as_bytes());
+        let mut bytes = [0u8; SWAP_TAG_LEN];
+        bytes.copy_from_slice(&hash[..SWAP_TAG_LEN]);
+        Self(bytes)
+    }
+
+    /// Return true if the tag has been derived from the swap identifier.
+    pub fn matches(&self, swap_id: SwapId) -> bool {
+        *self == Self::new(swap_id)
+    }
+
+    /// Return the bytes of the tag.
+    pub fn to_bytes(&self) -> [u8; SWAP_TAG_LEN] {
+        self.0
+    }
+
+    /// Return the `OP_RETURN` script carrying the tag.
+    pub fn script_pubkey(&self) -> Script {
+        let mut data = SWAP_TAG_MAGIC.to_vec();
+        data.extend_from_slice(&self.0);
+        Builder::new()
+            .push_opcode(opcodes::all::OP_RETURN)
+            .push_slice(&data)
+            .into_script()
+    }
+
+    /// Return the zero-value transaction output carrying the tag.
+    pub fn tx_out(&self) -> TxOut {
+        TxOut {
+            value: 0,
+            script_pubkey: self.script_pubkey(),
+        }
+    }
+
+    /// Return the tag carried by the output, if any.
+    pub fn from_output(txout: &TxOut) -> Option<Self> {
+        if txout.value != 0 || !txout.script_pubkey.is_op_return() {
+            return None;
+        }
+        let mut instructions = txout.script_pubkey.instructions().skip(1);
+        let data = match (instructions.next(), instructions.next()) {
+            (Some(Ok(Instruction::PushBytes(data))), None) => data,
+            _ => return None,
+        };
+        (data.len() == SWAP_TAG_MAGIC.len() + SWAP_TAG_LEN && data.starts_with(&SWAP_TAG_MAGIC))
+            .then(|| {
+                let mut bytes = [0u8; SWAP_TAG_LEN];
+                bytes.copy_from_slice(&data[SWAP_TAG_MAGIC.len()..]);
+                Self(bytes)
+            })
+    }
+
+    /// Return the tag carried by one of the transaction outputs, if any.
+    pub fn from_transaction(tx: &bitcoin::Transaction) -> Option<Self> {
+        tx.output.iter().find_map(Self::from_output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Uuid;
+
+    #[test]
+    fn tag_round_trip() {
+        let swap_id = SwapId(Uuid::new());
+        let tag = SwapTag::new(swap_id);
+        assert!(tag.matches(swap_id));
+        assert!(!tag.matches(SwapId(Uuid::new())));
+
+        let txout = tag.tx_out();
+        assert!(txout.script_pubkey.is_op_return());
+        // OP_RETURN, push of 12 bytes, magic and truncated hash
+        assert_eq!(txout.script_pubkey.len(), 14);
+        assert_eq!(SwapTag::from_output(&txout), Some(tag));
+
+        let mut txout = tag.tx_out();
+        txout.value = 1;
+        assert_eq!(SwapTag::from_output(&txout), None);
+        let other = TxOut {
+            value: 0,
+            script_pubkey: Builder::new()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .push_slice(&[0; 12])
+                .into_script(),
+        };
+        assert_eq!(SwapTag::from_output(&other), None);
+    }
+}
@@ -23,10 +23,14 @@ use std::str::FromStr;
 
 use crate::bitcoin::{Bitcoin, BitcoinTaproot, Btc, Strategy};
 use crate::consensus::{self, CanonicalBytes};
+use crate::crypto::musig2::KeyAggContext;
 use crate::crypto::{DeriveKeys, SharedKeyId};
 //use crate::role::Arbitrating;
 
+use bitcoin::blockdata::witness::Witness;
 use bitcoin::secp256k1::{schnorr::Signature, KeyPair, XOnlyPublicKey};
+use bitcoin::util::schnorr::TweakedPublicKey;
+use bitcoin::Script;
 
 /// Inner type for the Taproot strategy with on-chain scripts.
 #[derive(Clone, Debug, Copy, Eq, PartialEq)]
@@ -59,6 +63,21 @@ impl From<BitcoinTaproot> for Btc {
 
 //impl Arbitrating for Bitcoin<Taproot> {}
 
+/// Create the output script of a lock spendable only through the key path by the MuSig2
+/// aggregated key of the participants. `key_agg` must be tweaked for Taproot, see
+/// [`KeyAggContext::with_taproot_tweak`].
+pub fn key_path_lock_script(key_agg: &KeyAggContext) -> Script {
+    Script::new_v1_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(
+        key_agg.aggregated_key(),
+    ))
+}
+
+/// Create the witness spending a lock through the key path with the aggregated MuSig2
+/// signature, for the default sighash type.
+pub fn key_path_witness(sig: &Signature) -> Witness {
+    Witness::from_vec(vec![sig.as_ref().to_vec()])
+}
+
 impl TryFrom<Btc> for Bitcoin<Taproot> {
     type Error = consensus::Error;
 
@@ -116,3 +135,77 @@ impl CanonicalBytes for Signature {
         Signature::from_slice(bytes).map_err(consensus::Error::new)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+    use bitcoin::util::sighash::{Prevouts, SighashCache};
+    use bitcoin::SchnorrSighashType;
+
+    use crate::crypto::musig2::{SecretNonce, Session};
+
+    #[test]
+    fn spend_lock_through_key_path() {
+        let secp = Secp256k1::new();
+        let secrets = [
+            SecretKey::from_slice(&[1u8; 32]).unwrap(),
+            SecretKey::from_slice(&[2u8; 32]).unwrap(),
+        ];
+        let pubkeys: Vec<PublicKey> = secrets
+            .iter()
+            .map(|s| PublicKey::from_secret_key(&secp, s))
+            .collect();
+        let key_agg = KeyAggContext::new(pubkeys)
+            .unwrap()
+            .with_taproot_tweak(None)
+            .unwrap();
+
+        let lock_output = TxOut {
+            value: 100_000,
+            script_pubkey: key_path_lock_script(&key_agg),
+        };
+        let mut tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Script::default(),
+                sequence: 0xffffffff,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 99_000,
+                script_pubkey: Script::default(),
+            }],
+        };
+        let sighash = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&[lock_output]),
+                SchnorrSighashType::Default,
+            )
+            .unwrap();
+        let message = Message::from_slice(&sighash[..]).unwrap();
+
+        let nonces: Vec<SecretNonce> = (0..2)
+            .map(|_| SecretNonce::generate(&mut rand::thread_rng()))
+            .collect();
+        let public_nonces: Vec<_> = nonces.iter().map(|n| n.public_nonce()).collect();
+        let session = Session::new(&key_agg, &public_nonces, &message).unwrap();
+        let partial_sigs: Vec<_> = nonces
+            .into_iter()
+            .zip(secrets.iter())
+            .map(|(nonce, secret)| session.sign(&secp, nonce, secret).unwrap())
+            .collect();
+        let sig = session.aggregate(&secp, &partial_sigs).unwrap();
+
+        tx.input[0].witness = key_path_witness(&sig);
+        assert_eq!(tx.input[0].witness.len(), 1);
+        assert!(secp
+            .verify_schnorr(&sig, &message, &key_agg.aggregated_key())
+            .is_ok());
+    }
+}
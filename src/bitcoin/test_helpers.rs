@@ -0,0 +1,44 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Deterministic keys and addresses shared by the unit tests of the Bitcoin templates, each
+//! fixture is derived from a secret key filled with `byte`.
+
+use bitcoin::network::constants::Network;
+use bitcoin::secp256k1::{All, PublicKey, Secp256k1, SecretKey};
+use bitcoin::Address;
+use once_cell::sync::OnceCell;
+
+/// The secp256k1 context shared by the tests.
+pub fn secp() -> &'static Secp256k1<All> {
+    static CONTEXT: OnceCell<Secp256k1<All>> = OnceCell::new();
+    CONTEXT.get_or_init(Secp256k1::new)
+}
+
+/// The secret key with all its bytes set to `byte`.
+pub fn secret(byte: u8) -> SecretKey {
+    SecretKey::from_slice(&[byte; 32]).unwrap()
+}
+
+/// The public key of [`secret`].
+pub fn pubkey(byte: u8) -> PublicKey {
+    PublicKey::from_secret_key(secp(), &secret(byte))
+}
+
+/// The regtest P2WPKH address of [`pubkey`].
+pub fn address(byte: u8) -> Address {
+    Address::p2wpkh(&bitcoin::PublicKey::new(pubkey(byte)), Network::Regtest).unwrap()
+}
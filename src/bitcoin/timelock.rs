@@ -15,17 +15,45 @@
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
 
 //! Timelock unit and `OP_CODE` to use in Bitcoin transactions and scripts.
+//!
+//! Relative timelocks follow [BIP-68][bip-68]: a timelock is either a number of blocks or, when
+//! the type flag is set, a number of 512 seconds intervals of median time past. The same value is
+//! pushed in the scripts for `OP_CSV` and set in the sequence of the spending input.
+//!
+//! [bip-68]: https://github.com/bitcoin/bips/blob/master/bip-0068.mediawiki
 
+use crate::blockchain::Timelock;
 use crate::consensus::{self, CanonicalBytes};
 
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::str::FromStr;
 
+/// Flag set in time-based relative timelocks.
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+
+/// Mask of the value of a relative timelock, in blocks or in time intervals.
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
+
+/// Duration of a time interval of time-based relative timelocks, in seconds.
+pub const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 512;
+
+/// Expected interval between two Bitcoin blocks, in seconds. Used to express time-based
+/// timelocks in blocks.
+pub const TARGET_BLOCK_INTERVAL: u32 = 600;
+
 /// An `OP_CSV` value (32-bits integer) to use in transactions and scripts.
-#[derive(PartialEq, Eq, PartialOrd, Clone, Debug, Hash, Copy, Display, Serialize, Deserialize)]
-#[display("{0} blocks")]
+#[derive(PartialEq, Eq, PartialOrd, Clone, Debug, Hash, Copy, Serialize, Deserialize)]
 pub struct CSVTimelock(u32);
 
+impl fmt::Display for CSVTimelock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.seconds() {
+            Some(seconds) => write!(f, "{} seconds", seconds),
+            None => write!(f, "{} blocks", self.0),
+        }
+    }
+}
+
 impl FromStr for CSVTimelock {
     type Err = consensus::Error;
 
@@ -48,6 +76,63 @@ impl CSVTimelock {
         self.0
     }
 
+    /// Create a timelock of a number of blocks.
+    pub fn from_blocks(blocks: u16) -> Self {
+        Self(blocks as u32)
+    }
+
+    /// Create a time-based timelock of a number of 512 seconds intervals.
+    pub fn from_intervals(intervals: u16) -> Self {
+        Self(SEQUENCE_LOCKTIME_TYPE_FLAG | intervals as u32)
+    }
+
+    /// Create a time-based timelock lasting at least `seconds`, rounded up to the next 512
+    /// seconds interval. Fails if the duration exceeds the maximum relative timelock.
+    pub fn from_seconds(seconds: u32) -> Result<Self, consensus::Error> {
+        let intervals = (seconds as u64 + SEQUENCE_LOCKTIME_GRANULARITY as u64 - 1)
+            / SEQUENCE_LOCKTIME_GRANULARITY as u64;
+        (intervals <= SEQUENCE_LOCKTIME_MASK as u64)
+            .then(|| Self::from_intervals(intervals as u16))
+            .ok_or(consensus::Error::ParseFailed("CSV timelock too long"))
+    }
+
+    /// Return true if the timelock is expressed in time intervals instead of blocks.
+    pub fn is_time_based(&self) -> bool {
+        self.0 & SEQUENCE_LOCKTIME_TYPE_FLAG != 0
+    }
+
+    /// Return true if only the type flag and the value are set, other bits would be ignored by
+    /// the consensus rules or disable the timelock.
+    pub fn is_valid(&self) -> bool {
+        self.0 & !(SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK) == 0
+    }
+
+    /// Return true if the timelock expires immediately.
+    pub fn is_zero(&self) -> bool {
+        self.0 & SEQUENCE_LOCKTIME_MASK == 0
+    }
+
+    /// Return the number of blocks of a block-based timelock.
+    pub fn blocks(&self) -> Option<u32> {
+        (!self.is_time_based()).then(|| self.0 & SEQUENCE_LOCKTIME_MASK)
+    }
+
+    /// Return the duration of a time-based timelock, in seconds.
+    pub fn seconds(&self) -> Option<u32> {
+        self.is_time_based()
+            .then(|| (self.0 & SEQUENCE_LOCKTIME_MASK) * SEQUENCE_LOCKTIME_GRANULARITY)
+    }
+
+    /// Return the timelock in blocks. Time-based timelocks are converted with the
+    /// [`TARGET_BLOCK_INTERVAL`], rounded down: blocks may be mined slower than expected, fewer
+    /// blocks than the estimate can be mined before the timelock expires.
+    pub fn as_blocks(&self) -> u32 {
+        match self.seconds() {
+            Some(seconds) => seconds / TARGET_BLOCK_INTERVAL,
+            None => self.0 & SEQUENCE_LOCKTIME_MASK,
+        }
+    }
+
     /// Return the value of nSequence that disable `CHECK_SEQUENCE_VERIFY`.
     pub fn disable() -> u32 {
         (1 << 31) as u32
@@ -60,9 +145,9 @@ impl From<u32> for CSVTimelock {
     }
 }
 
-impl From<CSVTimelock> for u32 {
-    fn from(ti: CSVTimelock) -> Self {
-        ti.as_u32()
+impl Timelock for CSVTimelock {
+    fn as_blocks(&self) -> u32 {
+        CSVTimelock::as_blocks(self)
     }
 }
 
@@ -92,3 +177,33 @@ impl CanonicalBytes for CSVTimelock {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_based_timelocks() {
+        let blocks = CSVTimelock::from_blocks(144);
+        assert!(!blocks.is_time_based());
+        assert_eq!(blocks.blocks(), Some(144));
+        assert_eq!(blocks.as_blocks(), 144);
+        assert_eq!(blocks.to_string(), "144 blocks");
+
+        // A day, rounded up to the next interval
+        let day = CSVTimelock::from_seconds(86_400).unwrap();
+        assert!(day.is_time_based());
+        assert_eq!(day.as_u32(), SEQUENCE_LOCKTIME_TYPE_FLAG | 169);
+        assert_eq!(day.seconds(), Some(169 * 512));
+        assert_eq!(day.blocks(), None);
+        assert_eq!(day.as_blocks(), 144);
+        assert_eq!(day.to_string(), "86528 seconds");
+        assert_eq!(CSVTimelock::from_intervals(169), day);
+
+        assert!(CSVTimelock::from_seconds(512 * 0xffff).is_ok());
+        assert!(CSVTimelock::from_seconds(512 * 0xffff + 1).is_err());
+        assert!(day.is_valid());
+        assert!(!CSVTimelock::new(CSVTimelock::disable() | 10).is_valid());
+        assert!(CSVTimelock::from_intervals(0).is_zero());
+    }
+}
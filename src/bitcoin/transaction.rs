@@ -17,13 +17,19 @@
 //! Bitcoin transactions framework. This module contains types shared across strategies.
 
 use std::fmt::Debug;
+use std::io;
 use std::marker::PhantomData;
 
-use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::script::{Builder, Script};
 use bitcoin::blockdata::transaction::{EcdsaSighashType, OutPoint, TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::consensus::encode::{Encodable as _, VarInt};
+use bitcoin::hashes::Hash;
 use bitcoin::util::address;
 use bitcoin::util::ecdsa::EcdsaSig;
-use bitcoin::util::psbt::{self, PartiallySignedTransaction};
+use bitcoin::util::psbt::{self, PartiallySignedTransaction, PsbtSighashType};
+use bitcoin::{Txid, Wtxid};
+use once_cell::sync::OnceCell;
 
 #[cfg(feature = "experimental")]
 use bitcoin::{
@@ -33,13 +39,19 @@ use bitcoin::{
 
 use thiserror::Error;
 
+use crate::bitcoin::anchor::AnchorOutput;
+use crate::bitcoin::fee::{self, SatPerKvB};
+use crate::bitcoin::metadata::TxMetadata;
+use crate::bitcoin::sighash::{self, Bip143, ForkId, SighashAlgorithm};
+use crate::bitcoin::tag::SwapTag;
+use crate::bitcoin::truc::TRUC_VERSION;
+use crate::blockchain::{Fee, FeePriority, FeeStrategy, FeeStrategyError};
 use crate::consensus::{self, CanonicalBytes};
 use crate::transaction::{Broadcastable, Error as FError, Finalizable, Linkable};
 use bitcoin::hashes::sha256d::Hash as Sha256dHash;
 
 #[cfg(feature = "experimental")]
 use crate::{
-    bitcoin::segwitv0::signature_hash,
     script::ScriptPath,
     transaction::{Transaction, Witnessable},
 };
@@ -51,9 +63,18 @@ pub enum Error {
     /// Multi-input transaction is not supported
     #[error("Multi-input transaction is not supported")]
     MultiUTXOUnsuported,
+    /// The shares of the punish policy exceed the punished amount
+    #[error("The shares of the punish policy exceed the punished amount")]
+    InvalidPunishPolicy,
+    /// Output value is below the dust limit
+    #[error("Output value of {0} satoshis is below the dust limit")]
+    DustOutput(u64),
     /// SigHash type is missing
     #[error("SigHash type is missing")]
     MissingSigHashType,
+    /// SigHash type is not accepted by the transaction
+    #[error("SigHash type `{0}` is not accepted by the transaction")]
+    UnsupportedSigHashType(EcdsaSighashType),
     /// Partially signed transaction error
     #[error("Partially signed transaction error: `{0}`")]
     Psbt(#[from] psbt::Error),
@@ -75,13 +96,52 @@ impl From<Error> for FError {
 }
 
 /// A reference to some transaction output used to build new transaction on top of it.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct MetadataOutput {
     /// A reference to the transaction output with `txid` and `vout` index.
     pub out_point: OutPoint,
     /// A transaction output which defines the value (in satoshis) and the `script_pubkey`.
     pub tx_out: TxOut,
     pub script_pubkey: Option<Script>,
+    /// The redeem script if the output is a P2SH wrapped SegWit output.
+    pub redeem_script: Option<Script>,
+    /// The witness transaction id of the transaction creating the output, if known. Unlike the
+    /// `txid` it changes if the witness is malleated.
+    pub wtxid: Option<Wtxid>,
+}
+
+impl MetadataOutput {
+    /// The transaction id of the transaction creating the output.
+    pub fn txid(&self) -> Txid {
+        self.out_point.txid
+    }
+}
+
+// The witness transaction id is not part of the chaining of transactions, an output is the same
+// whatever witness the transaction creating it has.
+impl PartialEq for MetadataOutput {
+    fn eq(&self, other: &Self) -> bool {
+        self.out_point == other.out_point
+            && self.tx_out == other.tx_out
+            && self.script_pubkey == other.script_pubkey
+            && self.redeem_script == other.redeem_script
+    }
+}
+
+impl Eq for MetadataOutput {}
+
+/// The values negotiated for a transaction built by the counter-party, checked with
+/// [`Tx::verify_template_with`] before signing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateParams {
+    /// The output the single input must spend, with its value and scripts.
+    pub prev_output: MetadataOutput,
+    /// The sequence of the input, e.g. the cancel timelock.
+    pub sequence: u32,
+    /// The script the first output must pay to.
+    pub script_pubkey: Script,
+    /// The fee strategy the fee of the transaction must follow.
+    pub fee_strategy: FeeStrategy<SatPerKvB>,
 }
 
 /// Defines the inner behaviour of a generic transaction [`Tx`].
@@ -89,27 +149,469 @@ pub trait SubTransaction: Debug {
     /// Defines the behaviour for finalizing the `PartiallySignedTransaction` from a generic
     /// transaction [`Tx`].
     fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError>;
+
+    /// Return the upper bound, in weight units, of the witness of one input of the transaction.
+    /// The weight includes the item count and the length prefix of each witness item. Used to
+    /// estimate the size of the final transaction when applying fees.
+    fn estimated_witness_weight() -> usize;
+
+    /// Return the upper bound, in weight units, of the witness of the input at `index` given its
+    /// witness script. Defaults to [`SubTransaction::estimated_witness_weight`], overridden when
+    /// the input can be spent with witness templates of different sizes.
+    fn estimated_input_weight(_psbt: &PartiallySignedTransaction, _index: usize) -> usize {
+        Self::estimated_witness_weight()
+    }
+
+    /// Return the keys that must sign the input at `index` before the transaction can be
+    /// finalized. An empty list means a single signature from any key is enough.
+    fn required_signers(
+        _psbt: &PartiallySignedTransaction,
+        _index: usize,
+    ) -> Result<Vec<bitcoin::PublicKey>, FError> {
+        Ok(vec![])
+    }
+
+    /// Return how many of the [`SubTransaction::required_signers`] must sign the input at
+    /// `index`. Defaults to all of them.
+    fn signature_threshold(
+        psbt: &PartiallySignedTransaction,
+        index: usize,
+    ) -> Result<usize, FError> {
+        Self::required_signers(psbt, index).map(|signers| signers.len())
+    }
+
+    /// Return true if the input at `index` holds enough signatures to be finalized. Defaults to
+    /// [`SubTransaction::signature_threshold`] signatures from the required signers, or any
+    /// signature if no signer is required; overridden when some of the signers are mandatory.
+    fn has_required_signatures(
+        psbt: &PartiallySignedTransaction,
+        index: usize,
+    ) -> Result<bool, FError> {
+        let signers = Self::required_signers(psbt, index)?;
+        let input = &psbt.inputs[index];
+        Ok(match signers.is_empty() {
+            true => !input.partial_sigs.is_empty(),
+            false => {
+                signers
+                    .iter()
+                    .filter(|key| input.partial_sigs.contains_key(key))
+                    .count()
+                    >= Self::signature_threshold(psbt, index)?
+            }
+        })
+    }
+
+    /// Return true if the inputs of the transaction can be signed with `sighash_type`. Defaults
+    /// to `SIGHASH_ALL` only, overridden by transactions that can be completed with extra inputs
+    /// at broadcast time.
+    fn accepts_sighash_type(sighash_type: EcdsaSighashType) -> bool {
+        sighash_type == EcdsaSighashType::All
+    }
 }
 
 /// A general purpose Bitcoin transaction used in a swap context. This implements
 /// [`crate::transaction`] traits.
+///
+/// The borrowed accessors [`Tx::as_psbt`], [`Tx::unsigned_tx`], and [`Tx::txid`] inspect the
+/// transaction without cloning the partially signed transaction.
 #[derive(Debug)]
 pub struct Tx<T: SubTransaction> {
     pub(crate) psbt: PartiallySignedTransaction,
+    /// Cached transaction ids, reset by every method changing the partial transaction.
+    txid: OnceCell<Txid>,
+    wtxid: OnceCell<Wtxid>,
     pub(crate) _t: PhantomData<T>,
 }
 
+impl<T> Tx<T>
+where
+    T: SubTransaction,
+{
+    pub(crate) fn new(psbt: PartiallySignedTransaction) -> Self {
+        Self {
+            psbt,
+            txid: OnceCell::new(),
+            wtxid: OnceCell::new(),
+            _t: PhantomData,
+        }
+    }
+
+    /// Return a reference to the partially signed transaction.
+    pub fn as_psbt(&self) -> &PartiallySignedTransaction {
+        &self.psbt
+    }
+
+    /// Return a reference to the unsigned transaction.
+    pub fn unsigned_tx(&self) -> &bitcoin::Transaction {
+        &self.psbt.unsigned_tx
+    }
+
+    /// Computes the sighash of the single input with the given algorithm, the script and the
+    /// value are taken from the `witness_script` and `witness_utxo` of the partial transaction.
+    pub fn signature_hash_with<A>(
+        &self,
+        algorithm: &A,
+        sighash_type: EcdsaSighashType,
+    ) -> Result<Sha256dHash, FError>
+    where
+        A: SighashAlgorithm,
+    {
+        let value = self.psbt.inputs[0]
+            .witness_utxo
+            .as_ref()
+            .ok_or(FError::MissingWitness)?
+            .value;
+        let script = self.psbt.inputs[0]
+            .witness_script
+            .as_ref()
+            .ok_or(FError::MissingWitness)?;
+        Ok(algorithm.signature_hash(
+            TxInRef::new(&self.psbt.unsigned_tx, 0),
+            script,
+            Amount::from_sat(value),
+            sighash_type,
+        ))
+    }
+
+    /// Return the sighash type the inputs are signed with, `SIGHASH_ALL` if not set. Fails if the
+    /// type set in the partial transaction is not accepted by the transaction, see
+    /// [`SubTransaction::accepts_sighash_type`].
+    pub fn sighash_type(&self) -> Result<EcdsaSighashType, FError> {
+        let (sighash_type, _) = self.split_hash_type()?;
+        T::accepts_sighash_type(sighash_type)
+            .then(|| sighash_type)
+            .ok_or_else(|| Error::UnsupportedSigHashType(sighash_type).into())
+    }
+
+    /// Return the replay protection the inputs are signed with, if the `SIGHASH_FORKID` flag is
+    /// set in the partial transaction, see [`Tx::set_fork_id`].
+    pub fn fork_id(&self) -> Result<Option<ForkId>, FError> {
+        Ok(self.split_hash_type()?.1)
+    }
+
+    /// Sign the inputs with the `SIGHASH_FORKID` replay protection. Chains without SegWit, e.g.
+    /// Bitcoin Cash, use the legacy templates of [`crate::bitcoincash::legacy`] instead. The
+    /// flag and the fork identifier are kept in the sighash type of the partial transaction, so
+    /// the counterparty computes the same witness messages. Must be set before signing.
+    pub fn set_fork_id(&mut self, fork_id: ForkId) -> Result<(), FError> {
+        let hash_type = fork_id.hash_type(self.sighash_type()?);
+        self.reset_ids();
+        for input in self.psbt.inputs.iter_mut() {
+            input.sighash_type = Some(PsbtSighashType::from_u32(hash_type));
+        }
+        Ok(())
+    }
+
+    /// Split the raw sighash type of the partial transaction, `SIGHASH_ALL` if not set.
+    fn split_hash_type(&self) -> Result<(EcdsaSighashType, Option<ForkId>), FError> {
+        let hash_type = self.psbt.inputs[0]
+            .sighash_type
+            .map(PsbtSighashType::to_u32)
+            .unwrap_or_else(|| EcdsaSighashType::All.to_u32());
+        sighash::split_hash_type(hash_type).map_err(|_| Error::MissingSigHashType.into())
+    }
+
+    /// Set the sighash type the inputs must be signed with, e.g. `SIGHASH_ALL|ANYONECANPAY` to
+    /// let the broadcaster add fee inputs to a punish transaction. Must be set before signing and
+    /// fails with [`Error::UnsupportedSigHashType`] if the type is not accepted by the
+    /// transaction.
+    pub fn set_sighash_type(&mut self, sighash_type: EcdsaSighashType) -> Result<(), FError> {
+        T::accepts_sighash_type(sighash_type)
+            .then(|| ())
+            .ok_or(Error::UnsupportedSigHashType(sighash_type))?;
+        let hash_type = match self.fork_id()? {
+            Some(fork_id) => fork_id.hash_type(sighash_type),
+            None => sighash_type.to_u32(),
+        };
+        self.reset_ids();
+        for input in self.psbt.inputs.iter_mut() {
+            input.sighash_type = Some(PsbtSighashType::from_u32(hash_type));
+        }
+        Ok(())
+    }
+
+    /// Check that the signatures of all inputs commit to the configured sighash type.
+    fn check_sighash_types(&self) -> Result<(), FError> {
+        let sighash_type = self.sighash_type()?;
+        self.psbt
+            .inputs
+            .iter()
+            .flat_map(|input| input.partial_sigs.values())
+            .all(|sig| sig.hash_ty == sighash_type)
+            .then(|| ())
+            .ok_or(FError::WrongTemplate(
+                "Signature sighash type does not match",
+            ))
+    }
+
+    /// Return the transaction id. The id is computed once and cached until the partial
+    /// transaction is modified.
+    pub fn txid(&self) -> Txid {
+        *self.txid.get_or_init(|| compute_txid(&self.psbt))
+    }
+
+    /// Return the witness transaction id, computed with the final witnesses. Before finalization
+    /// it is computed without witness. The id is computed once, without extracting the
+    /// transaction, and cached until the partial transaction is modified or finalized.
+    pub fn wtxid(&self) -> Wtxid {
+        *self.wtxid.get_or_init(|| compute_wtxid(&self.psbt))
+    }
+
+    /// Combine `other` into this transaction, implementing the PSBT Combiner role: partial
+    /// signatures and other input and output fields known by `other` are merged in. Fails with
+    /// [`psbt::Error::UnexpectedUnsignedTx`] if the two partial transactions do not share the same
+    /// unsigned transaction.
+    ///
+    /// Returns the indexes of the inputs still missing signatures, see
+    /// [`Tx::missing_signatures`].
+    pub fn combine(&mut self, other: &Tx<T>) -> Result<Vec<usize>, FError> {
+        other
+            .psbt
+            .inputs
+            .iter()
+            .flat_map(|input| input.partial_sigs.values())
+            .try_for_each(|sig| check_low_s(&sig.sig))?;
+        other.check_sighash_types()?;
+        self.reset_ids();
+        self.psbt.combine(other.psbt.clone()).map_err(Error::from)?;
+        self.missing_signatures()
+    }
+
+    /// Add an anchor output to the transaction so it can be fee bumped with CPFP. The anchor value
+    /// is taken from the fee, the inputs must cover it. Transactions built on top ignore the
+    /// anchor and spend the first output.
+    pub fn add_anchor(&mut self, anchor: AnchorOutput) -> Result<(), FError> {
+        let available = fee::get_available_input_sat(&self.psbt).map_err(FError::new)?;
+        let outputs: u64 = self
+            .psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|txout| txout.value)
+            .sum();
+        if available.as_sat() < outputs + anchor.amount().as_sat() {
+            return Err(FError::NotEnoughAssets);
+        }
+        self.push_anchor(anchor);
+        Ok(())
+    }
+
+    fn push_anchor(&mut self, anchor: AnchorOutput) {
+        self.reset_ids();
+        self.psbt.unsigned_tx.output.push(anchor.tx_out());
+        self.psbt.outputs.push(psbt::Output {
+            witness_script: anchor.witness_script(),
+            ..Default::default()
+        });
+    }
+
+    /// Return the anchor output of the transaction following its first output, if any.
+    pub fn anchor(&self) -> Option<AnchorOutput> {
+        self.psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .zip(self.psbt.outputs.iter())
+            .skip(1)
+            .find_map(|(txout, output)| {
+                AnchorOutput::from_output(txout, output.witness_script.as_ref())
+            })
+    }
+
+    /// Add a zero-value `OP_RETURN` output carrying the swap tag, see
+    /// [`tag`](crate::bitcoin::tag). Must be done before signing, transactions built on top
+    /// ignore the tag and spend the first output.
+    pub fn add_tag(&mut self, tag: SwapTag) {
+        self.reset_ids();
+        self.psbt.unsigned_tx.output.push(tag.tx_out());
+        self.psbt.outputs.push(psbt::Output::default());
+    }
+
+    /// Return the swap tag carried by the transaction after its first output, if any.
+    pub fn tag(&self) -> Option<SwapTag> {
+        self.psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .skip(1)
+            .find_map(SwapTag::from_output)
+    }
+
+    /// Attach the metadata to the partially signed transaction, replacing the previous one, see
+    /// [`metadata`](crate::bitcoin::metadata). The transaction id is unchanged.
+    pub fn set_metadata(&mut self, metadata: &TxMetadata) {
+        metadata.write_to(&mut self.psbt.proprietary);
+    }
+
+    /// Return the metadata attached to the partially signed transaction.
+    pub fn metadata(&self) -> Result<TxMetadata, consensus::Error> {
+        TxMetadata::read_from(&self.psbt.proprietary)
+    }
+
+    /// Verify a transaction built by the counter-party against the negotiated values before
+    /// signing it: the single input spends the expected output with the expected sequence, the
+    /// first output pays the agreed script with a non-dust amount, the other outputs are only an
+    /// anchor or a swap tag, the outputs do not spend more than the input, and the fee follows
+    /// the strategy. Version 3 is accepted with an anchor, see
+    /// [`Tx::make_truc`].
+    ///
+    /// Fails with [`FError::InvalidTransactionChain`] if the input does not spend the expected
+    /// output, [`FError::WrongTemplate`] otherwise.
+    pub fn verify_template_with(&self, params: &TemplateParams) -> Result<(), FError> {
+        let tx = &self.psbt.unsigned_tx;
+        (tx.version == 2 || tx.version == TRUC_VERSION && self.anchor().is_some())
+            .then(|| ())
+            .ok_or(FError::WrongTemplate(
+                "Tx version is not 2 nor 3 with an anchor",
+            ))?;
+        (tx.lock_time == 0)
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("LockTime is not set to 0"))?;
+        let (txin, input) = match (tx.input.as_slice(), self.psbt.inputs.as_slice()) {
+            ([txin], [input]) => (txin, input),
+            _ => return Err(FError::WrongTemplate("Number of inputs is not 1")),
+        };
+
+        let prev_output = &params.prev_output;
+        (txin.previous_output == prev_output.out_point
+            && input.witness_utxo.as_ref() == Some(&prev_output.tx_out)
+            && input.witness_script == prev_output.script_pubkey
+            && input.redeem_script == prev_output.redeem_script)
+            .then(|| ())
+            .ok_or(FError::InvalidTransactionChain)?;
+        (txin.sequence == params.sequence)
+            .then(|| ())
+            .ok_or(FError::WrongTemplate(
+                "Sequence is not set correctly for timelock",
+            ))?;
+
+        (tx.output.first().map(|txout| &txout.script_pubkey) == Some(&params.script_pubkey))
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Script pubkey does not match"))?;
+        let optional = self.anchor().is_some() as usize + self.tag().is_some() as usize;
+        (tx.output.len() == self.psbt.outputs.len()
+            && tx.output.len() == 1 + optional
+            && self.auxiliary_outputs() == optional)
+            .then(|| ())
+            .ok_or(FError::WrongTemplate(
+                "Outputs are not 1 plus an optional anchor and tag",
+            ))?;
+
+        // The anchor and the tag hold fixed amounts, the first output holds the swapped amount:
+        // it must not be dust and the outputs cannot spend more than the input
+        let first = &tx.output[0];
+        if first.value < first.script_pubkey.dust_value().as_sat() {
+            return Err(Error::DustOutput(first.value).into());
+        }
+        let spent = tx
+            .output
+            .iter()
+            .try_fold(0u64, |total, txout| total.checked_add(txout.value));
+        matches!(spent, Some(spent) if spent <= prev_output.tx_out.value)
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Outputs spend more than the input"))?;
+
+        self.validate_fee(&params.fee_strategy)
+            .map_err(FError::new)?
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Fee does not follow the strategy"))
+    }
+
+    /// Number of outputs following the first one that are only anchors or tags.
+    pub(crate) fn auxiliary_outputs(&self) -> usize {
+        self.psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .zip(self.psbt.outputs.iter())
+            .skip(1)
+            .filter(|(txout, output)| {
+                AnchorOutput::from_output(txout, output.witness_script.as_ref()).is_some()
+                    || SwapTag::from_output(txout).is_some()
+            })
+            .count()
+    }
+
+    /// Mark the transaction as a TRUC, version 3, transaction with an anchor output, so it can be
+    /// broadcast in a package with a fee bumping child spending the anchor, see
+    /// [`truc`](crate::bitcoin::truc). The anchor value is taken from the first output, must be
+    /// done before applying the fee and signing.
+    pub fn make_truc(&mut self, anchor: AnchorOutput) -> Result<(), FError> {
+        self.reset_ids();
+        let first = self
+            .psbt
+            .unsigned_tx
+            .output
+            .first_mut()
+            .ok_or(FError::WrongTemplate("Transaction has no output"))?;
+        first.value = first
+            .value
+            .checked_sub(anchor.amount().as_sat())
+            .ok_or(FError::NotEnoughAssets)?;
+        self.push_anchor(anchor);
+        self.psbt.unsigned_tx.version = TRUC_VERSION;
+        Ok(())
+    }
+
+    /// Return the estimated virtual size of the final transaction, computed from the witness
+    /// templates of the sub-transaction, see [`SubTransaction::estimated_input_weight`].
+    pub fn estimated_vsize(&self) -> usize {
+        let mut tx = self.psbt.unsigned_tx.clone();
+        // The script sig of nested SegWit inputs is known in advance
+        for (txin, input) in tx.input.iter_mut().zip(self.psbt.inputs.iter()) {
+            if let Some(redeem_script) = &input.redeem_script {
+                txin.script_sig = redeem_script_sig(redeem_script);
+            }
+            txin.witness = Witness::new();
+        }
+        // Segwit marker and flag weight one unit each
+        let witness_weight: usize = (0..tx.input.len())
+            .map(|index| T::estimated_input_weight(&self.psbt, index))
+            .sum();
+        let weight = tx.weight() + 2 + witness_weight;
+        (weight + 3) / 4
+    }
+
+    /// Return the indexes of the inputs neither finalized nor signed by all their required
+    /// signers, empty once the transaction is ready to be finalized.
+    pub fn missing_signatures(&self) -> Result<Vec<usize>, FError> {
+        let mut missing = vec![];
+        for (index, input) in self.psbt.inputs.iter().enumerate() {
+            if input.final_script_witness.is_some() {
+                continue;
+            }
+            if !T::has_required_signatures(&self.psbt, index)? {
+                missing.push(index);
+            }
+        }
+        Ok(missing)
+    }
+
+    fn reset_ids(&mut self) {
+        self.txid.take();
+        self.wtxid.take();
+    }
+
+    /// Consume the transaction and extract the final network transaction without cloning.
+    pub fn into_transaction(self) -> bitcoin::Transaction {
+        self.psbt.extract_tx()
+    }
+}
+
 #[cfg(feature = "experimental")]
 #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
-impl<T> Transaction<PartiallySignedTransaction, MetadataOutput, Amount> for Tx<T>
+impl<T> Transaction<MetadataOutput, Amount> for Tx<T>
 where
     T: SubTransaction,
 {
+    type Partial = PartiallySignedTransaction;
+
     fn as_partial(&self) -> &PartiallySignedTransaction {
         &self.psbt
     }
 
     fn as_partial_mut(&mut self) -> &mut PartiallySignedTransaction {
+        self.reset_ids();
         &mut self.psbt
     }
 
@@ -118,10 +620,7 @@ where
     }
 
     fn from_partial(partial: PartiallySignedTransaction) -> Self {
-        Self {
-            psbt: partial,
-            _t: PhantomData,
-        }
+        Self::new(partial)
     }
 
     fn based_on(&self) -> MetadataOutput {
@@ -129,6 +628,9 @@ where
             out_point: self.psbt.unsigned_tx.input[0].previous_output,
             tx_out: self.psbt.inputs[0].witness_utxo.clone().unwrap(), // FIXME
             script_pubkey: self.psbt.inputs[0].witness_script.clone(),
+            redeem_script: self.psbt.inputs[0].redeem_script.clone(),
+            // The previous transaction is not part of the partial transaction
+            wtxid: None,
         }
     }
 
@@ -137,12 +639,48 @@ where
     }
 }
 
+impl<T> Fee for Tx<T>
+where
+    T: SubTransaction,
+{
+    type FeeUnit = SatPerKvB;
+
+    type Amount = bitcoin::Amount;
+
+    /// Calculates and sets the fee on the transaction given the size of its witness template
+    /// and return the fee set.
+    fn set_fee(
+        &mut self,
+        strategy: &FeeStrategy<SatPerKvB>,
+        politic: FeePriority,
+    ) -> Result<Self::Amount, FeeStrategyError> {
+        let vsize = self.estimated_vsize();
+        self.reset_ids();
+        fee::apply_fee(&mut self.psbt, strategy, politic, vsize)
+    }
+
+    /// Validates that the fee of the transaction is set accordingly to the strategy given the
+    /// size of its witness template.
+    fn validate_fee(&self, strategy: &FeeStrategy<SatPerKvB>) -> Result<bool, FeeStrategyError> {
+        fee::check_fee(&self.psbt, strategy, self.estimated_vsize())
+    }
+}
+
 impl<T> Finalizable for Tx<T>
 where
     T: SubTransaction,
 {
     fn finalize(&mut self) -> Result<(), FError> {
-        T::finalize(&mut self.psbt)
+        self.check_sighash_types()?;
+        self.reset_ids();
+        T::finalize(&mut self.psbt)?;
+        // Nested SegWit inputs must also push their redeem script
+        for input in self.psbt.inputs.iter_mut() {
+            if let Some(redeem_script) = &input.redeem_script {
+                input.final_script_sig = Some(redeem_script_sig(redeem_script));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -150,8 +688,15 @@ impl<T> Broadcastable<bitcoin::Transaction> for Tx<T>
 where
     T: SubTransaction,
 {
+    /// Extract the final network transaction by reference, only the unsigned transaction and
+    /// the final scripts are cloned.
     fn extract(&self) -> bitcoin::Transaction {
-        self.psbt.clone().extract_tx()
+        let mut tx = self.psbt.unsigned_tx.clone();
+        for (txin, input) in tx.input.iter_mut().zip(self.psbt.inputs.iter()) {
+            txin.script_sig = input.final_script_sig.clone().unwrap_or_default();
+            txin.witness = input.final_script_witness.clone().unwrap_or_default();
+        }
+        tx
     }
 }
 
@@ -160,7 +705,9 @@ where
     T: SubTransaction,
 {
     fn get_consumable_output(&self) -> Result<MetadataOutput, FError> {
-        match self.psbt.unsigned_tx.output.len() {
+        // Anchor and tag outputs after the first one are only used for fee bumping and recovery
+        // and are ignored
+        match self.psbt.unsigned_tx.output.len() - self.auxiliary_outputs() {
             1 => (),
             2 => {
                 if !self.psbt.unsigned_tx.is_coin_base() {
@@ -171,9 +718,11 @@ where
         }
 
         Ok(MetadataOutput {
-            out_point: OutPoint::new(self.psbt.unsigned_tx.txid(), 0),
+            out_point: OutPoint::new(self.txid(), 0),
             tx_out: self.psbt.unsigned_tx.output[0].clone(),
             script_pubkey: self.psbt.outputs[0].witness_script.clone(),
+            redeem_script: self.psbt.outputs[0].redeem_script.clone(),
+            wtxid: Some(self.wtxid()),
         })
     }
 }
@@ -184,36 +733,141 @@ impl<T> Witnessable<Sha256dHash, PublicKey, Signature> for Tx<T>
 where
     T: SubTransaction,
 {
+    /// The message is computed with [`ForkId`] if the replay protection is set, see
+    /// [`Tx::set_fork_id`], and with [`Bip143`] otherwise.
+    ///
     /// ## Safety
     /// This function is used for generating the witness message for all transactions but not
     /// funding. So implying only 1 input is valid as all templates only have 1 input.
     fn generate_witness_message(&self, _path: ScriptPath) -> Result<Sha256dHash, FError> {
-        let unsigned_tx = self.psbt.unsigned_tx.clone();
-        let txin = TxInRef::new(&unsigned_tx, 0);
-
-        let witness_utxo = self.psbt.inputs[0]
-            .witness_utxo
-            .clone()
-            .ok_or(FError::MissingWitness)?;
-
-        let script = self.psbt.inputs[0]
-            .witness_script
-            .clone()
-            .ok_or(FError::MissingWitness)?;
-        let value = witness_utxo.value;
-
-        Ok(signature_hash(txin, &script, value, EcdsaSighashType::All))
+        let sighash_type = self.sighash_type()?;
+        match self.fork_id()? {
+            Some(fork_id) => self.signature_hash_with(&fork_id, sighash_type),
+            None => self.signature_hash_with(&Bip143, sighash_type),
+        }
     }
 
     fn add_witness(&mut self, pubkey: PublicKey, sig: Signature) -> Result<(), FError> {
-        let sig_all = EcdsaSig::sighash_all(sig);
+        check_low_s(&sig)?;
+        let sig = EcdsaSig {
+            sig,
+            hash_ty: self.sighash_type()?,
+        };
+        self.reset_ids();
         self.psbt.inputs[0]
             .partial_sigs
-            .insert(bitcoin::PublicKey::new(pubkey), sig_all);
+            .insert(bitcoin::PublicKey::new(pubkey), sig);
         Ok(())
     }
 }
 
+/// Check that a signature is in its canonical low-S form, high-S signatures are non-standard
+/// since [`BIP-146`][bip-146] and transactions containing them are not relayed. Fails with
+/// [`FError::NonCanonicalSignature`] otherwise.
+///
+/// [bip-146]: https://github.com/bitcoin/bips/blob/master/bip-0146.mediawiki
+pub fn check_low_s(sig: &bitcoin::secp256k1::ecdsa::Signature) -> Result<(), FError> {
+    let mut normalized = *sig;
+    normalized.normalize_s();
+    (normalized == *sig)
+        .then(|| ())
+        .ok_or(FError::NonCanonicalSignature)
+}
+
+/// Parse a signature received from the counterparty, the signature must be strictly DER encoded
+/// as defined in [`BIP-66`][bip-66] and in its low-S form, see [`check_low_s`]. Fails with
+/// [`FError::NonCanonicalSignature`] otherwise.
+///
+/// [bip-66]: https://github.com/bitcoin/bips/blob/master/bip-0066.mediawiki
+pub fn parse_der_signature(bytes: &[u8]) -> Result<bitcoin::secp256k1::ecdsa::Signature, FError> {
+    let sig = bitcoin::secp256k1::ecdsa::Signature::from_der(bytes)
+        .map_err(|_| FError::NonCanonicalSignature)?;
+    // Reject any encoding not produced by the canonical serialization, e.g. padded integers
+    if sig.serialize_der().as_ref() != bytes {
+        return Err(FError::NonCanonicalSignature);
+    }
+    check_low_s(&sig)?;
+    Ok(sig)
+}
+
+/// Compute the id of the transaction. The script sig of nested SegWit inputs is part of the txid,
+/// it is known in advance as it only contains the redeem script.
+fn compute_txid(psbt: &PartiallySignedTransaction) -> Txid {
+    if psbt
+        .inputs
+        .iter()
+        .all(|input| input.redeem_script.is_none())
+    {
+        return psbt.unsigned_tx.txid();
+    }
+    let mut tx = psbt.unsigned_tx.clone();
+    for (txin, input) in tx.input.iter_mut().zip(psbt.inputs.iter()) {
+        if let Some(redeem_script) = &input.redeem_script {
+            txin.script_sig = redeem_script_sig(redeem_script);
+        }
+    }
+    tx.txid()
+}
+
+/// Compute the witness id of the transaction [`Broadcastable::extract`] would return, without
+/// cloning it.
+fn compute_wtxid(psbt: &PartiallySignedTransaction) -> Wtxid {
+    let mut engine = Wtxid::engine();
+    encode_final_tx(psbt, &mut engine).expect("engines don't error");
+    Wtxid::from_engine(engine)
+}
+
+/// Serialize the unsigned transaction with the final scripts and witnesses of the partial inputs
+/// in place, with the same rules as the transaction serialization.
+fn encode_final_tx<W: io::Write>(
+    psbt: &PartiallySignedTransaction,
+    mut writer: W,
+) -> Result<usize, io::Error> {
+    let tx = &psbt.unsigned_tx;
+    let (empty_script, empty_witness) = (Script::new(), Witness::default());
+    let finals: Vec<(&Script, &Witness)> = tx
+        .input
+        .iter()
+        .enumerate()
+        .map(|(index, txin)| match psbt.inputs.get(index) {
+            Some(input) => (
+                input.final_script_sig.as_ref().unwrap_or(&empty_script),
+                input
+                    .final_script_witness
+                    .as_ref()
+                    .unwrap_or(&empty_witness),
+            ),
+            None => (&txin.script_sig, &txin.witness),
+        })
+        .collect();
+    let segwit = tx.input.is_empty() || finals.iter().any(|(_, witness)| !witness.is_empty());
+
+    let mut len = tx.version.consensus_encode(&mut writer)?;
+    if segwit {
+        len += [0u8, 1u8].consensus_encode(&mut writer)?;
+    }
+    len += VarInt(tx.input.len() as u64).consensus_encode(&mut writer)?;
+    for (txin, (script_sig, _)) in tx.input.iter().zip(finals.iter()) {
+        len += txin.previous_output.consensus_encode(&mut writer)?;
+        len += script_sig.consensus_encode(&mut writer)?;
+        len += txin.sequence.consensus_encode(&mut writer)?;
+    }
+    len += tx.output.consensus_encode(&mut writer)?;
+    if segwit {
+        for (_, witness) in finals.iter() {
+            len += witness.consensus_encode(&mut writer)?;
+        }
+    }
+    Ok(len + tx.lock_time.consensus_encode(&mut writer)?)
+}
+
+/// Create the script sig spending a P2SH wrapped SegWit output with `redeem_script`.
+fn redeem_script_sig(redeem_script: &Script) -> Script {
+    Builder::new()
+        .push_slice(redeem_script.as_bytes())
+        .into_script()
+}
+
 /// A borrowed reference to a transaction input.
 #[derive(Debug, Copy, Clone)]
 pub struct TxInRef<'a> {
@@ -278,3 +932,73 @@ impl CanonicalBytes for PartiallySignedTransaction {
         bitcoin::consensus::encode::deserialize(bytes).map_err(consensus::Error::new)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+
+    fn signature() -> bitcoin::secp256k1::ecdsa::Signature {
+        let secp = Secp256k1::new();
+        let msg = Message::from_slice(&[1u8; 32]).unwrap();
+        let mut sig = secp.sign_ecdsa(&msg, &SecretKey::from_slice(&[2u8; 32]).unwrap());
+        sig.normalize_s();
+        sig
+    }
+
+    // Negate the `s` value of a low-S signature: s' = n - s
+    fn high_s(sig: &bitcoin::secp256k1::ecdsa::Signature) -> bitcoin::secp256k1::ecdsa::Signature {
+        const ORDER: [u8; 32] = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c,
+            0xd0, 0x36, 0x41, 0x41,
+        ];
+        let mut compact = sig.serialize_compact();
+        let mut borrow = 0i16;
+        for i in (0..32).rev() {
+            let diff = ORDER[i] as i16 - compact[32 + i] as i16 - borrow;
+            borrow = (diff < 0) as i16;
+            compact[32 + i] = diff.rem_euclid(256) as u8;
+        }
+        bitcoin::secp256k1::ecdsa::Signature::from_compact(&compact).unwrap()
+    }
+
+    #[test]
+    fn reject_high_s_signature() {
+        let sig = signature();
+        assert!(check_low_s(&sig).is_ok());
+        let high = high_s(&sig);
+        assert_ne!(sig, high);
+        assert!(matches!(
+            check_low_s(&high),
+            Err(FError::NonCanonicalSignature)
+        ));
+    }
+
+    #[test]
+    fn reject_non_strict_der_signature() {
+        let sig = signature();
+        let der = sig.serialize_der();
+        assert_eq!(parse_der_signature(der.as_ref()).unwrap(), sig);
+        assert!(matches!(
+            parse_der_signature(high_s(&sig).serialize_der().as_ref()),
+            Err(FError::NonCanonicalSignature)
+        ));
+
+        // Trailing garbage after the DER sequence
+        let mut padded = der.to_vec();
+        padded.push(0);
+        assert!(parse_der_signature(&padded).is_err());
+        // Wrong sequence length
+        let mut bad_len = der.to_vec();
+        bad_len[1] += 1;
+        assert!(parse_der_signature(&bad_len).is_err());
+    }
+
+    #[test]
+    fn transactions_are_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<crate::bitcoin::segwitv0::LockTx>();
+    }
+}
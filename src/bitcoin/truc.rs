@@ -0,0 +1,113 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Topologically restricted until confirmation (TRUC, BIP 431) transactions and package relay.
+//!
+//! The `cancel` and `punish` transactions are time-sensitive: during the timelock race the
+//! counterparty can pin them in the mempool with large low-fee descendants. Marked as version 3
+//! with an anchor output, see [`Tx::make_truc`](crate::bitcoin::transaction::Tx::make_truc), they
+//! accept a single small unconfirmed child and are submitted with their fee bumping child as a
+//! [`Package`], e.g. with Bitcoin Core `submitpackage`, see
+//! [`Syncer::broadcast_package`](crate::syncer::Syncer::broadcast_package).
+
+use bitcoin::Transaction;
+
+use crate::transaction::{Error as FError, TxLabel};
+
+/// Version of TRUC transactions.
+pub const TRUC_VERSION: i32 = 3;
+
+/// Maximum virtual size of a TRUC transaction.
+pub const TRUC_MAX_VSIZE: usize = 10_000;
+
+/// Maximum virtual size of a TRUC transaction with an unconfirmed TRUC parent.
+pub const TRUC_CHILD_MAX_VSIZE: usize = 1_000;
+
+/// Defines how the arbitrating transactions of a swap are broadcast. The mode must be agreed upon
+/// before signing as it changes the templates of the time-sensitive transactions, see
+/// [`SetupParameters`](crate::bitcoin::segwitv0::setup::SetupParameters).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum BroadcastMode {
+    /// All transactions are version 2 and broadcast one by one.
+    Standard,
+    /// The `cancel` and `punish` transactions are TRUC and broadcast in packages with their fee
+    /// bumping child.
+    Truc,
+}
+
+impl Default for BroadcastMode {
+    fn default() -> Self {
+        Self::Standard
+    }
+}
+
+impl BroadcastMode {
+    /// Return true if the transaction must be a TRUC transaction broadcast in a package.
+    pub fn is_truc(&self, label: TxLabel) -> bool {
+        match self {
+            Self::Standard => false,
+            Self::Truc => matches!(label, TxLabel::Cancel | TxLabel::Punish),
+        }
+    }
+}
+
+/// A TRUC parent transaction and the child bumping its fee, validated against the TRUC
+/// topology rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    parent: Transaction,
+    child: Transaction,
+}
+
+impl Package {
+    /// Create a package. Fails with [`FError::WrongTemplate`] if one of the transactions is not
+    /// TRUC, exceeds the TRUC size limits, or if the child does not spend the parent.
+    pub fn new(parent: Transaction, child: Transaction) -> Result<Self, FError> {
+        (parent.version == TRUC_VERSION && child.version == TRUC_VERSION)
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Package transactions are not TRUC"))?;
+        (parent.vsize() <= TRUC_MAX_VSIZE)
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Parent exceeds the TRUC size limit"))?;
+        (child.vsize() <= TRUC_CHILD_MAX_VSIZE)
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Child exceeds the TRUC size limit"))?;
+        let txid = parent.txid();
+        child
+            .input
+            .iter()
+            .any(|txin| txin.previous_output.txid == txid)
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Child does not spend the parent"))?;
+        Ok(Self { parent, child })
+    }
+
+    /// Return the parent transaction.
+    pub fn parent(&self) -> &Transaction {
+        &self.parent
+    }
+
+    /// Return the fee bumping child transaction.
+    pub fn child(&self) -> &Transaction {
+        &self.child
+    }
+
+    /// Return the transactions of the package, parent first, as expected by `submitpackage`.
+    pub fn into_transactions(self) -> Vec<Transaction> {
+        vec![self.parent, self.child]
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Primitives for Bitcoin Cash. Bitcoin Cash reuses the Bitcoin keys and swap scripts, but has no
+//! SegWit and requires replay protected signatures:
+//!
+//! - the swap lock is locked in a P2SH output and spent with a script signature by the [`legacy`]
+//!   lock and buy templates,
+//! - witness messages are computed with the [`ForkId`] sighash algorithm and signatures carry the
+//!   `SIGHASH_FORKID` flag,
+//! - addresses are encoded with [`CashAddr`].
+//!
+//! Legacy transaction ids are malleable, so the pre-signed cancel, refund and punish chain cannot
+//! be built safely and the arbitrating transaction traits are not implemented for Bitcoin Cash: it
+//! is not a [`Blockchain`](crate::blockchain::Blockchain) deals can be made on.
+//!
+//! [`CashAddr`]: crate::bitcoin::cashaddr::CashAddr
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+
+use crate::bitcoin::sighash::ForkId;
+use crate::consensus::{self, Decodable, Encodable};
+use crate::crypto::{DeriveKeys, SharedKeyId};
+
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub mod legacy;
+
+/// The Bitcoin Cash blockchain, reusing the Bitcoin keys and swap scripts in legacy P2SH
+/// transaction templates with replay protected signatures.
+#[derive(Clone, Debug, Copy, Eq, PartialEq)]
+pub struct BitcoinCash;
+
+impl BitcoinCash {
+    /// The replay protection the [`legacy`] transaction templates are signed with.
+    pub fn replay_protection() -> ForkId {
+        ForkId::bitcoin_cash()
+    }
+}
+
+impl Decodable for BitcoinCash {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match Decodable::consensus_decode(d)? {
+            0x80000091u32 => Ok(Self),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl Encodable for BitcoinCash {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        0x80000091u32.consensus_encode(writer)
+    }
+}
+
+impl FromStr for BitcoinCash {
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "BitcoinCash" | "bitcoincash" | "bch" => Ok(BitcoinCash),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl fmt::Display for BitcoinCash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bch")
+    }
+}
+
+impl DeriveKeys for BitcoinCash {
+    type PublicKey = PublicKey;
+    type PrivateKey = SecretKey;
+
+    fn extra_public_keys() -> Vec<u16> {
+        // No extra key
+        vec![]
+    }
+
+    fn extra_shared_private_keys() -> Vec<SharedKeyId> {
+        // No shared key
+        vec![]
+    }
+}
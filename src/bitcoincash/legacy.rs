@@ -0,0 +1,457 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Legacy P2SH transaction templates for Bitcoin Cash. The swap lock script is the one of the
+//! Bitcoin [`CoopLock`] template, used as redeem script of a P2SH output and spent with a script
+//! signature. All the inputs are signed with the [`ForkId`] sighash, the value of the spent output
+//! is kept in the `witness_utxo` of the partial transaction as the replay protected sighash
+//! commits to it.
+//!
+//! Unlike SegWit, the transaction id commits to the script signature, so the id of a legacy
+//! transaction is only known once it is finalized. Templates spending an output are initialized
+//! from the finalized transaction creating it, see [`LegacyTx::get_consumable_output`].
+//!
+//! # Safety
+//!
+//! Legacy transaction ids are malleable: any signer can produce a new valid signature for an
+//! input and change the id of the transaction. A template signed on top of a transaction that is
+//! not yet mined can therefore be invalidated by the counter-party, which rules out the pre-signed
+//! cancel, refund and punish chain the swap protocol relies on. Only the lock and buy primitives
+//! are provided, and a transaction spending an output must only be signed once the transaction
+//! creating it is mined.
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use bitcoin::blockdata::script::{Builder, Script};
+use bitcoin::blockdata::transaction::{EcdsaSighashType, OutPoint, TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::hashes::sha256d::Hash as Sha256dHash;
+use bitcoin::secp256k1::{ecdsa::Signature, PublicKey};
+use bitcoin::util::ecdsa::EcdsaSig;
+use bitcoin::util::psbt::{PartiallySignedTransaction, PsbtSighashType};
+use bitcoin::{Amount, Transaction, Txid};
+
+use crate::bitcoin::cashaddr::CashAddr;
+use crate::bitcoin::fee::{self, SatPerKvB};
+use crate::bitcoin::segwitv0::CoopLock;
+use crate::bitcoin::sighash::{ForkId, SighashAlgorithm};
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::transaction::{check_low_s, Error, MetadataOutput, TxInRef};
+use crate::blockchain::{FeePriority, FeeStrategy, FeeStrategyError};
+use crate::role::SwapRole;
+use crate::script::DataLock;
+use crate::transaction::Error as FError;
+
+/// Size of a signature push, a DER signature of at most 72 bytes and its sighash byte.
+pub const SIGNATURE_PUSH_SIZE: usize = 1 + 72 + 1;
+/// Size of a compressed public key push.
+pub const PUBKEY_PUSH_SIZE: usize = 1 + 33;
+
+/// Defines the script signature of the single input of a legacy transaction template.
+pub trait LegacySubTransaction: Debug {
+    /// Build the script signature spending `script_code` with the signatures of the partial
+    /// transaction, serialized with `sighash_byte`.
+    fn script_sig(psbt: &PartiallySignedTransaction, sighash_byte: u8) -> Result<Script, FError>;
+
+    /// Return the upper bound, in bytes, of the script signature spending `script_code`.
+    fn max_script_sig_size(script_code: &Script) -> usize;
+}
+
+/// Lock the funding P2PKH output in a P2SH output of the swap lock.
+#[derive(Debug)]
+pub struct Lock;
+
+/// Spend the swap lock with Alice's and Bob's signatures, revealing Alice's adaptor secret.
+#[derive(Debug)]
+pub struct Buy;
+
+/// Lock the funding UTXO in a P2SH output of the swap lock.
+pub type LockTx = LegacyTx<Lock>;
+/// Spend the swap lock and reveal the first secret.
+pub type BuyTx = LegacyTx<Buy>;
+
+/// A legacy Bitcoin Cash transaction with a single input, spent with a script signature.
+#[derive(Debug)]
+pub struct LegacyTx<T: LegacySubTransaction> {
+    psbt: PartiallySignedTransaction,
+    _t: PhantomData<T>,
+}
+
+impl<T> LegacyTx<T>
+where
+    T: LegacySubTransaction,
+{
+    fn initialize_template(
+        prev: MetadataOutput,
+        sequence: u32,
+        script_pubkey: Script,
+    ) -> Result<Self, FError> {
+        let script_code = prev
+            .redeem_script
+            .clone()
+            .unwrap_or_else(|| prev.tx_out.script_pubkey.clone());
+        let unsigned_tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: prev.out_point,
+                script_sig: Script::default(),
+                sequence,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: prev.tx_out.value,
+                script_pubkey,
+            }],
+        };
+        let mut psbt =
+            PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
+        // The forkid sighash commits to the spent value
+        psbt.inputs[0].witness_utxo = Some(prev.tx_out);
+        psbt.inputs[0].redeem_script = Some(script_code);
+        psbt.inputs[0].sighash_type = Some(PsbtSighashType::from_u32(
+            ForkId::bitcoin_cash().hash_type(EcdsaSighashType::All),
+        ));
+        Ok(Self {
+            psbt,
+            _t: PhantomData,
+        })
+    }
+
+    /// Return a reference to the partially signed transaction.
+    pub fn as_psbt(&self) -> &PartiallySignedTransaction {
+        &self.psbt
+    }
+
+    /// Return the script code of the input, the redeem script of the P2SH output or the script
+    /// pubkey of the P2PKH output.
+    fn script_code(&self) -> Result<&Script, FError> {
+        self.psbt.inputs[0]
+            .redeem_script
+            .as_ref()
+            .ok_or(FError::MissingWitness)
+    }
+
+    /// Return the message to sign with the [`ForkId`] sighash.
+    pub fn generate_witness_message(&self) -> Result<Sha256dHash, FError> {
+        let value = self.psbt.inputs[0]
+            .witness_utxo
+            .as_ref()
+            .ok_or(FError::MissingWitness)?
+            .value;
+        Ok(ForkId::bitcoin_cash().signature_hash(
+            TxInRef::new(&self.psbt.unsigned_tx, 0),
+            self.script_code()?,
+            Amount::from_sat(value),
+            EcdsaSighashType::All,
+        ))
+    }
+
+    /// Add the signature of `pubkey` on the message returned by
+    /// [`LegacyTx::generate_witness_message`], it must be in its low-S form.
+    pub fn add_signature(&mut self, pubkey: PublicKey, sig: Signature) -> Result<(), FError> {
+        check_low_s(&sig)?;
+        self.psbt.inputs[0].partial_sigs.insert(
+            bitcoin::PublicKey::new(pubkey),
+            EcdsaSig {
+                sig,
+                hash_ty: EcdsaSighashType::All,
+            },
+        );
+        Ok(())
+    }
+
+    /// Set the fee following the strategy, the size of the transaction is estimated with the
+    /// largest script signature of the template.
+    pub fn set_fee(
+        &mut self,
+        strategy: &FeeStrategy<SatPerKvB>,
+        politic: FeePriority,
+    ) -> Result<Amount, FeeStrategyError> {
+        let size = self.estimated_size().map_err(FeeStrategyError::new)?;
+        fee::apply_fee(&mut self.psbt, strategy, politic, size)
+    }
+
+    /// Return the upper bound of the size of the finalized transaction, in bytes.
+    pub fn estimated_size(&self) -> Result<usize, FError> {
+        let script_sig = T::max_script_sig_size(self.script_code()?);
+        // The empty script sig is serialized with a one byte length
+        Ok(self.psbt.unsigned_tx.size() - 1 + varint_size(script_sig) + script_sig)
+    }
+
+    /// Build the script signature of the input from the signatures.
+    pub fn finalize(&mut self) -> Result<(), FError> {
+        let sighash_byte = ForkId::bitcoin_cash().sighash_byte(EcdsaSighashType::All);
+        let script_sig = T::script_sig(&self.psbt, sighash_byte)?;
+        self.psbt.inputs[0].final_script_sig = Some(script_sig);
+        Ok(())
+    }
+
+    /// Extract the final network transaction.
+    pub fn extract(&self) -> Transaction {
+        let mut tx = self.psbt.unsigned_tx.clone();
+        tx.input[0].script_sig = self.psbt.inputs[0]
+            .final_script_sig
+            .clone()
+            .unwrap_or_default();
+        tx
+    }
+
+    /// Return the transaction id, only known once the transaction is finalized. Fails with
+    /// [`FError::MissingSignature`] otherwise.
+    pub fn txid(&self) -> Result<Txid, FError> {
+        self.psbt.inputs[0]
+            .final_script_sig
+            .as_ref()
+            .map(|_| self.extract().txid())
+            .ok_or(FError::MissingSignature)
+    }
+
+    /// Return the P2SH output of the finalized transaction to build the next template on. Fails
+    /// with [`FError::MissingSignature`] if the transaction is not finalized, as its id is not
+    /// known yet.
+    pub fn get_consumable_output(&self) -> Result<MetadataOutput, FError> {
+        Ok(MetadataOutput {
+            out_point: OutPoint::new(self.txid()?, 0),
+            tx_out: self.psbt.unsigned_tx.output[0].clone(),
+            script_pubkey: None,
+            redeem_script: self.psbt.outputs[0].redeem_script.clone(),
+            wtxid: None,
+        })
+    }
+}
+
+impl LegacyTx<Lock> {
+    /// Create the lock transaction spending the funding P2PKH output described by `funding`.
+    pub fn initialize(
+        funding: MetadataOutput,
+        lock: DataLock<CSVTimelock, PublicKey>,
+    ) -> Result<Self, FError> {
+        (funding.tx_out.script_pubkey.is_p2pkh() && funding.redeem_script.is_none())
+            .then(|| ())
+            .ok_or(FError::WrongTemplate("Funding output is not P2PKH"))?;
+        let script = CoopLock::script(lock);
+        let mut tx = Self::initialize_template(funding, u32::MAX, script.to_p2sh())?;
+        tx.psbt.outputs[0].redeem_script = Some(script);
+        Ok(tx)
+    }
+}
+
+impl LegacyTx<Buy> {
+    /// Create the buy transaction spending the lock output to the destination address.
+    pub fn initialize(lock: &LockTx, destination: &CashAddr) -> Result<Self, FError> {
+        Self::initialize_template(
+            lock.get_consumable_output()?,
+            0,
+            destination.script_pubkey(),
+        )
+    }
+}
+
+impl LegacySubTransaction for Lock {
+    fn script_sig(psbt: &PartiallySignedTransaction, sighash_byte: u8) -> Result<Script, FError> {
+        let (pubkey, sig) = psbt.inputs[0]
+            .partial_sigs
+            .iter()
+            .next()
+            .ok_or(FError::MissingSignature)?;
+        Ok(Builder::new()
+            .push_slice(&serialize_sig(&sig.sig, sighash_byte))
+            .push_key(pubkey)
+            .into_script())
+    }
+
+    fn max_script_sig_size(_script_code: &Script) -> usize {
+        SIGNATURE_PUSH_SIZE + PUBKEY_PUSH_SIZE
+    }
+}
+
+impl LegacySubTransaction for Buy {
+    fn script_sig(psbt: &PartiallySignedTransaction, sighash_byte: u8) -> Result<Script, FError> {
+        coop_script_sig(psbt, sighash_byte)
+    }
+
+    fn max_script_sig_size(script_code: &Script) -> usize {
+        2 * SIGNATURE_PUSH_SIZE + push_size(script_code.len())
+    }
+}
+
+/// Script signature of the swap lock: Bob's signature then Alice's, checked first.
+fn coop_script_sig(psbt: &PartiallySignedTransaction, sighash_byte: u8) -> Result<Script, FError> {
+    let script = redeem_script(psbt)?;
+    let swaplock = CoopLock::from_script(script)?;
+    let alice_sig = partial_sig(psbt, swaplock.get_pubkey(SwapRole::Alice))?;
+    let bob_sig = partial_sig(psbt, swaplock.get_pubkey(SwapRole::Bob))?;
+    Ok(Builder::new()
+        .push_slice(&serialize_sig(&bob_sig, sighash_byte))
+        .push_slice(&serialize_sig(&alice_sig, sighash_byte))
+        .push_slice(script.as_bytes())
+        .into_script())
+}
+
+fn redeem_script(psbt: &PartiallySignedTransaction) -> Result<&Script, FError> {
+    psbt.inputs[0]
+        .redeem_script
+        .as_ref()
+        .ok_or(FError::MissingWitness)
+}
+
+fn partial_sig(psbt: &PartiallySignedTransaction, key: &PublicKey) -> Result<Signature, FError> {
+    psbt.inputs[0]
+        .partial_sigs
+        .get(&bitcoin::PublicKey::new(*key))
+        .map(|sig| sig.sig)
+        .ok_or(FError::MissingSignature)
+}
+
+/// Serialize a signature in DER followed by its sighash byte.
+fn serialize_sig(sig: &Signature, sighash_byte: u8) -> Vec<u8> {
+    let mut bytes = sig.serialize_der().to_vec();
+    bytes.push(sighash_byte);
+    bytes
+}
+
+/// Size of the push of `len` bytes with the smallest push opcode.
+fn push_size(len: usize) -> usize {
+    match len {
+        0..=75 => 1 + len,
+        76..=0xff => 2 + len,
+        _ => 3 + len,
+    }
+}
+
+fn varint_size(len: usize) -> usize {
+    match len {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        _ => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::script::Instruction;
+    use bitcoin::secp256k1::{Message, Secp256k1};
+
+    use crate::bitcoin::segwitv0::sign_hash;
+    use crate::bitcoin::test_helpers::{pubkey, secp, secret};
+    use crate::blockchain::Network;
+    use crate::script::SwapRoleKeys;
+
+    fn funding() -> MetadataOutput {
+        let script_pubkey = Script::new_p2pkh(&bitcoin::PublicKey::new(pubkey(1)).pubkey_hash());
+        MetadataOutput {
+            out_point: OutPoint::default(),
+            tx_out: TxOut {
+                value: 100_000,
+                script_pubkey,
+            },
+            script_pubkey: None,
+            redeem_script: None,
+            wtxid: None,
+        }
+    }
+
+    fn data_lock() -> DataLock<CSVTimelock, PublicKey> {
+        DataLock {
+            timelock: CSVTimelock::new(10),
+            success: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+            failure: SwapRoleKeys::new(pubkey(2), pubkey(3)),
+        }
+    }
+
+    fn destination() -> CashAddr {
+        let script = Script::new_p2pkh(&bitcoin::PublicKey::new(pubkey(7)).pubkey_hash());
+        CashAddr::from_script(&script, Network::Local).unwrap()
+    }
+
+    fn sign<T: LegacySubTransaction>(tx: &mut LegacyTx<T>, byte: u8) {
+        let msg = tx.generate_witness_message().unwrap();
+        let sig = sign_hash(secp(), msg, &secret(byte)).unwrap();
+        tx.add_signature(pubkey(byte), sig).unwrap();
+    }
+
+    fn pushes(script: &Script) -> Vec<Vec<u8>> {
+        script
+            .instructions()
+            .map(|ins| match ins.unwrap() {
+                Instruction::PushBytes(bytes) => bytes.to_vec(),
+                Instruction::Op(op) => vec![op.into_u8()],
+            })
+            .collect()
+    }
+
+    fn check_signature(tx: &Transaction, item: &[u8], key: u8, msg: Sha256dHash) {
+        let (sighash_byte, der) = item.split_last().unwrap();
+        assert_eq!(*sighash_byte, 0x41);
+        let sig = Signature::from_der(der).unwrap();
+        let msg = Message::from_slice(&msg[..]).unwrap();
+        assert!(Secp256k1::verification_only()
+            .verify_ecdsa(&msg, &sig, &pubkey(key))
+            .is_ok());
+        assert!(tx.input[0].witness.is_empty());
+    }
+
+    #[test]
+    fn legacy_lock_and_buy() {
+        let data_lock = data_lock();
+        let strategy = FeeStrategy::Fixed(SatPerKvB::from_sat(1000));
+
+        let mut lock = LockTx::initialize(funding(), data_lock).unwrap();
+        assert!(lock.get_consumable_output().is_err());
+        lock.set_fee(&strategy, FeePriority::Low).unwrap();
+        sign(&mut lock, 1);
+        lock.finalize().unwrap();
+        let tx = lock.extract();
+        assert!(tx.output[0].script_pubkey.is_p2sh());
+        assert!(lock.estimated_size().unwrap() >= tx.size());
+        let items = pushes(&tx.input[0].script_sig);
+        assert_eq!(items.len(), 2);
+        check_signature(&tx, &items[0], 1, lock.generate_witness_message().unwrap());
+        assert_eq!(items[1], pubkey(1).serialize().to_vec());
+
+        // The buy spends the finalized lock
+        let mut buy = BuyTx::initialize(&lock, &destination()).unwrap();
+        assert_eq!(
+            buy.as_psbt().unsigned_tx.input[0].previous_output.txid,
+            tx.txid()
+        );
+        buy.set_fee(&strategy, FeePriority::Low).unwrap();
+        sign(&mut buy, 2);
+        assert!(matches!(buy.finalize(), Err(FError::MissingSignature)));
+        sign(&mut buy, 3);
+        buy.finalize().unwrap();
+        let tx = buy.extract();
+        assert!(buy.estimated_size().unwrap() >= tx.size());
+        let msg = buy.generate_witness_message().unwrap();
+        let items = pushes(&tx.input[0].script_sig);
+        assert_eq!(items.len(), 3);
+        check_signature(&tx, &items[0], 3, msg);
+        check_signature(&tx, &items[1], 2, msg);
+        assert_eq!(items[2], CoopLock::script(data_lock).into_bytes());
+    }
+
+    #[test]
+    fn reject_non_p2pkh_funding() {
+        let data_lock = data_lock();
+        let mut funding = funding();
+        funding.tx_out.script_pubkey = Script::new_v0_p2wsh(&Default::default());
+        assert!(LockTx::initialize(funding, data_lock).is_err());
+    }
+}
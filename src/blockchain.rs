@@ -21,6 +21,7 @@
 //! 44](https://github.com/satoshilabs/slips/blob/master/slip-0044.md#slip-0044--registered-coin-types-for-bip-0044)
 //! or must not conflict with any registered entity.
 
+use std::convert::TryFrom;
 use std::error;
 use std::fmt::{self, Debug};
 use std::io;
@@ -30,7 +31,10 @@ use strict_encoding::{StrictDecode, StrictEncode};
 use thiserror::Error;
 
 use crate::consensus::{self, deserialize, serialize, CanonicalBytes, Decodable, Encodable};
-use crate::transaction::{Buyable, Cancelable, Fundable, Lockable, Punishable, Refundable};
+use crate::protocol::ArbitratingParameters;
+use crate::role::SwapRole;
+use crate::script::{DataLock, DataPunishableLock};
+use crate::transaction::{self, Buyable, Cancelable, Fundable, Lockable, Punishable, Refundable};
 
 /// The list of supported blockchains (coins) by this library.
 #[derive(
@@ -86,6 +90,20 @@ impl Encodable for Blockchain {
     }
 }
 
+/// A relative timelock of an arbitrating blockchain, converted to a number of blocks to follow
+/// its expiration from the chain height.
+pub trait Timelock {
+    /// Return the duration of the timelock in blocks. Time-based timelocks are estimated from the
+    /// expected block interval of the chain.
+    fn as_blocks(&self) -> u32;
+}
+
+impl Timelock for u32 {
+    fn as_blocks(&self) -> u32 {
+        *self
+    }
+}
+
 /// Fix the types for all arbitrating transactions needed for the swap: [`Fundable`], [`Lockable`],
 /// [`Buyable`], [`Cancelable`], [`Refundable`], and [`Punishable`] transactions.
 ///
@@ -100,77 +118,124 @@ pub trait Transactions {
     type Ms;
     type Pk;
     type Si;
+    /// Unit of the fee rate applied on the transactions.
+    type FeeUnit;
 
     /// Defines the type for the `funding (a)` transaction
     type Funding: Fundable<Self::Tx, Self::Out, Self::Addr, Self::Pk>;
     /// Defines the type for the `lock (b)` transaction
     type Lock: Lockable<
-        Self::Addr,
-        Self::Tx,
-        Self::Px,
-        Self::Out,
-        Self::Amt,
-        Self::Ti,
-        Self::Ms,
-        Self::Pk,
-        Self::Si,
-    >;
+            Self::Addr,
+            Self::Tx,
+            Self::Px,
+            Self::Out,
+            Self::Amt,
+            Self::Ti,
+            Self::Ms,
+            Self::Pk,
+            Self::Si,
+        > + Fee<FeeUnit = Self::FeeUnit>;
     /// Defines the type for the `buy (c)` transaction
     type Buy: Buyable<
-        Self::Addr,
-        Self::Tx,
-        Self::Px,
-        Self::Out,
-        Self::Amt,
-        Self::Ti,
-        Self::Ms,
-        Self::Pk,
-        Self::Si,
-    >;
+            Self::Addr,
+            Self::Tx,
+            Self::Px,
+            Self::Out,
+            Self::Amt,
+            Self::Ti,
+            Self::Ms,
+            Self::Pk,
+            Self::Si,
+        > + Fee<FeeUnit = Self::FeeUnit>;
     /// Defines the type for the `cancel (d)` transaction
     type Cancel: Cancelable<
-        Self::Addr,
-        Self::Tx,
-        Self::Px,
-        Self::Out,
-        Self::Amt,
-        Self::Ti,
-        Self::Ms,
-        Self::Pk,
-        Self::Si,
-    >;
+            Self::Addr,
+            Self::Tx,
+            Self::Px,
+            Self::Out,
+            Self::Amt,
+            Self::Ti,
+            Self::Ms,
+            Self::Pk,
+            Self::Si,
+        > + Fee<FeeUnit = Self::FeeUnit>;
     /// Defines the type for the `refund (e)` transaction
     type Refund: Refundable<
-        Self::Addr,
-        Self::Tx,
-        Self::Px,
-        Self::Out,
-        Self::Amt,
-        Self::Ti,
-        Self::Ms,
-        Self::Pk,
-        Self::Si,
-    >;
+            Self::Addr,
+            Self::Tx,
+            Self::Px,
+            Self::Out,
+            Self::Amt,
+            Self::Ti,
+            Self::Ms,
+            Self::Pk,
+            Self::Si,
+        > + Fee<FeeUnit = Self::FeeUnit>;
     /// Defines the type for the `punish (f)` transaction
     type Punish: Punishable<
-        Self::Addr,
-        Self::Tx,
-        Self::Px,
-        Self::Out,
-        Self::Amt,
-        Self::Ti,
-        Self::Ms,
-        Self::Pk,
-        Self::Si,
-    >;
+            Self::Addr,
+            Self::Tx,
+            Self::Px,
+            Self::Out,
+            Self::Amt,
+            Self::Ti,
+            Self::Ms,
+            Self::Pk,
+            Self::Si,
+        > + Fee<FeeUnit = Self::FeeUnit>;
+
+    /// Return the value of the lock output for the swap parameters, paying the buy transaction
+    /// to `buy_destination`. The value is adjusted so the happy path fees are borne as negotiated
+    /// with [`FeePolitics`], both parties compute it to build, respectively verify, the lock.
+    fn lock_amount(
+        params: &ArbitratingParameters<Self::Amt, Self::Ti, Self::FeeUnit>,
+        buy_destination: &Self::Addr,
+    ) -> Result<Self::Amt, FeeStrategyError>;
+
+    /// Return the amount Bob must send to the funding address: the [`Transactions::lock_amount`]
+    /// plus the `lock` fee at the lowest rate of the strategy. The `lock` fee is validated against
+    /// the strategy, a funding of another amount is rejected.
+    fn funding_amount(
+        params: &ArbitratingParameters<Self::Amt, Self::Ti, Self::FeeUnit>,
+        buy_destination: &Self::Addr,
+    ) -> Result<Self::Amt, FeeStrategyError>;
+
+    /// Verify the `buy` built by the counter-party before signing it: it spends the `lock`
+    /// output, pays `destination_target` an amount above dust, and its fee follows the strategy.
+    fn verify_buy_template(
+        buy: &Self::Buy,
+        lock: &Self::Lock,
+        destination_target: &Self::Addr,
+        fee_strategy: &FeeStrategy<Self::FeeUnit>,
+    ) -> Result<(), transaction::Error>;
+
+    /// Verify the `cancel` built by the counter-party before signing it: it spends the `lock`
+    /// output after the cancel timelock of `data_lock`, pays the `punish_lock` an amount above
+    /// dust, and its fee follows the strategy.
+    fn verify_cancel_template(
+        cancel: &Self::Cancel,
+        lock: &Self::Lock,
+        data_lock: DataLock<Self::Ti, Self::Pk>,
+        punish_lock: DataPunishableLock<Self::Ti, Self::Pk>,
+        fee_strategy: &FeeStrategy<Self::FeeUnit>,
+    ) -> Result<(), transaction::Error>;
+
+    /// Verify the `refund` built by the counter-party before signing it: it spends the `cancel`
+    /// output, pays `refund_target` an amount above dust, and its fee follows the strategy.
+    fn verify_refund_template(
+        refund: &Self::Refund,
+        cancel: &Self::Cancel,
+        refund_target: &Self::Addr,
+        fee_strategy: &FeeStrategy<Self::FeeUnit>,
+    ) -> Result<(), transaction::Error>;
 }
 
 /// A fee strategy to be applied on an arbitrating transaction. As described in the specifications
 /// a fee strategy can be: fixed or range. When the fee strategy allows multiple possibilities, a
-/// [`FeePriority`] is used to determine what to apply.
+/// [`FeePriority`] or a [`FeeEstimator`] is used to determine what to apply.
 ///
 /// A fee strategy is included in a deal, so Alice and Bob can verify that transactions are valid
-/// upon reception by the other participant.
+/// upon reception by the other participant. With a range, any fee within the bounds is valid.
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub enum FeeStrategy<T> {
     /// A fixed strategy with the exact amount to set.
@@ -193,6 +258,48 @@ where
             Self::Range { min_inc, max_inc } => value >= min_inc && value <= max_inc,
         }
     }
+
+    /// Return the minimum and maximum (inclusive) fee rates allowed by the strategy, both bounds
+    /// are equal for a fixed strategy.
+    pub fn bounds(&self) -> (&T, &T) {
+        match self {
+            Self::Fixed(fee_strat) => (fee_strat, fee_strat),
+            #[cfg(feature = "fee_range")]
+            Self::Range { min_inc, max_inc } => (min_inc, max_inc),
+        }
+    }
+
+    /// Resolve the fee rate to apply, usually at broadcast time. A fixed strategy always returns
+    /// its fee rate without querying the estimator, a range returns the estimated fee rate
+    /// clamped within its bounds.
+    #[allow(unused_variables)]
+    pub fn resolve<E>(&self, estimator: &E) -> Result<T, FeeStrategyError>
+    where
+        T: Clone,
+        E: FeeEstimator<T> + ?Sized,
+    {
+        match self {
+            Self::Fixed(fee_strat) => Ok(fee_strat.clone()),
+            #[cfg(feature = "fee_range")]
+            Self::Range { min_inc, max_inc } => {
+                let estimation = estimator.estimate_fee_rate()?;
+                if estimation < *min_inc {
+                    Ok(min_inc.clone())
+                } else if estimation > *max_inc {
+                    Ok(max_inc.clone())
+                } else {
+                    Ok(estimation)
+                }
+            }
+        }
+    }
+}
+
+/// A source of fee rates, e.g. a node or a fee oracle, queried to resolve a [`FeeStrategy`] when
+/// a transaction is about to be broadcast.
+pub trait FeeEstimator<T> {
+    /// Return the current fee rate estimation.
+    fn estimate_fee_rate(&self) -> Result<T, FeeStrategyError>;
 }
 
 impl<T> FromStr for FeeStrategy<T>
@@ -317,9 +424,16 @@ pub enum FeeStrategyError {
     /// Not enough assets to cover the fees.
     #[error("Not enough assets to cover the fees")]
     NotEnoughAssets,
+    /// An amount overflows or cannot be represented.
+    #[error("Amount overflow")]
+    AmountOverflow,
+    /// The fee set on a transaction received from the counter-party does not follow the fee
+    /// strategy.
+    #[error("Fee does not follow the fee strategy")]
+    OutOfStrategy,
     /// Any fee strategy error not part of this list.
     #[error("Other: {0}")]
-    Other(Box<dyn error::Error + Sync + Send>),
+    Other(#[source] Box<dyn error::Error + Sync + Send>),
 }
 
 impl FeeStrategyError {
@@ -387,6 +501,71 @@ impl FromStr for FeePriority {
     }
 }
 
+/// Defines which party of the trade bears the arbitrating transaction fees of the happy path, the
+/// `lock` and `buy` fees. The fees of the `cancel` path are always borne by the party receiving
+/// its outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum FeePolitics {
+    /// The maker pays all the fees.
+    MakerPays,
+    /// The taker pays all the fees.
+    TakerPays,
+    /// The fees are split in half, the odd unit is paid by Bob.
+    Split,
+}
+
+impl FeePolitics {
+    /// Return the part of `fee` paid by Alice and by Bob given the maker swap role.
+    pub fn split_fee(&self, maker_role: SwapRole, fee: u64) -> (u64, u64) {
+        let payer = match self {
+            FeePolitics::MakerPays => maker_role,
+            FeePolitics::TakerPays => maker_role.other(),
+            FeePolitics::Split => return (fee / 2, fee - fee / 2),
+        };
+        match payer {
+            SwapRole::Alice => (fee, 0),
+            SwapRole::Bob => (0, fee),
+        }
+    }
+}
+
+impl Decodable for FeePolitics {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match Decodable::consensus_decode(d)? {
+            0x01u8 => Ok(FeePolitics::MakerPays),
+            0x02u8 => Ok(FeePolitics::TakerPays),
+            0x03u8 => Ok(FeePolitics::Split),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl Encodable for FeePolitics {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        match self {
+            FeePolitics::MakerPays => 0x01u8.consensus_encode(writer),
+            FeePolitics::TakerPays => 0x02u8.consensus_encode(writer),
+            FeePolitics::Split => 0x03u8.consensus_encode(writer),
+        }
+    }
+}
+
+impl_strict_encoding!(FeePolitics);
+
+impl FromStr for FeePolitics {
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "MakerPays" | "maker" => Ok(FeePolitics::MakerPays),
+            "TakerPays" | "taker" => Ok(FeePolitics::TakerPays),
+            "Split" | "split" => Ok(FeePolitics::Split),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
 /// Enable fee management for an arbitrating blockchain. The [`Fee`] trait declares a fee unit used
 /// in fee strategies and an amount used in transactions. Implementing this trait allow to set and
 /// verify fees on transactions given a strategy and a priority.
@@ -439,9 +618,25 @@ pub trait Fee {
         politic: FeePriority,
     ) -> Result<Self::Amount, FeeStrategyError>;
 
-    /// Validates that the fee for the given transaction are set accordingly to the strategy.
+    /// Validates that the fee for the given transaction are set accordingly to the strategy. For
+    /// a range strategy the fee must fall within the bounds.
     fn validate_fee(&self, strategy: &FeeStrategy<Self::FeeUnit>)
         -> Result<bool, FeeStrategyError>;
+
+    /// Resolves the strategy against the `estimator`, see [`FeeStrategy::resolve`], then sets the
+    /// resolved fee rate on the transaction and return the amount of fee set.
+    fn set_fee_with_estimator<E>(
+        &mut self,
+        strategy: &FeeStrategy<Self::FeeUnit>,
+        estimator: &E,
+    ) -> Result<Self::Amount, FeeStrategyError>
+    where
+        Self::FeeUnit: Clone + PartialOrd,
+        E: FeeEstimator<Self::FeeUnit> + ?Sized,
+    {
+        let fee_rate = strategy.resolve(estimator)?;
+        self.set_fee(&FeeStrategy::Fixed(fee_rate), FeePriority::Low)
+    }
 }
 
 impl FromStr for Network {
@@ -519,6 +714,102 @@ impl Decodable for Network {
 
 impl_strict_encoding!(Network);
 
+/// An amount of accordant asset expressed in the smallest unit of the accordant blockchain, e.g.
+/// piconero for Monero. Arithmetic is checked and returns `None` instead of overflowing.
+#[derive(
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Debug,
+    Default,
+    Display,
+    Serialize,
+    Deserialize,
+)]
+#[display("{0}")]
+pub struct AccordantAmount(u64);
+
+impl AccordantAmount {
+    /// The zero amount.
+    pub const ZERO: AccordantAmount = AccordantAmount(0);
+
+    /// Create an amount from a number of atomic units.
+    pub const fn from_atomic_units(units: u64) -> Self {
+        Self(units)
+    }
+
+    /// Return the number of atomic units of the amount.
+    pub fn as_atomic_units(&self) -> u64 {
+        self.0
+    }
+
+    /// Checked addition, returns `None` if an overflow occurred.
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Self)
+    }
+
+    /// Checked subtraction, returns `None` if an overflow occurred.
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Self)
+    }
+
+    /// Checked multiplication, returns `None` if an overflow occurred.
+    pub fn checked_mul(self, rhs: u64) -> Option<Self> {
+        self.0.checked_mul(rhs).map(Self)
+    }
+}
+
+impl TryFrom<i64> for AccordantAmount {
+    type Error = consensus::Error;
+
+    fn try_from(units: i64) -> Result<Self, Self::Error> {
+        u64::try_from(units)
+            .map(Self)
+            .map_err(|_| consensus::Error::ParseFailed("Negative accordant amount"))
+    }
+}
+
+impl FromStr for AccordantAmount {
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<u64>()
+            .map(Self)
+            .map_err(|_| consensus::Error::ParseFailed("Accordant amount in atomic units"))
+    }
+}
+
+impl Encodable for AccordantAmount {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        self.0.consensus_encode(writer)
+    }
+}
+
+impl Decodable for AccordantAmount {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self(Decodable::consensus_decode(d)?))
+    }
+}
+
+impl CanonicalBytes for AccordantAmount {
+    fn as_canonical_bytes(&self) -> Vec<u8> {
+        serialize(self)
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, consensus::Error>
+    where
+        Self: Sized,
+    {
+        deserialize(bytes)
+    }
+}
+
+impl_strict_encoding!(AccordantAmount);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -532,6 +823,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn accordant_amount_checked_arithmetic() {
+        let amount = AccordantAmount::from_atomic_units(u64::MAX - 1);
+        assert_eq!(
+            amount.checked_add(AccordantAmount::from_atomic_units(1)),
+            Some(AccordantAmount::from_atomic_units(u64::MAX))
+        );
+        assert_eq!(amount.checked_add(amount), None);
+        assert_eq!(AccordantAmount::ZERO.checked_sub(amount), None);
+        assert_eq!(amount.checked_mul(2), None);
+        assert!(AccordantAmount::try_from(-1i64).is_err());
+        assert_eq!(
+            amount.to_string().parse::<AccordantAmount>().unwrap(),
+            amount
+        );
+        assert!("0.1 XMR".parse::<AccordantAmount>().is_err());
+
+        let xmr = monero::Amount::from_pico(1_000_000);
+        assert_eq!(monero::Amount::from(AccordantAmount::from(xmr)), xmr);
+        let bytes = amount.as_canonical_bytes();
+        assert_eq!(
+            AccordantAmount::from_canonical_bytes(&bytes).unwrap(),
+            amount
+        );
+    }
+
     #[test]
     fn parse_network() {
         for s in ["Mainnet", "mainnet", "Testnet", "testnet", "Local", "local"].iter() {
@@ -654,4 +971,34 @@ mod tests {
         assert!(strategy.check(&SatPerKvB::from_sat(150)));
         assert!(!strategy.check(&SatPerKvB::from_sat(151)));
     }
+
+    struct Estimation(u64);
+
+    impl FeeEstimator<SatPerKvB> for Estimation {
+        fn estimate_fee_rate(&self) -> Result<SatPerKvB, FeeStrategyError> {
+            Ok(SatPerKvB::from_sat(self.0))
+        }
+    }
+
+    #[test]
+    fn fee_strategy_resolve() {
+        let strategy = FeeStrategy::Fixed(SatPerKvB::from_sat(100));
+        assert_eq!(
+            strategy.resolve(&Estimation(500)).unwrap(),
+            SatPerKvB::from_sat(100)
+        );
+        #[cfg(feature = "fee_range")]
+        {
+            let strategy = FeeStrategy::Range {
+                min_inc: SatPerKvB::from_sat(50),
+                max_inc: SatPerKvB::from_sat(150),
+            };
+            for (estimation, resolved) in [(10, 50), (50, 50), (120, 120), (150, 150), (500, 150)] {
+                assert_eq!(
+                    strategy.resolve(&Estimation(estimation)).unwrap(),
+                    SatPerKvB::from_sat(resolved)
+                );
+            }
+        }
+    }
 }
@@ -51,7 +51,7 @@ pub enum Error {
     ParseFailed(&'static str),
     /// Any Consensus error not part of this list.
     #[error("Consensus error: {0}")]
-    Other(Box<dyn error::Error + Send + Sync>),
+    Other(#[source] Box<dyn error::Error + Send + Sync>),
 }
 
 impl Error {
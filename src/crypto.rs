@@ -22,19 +22,33 @@ use std::convert::TryInto;
 use std::error;
 use std::fmt::{self, Debug};
 use std::io;
+use std::str::FromStr;
 
 use thiserror::Error;
-use tiny_keccak::{Hasher, Keccak};
+use zeroize::Zeroize;
 
 use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::primitives;
 
+pub mod cache;
+pub mod commitment;
+pub mod context;
 #[cfg(feature = "experimental")]
 #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
 pub mod dleq;
 #[cfg(feature = "experimental")]
 #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub mod encryption;
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub mod musig2;
+pub mod registry;
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
 pub mod slip10;
 
+pub use context::CryptoContext;
+
 /// List of cryptographic errors that can be encountered in cryptographic operations such as
 /// signatures, proofs, key derivation, or commitments.
 #[derive(Error, Debug)]
@@ -69,12 +83,27 @@ pub enum Error {
     /// The proof of knowledge signature is invalid.
     #[error("The proof of knowledge signature is invalid")]
     InvalidProofOfKnowledge,
+    /// The aggregated key is invalid, e.g. the point at infinity.
+    #[error("The aggregated key is invalid")]
+    InvalidAggregatedKey,
+    /// The nonces do not match the signers.
+    #[error("The nonces do not match the signers")]
+    InvalidNonce,
+    /// The partial signature does not pass the validation tests.
+    #[error("The partial signature does not pass the validation")]
+    InvalidPartialSignature,
+    /// The ciphertext is malformed or cannot be authenticated.
+    #[error("The ciphertext is malformed or cannot be authenticated")]
+    InvalidCiphertext,
+    /// Commitment error when opening a commitment with a reveal.
+    #[error("Commitment error: {0}")]
+    Commitment(#[from] commitment::Error),
     /// SLIP10 error when manipulating extended secret keys.
     #[error("SLIP10 error: {0}")]
     Slip10(#[from] slip10::Error),
     /// Any cryptographic error not part of this list.
     #[error("Cryptographic error: {0}")]
-    Other(Box<dyn error::Error + Send + Sync>),
+    Other(#[source] Box<dyn error::Error + Send + Sync>),
 }
 
 impl Error {
@@ -101,6 +130,57 @@ impl Error {
     }
 }
 
+impl From<primitives::dleq::Error> for Error {
+    fn from(e: primitives::dleq::Error) -> Self {
+        match e {
+            primitives::dleq::Error::InvalidPedersenCommitment => Self::InvalidPedersenCommitment,
+            primitives::dleq::Error::InvalidRingSignature => Self::InvalidRingSignature,
+            primitives::dleq::Error::InvalidProofOfKnowledge => Self::InvalidProofOfKnowledge,
+            _ => Self::InvalidProof,
+        }
+    }
+}
+
+/// A secret value wiped from memory when dropped. The [`Debug`] implementation never displays
+/// the wrapped value, it must be accessed explicitly with [`SecretData::expose_secret`].
+#[derive(Clone, Default)]
+pub struct SecretData<T: Zeroize>(T);
+
+impl<T: Zeroize> SecretData<T> {
+    /// Wrap the `secret` value.
+    pub fn new(secret: T) -> Self {
+        Self(secret)
+    }
+
+    /// Returns a reference to the secret value.
+    pub fn expose_secret(&self) -> &T {
+        &self.0
+    }
+
+    /// Returns a mutable reference to the secret value.
+    pub fn expose_secret_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Zeroize> From<T> for SecretData<T> {
+    fn from(secret: T) -> Self {
+        Self::new(secret)
+    }
+}
+
+impl<T: Zeroize> Debug for SecretData<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecretData(<redacted>)")
+    }
+}
+
+impl<T: Zeroize> Drop for SecretData<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
 /// Element `E` prefixed with a tag `T`. Used to tag content with some ids. Tag should be `Eq` to
 /// be used in vectors or sets and identify the content. Tags can be [`ArbitratingKeyId`],
 /// [`AccordantKeyId`] or any other type of identifiers.
@@ -248,6 +328,55 @@ impl Decodable for SharedKeyId {
     }
 }
 
+/// Parse the `u16` identifier of a displayed `name(id)` key identifier.
+fn parse_key_id(s: &str, name: &str) -> Option<u16> {
+    s.strip_prefix(name)?
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .parse()
+        .ok()
+}
+
+impl FromStr for ArbitratingKeyId {
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Lock" => Ok(Self::Lock),
+            "Buy" => Ok(Self::Buy),
+            "Cancel" => Ok(Self::Cancel),
+            "Refund" => Ok(Self::Refund),
+            "Punish" => Ok(Self::Punish),
+            _ => parse_key_id(s, "Extra")
+                .map(Self::Extra)
+                .ok_or(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl FromStr for AccordantKeyId {
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Spend" => Ok(Self::Spend),
+            _ => parse_key_id(s, "Extra")
+                .map(Self::Extra)
+                .ok_or(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl FromStr for SharedKeyId {
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_key_id(s, "SharedKeyId")
+            .map(Self)
+            .ok_or(consensus::Error::UnknownType)
+    }
+}
+
 /// The full set of keys (secret and public) a swap role has after the reveal round for the
 /// [`Accordant`] blockchain in the swap (e.g. the Monero blockchain).
 ///
@@ -273,10 +402,11 @@ pub struct AccordantKeySet<PublicKey, SharedSecretKey> {
 
 fixed_hash::construct_fixed_hash!(
     /// Result of a keccak256 commitment.
-    #[derive(Serialize, Deserialize)]
     pub struct KeccakCommitment(32);
 );
 
+impl_serde_hex!(KeccakCommitment);
+
 impl KeccakCommitment {
     /// Create a null commitment hash with all zeros.
     pub fn null_hash() -> Self {
@@ -290,15 +420,31 @@ impl KeccakCommitment {
 }
 
 impl CanonicalBytes for KeccakCommitment {
+    /// The identifier [`AlgorithmId::KECCAK_256`] followed by the hash, so commitments with
+    /// another algorithm can be introduced without breaking the encoding of the commit messages.
+    ///
+    /// [`AlgorithmId::KECCAK_256`]: registry::AlgorithmId::KECCAK_256
     fn as_canonical_bytes(&self) -> Vec<u8> {
-        (*self).to_fixed_bytes().into()
+        let mut bytes = vec![registry::AlgorithmId::KECCAK_256.0];
+        bytes.extend_from_slice(self.as_bytes());
+        bytes
     }
 
     fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, consensus::Error>
     where
         Self: Sized,
     {
-        Ok(Self::new(bytes.try_into().map_err(consensus::Error::new)?))
+        match bytes.split_first() {
+            Some((&id, hash)) if registry::AlgorithmId(id) == registry::AlgorithmId::KECCAK_256 => {
+                Ok(Self::new(hash.try_into().map_err(consensus::Error::new)?))
+            }
+            Some((&id, _)) => Err(consensus::Error::new(commitment::Error::UnknownAlgorithm(
+                registry::AlgorithmId(id),
+            ))),
+            None => Err(consensus::Error::ParseFailed(
+                "Missing commitment algorithm",
+            )),
+        }
     }
 }
 
@@ -309,11 +455,7 @@ pub struct CommitmentEngine;
 
 impl Commit<KeccakCommitment> for CommitmentEngine {
     fn commit_to<T: AsRef<[u8]>>(&self, value: T) -> KeccakCommitment {
-        let mut out = [0u8; 32];
-        let mut keccak = Keccak::v256();
-        keccak.update(value.as_ref());
-        keccak.finalize(&mut out);
-        KeccakCommitment::new(out)
+        KeccakCommitment::new(primitives::commitment::keccak256(value.as_ref()))
     }
 }
 
@@ -455,13 +597,14 @@ pub trait EncSign<PublicKey, Message, Signature, EncryptedSignature> {
 /// encryption key.
 pub trait RecoverSecret<PublicKey, SecretKey, Signature, EncryptedSignature> {
     /// Recover the encryption key based on the encrypted signature, the encryption public key, and
-    /// the regular (decrypted) signature.
+    /// the regular (decrypted) signature. Fails with [`Error::InvalidEncryptedSignature`] if the
+    /// signature is not the decryption of the encrypted signature.
     fn recover_secret_key(
         &self,
         encrypted_sig: EncryptedSignature,
         encryption_key: &PublicKey,
         sig: Signature,
-    ) -> SecretKey;
+    ) -> Result<SecretKey, Error>;
 }
 
 /// Commitment generator and verifier. Generated commitments can be validated against candidates,
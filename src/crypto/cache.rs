@@ -0,0 +1,221 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Cache of successful verifications, so keys, proofs and signatures received from the
+//! counter-party are not verified again at each state transition or after a restart.
+//!
+//! Entries are keyed by a tagged hash of the kind of verification and of all its inputs, e.g. the
+//! public keys and the proof for a cross-group DLEQ proof. Only successful verifications are
+//! cached: a cache hit means the exact same inputs already passed the verification. Failed
+//! verifications are always run again.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+
+use crate::consensus::{self, Decodable, Encodable};
+use crate::primitives::commitment::tagged_sha256;
+
+/// Tag of the hash used to derive the cache keys.
+pub const CACHE_KEY_TAG: &[u8] = b"farcaster/verification-cache";
+
+/// The kind of verification cached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+#[display(Debug)]
+pub enum VerificationKind {
+    /// A cross-group discrete logarithm equality proof.
+    DleqProof,
+    /// An adaptor signature.
+    EncryptedSignature,
+}
+
+impl VerificationKind {
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::DleqProof => 0x01,
+            Self::EncryptedSignature => 0x02,
+        }
+    }
+}
+
+/// Key of a verification in the cache, the tagged hash of the kind of verification and of its
+/// inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CacheKey([u8; 32]);
+
+impl CacheKey {
+    /// Derive the key of a verification from its inputs, in their canonical bytes. Inputs are
+    /// length prefixed so different splits of the same bytes give different keys.
+    pub fn new(kind: VerificationKind, inputs: &[&[u8]]) -> Self {
+        let mut preimage = vec![kind.to_u8()];
+        for input in inputs {
+            preimage.extend((input.len() as u64).to_le_bytes());
+            preimage.extend_from_slice(input);
+        }
+        Self(tagged_sha256(CACHE_KEY_TAG, &preimage))
+    }
+
+    /// Return the hash of the key.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+}
+
+/// Set of the verifications that already succeeded. The cache can be shared between threads and
+/// serialized to survive restarts.
+#[derive(Default)]
+pub struct VerificationCache {
+    entries: Mutex<BTreeSet<CacheKey>>,
+}
+
+impl VerificationCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, BTreeSet<CacheKey>> {
+        // The set is always left in a consistent state, a poisoned lock is safe to reuse
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Run the verification unless the same verification already succeeded, and cache it if it
+    /// succeeds.
+    pub fn verify<E>(
+        &self,
+        key: CacheKey,
+        verify: impl FnOnce() -> Result<(), E>,
+    ) -> Result<(), E> {
+        if self.contains(&key) {
+            return Ok(());
+        }
+        verify()?;
+        self.entries().insert(key);
+        Ok(())
+    }
+
+    /// Return true if the verification already succeeded.
+    pub fn contains(&self, key: &CacheKey) -> bool {
+        self.entries().contains(key)
+    }
+
+    /// Remove a verification from the cache, return true if it was cached.
+    pub fn invalidate(&self, key: &CacheKey) -> bool {
+        self.entries().remove(key)
+    }
+
+    /// Remove all the verifications from the cache.
+    pub fn clear(&self) {
+        self.entries().clear()
+    }
+
+    /// Return the number of cached verifications.
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    /// Return true if no verification is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Clone for VerificationCache {
+    fn clone(&self) -> Self {
+        Self {
+            entries: Mutex::new(self.entries().clone()),
+        }
+    }
+}
+
+impl fmt::Debug for VerificationCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VerificationCache")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl PartialEq for VerificationCache {
+    fn eq(&self, other: &Self) -> bool {
+        *self.entries() == *other.entries()
+    }
+}
+
+impl Eq for VerificationCache {}
+
+impl Encodable for VerificationCache {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let entries: Vec<[u8; 32]> = self.entries().iter().map(CacheKey::to_bytes).collect();
+        entries.consensus_encode(writer)
+    }
+}
+
+impl Decodable for VerificationCache {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        let entries: Vec<[u8; 32]> = Decodable::consensus_decode(d)?;
+        Ok(Self {
+            entries: Mutex::new(entries.into_iter().map(CacheKey).collect()),
+        })
+    }
+}
+
+impl_strict_encoding!(VerificationCache);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    #[test]
+    fn cache_successful_verifications() {
+        let cache = VerificationCache::new();
+        let key = CacheKey::new(VerificationKind::DleqProof, &[b"spend", b"proof"]);
+        let runs = Cell::new(0);
+        let verify = |result: Result<(), ()>| {
+            runs.set(runs.get() + 1);
+            result
+        };
+
+        // Failures are not cached
+        assert!(cache.verify(key, || verify(Err(()))).is_err());
+        assert!(cache.is_empty());
+        assert!(cache.verify(key, || verify(Ok(()))).is_ok());
+        assert!(cache.verify(key, || verify(Err(()))).is_ok());
+        assert_eq!(runs.get(), 2);
+
+        // Inputs are length prefixed and bound to the kind of verification
+        assert_ne!(
+            key,
+            CacheKey::new(VerificationKind::DleqProof, &[b"spendproof"])
+        );
+        assert_ne!(
+            key,
+            CacheKey::new(VerificationKind::EncryptedSignature, &[b"spend", b"proof"])
+        );
+
+        let restored: VerificationCache =
+            consensus::deserialize(&consensus::serialize(&cache)).unwrap();
+        assert_eq!(restored, cache);
+        assert!(restored.contains(&key));
+
+        assert!(cache.invalidate(&key));
+        assert!(cache.verify(key, || verify(Err(()))).is_err());
+        assert_eq!(runs.get(), 3);
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Binding commitments for the commit/reveal round of the protocol. Each party commits to the
+//! full set of parameters it reveals later, its public keys and the cross-group DLEQ proof,
+//! before receiving the counter-party parameters.
+//!
+//! Commitments are tagged SHA-256 hashes (as in BIP-340) over the consensus serialization of the
+//! reveal message. The tag includes the swap role of the committing party, so a commitment made
+//! by Alice can never be opened by a reveal from Bob, and the swap identifier is part of the
+//! commitment envelope, so it cannot be replayed in another swap.
+//!
+//! The commitment carries the identifier of its hash algorithm, tagged SHA-256 by default, see
+//! the [`registry`](crate::crypto::registry) of algorithms.
+
+use std::io;
+
+use thiserror::Error;
+
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::registry::{AlgorithmId, HashRegistry};
+use crate::primitives;
+use crate::protocol::message::{RevealAliceParameters, RevealBobParameters, SwapMessage};
+use crate::role::SwapRole;
+use crate::swap::SwapId;
+
+/// Tag prefix of the commitments, the swap role of the committing party is appended to it.
+pub const COMMITMENT_TAG: &str = "farcaster/commitment/";
+
+/// Errors encountered when verifying a reveal against a commitment.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The reveal does not belong to the same swap as the commitment.
+    #[error("The reveal swap id {found} does not match the committed swap id {expected}")]
+    SwapIdMismatch {
+        /// The swap identifier of the commitment.
+        expected: SwapId,
+        /// The swap identifier of the reveal.
+        found: SwapId,
+    },
+    /// The reveal is not from the same swap role as the commitment.
+    #[error("The reveal from {found} does not match the commitment from {expected}")]
+    RoleMismatch {
+        /// The swap role of the commitment.
+        expected: SwapRole,
+        /// The swap role of the reveal.
+        found: SwapRole,
+    },
+    /// The revealed parameters do not open the commitment.
+    #[error("The revealed parameters do not open the commitment")]
+    InvalidOpening,
+    /// The hash algorithm is not supported.
+    #[error("The hash algorithm {0} is not supported")]
+    UnknownAlgorithm(AlgorithmId),
+    /// The hash algorithm identifier is already registered.
+    #[error("The hash algorithm {0} is already registered")]
+    AlgorithmAlreadyRegistered(AlgorithmId),
+}
+
+fixed_hash::construct_fixed_hash!(
+    /// Result of a tagged SHA-256 commitment.
+    pub struct TaggedCommitment(32);
+);
+
+impl_serde_hex!(TaggedCommitment);
+
+impl CanonicalBytes for TaggedCommitment {
+    fn as_canonical_bytes(&self) -> Vec<u8> {
+        (*self).to_fixed_bytes().into()
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, consensus::Error>
+    where
+        Self: Sized,
+    {
+        Ok(Self::from(
+            <[u8; 32]>::try_from(bytes).map_err(consensus::Error::new)?,
+        ))
+    }
+}
+
+/// Compute `SHA256(SHA256(tag) || SHA256(tag) || data)`.
+pub fn tagged_hash(tag: &[u8], data: &[u8]) -> TaggedCommitment {
+    TaggedCommitment::from(primitives::commitment::tagged_sha256(tag, data))
+}
+
+/// A reveal protocol message a party commits to during the commit round.
+pub trait Reveal: Encodable {
+    /// The swap role of the party revealing the parameters.
+    const ROLE: SwapRole;
+
+    /// The swap identifier of the revealed parameters.
+    fn swap_id(&self) -> SwapId;
+}
+
+impl<Pk, Qk, Rk, Sk, Addr, Pr> Reveal for RevealAliceParameters<Pk, Qk, Rk, Sk, Addr, Pr>
+where
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+    Rk: CanonicalBytes,
+    Sk: CanonicalBytes,
+    Addr: CanonicalBytes,
+    Pr: CanonicalBytes,
+{
+    const ROLE: SwapRole = SwapRole::Alice;
+
+    fn swap_id(&self) -> SwapId {
+        self.swap_id
+    }
+}
+
+impl<Pk, Qk, Rk, Sk, Addr, Pr> Reveal for RevealBobParameters<Pk, Qk, Rk, Sk, Addr, Pr>
+where
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+    Rk: CanonicalBytes,
+    Sk: CanonicalBytes,
+    Addr: CanonicalBytes,
+    Pr: CanonicalBytes,
+{
+    const ROLE: SwapRole = SwapRole::Bob;
+
+    fn swap_id(&self) -> SwapId {
+        self.swap_id
+    }
+}
+
+/// Commitment to all the parameters a party reveals: public keys, shared keys, address and
+/// cross-group DLEQ proof. Sent during the commit round and opened with [`verify_reveal`].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionCommitment {
+    /// The swap identifier related to this commitment.
+    pub swap_id: SwapId,
+    /// The swap role of the committing party.
+    pub role: SwapRole,
+    /// The hash algorithm of the commitment.
+    pub algorithm: AlgorithmId,
+    /// The tagged hash of the reveal.
+    pub commitment: TaggedCommitment,
+}
+
+impl SessionCommitment {
+    /// Commit to the parameters of a reveal message with tagged SHA-256.
+    pub fn commit<R: Reveal>(reveal: &R) -> Self {
+        Self::commit_with(&HashRegistry::new(), AlgorithmId::TAGGED_SHA256, reveal)
+            .expect("built-in algorithm")
+    }
+
+    /// Commit to the parameters of a reveal message with an algorithm of the registry. Fails
+    /// with [`Error::UnknownAlgorithm`] if the algorithm is not registered.
+    pub fn commit_with<R: Reveal>(
+        registry: &HashRegistry,
+        algorithm: AlgorithmId,
+        reveal: &R,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            swap_id: reveal.swap_id(),
+            role: R::ROLE,
+            algorithm,
+            commitment: commit_reveal(registry, algorithm, reveal)?,
+        })
+    }
+}
+
+fn commit_reveal<R: Reveal>(
+    registry: &HashRegistry,
+    algorithm: AlgorithmId,
+    reveal: &R,
+) -> Result<TaggedCommitment, Error> {
+    let tag = format!("{}{}", COMMITMENT_TAG, R::ROLE);
+    let digest = registry.digest(algorithm, tag.as_bytes(), &consensus::serialize(reveal))?;
+    Ok(TaggedCommitment::from(digest.hash))
+}
+
+/// Verify that `reveal` opens `commit`. Fails if the reveal is for another swap, comes from the
+/// other swap role, or if any revealed parameter differs from the committed ones. Only the
+/// built-in algorithms are supported, see [`verify_reveal_with`].
+pub fn verify_reveal<R: Reveal>(commit: &SessionCommitment, reveal: &R) -> Result<(), Error> {
+    verify_reveal_with(&HashRegistry::new(), commit, reveal)
+}
+
+/// Verify that `reveal` opens `commit` with the algorithms of the registry, see
+/// [`verify_reveal`]. Fails with [`Error::UnknownAlgorithm`] if the algorithm of the commitment
+/// is not registered.
+pub fn verify_reveal_with<R: Reveal>(
+    registry: &HashRegistry,
+    commit: &SessionCommitment,
+    reveal: &R,
+) -> Result<(), Error> {
+    if commit.swap_id != reveal.swap_id() {
+        return Err(Error::SwapIdMismatch {
+            expected: commit.swap_id,
+            found: reveal.swap_id(),
+        });
+    }
+    if commit.role != R::ROLE {
+        return Err(Error::RoleMismatch {
+            expected: commit.role,
+            found: R::ROLE,
+        });
+    }
+    if commit.commitment != commit_reveal(registry, commit.algorithm, reveal)? {
+        return Err(Error::InvalidOpening);
+    }
+    Ok(())
+}
+
+impl Encodable for SessionCommitment {
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let len = self.swap_id.consensus_encode(s)?;
+        let len = len + self.role.consensus_encode(s)?;
+        let len = len + self.algorithm.consensus_encode(s)?;
+        Ok(len + self.commitment.as_canonical_bytes().consensus_encode(s)?)
+    }
+}
+
+impl Decodable for SessionCommitment {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            swap_id: Decodable::consensus_decode(d)?,
+            role: Decodable::consensus_decode(d)?,
+            algorithm: Decodable::consensus_decode(d)?,
+            commitment: TaggedCommitment::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(SessionCommitment);
+
+impl SwapMessage for SessionCommitment {
+    fn swap_id(&self) -> SwapId {
+        self.swap_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_wrong_length_commitment() {
+        let commitment = tagged_hash(b"farcaster/commitment/Alice", b"keys");
+        let bytes = commitment.as_canonical_bytes();
+        assert_eq!(
+            TaggedCommitment::from_canonical_bytes(&bytes).unwrap(),
+            commitment
+        );
+        assert!(TaggedCommitment::from_canonical_bytes(&bytes[1..]).is_err());
+        assert!(TaggedCommitment::from_canonical_bytes(&[0; 33]).is_err());
+    }
+
+    #[test]
+    fn tagged_hash_is_domain_separated() {
+        let alice = tagged_hash(b"farcaster/commitment/Alice", b"keys");
+        assert_eq!(alice, tagged_hash(b"farcaster/commitment/Alice", b"keys"));
+        assert_ne!(alice, tagged_hash(b"farcaster/commitment/Bob", b"keys"));
+        assert_ne!(
+            alice,
+            tagged_hash(b"farcaster/commitment/Alice", b"other keys")
+        );
+    }
+}
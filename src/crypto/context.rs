@@ -0,0 +1,218 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Cryptographic contexts created once and shared across signing, verification and adaptor
+//! operations. Creating a secp256k1 context is expensive compared to a signature, a
+//! [`CryptoContext`] is meant to be created at startup and passed where needed.
+//!
+//! With the `global-context` feature the secp256k1 context is the global static of the
+//! `secp256k1` crate, shared by the whole process, and a [`CryptoContext`] only holds the
+//! adaptor engine.
+//!
+//! Signatures are produced through the [`EcdsaSigner`] abstraction: the secp256k1 context signs
+//! with RFC6979 nonces, the ECDSA engine of a context with the nonces of its [`NonceProvider`].
+//! Production contexts use synthetic nonces mixing fresh randomness, tests and
+//! cross-implementation vectors can inject [`NonceProvider::Deterministic`] nonces.
+
+use bitcoin::secp256k1::{ecdsa::Signature, All, Message, Secp256k1, SecretKey, Signing};
+
+#[cfg(feature = "experimental")]
+use ecdsa_fun::{
+    adaptor::{Adaptor, HashTranscript},
+    fun::{marker::*, Scalar},
+    nonce::{self, AddTag},
+    ECDSA,
+};
+#[cfg(feature = "experimental")]
+use rand::rngs::ThreadRng;
+#[cfg(feature = "experimental")]
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+#[cfg(feature = "experimental")]
+use sha2::{Digest, Sha256};
+
+/// Transcript used by the adaptor signature engine.
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub type Transcript = HashTranscript<Sha256, ChaCha20Rng>;
+
+/// Nonce generator used by the ECDSA and adaptor signature engines.
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub type NonceGen = NonceProvider;
+
+/// Nonces injected in the ECDSA and adaptor signature engines of a [`CryptoContext`].
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+#[derive(Clone, Debug)]
+pub enum NonceProvider {
+    /// Nonces derived from the secret key, the message, and fresh randomness, the default.
+    Synthetic(nonce::Synthetic<Sha256, nonce::GlobalRng<ThreadRng>>),
+    /// Nonces derived from the secret key and the message only. Signatures are reproducible, meant
+    /// for tests and cross-implementation vectors.
+    Deterministic(nonce::Deterministic<Sha256>),
+}
+
+#[cfg(feature = "experimental")]
+impl NonceProvider {
+    /// Create a deterministic nonce provider.
+    pub fn deterministic() -> Self {
+        Self::Deterministic(nonce::Deterministic::default())
+    }
+}
+
+#[cfg(feature = "experimental")]
+impl NonceProvider {
+    /// The randomness source of a cross-group DLEQ proof of `secret`: fresh randomness for
+    /// synthetic nonces, a stream seeded from the secret for deterministic nonces so the proof is
+    /// reproducible.
+    pub(crate) fn proof_rng(&self, secret: &[u8; 32]) -> ChaCha20Rng {
+        match self {
+            Self::Synthetic(_) => {
+                ChaCha20Rng::from_rng(rand::thread_rng()).expect("thread rng does not fail")
+            }
+            Self::Deterministic(_) => {
+                let mut engine = Sha256::new();
+                engine.update(b"farcaster/dleq-proof");
+                engine.update(secret);
+                ChaCha20Rng::from_seed(engine.finalize().into())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "experimental")]
+impl Default for NonceProvider {
+    fn default() -> Self {
+        Self::Synthetic(nonce::Synthetic::default())
+    }
+}
+
+#[cfg(feature = "experimental")]
+impl nonce::NonceGen for NonceProvider {
+    type Hash = Sha256;
+
+    fn begin_derivation(&self, secret: &Scalar) -> Self::Hash {
+        match self {
+            Self::Synthetic(nonce_gen) => nonce_gen.begin_derivation(secret),
+            Self::Deterministic(nonce_gen) => nonce_gen.begin_derivation(secret),
+        }
+    }
+}
+
+#[cfg(feature = "experimental")]
+impl AddTag for NonceProvider {
+    fn add_tag(self, tag: &str) -> Self {
+        match self {
+            Self::Synthetic(nonce_gen) => Self::Synthetic(nonce_gen.add_tag(tag)),
+            Self::Deterministic(nonce_gen) => Self::Deterministic(nonce_gen.add_tag(tag)),
+        }
+    }
+}
+
+/// Produce low-S ECDSA signatures over a message, the nonce generation is left to the
+/// implementation.
+pub trait EcdsaSigner {
+    /// Sign the message with the secret key, the returned signature is normalized to low-S.
+    fn sign_low_s(&self, msg: &Message, secret_key: &SecretKey) -> Signature;
+}
+
+/// Sign with RFC6979 nonces.
+impl<C: Signing> EcdsaSigner for Secp256k1<C> {
+    fn sign_low_s(&self, msg: &Message, secret_key: &SecretKey) -> Signature {
+        let mut sig = self.sign_ecdsa(msg, secret_key);
+        sig.normalize_s();
+        sig
+    }
+}
+
+/// Sign with the nonces of the engine's generator, e.g. [`CryptoContext::ecdsa`].
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+impl<NG: nonce::NonceGen> EcdsaSigner for ECDSA<NG> {
+    fn sign_low_s(&self, msg: &Message, secret_key: &SecretKey) -> Signature {
+        let secret_key = Scalar::from_slice(&secret_key[..])
+            .expect("SecretKey is a valid scalar")
+            .mark::<NonZero>()
+            .expect("SecretKey is never zero");
+        let message_hash: [u8; 32] = *msg.as_ref();
+        let mut sig =
+            Signature::from_compact(self.sign(&secret_key, &message_hash).to_bytes().as_ref())
+                .expect("ECDSA engine signatures are valid");
+        sig.normalize_s();
+        sig
+    }
+}
+
+/// Contexts used for secp256k1 signing and verification and ECDSA adaptor signatures.
+#[derive(Clone, Debug)]
+pub struct CryptoContext {
+    #[cfg(not(feature = "global-context"))]
+    secp: Secp256k1<All>,
+    #[cfg(feature = "experimental")]
+    adaptor: Adaptor<Transcript, NonceGen>,
+}
+
+impl CryptoContext {
+    /// Create the contexts, this is expensive and should be done once.
+    pub fn new() -> Self {
+        Self {
+            #[cfg(not(feature = "global-context"))]
+            secp: Secp256k1::new(),
+            #[cfg(feature = "experimental")]
+            adaptor: Adaptor::default(),
+        }
+    }
+
+    /// Create the contexts with the nonces used by the ECDSA and adaptor signature engines, see
+    /// [`CryptoContext::new`].
+    #[cfg(feature = "experimental")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+    pub fn with_nonce_provider(nonce_provider: NonceProvider) -> Self {
+        Self {
+            #[cfg(not(feature = "global-context"))]
+            secp: Secp256k1::new(),
+            adaptor: Adaptor::new(nonce_provider),
+        }
+    }
+
+    /// The secp256k1 context for signing and verification.
+    pub fn secp(&self) -> &Secp256k1<All> {
+        #[cfg(feature = "global-context")]
+        return bitcoin::secp256k1::SECP256K1;
+        #[cfg(not(feature = "global-context"))]
+        return &self.secp;
+    }
+
+    /// The ECDSA adaptor signature engine.
+    #[cfg(feature = "experimental")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+    pub fn adaptor(&self) -> &Adaptor<Transcript, NonceGen> {
+        &self.adaptor
+    }
+
+    /// The ECDSA signature engine.
+    #[cfg(feature = "experimental")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+    pub fn ecdsa(&self) -> &ECDSA<NonceGen> {
+        &self.adaptor.ecdsa
+    }
+}
+
+impl Default for CryptoContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -16,25 +16,46 @@
 
 //! Discrete Logarithm Equality Proof system across the different groups secp256k1 and curve25519.
 
-use std::convert::TryInto;
 use std::fmt;
 
 use crate::{
     consensus::{self, deserialize, serialize, CanonicalBytes, Decodable, Encodable},
     crypto,
+    primitives::dleq::{bit_indexes, ring_hash, verify_proof, G_p, H_p, RingSignature},
 };
 use amplify::num::u256;
 
-use bitcoin::hashes::Hash;
-
 use bitvec::{order::Lsb0, prelude::BitSlice};
 use curve25519_dalek::{
     constants::ED25519_BASEPOINT_POINT as G, edwards::CompressedEdwardsY as ed25519PointCompressed,
     edwards::EdwardsPoint as ed25519Point, scalar::Scalar as ed25519Scalar,
 };
+use rand::{CryptoRng, RngCore};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 const ENTROPY: bool = true;
 
+/// Draw a blinder or a nonce on curve25519 from the proof's randomness source.
+fn random_ed25519<R: RngCore + CryptoRng>(rng: &mut R) -> ed25519Scalar {
+    match ENTROPY {
+        true => {
+            let mut bytes = [0u8; 64];
+            rng.fill_bytes(&mut bytes);
+            ed25519Scalar::from_bytes_mod_order_wide(&bytes)
+        }
+        false => ed25519Scalar::zero(),
+    }
+}
+
+/// Draw a blinder or a nonce on secp256k1 from the proof's randomness source.
+fn random_secp256k1<R: RngCore + CryptoRng>(rng: &mut R) -> secp256k1Scalar {
+    match ENTROPY {
+        true => secp256k1Scalar::random(rng),
+        false => secp256k1Scalar::one(),
+    }
+}
+
 fn _max_ed25519() -> u256 {
     (u256::from(1u32) << 252) + 27742317777372353535851937790883648493u128
 }
@@ -45,19 +66,6 @@ fn reverse_endianness(bytes: &[u8; 32]) -> [u8; 32] {
     bytes_rev
 }
 
-// matches https://github.com/monero-project/monero/blob/9414194b1e47730843e4dbbd4214bf72d3540cf9/src/ringct/rctTypes.h#L454
-// i.e. hash-to-curve of G as in https://github.com/monero-project/mininero/blob/master/mininero.py#L305-L323
-// TODO: this is disgusting and must be removed asap
-#[allow(non_snake_case)]
-fn G_p() -> ed25519Point {
-    let hash_G = monero::cryptonote::hash::keccak_256(G.compress().as_bytes());
-
-    ed25519PointCompressed::from_slice(&hash_G)
-        .decompress()
-        .unwrap()
-        .mul_by_cofactor() // should be in basepoint's subgroup, i.e. 8 * toPoint(hash_G)
-}
-
 #[cfg(feature = "experimental")]
 use ecdsa_fun::fun::{Point as secp256k1Point, Scalar as secp256k1Scalar, G as H};
 #[cfg(feature = "experimental")]
@@ -83,56 +91,12 @@ fn _max_secp256k1() -> u256 {
     n
 }
 
-// Hash to curve of the generator G as explained over here:
-// https://crypto.stackexchange.com/a/25603
-// Matches the result here:
-// https://github.com/mimblewimble/rust-secp256k1-zkp/blob/caa49992ae67f131157f6341f4e8b0b0c1e53055/src/constants.rs#L79-L136
-// TODO: this is disgusting and must be removed asap (i.e. change to constant)
-#[allow(non_snake_case)]
-fn H_p() -> secp256k1Point {
-    let hash_H: [u8; 32] =
-        bitcoin::hashes::sha256::Hash::hash(&H.to_bytes_uncompressed()).into_inner();
-    let even_y_prepend_hash_H: [u8; 33] = [2u8]
-        .iter()
-        .chain(hash_H.iter())
-        .cloned()
-        .collect::<Vec<u8>>()
-        .try_into()
-        .unwrap();
-    secp256k1Point::from_bytes(even_y_prepend_hash_H).expect("Alternate basepoint is invalid")
-    // secp256k1Point::from_bytes([2, 80, 146, 155, 116, 193, 160, 73, 84, 183, 139, 75, 96, 53, 233, 122, 94, 7, 138, 90, 15, 40, 236, 150, 213, 71, 191, 238, 154, 206, 128, 58, 192])
-    // .expect("Alternate basepoint is invalid")
-}
-
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 struct PedersenCommitment<Point, Scalar> {
     commitment: Point,
     blinder: Scalar,
 }
 
-impl From<(bool, usize)> for PedersenCommitment<ed25519Point, ed25519Scalar> {
-    fn from((bit, index): (bool, usize)) -> PedersenCommitment<ed25519Point, ed25519Scalar> {
-        // let mut csprng = rand_alt::rngs::OsRng;
-        // let blinder = ed25519Scalar::random(&mut csprng);
-        let blinder = match ENTROPY {
-            true => ed25519Scalar::random(&mut rand_alt::rngs::OsRng),
-            false => ed25519Scalar::zero(),
-        };
-
-        let order = u256::from(1u32) << index;
-
-        let commitment = match bit {
-            false => blinder * G_p(),
-            true => G * ed25519Scalar::from_bits(order.to_le_bytes()) + blinder * G_p(),
-        };
-
-        PedersenCommitment {
-            commitment,
-            blinder,
-        }
-    }
-}
-
 impl From<(bool, usize, ed25519Scalar)> for PedersenCommitment<ed25519Point, ed25519Scalar> {
     fn from(
         (bit, index, blinder): (bool, usize, ed25519Scalar),
@@ -151,37 +115,6 @@ impl From<(bool, usize, ed25519Scalar)> for PedersenCommitment<ed25519Point, ed2
     }
 }
 
-impl From<(bool, usize)> for PedersenCommitment<secp256k1Point, secp256k1Scalar> {
-    fn from((bit, index): (bool, usize)) -> PedersenCommitment<secp256k1Point, secp256k1Scalar> {
-        // let blinder = secp256k1Scalar::random(&mut rand::thread_rng());
-        let blinder = match ENTROPY {
-            true => secp256k1Scalar::random(&mut rand::thread_rng()),
-            false => secp256k1Scalar::one(),
-        };
-
-        let order = u256::from(1u32) << index;
-
-        let order_on_curve = secp256k1Scalar::from_bytes(order.to_be_bytes())
-            .expect("integer greater than curve order");
-        #[allow(non_snake_case)]
-        let H_p = H_p();
-        let blinder_point = g!(blinder * H_p).mark::<NonZero>().unwrap();
-
-        let commitment = match bit {
-            true => g!(order_on_curve * H + blinder_point)
-                .mark::<NonZero>()
-                .unwrap(),
-            false => blinder_point,
-        }
-        .mark::<Normal>();
-
-        PedersenCommitment {
-            commitment,
-            blinder,
-        }
-    }
-}
-
 impl From<(bool, usize, secp256k1Scalar)> for PedersenCommitment<secp256k1Point, secp256k1Scalar> {
     fn from(
         (bit, index, blinder): (bool, usize, secp256k1Scalar),
@@ -210,16 +143,18 @@ impl From<(bool, usize, secp256k1Scalar)> for PedersenCommitment<secp256k1Point,
     }
 }
 
-fn key_commitment(
+fn key_commitment<R: RngCore + CryptoRng>(
     x_bits: &BitSlice<u8, Lsb0>,
     msb_index: usize,
+    rng: &mut R,
 ) -> Vec<PedersenCommitment<ed25519Point, ed25519Scalar>> {
-    let mut commitment: Vec<PedersenCommitment<ed25519Point, ed25519Scalar>> = x_bits
-        .iter()
-        .take(msb_index)
-        .enumerate()
-        .map(|(index, bit)| (*bit, index).into())
-        .collect();
+    let bits: Vec<bool> = x_bits.iter().by_vals().take(msb_index).collect();
+    // blinders are drawn in order so the proof only depends on the randomness source
+    let blinders: Vec<ed25519Scalar> = (0..msb_index).map(|_| random_ed25519(rng)).collect();
+    let mut commitment: Vec<PedersenCommitment<ed25519Point, ed25519Scalar>> =
+        bit_indexes(msb_index)
+            .map(|index| (bits[index], index, blinders[index]).into())
+            .collect();
     let commitment_last = x_bits.get(msb_index).unwrap();
     let _commitment_last_value = match *commitment_last {
         true => ed25519Scalar::one(),
@@ -232,16 +167,17 @@ fn key_commitment(
     commitment
 }
 
-fn key_commitment_secp256k1(
+fn key_commitment_secp256k1<R: RngCore + CryptoRng>(
     x_bits: &BitSlice<u8, Lsb0>,
     msb_index: usize,
+    rng: &mut R,
 ) -> Vec<PedersenCommitment<secp256k1Point, secp256k1Scalar>> {
-    let mut commitment: Vec<PedersenCommitment<secp256k1Point, secp256k1Scalar>> = x_bits
-        .iter()
-        .take(msb_index)
-        .enumerate()
-        .map(|(index, bit)| (*bit, index).into())
-        .collect();
+    let bits: Vec<bool> = x_bits.iter().by_vals().take(msb_index).collect();
+    let blinders: Vec<secp256k1Scalar> = (0..msb_index).map(|_| random_secp256k1(rng)).collect();
+    let mut commitment: Vec<PedersenCommitment<secp256k1Point, secp256k1Scalar>> =
+        bit_indexes(msb_index)
+            .map(|index| (bits[index], index, blinders[index].clone()).into())
+            .collect();
     let commitment_last = x_bits.get(msb_index).unwrap();
     let blinder_last = commitment
         .iter()
@@ -257,82 +193,25 @@ fn key_commitment_secp256k1(
     commitment
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-struct RingSignature<ScalarCurveA, ScalarCurveB> {
-    e_g_0_i: ScalarCurveA,
-    e_h_0_i: ScalarCurveB,
-    a_0_i: ScalarCurveA,
-    b_0_i: ScalarCurveB,
-    a_1_i: ScalarCurveA,
-    b_1_i: ScalarCurveB,
+/// The random scalars of a ring signature: the nonces `j_i` and `k_i` of the known branch and
+/// the responses `a_sim_i` and `b_sim_i` of the simulated branch.
+#[derive(Clone, Debug)]
+struct RingNonces {
+    j_i: ed25519Scalar,
+    k_i: secp256k1Scalar,
+    a_sim_i: ed25519Scalar,
+    b_sim_i: secp256k1Scalar,
 }
 
-fn ring_hash(term0: [u8; 32], term1: [u8; 33], term2: [u8; 32], term3: [u8; 33]) -> [u8; 32] {
-    let preimage: [u8; 130] = term0
-        .iter()
-        .chain(term1.iter())
-        .chain(term2.iter())
-        .chain(term3.iter())
-        .cloned()
-        .collect::<Vec<u8>>()
-        .try_into()
-        .unwrap();
-    bitcoin::hashes::sha256::Hash::hash(&preimage).into_inner()
-}
-
-fn verify_ring_sig(
-    index: usize,
-    c_g_i: ed25519Point,
-    c_h_i: secp256k1Point,
-    ring_sig: RingSignature<ed25519Scalar, secp256k1Scalar>,
-) -> bool {
-    let term0: [u8; 32] = *c_g_i.compress().as_bytes();
-    let term1: [u8; 33] = c_h_i.to_bytes();
-
-    let order = u256::from(1u32) << index;
-    let order_on_secp256k1 =
-        secp256k1Scalar::from_bytes(order.to_be_bytes()).expect("integer greater than curve order");
-    #[allow(non_snake_case)]
-    let H_p = H_p();
-
-    // compute e_1_i
-    let e_1_i = {
-        let term2: [u8; 32] = *(ring_sig.a_1_i * G_p() - ring_sig.e_g_0_i * c_g_i)
-            .compress()
-            .as_bytes();
-
-        let term3: [u8; 33] = g!(ring_sig.b_1_i * H_p - ring_sig.e_h_0_i * c_h_i)
-            .mark::<Normal>()
-            .mark::<NonZero>()
-            .expect("is zero")
-            .to_bytes();
-
-        ring_hash(term0, term1, term2, term3)
-    };
-    let e_g_1_i = ed25519Scalar::from_bytes_mod_order(e_1_i);
-    let e_h_1_i = secp256k1Scalar::from_bytes_mod_order(e_1_i);
-
-    // compute e_0_i
-    let e_0_i = {
-        let term2: [u8; 32] = *(ring_sig.a_0_i * G_p()
-            - e_g_1_i * (c_g_i - ed25519Scalar::from_bytes_mod_order(order.to_le_bytes()) * G))
-            .compress()
-            .as_bytes();
-
-        let term3: [u8; 33] = g!(ring_sig.b_0_i * H_p - e_h_1_i * (c_h_i - order_on_secp256k1 * H))
-            .mark::<Normal>()
-            .mark::<NonZero>()
-            .expect("is zero")
-            .to_bytes();
-
-        ring_hash(term0, term1, term2, term3)
-    };
-
-    let e_g_0_i = ed25519Scalar::from_bytes_mod_order(e_0_i);
-    let e_h_0_i = secp256k1Scalar::from_bytes_mod_order(e_0_i);
-
-    // compare computed results with provided values
-    (e_g_0_i == ring_sig.e_g_0_i) && (e_h_0_i == ring_sig.e_h_0_i)
+impl RingNonces {
+    fn random<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        Self {
+            j_i: random_ed25519(rng),
+            k_i: random_secp256k1(rng),
+            a_sim_i: random_ed25519(rng),
+            b_sim_i: random_secp256k1(rng),
+        }
+    }
 }
 
 impl
@@ -341,14 +220,16 @@ impl
         bool,
         PedersenCommitment<ed25519Point, ed25519Scalar>,
         PedersenCommitment<secp256k1Point, secp256k1Scalar>,
+        RingNonces,
     )> for RingSignature<ed25519Scalar, secp256k1Scalar>
 {
     fn from(
-        (index, b_i, c_g_i, c_h_i): (
+        (index, b_i, c_g_i, c_h_i, nonces): (
             usize,
             bool,
             PedersenCommitment<ed25519Point, ed25519Scalar>,
             PedersenCommitment<secp256k1Point, secp256k1Scalar>,
+            RingNonces,
         ),
     ) -> Self {
         // first confirm that the pedersen commitments are correctly calculated
@@ -365,16 +246,12 @@ impl
         let term0: [u8; 32] = *c_g_i.commitment.compress().as_bytes();
         let term1: [u8; 33] = c_h_i.commitment.to_bytes();
 
-        // let j_i = ed25519Scalar::random(&mut csprng);
-        // let k_i = secp256k1Scalar::random(&mut rand::thread_rng());
-        let j_i = match ENTROPY {
-            true => ed25519Scalar::random(&mut rand_alt::rngs::OsRng),
-            false => ed25519Scalar::zero(),
-        };
-        let k_i = match ENTROPY {
-            true => secp256k1Scalar::random(&mut rand::thread_rng()),
-            false => secp256k1Scalar::one(),
-        };
+        let RingNonces {
+            j_i,
+            k_i,
+            a_sim_i,
+            b_sim_i,
+        } = nonces;
 
         #[allow(non_snake_case)]
         let H_p = H_p();
@@ -391,16 +268,8 @@ impl
                 .mark::<NonZero>()
                 .expect("is zero");
 
-            // let a_1_i = ed25519Scalar::random(&mut csprng);
-            // let b_1_i = secp256k1Scalar::random(&mut rand::thread_rng());
-            let a_1_i = match ENTROPY {
-                true => ed25519Scalar::random(&mut rand_alt::rngs::OsRng),
-                false => ed25519Scalar::zero(),
-            };
-            let b_1_i = match ENTROPY {
-                true => secp256k1Scalar::random(&mut rand::thread_rng()),
-                false => secp256k1Scalar::one(),
-            };
+            let a_1_i = a_sim_i;
+            let b_1_i = b_sim_i;
 
             let term2 = *(a_1_i * G_p() - e_g_0_i * c_g_i.commitment)
                 .compress()
@@ -455,16 +324,8 @@ impl
             let e_g_1_i = e_g_0_i;
             let e_h_1_i = secp256k1Scalar::from_bytes_mod_order(e_1_i);
 
-            // let a_0_i = ed25519Scalar::random(&mut csprng);
-            // let b_0_i = secp256k1Scalar::random(&mut rand::thread_rng());
-            let a_0_i = match ENTROPY {
-                true => ed25519Scalar::random(&mut rand_alt::rngs::OsRng),
-                false => ed25519Scalar::zero(),
-            };
-            let b_0_i = match ENTROPY {
-                true => secp256k1Scalar::random(&mut rand::thread_rng()),
-                false => secp256k1Scalar::one(),
-            };
+            let a_0_i = a_sim_i;
+            let b_0_i = b_sim_i;
 
             let order = u256::from(1u32) << index;
             let order_on_secp256k1 = secp256k1Scalar::from_bytes(order.to_be_bytes())
@@ -737,7 +598,14 @@ fn _zeroize_highest_bits(x: [u8; 32], highest_bit: usize) -> [u8; 32] {
 }
 
 impl DLEQProof {
+    #[cfg(test)]
     pub(crate) fn generate(x: [u8; 32]) -> Self {
+        Self::generate_with(x, &mut rand::thread_rng())
+    }
+
+    /// Generate the proof of `x`, drawing the blinders and the ring signature nonces from `rng`.
+    /// The same secret and the same randomness source produce the same proof.
+    pub(crate) fn generate_with<R: RngCore + CryptoRng>(x: [u8; 32], rng: &mut R) -> Self {
         // convention: start count at 0
         let msb_index = 251;
 
@@ -757,17 +625,25 @@ impl DLEQProof {
         #[allow(non_snake_case)]
         let xH_p = g!(x_secp256k1 * H).mark::<Normal>();
 
-        let c_g = key_commitment(x_bits, msb_index);
-        let c_h = key_commitment_secp256k1(x_bits, msb_index);
+        let c_g = key_commitment(x_bits, msb_index, rng);
+        let c_h = key_commitment_secp256k1(x_bits, msb_index, rng);
 
-        let ring_signatures: Vec<RingSignature<ed25519Scalar, secp256k1Scalar>> = x_bits
-            .iter()
-            .take(msb_index + 1)
-            .enumerate()
-            .zip(c_g.clone())
-            .zip(c_h.clone())
-            .map(|(((index, b_i), c_g_i), c_h_i)| RingSignature::from((index, *b_i, c_g_i, c_h_i)))
+        let bits: Vec<bool> = x_bits.iter().by_vals().take(msb_index + 1).collect();
+        let nonces: Vec<RingNonces> = (0..msb_index + 1)
+            .map(|_| RingNonces::random(rng))
             .collect();
+        let ring_signatures: Vec<RingSignature<ed25519Scalar, secp256k1Scalar>> =
+            bit_indexes(msb_index + 1)
+                .map(|index| {
+                    RingSignature::from((
+                        index,
+                        bits[index],
+                        c_g[index],
+                        c_h[index].clone(),
+                        nonces[index].clone(),
+                    ))
+                })
+                .collect();
 
         let c_g: Vec<ed25519Point> = c_g.iter().map(|pc| pc.commitment).collect();
         let c_h: Vec<secp256k1Point> = c_h.iter().map(|pc| pc.commitment).collect();
@@ -798,13 +674,11 @@ impl DLEQProof {
 
         let pok_0 = (alpha_G, response);
 
-        // Proof of Knowledge secp256k1 (ECDSA)
-        let nonce_gen = ecdsa_fun::nonce::Synthetic::<
-            sha2::Sha256,
-            ecdsa_fun::nonce::GlobalRng<rand::rngs::ThreadRng>,
-        >::default();
+        // Proof of Knowledge secp256k1 (ECDSA), the nonce is derived from the key and the
+        // commitments, which already carry the randomness of the blinders
+        let nonce_gen = ecdsa_fun::nonce::Deterministic::<sha2::Sha256>::default();
         let pok_1_message = serialize(&c_h);
-        let pok_1_message_hash: [u8; 32] = sha2::Sha256::digest(&pok_1_message).try_into().unwrap();
+        let pok_1_message_hash: [u8; 32] = sha2::Sha256::digest(&pok_1_message).into();
         let ecdsa = ecdsa_fun::ECDSA::new(nonce_gen);
 
         let pok_1 = ecdsa.sign(&x_secp256k1, &pok_1_message_hash);
@@ -825,76 +699,24 @@ impl DLEQProof {
         #[allow(non_snake_case)] xG_p: ed25519Point,
         #[allow(non_snake_case)] xH_p: secp256k1Point,
     ) -> Result<(), crypto::Error> {
-        assert_eq!(252, self.c_g.len());
-        assert_eq!(252, self.c_h.len());
-        assert_eq!(252, self.ring_signatures.len());
-
-        // Commitments
-        let commitment_agg_ed25519 = self.c_g.iter().sum();
-
-        if xG_p != commitment_agg_ed25519 {
-            return Err(crypto::Error::InvalidPedersenCommitment);
-        }
-
-        let commitment_agg_secp256k1 = self
-            .c_h
-            .iter()
-            .fold(secp256k1Point::zero(), |acc, bit_commitment| {
-                g!(acc + bit_commitment).mark::<Normal>()
-            });
-
-        if !(xH_p == commitment_agg_secp256k1) {
-            return Err(crypto::Error::InvalidPedersenCommitment);
-        }
-
-        // Ring signatures
-        let valid_ring_signatures = self
-            .c_g
-            .clone()
-            .iter()
-            .enumerate()
-            .zip(self.c_h.clone())
-            .zip(self.ring_signatures.clone())
-            .all(|(((index, c_g_i), c_h_i), ring_sig)| {
-                verify_ring_sig(index, *c_g_i, c_h_i, ring_sig)
-            });
-
-        if !(valid_ring_signatures) {
-            return Err(crypto::Error::InvalidRingSignature);
-        }
-
-        // Proof of Knowledge
-        // ed25519 (edDSA)
-        #[allow(non_snake_case)]
-        let (alpha_G, r) = self.pok_0;
-        let mut challenge_preimage = vec![];
-        challenge_preimage.extend_from_slice(alpha_G.compress().as_bytes());
-        challenge_preimage.extend_from_slice(xG_p.compress().as_bytes());
-        challenge_preimage.extend_from_slice(serialize(&self.c_g).as_slice());
-        let challenge = monero::cryptonote::hash::keccak_256(&challenge_preimage);
-
-        if r * G != alpha_G + ed25519Scalar::from_bytes_mod_order(challenge) * xG_p {
-            return Err(crypto::Error::InvalidProofOfKnowledge);
-        }
-
-        // secp256k1 (ECDSA)
-        let ecdsa = ecdsa_fun::ECDSA::verify_only();
-        let pok_1_message = serialize(&self.c_h);
-        let pok_1_message_hash: [u8; 32] = sha2::Sha256::digest(pok_1_message.as_slice())
-            .try_into()
-            .unwrap();
-        if !ecdsa_fun::ECDSA::verify(&ecdsa, &xH_p, &pok_1_message_hash, &self.pok_1) {
-            return Err(crypto::Error::InvalidProofOfKnowledge);
-        }
-
-        // Everything ok
-        Ok(())
+        verify_proof(
+            &self.c_g,
+            &self.c_h,
+            &self.ring_signatures,
+            &self.pok_0,
+            &self.pok_1,
+            &xG_p,
+            &xH_p,
+        )
+        .map_err(crypto::Error::from)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::primitives;
+    use rand_chacha::ChaCha20Rng;
 
     #[test]
     fn pedersen_commitment_works() {
@@ -903,7 +725,7 @@ mod tests {
         // ensure 256th bit is 0
         x[31] &= 0b0111_1111;
         let x_bits = BitSlice::<u8, Lsb0>::from_slice(&x);
-        let key_commitment = key_commitment(x_bits, 255);
+        let key_commitment = key_commitment(x_bits, 255, &mut rand::thread_rng());
         let commitment_acc = key_commitment.iter().map(|pc| pc.commitment).sum();
         assert_eq!(ed25519Scalar::from_bytes_mod_order(x) * G, commitment_acc);
     }
@@ -916,7 +738,7 @@ mod tests {
         // ensure 256th bit is 0
         // x[31] &= 0b0111_1111;
         let x_bits = BitSlice::<u8, Lsb0>::from_slice(&x);
-        let key_commitment = key_commitment_secp256k1(x_bits, 255);
+        let key_commitment = key_commitment_secp256k1(x_bits, 255, &mut rand::thread_rng());
         // let commitment_acc: secp256k1Point<Jacobian, Public, Zero> = key_commitment
         let commitment_acc = key_commitment.iter().fold(
             secp256k1Point::zero(),
@@ -946,12 +768,49 @@ mod tests {
         );
     }
 
+    #[test]
+    #[allow(non_snake_case)]
+    fn dleq_proof_verifies_from_canonical_bytes() {
+        use rand::Rng;
+        let x: [u8; 32] = rand::thread_rng().gen();
+        let x_shaved = _zeroize_highest_bits(x, 252);
+        let dleq = DLEQProof::generate(x_shaved);
+
+        let xG_p = ed25519Scalar::from_bytes_mod_order(x_shaved) * G;
+        let xH_p_secp256k1 = secp256k1Scalar::from_bytes_mod_order(reverse_endianness(&x_shaved))
+            .mark::<NonZero>()
+            .unwrap();
+        let xH_p = g!(xH_p_secp256k1 * H).mark::<Normal>();
+        let mut bytes = dleq.as_canonical_bytes();
+        assert!(
+            primitives::dleq::verify(&bytes, xG_p.compress().as_bytes(), &xH_p.to_bytes()).is_ok()
+        );
+
+        // Tamper with a ring signature scalar
+        let ring_signature_offset = 2 + 32 * 252 + 2 + 33 * 252 + 2;
+        bytes[ring_signature_offset] ^= 1;
+        assert_eq!(
+            primitives::dleq::verify(&bytes, xG_p.compress().as_bytes(), &xH_p.to_bytes()),
+            Err(primitives::dleq::Error::InvalidRingSignature)
+        );
+        assert_eq!(
+            primitives::dleq::verify(
+                &bytes[..bytes.len() - 1],
+                xG_p.compress().as_bytes(),
+                &xH_p.to_bytes()
+            ),
+            Err(primitives::dleq::Error::Encoding(
+                primitives::encoding::Error::UnexpectedEnd
+            ))
+        );
+    }
+
     #[test]
     fn blinders_sum_to_zero() {
         use rand::Rng;
         let x: [u8; 32] = rand::thread_rng().gen();
         let x_bits = BitSlice::<u8, Lsb0>::from_slice(&x);
-        let key_commitment = key_commitment(x_bits, 255);
+        let key_commitment = key_commitment(x_bits, 255, &mut rand::thread_rng());
         let blinder_acc = key_commitment
             .iter()
             .fold(ed25519Scalar::zero(), |acc, bit_commitment| {
@@ -966,6 +825,23 @@ mod tests {
         assert_eq!(G_p(), monero::util::key::H.point.decompress().unwrap())
     }
 
+    #[test]
+    fn dleq_proof_is_reproducible_from_its_randomness_source() {
+        use rand::{Rng, SeedableRng};
+        let x: [u8; 32] = rand::thread_rng().gen();
+        let x_shaved = _zeroize_highest_bits(x, 252);
+        let seed = [7u8; 32];
+        let dleq = DLEQProof::generate_with(x_shaved, &mut ChaCha20Rng::from_seed(seed));
+        assert_eq!(
+            DLEQProof::generate_with(x_shaved, &mut ChaCha20Rng::from_seed(seed)),
+            dleq
+        );
+        assert_ne!(
+            DLEQProof::generate_with(x_shaved, &mut ChaCha20Rng::from_seed([8u8; 32])),
+            dleq
+        );
+    }
+
     #[test]
     fn canonical_encoding_decoding_idempotent() {
         use rand::Rng;
@@ -0,0 +1,189 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Authenticated symmetric encryption of opaque blobs. Used to protect swap data handed over to
+//! third parties or stored at rest.
+//!
+//! Blobs are sealed with XChaCha20-Poly1305, with a random 24 bytes nonce per blob so a key can
+//! safely seal many blobs. The version byte, and optionally caller provided data such as a
+//! header, is passed as associated data and is authenticated with the ciphertext. A sealed blob
+//! is serialized as:
+//!
+//! ```text
+//! version (1 byte) || nonce (24 bytes) || ciphertext || tag (16 bytes)
+//! ```
+
+use bitcoin::hashes::{sha256, Hash, HashEngine, Hmac, HmacEngine};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::{CryptoRng, RngCore};
+
+use crate::crypto::Error;
+
+/// Version of the sealed blob format.
+pub const SEALED_BLOB_VERSION: u8 = 1;
+
+/// Length of the random nonce used for each sealed blob.
+pub const NONCE_LEN: usize = 24;
+
+/// Length of the authentication tag appended to each sealed blob.
+pub const TAG_LEN: usize = 16;
+
+/// A 32 bytes symmetric key used to seal and open blobs.
+pub type SealingKey = [u8; 32];
+
+/// Derive the sealing key dedicated to `purpose` from a secret, e.g. the wallet seed, so the
+/// blobs of different purposes are sealed with independent keys.
+pub fn derive_sealing_key(secret: &[u8; 32], purpose: &[u8]) -> SealingKey {
+    let mut engine: HmacEngine<sha256::Hash> = HmacEngine::new(secret);
+    engine.input(b"farcaster-sealing-key");
+    engine.input(purpose);
+    Hmac::<sha256::Hash>::from_engine(engine).into_inner()
+}
+
+/// Encrypt and authenticate `plaintext` with `key`, a fresh nonce is drawn from `rng`.
+pub fn seal<R: RngCore + CryptoRng>(rng: &mut R, key: &SealingKey, plaintext: &[u8]) -> Vec<u8> {
+    seal_with_associated_data(rng, key, &[], plaintext)
+}
+
+/// Authenticate and decrypt a blob sealed with [`seal`]. Fails with
+/// [`Error::InvalidCiphertext`] if the blob is malformed, of an unknown version, or if it was
+/// not sealed with `key`.
+pub fn open(key: &SealingKey, blob: &[u8]) -> Result<Vec<u8>, Error> {
+    open_with_associated_data(key, &[], blob)
+}
+
+/// Encrypt and authenticate `plaintext` with `key`, and authenticate the `associated_data`
+/// without encrypting nor including it in the blob, e.g. a header stored in front of the blob.
+pub fn seal_with_associated_data<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    key: &SealingKey,
+    associated_data: &[u8],
+    plaintext: &[u8],
+) -> Vec<u8> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill_bytes(&mut nonce);
+
+    let ciphertext = XChaCha20Poly1305::new(key.into())
+        .encrypt(
+            &XNonce::from(nonce),
+            Payload {
+                msg: plaintext,
+                aad: &aad(associated_data),
+            },
+        )
+        .expect("plaintext length is bounded");
+
+    let mut blob = Vec::with_capacity(1 + NONCE_LEN + ciphertext.len());
+    blob.push(SEALED_BLOB_VERSION);
+    blob.extend_from_slice(&nonce);
+    blob.extend(ciphertext);
+    blob
+}
+
+/// Authenticate and decrypt a blob sealed with [`seal_with_associated_data`], the same
+/// `associated_data` must be provided.
+pub fn open_with_associated_data(
+    key: &SealingKey,
+    associated_data: &[u8],
+    blob: &[u8],
+) -> Result<Vec<u8>, Error> {
+    if blob.len() < 1 + NONCE_LEN + TAG_LEN || blob[0] != SEALED_BLOB_VERSION {
+        return Err(Error::InvalidCiphertext);
+    }
+    let (nonce, ciphertext) = blob[1..].split_at(NONCE_LEN);
+    let nonce: [u8; NONCE_LEN] = nonce.try_into().expect("nonce has the right length");
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(
+            &XNonce::from(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &aad(associated_data),
+            },
+        )
+        .map_err(|_| Error::InvalidCiphertext)
+}
+
+// The version byte is always authenticated along the caller's associated data
+fn aad(associated_data: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(1 + associated_data.len());
+    aad.push(SEALED_BLOB_VERSION);
+    aad.extend_from_slice(associated_data);
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open_blob() {
+        let key = [7u8; 32];
+        let blob = seal(&mut rand::thread_rng(), &key, b"swap secrets");
+        assert_eq!(blob.len(), 1 + NONCE_LEN + 12 + TAG_LEN);
+        assert_eq!(open(&key, &blob).unwrap(), b"swap secrets".to_vec());
+
+        // An other nonce gives a different blob
+        assert_ne!(seal(&mut rand::thread_rng(), &key, b"swap secrets"), blob);
+    }
+
+    #[test]
+    fn reject_tampered_blob() {
+        let key = [7u8; 32];
+        let mut blob = seal(&mut rand::thread_rng(), &key, b"swap secrets");
+        assert!(open(&[8u8; 32], &blob).is_err());
+        blob[1 + NONCE_LEN] ^= 1;
+        assert!(open(&key, &blob).is_err());
+        assert!(open(&key, &blob[..TAG_LEN]).is_err());
+    }
+
+    #[test]
+    fn reject_unknown_version() {
+        let key = [7u8; 32];
+        let mut blob = seal(&mut rand::thread_rng(), &key, b"swap secrets");
+        assert_eq!(blob[0], 1);
+        for version in [0, SEALED_BLOB_VERSION + 1] {
+            blob[0] = version;
+            assert!(open(&key, &blob).is_err());
+        }
+    }
+
+    #[test]
+    fn authenticate_associated_data() {
+        let key = [7u8; 32];
+        let blob = seal_with_associated_data(&mut rand::thread_rng(), &key, b"header", b"secrets");
+        assert_eq!(
+            open_with_associated_data(&key, b"header", &blob).unwrap(),
+            b"secrets".to_vec()
+        );
+        assert!(open_with_associated_data(&key, b"headex", &blob).is_err());
+        assert!(open(&key, &blob).is_err());
+    }
+
+    #[test]
+    fn derive_independent_keys() {
+        let seed = [7u8; 32];
+        assert_eq!(
+            derive_sealing_key(&seed, b"checkpoint"),
+            derive_sealing_key(&seed, b"checkpoint")
+        );
+        assert_ne!(
+            derive_sealing_key(&seed, b"checkpoint"),
+            derive_sealing_key(&seed, b"watchtower")
+        );
+        assert_ne!(derive_sealing_key(&seed, b"checkpoint"), seed);
+    }
+}
@@ -0,0 +1,531 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! MuSig2 multi-signatures following
+//! [BIP-327](https://github.com/bitcoin/bips/blob/master/bip-0327.mediawiki). Used to aggregate
+//! the participants keys into a single Taproot output key so the lock output can be spent
+//! through the key path with one BIP-340 signature.
+//!
+//! A signing round goes as follow:
+//!
+//! 1. each participant creates a [`KeyAggContext`] from all the public keys, optionally tweaked
+//!    for Taproot with [`KeyAggContext::with_taproot_tweak`],
+//! 2. each participant generates a [`SecretNonce`] and sends its [`PublicNonce`] to the others,
+//! 3. once all public nonces are received a [`Session`] is created for the message,
+//! 4. each participant computes its [`PartialSignature`] with [`Session::sign`] and sends it,
+//! 5. the partial signatures are verified with [`Session::verify_partial`] and combined into the
+//!    final signature with [`Session::aggregate`].
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::XOnlyPublicKey;
+use bitcoin::secp256k1::{
+    schnorr::Signature, Message, PublicKey, Secp256k1, SecretKey, Signing, Verification,
+};
+use bitcoin::util::taproot::{TapBranchHash, TapTweakHash};
+use rand::{CryptoRng, RngCore};
+use secp256kfun::{g, marker::*, s as sc, Point, Scalar, G};
+
+use crate::consensus::{self, CanonicalBytes};
+use crate::crypto::Error;
+
+fn tagged_hash(tag: &str, chunks: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag_hash[..]);
+    engine.input(&tag_hash[..]);
+    chunks.iter().for_each(|chunk| engine.input(chunk));
+    sha256::Hash::from_engine(engine).into_inner()
+}
+
+fn to_point(key: &PublicKey) -> Point {
+    Point::from_bytes(key.serialize()).expect("a valid public key is a valid point")
+}
+
+fn to_bytes_or_zero(point: &Point<Normal, Public, Zero>) -> [u8; 33] {
+    (*point)
+        .mark::<NonZero>()
+        .map(|p| p.to_bytes())
+        .unwrap_or([0u8; 33])
+}
+
+/// The aggregation of a set of public keys into a single key, possibly tweaked for Taproot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyAggContext {
+    keys: Vec<PublicKey>,
+    coefficients: Vec<Scalar<Public, Zero>>,
+    internal_key: XOnlyPublicKey,
+    aggregated_key: Point,
+    // true if the accumulated sign of the keys is negative, `gacc` in BIP-327
+    negated: bool,
+    // the accumulated tweak, `tacc` in BIP-327
+    tweak: Scalar<Public, Zero>,
+}
+
+impl KeyAggContext {
+    /// Aggregate the public keys of the signers. The keys are sorted first so the aggregated key
+    /// does not depend on the order in which the participants provide them.
+    ///
+    /// Fails with [`Error::MissingKey`] if no key is provided and with
+    /// [`Error::InvalidAggregatedKey`] if the aggregated key is the point at infinity.
+    pub fn new(mut keys: Vec<PublicKey>) -> Result<Self, Error> {
+        if keys.is_empty() {
+            return Err(Error::MissingKey);
+        }
+        keys.sort_by_key(|key| key.serialize());
+
+        let serialized: Vec<[u8; 33]> = keys.iter().map(|key| key.serialize()).collect();
+        let list_hash = tagged_hash(
+            "KeyAgg list",
+            &serialized.iter().map(|k| &k[..]).collect::<Vec<_>>(),
+        );
+        let second_key = serialized.iter().find(|key| **key != serialized[0]);
+
+        let coefficients: Vec<Scalar<Public, Zero>> = serialized
+            .iter()
+            .map(|key| match second_key {
+                Some(second) if second == key => Scalar::one().mark::<Zero>().mark::<Public>(),
+                _ => Scalar::from_bytes_mod_order(tagged_hash(
+                    "KeyAgg coefficient",
+                    &[&list_hash, key],
+                ))
+                .mark::<Public>(),
+            })
+            .collect();
+
+        let points: Vec<Point> = keys.iter().map(to_point).collect();
+        let aggregated_key = secp256kfun::op::lincomb(&coefficients, &points)
+            .mark::<Normal>()
+            .mark::<NonZero>()
+            .ok_or(Error::InvalidAggregatedKey)?;
+
+        Ok(Self {
+            keys,
+            coefficients,
+            internal_key: XOnlyPublicKey::from_slice(aggregated_key.to_xonly().as_bytes())
+                .expect("valid x-only key"),
+            aggregated_key,
+            negated: false,
+            tweak: Scalar::zero().mark::<Public>(),
+        })
+    }
+
+    /// Tweak the aggregated key as a Taproot internal key committing to `merkle_root`, use
+    /// `None` for a key path only output. The resulting key is the Taproot output key.
+    ///
+    /// Fails with [`Error::InvalidAggregatedKey`] if the tweak is invalid.
+    pub fn with_taproot_tweak(mut self, merkle_root: Option<TapBranchHash>) -> Result<Self, Error> {
+        let tweak_hash = TapTweakHash::from_key_and_tweak(self.internal_key, merkle_root);
+        let tweak = Scalar::from_bytes(tweak_hash.into_inner())
+            .ok_or(Error::InvalidAggregatedKey)?
+            .mark::<Public>();
+
+        let is_odd = !self.aggregated_key.is_y_even();
+        let even_key = self.aggregated_key.conditional_negate(is_odd);
+        self.aggregated_key = g!(even_key + tweak * G)
+            .mark::<Normal>()
+            .mark::<NonZero>()
+            .ok_or(Error::InvalidAggregatedKey)?;
+        self.negated ^= is_odd;
+        let mut accumulated = self.tweak;
+        accumulated.conditional_negate(is_odd);
+        self.tweak = sc!(tweak + accumulated).mark::<Public>();
+        Ok(self)
+    }
+
+    /// The public keys of the signers, sorted.
+    pub fn keys(&self) -> &[PublicKey] {
+        &self.keys
+    }
+
+    /// The untweaked aggregated key, which is the Taproot internal key.
+    pub fn internal_key(&self) -> XOnlyPublicKey {
+        self.internal_key
+    }
+
+    /// The aggregated key the final signature is valid for, which is the Taproot output key
+    /// when tweaked.
+    pub fn aggregated_key(&self) -> XOnlyPublicKey {
+        XOnlyPublicKey::from_slice(self.aggregated_key.to_xonly().as_bytes())
+            .expect("valid x-only key")
+    }
+
+    fn coefficient(&self, key: &PublicKey) -> Option<&Scalar<Public, Zero>> {
+        self.keys
+            .iter()
+            .position(|k| k == key)
+            .map(|i| &self.coefficients[i])
+    }
+
+    // true if the secret keys must be negated when signing: `g * gacc` in BIP-327
+    fn needs_negation(&self) -> bool {
+        !self.aggregated_key.is_y_even() ^ self.negated
+    }
+}
+
+/// The secret nonce of a signer for one signing session. It does not implement [`Clone`] and is
+/// consumed when signing to prevent its reuse, reusing a nonce leaks the secret key.
+#[derive(Debug)]
+pub struct SecretNonce {
+    k1: Scalar,
+    k2: Scalar,
+    public: PublicNonce,
+}
+
+impl SecretNonce {
+    /// Generate a fresh secret nonce with `rng`.
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let k1 = Scalar::random(rng);
+        let k2 = Scalar::random(rng);
+        let public = PublicNonce {
+            r1: g!(k1 * G).mark::<Normal>(),
+            r2: g!(k2 * G).mark::<Normal>(),
+        };
+        Self { k1, k2, public }
+    }
+
+    /// The public nonce to send to the other signers.
+    pub fn public_nonce(&self) -> PublicNonce {
+        self.public.clone()
+    }
+}
+
+/// The public nonce of a signer, exchanged before creating the partial signatures.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PublicNonce {
+    r1: Point,
+    r2: Point,
+}
+
+impl CanonicalBytes for PublicNonce {
+    fn as_canonical_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.r1.to_bytes().to_vec();
+        bytes.extend_from_slice(&self.r2.to_bytes());
+        bytes
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, consensus::Error>
+    where
+        Self: Sized,
+    {
+        if bytes.len() != 66 {
+            return Err(consensus::Error::ParseFailed("Invalid public nonce length"));
+        }
+        match (
+            Point::from_slice(&bytes[..33]),
+            Point::from_slice(&bytes[33..]),
+        ) {
+            (Some(r1), Some(r2)) => Ok(Self { r1, r2 }),
+            _ => Err(consensus::Error::ParseFailed("Invalid public nonce point")),
+        }
+    }
+}
+
+/// The partial signature of a signer, combined with the others into the final signature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialSignature(Scalar<Public, Zero>);
+
+impl CanonicalBytes for PartialSignature {
+    fn as_canonical_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes().into()
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, consensus::Error>
+    where
+        Self: Sized,
+    {
+        Scalar::from_slice(bytes)
+            .map(|s| Self(s.mark::<Public>()))
+            .ok_or(consensus::Error::ParseFailed("Invalid partial signature"))
+    }
+}
+
+/// A signing session for one message, created once the public nonces of all the signers are
+/// known.
+#[derive(Debug, Clone)]
+pub struct Session {
+    key_agg: KeyAggContext,
+    message: [u8; 32],
+    // the nonce coefficient `b`
+    nonce_coefficient: Scalar<Public, Zero>,
+    // the final nonce, with an even y-coordinate
+    nonce: Point<EvenY>,
+    // true if the aggregated nonce had to be negated to be even
+    nonce_negated: bool,
+    // the challenge `e`
+    challenge: Scalar<Public, Zero>,
+}
+
+impl Session {
+    /// Start a new session to sign `message` with the public nonces of all the signers.
+    ///
+    /// Fails with [`Error::InvalidNonce`] if the number of nonces does not match the number of
+    /// signers.
+    pub fn new(
+        key_agg: &KeyAggContext,
+        nonces: &[PublicNonce],
+        message: &Message,
+    ) -> Result<Self, Error> {
+        if nonces.len() != key_agg.keys.len() {
+            return Err(Error::InvalidNonce);
+        }
+        let (r1, r2) = nonces
+            .iter()
+            .fold((Point::zero(), Point::zero()), |(r1, r2), nonce| {
+                (
+                    g!(r1 + { &nonce.r1 }).mark::<Normal>(),
+                    g!(r2 + { &nonce.r2 }).mark::<Normal>(),
+                )
+            });
+
+        let mut message_bytes = [0u8; 32];
+        message_bytes.copy_from_slice(&message[..]);
+        let aggregated_key = key_agg.aggregated_key.to_xonly();
+
+        let nonce_coefficient = Scalar::from_bytes_mod_order(tagged_hash(
+            "MuSig/noncecoef",
+            &[
+                &to_bytes_or_zero(&r1),
+                &to_bytes_or_zero(&r2),
+                aggregated_key.as_bytes(),
+                &message_bytes,
+            ],
+        ))
+        .mark::<Public>();
+        let nonce = g!(r1 + nonce_coefficient * r2)
+            .mark::<Normal>()
+            .mark::<NonZero>()
+            .unwrap_or_else(|| (*G).mark::<Normal>());
+        let (nonce, nonce_negated) = nonce.into_point_with_even_y();
+
+        let challenge = Scalar::from_bytes_mod_order(tagged_hash(
+            "BIP0340/challenge",
+            &[
+                nonce.to_xonly().as_bytes(),
+                aggregated_key.as_bytes(),
+                &message_bytes,
+            ],
+        ))
+        .mark::<Public>();
+
+        Ok(Self {
+            key_agg: key_agg.clone(),
+            message: message_bytes,
+            nonce_coefficient,
+            nonce,
+            nonce_negated,
+            challenge,
+        })
+    }
+
+    /// Create the partial signature of the signer owning `secret_key`, consuming its secret
+    /// nonce.
+    ///
+    /// Fails with [`Error::MissingKey`] if the key is not part of the aggregated keys.
+    pub fn sign<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        secret_nonce: SecretNonce,
+        secret_key: &SecretKey,
+    ) -> Result<PartialSignature, Error> {
+        let coefficient = self
+            .key_agg
+            .coefficient(&PublicKey::from_secret_key(secp, secret_key))
+            .ok_or(Error::MissingKey)?;
+
+        let mut d = Scalar::from_bytes(secret_key.secret_bytes())
+            .and_then(|s| s.mark::<NonZero>())
+            .ok_or(Error::MissingKey)?;
+        d.conditional_negate(self.key_agg.needs_negation());
+        let SecretNonce { mut k1, mut k2, .. } = secret_nonce;
+        k1.conditional_negate(self.nonce_negated);
+        k2.conditional_negate(self.nonce_negated);
+
+        let b = &self.nonce_coefficient;
+        let e = &self.challenge;
+        let a = coefficient;
+        Ok(PartialSignature(
+            sc!(k1 + b * k2 + e * a * d).mark::<Public>(),
+        ))
+    }
+
+    /// Verify the partial signature of the signer with `public_key` and `public_nonce`.
+    ///
+    /// Fails with [`Error::InvalidPartialSignature`] if the signature is not valid and with
+    /// [`Error::MissingKey`] if the key is not part of the aggregated keys.
+    pub fn verify_partial(
+        &self,
+        partial_sig: &PartialSignature,
+        public_nonce: &PublicNonce,
+        public_key: &PublicKey,
+    ) -> Result<(), Error> {
+        let a = self
+            .key_agg
+            .coefficient(public_key)
+            .ok_or(Error::MissingKey)?;
+        let key = to_point(public_key).conditional_negate(self.key_agg.needs_negation());
+        let (r1, r2) = (&public_nonce.r1, &public_nonce.r2);
+        let b = &self.nonce_coefficient;
+        let ea = sc!({ &self.challenge } * a);
+        let nonce = g!(r1 + b * r2)
+            .mark::<Normal>()
+            .conditional_negate(self.nonce_negated);
+        let s = &partial_sig.0;
+
+        if g!(s * G) == g!(nonce + ea * key) {
+            Ok(())
+        } else {
+            Err(Error::InvalidPartialSignature)
+        }
+    }
+
+    /// Combine the partial signatures of all the signers into a BIP-340 signature valid for
+    /// the aggregated key.
+    ///
+    /// Fails with [`Error::InvalidSignature`] if the resulting signature is not valid, i.e. if
+    /// one of the partial signatures is invalid or missing.
+    pub fn aggregate<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        partial_sigs: &[PartialSignature],
+    ) -> Result<Signature, Error> {
+        let mut tweak = self.key_agg.tweak;
+        tweak.conditional_negate(!self.key_agg.aggregated_key.is_y_even());
+        let e = &self.challenge;
+        let s = partial_sigs
+            .iter()
+            .fold(sc!(e * tweak), |acc, partial| sc!(acc + { &partial.0 }));
+
+        let mut bytes = self.nonce.to_xonly().into_bytes().to_vec();
+        bytes.extend_from_slice(&s.to_bytes());
+        let sig = Signature::from_slice(&bytes).map_err(|_| Error::InvalidSignature)?;
+
+        let message = Message::from_slice(&self.message).expect("32 bytes message");
+        secp.verify_schnorr(&sig, &message, &self.key_agg.aggregated_key())
+            .map_err(|_| Error::InvalidSignature)?;
+        Ok(sig)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> (SecretKey, SecretKey, Vec<PublicKey>) {
+        let secp = Secp256k1::new();
+        let alice =
+            SecretKey::from_slice(&Scalar::random(&mut rand::thread_rng()).to_bytes()).unwrap();
+        let bob =
+            SecretKey::from_slice(&Scalar::random(&mut rand::thread_rng()).to_bytes()).unwrap();
+        let pubkeys = vec![
+            PublicKey::from_secret_key(&secp, &alice),
+            PublicKey::from_secret_key(&secp, &bob),
+        ];
+        (alice, bob, pubkeys)
+    }
+
+    fn sign_with(
+        key_agg: &KeyAggContext,
+        alice: &SecretKey,
+        bob: &SecretKey,
+        pubkeys: &[PublicKey],
+    ) {
+        let message = Message::from_slice(&[42u8; 32]).unwrap();
+        let alice_nonce = SecretNonce::generate(&mut rand::thread_rng());
+        let bob_nonce = SecretNonce::generate(&mut rand::thread_rng());
+        let nonces = vec![alice_nonce.public_nonce(), bob_nonce.public_nonce()];
+        let session = Session::new(key_agg, &nonces, &message).unwrap();
+
+        let secp = Secp256k1::new();
+        let alice_sig = session.sign(&secp, alice_nonce, alice).unwrap();
+        let bob_sig = session.sign(&secp, bob_nonce, bob).unwrap();
+        assert!(session
+            .verify_partial(&alice_sig, &nonces[0], &pubkeys[0])
+            .is_ok());
+        assert!(session
+            .verify_partial(&bob_sig, &nonces[1], &pubkeys[1])
+            .is_ok());
+        assert!(matches!(
+            session.verify_partial(&alice_sig, &nonces[1], &pubkeys[1]),
+            Err(Error::InvalidPartialSignature)
+        ));
+
+        assert!(session
+            .aggregate(&secp, &[alice_sig.clone(), bob_sig])
+            .is_ok());
+        assert!(matches!(
+            session.aggregate(&secp, &[alice_sig]),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn aggregate_key_independent_of_order() {
+        let (_, _, mut pubkeys) = keys();
+        let key_agg = KeyAggContext::new(pubkeys.clone()).unwrap();
+        pubkeys.reverse();
+        assert_eq!(
+            KeyAggContext::new(pubkeys).unwrap().aggregated_key(),
+            key_agg.aggregated_key()
+        );
+        assert!(matches!(KeyAggContext::new(vec![]), Err(Error::MissingKey)));
+    }
+
+    #[test]
+    fn sign_with_aggregated_key() {
+        for _ in 0..8 {
+            let (alice, bob, pubkeys) = keys();
+            let key_agg = KeyAggContext::new(pubkeys.clone()).unwrap();
+            sign_with(&key_agg, &alice, &bob, &pubkeys);
+        }
+    }
+
+    #[test]
+    fn sign_with_taproot_output_key() {
+        let secp = Secp256k1::new();
+        for _ in 0..8 {
+            let (alice, bob, pubkeys) = keys();
+            let key_agg = KeyAggContext::new(pubkeys.clone())
+                .unwrap()
+                .with_taproot_tweak(None)
+                .unwrap();
+            // The output key must match the one computed by rust-bitcoin
+            let script = bitcoin::Script::new_v1_p2tr(&secp, key_agg.internal_key(), None);
+            assert_eq!(&script[2..], &key_agg.aggregated_key().serialize()[..]);
+            sign_with(&key_agg, &alice, &bob, &pubkeys);
+        }
+    }
+
+    #[test]
+    fn serialize_nonce_and_partial_signature() {
+        let nonce = SecretNonce::generate(&mut rand::thread_rng()).public_nonce();
+        let bytes = nonce.as_canonical_bytes();
+        assert_eq!(bytes.len(), 66);
+        assert_eq!(PublicNonce::from_canonical_bytes(&bytes).unwrap(), nonce);
+        assert!(PublicNonce::from_canonical_bytes(&bytes[1..]).is_err());
+
+        let partial = PartialSignature(
+            Scalar::random(&mut rand::thread_rng())
+                .mark::<Zero>()
+                .mark::<Public>(),
+        );
+        let bytes = partial.as_canonical_bytes();
+        assert_eq!(
+            PartialSignature::from_canonical_bytes(&bytes).unwrap(),
+            partial
+        );
+        assert!(PartialSignature::from_canonical_bytes(&[255u8; 32]).is_err());
+    }
+}
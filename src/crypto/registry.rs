@@ -0,0 +1,249 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Registry of the hash algorithms used for commitments, swap identifiers and tagged hashes.
+//! Each algorithm has a versioned [`AlgorithmId`] carried in the encoded messages next to the
+//! hash, e.g. in [`SessionCommitment`](crate::crypto::commitment::SessionCommitment), so a new
+//! algorithm can be introduced without breaking the encoding of existing messages: peers decode
+//! the identifier and fail with [`Error::UnknownAlgorithm`] only when they do not support it.
+//!
+//! The [`HashRegistry`] resolves identifiers to hash functions. The built-in algorithms are
+//! always available, others, e.g. BLAKE3, are plugged in with [`HashRegistry::register`].
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+use crate::consensus::{self, Decodable, Encodable};
+use crate::crypto::commitment::Error;
+use crate::primitives;
+
+/// A hash function over a domain separation tag and the data to hash.
+pub type HashFn = fn(tag: &[u8], data: &[u8]) -> [u8; 32];
+
+/// Versioned identifier of a hash algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AlgorithmId(pub u8);
+
+impl AlgorithmId {
+    /// Keccak-256 of the tag followed by the data, with an empty tag the hash used by the
+    /// [`CommitmentEngine`](crate::crypto::CommitmentEngine).
+    pub const KECCAK_256: Self = Self(primitives::commitment::KECCAK_256_ID);
+    /// Tagged SHA-256 as in BIP-340, see [`tagged_hash`](crate::crypto::commitment::tagged_hash).
+    pub const TAGGED_SHA256: Self = Self(0x02);
+
+    /// Return the hash function of a built-in algorithm.
+    pub fn builtin(&self) -> Option<HashFn> {
+        match *self {
+            Self::KECCAK_256 => Some(keccak_256),
+            Self::TAGGED_SHA256 => Some(primitives::commitment::tagged_sha256),
+            _ => None,
+        }
+    }
+}
+
+impl Default for AlgorithmId {
+    fn default() -> Self {
+        Self::TAGGED_SHA256
+    }
+}
+
+impl fmt::Display for AlgorithmId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::KECCAK_256 => write!(f, "keccak-256"),
+            Self::TAGGED_SHA256 => write!(f, "tagged-sha256"),
+            Self(id) => write!(f, "{:#04x}", id),
+        }
+    }
+}
+
+fn keccak_256(tag: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut preimage = tag.to_vec();
+    preimage.extend_from_slice(data);
+    primitives::commitment::keccak256(&preimage)
+}
+
+/// A hash with the identifier of the algorithm that produced it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest {
+    /// The algorithm used to compute the hash.
+    pub algorithm: AlgorithmId,
+    /// The hash.
+    pub hash: [u8; 32],
+}
+
+/// Resolves algorithm identifiers to hash functions, see the [module documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct HashRegistry {
+    custom: BTreeMap<AlgorithmId, HashFn>,
+}
+
+impl HashRegistry {
+    /// Create a registry with the built-in algorithms only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new algorithm. Fails with [`Error::AlgorithmAlreadyRegistered`] if the
+    /// identifier is already taken, built-in algorithms cannot be replaced.
+    pub fn register(&mut self, algorithm: AlgorithmId, hash: HashFn) -> Result<(), Error> {
+        (algorithm.builtin().is_none() && !self.custom.contains_key(&algorithm))
+            .then(|| ())
+            .ok_or(Error::AlgorithmAlreadyRegistered(algorithm))?;
+        self.custom.insert(algorithm, hash);
+        Ok(())
+    }
+
+    /// Return true if the algorithm is supported.
+    pub fn supports(&self, algorithm: AlgorithmId) -> bool {
+        self.get(algorithm).is_ok()
+    }
+
+    /// Return the hash function of an algorithm. Fails with [`Error::UnknownAlgorithm`] if the
+    /// algorithm is not supported.
+    pub fn get(&self, algorithm: AlgorithmId) -> Result<HashFn, Error> {
+        algorithm
+            .builtin()
+            .or_else(|| self.custom.get(&algorithm).copied())
+            .ok_or(Error::UnknownAlgorithm(algorithm))
+    }
+
+    /// Hash the data under the tag with an algorithm.
+    pub fn digest(&self, algorithm: AlgorithmId, tag: &[u8], data: &[u8]) -> Result<Digest, Error> {
+        Ok(Digest {
+            algorithm,
+            hash: self.get(algorithm)?(tag, data),
+        })
+    }
+
+    /// Return true if the digest is the hash of the data under the tag with the algorithm of
+    /// the digest.
+    pub fn verify(&self, digest: &Digest, tag: &[u8], data: &[u8]) -> Result<bool, Error> {
+        Ok(self.digest(digest.algorithm, tag, data)? == *digest)
+    }
+}
+
+impl Encodable for AlgorithmId {
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        self.0.consensus_encode(s)
+    }
+}
+
+impl Decodable for AlgorithmId {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self(u8::consensus_decode(d)?))
+    }
+}
+
+impl Encodable for Digest {
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let len = self.algorithm.consensus_encode(s)?;
+        Ok(len + self.hash.consensus_encode(s)?)
+    }
+}
+
+impl Decodable for Digest {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            algorithm: Decodable::consensus_decode(d)?,
+            hash: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(AlgorithmId);
+impl_strict_encoding!(Digest);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::crypto::commitment::tagged_hash;
+    use crate::crypto::{Commit, CommitmentEngine};
+
+    fn reversed(tag: &[u8], data: &[u8]) -> [u8; 32] {
+        let mut hash = primitives::commitment::tagged_sha256(tag, data);
+        hash.reverse();
+        hash
+    }
+
+    #[test]
+    fn resolve_registered_algorithms() {
+        let mut registry = HashRegistry::new();
+        let digest = registry
+            .digest(AlgorithmId::TAGGED_SHA256, b"tag", b"data")
+            .unwrap();
+        assert_eq!(digest.hash, tagged_hash(b"tag", b"data").to_fixed_bytes());
+        assert!(registry.verify(&digest, b"tag", b"data").unwrap());
+        assert!(!registry.verify(&digest, b"other", b"data").unwrap());
+        assert_eq!(
+            registry
+                .digest(AlgorithmId::KECCAK_256, b"", b"data")
+                .unwrap()
+                .hash,
+            CommitmentEngine.commit_to(b"data").to_fixed_bytes()
+        );
+
+        // Unknown algorithms are decoded but cannot be verified until registered
+        let custom = AlgorithmId(0x10);
+        let decoded: Digest = consensus::deserialize(&consensus::serialize(&Digest {
+            algorithm: custom,
+            hash: [0; 32],
+        }))
+        .unwrap();
+        assert_eq!(
+            registry.verify(&decoded, b"tag", b"data"),
+            Err(Error::UnknownAlgorithm(custom))
+        );
+        registry.register(custom, reversed).unwrap();
+        let digest = registry.digest(custom, b"tag", b"data").unwrap();
+        assert!(registry.verify(&digest, b"tag", b"data").unwrap());
+        assert_eq!(
+            registry.register(custom, reversed),
+            Err(Error::AlgorithmAlreadyRegistered(custom))
+        );
+        assert_eq!(
+            registry.register(AlgorithmId::TAGGED_SHA256, reversed),
+            Err(Error::AlgorithmAlreadyRegistered(
+                AlgorithmId::TAGGED_SHA256
+            ))
+        );
+    }
+
+    #[test]
+    fn encode_keccak_commitments_with_their_algorithm() {
+        use crate::consensus::CanonicalBytes;
+        use crate::crypto::KeccakCommitment;
+
+        let commitment = CommitmentEngine.commit_to(b"data");
+        let bytes = commitment.as_canonical_bytes();
+        assert_eq!(bytes.len(), 33);
+        assert_eq!(AlgorithmId(bytes[0]), AlgorithmId::KECCAK_256);
+        assert_eq!(
+            KeccakCommitment::from_canonical_bytes(&bytes).unwrap(),
+            commitment
+        );
+
+        // Another algorithm or a bare hash is rejected
+        let mut other = bytes.clone();
+        other[0] = AlgorithmId::TAGGED_SHA256.0;
+        assert!(KeccakCommitment::from_canonical_bytes(&other).is_err());
+        assert!(KeccakCommitment::from_canonical_bytes(&bytes[1..]).is_err());
+        assert!(KeccakCommitment::from_canonical_bytes(&[]).is_err());
+    }
+}
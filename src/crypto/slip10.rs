@@ -38,6 +38,11 @@ use bitcoin::secp256k1::{self, Secp256k1};
 
 use thiserror::Error;
 
+use std::fmt;
+use std::sync::atomic;
+
+use zeroize::Zeroize;
+
 pub use bitcoin::hash_types::XpubIdentifier;
 /// The 32-bytes entropy extention called chain code.
 pub use bitcoin::util::bip32::ChainCode;
@@ -60,7 +65,10 @@ pub enum Error {
 
 /// Ed25519 extended secret key. The extended secret key contains its depth, parent figerprint,
 /// child number, the derived secret key, and the chain code.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+///
+/// The secret key and the chain code are wiped from memory when the key is dropped, the key is not
+/// `Copy` so that no unmanaged copy is left behind.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Ed25519ExtSecretKey {
     /// The depth of this extended key, start with 0 for the master.
     pub depth: u8,
@@ -75,6 +83,31 @@ pub struct Ed25519ExtSecretKey {
     pub chain_code: ChainCode,
 }
 
+// Never display the secret key and the chain code
+impl fmt::Debug for Ed25519ExtSecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Ed25519ExtSecretKey")
+            .field("depth", &self.depth)
+            .field("parent_fingerprint", &self.parent_fingerprint)
+            .field("child_number", &self.child_number)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Zeroize for Ed25519ExtSecretKey {
+    fn zeroize(&mut self) {
+        self.secret_key.zeroize();
+        self.chain_code = ChainCode::from(&[0u8; 32][..]);
+        atomic::compiler_fence(atomic::Ordering::SeqCst);
+    }
+}
+
+impl Drop for Ed25519ExtSecretKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
 impl Encodable for Ed25519ExtSecretKey {
     fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
         let mut len = self.depth.consensus_encode(writer)?;
@@ -97,12 +130,10 @@ impl Encodable for Ed25519ExtSecretKey {
 impl Decodable for Ed25519ExtSecretKey {
     fn consensus_decode<D: std::io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
         let depth = u8::consensus_decode(d)?;
-        let parent_fingerprint = Fingerprint::try_from(Vec::<u8>::consensus_decode(d)?.as_slice())
-            .map_err(consensus::Error::new)?;
+        let parent_fingerprint = Fingerprint::from(Vec::<u8>::consensus_decode(d)?.as_slice());
         let child_number = ChildNumber::from(u32::consensus_decode(d)?);
         let secret_key = <[u8; 32]>::consensus_decode(d)?;
-        let chain_code = ChainCode::try_from(Vec::<u8>::consensus_decode(d)?.as_slice())
-            .map_err(consensus::Error::new)?;
+        let chain_code = ChainCode::from(Vec::<u8>::consensus_decode(d)?.as_slice());
         Ok(Ed25519ExtSecretKey {
             depth,
             parent_fingerprint,
@@ -135,7 +166,7 @@ impl Ed25519ExtSecretKey {
 
     /// Derive the extended secret key from `&self` up to the given `path`.
     pub fn derive_priv(&self, path: &impl AsRef<[ChildNumber]>) -> Result<Self, Error> {
-        let mut sk = *self;
+        let mut sk = self.clone();
         for cnum in path.as_ref() {
             sk = sk.ckd_priv(*cnum)?;
         }
@@ -214,7 +245,10 @@ impl Ed25519ExtSecretKey {
 
 /// Secp256k1 extended secret key. The extended secret key contains its depth, parent figerprint,
 /// child number, the derived secret key, and the chain code.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+///
+/// The secret key and the chain code are wiped from memory when the key is dropped, the key is not
+/// `Copy` so that no unmanaged copy is left behind.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Secp256k1ExtSecretKey {
     /// The depth of this extended key, start with 0 for the master.
     pub depth: u8,
@@ -228,6 +262,33 @@ pub struct Secp256k1ExtSecretKey {
     pub chain_code: ChainCode,
 }
 
+// Never display the secret key and the chain code
+impl fmt::Debug for Secp256k1ExtSecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Secp256k1ExtSecretKey")
+            .field("depth", &self.depth)
+            .field("parent_fingerprint", &self.parent_fingerprint)
+            .field("child_number", &self.child_number)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Zeroize for Secp256k1ExtSecretKey {
+    fn zeroize(&mut self) {
+        // A secp256k1 secret key cannot be zero and does not expose its bytes mutably, it is
+        // overwritten with the smallest valid key instead
+        self.secret_key = secp256k1::ONE_KEY;
+        self.chain_code = ChainCode::from(&[0u8; 32][..]);
+        atomic::compiler_fence(atomic::Ordering::SeqCst);
+    }
+}
+
+impl Drop for Secp256k1ExtSecretKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
 impl Encodable for Secp256k1ExtSecretKey {
     fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
         let mut len = self.depth.consensus_encode(writer)?;
@@ -250,13 +311,11 @@ impl Encodable for Secp256k1ExtSecretKey {
 impl Decodable for Secp256k1ExtSecretKey {
     fn consensus_decode<D: std::io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
         let depth = u8::consensus_decode(d)?;
-        let parent_fingerprint = Fingerprint::try_from(Vec::<u8>::consensus_decode(d)?.as_slice())
-            .map_err(consensus::Error::new)?;
+        let parent_fingerprint = Fingerprint::from(Vec::<u8>::consensus_decode(d)?.as_slice());
         let child_number = ChildNumber::from(u32::consensus_decode(d)?);
         let secret_key = secp256k1::SecretKey::from_slice(&<[u8; 32]>::consensus_decode(d)?)
             .map_err(consensus::Error::new)?;
-        let chain_code = ChainCode::try_from(Vec::<u8>::consensus_decode(d)?.as_slice())
-            .map_err(consensus::Error::new)?;
+        let chain_code = ChainCode::from(Vec::<u8>::consensus_decode(d)?.as_slice());
         Ok(Secp256k1ExtSecretKey {
             depth,
             parent_fingerprint,
@@ -301,7 +360,7 @@ impl Secp256k1ExtSecretKey {
         secp: &Secp256k1<C>,
         path: &impl AsRef<[ChildNumber]>,
     ) -> Result<Self, Error> {
-        let mut sk = *self;
+        let mut sk = self.clone();
         for cnum in path.as_ref() {
             sk = sk.ckd_priv(secp, *cnum)?;
         }
@@ -386,7 +445,7 @@ impl Secp256k1ExtSecretKey {
 
 /// An extended secret key. Generic interface for creating either a secp256k1 extended secret key
 /// or an ed25519 extended secret key and deriving sub-keys.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExtSecretKey {
     /// An extended secret key of type secp256k1.
     Secp256k1(Secp256k1ExtSecretKey),
@@ -408,7 +467,7 @@ impl ExtSecretKey {
     /// Derive the extended secret key given the path. When operating on Bitcoin curve a new
     /// `secp256k1` context is created.
     pub fn derive_priv(&self, path: &impl AsRef<[ChildNumber]>) -> Result<Self, Error> {
-        let mut sk = *self;
+        let mut sk = self.clone();
         for cnum in path.as_ref() {
             sk = sk.ckd_priv(*cnum)?;
         }
@@ -733,4 +792,19 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn wipe_extended_secret_keys() {
+        let mut key = Ed25519ExtSecretKey::new_master(b"seed");
+        assert_ne!(key.secret_key, [0u8; 32]);
+        key.zeroize();
+        assert_eq!(key.secret_key, [0u8; 32]);
+        assert_eq!(key.chain_code, ChainCode::from(&[0u8; 32][..]));
+
+        let mut key = Secp256k1ExtSecretKey::new_master(b"seed");
+        assert_ne!(key.secret_key, secp256k1::ONE_KEY);
+        key.zeroize();
+        assert_eq!(key.secret_key, secp256k1::ONE_KEY);
+        assert_eq!(key.chain_code, ChainCode::from(&[0u8; 32][..]));
+    }
 }
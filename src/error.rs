@@ -0,0 +1,539 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Machine-readable identity of the errors returned by the crate. Each error variant has a stable
+//! numeric code and a class telling a daemon whether the failed operation can be retried, the
+//! input must be dropped, or the swap cannot continue.
+//!
+//! Codes are grouped by module and never reused:
+//!
+//! | Range       | Module                                          |
+//! |-------------|-------------------------------------------------|
+//! | `1000-1999` | [`consensus`](crate::consensus)                 |
+//! | `2000-2999` | [`crypto`](crate::crypto)                       |
+//! | `3000-3999` | [`blockchain`](crate::blockchain) fee strategies |
+//! | `4000-4999` | [`transaction`](crate::transaction)             |
+//! | `5000-5999` | [`trade`](crate::trade)                         |
+//! | `6000-6999` | [`swap`](crate::swap)                           |
+//! | `7000-7999` | [`bitcoin`](crate::bitcoin)                     |
+//! | `8000-8999` | [`monero`](crate::monero), [`liquid`](crate::liquid) and [`ethereum`](crate::ethereum) |
+//! | `9000-9999` | [`primitives`](crate::primitives)               |
+//!
+//! The `Other` variants take the code and the class of the error they box when its type is part
+//! of the crate, e.g. a [`bitcoin::transaction::Error`] wrapped in a [`transaction::Error`]. The
+//! underlying causes of an error are available with [`sources`].
+
+use std::error;
+use std::iter;
+
+use crate::bitcoin::address::DestinationError;
+use crate::bitcoin::{self, cashaddr, network};
+use crate::blockchain::FeeStrategyError;
+use crate::primitives::{adaptor, dleq, encoding};
+use crate::{consensus, crypto, ethereum, liquid, monero, swap, trade, transaction};
+
+use ErrorClass::*;
+
+/// The class of an error, used to select a retry policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+#[display(Debug)]
+pub enum ErrorClass {
+    /// The failure is transient, the same operation can be retried later.
+    Retryable,
+    /// The input is invalid and must be dropped, the swap can continue.
+    Rejected,
+    /// The swap cannot continue.
+    Fatal,
+}
+
+/// Errors with a stable numeric code and a class.
+pub trait ErrorCode: error::Error {
+    /// Return the stable numeric code of the error.
+    fn code(&self) -> u16;
+
+    /// Return the class of the error.
+    fn class(&self) -> ErrorClass;
+
+    /// Return `true` if the operation can be retried later.
+    fn is_retryable(&self) -> bool {
+        self.class() == ErrorClass::Retryable
+    }
+
+    /// Return `true` if the swap cannot continue.
+    fn is_fatal(&self) -> bool {
+        self.class() == ErrorClass::Fatal
+    }
+}
+
+/// Return the error boxed in an `Other` variant as an [`ErrorCode`] when its type is part of the
+/// crate.
+fn coded<'a>(error: &'a (dyn error::Error + Send + Sync + 'static)) -> Option<&'a dyn ErrorCode> {
+    macro_rules! downcast {
+        ($($ty:ty),*) => {
+            $(
+                if let Some(e) = error.downcast_ref::<$ty>() {
+                    return Some(e);
+                }
+            )*
+        };
+    }
+    downcast!(
+        consensus::Error,
+        crypto::Error,
+        FeeStrategyError,
+        transaction::Error,
+        trade::Error,
+        swap::Error,
+        bitcoin::transaction::Error,
+        network::Error,
+        DestinationError,
+        cashaddr::Error,
+        monero::Error,
+        liquid::Error,
+        ethereum::Error,
+        encoding::Error,
+        dleq::Error,
+        adaptor::Error
+    );
+    None
+}
+
+/// Iterate over an error and its chain of underlying causes, starting with the error itself.
+pub fn sources<'a>(
+    error: &'a (dyn error::Error + 'static),
+) -> impl Iterator<Item = &'a (dyn error::Error + 'static)> {
+    iter::successors(Some(error), |e| e.source())
+}
+
+impl ErrorCode for consensus::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::UnknownType => 1000,
+            Self::TypeMismatch => 1001,
+            Self::IncorrectMagicBytes => 1002,
+            Self::Io(_) => 1003,
+            Self::ParseFailed(_) => 1004,
+            Self::Other(e) => coded(e.as_ref()).map_or(1999, |e| e.code()),
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        match self {
+            Self::Other(e) => coded(e.as_ref()).map_or(Rejected, |e| e.class()),
+            _ => Rejected,
+        }
+    }
+}
+
+impl ErrorCode for crypto::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::UnsupportedKey => 2000,
+            Self::MissingKey => 2001,
+            Self::InvalidSignature => 2002,
+            Self::InvalidAdaptorKey => 2003,
+            Self::InvalidEncryptedSignature => 2004,
+            Self::InvalidProof => 2005,
+            Self::InvalidCommitment => 2006,
+            Self::InvalidPedersenCommitment => 2007,
+            Self::InvalidRingSignature => 2008,
+            Self::InvalidProofOfKnowledge => 2009,
+            Self::InvalidAggregatedKey => 2010,
+            Self::InvalidNonce => 2011,
+            Self::InvalidPartialSignature => 2012,
+            Self::InvalidCiphertext => 2013,
+            Self::Commitment(_) => 2014,
+            Self::Slip10(_) => 2015,
+            Self::Other(e) => coded(e.as_ref()).map_or(2999, |e| e.code()),
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        match self {
+            // Undecryptable blobs do not compromise the swap
+            Self::InvalidCiphertext => Rejected,
+            Self::Other(e) => coded(e.as_ref()).map_or(Fatal, |e| e.class()),
+            _ => Fatal,
+        }
+    }
+}
+
+impl ErrorCode for FeeStrategyError {
+    fn code(&self) -> u16 {
+        match self {
+            Self::MissingInputsMetadata => 3000,
+            Self::AmountOfFeeTooLow => 3001,
+            Self::AmountOfFeeTooHigh => 3002,
+            Self::NotEnoughAssets => 3003,
+            Self::AmountOverflow => 3004,
+            Self::OutOfStrategy => 3005,
+            Self::Other(e) => coded(e.as_ref()).map_or(3999, |e| e.code()),
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        match self {
+            // A new fee estimation can be applied
+            Self::AmountOfFeeTooLow | Self::AmountOfFeeTooHigh => Retryable,
+            // The funds are not on-chain yet or the fee rate is too high for them, as for
+            // transaction::Error::NotEnoughAssets
+            Self::NotEnoughAssets => Retryable,
+            Self::OutOfStrategy => Rejected,
+            Self::Other(e) => coded(e.as_ref()).map_or(Fatal, |e| e.class()),
+            _ => Fatal,
+        }
+    }
+}
+
+impl ErrorCode for transaction::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::MissingUTXO => 4000,
+            Self::MissingSignature => 4001,
+            Self::MissingWitness => 4002,
+            Self::MissingNetwork => 4003,
+            Self::MissingPublicKey => 4004,
+            Self::MissingOnchainTransaction => 4005,
+            Self::InvalidTargetAmount => 4006,
+            Self::NotEnoughAssets => 4007,
+            Self::Overfunded => 4008,
+            Self::NonCanonicalSignature => 4009,
+            Self::WrongTemplate(_) => 4010,
+            Self::InvalidTransactionChain => 4011,
+            Self::AddressReuse(_) => 4012,
+            Self::Other(e) => coded(e.as_ref()).map_or(4999, |e| e.code()),
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        match self {
+            // The transaction or the funds are not on-chain yet
+            Self::MissingOnchainTransaction | Self::NotEnoughAssets => Retryable,
+            Self::MissingSignature | Self::MissingWitness | Self::MissingPublicKey => Rejected,
+            // A fresh funding address can be derived
+            Self::AddressReuse(_) => Rejected,
+            Self::Other(e) => coded(e.as_ref()).map_or(Fatal, |e| e.class()),
+            _ => Fatal,
+        }
+    }
+}
+
+impl ErrorCode for trade::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::UnsupportedVersion => 5000,
+            Self::InvalidSignature => 5001,
+            Self::NodeIdMismatch => 5002,
+            Self::Expired => 5003,
+            Self::AmountOutOfRange => 5004,
+            Self::InvalidRate => 5005,
+            Self::AmountOverflow => 5006,
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        Rejected
+    }
+}
+
+impl ErrorCode for swap::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::SwapIdMismatch { .. } => 6000,
+            Self::InvalidRoute(_) => 6001,
+            Self::AbortAfterAccordantLock => 6002,
+            Self::InvalidSetup(_) => 6003,
+            Self::NotCompleted => 6004,
+            Self::InvalidRecoveryKey(_) => 6005,
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        Rejected
+    }
+}
+
+impl ErrorCode for bitcoin::transaction::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::MultiUTXOUnsuported => 7000,
+            Self::InvalidPunishPolicy => 7001,
+            Self::DustOutput(_) => 7002,
+            Self::MissingSigHashType => 7003,
+            Self::UnsupportedSigHashType(_) => 7004,
+            Self::Psbt(_) => 7005,
+            Self::Address(_) => 7006,
+            Self::Secp256k1(_) => 7007,
+            Self::BitcoinScript(_) => 7008,
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        match self {
+            // The negotiated amounts cannot produce the transactions
+            Self::InvalidPunishPolicy | Self::DustOutput(_) => Fatal,
+            _ => Rejected,
+        }
+    }
+}
+
+impl ErrorCode for network::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::Address(_) => 7100,
+            Self::PrefixMismatch(_) => 7101,
+            Self::Destination(e) => e.code(),
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        Rejected
+    }
+}
+
+impl ErrorCode for DestinationError {
+    fn code(&self) -> u16 {
+        match self {
+            Self::NetworkMismatch { .. } => 7200,
+            Self::UnsupportedType(_) => 7201,
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        Rejected
+    }
+}
+
+impl ErrorCode for cashaddr::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::InvalidPrefix => 7300,
+            Self::MixedCase => 7301,
+            Self::InvalidCharacter => 7302,
+            Self::InvalidChecksum => 7303,
+            Self::InvalidPayload => 7304,
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        Rejected
+    }
+}
+
+impl ErrorCode for monero::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::NetworkMismatch { .. } => 8000,
+            Self::LockNotFound => 8001,
+            Self::Underpaid { .. } => 8002,
+            Self::TimeLocked(_) => 8003,
+            Self::InvalidTransaction => 8004,
+            Self::InvalidSpendKey => 8005,
+            Self::Overpaid { .. } => 8006,
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        match self {
+            // The lock is not on-chain yet
+            Self::LockNotFound => Retryable,
+            // The counterparty locked funds that cannot be swapped
+            Self::Underpaid { .. }
+            | Self::Overpaid { .. }
+            | Self::TimeLocked(_)
+            | Self::InvalidSpendKey => Fatal,
+            _ => Rejected,
+        }
+    }
+}
+
+impl ErrorCode for liquid::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::ScriptMismatch => 8100,
+            Self::AssetMismatch { .. } => 8101,
+            Self::Underpaid { .. } => 8102,
+            Self::MissingRangeproof => 8103,
+            Self::Blinded => 8104,
+            Self::Unblinding(_) => 8105,
+            Self::InvalidOpening => 8106,
+            Self::InvalidRangeproof => 8107,
+            Self::MissingSurjectionProof => 8108,
+            Self::InvalidSurjectionProof => 8109,
+            Self::InputOutOfBounds(_) => 8110,
+            Self::Overpaid { .. } => 8111,
+            Self::Unverifiable => 8112,
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        match self {
+            // The counterparty locked funds that cannot be swapped
+            Self::Underpaid { .. } | Self::Overpaid { .. } => Fatal,
+            _ => Rejected,
+        }
+    }
+}
+
+impl ErrorCode for ethereum::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::InvalidAddress => 8200,
+            Self::InvalidSignature => 8201,
+            Self::SignerMismatch { .. } => 8202,
+            Self::InvalidCallData => 8203,
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        Rejected
+    }
+}
+
+impl ErrorCode for encoding::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::UnexpectedEnd => 9000,
+            Self::TooLong => 9001,
+            Self::TrailingBytes => 9002,
+            Self::UnsupportedVersion(_) => 9003,
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        Rejected
+    }
+}
+
+impl ErrorCode for dleq::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::Encoding(e) => e.code(),
+            Self::InvalidPoint => 9100,
+            Self::InvalidScalar => 9101,
+            Self::InvalidLength => 9102,
+            Self::InvalidPedersenCommitment => 9103,
+            Self::InvalidRingSignature => 9104,
+            Self::InvalidProofOfKnowledge => 9105,
+        }
+    }
+
+    // As crypto::Error::InvalidProof, the counterparty keys cannot be trusted
+    fn class(&self) -> ErrorClass {
+        Fatal
+    }
+}
+
+impl ErrorCode for adaptor::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::Encoding(e) => e.code(),
+            Self::InvalidPoint => 9200,
+            Self::InvalidScalar => 9201,
+            Self::InvalidProof => 9202,
+            Self::InvalidEncryptedSignature => 9203,
+        }
+    }
+
+    // As crypto::Error::InvalidEncryptedSignature, the counterparty cannot be trusted
+    fn class(&self) -> ErrorClass {
+        Fatal
+    }
+}
+
+impl ErrorCode for crate::Error {
+    fn code(&self) -> u16 {
+        match self {
+            Self::Consensus(e) => e.code(),
+            Self::Crypto(e) => e.code(),
+            Self::FeeStrategy(e) => e.code(),
+            Self::Transaction(e) => e.code(),
+            Self::Trade(e) => e.code(),
+            Self::Swap(e) => e.code(),
+        }
+    }
+
+    fn class(&self) -> ErrorClass {
+        match self {
+            Self::Consensus(e) => e.class(),
+            Self::Crypto(e) => e.class(),
+            Self::FeeStrategy(e) => e.class(),
+            Self::Transaction(e) => e.class(),
+            Self::Trade(e) => e.class(),
+            Self::Swap(e) => e.class(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_errors() {
+        let err: crate::Error = transaction::Error::MissingOnchainTransaction.into();
+        assert_eq!(err.code(), 4005);
+        assert!(err.is_retryable());
+        assert!(!err.is_fatal());
+
+        let err: crate::Error = crypto::Error::InvalidProof.into();
+        assert_eq!(err.code(), 2005);
+        assert!(err.is_fatal());
+
+        let err: crate::Error = trade::Error::Expired.into();
+        assert_eq!(err.code(), 5003);
+        assert_eq!(err.class(), ErrorClass::Rejected);
+    }
+
+    #[test]
+    fn classify_boxed_errors() {
+        let err: crate::Error =
+            transaction::Error::from(bitcoin::transaction::Error::DustOutput(100)).into();
+        assert_eq!(err.code(), 7002);
+        assert!(err.is_fatal());
+
+        let err: crate::Error = transaction::Error::new(monero::Error::LockNotFound).into();
+        assert_eq!(err.code(), 8001);
+        assert!(err.is_retryable());
+
+        let err: crate::Error =
+            crypto::Error::new(adaptor::Error::Encoding(encoding::Error::UnexpectedEnd)).into();
+        assert_eq!(err.code(), 9000);
+        assert!(err.is_fatal());
+
+        let err: crate::Error = transaction::Error::new(std::fmt::Error).into();
+        assert_eq!(err.code(), 4999);
+        assert!(err.is_fatal());
+    }
+
+    #[test]
+    fn not_enough_assets_is_retryable() {
+        assert!(FeeStrategyError::NotEnoughAssets.is_retryable());
+        assert!(transaction::Error::NotEnoughAssets.is_retryable());
+    }
+
+    #[test]
+    fn chain_error_sources() {
+        let io = std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "eof");
+        let err: crate::Error = crypto::Error::Other(Box::new(consensus::Error::from(io))).into();
+        let chain: Vec<String> = sources(&err).map(|e| e.to_string()).collect();
+        assert_eq!(chain.len(), 4);
+        assert_eq!(chain[3], "eof");
+        // The boxed consensus error gives its code
+        assert_eq!(err.code(), 1003);
+        assert_eq!(err.class(), ErrorClass::Rejected);
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Helpers to drive a swap on Ethereum through a reference HTLC-style swap contract. Unlike
+//! Bitcoin, funds are not locked in pre-signed transactions but in the contract:
+//!
+//! - `lock` deposits the ether with both participants' addresses, adaptor addresses, and
+//!   timelocks,
+//! - `buy` and `refund` reveal the adaptor secret of Alice, respectively Bob, the contract checks
+//!   the secret against the adaptor address set in `lock`; the revealed secret is the Monero spend
+//!   key share of its owner,
+//! - `cancel` requires an [EIP-712](https://eips.ethereum.org/EIPS/eip-712) authorization signed
+//!   by both participants, like the pre-signed cancel transaction on Bitcoin,
+//! - `punish` is available to Alice once the punish timelock expires.
+//!
+//! The [`contract`] module encodes the calls to the contract and the [`eip712`] module computes
+//! the payloads signed by the participants.
+//!
+//! # Scope
+//!
+//! Only these helpers are provided, Ethereum is not an arbitrating implementation. [`Ethereum`]
+//! implements [`DeriveKeys`] but not the arbitrating transaction traits:
+//! [`crate::blockchain::Transactions`] and the [`crate::transaction`] templates assume pre-signed
+//! UTXO transactions chained on each other, which contract calls are not. Hence the protocol state
+//! machines in [`crate::protocol`] and [`crate::swap`] cannot run an ETH-XMR swap and Ethereum is
+//! not a [`Blockchain`](crate::blockchain::Blockchain) deals can be made on; a client must drive
+//! the contract calls itself with [`contract::SwapCall`], [`eip712::Authorization`], and
+//! [`contract::extract_secret`].
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use thiserror::Error;
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::{DeriveKeys, SharedKeyId};
+
+pub mod contract;
+pub mod eip712;
+
+/// Errors when manipulating Ethereum addresses, contract calls, and signatures.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The address is not 20 bytes hex encoded with a `0x` prefix.
+    #[error("Invalid Ethereum address")]
+    InvalidAddress,
+    /// The signature is malformed or cannot be recovered.
+    #[error("Invalid Ethereum signature")]
+    InvalidSignature,
+    /// The signature is valid but not from the expected signer.
+    #[error("The signature is from {found} while {expected} is expected")]
+    SignerMismatch {
+        /// The expected signer.
+        expected: Address,
+        /// The recovered signer.
+        found: Address,
+    },
+    /// The call data does not encode the expected contract call.
+    #[error("Invalid contract call data")]
+    InvalidCallData,
+}
+
+/// Hash `data` with Keccak-256, the hash function used by Ethereum.
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(data);
+    keccak.finalize(&mut out);
+    out
+}
+
+/// A 20 bytes Ethereum address, the last 20 bytes of the Keccak-256 hash of the uncompressed
+/// public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Address(pub [u8; 20]);
+
+impl Address {
+    /// Derive the address controlled by `pubkey`.
+    pub fn from_public_key(pubkey: &PublicKey) -> Self {
+        let hash = keccak256(&pubkey.serialize_uncompressed()[1..]);
+        let mut address = [0u8; 20];
+        address.copy_from_slice(&hash[12..]);
+        Self(address)
+    }
+
+    /// The address as a 32 bytes ABI word, left padded with zeros.
+    pub fn to_word(&self) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        word[12..].copy_from_slice(&self.0);
+        word
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(self.0))
+    }
+}
+
+impl FromStr for Address {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(s.strip_prefix("0x").ok_or(Error::InvalidAddress)?)
+            .map_err(|_| Error::InvalidAddress)?;
+        Ok(Self(bytes.try_into().map_err(|_| Error::InvalidAddress)?))
+    }
+}
+
+impl CanonicalBytes for Address {
+    fn as_canonical_bytes(&self) -> Vec<u8> {
+        self.0.into()
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, consensus::Error>
+    where
+        Self: Sized,
+    {
+        Ok(Self(bytes.try_into().map_err(consensus::Error::new)?))
+    }
+}
+
+/// The Ethereum chain type, used to derive the participants' keys. It does not implement the
+/// arbitrating transaction traits.
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub struct Ethereum;
+
+impl Decodable for Ethereum {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match Decodable::consensus_decode(d)? {
+            0x8000003cu32 => Ok(Self),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl Encodable for Ethereum {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        0x8000003cu32.consensus_encode(writer)
+    }
+}
+
+impl FromStr for Ethereum {
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Ethereum" | "ethereum" | "eth" => Ok(Ethereum),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl fmt::Display for Ethereum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "eth")
+    }
+}
+
+impl DeriveKeys for Ethereum {
+    type PublicKey = PublicKey;
+    type PrivateKey = SecretKey;
+
+    fn extra_public_keys() -> Vec<u16> {
+        // No extra key
+        vec![]
+    }
+
+    fn extra_shared_private_keys() -> Vec<SharedKeyId> {
+        // No shared key
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::Secp256k1;
+
+    #[test]
+    fn derive_address() {
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(
+            &hex::decode("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318")
+                .unwrap(),
+        )
+        .unwrap();
+        let address = Address::from_public_key(&PublicKey::from_secret_key(&secp, &key));
+        assert_eq!(
+            address.to_string(),
+            "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23"
+        );
+        assert_eq!(Address::from_str(&address.to_string()), Ok(address));
+        assert!(Address::from_str("2c7536e3605d9c16a7a3d7b1898e529396a65c23").is_err());
+        assert!(Address::from_str("0x2c75").is_err());
+    }
+}
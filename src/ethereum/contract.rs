@@ -0,0 +1,245 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! ABI encoding of the calls to the reference swap contract:
+//!
+//! ```text
+//! lock(bytes32 swapId,address alice,address bob,address aliceAdaptor,address bobAdaptor,uint256 cancelTimelock,uint256 punishTimelock)
+//! cancel(bytes32 swapId,bytes32 aliceR,bytes32 aliceS,uint8 aliceV,bytes32 bobR,bytes32 bobS,uint8 bobV)
+//! buy(bytes32 swapId,bytes32 secret)
+//! refund(bytes32 swapId,bytes32 secret)
+//! punish(bytes32 swapId)
+//! ```
+//!
+//! Adaptor addresses are the addresses of the participants' adaptor public keys, see
+//! [`Address::from_public_key`]. The contract verifies a revealed secret by checking it controls
+//! the adaptor address.
+
+use bitcoin::secp256k1::SecretKey;
+
+use crate::ethereum::eip712::{swap_id_word, Signature};
+use crate::ethereum::{keccak256, Address, Error};
+use crate::swap::SwapId;
+use crate::Uuid;
+
+/// Solidity signature of the `lock` function.
+pub const LOCK_SIGNATURE: &str = "lock(bytes32,address,address,address,address,uint256,uint256)";
+/// Solidity signature of the `cancel` function.
+pub const CANCEL_SIGNATURE: &str = "cancel(bytes32,bytes32,bytes32,uint8,bytes32,bytes32,uint8)";
+/// Solidity signature of the `buy` function.
+pub const BUY_SIGNATURE: &str = "buy(bytes32,bytes32)";
+/// Solidity signature of the `refund` function.
+pub const REFUND_SIGNATURE: &str = "refund(bytes32,bytes32)";
+/// Solidity signature of the `punish` function.
+pub const PUNISH_SIGNATURE: &str = "punish(bytes32)";
+
+/// Compute the 4 bytes selector of a Solidity function signature.
+pub fn selector(signature: &str) -> [u8; 4] {
+    let mut selector = [0u8; 4];
+    selector.copy_from_slice(&keccak256(signature.as_bytes())[..4]);
+    selector
+}
+
+fn uint_word(value: u64) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn signature_words(sig: &Signature) -> [[u8; 32]; 3] {
+    let bytes = sig.to_bytes();
+    let mut r = [0u8; 32];
+    let mut s = [0u8; 32];
+    r.copy_from_slice(&bytes[..32]);
+    s.copy_from_slice(&bytes[32..64]);
+    [r, s, uint_word(bytes[64] as u64)]
+}
+
+/// A call to the reference swap contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapCall {
+    /// Lock the ether sent with the call for the swap.
+    Lock {
+        /// The swap identifier.
+        swap_id: SwapId,
+        /// Alice's address, receiving the funds on punish.
+        alice: Address,
+        /// Bob's address, receiving the funds on refund.
+        bob: Address,
+        /// The address of Alice's adaptor key, checked on buy.
+        alice_adaptor: Address,
+        /// The address of Bob's adaptor key, checked on refund.
+        bob_adaptor: Address,
+        /// Seconds after the lock before the swap can be cancelled.
+        cancel_timelock: u64,
+        /// Seconds after the cancel before Alice can punish.
+        punish_timelock: u64,
+    },
+    /// Cancel the swap with both participants' authorizations.
+    Cancel {
+        /// The swap identifier.
+        swap_id: SwapId,
+        /// Alice's cancel authorization.
+        alice_sig: Signature,
+        /// Bob's cancel authorization.
+        bob_sig: Signature,
+    },
+    /// Alice claims the funds by revealing her adaptor secret.
+    Buy {
+        /// The swap identifier.
+        swap_id: SwapId,
+        /// Alice's adaptor secret.
+        secret: SecretKey,
+    },
+    /// Bob claims back the funds by revealing his adaptor secret.
+    Refund {
+        /// The swap identifier.
+        swap_id: SwapId,
+        /// Bob's adaptor secret.
+        secret: SecretKey,
+    },
+    /// Alice claims the funds after the punish timelock.
+    Punish {
+        /// The swap identifier.
+        swap_id: SwapId,
+    },
+}
+
+impl SwapCall {
+    /// The Solidity signature of the called function.
+    pub fn signature(&self) -> &'static str {
+        match self {
+            Self::Lock { .. } => LOCK_SIGNATURE,
+            Self::Cancel { .. } => CANCEL_SIGNATURE,
+            Self::Buy { .. } => BUY_SIGNATURE,
+            Self::Refund { .. } => REFUND_SIGNATURE,
+            Self::Punish { .. } => PUNISH_SIGNATURE,
+        }
+    }
+
+    /// The swap identifier of the call.
+    pub fn swap_id(&self) -> SwapId {
+        match self {
+            Self::Lock { swap_id, .. }
+            | Self::Cancel { swap_id, .. }
+            | Self::Buy { swap_id, .. }
+            | Self::Refund { swap_id, .. }
+            | Self::Punish { swap_id } => *swap_id,
+        }
+    }
+
+    /// Encode the call data: the function selector followed by the ABI encoded arguments.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut words = vec![swap_id_word(self.swap_id())];
+        match self {
+            Self::Lock {
+                alice,
+                bob,
+                alice_adaptor,
+                bob_adaptor,
+                cancel_timelock,
+                punish_timelock,
+                ..
+            } => {
+                words.extend([
+                    alice.to_word(),
+                    bob.to_word(),
+                    alice_adaptor.to_word(),
+                    bob_adaptor.to_word(),
+                    uint_word(*cancel_timelock),
+                    uint_word(*punish_timelock),
+                ]);
+            }
+            Self::Cancel {
+                alice_sig, bob_sig, ..
+            } => {
+                words.extend(signature_words(alice_sig));
+                words.extend(signature_words(bob_sig));
+            }
+            Self::Buy { secret, .. } | Self::Refund { secret, .. } => {
+                words.push(secret.secret_bytes());
+            }
+            Self::Punish { .. } => (),
+        }
+        let mut data = selector(self.signature()).to_vec();
+        words.iter().for_each(|word| data.extend(word));
+        data
+    }
+}
+
+/// Extract the adaptor secret revealed by a `buy` or `refund` call seen on-chain, with the swap
+/// identifier of the call. The secret of a buy is Alice's Monero spend key share, the secret of a
+/// refund is Bob's.
+pub fn extract_secret(call_data: &[u8]) -> Result<SwapCall, Error> {
+    if call_data.len() != 4 + 2 * 32 {
+        return Err(Error::InvalidCallData);
+    }
+    let (swap_id, secret) = call_data[4..].split_at(32);
+    if swap_id[16..].iter().any(|b| *b != 0) {
+        return Err(Error::InvalidCallData);
+    }
+    let swap_id = SwapId(Uuid::from(
+        uuid::Uuid::from_slice(&swap_id[..16]).map_err(|_| Error::InvalidCallData)?,
+    ));
+    let secret = SecretKey::from_slice(secret).map_err(|_| Error::InvalidCallData)?;
+    match call_data[..4] {
+        ref s if *s == selector(BUY_SIGNATURE) => Ok(SwapCall::Buy { swap_id, secret }),
+        ref s if *s == selector(REFUND_SIGNATURE) => Ok(SwapCall::Refund { swap_id, secret }),
+        _ => Err(Error::InvalidCallData),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compute_selector() {
+        assert_eq!(
+            hex::encode(selector("transfer(address,uint256)")),
+            "a9059cbb"
+        );
+    }
+
+    #[test]
+    fn encode_calls_and_extract_secret() {
+        let swap_id: SwapId = Uuid::random().into();
+        let lock = SwapCall::Lock {
+            swap_id,
+            alice: Address([1; 20]),
+            bob: Address([2; 20]),
+            alice_adaptor: Address([3; 20]),
+            bob_adaptor: Address([4; 20]),
+            cancel_timelock: 3600,
+            punish_timelock: 7200,
+        };
+        let data = lock.encode();
+        assert_eq!(data.len(), 4 + 7 * 32);
+        assert_eq!(data[..4], selector(LOCK_SIGNATURE));
+        assert_eq!(data[4 + 32 + 12..4 + 64], [1; 20]);
+        assert_eq!(data[data.len() - 2..], [0x1c, 0x20]);
+
+        let secret = SecretKey::from_slice(&[7; 32]).unwrap();
+        let buy = SwapCall::Buy { swap_id, secret };
+        assert_eq!(extract_secret(&buy.encode()), Ok(buy));
+        let refund = SwapCall::Refund { swap_id, secret };
+        assert_eq!(extract_secret(&refund.encode()), Ok(refund));
+        assert_eq!(
+            extract_secret(&SwapCall::Punish { swap_id }.encode()),
+            Err(Error::InvalidCallData)
+        );
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Payloads signed by the swap participants to authorize contract calls, following the
+//! [EIP-712](https://eips.ethereum.org/EIPS/eip-712) typed structured data hashing. The signed
+//! struct is:
+//!
+//! ```text
+//! Authorization(bytes32 swapId,uint8 action,address recipient)
+//! ```
+
+use bitcoin::secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey, Signing, Verification};
+
+use crate::ethereum::{keccak256, Address, Error};
+use crate::swap::SwapId;
+
+/// Type of the domain separator struct.
+pub const DOMAIN_TYPE: &str =
+    "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)";
+
+/// Type of the authorization struct.
+pub const AUTHORIZATION_TYPE: &str = "Authorization(bytes32 swapId,uint8 action,address recipient)";
+
+/// Name of the reference swap contract in the domain separator.
+pub const CONTRACT_NAME: &str = "FarcasterSwap";
+
+/// Version of the reference swap contract in the domain separator.
+pub const CONTRACT_VERSION: &str = "1";
+
+fn uint_word(value: u64) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+/// The signing domain: the swap contract deployed at `verifying_contract` on chain `chain_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Domain {
+    /// The chain identifier, e.g. 1 for mainnet.
+    pub chain_id: u64,
+    /// The address of the swap contract.
+    pub verifying_contract: Address,
+}
+
+impl Domain {
+    /// The domain separator hash.
+    pub fn separator(&self) -> [u8; 32] {
+        let mut data = keccak256(DOMAIN_TYPE.as_bytes()).to_vec();
+        data.extend(keccak256(CONTRACT_NAME.as_bytes()));
+        data.extend(keccak256(CONTRACT_VERSION.as_bytes()));
+        data.extend(uint_word(self.chain_id));
+        data.extend(self.verifying_contract.to_word());
+        keccak256(&data)
+    }
+}
+
+/// Contract actions requiring an authorization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+#[display(Debug)]
+pub enum Action {
+    /// Move the locked funds into the cancel state, signed by both participants.
+    Cancel = 0x01,
+}
+
+/// Authorization of a contract `action` for the swap `swap_id`, sending the funds to
+/// `recipient` if the action releases funds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Authorization {
+    /// The swap identifier, also identifying the lock in the contract.
+    pub swap_id: SwapId,
+    /// The authorized action.
+    pub action: Action,
+    /// The recipient of the funds, zero if the action does not release funds.
+    pub recipient: Address,
+}
+
+impl Authorization {
+    /// The EIP-712 struct hash of the authorization.
+    pub fn struct_hash(&self) -> [u8; 32] {
+        let mut data = keccak256(AUTHORIZATION_TYPE.as_bytes()).to_vec();
+        data.extend(swap_id_word(self.swap_id));
+        data.extend(uint_word(self.action as u64));
+        data.extend(self.recipient.to_word());
+        keccak256(&data)
+    }
+
+    /// The payload to sign, `keccak256(0x1901 || domainSeparator || structHash)`.
+    pub fn signing_payload(&self, domain: &Domain) -> [u8; 32] {
+        let mut data = vec![0x19, 0x01];
+        data.extend(domain.separator());
+        data.extend(self.struct_hash());
+        keccak256(&data)
+    }
+
+    /// Sign the authorization.
+    pub fn sign<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        domain: &Domain,
+        key: &SecretKey,
+    ) -> Signature {
+        let msg = Message::from_slice(&self.signing_payload(domain)).expect("32 bytes hash");
+        Signature(secp.sign_ecdsa_recoverable(&msg, key))
+    }
+
+    /// Verify the authorization is signed by `signer`. Fails with [`Error::SignerMismatch`] if
+    /// signed by another address.
+    pub fn verify<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        domain: &Domain,
+        signature: &Signature,
+        signer: Address,
+    ) -> Result<(), Error> {
+        let msg = Message::from_slice(&self.signing_payload(domain)).expect("32 bytes hash");
+        let pubkey = secp
+            .recover_ecdsa(&msg, &signature.0)
+            .map_err(|_| Error::InvalidSignature)?;
+        let found = Address::from_public_key(&pubkey);
+        if found != signer {
+            return Err(Error::SignerMismatch {
+                expected: signer,
+                found,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The swap identifier as a 32 bytes ABI word, right padded with zeros.
+pub fn swap_id_word(swap_id: SwapId) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[..16].copy_from_slice(swap_id.0.as_ref().as_bytes());
+    word
+}
+
+/// A recoverable ECDSA signature, serialized as `r || s || v` with `v` in `{27, 28}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature(pub RecoverableSignature);
+
+impl Signature {
+    /// Serialize the signature in the 65 bytes Ethereum format.
+    pub fn to_bytes(&self) -> [u8; 65] {
+        let (id, rs) = self.0.serialize_compact();
+        let mut bytes = [0u8; 65];
+        bytes[..64].copy_from_slice(&rs);
+        bytes[64] = 27 + id.to_i32() as u8;
+        bytes
+    }
+
+    /// Parse a signature in the 65 bytes Ethereum format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != 65 || !(27..=28).contains(&bytes[64]) {
+            return Err(Error::InvalidSignature);
+        }
+        let id =
+            RecoveryId::from_i32((bytes[64] - 27) as i32).map_err(|_| Error::InvalidSignature)?;
+        Ok(Self(
+            RecoverableSignature::from_compact(&bytes[..64], id)
+                .map_err(|_| Error::InvalidSignature)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Uuid;
+    use bitcoin::secp256k1::PublicKey;
+
+    #[test]
+    fn sign_and_verify_authorization() {
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let signer = Address::from_public_key(&PublicKey::from_secret_key(&secp, &key));
+        let domain = Domain {
+            chain_id: 1,
+            verifying_contract: Address([0xaa; 20]),
+        };
+        let auth = Authorization {
+            swap_id: Uuid::random().into(),
+            action: Action::Cancel,
+            recipient: Address([0; 20]),
+        };
+
+        let sig = auth.sign(&secp, &domain, &key);
+        assert_eq!(Signature::from_bytes(&sig.to_bytes()), Ok(sig));
+        assert!(auth.verify(&secp, &domain, &sig, signer).is_ok());
+
+        // The signature is bound to the chain and the contract
+        let other_chain = Domain {
+            chain_id: 5,
+            ..domain
+        };
+        assert!(auth.verify(&secp, &other_chain, &sig, signer).is_err());
+        let other = Address([0xbb; 20]);
+        assert!(matches!(
+            auth.verify(&secp, &domain, &sig, other),
+            Err(Error::SignerMismatch { .. })
+        ));
+    }
+}
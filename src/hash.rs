@@ -43,3 +43,30 @@ impl<'de> Visitor<'de> for HashString {
         }
     }
 }
+
+/// Implement `Serialize` and `Deserialize` for a fixed hash as a `0x` prefixed full hex string,
+/// the inverse of its `FromStr` implementation.
+macro_rules! impl_serde_hex {
+    ($type:ty) => {
+        impl ::serde::Serialize for $type {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ::serde::Serializer,
+            {
+                serializer.serialize_str(format!("{:#x}", self).as_ref())
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $type {
+            fn deserialize<D>(deserializer: D) -> Result<$type, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                <$type as ::std::str::FromStr>::from_str(
+                    &deserializer.deserialize_string($crate::hash::HashString)?,
+                )
+                .map_err(::serde::de::Error::custom)
+            }
+        }
+    };
+}
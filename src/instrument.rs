@@ -0,0 +1,365 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Instrumentation of the protocol steps for monitoring swap latency and failure points. Each
+//! instrumented step runs in a span whose outcome and duration are reported to an [`Observer`]
+//! provided by the operator, e.g. bridging to a Prometheus registry.
+//!
+//! Instrumented components carry an [`Instrumentation`] handle: the key manager of the
+//! [`btcxmr`](crate::swap::btcxmr) swap for signing, proof generation and verification, and the
+//! JSON-RPC swap server for message decoding and state transitions. Without the `instrument`
+//! feature no observer can be attached and the handle is free.
+//!
+//! With the `instrument` feature the steps are also emitted as `tracing` spans named `step`, and
+//! the state transitions of [`Alice`](crate::protocol::Alice) and [`Bob`](crate::protocol::Bob)
+//! run in spans named after the transition with the `role` of the participant, errors are
+//! recorded as events of the span. Spans are collected by the `tracing` subscriber installed by
+//! the operator, if any.
+//!
+//! For each span the observer receives, in order:
+//!
+//! - [`Observer::span_started`],
+//! - [`Observer::span_finished`] with the duration and the outcome of the step,
+//! - [`Observer::increment_counter`] on the [`Step::counter`] and, if the step failed, the
+//!   [`Step::failure_counter`],
+//! - [`Observer::record_histogram`] on the [`Step::histogram`] with the duration in seconds.
+
+#[cfg(feature = "instrument")]
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "instrument")]
+use std::time::Instant;
+
+use crate::consensus::{self, Decodable, Encodable};
+
+/// An instrumented protocol step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum Step {
+    /// Signing an arbitrating transaction.
+    Sign,
+    /// Producing an adaptor signature.
+    EncryptSign,
+    /// Verifying a counter-party adaptor signature.
+    VerifyEncryptedSignature,
+    /// Generating the cross-group DLEQ proof.
+    GenerateProof,
+    /// Verifying the counter-party DLEQ proof.
+    VerifyProof,
+    /// Encoding a protocol message.
+    EncodeMessage,
+    /// Decoding a protocol message.
+    DecodeMessage,
+    /// Applying a message or a chain event to the state of a swap.
+    StateTransition,
+}
+
+impl Step {
+    /// Name of the counter incremented each time the step runs.
+    pub fn counter(&self) -> &'static str {
+        match self {
+            Self::Sign => "farcaster_sign_total",
+            Self::EncryptSign => "farcaster_encrypt_sign_total",
+            Self::VerifyEncryptedSignature => "farcaster_verify_encrypted_signature_total",
+            Self::GenerateProof => "farcaster_generate_proof_total",
+            Self::VerifyProof => "farcaster_verify_proof_total",
+            Self::EncodeMessage => "farcaster_encode_message_total",
+            Self::DecodeMessage => "farcaster_decode_message_total",
+            Self::StateTransition => "farcaster_state_transition_total",
+        }
+    }
+
+    /// Name of the counter incremented each time the step fails.
+    pub fn failure_counter(&self) -> &'static str {
+        match self {
+            Self::Sign => "farcaster_sign_failures_total",
+            Self::EncryptSign => "farcaster_encrypt_sign_failures_total",
+            Self::VerifyEncryptedSignature => "farcaster_verify_encrypted_signature_failures_total",
+            Self::GenerateProof => "farcaster_generate_proof_failures_total",
+            Self::VerifyProof => "farcaster_verify_proof_failures_total",
+            Self::EncodeMessage => "farcaster_encode_message_failures_total",
+            Self::DecodeMessage => "farcaster_decode_message_failures_total",
+            Self::StateTransition => "farcaster_state_transition_failures_total",
+        }
+    }
+
+    /// Name of the histogram recording the duration of the step, in seconds.
+    pub fn histogram(&self) -> &'static str {
+        match self {
+            Self::Sign => "farcaster_sign_duration_seconds",
+            Self::EncryptSign => "farcaster_encrypt_sign_duration_seconds",
+            Self::VerifyEncryptedSignature => {
+                "farcaster_verify_encrypted_signature_duration_seconds"
+            }
+            Self::GenerateProof => "farcaster_generate_proof_duration_seconds",
+            Self::VerifyProof => "farcaster_verify_proof_duration_seconds",
+            Self::EncodeMessage => "farcaster_encode_message_duration_seconds",
+            Self::DecodeMessage => "farcaster_decode_message_duration_seconds",
+            Self::StateTransition => "farcaster_state_transition_duration_seconds",
+        }
+    }
+}
+
+/// Receives the spans and metrics of the instrumented steps, all methods default to doing
+/// nothing. Observers are shared between threads and must not block.
+pub trait Observer: Send + Sync {
+    /// A step started.
+    fn span_started(&self, _step: Step) {}
+
+    /// A step finished after `elapsed`, `success` is false if it returned an error.
+    fn span_finished(&self, _step: Step, _elapsed: Duration, _success: bool) {}
+
+    /// Increment the counter `name` by `value`.
+    fn increment_counter(&self, _name: &'static str, _value: u64) {}
+
+    /// Record `value` in the histogram `name`.
+    fn record_histogram(&self, _name: &'static str, _value: f64) {}
+}
+
+/// A handle reporting the instrumented steps of a component to its observer, if any.
+#[derive(Clone, Default)]
+pub struct Instrumentation {
+    #[cfg(feature = "instrument")]
+    observer: Option<Arc<dyn Observer>>,
+}
+
+impl std::fmt::Debug for Instrumentation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Instrumentation")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+impl Instrumentation {
+    /// Create a handle without observer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a handle reporting to the observer.
+    #[cfg(feature = "instrument")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "instrument")))]
+    pub fn with_observer(observer: Arc<dyn Observer>) -> Self {
+        Self {
+            observer: Some(observer),
+        }
+    }
+
+    /// Return true if the steps are reported to an observer.
+    pub fn is_enabled(&self) -> bool {
+        #[cfg(feature = "instrument")]
+        return self.observer.is_some();
+        #[cfg(not(feature = "instrument"))]
+        false
+    }
+
+    /// Run the step `f` in a span and report it.
+    pub fn record<T, E>(&self, step: Step, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        #[cfg(feature = "instrument")]
+        let _span = tracing::info_span!("step", %step).entered();
+        #[cfg(feature = "instrument")]
+        if let Some(observer) = &self.observer {
+            observer.span_started(step);
+            let start = Instant::now();
+            let res = f();
+            let elapsed = start.elapsed();
+            observer.span_finished(step, elapsed, res.is_ok());
+            observer.increment_counter(step.counter(), 1);
+            if res.is_err() {
+                observer.increment_counter(step.failure_counter(), 1);
+            }
+            observer.record_histogram(step.histogram(), elapsed.as_secs_f64());
+            return res;
+        }
+        #[cfg(not(feature = "instrument"))]
+        let _ = step;
+        f()
+    }
+
+    /// Encode a protocol message in a [`Step::EncodeMessage`] span.
+    pub fn encode<T: Encodable>(&self, message: &T) -> Vec<u8> {
+        self.record(Step::EncodeMessage, || {
+            Ok::<_, consensus::Error>(consensus::serialize(message))
+        })
+        .expect("encoding does not fail")
+    }
+
+    /// Decode a protocol message in a [`Step::DecodeMessage`] span.
+    pub fn decode<T: Decodable>(&self, bytes: &[u8]) -> Result<T, consensus::Error> {
+        self.record(Step::DecodeMessage, || consensus::deserialize(bytes))
+    }
+}
+
+#[cfg(all(test, feature = "instrument"))]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use crate::protocol::message::Abort;
+    use crate::Uuid;
+
+    #[derive(Default)]
+    struct Recorder {
+        spans: Mutex<Vec<(Step, bool)>>,
+        counters: Mutex<Vec<&'static str>>,
+        histograms: Mutex<Vec<&'static str>>,
+    }
+
+    impl Observer for Recorder {
+        fn span_finished(&self, step: Step, _elapsed: Duration, success: bool) {
+            self.spans.lock().unwrap().push((step, success));
+        }
+
+        fn increment_counter(&self, name: &'static str, _value: u64) {
+            self.counters.lock().unwrap().push(name);
+        }
+
+        fn record_histogram(&self, name: &'static str, _value: f64) {
+            self.histograms.lock().unwrap().push(name);
+        }
+    }
+
+    #[test]
+    fn report_steps_to_observer() {
+        let recorder = Arc::new(Recorder::default());
+        let instrumentation = Instrumentation::with_observer(recorder.clone());
+        assert!(instrumentation.is_enabled());
+        assert!(!Instrumentation::new().is_enabled());
+
+        let abort = Abort {
+            swap_id: Uuid::new().into(),
+            error_body: None,
+        };
+        let bytes = instrumentation.encode(&abort);
+        let decoded: Abort = instrumentation.decode(&bytes).unwrap();
+        assert_eq!(decoded.swap_id, abort.swap_id);
+        assert!(instrumentation.decode::<Abort>(&bytes[1..]).is_err());
+
+        assert_eq!(
+            *recorder.spans.lock().unwrap(),
+            vec![
+                (Step::EncodeMessage, true),
+                (Step::DecodeMessage, true),
+                (Step::DecodeMessage, false)
+            ]
+        );
+        assert_eq!(
+            *recorder.counters.lock().unwrap(),
+            vec![
+                "farcaster_encode_message_total",
+                "farcaster_decode_message_total",
+                "farcaster_decode_message_total",
+                "farcaster_decode_message_failures_total"
+            ]
+        );
+        assert_eq!(recorder.histograms.lock().unwrap().len(), 3);
+    }
+
+    #[derive(Default)]
+    struct SpanRecorder {
+        spans: Mutex<Vec<(&'static str, String)>>,
+        events: Mutex<Vec<tracing::Level>>,
+    }
+
+    struct Fields(String);
+
+    impl tracing::field::Visit for Fields {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.push_str(&format!("{}={} ", field.name(), value));
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!("{}={:?} ", field.name(), value));
+        }
+    }
+
+    impl tracing::Subscriber for SpanRecorder {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut fields = Fields(String::new());
+            span.record(&mut fields);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata().name(), fields.0.trim_end().to_string()));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            self.events.lock().unwrap().push(*event.metadata().level());
+        }
+
+        fn enter(&self, _span: &tracing::span::Id) {}
+
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    #[test]
+    fn emit_tracing_spans() {
+        use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+        use crate::bitcoin::BitcoinSegwitV0;
+        use crate::blockchain::FeePriority;
+        use crate::monero::Monero;
+        use crate::swap::btcxmr::Alice;
+
+        let recorder = Arc::new(SpanRecorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let abort = Abort {
+                swap_id: Uuid::new().into(),
+                error_body: None,
+            };
+            Instrumentation::new().encode(&abort);
+
+            let secp = Secp256k1::new();
+            let secret_key = SecretKey::from_slice(&[1; 32]).unwrap();
+            let alice = Alice::new(
+                BitcoinSegwitV0::new(),
+                Monero,
+                "bc1qesgvtyx9y6lax0x34napc2m7t5zdq6s7xxwpvk"
+                    .parse()
+                    .unwrap(),
+                FeePriority::Low,
+            );
+            let (signed, _) = alice.abort(&secp, abort, &secret_key, &[]).unwrap();
+            let peer_pubkey = PublicKey::from_secret_key(&secp, &secret_key);
+            assert!(alice
+                .handle_abort(&secp, &signed, &peer_pubkey, Uuid::new().into(), &[])
+                .is_err());
+        });
+
+        assert_eq!(
+            *recorder.spans.lock().unwrap(),
+            vec![
+                ("step", "step=EncodeMessage".to_string()),
+                ("abort", "role=alice".to_string()),
+                ("handle_abort", "role=alice".to_string()),
+            ]
+        );
+        // The failed transition is reported as an error event
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec![tracing::Level::ERROR]
+        );
+    }
+}
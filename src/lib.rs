@@ -32,6 +32,14 @@
 //!
 //! - `bitcoin`: support for Bitcoin, implementation of the `Arbitrating` blockchain role.
 //! - `monero`: support for Monero, implementation of the `Accordant` blockchain role.
+//! - `bitcoincash`: legacy P2SH lock and buy templates with replay protected signatures for
+//!   Bitcoin Cash, the `Arbitrating` transaction traits are not implemented and deals cannot be
+//!   made on it.
+//! - `ethereum`: helpers for the reference swap contract on Ethereum, call encoding and EIP-712
+//!   authorizations, the `Arbitrating` transaction traits are not implemented and deals cannot be
+//!   made on it.
+//! - `liquid`: confidential lock outputs and sighash for Liquid and Elements based chains, the
+//!   `Arbitrating` transaction traits are not implemented and deals cannot be made on it.
 //! - `swap/btcxmr`: definition of a swap between `bitcoin` and `monero` implementations.
 //!
 //! ### Adding blockchain support
@@ -50,10 +58,23 @@
 //! As default the `experimental` feature is enable.
 //!
 //! - **experimental**: enable experimental cryptography, i.e. not battle tested nor peer reviewed,
-//! use it at your own risks.
+//!   use it at your own risks.
+//! - **instrument**: report the duration and outcome of the protocol steps to an observer and
+//!   emit them, with the state transitions of Alice and Bob, as `tracing` spans, see
+//!   [`instrument`].
 //! - **taproot**: enable support for Bitcoin Taproot on-chain scripts as the arbitrating engine
-//! method.
+//!   method.
+//! - **vectors**: export the canonical test vectors of the Bitcoin-Monero swap to JSON, see
+//!   `swap::btcxmr::vectors`.
+//! - **zkp**: verify the openings, the rangeproofs and the surjection proofs of Liquid
+//!   confidential outputs with `secp256k1-zkp`, see `liquid::verify_opening` and
+//!   `liquid::verify_surjection`.
+//! - **std**: enable the whole framework and the blockchain implementations. Without it the crate
+//!   is `no_std` with `alloc` and only provides the pure computation layer in [`primitives`]:
+//!   message encoding, commitments, DLEQ proof and adaptor signature verification. Dependents
+//!   using `default-features = false` must enable `std` to keep the rest of the API.
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![cfg_attr(feature = "nightly", feature(stmt_expr_attributes))]
 // Coding conventions
@@ -63,40 +84,82 @@
 #![deny(unused_mut)]
 //#![deny(missing_docs)]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate amplify;
 
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate serde;
 
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate clap;
 
+#[cfg(feature = "std")]
 use std::io;
+#[cfg(feature = "std")]
 use std::str::FromStr;
 
+#[cfg(feature = "std")]
 use crate::consensus::{Decodable, Encodable};
 
+#[cfg(feature = "std")]
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
 use thiserror::Error;
 
+#[cfg(feature = "std")]
 #[macro_use]
 pub mod consensus;
+#[cfg(feature = "std")]
+#[macro_use]
+pub(crate) mod hash;
 
+#[cfg(all(feature = "std", any(test, feature = "test-utils")))]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-utils")))]
+pub mod arbitrary;
+#[cfg(feature = "std")]
 pub mod bitcoin;
+#[cfg(feature = "std")]
+pub mod bitcoincash;
+#[cfg(feature = "std")]
 pub mod blockchain;
+#[cfg(feature = "std")]
 pub mod crypto;
-pub(crate) mod hash;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
+pub mod ethereum;
+#[cfg(feature = "std")]
+pub mod instrument;
+#[cfg(feature = "std")]
+pub mod liquid;
+#[cfg(feature = "std")]
 pub mod monero;
+pub mod primitives;
+#[cfg(feature = "std")]
 pub mod protocol;
+#[cfg(feature = "std")]
 pub mod role;
+#[cfg(feature = "std")]
 pub mod script;
+#[cfg(feature = "std")]
 pub mod swap;
+#[cfg(feature = "std")]
+pub mod syncer;
+#[cfg(feature = "std")]
 pub mod trade;
+#[cfg(feature = "std")]
 pub mod transaction;
 
 /// A list of possible errors when performing a cross-chain atomic swap with the **Farcaster**
 /// software stack. Each error can have multiple level down to the blockchain implementation.
+///
+/// Errors carry a stable code and a retry class, see [`error::ErrorCode`].
+#[cfg(feature = "std")]
 #[derive(Error, Debug)]
 pub enum Error {
     /// A consensus error during encoding/decoding operation or data type missmatch.
@@ -116,21 +179,27 @@ pub enum Error {
     /// A trade error.
     #[error("Trade error: {0}")]
     Trade(#[from] trade::Error),
+    /// A swap error.
+    #[error("Swap error: {0}")]
+    Swap(#[from] swap::Error),
 }
 
 /// Result of an high level computation such as in Alice and Bob roles executing the protocol,
 /// wraps the crate level [`enum@Error`] type.
+#[cfg(feature = "std")]
 pub type Res<T> = Result<T, Error>;
 
 /// A unique identifier used to identify trades and swaps.
 ///
 /// This is a wrapper against `uuid::Uuid` with `StrictEncode` and `StrictDecode` implementation.
+#[cfg(feature = "std")]
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Display, Serialize, Deserialize,
 )]
 #[display(inner)]
 pub struct Uuid(uuid::Uuid);
 
+#[cfg(feature = "std")]
 impl Uuid {
     /// Creates a new uuid for trades and swaps.
     pub fn new() -> Self {
@@ -143,30 +212,35 @@ impl Uuid {
     }
 }
 
+#[cfg(feature = "std")]
 impl Default for Uuid {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[cfg(feature = "std")]
 impl From<uuid::Uuid> for Uuid {
     fn from(u: uuid::Uuid) -> Self {
         Self(u)
     }
 }
 
+#[cfg(feature = "std")]
 impl From<trade::DealId> for Uuid {
     fn from(id: trade::DealId) -> Self {
         id.0
     }
 }
 
+#[cfg(feature = "std")]
 impl From<swap::SwapId> for Uuid {
     fn from(id: swap::SwapId) -> Self {
         id.0
     }
 }
 
+#[cfg(feature = "std")]
 impl FromStr for Uuid {
     type Err = uuid::Error;
 
@@ -175,18 +249,29 @@ impl FromStr for Uuid {
     }
 }
 
+/// Format the uuid as 32 lowercase hex digits without hyphens, parsable with `FromStr`.
+#[cfg(feature = "std")]
+impl std::fmt::LowerHex for Uuid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.as_simple())
+    }
+}
+
+#[cfg(feature = "std")]
 impl AsRef<uuid::Uuid> for Uuid {
     fn as_ref(&self) -> &uuid::Uuid {
         &self.0
     }
 }
 
+#[cfg(feature = "std")]
 impl Encodable for Uuid {
     fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
         self.0.to_bytes_le().consensus_encode(s)
     }
 }
 
+#[cfg(feature = "std")]
 impl Decodable for Uuid {
     fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
         Ok(Self(uuid::Uuid::from_bytes_le(
@@ -195,12 +280,14 @@ impl Decodable for Uuid {
     }
 }
 
+#[cfg(feature = "std")]
 impl strict_encoding::StrictEncode for Uuid {
     fn strict_encode<E: io::Write>(&self, mut e: E) -> Result<usize, strict_encoding::Error> {
         self.as_ref().to_bytes_le().strict_encode(&mut e)
     }
 }
 
+#[cfg(feature = "std")]
 impl strict_encoding::StrictDecode for Uuid {
     fn strict_decode<D: io::Read>(mut d: D) -> Result<Self, strict_encoding::Error> {
         Ok(Self(uuid::Uuid::from_bytes_le(<[u8; 16]>::strict_decode(
@@ -209,7 +296,7 @@ impl strict_encoding::StrictDecode for Uuid {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::Uuid;
     use uuid::uuid;
@@ -224,7 +311,71 @@ mod tests {
     #[test]
     fn deserialize_swapid_from_yaml() {
         let s = "---\n67e55044-10b1-426f-9247-bb680e5fe0c8\n";
-        let id: Uuid = serde_yaml::from_str(&s).expect("Decode uuid from yaml");
+        let id: Uuid = serde_yaml::from_str(s).expect("Decode uuid from yaml");
         assert_eq!(id, uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8").into(),);
     }
+
+    #[test]
+    fn identifiers_round_trip_as_strings() {
+        use crate::crypto::{
+            commitment::tagged_hash, AccordantKeyId, ArbitratingKeyId, KeccakCommitment,
+            SharedKeyId,
+        };
+        use crate::swap::SwapId;
+        use crate::trade::{DealFingerprint, DealId};
+        use std::str::FromStr;
+
+        let id: SwapId = uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8").into();
+        assert_eq!(format!("{:x}", id), "67e5504410b1426f9247bb680e5fe0c8");
+        assert_eq!(SwapId::from_str(&format!("{:x}", id)).unwrap(), id);
+        assert_eq!(SwapId::from_str(&id.to_string()).unwrap(), id);
+        let deal_id = DealId(id.0);
+        assert_eq!(
+            DealId::from_str(&format!("{:x}", deal_id)).unwrap(),
+            deal_id
+        );
+
+        let commitment = KeccakCommitment::new([0xab; 32]);
+        assert_eq!(
+            KeccakCommitment::from_str(&format!("{:x}", commitment)).unwrap(),
+            commitment
+        );
+        let s = serde_yaml::to_string(&commitment).unwrap();
+        assert_eq!(s, format!("---\n\"{:#x}\"\n", commitment));
+        assert_eq!(
+            serde_yaml::from_str::<KeccakCommitment>(&s).unwrap(),
+            commitment
+        );
+        let tagged = tagged_hash(b"tag", b"data");
+        assert_eq!(
+            serde_yaml::from_str::<crate::crypto::commitment::TaggedCommitment>(
+                &serde_yaml::to_string(&tagged).unwrap()
+            )
+            .unwrap(),
+            tagged
+        );
+        let fingerprint = DealFingerprint::from([7u8; 32]);
+        assert_eq!(
+            DealFingerprint::from_str(&format!("{:#x}", fingerprint)).unwrap(),
+            fingerprint
+        );
+
+        for key in [ArbitratingKeyId::Buy, ArbitratingKeyId::Extra(12)] {
+            assert_eq!(
+                ArbitratingKeyId::from_str(&key.to_string())
+                    .unwrap()
+                    .to_string(),
+                key.to_string()
+            );
+        }
+        assert!(matches!(
+            AccordantKeyId::from_str("Extra(3)"),
+            Ok(AccordantKeyId::Extra(3))
+        ));
+        assert_eq!(
+            SharedKeyId::from_str(&SharedKeyId::new(2).to_string()).unwrap(),
+            SharedKeyId::new(2)
+        );
+        assert!(ArbitratingKeyId::from_str("Extra(x)").is_err());
+    }
 }
@@ -0,0 +1,1194 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Implementation for Liquid, and more generally Elements based blockchains, as an arbitrating
+//! blockchain in a swap. Elements reuses the Bitcoin scripts, e.g.
+//! [`CoopLock`](crate::bitcoin::segwitv0::CoopLock), but outputs carry an asset in addition to
+//! the value, and both can be blinded with confidential transactions.
+//!
+//! A blinded output only reveals Pedersen commitments of its asset and value, the amount locked
+//! is proven with a rangeproof and the asset with a surjection proof over the assets of the
+//! transaction inputs. Blinding and unblinding outputs, i.e. deriving the blinding factors from
+//! the blinding keys, is provided by the client through the [`Blinder`] trait, e.g. backed by
+//! `rust-elements`. Outputs with an explicit asset and value are verified without a blinder.
+//!
+//! # Scope
+//!
+//! This module provides the lock output creation and verification and the Elements SegWit
+//! version 0 sighash, see [`Transaction::segwit_v0_sighash`], which differs from
+//! [BIP-143](https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki). The swap
+//! transaction templates of [`crate::bitcoin::segwitv0`] are built on Bitcoin partial
+//! transactions and cannot carry confidential outputs, [`Liquid`] does not implement
+//! [`Transactions`](crate::blockchain::Transactions) and is not a
+//! [`Blockchain`](crate::blockchain::Blockchain) deals can be made on: the client builds and signs
+//! the Elements transactions with the scripts and the sighash of this module.
+//!
+//! # Trust boundary
+//!
+//! With the `zkp` feature the crate does not trust the secrets returned by the blinder: the asset
+//! generator and the value commitment are recomputed from the opened asset, value, and blinding
+//! factors and must match the output, the rangeproof is verified against the commitment, see
+//! [`verify_opening`], and the surjection proof is verified against the input assets, see
+//! [`verify_surjection`]. Without the feature the `secp256k1-zkp` primitives are not available and
+//! the proofs of a blinded output cannot be verified, [`verify_lock`] then rejects blinded outputs
+//! with [`Error::Unverifiable`] and only explicit outputs can be verified.
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{EcdsaSighashType, OutPoint};
+use bitcoin::hashes::sha256d::Hash as Sha256dHash;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use bitcoin::Amount;
+use thiserror::Error;
+
+#[cfg(feature = "zkp")]
+use crate::bitcoin::segwitv0::verification_context;
+use crate::blockchain::Network;
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::{DeriveKeys, SharedKeyId};
+
+/// Errors when verifying confidential outputs.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The output does not pay to the expected script.
+    #[error("The output does not pay to the lock script")]
+    ScriptMismatch,
+    /// The output does not hold the expected asset.
+    #[error("The output holds asset {found} while {expected} is expected")]
+    AssetMismatch {
+        /// The expected asset.
+        expected: AssetId,
+        /// The asset found in the output.
+        found: AssetId,
+    },
+    /// The output holds less than the expected amount.
+    #[error("The output holds {found} while {expected} is expected")]
+    Underpaid {
+        /// The expected amount.
+        expected: Amount,
+        /// The amount found in the output.
+        found: Amount,
+    },
+    /// The output is blinded but carries no rangeproof.
+    #[error("The blinded output has no rangeproof")]
+    MissingRangeproof,
+    /// The output is blinded and no blinder is available to unblind it.
+    #[error("The output is blinded and cannot be unblinded")]
+    Blinded,
+    /// The output cannot be unblinded or its proofs are invalid.
+    #[error("Unblinding failed: {0}")]
+    Unblinding(String),
+    /// The opened asset or value do not match the commitments of the output.
+    #[error("The secrets do not open the output commitments")]
+    InvalidOpening,
+    /// The rangeproof is invalid or does not cover the opened value.
+    #[error("Invalid rangeproof")]
+    InvalidRangeproof,
+    /// The output asset is blinded but carries no surjection proof.
+    #[error("The blinded output has no surjection proof")]
+    MissingSurjectionProof,
+    /// The surjection proof is invalid or does not prove the asset is one of the input assets.
+    #[error("Invalid surjection proof")]
+    InvalidSurjectionProof,
+    /// The output holds more than the expected amount.
+    #[error("The output holds {found} while exactly {expected} is expected")]
+    Overpaid {
+        /// The expected amount.
+        expected: Amount,
+        /// The amount found in the output.
+        found: Amount,
+    },
+    /// The transaction has no input at the given index.
+    #[error("The transaction has no input at index {0}")]
+    InputOutOfBounds(usize),
+    /// The output is blinded and its proofs cannot be verified without the `zkp` feature.
+    #[error("The blinded output cannot be verified without the zkp feature")]
+    Unverifiable,
+}
+
+/// An Elements asset identifier, displayed in reversed byte order like transaction ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AssetId(pub [u8; 32]);
+
+impl AssetId {
+    /// Return the policy asset, i.e. L-BTC, of Liquid on the given network. Elements regtest
+    /// chains define their own policy asset and return `None`.
+    pub fn policy_asset(network: Network) -> Option<Self> {
+        match network {
+            Network::Mainnet => {
+                Self::from_str("6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d")
+                    .ok()
+            }
+            Network::Testnet => {
+                Self::from_str("144c654344aa716d6f3abcc1ca90e5641e4e2a7f633bc09fe3baf64585819a49")
+                    .ok()
+            }
+            Network::Local => None,
+        }
+    }
+}
+
+impl fmt::Display for AssetId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut bytes = self.0;
+        bytes.reverse();
+        write!(f, "{}", hex::encode(bytes))
+    }
+}
+
+impl FromStr for AssetId {
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes: [u8; 32] = hex::decode(s)
+            .map_err(consensus::Error::new)?
+            .try_into()
+            .map_err(|_| consensus::Error::ParseFailed("Asset id must be 32 bytes"))?;
+        bytes.reverse();
+        Ok(Self(bytes))
+    }
+}
+
+/// The asset of an output, explicit or blinded with a commitment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfidentialAsset {
+    /// No asset, only used in coinbase outputs.
+    Null,
+    /// An explicit asset.
+    Explicit(AssetId),
+    /// A blinded asset generator.
+    Confidential([u8; 33]),
+}
+
+/// The value of an output, explicit or blinded with a Pedersen commitment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfidentialValue {
+    /// No value.
+    Null,
+    /// An explicit value.
+    Explicit(Amount),
+    /// A Pedersen commitment to the value.
+    Confidential([u8; 33]),
+}
+
+fn decode_commitment<D: io::Read>(
+    d: &mut D,
+    prefix: u8,
+    valid_prefixes: [u8; 2],
+) -> Result<[u8; 33], consensus::Error> {
+    if !valid_prefixes.contains(&prefix) {
+        return Err(consensus::Error::ParseFailed("Invalid commitment prefix"));
+    }
+    let mut commitment = [prefix; 33];
+    d.read_exact(&mut commitment[1..])?;
+    Ok(commitment)
+}
+
+impl Encodable for ConfidentialAsset {
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        match self {
+            Self::Null => 0u8.consensus_encode(s),
+            Self::Explicit(asset) => {
+                s.write_all(&[1])?;
+                s.write_all(&asset.0)?;
+                Ok(33)
+            }
+            Self::Confidential(commitment) => {
+                s.write_all(commitment)?;
+                Ok(33)
+            }
+        }
+    }
+}
+
+impl Decodable for ConfidentialAsset {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match u8::consensus_decode(d)? {
+            0 => Ok(Self::Null),
+            1 => {
+                let mut asset = [0u8; 32];
+                d.read_exact(&mut asset)?;
+                Ok(Self::Explicit(AssetId(asset)))
+            }
+            prefix => Ok(Self::Confidential(decode_commitment(
+                d,
+                prefix,
+                [0x0a, 0x0b],
+            )?)),
+        }
+    }
+}
+
+impl Encodable for ConfidentialValue {
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        match self {
+            Self::Null => 0u8.consensus_encode(s),
+            Self::Explicit(amount) => {
+                // Explicit values are serialized in big-endian
+                s.write_all(&[1])?;
+                s.write_all(&amount.as_sat().to_be_bytes())?;
+                Ok(9)
+            }
+            Self::Confidential(commitment) => {
+                s.write_all(commitment)?;
+                Ok(33)
+            }
+        }
+    }
+}
+
+impl Decodable for ConfidentialValue {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match u8::consensus_decode(d)? {
+            0 => Ok(Self::Null),
+            1 => {
+                let mut value = [0u8; 8];
+                d.read_exact(&mut value)?;
+                Ok(Self::Explicit(Amount::from_sat(u64::from_be_bytes(value))))
+            }
+            prefix => Ok(Self::Confidential(decode_commitment(
+                d,
+                prefix,
+                [0x08, 0x09],
+            )?)),
+        }
+    }
+}
+
+impl_strict_encoding!(ConfidentialAsset);
+impl_strict_encoding!(ConfidentialValue);
+
+/// An Elements transaction output with its witness proofs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxOut {
+    /// The asset held by the output.
+    pub asset: ConfidentialAsset,
+    /// The value held by the output.
+    pub value: ConfidentialValue,
+    /// The ephemeral public key used by the receiver to derive the blinding factors, if blinded.
+    pub nonce: Option<PublicKey>,
+    /// The script locking the output.
+    pub script_pubkey: Script,
+    /// The rangeproof of the value commitment, empty if the value is explicit.
+    pub rangeproof: Vec<u8>,
+    /// The surjection proof of the asset commitment, empty if the asset is explicit.
+    pub surjection_proof: Vec<u8>,
+}
+
+impl TxOut {
+    /// Create an output with an explicit asset and value.
+    pub fn explicit(script_pubkey: Script, asset: AssetId, value: Amount) -> Self {
+        Self {
+            asset: ConfidentialAsset::Explicit(asset),
+            value: ConfidentialValue::Explicit(value),
+            nonce: None,
+            script_pubkey,
+            rangeproof: vec![],
+            surjection_proof: vec![],
+        }
+    }
+
+    /// Return `true` if the asset or the value of the output is blinded.
+    pub fn is_confidential(&self) -> bool {
+        matches!(self.asset, ConfidentialAsset::Confidential(_))
+            || matches!(self.value, ConfidentialValue::Confidential(_))
+    }
+}
+
+impl Encodable for TxOut {
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.asset.consensus_encode(s)?;
+        len += self.value.consensus_encode(s)?;
+        len += self.nonce.as_canonical_bytes().consensus_encode(s)?;
+        len += self.script_pubkey.as_bytes().to_vec().consensus_encode(s)?;
+        len += self.rangeproof.consensus_encode(s)?;
+        Ok(len + self.surjection_proof.consensus_encode(s)?)
+    }
+}
+
+impl Decodable for TxOut {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            asset: Decodable::consensus_decode(d)?,
+            value: Decodable::consensus_decode(d)?,
+            nonce: Option::<PublicKey>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            script_pubkey: Script::from(Vec::<u8>::consensus_decode(d)?),
+            rangeproof: Decodable::consensus_decode(d)?,
+            surjection_proof: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(TxOut);
+
+impl TxOut {
+    /// Serialize the output as committed in the signature hash, i.e. without its witness proofs.
+    fn sighash_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.asset.consensus_encode(s)?;
+        len += self.value.consensus_encode(s)?;
+        len += match self.nonce {
+            Some(nonce) => {
+                s.write_all(&nonce.serialize())?;
+                33
+            }
+            None => 0u8.consensus_encode(s)?,
+        };
+        Ok(len + bitcoin::consensus::Encodable::consensus_encode(&self.script_pubkey, s)?)
+    }
+}
+
+/// An Elements transaction input. Asset issuances and peg-ins are not used by swap transactions
+/// and are not supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxIn {
+    /// The output spent by the input.
+    pub previous_output: OutPoint,
+    /// The script signature, empty for SegWit inputs.
+    pub script_sig: Script,
+    /// The sequence number of the input.
+    pub sequence: u32,
+}
+
+/// An Elements transaction, without the witnesses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    /// The transaction version.
+    pub version: u32,
+    /// The transaction lock time.
+    pub lock_time: u32,
+    /// The inputs of the transaction.
+    pub input: Vec<TxIn>,
+    /// The outputs of the transaction, explicit or blinded.
+    pub output: Vec<TxOut>,
+}
+
+impl Transaction {
+    /// Computes the SegWit version 0 sighash of the input at `input_index` spending an output
+    /// locked with `script` and holding `value`.
+    ///
+    /// Elements modifies [BIP-143][bip-143]: the asset issuances of the inputs are committed after
+    /// the sequences, the outputs commit to their asset, value and nonce, and the spent value is
+    /// serialized as a confidential value, so the Bitcoin sighash cannot be reused.
+    ///
+    /// [bip-143]: https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
+    pub fn segwit_v0_sighash(
+        &self,
+        input_index: usize,
+        script: &Script,
+        value: ConfidentialValue,
+        sighash_type: EcdsaSighashType,
+    ) -> Result<Sha256dHash, Error> {
+        let input = self
+            .input
+            .get(input_index)
+            .ok_or(Error::InputOutOfBounds(input_index))?;
+        let hash_type = sighash_type.to_u32();
+        let anyone_can_pay = hash_type & 0x80 != 0;
+        let base = hash_type & 0x1f;
+        let is_single = base == EcdsaSighashType::Single.to_u32();
+        let is_none = base == EcdsaSighashType::None.to_u32();
+
+        let hash_prevouts = if anyone_can_pay {
+            Sha256dHash::default()
+        } else {
+            let mut engine = Sha256dHash::engine();
+            for input in &self.input {
+                bitcoin::consensus::Encodable::consensus_encode(
+                    &input.previous_output,
+                    &mut engine,
+                )
+                .expect("engines don't error");
+            }
+            Sha256dHash::from_engine(engine)
+        };
+
+        let hash_sequence = if anyone_can_pay || is_single || is_none {
+            Sha256dHash::default()
+        } else {
+            let mut engine = Sha256dHash::engine();
+            for input in &self.input {
+                engine.input(&input.sequence.to_le_bytes());
+            }
+            Sha256dHash::from_engine(engine)
+        };
+
+        // Inputs without issuance commit to a null byte
+        let hash_issuances = if anyone_can_pay {
+            Sha256dHash::default()
+        } else {
+            Sha256dHash::hash(&vec![0u8; self.input.len()])
+        };
+
+        let hash_outputs = if !is_single && !is_none {
+            let mut engine = Sha256dHash::engine();
+            for output in &self.output {
+                output
+                    .sighash_encode(&mut engine)
+                    .expect("engines don't error");
+            }
+            Sha256dHash::from_engine(engine)
+        } else if is_single && input_index < self.output.len() {
+            let mut engine = Sha256dHash::engine();
+            self.output[input_index]
+                .sighash_encode(&mut engine)
+                .expect("engines don't error");
+            Sha256dHash::from_engine(engine)
+        } else {
+            Sha256dHash::default()
+        };
+
+        let mut engine = Sha256dHash::engine();
+        (|| -> Result<(), io::Error> {
+            engine.input(&self.version.to_le_bytes());
+            engine.input(&hash_prevouts[..]);
+            engine.input(&hash_sequence[..]);
+            engine.input(&hash_issuances[..]);
+            bitcoin::consensus::Encodable::consensus_encode(&input.previous_output, &mut engine)?;
+            bitcoin::consensus::Encodable::consensus_encode(script, &mut engine)?;
+            value.consensus_encode(&mut engine)?;
+            engine.input(&input.sequence.to_le_bytes());
+            engine.input(&hash_outputs[..]);
+            engine.input(&self.lock_time.to_le_bytes());
+            engine.input(&hash_type.to_le_bytes());
+            Ok(())
+        })()
+        .expect("engines don't error");
+        Ok(Sha256dHash::from_engine(engine))
+    }
+}
+
+/// The opened asset and value of a blinded output with their blinding factors.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TxOutSecrets {
+    /// The explicit asset.
+    pub asset: AssetId,
+    /// The asset blinding factor.
+    pub asset_blinder: [u8; 32],
+    /// The explicit value.
+    pub value: Amount,
+    /// The value blinding factor.
+    pub value_blinder: [u8; 32],
+}
+
+impl fmt::Debug for TxOutSecrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TxOutSecrets")
+            .field("asset", &self.asset)
+            .field("value", &self.value)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Confidential transactions primitives provided by the client. The secrets returned by
+/// [`Blinder::unblind`] are not trusted, they are checked against the output with the `zkp`
+/// feature.
+pub trait Blinder {
+    /// Blind an explicit output for the `blinding_key` of the receiver.
+    fn blind(&self, output: &TxOut, blinding_key: &PublicKey) -> Result<TxOut, Error>;
+
+    /// Unblind an output with the private blinding key of the receiver, verifying its proofs, and
+    /// return the opened asset and value.
+    fn unblind(&self, output: &TxOut) -> Result<TxOutSecrets, Error>;
+}
+
+/// Create the lock output paying `amount` of `asset` to `lock_script`. The output is blinded if
+/// a blinder and the blinding key of the receiver are given, otherwise the asset and the value
+/// are explicit.
+pub fn lock_output<B>(
+    lock_script: Script,
+    asset: AssetId,
+    amount: Amount,
+    blinding: Option<(&B, &PublicKey)>,
+) -> Result<TxOut, Error>
+where
+    B: Blinder,
+{
+    let output = TxOut::explicit(lock_script, asset, amount);
+    match blinding {
+        Some((blinder, key)) => blinder.blind(&output, key),
+        None => Ok(output),
+    }
+}
+
+/// Verify that `output` locks exactly `amount` of `asset` in `lock_script`. Explicit outputs
+/// are verified directly, blinded outputs require a blinder able to unblind them and a surjection
+/// proof over `input_assets`, the assets of the outputs spent by the lock transaction. The
+/// secrets are checked against the output with [`verify_opening`] and the surjection proof with
+/// [`verify_surjection`], without the `zkp` feature blinded outputs are rejected with
+/// [`Error::Unverifiable`]. Return the locked amount.
+#[cfg_attr(not(feature = "zkp"), allow(unused_variables))]
+pub fn verify_lock<B>(
+    output: &TxOut,
+    lock_script: &Script,
+    asset: AssetId,
+    amount: Amount,
+    input_assets: &[ConfidentialAsset],
+    blinder: Option<&B>,
+) -> Result<Amount, Error>
+where
+    B: Blinder,
+{
+    (&output.script_pubkey == lock_script)
+        .then(|| ())
+        .ok_or(Error::ScriptMismatch)?;
+
+    let (found_asset, found) = match (output.asset, output.value) {
+        (ConfidentialAsset::Explicit(a), ConfidentialValue::Explicit(v)) => (a, v),
+        _ if output.is_confidential() => {
+            if output.rangeproof.is_empty() {
+                return Err(Error::MissingRangeproof);
+            }
+            if matches!(output.asset, ConfidentialAsset::Confidential(_))
+                && output.surjection_proof.is_empty()
+            {
+                return Err(Error::MissingSurjectionProof);
+            }
+            #[cfg(not(feature = "zkp"))]
+            return Err(Error::Unverifiable);
+            #[cfg(feature = "zkp")]
+            {
+                let secrets = blinder.ok_or(Error::Blinded)?.unblind(output)?;
+                verify_opening(output, &secrets)?;
+                verify_surjection(output, input_assets)?;
+                (secrets.asset, secrets.value)
+            }
+        }
+        _ => return Err(Error::Unblinding("Null asset or value".into())),
+    };
+
+    (found_asset == asset)
+        .then(|| ())
+        .ok_or(Error::AssetMismatch {
+            expected: asset,
+            found: found_asset,
+        })?;
+    (found <= amount).then(|| ()).ok_or(Error::Overpaid {
+        expected: amount,
+        found,
+    })?;
+    (found >= amount).then(|| found).ok_or(Error::Underpaid {
+        expected: amount,
+        found,
+    })
+}
+
+/// Verify that `secrets` open the asset and value commitments of `output`, and that the
+/// rangeproof of the value commitment covers the opened value. The rangeproof commits to the
+/// script of the output, as in Elements.
+#[cfg(feature = "zkp")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zkp")))]
+pub fn verify_opening(output: &TxOut, secrets: &TxOutSecrets) -> Result<(), Error> {
+    use secp256k1_zkp::{Generator, PedersenCommitment, RangeProof, Tag, Tweak};
+
+    let secp = verification_context();
+    let tag = Tag::from(secrets.asset.0);
+    let generator = match output.asset {
+        ConfidentialAsset::Explicit(asset) if asset == secrets.asset => {
+            Generator::new_unblinded(secp, tag)
+        }
+        ConfidentialAsset::Confidential(commitment) => {
+            let generator =
+                Generator::from_slice(&commitment).map_err(|_| Error::InvalidOpening)?;
+            let blinder =
+                Tweak::from_inner(secrets.asset_blinder).map_err(|_| Error::InvalidOpening)?;
+            (Generator::new_blinded(secp, tag, blinder) == generator)
+                .then(|| generator)
+                .ok_or(Error::InvalidOpening)?
+        }
+        _ => return Err(Error::InvalidOpening),
+    };
+
+    match output.value {
+        ConfidentialValue::Explicit(value) => (value == secrets.value)
+            .then(|| ())
+            .ok_or(Error::InvalidOpening),
+        ConfidentialValue::Confidential(commitment) => {
+            let commitment =
+                PedersenCommitment::from_slice(&commitment).map_err(|_| Error::InvalidOpening)?;
+            let blinder =
+                Tweak::from_inner(secrets.value_blinder).map_err(|_| Error::InvalidOpening)?;
+            let value = secrets.value.as_sat();
+            (PedersenCommitment::new(secp, value, blinder, generator) == commitment)
+                .then(|| ())
+                .ok_or(Error::InvalidOpening)?;
+            let range = RangeProof::from_slice(&output.rangeproof)
+                .and_then(|proof| {
+                    proof.verify(secp, commitment, output.script_pubkey.as_bytes(), generator)
+                })
+                .map_err(|_| Error::InvalidRangeproof)?;
+            range
+                .contains(&value)
+                .then(|| ())
+                .ok_or(Error::InvalidRangeproof)
+        }
+        ConfidentialValue::Null => Err(Error::InvalidOpening),
+    }
+}
+
+/// Verify that the surjection proof of `output` proves its asset is one of `input_assets`, the
+/// assets of the outputs spent by the transaction. Outputs with an explicit asset need no proof.
+#[cfg(feature = "zkp")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zkp")))]
+pub fn verify_surjection(output: &TxOut, input_assets: &[ConfidentialAsset]) -> Result<(), Error> {
+    use secp256k1_zkp::{Generator, SurjectionProof, Tag};
+
+    let secp = verification_context();
+    let codomain = match output.asset {
+        ConfidentialAsset::Explicit(_) => return Ok(()),
+        ConfidentialAsset::Confidential(commitment) => {
+            Generator::from_slice(&commitment).map_err(|_| Error::InvalidSurjectionProof)?
+        }
+        ConfidentialAsset::Null => return Err(Error::InvalidSurjectionProof),
+    };
+    let domain = input_assets
+        .iter()
+        .map(|asset| match asset {
+            ConfidentialAsset::Explicit(asset) => {
+                Ok(Generator::new_unblinded(secp, Tag::from(asset.0)))
+            }
+            ConfidentialAsset::Confidential(commitment) => {
+                Generator::from_slice(commitment).map_err(|_| Error::InvalidSurjectionProof)
+            }
+            ConfidentialAsset::Null => Err(Error::InvalidSurjectionProof),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let proof = SurjectionProof::from_slice(&output.surjection_proof)
+        .map_err(|_| Error::InvalidSurjectionProof)?;
+    proof
+        .verify(secp, codomain, &domain)
+        .then(|| ())
+        .ok_or(Error::InvalidSurjectionProof)
+}
+
+/// The Liquid blockchain, reusing the Bitcoin keys and scripts.
+#[derive(Clone, Debug, Copy, Eq, PartialEq)]
+pub struct Liquid;
+
+impl Decodable for Liquid {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match Decodable::consensus_decode(d)? {
+            0x800006f0u32 => Ok(Self),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl Encodable for Liquid {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        0x800006f0u32.consensus_encode(writer)
+    }
+}
+
+impl FromStr for Liquid {
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Liquid" | "liquid" | "lbtc" => Ok(Liquid),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl fmt::Display for Liquid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "lbtc")
+    }
+}
+
+impl DeriveKeys for Liquid {
+    type PublicKey = PublicKey;
+    type PrivateKey = SecretKey;
+
+    fn extra_public_keys() -> Vec<u16> {
+        // No extra key
+        vec![]
+    }
+
+    fn extra_shared_private_keys() -> Vec<SharedKeyId> {
+        // No shared key
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::Secp256k1;
+
+    fn lbtc() -> AssetId {
+        AssetId::policy_asset(Network::Mainnet).unwrap()
+    }
+
+    /// Mock blinder that commits to nothing and opens the explicit output it blinded.
+    struct MockBlinder;
+
+    impl Blinder for MockBlinder {
+        fn blind(&self, output: &TxOut, blinding_key: &PublicKey) -> Result<TxOut, Error> {
+            let value = match output.value {
+                ConfidentialValue::Explicit(v) => v,
+                _ => return Err(Error::Unblinding("Already blinded".into())),
+            };
+            let mut commitment = [0x08; 33];
+            commitment[1..9].copy_from_slice(&value.as_sat().to_be_bytes());
+            Ok(TxOut {
+                asset: ConfidentialAsset::Confidential([0x0a; 33]),
+                value: ConfidentialValue::Confidential(commitment),
+                nonce: Some(*blinding_key),
+                script_pubkey: output.script_pubkey.clone(),
+                rangeproof: vec![1; 32],
+                surjection_proof: vec![2; 32],
+            })
+        }
+
+        fn unblind(&self, output: &TxOut) -> Result<TxOutSecrets, Error> {
+            match output.value {
+                ConfidentialValue::Confidential(c) => Ok(TxOutSecrets {
+                    asset: lbtc(),
+                    asset_blinder: [0; 32],
+                    value: Amount::from_sat(u64::from_be_bytes(c[1..9].try_into().unwrap())),
+                    value_blinder: [0; 32],
+                }),
+                _ => Err(Error::Unblinding("Not blinded".into())),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_policy_asset() {
+        let asset = lbtc();
+        assert_eq!(
+            asset.to_string(),
+            "6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d"
+        );
+        assert_eq!(asset.0[0], 0x6d);
+        assert!(AssetId::policy_asset(Network::Local).is_none());
+    }
+
+    #[test]
+    fn verify_explicit_lock() {
+        let script = Script::new_op_return(&[1]);
+        let output =
+            lock_output::<MockBlinder>(script.clone(), lbtc(), Amount::from_sat(1_000), None)
+                .unwrap();
+        assert!(!output.is_confidential());
+        assert_eq!(
+            verify_lock::<MockBlinder>(
+                &output,
+                &script,
+                lbtc(),
+                Amount::from_sat(1_000),
+                &[],
+                None
+            ),
+            Ok(Amount::from_sat(1_000))
+        );
+        assert_eq!(
+            verify_lock::<MockBlinder>(
+                &output,
+                &script,
+                lbtc(),
+                Amount::from_sat(1_001),
+                &[],
+                None
+            ),
+            Err(Error::Underpaid {
+                expected: Amount::from_sat(1_001),
+                found: Amount::from_sat(1_000)
+            })
+        );
+        assert_eq!(
+            verify_lock::<MockBlinder>(&output, &script, lbtc(), Amount::from_sat(999), &[], None),
+            Err(Error::Overpaid {
+                expected: Amount::from_sat(999),
+                found: Amount::from_sat(1_000)
+            })
+        );
+        let other = AssetId([1; 32]);
+        assert_eq!(
+            verify_lock::<MockBlinder>(&output, &script, other, Amount::from_sat(1_000), &[], None),
+            Err(Error::AssetMismatch {
+                expected: other,
+                found: lbtc()
+            })
+        );
+        assert_eq!(
+            verify_lock::<MockBlinder>(
+                &output,
+                &Script::new(),
+                lbtc(),
+                Amount::from_sat(1_000),
+                &[],
+                None
+            ),
+            Err(Error::ScriptMismatch)
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "zkp"))]
+    fn reject_blinded_lock() {
+        let secp = Secp256k1::new();
+        let key = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let script = Script::new_op_return(&[1]);
+        let inputs = [ConfidentialAsset::Explicit(lbtc())];
+        let mut output = lock_output(
+            script.clone(),
+            lbtc(),
+            Amount::from_sat(1_000),
+            Some((&MockBlinder, &key)),
+        )
+        .unwrap();
+        assert!(output.is_confidential());
+        // The secrets of the blinder cannot be checked against the output
+        assert_eq!(
+            verify_lock(
+                &output,
+                &script,
+                lbtc(),
+                Amount::from_sat(1_000),
+                &inputs,
+                Some(&MockBlinder)
+            ),
+            Err(Error::Unverifiable)
+        );
+        let mut unproven = output.clone();
+        unproven.surjection_proof.clear();
+        assert_eq!(
+            verify_lock(
+                &unproven,
+                &script,
+                lbtc(),
+                Amount::from_sat(1_000),
+                &inputs,
+                Some(&MockBlinder)
+            ),
+            Err(Error::MissingSurjectionProof)
+        );
+        output.rangeproof.clear();
+        assert_eq!(
+            verify_lock(
+                &output,
+                &script,
+                lbtc(),
+                Amount::from_sat(1_000),
+                &inputs,
+                Some(&MockBlinder)
+            ),
+            Err(Error::MissingRangeproof)
+        );
+    }
+
+    /// Blinder with fixed blinding factors, optionally lying on the opened value. The surjection
+    /// proof is created over a single explicit input holding the same asset.
+    #[cfg(feature = "zkp")]
+    struct ZkpBlinder {
+        lie: u64,
+    }
+
+    #[cfg(feature = "zkp")]
+    impl Blinder for ZkpBlinder {
+        fn blind(&self, output: &TxOut, _blinding_key: &PublicKey) -> Result<TxOut, Error> {
+            use secp256k1_zkp::rand::rngs::mock::StepRng;
+            use secp256k1_zkp::{
+                Generator, PedersenCommitment, RangeProof, SurjectionProof, Tag, Tweak, ZERO_TWEAK,
+            };
+
+            let secp = Secp256k1::new();
+            let (asset, value) = match (output.asset, output.value) {
+                (ConfidentialAsset::Explicit(a), ConfidentialValue::Explicit(v)) => (a, v),
+                _ => return Err(Error::Unblinding("Already blinded".into())),
+            };
+            let tag = Tag::from(asset.0);
+            let asset_blinder = Tweak::from_inner([3; 32]).unwrap();
+            let generator = Generator::new_blinded(&secp, tag, asset_blinder);
+            let blinder = Tweak::from_inner([4; 32]).unwrap();
+            let commitment = PedersenCommitment::new(&secp, value.as_sat(), blinder, generator);
+            let rangeproof = RangeProof::new(
+                &secp,
+                1,
+                commitment,
+                value.as_sat(),
+                blinder,
+                &[],
+                output.script_pubkey.as_bytes(),
+                SecretKey::from_slice(&[5; 32]).unwrap(),
+                0,
+                52,
+                generator,
+            )
+            .unwrap();
+            let input = (Generator::new_unblinded(&secp, tag), tag, ZERO_TWEAK);
+            let surjection_proof =
+                SurjectionProof::new(&secp, &mut StepRng::new(6, 1), tag, asset_blinder, &[input])
+                    .unwrap();
+            Ok(TxOut {
+                asset: ConfidentialAsset::Confidential(generator.serialize()),
+                value: ConfidentialValue::Confidential(commitment.serialize()),
+                nonce: None,
+                script_pubkey: output.script_pubkey.clone(),
+                rangeproof: rangeproof.serialize(),
+                surjection_proof: surjection_proof.serialize(),
+            })
+        }
+
+        fn unblind(&self, _output: &TxOut) -> Result<TxOutSecrets, Error> {
+            Ok(TxOutSecrets {
+                asset: lbtc(),
+                asset_blinder: [3; 32],
+                value: Amount::from_sat(1_000 + self.lie),
+                value_blinder: [4; 32],
+            })
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "zkp")]
+    fn verify_blinded_lock_opening() {
+        let secp = Secp256k1::new();
+        let key = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let script = Script::new_op_return(&[1]);
+        let inputs = [ConfidentialAsset::Explicit(lbtc())];
+        let honest = ZkpBlinder { lie: 0 };
+        let mut output = lock_output(
+            script.clone(),
+            lbtc(),
+            Amount::from_sat(1_000),
+            Some((&honest, &key)),
+        )
+        .unwrap();
+        assert!(output.is_confidential());
+        assert_eq!(
+            verify_lock(
+                &output,
+                &script,
+                lbtc(),
+                Amount::from_sat(1_000),
+                &inputs,
+                Some(&honest)
+            ),
+            Ok(Amount::from_sat(1_000))
+        );
+        assert_eq!(
+            verify_lock::<ZkpBlinder>(
+                &output,
+                &script,
+                lbtc(),
+                Amount::from_sat(1_000),
+                &inputs,
+                None
+            ),
+            Err(Error::Blinded)
+        );
+
+        // The blinder cannot open the commitment to another value
+        let liar = ZkpBlinder { lie: 1 };
+        assert_eq!(
+            verify_lock(
+                &output,
+                &script,
+                lbtc(),
+                Amount::from_sat(1_000),
+                &inputs,
+                Some(&liar)
+            ),
+            Err(Error::InvalidOpening)
+        );
+
+        // The asset must be one of the input assets
+        assert_eq!(
+            verify_lock(
+                &output,
+                &script,
+                lbtc(),
+                Amount::from_sat(1_000),
+                &[ConfidentialAsset::Explicit(AssetId([1; 32]))],
+                Some(&honest)
+            ),
+            Err(Error::InvalidSurjectionProof)
+        );
+        let mut forged = output.clone();
+        forged.surjection_proof = vec![2; 32];
+        assert_eq!(
+            verify_lock(
+                &forged,
+                &script,
+                lbtc(),
+                Amount::from_sat(1_000),
+                &inputs,
+                Some(&honest)
+            ),
+            Err(Error::InvalidSurjectionProof)
+        );
+
+        // The rangeproof commits to the output script
+        let other = Script::new_op_return(&[2]);
+        output.script_pubkey = other.clone();
+        assert_eq!(
+            verify_lock(
+                &output,
+                &other,
+                lbtc(),
+                Amount::from_sat(1_000),
+                &inputs,
+                Some(&honest)
+            ),
+            Err(Error::InvalidRangeproof)
+        );
+        output.rangeproof.clear();
+        assert_eq!(
+            verify_lock(
+                &output,
+                &other,
+                lbtc(),
+                Amount::from_sat(1_000),
+                &inputs,
+                Some(&honest)
+            ),
+            Err(Error::MissingRangeproof)
+        );
+    }
+
+    fn transaction() -> Transaction {
+        let input = |vout| TxIn {
+            previous_output: OutPoint::new(Default::default(), vout),
+            script_sig: Script::default(),
+            sequence: 0xffff_fffd,
+        };
+        let secp = Secp256k1::new();
+        let key = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let explicit = TxOut::explicit(Script::new_op_return(&[1]), lbtc(), Amount::from_sat(7));
+        let blinded = MockBlinder.blind(&explicit, &key).unwrap();
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![input(0), input(1)],
+            output: vec![explicit, blinded],
+        }
+    }
+
+    #[test]
+    fn segwit_v0_sighash_commits_to_confidential_outputs() {
+        let tx = transaction();
+        let script = Script::new_op_return(&[1]);
+        let value = ConfidentialValue::Explicit(Amount::from_sat(7));
+        let sighash = |tx: &Transaction, index, value, sighash_type| {
+            tx.segwit_v0_sighash(index, &script, value, sighash_type)
+                .unwrap()
+        };
+        let all = sighash(&tx, 0, value, EcdsaSighashType::All);
+        assert_ne!(all, sighash(&tx, 1, value, EcdsaSighashType::All));
+        assert_ne!(
+            all,
+            sighash(&tx, 0, tx.output[1].value, EcdsaSighashType::All)
+        );
+
+        // Blinding factors are committed through the asset, value and nonce of the outputs
+        let mut reblinded = tx.clone();
+        reblinded.output[1].asset = ConfidentialAsset::Confidential([0x0b; 33]);
+        assert_ne!(all, sighash(&reblinded, 0, value, EcdsaSighashType::All));
+        let mut renonced = tx.clone();
+        renonced.output[1].nonce = None;
+        assert_ne!(all, sighash(&renonced, 0, value, EcdsaSighashType::All));
+
+        // Witness proofs are not committed
+        let mut reproven = tx.clone();
+        reproven.output[1].rangeproof.clear();
+        reproven.output[1].surjection_proof.clear();
+        assert_eq!(all, sighash(&reproven, 0, value, EcdsaSighashType::All));
+
+        // Single only commits to the output at the same index
+        let single = EcdsaSighashType::SinglePlusAnyoneCanPay;
+        assert_eq!(
+            sighash(&tx, 0, value, single),
+            sighash(&reblinded, 0, value, single)
+        );
+        assert_ne!(
+            sighash(&tx, 1, value, single),
+            sighash(&reblinded, 1, value, single)
+        );
+
+        assert_eq!(
+            tx.segwit_v0_sighash(2, &script, value, EcdsaSighashType::All),
+            Err(Error::InputOutOfBounds(2))
+        );
+    }
+
+    #[test]
+    fn segwit_v0_sighash_differs_from_bip143() {
+        use crate::bitcoin::sighash::{Bip143, SighashAlgorithm};
+        use crate::bitcoin::transaction::TxInRef;
+
+        let script = Script::new_op_return(&[1]);
+        let mut tx = transaction();
+        tx.output.truncate(1);
+        let bitcoin_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: tx
+                .input
+                .iter()
+                .map(|input| bitcoin::TxIn {
+                    previous_output: input.previous_output,
+                    script_sig: input.script_sig.clone(),
+                    sequence: input.sequence,
+                    witness: Default::default(),
+                })
+                .collect(),
+            output: vec![bitcoin::TxOut {
+                value: 7,
+                script_pubkey: script.clone(),
+            }],
+        };
+        // Even without blinding the issuances and the explicit asset are committed
+        assert_ne!(
+            tx.segwit_v0_sighash(
+                0,
+                &script,
+                ConfidentialValue::Explicit(Amount::from_sat(7)),
+                EcdsaSighashType::All
+            )
+            .unwrap(),
+            Bip143.signature_hash(
+                TxInRef::new(&bitcoin_tx, 0),
+                &script,
+                Amount::from_sat(7),
+                EcdsaSighashType::All
+            )
+        );
+    }
+
+    #[test]
+    fn encode_confidential_output() {
+        let secp = Secp256k1::new();
+        let key = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let explicit = TxOut::explicit(Script::new_op_return(&[1]), lbtc(), Amount::from_sat(7));
+        let blinded = MockBlinder.blind(&explicit, &key).unwrap();
+        for output in [explicit, blinded] {
+            let bytes = consensus::serialize(&output);
+            assert_eq!(consensus::deserialize::<TxOut>(&bytes).unwrap(), output);
+        }
+        assert_eq!(
+            consensus::serialize(&ConfidentialValue::Explicit(Amount::from_sat(1))),
+            vec![1, 0, 0, 0, 0, 0, 0, 0, 1]
+        );
+        assert!(consensus::deserialize::<ConfidentialValue>(&[0x0a; 33]).is_err());
+    }
+}
@@ -17,21 +17,245 @@
 //! Implementation of the Monero blockchain as an accordant blockchain in a swap. This
 //! implementation should work in pair with any other arbitrating implementation, like Bitcoin.
 
-use crate::blockchain::Network;
+use crate::blockchain::{AccordantAmount, Network};
 use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
 use crate::crypto::{self, AccordantKeySet, AccordantKeys, DeriveKeys, SharedKeyId};
 use crate::role::Accordant;
 
-use monero::util::key::{PrivateKey, PublicKey};
-use monero::Address;
-use monero::Amount;
+use monero::cryptonote::hash::Hashable;
+use monero::util::address::AddressType;
+use monero::util::key::{KeyPair, PrivateKey, PublicKey, ViewPair};
+use monero::{Address, Amount, Hash, Transaction};
+use thiserror::Error;
+use zeroize::Zeroize;
 
 use std::fmt::{self, Debug};
 use std::io;
+use std::str::FromStr;
 
 /// The identifier for the only shared private key on the Monero side: the secret view key.
 pub const SHARED_VIEW_KEY_ID: u16 = 0x01;
 
+/// The maximum amount of a swap, about 9.2 million XMR. Wallets handle amounts and balance
+/// differences as signed 64 bits integers, larger amounts cannot be represented, and no swap can
+/// lock half of the main emission.
+pub const MAX_AMOUNT: AccordantAmount = AccordantAmount::from_atomic_units(i64::MAX as u64);
+
+/// Errors when validating a Monero destination address.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The address does not belong to the network of the swap.
+    #[error("The address is on {found} while the swap is on {expected}")]
+    NetworkMismatch {
+        /// The network of the swap.
+        expected: Network,
+        /// The network of the address.
+        found: Network,
+    },
+    /// None of the scanned transactions pays the swap address.
+    #[error("The lock transaction has not been found")]
+    LockNotFound,
+    /// The lock transaction pays less than the agreed amount.
+    #[error("The lock pays {found} while {expected} is expected")]
+    Underpaid {
+        /// The agreed amount.
+        expected: AccordantAmount,
+        /// The amount received on the swap address.
+        found: AccordantAmount,
+    },
+    /// The lock transaction pays more than the agreed amount.
+    #[error("The lock pays {found} while exactly {expected} is expected")]
+    Overpaid {
+        /// The agreed amount.
+        expected: AccordantAmount,
+        /// The amount received on the swap address.
+        found: AccordantAmount,
+    },
+    /// The outputs of the lock transaction cannot be spent before the given unlock time.
+    #[error("The lock outputs are locked until {0}")]
+    TimeLocked(u64),
+    /// The transaction cannot be scanned.
+    #[error("The transaction cannot be scanned")]
+    InvalidTransaction,
+    /// The reconstructed keys do not control the swap address.
+    #[error("The reconstructed keys do not match the swap address")]
+    InvalidSpendKey,
+}
+
+/// Parse and validate an address to receive the swapped funds on `network`. Standard addresses,
+/// subaddresses, and integrated addresses are accepted, see [`Monero::validate_destination`].
+pub fn parse_destination(address: &str, network: Network) -> Result<Address, consensus::Error> {
+    let address = Address::from_str(address).map_err(consensus::Error::new)?;
+    Monero::validate_destination(network, &address)?;
+    Ok(address)
+}
+
+/// Return true if the address is a subaddress. Funds sent to a subaddress must be constructed
+/// with the subaddress public keys, wallets sweeping the lock address must be aware of it.
+pub fn is_subaddress(address: &Address) -> bool {
+    address.addr_type == AddressType::SubAddress
+}
+
+/// An output paying the swap address, found by scanning a transaction with the shared view key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockOutput {
+    /// The hash of the transaction creating the output.
+    pub tx_hash: Hash,
+    /// The index of the output in the transaction.
+    pub index: usize,
+    /// The amount received, decrypted with the view key.
+    pub amount: AccordantAmount,
+    /// The unlock time of the transaction, zero if the output is spendable once confirmed.
+    pub unlock_time: u64,
+}
+
+/// Scan `tx` with the shared secret view key for the outputs paying the swap address of
+/// aggregated public spend key `spend`.
+pub fn scan_lock(
+    tx: &Transaction,
+    view: PrivateKey,
+    spend: PublicKey,
+) -> Result<Vec<LockOutput>, Error> {
+    let tx_hash = tx.hash();
+    let unlock_time = *tx.prefix.unlock_time;
+    Ok(tx
+        .check_outputs(&ViewPair { view, spend }, 0..1, 0..1)
+        .map_err(|_| Error::InvalidTransaction)?
+        .iter()
+        .map(|out| LockOutput {
+            tx_hash,
+            index: out.index(),
+            amount: out.amount().unwrap_or(Amount::ZERO).into(),
+            unlock_time,
+        })
+        .collect())
+}
+
+/// Verify that one of the `candidates`, e.g. transactions supplied by a daemon, is the
+/// accordant lock: it must pay exactly `amount` on the swap address and its outputs must be
+/// spendable once confirmed. Returns the outputs of the first valid lock.
+///
+/// Fails with [`Error::Underpaid`], [`Error::Overpaid`] or [`Error::TimeLocked`] if a transaction
+/// pays the swap address but not under the agreed terms, and with [`Error::LockNotFound`] if none
+/// does.
+pub fn verify_lock(
+    candidates: &[Transaction],
+    view: PrivateKey,
+    spend: PublicKey,
+    amount: AccordantAmount,
+) -> Result<Vec<LockOutput>, Error> {
+    let mut error = Error::LockNotFound;
+    for tx in candidates {
+        let outputs = match scan_lock(tx, view, spend) {
+            Ok(outputs) if !outputs.is_empty() => outputs,
+            // Not a lock transaction, keep looking
+            _ => continue,
+        };
+        let found = outputs
+            .iter()
+            .try_fold(AccordantAmount::ZERO, |acc, out| {
+                acc.checked_add(out.amount)
+            })
+            .ok_or(Error::InvalidTransaction)?;
+        error = if found < amount {
+            Error::Underpaid {
+                expected: amount,
+                found,
+            }
+        } else if found > amount {
+            Error::Overpaid {
+                expected: amount,
+                found,
+            }
+        } else if outputs[0].unlock_time != 0 {
+            Error::TimeLocked(outputs[0].unlock_time)
+        } else {
+            return Ok(outputs);
+        };
+    }
+    Err(error)
+}
+
+/// Everything a wallet needs to sweep the swap address once the counterparty spend key share
+/// has been recovered: the full spend and view keys, the swap address, the destination, and the
+/// height to restore the wallet from, e.g. with the `generate_from_keys` and `sweep_all` wallet
+/// RPC calls.
+///
+/// The private keys are wiped from memory when the sweep is dropped and are never displayed.
+pub struct MoneroSweep {
+    keys: KeyPair,
+    /// The swap address holding the locked funds.
+    pub address: Address,
+    /// The address receiving the swept funds.
+    pub destination: Address,
+    /// The block height from which the wallet must scan for the lock.
+    pub restore_height: u64,
+}
+
+impl MoneroSweep {
+    /// Reconstruct the full spend key from our spend key share and the share recovered from the
+    /// counterparty, and prepare the sweep of `address` to `destination`. Fails with
+    /// [`Error::InvalidSpendKey`] if the keys do not control `address` and with
+    /// [`Error::NetworkMismatch`] if `destination` is on another network.
+    pub fn new(
+        spend_share: PrivateKey,
+        recovered_share: PrivateKey,
+        view: PrivateKey,
+        address: Address,
+        destination: Address,
+        restore_height: u64,
+    ) -> Result<Self, Error> {
+        let sweep = Self {
+            keys: KeyPair {
+                view,
+                spend: spend_share + recovered_share,
+            },
+            address,
+            destination,
+            restore_height,
+        };
+        if PublicKey::from_private_key(&sweep.keys.spend) != address.public_spend
+            || PublicKey::from_private_key(&sweep.keys.view) != address.public_view
+        {
+            return Err(Error::InvalidSpendKey);
+        }
+        if destination.network != address.network {
+            return Err(Error::NetworkMismatch {
+                expected: address.network.into(),
+                found: destination.network.into(),
+            });
+        }
+        Ok(sweep)
+    }
+
+    /// The full private spend key of the swap address.
+    pub fn spend_key(&self) -> &PrivateKey {
+        &self.keys.spend
+    }
+
+    /// The private view key of the swap address.
+    pub fn view_key(&self) -> &PrivateKey {
+        &self.keys.view
+    }
+}
+
+impl Debug for MoneroSweep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MoneroSweep")
+            .field("address", &self.address)
+            .field("destination", &self.destination)
+            .field("restore_height", &self.restore_height)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for MoneroSweep {
+    fn drop(&mut self) {
+        self.keys.spend.scalar.zeroize();
+        self.keys.view.scalar.zeroize();
+    }
+}
+
 /// The implementation of Monero with all the traits necessary to comply with [`Accordant`]
 /// blockchain role.
 #[derive(Clone, Debug, Copy, PartialEq, Eq)]
@@ -53,42 +277,82 @@ impl Encodable for Monero {
 }
 
 impl Accordant<PublicKey, PrivateKey, Address> for Monero {
+    type Sweep = MoneroSweep;
+
+    fn aggregate_public_keys(alice: &PublicKey, bob: &PublicKey) -> PublicKey {
+        alice + bob
+    }
+
+    fn aggregate_secret_keys(share: &PrivateKey, recovered_share: &PrivateKey) -> PrivateKey {
+        share + recovered_share
+    }
+
     fn derive_lock_address(
         network: Network,
         keys: AccordantKeySet<PublicKey, PrivateKey>,
     ) -> Result<Address, crypto::Error> {
-        let AccordantKeySet {
-            alice:
-                AccordantKeys {
-                    public_spend_key: alice_public_spend_key,
-                    shared_secret_keys: alice_shared_secret_keys,
-                    ..
-                },
-            bob:
-                AccordantKeys {
-                    public_spend_key: bob_public_spend_key,
-                    shared_secret_keys: bob_shared_secret_keys,
-                    ..
-                },
-        } = keys;
-
-        let alice_tagged_view_secretkey = alice_shared_secret_keys
-            .iter()
-            .find(|tagged_key| *tagged_key.tag() == SharedKeyId::new(SHARED_VIEW_KEY_ID))
-            .ok_or(crypto::Error::MissingKey)?;
-        let bob_tagged_view_secretkey = bob_shared_secret_keys
-            .iter()
-            .find(|tagged_key| *tagged_key.tag() == SharedKeyId::new(SHARED_VIEW_KEY_ID))
-            .ok_or(crypto::Error::MissingKey)?;
+        let public_spend =
+            Self::aggregate_public_keys(&keys.alice.public_spend_key, &keys.bob.public_spend_key);
+        let public_view = PublicKey::from_private_key(&shared_view_key(&keys)?);
+        Ok(Address::standard(network.into(), public_spend, public_view))
+    }
 
-        let public_spend = alice_public_spend_key + bob_public_spend_key;
-        let secret_view = alice_tagged_view_secretkey.elem() + bob_tagged_view_secretkey.elem();
-        let public_view = PublicKey::from_private_key(&secret_view);
+    fn sweep(
+        network: Network,
+        keys: AccordantKeySet<PublicKey, PrivateKey>,
+        share: PrivateKey,
+        recovered_share: PrivateKey,
+        destination: Address,
+        restore_height: u64,
+    ) -> Result<MoneroSweep, crypto::Error> {
+        Self::validate_destination(network, &destination).map_err(crypto::Error::new)?;
+        let view = shared_view_key(&keys)?;
+        let address = Self::derive_lock_address(network, keys)?;
+        MoneroSweep::new(
+            share,
+            recovered_share,
+            view,
+            address,
+            destination,
+            restore_height,
+        )
+        .map_err(crypto::Error::new)
+    }
 
-        Ok(Address::standard(network.into(), public_spend, public_view))
+    /// Standard addresses, subaddresses, and integrated addresses are valid destinations as long
+    /// as they belong to the swap network. Testnet swaps accept both stagenet and testnet
+    /// addresses.
+    fn validate_destination(network: Network, address: &Address) -> Result<(), consensus::Error> {
+        let found = Network::from(address.network);
+        // Local swaps use mainnet addresses
+        let expected = match network {
+            Network::Local => Network::Mainnet,
+            network => network,
+        };
+        if found != expected {
+            return Err(consensus::Error::new(Error::NetworkMismatch {
+                expected: network,
+                found,
+            }));
+        }
+        Ok(())
     }
 }
 
+/// The secret view key of the lock address, the sum of Alice's and Bob's shared view keys.
+fn shared_view_key(
+    keys: &AccordantKeySet<PublicKey, PrivateKey>,
+) -> Result<PrivateKey, crypto::Error> {
+    let view_key = |keys: &AccordantKeys<PublicKey, PrivateKey>| {
+        keys.shared_secret_keys
+            .iter()
+            .find(|tagged_key| *tagged_key.tag() == SharedKeyId::new(SHARED_VIEW_KEY_ID))
+            .map(|tagged_key| *tagged_key.elem())
+            .ok_or(crypto::Error::MissingKey)
+    };
+    Ok(view_key(&keys.alice)? + view_key(&keys.bob)?)
+}
+
 impl From<Network> for monero::Network {
     fn from(network: Network) -> Self {
         match network {
@@ -126,6 +390,18 @@ impl fmt::Display for Monero {
     }
 }
 
+impl From<Amount> for AccordantAmount {
+    fn from(amount: Amount) -> Self {
+        AccordantAmount::from_atomic_units(amount.as_pico())
+    }
+}
+
+impl From<AccordantAmount> for Amount {
+    fn from(amount: AccordantAmount) -> Self {
+        Amount::from_pico(amount.as_atomic_units())
+    }
+}
+
 impl CanonicalBytes for Amount {
     fn as_canonical_bytes(&self) -> Vec<u8> {
         monero::consensus::encode::serialize(&self.as_pico())
@@ -194,3 +470,240 @@ impl CanonicalBytes for PublicKey {
         PublicKey::from_slice(bytes).map_err(consensus::Error::new)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use monero::blockdata::transaction::{
+        ExtraField, SubField, TransactionPrefix, TxOut, TxOutTarget,
+    };
+    use monero::cryptonote::onetime_key::KeyGenerator;
+    use monero::util::address::PaymentId;
+    use monero::util::ringct::RctSig;
+    use monero::VarInt;
+
+    fn keys(byte: u8) -> (PublicKey, PublicKey) {
+        let spend = PrivateKey::from_slice(&[byte; 32]).unwrap();
+        let view = PrivateKey::from_slice(&[byte + 1; 32]).unwrap();
+        (
+            PublicKey::from_private_key(&spend),
+            PublicKey::from_private_key(&view),
+        )
+    }
+
+    #[test]
+    fn validate_destination_types() {
+        let (spend, view) = keys(1);
+        let standard = Address::standard(monero::Network::Mainnet, spend, view);
+        let subaddress = Address::subaddress(monero::Network::Mainnet, spend, view);
+        let integrated =
+            Address::integrated(monero::Network::Mainnet, spend, view, PaymentId([7u8; 8]));
+
+        for address in [standard, subaddress, integrated].iter() {
+            let parsed = parse_destination(&address.to_string(), Network::Mainnet).unwrap();
+            assert_eq!(&parsed, address);
+            // Local swaps use mainnet addresses
+            assert!(Monero::validate_destination(Network::Local, address).is_ok());
+        }
+        assert!(!is_subaddress(&standard));
+        assert!(is_subaddress(&subaddress));
+    }
+
+    #[test]
+    fn reject_destination_on_other_network() {
+        let (spend, view) = keys(1);
+        let stagenet = Address::subaddress(monero::Network::Stagenet, spend, view);
+        assert!(Monero::validate_destination(Network::Testnet, &stagenet).is_ok());
+        assert!(Monero::validate_destination(Network::Mainnet, &stagenet).is_err());
+        assert!(parse_destination(&stagenet.to_string(), Network::Local).is_err());
+        assert!(parse_destination("not an address", Network::Mainnet).is_err());
+
+        let err = parse_destination(&stagenet.to_string(), Network::Mainnet).unwrap_err();
+        assert!(err.to_string().contains("Testnet"));
+    }
+
+    // A transaction in clear amounts paying `amounts` to the address of the view pair
+    fn lock_tx(
+        view: PrivateKey,
+        spend: PublicKey,
+        amounts: &[u64],
+        unlock_time: u64,
+    ) -> Transaction {
+        let random = PrivateKey::from_slice(&[9u8; 32]).unwrap();
+        let generator =
+            KeyGenerator::from_random(PublicKey::from_private_key(&view), spend, random);
+        Transaction {
+            prefix: TransactionPrefix {
+                version: VarInt(1),
+                unlock_time: VarInt(unlock_time),
+                inputs: vec![],
+                outputs: amounts
+                    .iter()
+                    .enumerate()
+                    .map(|(index, amount)| TxOut {
+                        amount: VarInt(*amount),
+                        target: TxOutTarget::ToKey {
+                            key: generator.one_time_key(index).to_bytes(),
+                        },
+                    })
+                    .collect(),
+                extra: ExtraField(vec![SubField::TxPublicKey(PublicKey::from_private_key(
+                    &random,
+                ))])
+                .into(),
+            },
+            signatures: vec![],
+            rct_signatures: RctSig { sig: None, p: None },
+        }
+    }
+
+    #[test]
+    fn verify_accordant_lock() {
+        let view = PrivateKey::from_slice(&[3u8; 32]).unwrap();
+        let (spend, _) = keys(5);
+        let (other_spend, _) = keys(7);
+        let amount = AccordantAmount::from_atomic_units(1_000_000);
+
+        let lock = lock_tx(view, spend, &[600_000, 400_000], 0);
+        let outputs = scan_lock(&lock, view, spend).unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(
+            outputs[1].amount,
+            AccordantAmount::from_atomic_units(400_000)
+        );
+        assert_eq!(outputs[0].tx_hash, lock.hash());
+
+        // Transactions not paying the swap address are skipped
+        let unrelated = lock_tx(view, other_spend, &[1_000_000], 0);
+        assert!(scan_lock(&unrelated, view, spend).unwrap().is_empty());
+        assert_eq!(
+            verify_lock(&[unrelated.clone(), lock], view, spend, amount).unwrap(),
+            outputs
+        );
+        assert_eq!(
+            verify_lock(&[unrelated], view, spend, amount),
+            Err(Error::LockNotFound)
+        );
+    }
+
+    #[test]
+    fn reject_wrong_amount_or_locked_lock() {
+        let view = PrivateKey::from_slice(&[3u8; 32]).unwrap();
+        let (spend, _) = keys(5);
+        let amount = AccordantAmount::from_atomic_units(1_000_000);
+
+        let underpaid = lock_tx(view, spend, &[999_999], 0);
+        assert_eq!(
+            verify_lock(&[underpaid], view, spend, amount),
+            Err(Error::Underpaid {
+                expected: amount,
+                found: AccordantAmount::from_atomic_units(999_999)
+            })
+        );
+
+        let overpaid = lock_tx(view, spend, &[600_000, 400_001], 0);
+        assert_eq!(
+            verify_lock(&[overpaid], view, spend, amount),
+            Err(Error::Overpaid {
+                expected: amount,
+                found: AccordantAmount::from_atomic_units(1_000_001)
+            })
+        );
+
+        let locked = lock_tx(view, spend, &[1_000_000], 2_000_000);
+        assert_eq!(
+            verify_lock(&[locked], view, spend, amount),
+            Err(Error::TimeLocked(2_000_000))
+        );
+    }
+
+    #[test]
+    fn reconstruct_sweep_keys() {
+        let alice_share = PrivateKey::from_slice(&[3u8; 32]).unwrap();
+        let bob_share = PrivateKey::from_slice(&[5u8; 32]).unwrap();
+        let view = PrivateKey::from_slice(&[7u8; 32]).unwrap();
+        let address = Address::standard(
+            monero::Network::Stagenet,
+            PublicKey::from_private_key(&alice_share) + PublicKey::from_private_key(&bob_share),
+            PublicKey::from_private_key(&view),
+        );
+        let (spend, view_pub) = keys(9);
+        let destination = Address::standard(monero::Network::Stagenet, spend, view_pub);
+
+        let sweep =
+            MoneroSweep::new(alice_share, bob_share, view, address, destination, 10).unwrap();
+        assert_eq!(*sweep.spend_key(), alice_share + bob_share);
+        assert_eq!(*sweep.view_key(), view);
+        assert!(!format!("{:?}", sweep).contains(&sweep.spend_key().to_string()));
+
+        assert_eq!(
+            MoneroSweep::new(alice_share, view, view, address, destination, 10).unwrap_err(),
+            Error::InvalidSpendKey
+        );
+        let mainnet = Address::standard(monero::Network::Mainnet, spend, view_pub);
+        assert!(matches!(
+            MoneroSweep::new(alice_share, bob_share, view, address, mainnet, 10),
+            Err(Error::NetworkMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn sweep_through_accordant_role() {
+        use crate::crypto::TaggedElement;
+
+        let accordant_keys = |spend: &PrivateKey, view: PrivateKey| AccordantKeys {
+            public_spend_key: PublicKey::from_private_key(spend),
+            extra_public_keys: vec![],
+            shared_secret_keys: vec![TaggedElement::new(
+                SharedKeyId::new(SHARED_VIEW_KEY_ID),
+                view,
+            )],
+        };
+        let alice_share = PrivateKey::from_slice(&[3u8; 32]).unwrap();
+        let bob_share = PrivateKey::from_slice(&[5u8; 32]).unwrap();
+        let alice_view = PrivateKey::from_slice(&[7u8; 32]).unwrap();
+        let bob_view = PrivateKey::from_slice(&[9u8; 32]).unwrap();
+        let key_set = AccordantKeySet {
+            alice: accordant_keys(&alice_share, alice_view),
+            bob: accordant_keys(&bob_share, bob_view),
+        };
+        let (spend, view) = keys(11);
+        let destination = Address::standard(monero::Network::Stagenet, spend, view);
+
+        let address = Monero::derive_lock_address(Network::Testnet, key_set.clone()).unwrap();
+        assert_eq!(
+            address.public_spend,
+            Monero::aggregate_public_keys(
+                &key_set.alice.public_spend_key,
+                &key_set.bob.public_spend_key
+            )
+        );
+        let sweep = Monero::sweep(
+            Network::Testnet,
+            key_set.clone(),
+            alice_share,
+            bob_share,
+            destination,
+            10,
+        )
+        .unwrap();
+        assert_eq!(sweep.address, address);
+        assert_eq!(
+            *sweep.spend_key(),
+            Monero::aggregate_secret_keys(&alice_share, &bob_share)
+        );
+        assert_eq!(*sweep.view_key(), alice_view + bob_view);
+
+        // The destination must be on the swap network
+        assert!(Monero::sweep(
+            Network::Mainnet,
+            key_set,
+            alice_share,
+            bob_share,
+            destination,
+            10
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,30 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Pure computation layer compiling with `no_std` and `alloc` when the `std` feature is disabled,
+//! so embedded signers and enclaves can verify swap artifacts without the std-dependent
+//! blockchain implementations: decode the protocol messages with [`encoding`], open the
+//! commitments of the commit/reveal round with [`commitment`], verify the cross-group DLEQ proof
+//! of the revealed keys with [`dleq`] and the adaptor signatures of the procedure signatures with
+//! [`adaptor`].
+//!
+//! The full framework builds on this layer, e.g. the `CommitmentEngine` commits with
+//! [`commitment::keccak256`] and the `DLEQProof` is verified with the [`dleq`] verifier.
+
+pub mod adaptor;
+pub mod commitment;
+pub mod dleq;
+pub mod encoding;
@@ -0,0 +1,126 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Verification of ECDSA adaptor signatures, also called encrypted signatures, from their
+//! canonical bytes as produced by `ecdsa_fun`: the nonce `R`, the nonce `R_hat`, the scalar
+//! `s_hat` and the compact DLEQ proof binding `R_hat` and `R` to the encryption key.
+
+use core::fmt;
+
+use secp256kfun::digest::generic_array::typenum::U32;
+use secp256kfun::{g, marker::*, Point, Scalar, G};
+use sha2::Sha256;
+use sigma_fun::{secp256k1, CompactProof, Eq, FiatShamir, HashTranscript};
+
+use crate::primitives::encoding::{self, Reader};
+
+/// Length of the canonical bytes of an encrypted signature.
+pub const ENCRYPTED_SIGNATURE_LEN: usize = 162;
+
+/// The DLEQ proof system of the nonces, as in `ecdsa_fun::adaptor::DLEQ`.
+type Dleq = Eq<secp256k1::DLG<U32>, secp256k1::DL<U32>>;
+
+/// Errors encountered when decoding or verifying an encrypted signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The encrypted signature is not correctly encoded.
+    Encoding(encoding::Error),
+    /// A point is not on the curve.
+    InvalidPoint,
+    /// A scalar is zero or not reduced.
+    InvalidScalar,
+    /// The DLEQ proof of the nonces does not verify.
+    InvalidProof,
+    /// The encrypted signature does not verify under the keys.
+    InvalidEncryptedSignature,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Encoding(e) => write!(f, "Encoding error: {}", e),
+            Self::InvalidPoint => write!(f, "The point is not on the curve"),
+            Self::InvalidScalar => write!(f, "The scalar is not valid"),
+            Self::InvalidProof => write!(f, "The nonces proof does not pass the validation"),
+            Self::InvalidEncryptedSignature => {
+                write!(f, "The adaptor signature does not pass the validation")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+impl From<encoding::Error> for Error {
+    fn from(e: encoding::Error) -> Self {
+        Self::Encoding(e)
+    }
+}
+
+fn read_point(reader: &mut Reader) -> Result<Point, Error> {
+    Point::from_bytes(reader.read_array()?).ok_or(Error::InvalidPoint)
+}
+
+/// Verify the canonical bytes of an encrypted signature of the `message` hash under the
+/// compressed `verification_key`, encrypted with the compressed `encryption_key`.
+#[allow(non_snake_case)]
+pub fn verify_encrypted_signature(
+    verification_key: &[u8; 33],
+    encryption_key: &[u8; 33],
+    message: &[u8; 32],
+    encrypted_signature: &[u8],
+) -> Result<(), Error> {
+    let X = Point::from_bytes(*verification_key).ok_or(Error::InvalidPoint)?;
+    let Y = Point::from_bytes(*encryption_key).ok_or(Error::InvalidPoint)?;
+
+    let mut reader = Reader::new(encrypted_signature);
+    let R = read_point(&mut reader)?;
+    let R_hat = read_point(&mut reader)?;
+    let s_hat = Scalar::from_bytes(reader.read_array()?)
+        .and_then(|s| s.mark::<NonZero>())
+        .ok_or(Error::InvalidScalar)?
+        .mark::<Public>();
+    let challenge = reader.read_array::<32>()?.into();
+    let response = Scalar::from_bytes(reader.read_array()?)
+        .ok_or(Error::InvalidScalar)?
+        .mark::<Public>();
+    reader.finish()?;
+
+    let R_x = Scalar::from_bytes_mod_order(R.to_xonly().into_bytes())
+        .mark::<(Public, NonZero)>()
+        .ok_or(Error::InvalidScalar)?;
+
+    let proof_system = FiatShamir::<Dleq, HashTranscript<Sha256>>::new(
+        Dleq::default(),
+        HashTranscript::default(),
+        Some("DLEQ"),
+    );
+    let proof = CompactProof::<Dleq> {
+        challenge,
+        response,
+    };
+    if !proof_system.verify(&(R_hat, (Y, R)), &proof) {
+        return Err(Error::InvalidProof);
+    }
+
+    let m = Scalar::from_bytes_mod_order(*message);
+    let s_hat_inv = s_hat.invert();
+    match g!((s_hat_inv * m) * G + (s_hat_inv * R_x) * X) == R_hat {
+        true => Ok(()),
+        false => Err(Error::InvalidEncryptedSignature),
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Commitment schemes of the commit/reveal round: Keccak-256 commitments to each revealed
+//! element and tagged SHA-256 commitments (as in BIP-340) to the full reveal message. The
+//! commitments of the commit messages are encoded with the identifier of their algorithm
+//! followed by the hash, see [`verify_commitment`].
+
+use bitcoin_hashes::{sha256, Hash, HashEngine};
+use tiny_keccak::{Hasher, Keccak};
+
+/// Identifier of the Keccak-256 algorithm, see `crypto::registry::AlgorithmId::KECCAK_256`.
+pub const KECCAK_256_ID: u8 = 0x01;
+
+/// Hash the bytes with the Keccak-256 hash function.
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(data);
+    keccak.finalize(&mut out);
+    out
+}
+
+/// Compute `SHA256(SHA256(tag) || SHA256(tag) || data)`.
+pub fn tagged_sha256(tag: &[u8], data: &[u8]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag);
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag_hash[..]);
+    engine.input(&tag_hash[..]);
+    engine.input(data);
+    sha256::Hash::from_engine(engine).into_inner()
+}
+
+/// Return true if the revealed bytes open the Keccak-256 commitment.
+pub fn verify_keccak256(commitment: &[u8; 32], revealed: &[u8]) -> bool {
+    keccak256(revealed) == *commitment
+}
+
+/// Return true if the revealed bytes open the encoded commitment of a commit message: the
+/// [`KECCAK_256_ID`] identifier followed by the Keccak-256 hash. Commitments with another
+/// algorithm are not supported.
+pub fn verify_commitment(commitment: &[u8], revealed: &[u8]) -> bool {
+    match commitment.split_first() {
+        Some((&KECCAK_256_ID, hash)) => hash == keccak256(revealed),
+        _ => false,
+    }
+}
+
+/// Return true if the revealed bytes open the tagged SHA-256 commitment.
+pub fn verify_tagged_sha256(commitment: &[u8; 32], tag: &[u8], revealed: &[u8]) -> bool {
+    tagged_sha256(tag, revealed) == *commitment
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    use crate::consensus::CanonicalBytes;
+    use crate::crypto::commitment::tagged_hash;
+    use crate::crypto::{Commit, CommitmentEngine};
+
+    #[test]
+    fn commitments_match_the_framework() {
+        let data = b"farcaster";
+        let commitment = keccak256(data);
+        assert_eq!(
+            CommitmentEngine.commit_to(data).to_fixed_bytes(),
+            commitment
+        );
+        assert!(verify_keccak256(&commitment, data));
+        assert!(!verify_keccak256(&commitment, b"other"));
+
+        let encoded = CommitmentEngine.commit_to(data).as_canonical_bytes();
+        assert_eq!(encoded[0], KECCAK_256_ID);
+        assert!(verify_commitment(&encoded, data));
+        assert!(!verify_commitment(&encoded, b"other"));
+        assert!(!verify_commitment(&encoded[1..], data));
+
+        let tagged = tagged_sha256(b"tag", data);
+        assert_eq!(tagged_hash(b"tag", data).to_fixed_bytes(), tagged);
+        assert!(verify_tagged_sha256(&tagged, b"tag", data));
+        assert!(!verify_tagged_sha256(&tagged, b"other", data));
+    }
+}
@@ -0,0 +1,361 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Verification of the cross-group discrete logarithm equality proof revealed with the
+//! parameters, proving that the accordant spend key share on curve25519 and the adaptor key on
+//! secp256k1 have the same secret. The proof is verified from its canonical bytes, as encoded by
+//! the `DLEQProof` of the framework.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use bitcoin_hashes::{sha256, Hash};
+use curve25519_dalek::{
+    constants::ED25519_BASEPOINT_POINT as G, edwards::CompressedEdwardsY as ed25519PointCompressed,
+    edwards::EdwardsPoint as ed25519Point, scalar::Scalar as ed25519Scalar,
+};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use secp256kfun::{g, marker::*, Point as secp256k1Point, Scalar as secp256k1Scalar, G as H};
+
+use crate::primitives::commitment::keccak256;
+use crate::primitives::encoding::{self, Reader};
+
+/// Number of bits of the secret committed to in a proof.
+pub const PROOF_BITS: usize = 252;
+
+/// Errors encountered when decoding or verifying a DLEQ proof.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The proof or the keys are not correctly encoded.
+    Encoding(encoding::Error),
+    /// A point is not on the curve.
+    InvalidPoint,
+    /// A scalar is zero or not reduced.
+    InvalidScalar,
+    /// The proof does not commit to [`PROOF_BITS`] bits.
+    InvalidLength,
+    /// The Pedersen commitments do not sum up to the keys.
+    InvalidPedersenCommitment,
+    /// A ring signature does not recompute.
+    InvalidRingSignature,
+    /// A proof of knowledge signature is invalid.
+    InvalidProofOfKnowledge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Encoding(e) => write!(f, "Encoding error: {}", e),
+            Self::InvalidPoint => write!(f, "The point is not on the curve"),
+            Self::InvalidScalar => write!(f, "The scalar is not valid"),
+            Self::InvalidLength => write!(f, "The proof does not commit to {} bits", PROOF_BITS),
+            Self::InvalidPedersenCommitment => {
+                write!(f, "The Pedersen commitment does not match the given value")
+            }
+            Self::InvalidRingSignature => write!(f, "The ring signature does not recompute"),
+            Self::InvalidProofOfKnowledge => {
+                write!(f, "The proof of knowledge signature is invalid")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+impl From<encoding::Error> for Error {
+    fn from(e: encoding::Error) -> Self {
+        Self::Encoding(e)
+    }
+}
+
+/// Iterate over the bit indexes `0..len` of the proof, in parallel with the `parallel` feature.
+/// Each bit commitment and ring signature is independent, so the work is spread across threads
+/// without changing the produced proof format.
+#[cfg(feature = "parallel")]
+pub(crate) fn bit_indexes(len: usize) -> rayon::range::Iter<usize> {
+    (0..len).into_par_iter()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn bit_indexes(len: usize) -> core::ops::Range<usize> {
+    0..len
+}
+
+// matches https://github.com/monero-project/monero/blob/9414194b1e47730843e4dbbd4214bf72d3540cf9/src/ringct/rctTypes.h#L454
+// i.e. hash-to-curve of G as in https://github.com/monero-project/mininero/blob/master/mininero.py#L305-L323
+// TODO: this is disgusting and must be removed asap
+#[allow(non_snake_case)]
+pub(crate) fn G_p() -> ed25519Point {
+    let hash_G = keccak256(G.compress().as_bytes());
+
+    ed25519PointCompressed::from_slice(&hash_G)
+        .decompress()
+        .unwrap()
+        .mul_by_cofactor() // should be in basepoint's subgroup, i.e. 8 * toPoint(hash_G)
+}
+
+// Hash to curve of the generator G as explained over here:
+// https://crypto.stackexchange.com/a/25603
+// Matches the result here:
+// https://github.com/mimblewimble/rust-secp256k1-zkp/blob/caa49992ae67f131157f6341f4e8b0b0c1e53055/src/constants.rs#L79-L136
+// TODO: this is disgusting and must be removed asap (i.e. change to constant)
+#[allow(non_snake_case)]
+pub(crate) fn H_p() -> secp256k1Point {
+    let hash_H: [u8; 32] = sha256::Hash::hash(&H.to_bytes_uncompressed()).into_inner();
+    let mut even_y_prepend_hash_H = [2u8; 33];
+    even_y_prepend_hash_H[1..].copy_from_slice(&hash_H);
+    secp256k1Point::from_bytes(even_y_prepend_hash_H).expect("Alternate basepoint is invalid")
+}
+
+/// The little-endian bytes of `2^index`.
+pub(crate) fn bit_order(index: usize) -> [u8; 32] {
+    let mut order = [0u8; 32];
+    order[index / 8] = 1 << (index % 8);
+    order
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub(crate) struct RingSignature<ScalarCurveA, ScalarCurveB> {
+    pub(crate) e_g_0_i: ScalarCurveA,
+    pub(crate) e_h_0_i: ScalarCurveB,
+    pub(crate) a_0_i: ScalarCurveA,
+    pub(crate) b_0_i: ScalarCurveB,
+    pub(crate) a_1_i: ScalarCurveA,
+    pub(crate) b_1_i: ScalarCurveB,
+}
+
+pub(crate) fn ring_hash(
+    term0: [u8; 32],
+    term1: [u8; 33],
+    term2: [u8; 32],
+    term3: [u8; 33],
+) -> [u8; 32] {
+    let mut preimage = [0u8; 130];
+    preimage[..32].copy_from_slice(&term0);
+    preimage[32..65].copy_from_slice(&term1);
+    preimage[65..97].copy_from_slice(&term2);
+    preimage[97..].copy_from_slice(&term3);
+    sha256::Hash::hash(&preimage).into_inner()
+}
+
+fn verify_ring_sig(
+    index: usize,
+    c_g_i: ed25519Point,
+    c_h_i: &secp256k1Point,
+    ring_sig: &RingSignature<ed25519Scalar, secp256k1Scalar>,
+) -> bool {
+    let term0: [u8; 32] = *c_g_i.compress().as_bytes();
+    let term1: [u8; 33] = c_h_i.to_bytes();
+
+    let order = bit_order(index);
+    let mut order_be = order;
+    order_be.reverse();
+    let order_on_secp256k1 =
+        secp256k1Scalar::from_bytes(order_be).expect("integer greater than curve order");
+    #[allow(non_snake_case)]
+    let H_p = H_p();
+
+    // compute e_1_i
+    let e_1_i = {
+        let term2: [u8; 32] = *(ring_sig.a_1_i * G_p() - ring_sig.e_g_0_i * c_g_i)
+            .compress()
+            .as_bytes();
+
+        let term3: [u8; 33] = match g!(ring_sig.b_1_i * H_p - ring_sig.e_h_0_i * c_h_i)
+            .mark::<Normal>()
+            .mark::<NonZero>()
+        {
+            Some(point) => point.to_bytes(),
+            None => return false,
+        };
+
+        ring_hash(term0, term1, term2, term3)
+    };
+    let e_g_1_i = ed25519Scalar::from_bytes_mod_order(e_1_i);
+    let e_h_1_i = secp256k1Scalar::from_bytes_mod_order(e_1_i);
+
+    // compute e_0_i
+    let e_0_i = {
+        let term2: [u8; 32] = *(ring_sig.a_0_i * G_p()
+            - e_g_1_i * (c_g_i - ed25519Scalar::from_bytes_mod_order(order) * G))
+            .compress()
+            .as_bytes();
+
+        let term3: [u8; 33] =
+            match g!(ring_sig.b_0_i * H_p - e_h_1_i * (c_h_i - order_on_secp256k1 * H))
+                .mark::<Normal>()
+                .mark::<NonZero>()
+            {
+                Some(point) => point.to_bytes(),
+                None => return false,
+            };
+
+        ring_hash(term0, term1, term2, term3)
+    };
+
+    let e_g_0_i = ed25519Scalar::from_bytes_mod_order(e_0_i);
+    let e_h_0_i = secp256k1Scalar::from_bytes_mod_order(e_0_i);
+
+    // compare computed results with provided values
+    (e_g_0_i == ring_sig.e_g_0_i) && (e_h_0_i == ring_sig.e_h_0_i)
+}
+
+/// Verify the proof components against the accordant spend key share `xG_p` and the adaptor key
+/// `xH_p`.
+#[allow(non_snake_case)]
+pub(crate) fn verify_proof(
+    c_g: &[ed25519Point],
+    c_h: &[secp256k1Point],
+    ring_signatures: &[RingSignature<ed25519Scalar, secp256k1Scalar>],
+    pok_0: &(ed25519Point, ed25519Scalar),
+    pok_1: &ecdsa_fun::Signature,
+    xG_p: &ed25519Point,
+    xH_p: &secp256k1Point,
+) -> Result<(), Error> {
+    if c_g.len() != PROOF_BITS || c_h.len() != PROOF_BITS || ring_signatures.len() != PROOF_BITS {
+        return Err(Error::InvalidLength);
+    }
+
+    // Commitments
+    let commitment_agg_ed25519: ed25519Point = c_g.iter().sum();
+
+    if *xG_p != commitment_agg_ed25519 {
+        return Err(Error::InvalidPedersenCommitment);
+    }
+
+    let commitment_agg_secp256k1 = c_h
+        .iter()
+        .fold(secp256k1Point::zero(), |acc, bit_commitment| {
+            g!(acc + bit_commitment).mark::<Normal>()
+        });
+
+    if *xH_p != commitment_agg_secp256k1 {
+        return Err(Error::InvalidPedersenCommitment);
+    }
+
+    // Ring signatures
+    let valid_ring_signatures = bit_indexes(PROOF_BITS)
+        .all(|index| verify_ring_sig(index, c_g[index], &c_h[index], &ring_signatures[index]));
+
+    if !(valid_ring_signatures) {
+        return Err(Error::InvalidRingSignature);
+    }
+
+    // Proof of Knowledge
+    // ed25519 (edDSA)
+    let (alpha_G, r) = pok_0;
+    let mut challenge_preimage = Vec::with_capacity(66 + 32 * PROOF_BITS);
+    challenge_preimage.extend_from_slice(alpha_G.compress().as_bytes());
+    challenge_preimage.extend_from_slice(xG_p.compress().as_bytes());
+    challenge_preimage.extend_from_slice(&(c_g.len() as u16).to_le_bytes());
+    for c_g_i in c_g {
+        challenge_preimage.extend_from_slice(c_g_i.compress().as_bytes());
+    }
+    let challenge = keccak256(&challenge_preimage);
+
+    if r * G != alpha_G + ed25519Scalar::from_bytes_mod_order(challenge) * xG_p {
+        return Err(Error::InvalidProofOfKnowledge);
+    }
+
+    // secp256k1 (ECDSA)
+    let mut pok_1_message = Vec::with_capacity(2 + 33 * PROOF_BITS);
+    pok_1_message.extend_from_slice(&(c_h.len() as u16).to_le_bytes());
+    for c_h_i in c_h {
+        pok_1_message.extend_from_slice(&c_h_i.to_bytes());
+    }
+    let pok_1_message_hash = sha256::Hash::hash(&pok_1_message).into_inner();
+    let ecdsa = ecdsa_fun::ECDSA::verify_only();
+    if !ecdsa.verify(xH_p, &pok_1_message_hash, pok_1) {
+        return Err(Error::InvalidProofOfKnowledge);
+    }
+
+    // Everything ok
+    Ok(())
+}
+
+fn read_ed25519_point(reader: &mut Reader) -> Result<ed25519Point, Error> {
+    ed25519PointCompressed(reader.read_array()?)
+        .decompress()
+        .ok_or(Error::InvalidPoint)
+}
+
+fn read_secp256k1_point(reader: &mut Reader) -> Result<secp256k1Point, Error> {
+    secp256k1Point::from_bytes(reader.read_array()?).ok_or(Error::InvalidPoint)
+}
+
+fn read_ed25519_scalar(reader: &mut Reader) -> Result<ed25519Scalar, Error> {
+    Ok(ed25519Scalar::from_bytes_mod_order(reader.read_array()?))
+}
+
+fn read_secp256k1_scalar(reader: &mut Reader) -> Result<secp256k1Scalar, Error> {
+    secp256k1Scalar::from_bytes_mod_order(reader.read_array()?)
+        .mark::<NonZero>()
+        .ok_or(Error::InvalidScalar)
+}
+
+fn read_vec<'a, T>(
+    reader: &mut Reader<'a>,
+    read: impl Fn(&mut Reader<'a>) -> Result<T, Error>,
+) -> Result<Vec<T>, Error> {
+    let len = reader.read_u16()? as usize;
+    if len != PROOF_BITS {
+        return Err(Error::InvalidLength);
+    }
+    (0..len).map(|_| read(reader)).collect()
+}
+
+/// Verify the canonical bytes of a DLEQ proof against the compressed accordant spend key share
+/// `spend` and the compressed adaptor key `adaptor`.
+pub fn verify(proof: &[u8], spend: &[u8; 32], adaptor: &[u8; 33]) -> Result<(), Error> {
+    let spend = ed25519PointCompressed(*spend)
+        .decompress()
+        .ok_or(Error::InvalidPoint)?;
+    let adaptor = secp256k1Point::from_bytes(*adaptor).ok_or(Error::InvalidPoint)?;
+
+    let mut reader = Reader::new(proof);
+    let c_g = read_vec(&mut reader, read_ed25519_point)?;
+    let c_h = read_vec(&mut reader, read_secp256k1_point)?;
+    let ring_signatures = read_vec(&mut reader, |reader| {
+        Ok(RingSignature {
+            e_g_0_i: read_ed25519_scalar(reader)?,
+            e_h_0_i: read_secp256k1_scalar(reader)?,
+            a_0_i: read_ed25519_scalar(reader)?,
+            b_0_i: read_secp256k1_scalar(reader)?,
+            a_1_i: read_ed25519_scalar(reader)?,
+            b_1_i: read_secp256k1_scalar(reader)?,
+        })
+    })?;
+    let pok_0 = (
+        read_ed25519_point(&mut reader)?,
+        read_ed25519_scalar(&mut reader)?,
+    );
+    let pok_1 = ecdsa_fun::Signature::from_bytes(reader.read_array()?)
+        .ok_or(Error::InvalidProofOfKnowledge)?;
+    reader.finish()?;
+
+    verify_proof(
+        &c_g,
+        &c_h,
+        &ring_signatures,
+        &pok_0,
+        &pok_1,
+        &spend,
+        &adaptor,
+    )
+}
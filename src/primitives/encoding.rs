@@ -0,0 +1,550 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Wire encoding of the protocol messages, byte-for-byte compatible with the
+//! [`consensus`](crate::consensus) encoding of the framework: integers are little-endian and
+//! vectors are prefixed with their length on two bytes.
+//!
+//! The messages of the commit/reveal round and the procedure signatures are decoded into views
+//! borrowing the canonical bytes of their keys, commitments, proof and signatures, which are then
+//! checked with the [`commitment`](super::commitment), [`dleq`](super::dleq) and
+//! [`adaptor`](super::adaptor) primitives.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Maximum number of elements of an encoded vector.
+pub const MAX_VEC_LEN: usize = u16::MAX as usize;
+
+/// Version of the protocol messages encoding, prefixed to the versioned messages.
+pub const MESSAGE_ENCODING_VERSION: u16 = 1;
+
+/// Errors encountered when encoding or decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The input ended before the value was fully decoded.
+    UnexpectedEnd,
+    /// The vector has more than [`MAX_VEC_LEN`] elements.
+    TooLong,
+    /// The input was not entirely consumed.
+    TrailingBytes,
+    /// The message is encoded with an unsupported version.
+    UnsupportedVersion(u16),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "Unexpected end of input"),
+            Self::TooLong => write!(f, "Value is too long"),
+            Self::TrailingBytes => write!(f, "Data not consumed entirely"),
+            Self::UnsupportedVersion(v) => write!(f, "Unsupported message version {}", v),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Append the length-prefixed bytes to the output.
+pub fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) -> Result<(), Error> {
+    if bytes.len() > MAX_VEC_LEN {
+        return Err(Error::TooLong);
+    }
+    out.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+    out.extend_from_slice(bytes);
+    Ok(())
+}
+
+/// A cursor decoding values from a byte slice.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Start reading the bytes.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Return the bytes not read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Read the next `len` bytes.
+    pub fn read_slice(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < len {
+            return Err(Error::UnexpectedEnd);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    /// Read a fixed size array.
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.read_slice(N)?);
+        Ok(array)
+    }
+
+    /// Read a `u8`.
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        self.read_array::<1>().map(u8::from_le_bytes)
+    }
+
+    /// Read a little-endian `u16`.
+    pub fn read_u16(&mut self) -> Result<u16, Error> {
+        self.read_array::<2>().map(u16::from_le_bytes)
+    }
+
+    /// Read a little-endian `u32`.
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        self.read_array::<4>().map(u32::from_le_bytes)
+    }
+
+    /// Read a little-endian `u64`.
+    pub fn read_u64(&mut self) -> Result<u64, Error> {
+        self.read_array::<8>().map(u64::from_le_bytes)
+    }
+
+    /// Read length-prefixed bytes, e.g. the canonical bytes of a key or a signature.
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.read_u16()?;
+        self.read_slice(len as usize)
+    }
+
+    /// Check that all the bytes have been read.
+    pub fn finish(self) -> Result<(), Error> {
+        match self.bytes.is_empty() {
+            true => Ok(()),
+            false => Err(Error::TrailingBytes),
+        }
+    }
+}
+
+/// An element tagged with an identifier, e.g. an extra key tagged with its key identifier or a
+/// shared key tagged with its shared key identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaggedBytes<'a> {
+    /// The identifier of the element.
+    pub tag: u16,
+    /// The canonical bytes of the element.
+    pub bytes: &'a [u8],
+}
+
+fn read_tagged<'a>(reader: &mut Reader<'a>) -> Result<Vec<TaggedBytes<'a>>, Error> {
+    let len = reader.read_u16()?;
+    (0..len)
+        .map(|_| {
+            Ok(TaggedBytes {
+                tag: reader.read_u16()?,
+                bytes: reader.read_bytes()?,
+            })
+        })
+        .collect()
+}
+
+fn write_tagged(out: &mut Vec<u8>, elements: &[TaggedBytes]) -> Result<(), Error> {
+    if elements.len() > MAX_VEC_LEN {
+        return Err(Error::TooLong);
+    }
+    out.extend_from_slice(&(elements.len() as u16).to_le_bytes());
+    for element in elements {
+        out.extend_from_slice(&element.tag.to_le_bytes());
+        write_bytes(out, element.bytes)?;
+    }
+    Ok(())
+}
+
+/// A protocol message decoded from its wire encoding, borrowing the canonical bytes of its fields
+/// from the decoded input.
+pub trait Message<'a>: Sized {
+    /// Decode the message from the reader.
+    fn decode(reader: &mut Reader<'a>) -> Result<Self, Error>;
+
+    /// Append the encoded message to the output.
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), Error>;
+}
+
+/// Decode a message, all the bytes must be consumed.
+pub fn decode<'a, M: Message<'a>>(bytes: &'a [u8]) -> Result<M, Error> {
+    let mut reader = Reader::new(bytes);
+    let message = M::decode(&mut reader)?;
+    reader.finish()?;
+    Ok(message)
+}
+
+/// Decode a message prefixed with the [`MESSAGE_ENCODING_VERSION`], all the bytes must be
+/// consumed.
+pub fn decode_versioned<'a, M: Message<'a>>(bytes: &'a [u8]) -> Result<M, Error> {
+    let mut reader = Reader::new(bytes);
+    match reader.read_u16()? {
+        MESSAGE_ENCODING_VERSION => (),
+        version => return Err(Error::UnsupportedVersion(version)),
+    }
+    let message = M::decode(&mut reader)?;
+    reader.finish()?;
+    Ok(message)
+}
+
+/// Encode a message prefixed with the [`MESSAGE_ENCODING_VERSION`].
+pub fn encode_versioned<'a, M: Message<'a>>(message: &M) -> Result<Vec<u8>, Error> {
+    let mut out = MESSAGE_ENCODING_VERSION.to_le_bytes().to_vec();
+    message.encode(&mut out)?;
+    Ok(out)
+}
+
+/// Alice's commitments to her parameters, see `protocol::message::CommitAliceParameters`. Each
+/// commitment is the algorithm identifier followed by the hash, opened with
+/// [`verify_commitment`](super::commitment::verify_commitment).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitAliceParameters<'a> {
+    /// The swap identifier.
+    pub swap_id: [u8; 16],
+    /// Commitment to the buy public key.
+    pub buy: &'a [u8],
+    /// Commitment to the cancel public key.
+    pub cancel: &'a [u8],
+    /// Commitment to the refund public key.
+    pub refund: &'a [u8],
+    /// Commitment to the punish public key.
+    pub punish: &'a [u8],
+    /// Commitment to the adaptor public key.
+    pub adaptor: &'a [u8],
+    /// Commitments to the extra arbitrating public keys.
+    pub extra_arbitrating_keys: Vec<TaggedBytes<'a>>,
+    /// Commitments to the arbitrating shared keys.
+    pub arbitrating_shared_keys: Vec<TaggedBytes<'a>>,
+    /// Commitment to the spend public key.
+    pub spend: &'a [u8],
+    /// Commitments to the extra accordant public keys.
+    pub extra_accordant_keys: Vec<TaggedBytes<'a>>,
+    /// Commitments to the accordant shared keys.
+    pub accordant_shared_keys: Vec<TaggedBytes<'a>>,
+}
+
+impl<'a> Message<'a> for CommitAliceParameters<'a> {
+    fn decode(reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            swap_id: reader.read_array()?,
+            buy: reader.read_bytes()?,
+            cancel: reader.read_bytes()?,
+            refund: reader.read_bytes()?,
+            punish: reader.read_bytes()?,
+            adaptor: reader.read_bytes()?,
+            extra_arbitrating_keys: read_tagged(reader)?,
+            arbitrating_shared_keys: read_tagged(reader)?,
+            spend: reader.read_bytes()?,
+            extra_accordant_keys: read_tagged(reader)?,
+            accordant_shared_keys: read_tagged(reader)?,
+        })
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        out.extend_from_slice(&self.swap_id);
+        write_bytes(out, self.buy)?;
+        write_bytes(out, self.cancel)?;
+        write_bytes(out, self.refund)?;
+        write_bytes(out, self.punish)?;
+        write_bytes(out, self.adaptor)?;
+        write_tagged(out, &self.extra_arbitrating_keys)?;
+        write_tagged(out, &self.arbitrating_shared_keys)?;
+        write_bytes(out, self.spend)?;
+        write_tagged(out, &self.extra_accordant_keys)?;
+        write_tagged(out, &self.accordant_shared_keys)
+    }
+}
+
+/// Bob's commitments to his parameters, see `protocol::message::CommitBobParameters`. Each
+/// commitment is the algorithm identifier followed by the hash, opened with
+/// [`verify_commitment`](super::commitment::verify_commitment).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitBobParameters<'a> {
+    /// The swap identifier.
+    pub swap_id: [u8; 16],
+    /// Commitment to the buy public key.
+    pub buy: &'a [u8],
+    /// Commitment to the cancel public key.
+    pub cancel: &'a [u8],
+    /// Commitment to the refund public key.
+    pub refund: &'a [u8],
+    /// Commitment to the adaptor public key.
+    pub adaptor: &'a [u8],
+    /// Commitments to the extra arbitrating public keys.
+    pub extra_arbitrating_keys: Vec<TaggedBytes<'a>>,
+    /// Commitments to the arbitrating shared keys.
+    pub arbitrating_shared_keys: Vec<TaggedBytes<'a>>,
+    /// Commitment to the spend public key.
+    pub spend: &'a [u8],
+    /// Commitments to the extra accordant public keys.
+    pub extra_accordant_keys: Vec<TaggedBytes<'a>>,
+    /// Commitments to the accordant shared keys.
+    pub accordant_shared_keys: Vec<TaggedBytes<'a>>,
+}
+
+impl<'a> Message<'a> for CommitBobParameters<'a> {
+    fn decode(reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            swap_id: reader.read_array()?,
+            buy: reader.read_bytes()?,
+            cancel: reader.read_bytes()?,
+            refund: reader.read_bytes()?,
+            adaptor: reader.read_bytes()?,
+            extra_arbitrating_keys: read_tagged(reader)?,
+            arbitrating_shared_keys: read_tagged(reader)?,
+            spend: reader.read_bytes()?,
+            extra_accordant_keys: read_tagged(reader)?,
+            accordant_shared_keys: read_tagged(reader)?,
+        })
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        out.extend_from_slice(&self.swap_id);
+        write_bytes(out, self.buy)?;
+        write_bytes(out, self.cancel)?;
+        write_bytes(out, self.refund)?;
+        write_bytes(out, self.adaptor)?;
+        write_tagged(out, &self.extra_arbitrating_keys)?;
+        write_tagged(out, &self.arbitrating_shared_keys)?;
+        write_bytes(out, self.spend)?;
+        write_tagged(out, &self.extra_accordant_keys)?;
+        write_tagged(out, &self.accordant_shared_keys)
+    }
+}
+
+/// Alice's revealed parameters, see `protocol::message::RevealAliceParameters`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevealAliceParameters<'a> {
+    /// The swap identifier.
+    pub swap_id: [u8; 16],
+    /// The buy public key.
+    pub buy: &'a [u8],
+    /// The cancel public key.
+    pub cancel: &'a [u8],
+    /// The refund public key.
+    pub refund: &'a [u8],
+    /// The punish public key.
+    pub punish: &'a [u8],
+    /// The adaptor public key.
+    pub adaptor: &'a [u8],
+    /// The extra arbitrating public keys.
+    pub extra_arbitrating_keys: Vec<TaggedBytes<'a>>,
+    /// The arbitrating shared keys.
+    pub arbitrating_shared_keys: Vec<TaggedBytes<'a>>,
+    /// The spend public key.
+    pub spend: &'a [u8],
+    /// The extra accordant public keys.
+    pub extra_accordant_keys: Vec<TaggedBytes<'a>>,
+    /// The accordant shared keys.
+    pub accordant_shared_keys: Vec<TaggedBytes<'a>>,
+    /// The destination address.
+    pub address: &'a [u8],
+    /// The cross-group DLEQ proof of the adaptor and spend keys.
+    pub proof: &'a [u8],
+}
+
+impl<'a> Message<'a> for RevealAliceParameters<'a> {
+    fn decode(reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            swap_id: reader.read_array()?,
+            buy: reader.read_bytes()?,
+            cancel: reader.read_bytes()?,
+            refund: reader.read_bytes()?,
+            punish: reader.read_bytes()?,
+            adaptor: reader.read_bytes()?,
+            extra_arbitrating_keys: read_tagged(reader)?,
+            arbitrating_shared_keys: read_tagged(reader)?,
+            spend: reader.read_bytes()?,
+            extra_accordant_keys: read_tagged(reader)?,
+            accordant_shared_keys: read_tagged(reader)?,
+            address: reader.read_bytes()?,
+            proof: reader.read_bytes()?,
+        })
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        out.extend_from_slice(&self.swap_id);
+        write_bytes(out, self.buy)?;
+        write_bytes(out, self.cancel)?;
+        write_bytes(out, self.refund)?;
+        write_bytes(out, self.punish)?;
+        write_bytes(out, self.adaptor)?;
+        write_tagged(out, &self.extra_arbitrating_keys)?;
+        write_tagged(out, &self.arbitrating_shared_keys)?;
+        write_bytes(out, self.spend)?;
+        write_tagged(out, &self.extra_accordant_keys)?;
+        write_tagged(out, &self.accordant_shared_keys)?;
+        write_bytes(out, self.address)?;
+        write_bytes(out, self.proof)
+    }
+}
+
+/// Bob's revealed parameters, see `protocol::message::RevealBobParameters`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevealBobParameters<'a> {
+    /// The swap identifier.
+    pub swap_id: [u8; 16],
+    /// The buy public key.
+    pub buy: &'a [u8],
+    /// The cancel public key.
+    pub cancel: &'a [u8],
+    /// The refund public key.
+    pub refund: &'a [u8],
+    /// The adaptor public key.
+    pub adaptor: &'a [u8],
+    /// The extra arbitrating public keys.
+    pub extra_arbitrating_keys: Vec<TaggedBytes<'a>>,
+    /// The arbitrating shared keys.
+    pub arbitrating_shared_keys: Vec<TaggedBytes<'a>>,
+    /// The spend public key.
+    pub spend: &'a [u8],
+    /// The extra accordant public keys.
+    pub extra_accordant_keys: Vec<TaggedBytes<'a>>,
+    /// The accordant shared keys.
+    pub accordant_shared_keys: Vec<TaggedBytes<'a>>,
+    /// The refund address.
+    pub address: &'a [u8],
+    /// The cross-group DLEQ proof of the adaptor and spend keys.
+    pub proof: &'a [u8],
+}
+
+impl<'a> Message<'a> for RevealBobParameters<'a> {
+    fn decode(reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            swap_id: reader.read_array()?,
+            buy: reader.read_bytes()?,
+            cancel: reader.read_bytes()?,
+            refund: reader.read_bytes()?,
+            adaptor: reader.read_bytes()?,
+            extra_arbitrating_keys: read_tagged(reader)?,
+            arbitrating_shared_keys: read_tagged(reader)?,
+            spend: reader.read_bytes()?,
+            extra_accordant_keys: read_tagged(reader)?,
+            accordant_shared_keys: read_tagged(reader)?,
+            address: reader.read_bytes()?,
+            proof: reader.read_bytes()?,
+        })
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        out.extend_from_slice(&self.swap_id);
+        write_bytes(out, self.buy)?;
+        write_bytes(out, self.cancel)?;
+        write_bytes(out, self.refund)?;
+        write_bytes(out, self.adaptor)?;
+        write_tagged(out, &self.extra_arbitrating_keys)?;
+        write_tagged(out, &self.arbitrating_shared_keys)?;
+        write_bytes(out, self.spend)?;
+        write_tagged(out, &self.extra_accordant_keys)?;
+        write_tagged(out, &self.accordant_shared_keys)?;
+        write_bytes(out, self.address)?;
+        write_bytes(out, self.proof)
+    }
+}
+
+/// Bob's cancel signature and refund adaptor signature, see
+/// `protocol::message::RefundProcedureSignatures`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefundProcedureSignatures<'a> {
+    /// The swap identifier.
+    pub swap_id: [u8; 16],
+    /// The cancel signature.
+    pub cancel_sig: &'a [u8],
+    /// The refund adaptor signature.
+    pub refund_adaptor_sig: &'a [u8],
+}
+
+impl<'a> Message<'a> for RefundProcedureSignatures<'a> {
+    fn decode(reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            swap_id: reader.read_array()?,
+            cancel_sig: reader.read_bytes()?,
+            refund_adaptor_sig: reader.read_bytes()?,
+        })
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        out.extend_from_slice(&self.swap_id);
+        write_bytes(out, self.cancel_sig)?;
+        write_bytes(out, self.refund_adaptor_sig)
+    }
+}
+
+/// Bob's buy transaction and buy adaptor signature, see
+/// `protocol::message::BuyProcedureSignature`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuyProcedureSignature<'a> {
+    /// The swap identifier.
+    pub swap_id: [u8; 16],
+    /// The partial buy transaction.
+    pub buy: &'a [u8],
+    /// The buy adaptor signature.
+    pub buy_adaptor_sig: &'a [u8],
+}
+
+impl<'a> Message<'a> for BuyProcedureSignature<'a> {
+    fn decode(reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            swap_id: reader.read_array()?,
+            buy: reader.read_bytes()?,
+            buy_adaptor_sig: reader.read_bytes()?,
+        })
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        out.extend_from_slice(&self.swap_id);
+        write_bytes(out, self.buy)?;
+        write_bytes(out, self.buy_adaptor_sig)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    use crate::consensus::{self, serialize};
+
+    #[test]
+    fn read_consensus_encoding() {
+        let value: Vec<u8> = vec![1, 2, 3];
+        let mut bytes = serialize(&value);
+        bytes.extend(serialize(&0x0102_0304u32));
+        bytes.extend(serialize(&0x0506u16));
+
+        let mut out = vec![];
+        write_bytes(&mut out, &value).unwrap();
+        assert_eq!(out, serialize(&value));
+
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(reader.read_bytes().unwrap(), &value[..]);
+        assert_eq!(reader.read_u32().unwrap(), 0x0102_0304);
+        assert_eq!(reader.clone().finish(), Err(Error::TrailingBytes));
+        assert_eq!(reader.read_u16().unwrap(), 0x0506);
+        assert_eq!(reader.read_u8(), Err(Error::UnexpectedEnd));
+        assert!(reader.finish().is_ok());
+
+        let uuid = crate::Uuid::new();
+        let bytes = serialize(&uuid);
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(
+            consensus::deserialize::<crate::Uuid>(reader.read_slice(16).unwrap()).unwrap(),
+            uuid
+        );
+    }
+}
@@ -22,27 +22,44 @@
 
 use std::io;
 
-use crate::blockchain::{Fee, FeePriority, FeeStrategy, Transactions};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey, Signing, Verification};
+
+use crate::blockchain::{
+    Fee, FeePolitics, FeePriority, FeeStrategy, FeeStrategyError, Transactions,
+};
 use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
 use crate::crypto::{
     self, AccordantKeyId, ArbitratingKeyId, Commit, DeriveKeys, EncSign, KeyGenerator,
     RecoverSecret, SharedKeyId, Sign, TaggedElement, TaggedElements, TaggedExtraKeys,
     TaggedSharedKeys,
 };
+use crate::protocol::abort::{AbortPlan, SignedAbort};
+use crate::protocol::events::SwapEvent;
 use crate::protocol::message::{
-    BuyProcedureSignature, CommitAliceParameters, CommitBobParameters, CoreArbitratingSetup,
-    RevealAliceParameters, RevealBobParameters,
+    Abort, BuyProcedureSignature, CommitAliceParameters, CommitBobParameters, CoreArbitratingSetup,
+    RevealAliceParameters, RevealBobParameters, SwapMessage,
 };
-use crate::script::{DataLock, DataPunishableLock, ScriptPath, SwapRoleKeys};
-use crate::swap::SwapId;
+use crate::role::SwapRole;
+use crate::script::{DataLock, DataPunishableLock, ScriptPath, SwapRoleKeys, RECOVERY_KEY_TAG};
+use crate::swap::{self, SwapId};
 use crate::trade::Deal;
 use crate::transaction::{
-    Buyable, Cancelable, Chainable, Fundable, Lockable, Punishable, Refundable, Transaction,
-    Witnessable,
+    Buyable, Cancelable, Fundable, Lockable, Punishable, Refundable, Transaction, Witnessable,
 };
 use crate::{Error, Res};
 
+pub mod abort;
+pub mod confirmation;
+pub mod events;
+pub mod lifecycle;
 pub mod message;
+#[cfg(feature = "protobuf")]
+#[cfg_attr(docsrs, doc(cfg(feature = "protobuf")))]
+pub mod protobuf;
+pub mod race;
+pub mod route;
+pub mod schedule;
+pub mod signed;
 
 struct ValidatedCoreTransactions<Px, Ti, Pk> {
     lock: Px,
@@ -90,6 +107,11 @@ pub struct ArbitratingParameters<Amt, Ti, F> {
     pub cancel_timelock: Ti,
     pub punish_timelock: Ti,
     pub fee_strategy: FeeStrategy<F>,
+    /// The party bearing the fees, if negotiated in the deal terms. Without politics the lock
+    /// output holds the arbitrating amount.
+    pub fee_politics: Option<FeePolitics>,
+    /// The maker swap role, needed to apply the fee politics.
+    pub maker_role: SwapRole,
 }
 
 /// A pair of signatures, one regular and one encrypted.
@@ -257,6 +279,91 @@ pub fn verify_vec_of_commitments<T: Eq, K: CanonicalBytes, C: Clone + Eq>(
         .map(|_| ())
 }
 
+impl<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr> Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr> {
+    /// Return the recovery key added to the extra arbitrating keys under [`RECOVERY_KEY_TAG`], if
+    /// any. The key is used in the punishable lock only if both participants reveal it, see
+    /// [`agreed_recovery_key`].
+    pub fn recovery_key(&self) -> Option<&Pk> {
+        self.extra_arbitrating_keys
+            .iter()
+            .find(|key| *key.tag() == RECOVERY_KEY_TAG)
+            .map(|key| key.elem())
+    }
+
+    // All the arbitrating keys of the participant but its recovery key.
+    fn participant_keys(&self) -> impl Iterator<Item = &Pk> {
+        [&self.buy, &self.cancel, &self.refund, &self.adaptor]
+            .into_iter()
+            .chain(self.punish.as_ref())
+            .chain(
+                self.extra_arbitrating_keys
+                    .iter()
+                    .filter(|key| *key.tag() != RECOVERY_KEY_TAG)
+                    .map(|key| key.elem()),
+            )
+    }
+}
+
+/// Return the recovery key of the punishable lock agreed upon by Alice and Bob. A recovery key is
+/// used only if both participants reveal the same third-party key, fails with
+/// [`swap::Error::InvalidRecoveryKey`] if only one of them reveals a recovery key, if the keys
+/// differ, or if the key is one of the participants' arbitrating keys.
+pub fn agreed_recovery_key<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>(
+    alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+    bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+) -> Result<Option<Pk>, swap::Error>
+where
+    Pk: Copy + PartialEq,
+{
+    let recovery = match (
+        alice_parameters.recovery_key(),
+        bob_parameters.recovery_key(),
+    ) {
+        (None, None) => return Ok(None),
+        (Some(alice), Some(bob)) if alice == bob => *alice,
+        (Some(_), Some(_)) => {
+            return Err(swap::Error::InvalidRecoveryKey(
+                "Participants reveal different recovery keys",
+            ))
+        }
+        _ => {
+            return Err(swap::Error::InvalidRecoveryKey(
+                "Recovery key is not revealed by both participants",
+            ))
+        }
+    };
+    alice_parameters
+        .participant_keys()
+        .chain(bob_parameters.participant_keys())
+        .all(|key| *key != recovery)
+        .then(|| Some(recovery))
+        .ok_or(swap::Error::InvalidRecoveryKey(
+            "Recovery key is a participant key",
+        ))
+}
+
+/// Derive the identifier of the swap from the deal and both commit messages, see
+/// [`SwapId::derive`]. The commit messages are sent before the identifier is known and carry the
+/// identifier of the deal, fails with [`swap::Error::SwapIdMismatch`] otherwise. All the following
+/// messages carry the derived identifier, checked by [`Alice`] and [`Bob`] when received.
+pub fn derive_swap_id<Amt, Bmt, Ti, F, C>(
+    deal: &Deal<Amt, Bmt, Ti, F>,
+    commit_alice: &CommitAliceParameters<C>,
+    commit_bob: &CommitBobParameters<C>,
+) -> Result<SwapId, swap::Error>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+    C: CanonicalBytes,
+{
+    let deal_id = SwapId::from(deal.id());
+    commit_alice.check_swap_id(deal_id)?;
+    commit_bob.check_swap_id(deal_id)?;
+    Ok(SwapId::derive(deal, commit_alice, commit_bob))
+}
+
 impl<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr> Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>
 where
     Pk: Clone + CanonicalBytes,
@@ -423,6 +530,86 @@ impl<Addr, Ar, Ac> Alice<Addr, Ar, Ac> {
             fee_politic,
         }
     }
+
+    /// Abort the swap before the accordant lock, return the signed [`Abort`] message to send to
+    /// the counter-party and the cleanup to apply, see [`abort`](crate::protocol::abort).
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "alice"), err)
+    )]
+    pub fn abort<'a, C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        abort: Abort,
+        secret_key: &SecretKey,
+        events: impl IntoIterator<Item = &'a SwapEvent>,
+    ) -> Res<(SignedAbort, AbortPlan)> {
+        abort::abort(secp, abort, secret_key, SwapRole::Alice, events)
+    }
+
+    /// Handle a signed [`Abort`] message received from the counter-party and return the cleanup
+    /// to apply, see [`abort`](crate::protocol::abort).
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "alice"), err)
+    )]
+    pub fn handle_abort<'a, C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        abort: &SignedAbort,
+        peer_pubkey: &PublicKey,
+        swap_id: SwapId,
+        events: impl IntoIterator<Item = &'a SwapEvent>,
+    ) -> Res<AbortPlan> {
+        abort::handle_abort(secp, abort, peer_pubkey, swap_id, SwapRole::Alice, events)
+    }
+
+    /// Derive the identifier of the swap once both commit messages are exchanged, see
+    /// [`derive_swap_id`].
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "alice"), err)
+    )]
+    pub fn derive_swap_id<Amt, Bmt, Ti, F, C>(
+        &self,
+        deal: &Deal<Amt, Bmt, Ti, F>,
+        commit_alice: &CommitAliceParameters<C>,
+        commit_bob: &CommitBobParameters<C>,
+    ) -> Res<SwapId>
+    where
+        Amt: CanonicalBytes,
+        Bmt: CanonicalBytes,
+        Ti: CanonicalBytes,
+        F: CanonicalBytes,
+        C: CanonicalBytes,
+    {
+        derive_swap_id(deal, commit_alice, commit_bob).map_err(Into::into)
+    }
+
+    /// Verify the reveal message of the counter-party against its commit message. Fails with
+    /// [`swap::Error::SwapIdMismatch`] if the reveal does not carry the derived swap identifier,
+    /// see [`derive_swap_id`].
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "alice"), err)
+    )]
+    pub fn verify_reveal<C, Pk, Qk, Rk, Sk, Pr>(
+        &self,
+        swap_id: SwapId,
+        wallet: &impl Commit<C>,
+        commit: &CommitBobParameters<C>,
+        reveal: RevealBobParameters<Pk, Qk, Rk, Sk, Addr, Pr>,
+    ) -> Res<()>
+    where
+        C: Eq + Clone + CanonicalBytes,
+        Pk: CanonicalBytes,
+        Qk: CanonicalBytes,
+        Rk: CanonicalBytes,
+        Sk: CanonicalBytes,
+    {
+        reveal.check_swap_id(swap_id)?;
+        commit.verify_with_reveal(wallet, reveal)
+    }
 }
 
 impl<Addr, Ar, Ac> Alice<Addr, Ar, Ac>
@@ -436,6 +623,10 @@ where
     /// All data passed to the function are considered trusted and does not require extra
     /// validation. Thus we assume the deal has been validated upfront.
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "alice"), err)
+    )]
     pub fn generate_parameters<Kg, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr>(
         &self,
         key_gen: &mut Kg,
@@ -528,13 +719,17 @@ where
     /// # Execution
     ///
     ///  * Parse and validate the [`Lockable`], [`Cancelable`], [`Refundable`] partial transactions
-    ///  in [`CoreArbitratingTransactions`]
+    ///    in [`CoreArbitratingTransactions`]
     ///  * Retrieve Bob's(counter-party) adaptor public key from Bob's [`Parameters`]
     ///  * Retrieve Alice's own refund public key from Alice's [`Parameters`]
     ///  * Generate the witness data and adaptor-sign(encrypt) it
     ///
     /// Returns the adaptor(encrypted) signature.
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "alice"), err)
+    )]
     pub fn sign_adaptor_refund<Amt, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, S, Ms, Si, EncSig>(
         &self,
         wallet: &mut S,
@@ -545,9 +740,18 @@ where
     ) -> Res<EncSig>
     where
         S: EncSign<Pk, Ms, Si, EncSig>,
-        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
-        Px: Clone + Fee<FeeUnit = F>,
-        Pk: Copy,
+        Ar: Transactions<
+            Addr = Addr,
+            Amt = Amt,
+            Ti = Ti,
+            Ms = Ms,
+            Pk = Pk,
+            Si = Si,
+            Px = Px,
+            FeeUnit = F,
+        >,
+        Px: Clone,
+        Pk: Copy + PartialEq,
         Ti: Copy,
         Amt: Copy + PartialEq,
     {
@@ -586,12 +790,16 @@ where
     /// # Execution
     ///
     ///  * Parse and validate the [`Lockable`], [`Cancelable`], [`Refundable`] partial transactions
-    ///  in [`CoreArbitratingTransactions`]
+    ///    in [`CoreArbitratingTransactions`]
     ///  * Retreive Alice's cancel public key from her own parameters
     ///  * Generate the witness data and sign it
     ///
     /// Returns the witness signature.
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "alice"), err)
+    )]
     pub fn cosign_arbitrating_cancel<Amt, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, S, Ms, Si>(
         &self,
         wallet: &mut S,
@@ -602,9 +810,18 @@ where
     ) -> Res<Si>
     where
         S: Sign<Pk, Ms, Si>,
-        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
-        Px: Clone + Fee<FeeUnit = F>,
-        Pk: Copy,
+        Ar: Transactions<
+            Addr = Addr,
+            Amt = Amt,
+            Ti = Ti,
+            Ms = Ms,
+            Pk = Pk,
+            Si = Si,
+            Px = Px,
+            FeeUnit = F,
+        >,
+        Px: Clone,
+        Pk: Copy + PartialEq,
         Ti: Copy,
         Amt: Copy + PartialEq,
     {
@@ -639,6 +856,7 @@ where
     ///  * `arb_params`: The parameters used to verify core
     ///
     /// _Trusted data_:
+    ///  * `swap_id`: The swap identifier, see [`derive_swap_id`]
     ///  * `wallet`: Alice's own wallet, used to perform cryptographic operations
     ///  * `alice_parameters`: Alice's own parameters
     ///
@@ -648,13 +866,20 @@ where
     ///
     /// # Execution
     ///
+    ///  * Check the swap identifier of the [`BuyProcedureSignature`]
     ///  * Parse and verify the [`Buyable`] partial transaction and the adaptor witness in
-    ///  [`BuyProcedureSignature`] with the public keys from the parameters
+    ///    [`BuyProcedureSignature`] with the public keys from the parameters
     ///
     /// Return `Ok(())` if all tests succeed.
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "alice"), err)
+    )]
+    #[allow(clippy::too_many_arguments)]
     pub fn validate_adaptor_buy<Amt, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, S, Ms, Si, EncSig>(
         &self,
+        swap_id: SwapId,
         wallet: &mut S,
         alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
         bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
@@ -664,13 +889,24 @@ where
     ) -> Res<()>
     where
         S: EncSign<Pk, Ms, Si, EncSig>,
-        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
-        Px: Clone + Fee<FeeUnit = F>,
-        Pk: Copy,
+        Ar: Transactions<
+            Addr = Addr,
+            Amt = Amt,
+            Ti = Ti,
+            Ms = Ms,
+            Pk = Pk,
+            Si = Si,
+            Px = Px,
+            FeeUnit = F,
+        >,
+        Px: Clone,
+        Pk: Copy + PartialEq,
         Ti: Copy,
         F: Copy,
         Amt: Copy + PartialEq,
     {
+        adaptor_buy.check_swap_id(swap_id)?;
+
         // Verifies the core arbitrating transactions.
         let ValidatedCoreTransactions { lock, .. } =
             self.validate_core(alice_parameters, bob_parameters, core, arb_params)?;
@@ -685,9 +921,10 @@ where
         // Initialize the buy transaction based on the extracted partial transaction format.
         let buy = <Ar::Buy>::from_partial(partial_buy);
 
-        buy.is_build_on_top_of(&lock)?;
         buy.verify_template(self.destination_address.clone())?;
-        buy.as_partial().validate_fee(fee_strategy)?;
+        // Check that the buy transaction spends the lock, its output amounts and that its fee
+        // follows the strategy.
+        Ar::verify_buy_template(&buy, &lock, &self.destination_address, fee_strategy)?;
 
         // Verify the adaptor buy witness
         let msg = buy.generate_witness_message(ScriptPath::Success)?;
@@ -719,6 +956,7 @@ where
     ///  * `arb_params`: The parameters used to verify core
     ///
     /// _Trusted data_:
+    ///  * `swap_id`: The swap identifier, see [`derive_swap_id`]
     ///  * `wallet`: Alice's own wallet, used to perform cryptographic operations
     ///  * `alice_parameters`: Alice's own parameters
     ///  * `bob_parameters`: Bob's parameters
@@ -728,6 +966,7 @@ where
     ///
     /// # Execution
     ///
+    ///  * Check the swap identifier of the [`BuyProcedureSignature`]
     ///  * Parse the [`Buyable`] partial transaction in [`BuyProcedureSignature`]
     ///  * Retreive the buy public key from the paramters
     ///  * Generate the buy witness data and sign it
@@ -738,8 +977,14 @@ where
     ///
     /// [`validate_adaptor_buy`]: Alice::validate_adaptor_buy
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "alice"), err)
+    )]
+    #[allow(clippy::too_many_arguments)]
     pub fn fully_sign_buy<Amt, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, S, Ms, Si, EncSig>(
         &self,
+        swap_id: SwapId,
         wallet: &mut S,
         alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
         bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
@@ -749,14 +994,25 @@ where
     ) -> Res<TxSignatures<Si>>
     where
         S: Sign<Pk, Ms, Si> + EncSign<Pk, Ms, Si, EncSig>,
-        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
-        Px: Clone + Fee<FeeUnit = F>,
-        Pk: Copy,
+        Ar: Transactions<
+            Addr = Addr,
+            Amt = Amt,
+            Ti = Ti,
+            Ms = Ms,
+            Pk = Pk,
+            Si = Si,
+            Px = Px,
+            FeeUnit = F,
+        >,
+        Px: Clone,
+        Pk: Copy + PartialEq,
         Ti: Copy,
         F: Copy,
         Amt: Copy + PartialEq,
         EncSig: Clone,
     {
+        adaptor_buy.check_swap_id(swap_id)?;
+
         // Verifies the core arbitrating transactions.
         let ValidatedCoreTransactions { lock, .. } =
             self.validate_core(alice_parameters, bob_parameters, core, arb_params)?;
@@ -771,9 +1027,10 @@ where
         // Initialize the buy transaction based on the extracted partial transaction format.
         let buy = <Ar::Buy>::from_partial(partial_buy);
 
-        buy.is_build_on_top_of(&lock)?;
         buy.verify_template(self.destination_address.clone())?;
-        buy.as_partial().validate_fee(fee_strategy)?;
+        // Check that the buy transaction spends the lock, its output amounts and that its fee
+        // follows the strategy.
+        Ar::verify_buy_template(&buy, &lock, &self.destination_address, fee_strategy)?;
 
         // Generate the witness message to sign and sign with the buy key.
         let msg = buy.generate_witness_message(ScriptPath::Success)?;
@@ -811,6 +1068,10 @@ where
     ///
     /// [`validate_adaptor_buy`]: Alice::validate_adaptor_buy
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "alice"), err)
+    )]
     pub fn fully_sign_punish<Amt, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, S, Ms, Si>(
         &self,
         wallet: &mut S,
@@ -821,9 +1082,18 @@ where
     ) -> Res<FullySignedPunish<Px, Si>>
     where
         S: Sign<Pk, Ms, Si>,
-        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
-        Px: Clone + Fee<FeeUnit = F>,
-        Pk: Copy,
+        Ar: Transactions<
+            Addr = Addr,
+            Amt = Amt,
+            Ti = Ti,
+            Ms = Ms,
+            Pk = Pk,
+            Si = Si,
+            Px = Px,
+            FeeUnit = F,
+        >,
+        Px: Clone,
+        Pk: Copy + PartialEq,
         Ti: Copy,
         F: Copy,
         Amt: Copy + PartialEq,
@@ -843,9 +1113,7 @@ where
             <Ar::Punish>::initialize(&cancel, punish_lock, self.destination_address.clone())?;
 
         // Set the fees according to the strategy in the deal and the local politic.
-        punish
-            .as_partial_mut()
-            .set_fee(fee_strategy, self.fee_politic)?;
+        punish.set_fee(fee_strategy, self.fee_politic)?;
 
         // Generate the witness message to sign and sign with the punish key.
         let msg = punish.generate_witness_message(ScriptPath::Failure)?;
@@ -860,21 +1128,30 @@ where
     /// Given the Bob's parameters, the refund transaction and the encrypted signature for the
     /// refund transaction, return the secret key used to encrypt the signature.
     ///
-    /// This method is used if the refund occurs to allow Alice to unlock her funds.
+    /// This method is used if the refund occurs to allow Alice to unlock her funds. Fails if the
+    /// witness of the refund does not follow the template.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "alice"), err)
+    )]
     pub fn recover_accordant_key<Amt, Tx, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, S, Si, EncSig>(
         &self,
         wallet: &mut S,
         bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+        core: &CoreArbitratingTransactions<Px>,
         refund_adaptor_sig: EncSig,
         refund_tx: Tx,
-    ) -> Rk
+    ) -> Res<Rk>
     where
         S: RecoverSecret<Pk, Rk, Si, EncSig>,
         Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Pk = Pk, Si = Si, Px = Px, Tx = Tx>,
+        Px: Clone,
     {
         let encryption_key = &bob_parameters.adaptor;
-        let signature = <Ar::Refund>::extract_witness(refund_tx);
-        wallet.recover_secret_key(refund_adaptor_sig, encryption_key, signature)
+        // The refund signed by Bob, Alice's signature is found by verifying it against her key
+        let refund = <Ar::Refund>::from_partial(core.refund.clone());
+        let signature = refund.extract_witness(refund_tx)?;
+        Ok(wallet.recover_secret_key(refund_adaptor_sig, encryption_key, signature)?)
     }
 
     // Internal method to parse and validate the core arbitratring transactions received by Alice
@@ -886,6 +1163,8 @@ where
     //  * the transaction template is valid (transaction is well formed, contract and keys are used
     //  correctly)
     //  * the target amount from the deal is correct (for the lock transaction)
+    //  * the transactions spend the expected outputs with the negotiated timelocks and pay the
+    //  agreed scripts amounts above dust (for the cancel and refund transactions)
     //  * the fee strategy validation passes
     //
     fn validate_core<Amt, Pk, Qk, Rk, Sk, Ti, F, Pr, Ms, Si, Px>(
@@ -896,10 +1175,19 @@ where
         arb_params: ArbitratingParameters<Amt, Ti, F>,
     ) -> Res<ValidatedCoreTransactions<Px, Ti, Pk>>
     where
-        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
-        Px: Clone + Fee<FeeUnit = F>,
+        Ar: Transactions<
+            Addr = Addr,
+            Amt = Amt,
+            Ti = Ti,
+            Ms = Ms,
+            Pk = Pk,
+            Si = Si,
+            Px = Px,
+            FeeUnit = F,
+        >,
+        Px: Clone,
         Amt: PartialEq + Copy,
-        Pk: Copy,
+        Pk: Copy + PartialEq,
         Ti: Copy,
     {
         // Extract the partial transaction from the core arbitrating message, this operation should
@@ -927,13 +1215,15 @@ where
 
         // Verify the lock transaction template.
         lock.verify_template(data_lock)?;
-        // The target amount is dictated from the deal.
-        let target_amount = arb_params.arbitrating_amount;
+        // The target amount is dictated from the deal and the fee politics.
+        let target_amount = Ar::lock_amount(&arb_params, &alice_parameters.destination_address)?;
         // Verify the target amount
         lock.verify_target_amount(target_amount)?;
         // Validate that the transaction follows the strategy.
         let fee_strategy = &arb_params.fee_strategy;
-        lock.as_partial().validate_fee(fee_strategy)?;
+        lock.validate_fee(fee_strategy)?
+            .then(|| ())
+            .ok_or(FeeStrategyError::OutOfStrategy)?;
 
         // Get the three keys, Alice and Bob for refund and Alice's punish key. The keys are
         // needed, along with the timelock for the punish, to create the punishable on-chain
@@ -950,6 +1240,7 @@ where
             timelock: arb_params.punish_timelock,
             success: SwapRoleKeys::new(alice_refund, bob_refund),
             failure: alice_punish,
+            recovery: agreed_recovery_key(alice_parameters, bob_parameters)?,
         };
 
         // Extract the partial transaction from the core arbitrating protocol message, this
@@ -958,11 +1249,10 @@ where
 
         // Initialize the lock transaction based on the extracted partial transaction format.
         let cancel = <Ar::Cancel>::from_partial(partial_cancel);
-        // Check that the cancel transaction is build on top of the lock.
-        cancel.is_build_on_top_of(&lock)?;
         cancel.verify_template(data_lock, punish_lock)?;
-        // Validate the fee strategy
-        cancel.as_partial().validate_fee(fee_strategy)?;
+        // Check that the cancel transaction spends the lock with the cancel timelock, its output
+        // amounts and that its fee follows the strategy.
+        Ar::verify_cancel_template(&cancel, &lock, data_lock, punish_lock, fee_strategy)?;
 
         // Extract the partial transaction from the core arbitrating protocol message, this
         // operation should not error if the message is well formed.
@@ -970,12 +1260,11 @@ where
 
         // Initialize the refund transaction based on the extracted partial transaction format.
         let refund = <Ar::Refund>::from_partial(partial_refund);
-        // Check that the refund transaction is build on top of the cancel transaction.
-        refund.is_build_on_top_of(&cancel)?;
-        let refund_address = bob_parameters.destination_address.clone();
-        refund.verify_template(refund_address)?;
-        // Validate the fee strategy
-        refund.as_partial().validate_fee(fee_strategy)?;
+        let refund_address = &bob_parameters.destination_address;
+        refund.verify_template(refund_address.clone())?;
+        // Check that the refund transaction spends the cancel, its output amounts and that its
+        // fee follows the strategy.
+        Ar::verify_refund_template(&refund, &cancel, refund_address, fee_strategy)?;
 
         Ok(ValidatedCoreTransactions {
             lock: lock.to_partial(),
@@ -1056,6 +1345,86 @@ impl<Addr, Ar, Ac> Bob<Addr, Ar, Ac> {
             fee_politic,
         }
     }
+
+    /// Abort the swap before the accordant lock, return the signed [`Abort`] message to send to
+    /// the counter-party and the cleanup to apply, see [`abort`](crate::protocol::abort).
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
+    pub fn abort<'a, C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        abort: Abort,
+        secret_key: &SecretKey,
+        events: impl IntoIterator<Item = &'a SwapEvent>,
+    ) -> Res<(SignedAbort, AbortPlan)> {
+        abort::abort(secp, abort, secret_key, SwapRole::Bob, events)
+    }
+
+    /// Handle a signed [`Abort`] message received from the counter-party and return the cleanup
+    /// to apply, see [`abort`](crate::protocol::abort).
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
+    pub fn handle_abort<'a, C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        abort: &SignedAbort,
+        peer_pubkey: &PublicKey,
+        swap_id: SwapId,
+        events: impl IntoIterator<Item = &'a SwapEvent>,
+    ) -> Res<AbortPlan> {
+        abort::handle_abort(secp, abort, peer_pubkey, swap_id, SwapRole::Bob, events)
+    }
+
+    /// Derive the identifier of the swap once both commit messages are exchanged, see
+    /// [`derive_swap_id`].
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
+    pub fn derive_swap_id<Amt, Bmt, Ti, F, C>(
+        &self,
+        deal: &Deal<Amt, Bmt, Ti, F>,
+        commit_alice: &CommitAliceParameters<C>,
+        commit_bob: &CommitBobParameters<C>,
+    ) -> Res<SwapId>
+    where
+        Amt: CanonicalBytes,
+        Bmt: CanonicalBytes,
+        Ti: CanonicalBytes,
+        F: CanonicalBytes,
+        C: CanonicalBytes,
+    {
+        derive_swap_id(deal, commit_alice, commit_bob).map_err(Into::into)
+    }
+
+    /// Verify the reveal message of the counter-party against its commit message. Fails with
+    /// [`swap::Error::SwapIdMismatch`] if the reveal does not carry the derived swap identifier,
+    /// see [`derive_swap_id`].
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
+    pub fn verify_reveal<C, Pk, Qk, Rk, Sk, Pr>(
+        &self,
+        swap_id: SwapId,
+        wallet: &impl Commit<C>,
+        commit: &CommitAliceParameters<C>,
+        reveal: RevealAliceParameters<Pk, Qk, Rk, Sk, Addr, Pr>,
+    ) -> Res<()>
+    where
+        C: Eq + Clone + CanonicalBytes,
+        Pk: CanonicalBytes,
+        Qk: CanonicalBytes,
+        Rk: CanonicalBytes,
+        Sk: CanonicalBytes,
+    {
+        reveal.check_swap_id(swap_id)?;
+        commit.verify_with_reveal(wallet, reveal)
+    }
 }
 
 impl<Addr, Ar, Ac> Bob<Addr, Ar, Ac>
@@ -1069,6 +1438,10 @@ where
     /// All the data passed to the function are considered trusted and does not require extra
     /// validation. The deal is assumend to be validated by user upfront.
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
     pub fn generate_parameters<Amt, Bmt, Pk, Qk, Rk, Sk, Ti, F, Pr, Kg>(
         &self,
         key_gen: &mut Kg,
@@ -1172,6 +1545,10 @@ where
     ///
     /// [`FeeStrategy`]: crate::blockchain::FeeStrategy
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
     pub fn core_arbitrating_transactions<Amt, Tx, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, Out>(
         &self,
         alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
@@ -1180,10 +1557,18 @@ where
         arb_params: ArbitratingParameters<Amt, Ti, F>,
     ) -> Res<CoreArbitratingTransactions<Px>>
     where
-        Ar: Transactions<Addr = Addr, Amt = Amt, Tx = Tx, Out = Out, Ti = Ti, Pk = Pk, Px = Px>,
-        Px: Fee<FeeUnit = F>,
+        Ar: Transactions<
+            Addr = Addr,
+            Amt = Amt,
+            Tx = Tx,
+            Out = Out,
+            Ti = Ti,
+            Pk = Pk,
+            Px = Px,
+            FeeUnit = F,
+        >,
         Out: Eq,
-        Pk: Copy,
+        Pk: Copy + PartialEq,
         Amt: Copy,
         Ti: Copy,
     {
@@ -1209,8 +1594,8 @@ where
             failure: SwapRoleKeys::new(alice_cancel, bob_cancel),
         };
 
-        // The target amount is dictated from the deal.
-        let target_amount = arb_params.arbitrating_amount;
+        // The target amount is dictated from the deal and the fee politics.
+        let target_amount = Ar::lock_amount(&arb_params, &alice_parameters.destination_address)?;
 
         // Initialize the lockable transaction based on the fundable structure. The lockable
         // transaction prepare the on-chain contract for a buy or a cancel. The amount of available
@@ -1219,7 +1604,9 @@ where
 
         // Ensure that the transaction contains enough assets to pass the fee validation latter.
         let fee_strategy = &arb_params.fee_strategy;
-        lock.as_partial().validate_fee(fee_strategy)?;
+        lock.validate_fee(fee_strategy)?
+            .then(|| ())
+            .ok_or(FeeStrategyError::OutOfStrategy)?;
 
         // Get the three keys, Alice and Bob for refund and Alice's punish key. The keys are
         // needed, along with the timelock for the punish, to create the punishable on-chain
@@ -1234,6 +1621,7 @@ where
             timelock: arb_params.punish_timelock,
             success: SwapRoleKeys::new(alice_refund, bob_refund),
             failure: alice_punish,
+            recovery: agreed_recovery_key(alice_parameters, bob_parameters)?,
         };
 
         // Initialize the cancel transaction for the lock transaction, removing the funds from the
@@ -1241,18 +1629,14 @@ where
         let mut cancel = <Ar::Cancel>::initialize(&lock, cancel_lock, punish_lock)?;
 
         // Set the fees according to the strategy in the deal and the local politic.
-        cancel
-            .as_partial_mut()
-            .set_fee(fee_strategy, self.fee_politic)?;
+        cancel.set_fee(fee_strategy, self.fee_politic)?;
 
         // Initialize the refund transaction for the cancel transaction, moving the funds out of
         // the punishable lock to Bob's refund address.
         let mut refund = <Ar::Refund>::initialize(&cancel, self.refund_address.clone())?;
 
         // Set the fees according to the strategy in the deal and the local politic.
-        refund
-            .as_partial_mut()
-            .set_fee(fee_strategy, self.fee_politic)?;
+        refund.set_fee(fee_strategy, self.fee_politic)?;
 
         Ok(CoreArbitratingTransactions {
             lock: lock.to_partial(),
@@ -1279,6 +1663,10 @@ where
     ///
     /// [`cosign_arbitrating_cancel`]: Bob::cosign_arbitrating_cancel
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
     pub fn cosign_arbitrating_cancel<S, Px, Si, Pk, Ms>(
         &self,
         wallet: &mut S,
@@ -1334,6 +1722,10 @@ where
     ///
     /// Return `Ok(())` if all tests succeed.
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
     pub fn validate_adaptor_refund<Amt, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, S, Ms, Si, EncSig>(
         &self,
         wallet: &mut S,
@@ -1406,6 +1798,10 @@ where
     /// [`sign_adaptor_buy`]: Bob::sign_adaptor_buy
     /// [`validate_adaptor_refund`]: Bob::validate_adaptor_refund
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
     pub fn sign_adaptor_buy<Amt, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, S, Ms, Si, EncSig, U>(
         &self,
         swap_id: U,
@@ -1417,8 +1813,17 @@ where
     ) -> Res<BuyProcedureSignature<Px, EncSig>>
     where
         S: EncSign<Pk, Ms, Si, EncSig>,
-        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
-        Px: Clone + Fee<FeeUnit = F>,
+        Ar: Transactions<
+            Addr = Addr,
+            Amt = Amt,
+            Ti = Ti,
+            Ms = Ms,
+            Pk = Pk,
+            Si = Si,
+            Px = Px,
+            FeeUnit = F,
+        >,
+        Px: Clone,
         Pk: Copy,
         Ti: Copy,
         U: Into<SwapId>,
@@ -1457,8 +1862,7 @@ where
 
         // Set the fees according to the strategy in the deal and the local politic.
         let fee_strategy = &arb_params.fee_strategy;
-        buy.as_partial_mut()
-            .set_fee(fee_strategy, self.fee_politic)?;
+        buy.set_fee(fee_strategy, self.fee_politic)?;
 
         // Generate the witness message to sign and adaptor sign with the buy key and the
         // counter-party adaptor.
@@ -1495,6 +1899,10 @@ where
     /// [`sign_arbitrating_lock`]: Bob::sign_arbitrating_lock
     /// [`validate_adaptor_refund`]: Bob::validate_adaptor_refund
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
     pub fn sign_arbitrating_lock<S, Px, Si, Pk, Ms>(
         &self,
         wallet: &mut S,
@@ -1544,6 +1952,10 @@ where
     ///
     /// [`validate_adaptor_refund`]: Bob::validate_adaptor_refund
     ///
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
     pub fn fully_sign_refund<S, Px, Si, Pk, Ms, EncSig>(
         &self,
         wallet: &mut S,
@@ -1575,19 +1987,35 @@ where
 
     /// This function allows to recover the secret key used to encrypt the buy signature, allowing
     /// Bob to recover Alice's secret and transfer ownership of funds.
+    ///
+    /// The whole witness of the buy transaction is searched for Bob's decrypted signature, so a
+    /// non-standard witness from Alice does not prevent the recovery. Fails if the signature is
+    /// not found.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(skip_all, fields(role = "bob"), err)
+    )]
     pub fn recover_accordant_key<S, Tx, Px, Si, Pk, Qk, Rk, Sk, Ti, F, Pr, EncSig>(
         &self,
         wallet: &mut S,
         alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
-        buy_adaptor_sig: EncSig,
+        adaptor_buy: &BuyProcedureSignature<Px, EncSig>,
         buy_tx: Tx,
-    ) -> Rk
+    ) -> Res<Rk>
     where
         S: RecoverSecret<Pk, Rk, Si, EncSig>,
         Ar: Transactions<Addr = Addr, Tx = Tx, Px = Px, Pk = Pk, Si = Si>,
+        Px: Clone,
+        EncSig: Clone,
     {
         let encryption_key = &alice_parameters.adaptor;
-        let signature = <Ar::Buy>::extract_witness(buy_tx);
-        wallet.recover_secret_key(buy_adaptor_sig, encryption_key, signature)
+        // The buy signed by Alice, Bob's signature is found by verifying it against his key
+        let buy = <Ar::Buy>::from_partial(adaptor_buy.buy.clone());
+        let signature = buy.extract_witness(buy_tx)?;
+        Ok(wallet.recover_secret_key(
+            adaptor_buy.buy_adaptor_sig.clone(),
+            encryption_key,
+            signature,
+        )?)
     }
 }
@@ -0,0 +1,307 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Standardized early exit of a swap. A participant can abort the swap as long as the accordant
+//! lock has not been seen, afterwards Alice's assets are locked and the swap must complete or be
+//! cancelled on-chain. The [`Abort`] message is then sent signed with the session identity key of
+//! the sender, see [`SignedMessage`], so a relay cannot abort a swap on behalf of a participant.
+//!
+//! Both the sender and the receiver of the abort compute an [`AbortPlan`] from the events seen so
+//! far: the counter-party signatures that must be dropped, so the swap can never be resumed, and
+//! how the funds already sent on-chain are recovered.
+//!
+//! | Role  | Arbitrating lock | Invalidated signatures | Refund                            |
+//! |-------|------------------|------------------------|-----------------------------------|
+//! | Alice | any              | `buy`                  | [`RefundPlan::Nothing`]           |
+//! | Bob   | not seen         | `cancel`, `refund`     | [`RefundPlan::Nothing`]           |
+//! | Bob   | not seen, funding seen | `cancel`, `refund` once the sweep is confirmed | [`RefundPlan::SweepFunding`] |
+//! | Bob   | seen             | none                   | [`RefundPlan::CancelAndRefund`]   |
+//!
+//! Once the arbitrating lock is seen Bob keeps Alice's `cancel` and `refund` signatures, they are
+//! the only way to get his funds back. If only the funding has been seen, the lock may still be
+//! mined instead of the sweep of the funding, so the signatures are kept until the sweep is
+//! confirmed, see [`AbortPlan::sweep_confirmed`].
+
+use std::collections::HashMap;
+
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey, Signing, Verification};
+
+use crate::protocol::events::SwapEvent;
+use crate::protocol::message::{Abort, SwapMessage};
+use crate::protocol::signed::SignedMessage;
+use crate::role::SwapRole;
+use crate::swap::{self, SwapId};
+use crate::transaction::TxLabel;
+use crate::Res;
+
+/// An [`Abort`] message signed by its sender.
+pub type SignedAbort = SignedMessage<Abort>;
+
+/// How the funds of a participant are recovered after an abort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum RefundPlan {
+    /// No funds have been sent on-chain.
+    Nothing,
+    /// The funding has been seen but not the arbitrating lock, the funding output is swept back
+    /// to the wallet.
+    SweepFunding,
+    /// The arbitrating lock has been seen, the cancel is broadcast once its timelock expires and
+    /// followed by the refund.
+    CancelAndRefund,
+}
+
+/// The cleanup to apply after an abort.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbortPlan {
+    /// The aborted swap.
+    pub swap_id: SwapId,
+    /// The role of the participant applying the plan.
+    pub role: SwapRole,
+    /// The transactions for which the signatures received from the counter-party must be
+    /// dropped.
+    pub invalidated: Vec<TxLabel>,
+    /// The transactions for which the signatures received from the counter-party must be kept
+    /// until the sweep of the funding is confirmed, and dropped afterwards.
+    pub invalidated_after_sweep: Vec<TxLabel>,
+    /// How the funds already sent on-chain are recovered.
+    pub refund: RefundPlan,
+}
+
+impl AbortPlan {
+    /// Compute the plan of the participant with `role` given the events seen so far. Fails with
+    /// [`swap::Error::AbortAfterAccordantLock`] if the accordant lock has been seen.
+    pub fn new<'a>(
+        swap_id: SwapId,
+        role: SwapRole,
+        events: impl IntoIterator<Item = &'a SwapEvent>,
+    ) -> Result<Self, swap::Error> {
+        let seen: Vec<TxLabel> = events.into_iter().filter_map(seen_label).collect();
+        (!seen.contains(&TxLabel::AccLock))
+            .then(|| ())
+            .ok_or(swap::Error::AbortAfterAccordantLock)?;
+        let (invalidated, invalidated_after_sweep, refund) = match role {
+            SwapRole::Alice => (vec![TxLabel::Buy], vec![], RefundPlan::Nothing),
+            SwapRole::Bob if seen.contains(&TxLabel::Lock) => {
+                (vec![], vec![], RefundPlan::CancelAndRefund)
+            }
+            // The lock may still be mined until the sweep is confirmed
+            SwapRole::Bob if seen.contains(&TxLabel::Funding) => (
+                vec![],
+                vec![TxLabel::Cancel, TxLabel::Refund],
+                RefundPlan::SweepFunding,
+            ),
+            SwapRole::Bob => (
+                vec![TxLabel::Cancel, TxLabel::Refund],
+                vec![],
+                RefundPlan::Nothing,
+            ),
+        };
+        Ok(Self {
+            swap_id,
+            role,
+            invalidated,
+            invalidated_after_sweep,
+            refund,
+        })
+    }
+
+    /// Record that the sweep of the funding is confirmed, the arbitrating lock can no longer be
+    /// mined: the signatures kept until then are invalidated, see
+    /// [`CounterpartySignatures::invalidate`].
+    pub fn sweep_confirmed(&mut self) {
+        self.invalidated.append(&mut self.invalidated_after_sweep);
+    }
+}
+
+// Return the label of the transaction an event proves has been broadcast.
+fn seen_label(event: &SwapEvent) -> Option<TxLabel> {
+    match event {
+        SwapEvent::TxSeen(tx)
+        | SwapEvent::TxConfirmed { tx, .. }
+        | SwapEvent::TxFinal { tx, .. }
+        | SwapEvent::TxReorged { tx, .. }
+        | SwapEvent::SecretRecovered(tx) => Some(*tx),
+        _ => None,
+    }
+}
+
+/// Abort the swap: sign the abort message with the session identity key and return it with the
+/// plan of the sender.
+pub fn abort<'a, C: Signing>(
+    secp: &Secp256k1<C>,
+    abort: Abort,
+    secret_key: &SecretKey,
+    role: SwapRole,
+    events: impl IntoIterator<Item = &'a SwapEvent>,
+) -> Res<(SignedAbort, AbortPlan)> {
+    let plan = AbortPlan::new(abort.swap_id, role, events)?;
+    Ok((SignedMessage::sign(secp, abort, secret_key), plan))
+}
+
+/// Handle an abort received from the counter-party: verify that it belongs to the swap and has
+/// been signed by the peer, then return the plan of the receiver.
+pub fn handle_abort<'a, C: Verification>(
+    secp: &Secp256k1<C>,
+    abort: &SignedAbort,
+    peer_pubkey: &PublicKey,
+    swap_id: SwapId,
+    role: SwapRole,
+    events: impl IntoIterator<Item = &'a SwapEvent>,
+) -> Res<AbortPlan> {
+    abort.check_swap_id(swap_id)?;
+    abort.verify(secp, peer_pubkey)?;
+    Ok(AbortPlan::new(swap_id, role, events)?)
+}
+
+/// The signatures received from the counter-party, stored by transaction until used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CounterpartySignatures<Sig, EncSig> {
+    signatures: HashMap<TxLabel, Sig>,
+    adaptor_signatures: HashMap<TxLabel, EncSig>,
+}
+
+impl<Sig, EncSig> Default for CounterpartySignatures<Sig, EncSig> {
+    fn default() -> Self {
+        Self {
+            signatures: HashMap::new(),
+            adaptor_signatures: HashMap::new(),
+        }
+    }
+}
+
+impl<Sig, EncSig> CounterpartySignatures<Sig, EncSig> {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store the signature of a transaction.
+    pub fn insert_signature(&mut self, tx: TxLabel, sig: Sig) {
+        self.signatures.insert(tx, sig);
+    }
+
+    /// Store the adaptor signature of a transaction.
+    pub fn insert_adaptor_signature(&mut self, tx: TxLabel, sig: EncSig) {
+        self.adaptor_signatures.insert(tx, sig);
+    }
+
+    /// Return the signature of a transaction, if stored.
+    pub fn signature(&self, tx: TxLabel) -> Option<&Sig> {
+        self.signatures.get(&tx)
+    }
+
+    /// Return the adaptor signature of a transaction, if stored.
+    pub fn adaptor_signature(&self, tx: TxLabel) -> Option<&EncSig> {
+        self.adaptor_signatures.get(&tx)
+    }
+
+    /// Drop the signatures invalidated by the plan.
+    pub fn invalidate(&mut self, plan: &AbortPlan) {
+        for tx in plan.invalidated.iter() {
+            self.signatures.remove(tx);
+            self.adaptor_signatures.remove(tx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Error;
+    use crate::Uuid;
+
+    #[test]
+    fn abort_before_accordant_lock() {
+        let secp = Secp256k1::new();
+        let alice_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let alice_pubkey = PublicKey::from_secret_key(&secp, &alice_key);
+        let swap_id = SwapId(Uuid::new());
+        let message = Abort {
+            swap_id,
+            error_body: Some("price moved".to_string()),
+        };
+        let events = vec![SwapEvent::TxSeen(TxLabel::Funding)];
+
+        let (signed, plan) =
+            abort(&secp, message.clone(), &alice_key, SwapRole::Alice, &events).unwrap();
+        assert_eq!(plan.invalidated, vec![TxLabel::Buy]);
+        assert_eq!(plan.refund, RefundPlan::Nothing);
+
+        // Bob sweeps his funding and drops Alice's signatures once the sweep is confirmed
+        let mut plan = handle_abort(
+            &secp,
+            &signed,
+            &alice_pubkey,
+            swap_id,
+            SwapRole::Bob,
+            &events,
+        )
+        .unwrap();
+        assert_eq!(plan.refund, RefundPlan::SweepFunding);
+        let mut store = CounterpartySignatures::<u8, u8>::new();
+        store.insert_signature(TxLabel::Cancel, 1);
+        store.insert_adaptor_signature(TxLabel::Refund, 2);
+        // The lock may still be mined, the signatures are kept until the sweep is confirmed
+        store.invalidate(&plan);
+        assert_eq!(store.signature(TxLabel::Cancel), Some(&1));
+        assert_eq!(store.adaptor_signature(TxLabel::Refund), Some(&2));
+        plan.sweep_confirmed();
+        store.invalidate(&plan);
+        assert_eq!(store, CounterpartySignatures::new());
+
+        // Nothing has been sent on-chain, the signatures are dropped right away
+        let plan = AbortPlan::new(swap_id, SwapRole::Bob, &[]).unwrap();
+        assert_eq!(plan.invalidated, vec![TxLabel::Cancel, TxLabel::Refund]);
+        assert_eq!(plan.refund, RefundPlan::Nothing);
+
+        // Once locked Bob keeps them to refund
+        let locked = vec![
+            SwapEvent::TxSeen(TxLabel::Funding),
+            SwapEvent::TxConfirmed {
+                tx: TxLabel::Lock,
+                height: 100,
+            },
+        ];
+        let plan = AbortPlan::new(swap_id, SwapRole::Bob, &locked).unwrap();
+        assert!(plan.invalidated.is_empty());
+        assert_eq!(plan.refund, RefundPlan::CancelAndRefund);
+
+        // Rejected for another swap, another signer, or after the accordant lock
+        assert!(matches!(
+            handle_abort(
+                &secp,
+                &signed,
+                &alice_pubkey,
+                SwapId(Uuid::new()),
+                SwapRole::Bob,
+                &events
+            ),
+            Err(Error::Swap(swap::Error::SwapIdMismatch { .. }))
+        ));
+        let other = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[2; 32]).unwrap());
+        assert!(matches!(
+            handle_abort(&secp, &signed, &other, swap_id, SwapRole::Bob, &events),
+            Err(Error::Crypto(_))
+        ));
+        let acc_locked = vec![SwapEvent::TxSeen(TxLabel::AccLock)];
+        assert!(matches!(
+            abort(&secp, message, &alice_key, SwapRole::Alice, &acc_locked),
+            Err(Error::Swap(swap::Error::AbortAfterAccordantLock))
+        ));
+    }
+}
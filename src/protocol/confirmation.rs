@@ -0,0 +1,261 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Confirmation depths required before acting on each transaction of a swap. A
+//! [`ConfirmationPolicy`] is configured per blockchain and per transaction, from the
+//! [`conservative`](ConfirmationPolicy::conservative) or [`economic`](ConfirmationPolicy::economic)
+//! presets, and must be validated against the negotiated timelocks: waiting too long for the lock
+//! transactions leaves no time to complete the swap before the cancel timelock expires.
+
+use thiserror::Error;
+
+use crate::blockchain::{Blockchain, Timelock};
+use crate::protocol::race::ReorgPolicy;
+use crate::transaction::TxLabel;
+
+/// Violation of the compatibility between a [`ConfirmationPolicy`] and the swap timelocks,
+/// returned by [`ConfirmationPolicy::validate`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConfirmationViolation {
+    /// The transaction does not require any confirmation.
+    #[error("The {0} transaction must require at least one confirmation")]
+    ZeroDepth(TxLabel),
+    /// Waiting for both lock transactions and mining the buy transaction takes `required`
+    /// arbitrating blocks, the cancel timelock expires before.
+    #[error("Locking requires {required} blocks, cancel timelock {cancel_timelock} is too short")]
+    CancelTimelockTooShort { required: u32, cancel_timelock: u32 },
+    /// Waiting for the cancel transaction and mining the refund transaction takes `required`
+    /// blocks, the punish timelock expires before.
+    #[error(
+        "Refunding requires {required} blocks, punish timelock {punish_timelock} is too short"
+    )]
+    PunishTimelockTooShort { required: u32, punish_timelock: u32 },
+}
+
+/// Number of confirmations required on each transaction of a swap before acting on it, e.g.
+/// before Alice locks the accordant assets on top of the arbitrating lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ConfirmationPolicy {
+    /// The arbitrating blockchain of the swap.
+    pub arbitrating: Blockchain,
+    /// The accordant blockchain of the swap.
+    pub accordant: Blockchain,
+    /// Depth of the funding transaction before signing the lock.
+    pub funding: u32,
+    /// Depth of the arbitrating lock transaction before locking the accordant assets.
+    pub lock: u32,
+    /// Depth of the accordant lock transaction before revealing the buy signature.
+    pub accordant_lock: u32,
+    /// Depth of the buy transaction before considering the swap complete.
+    pub buy: u32,
+    /// Depth of the cancel transaction before broadcasting the refund.
+    pub cancel: u32,
+    /// Depth of the refund transaction before considering the swap refunded.
+    pub refund: u32,
+    /// Depth of the punish transaction before considering the swap punished.
+    pub punish: u32,
+}
+
+impl ConfirmationPolicy {
+    /// A policy waiting for the safety depth of each blockchain on every transaction, see
+    /// [`ReorgPolicy::default_depth`].
+    pub fn conservative(arbitrating: Blockchain, accordant: Blockchain) -> Self {
+        let depth = ReorgPolicy::default_depth(arbitrating);
+        Self {
+            arbitrating,
+            accordant,
+            funding: depth,
+            lock: depth,
+            accordant_lock: ReorgPolicy::default_depth(accordant),
+            buy: depth,
+            cancel: depth,
+            refund: depth,
+            punish: depth,
+        }
+    }
+
+    /// A policy only waiting for the safety depth on the lock transactions, the ones the other
+    /// participant relies on to move assets, and for a single confirmation otherwise.
+    pub fn economic(arbitrating: Blockchain, accordant: Blockchain) -> Self {
+        Self {
+            funding: 1,
+            buy: 1,
+            cancel: 1,
+            refund: 1,
+            punish: 1,
+            ..Self::conservative(arbitrating, accordant)
+        }
+    }
+
+    /// The average time between two blocks of a blockchain, in seconds.
+    pub fn block_interval(blockchain: Blockchain) -> u32 {
+        match blockchain {
+            Blockchain::Bitcoin => 600,
+            Blockchain::Monero => 120,
+        }
+    }
+
+    /// The depth required on a transaction.
+    pub fn depth(&self, label: TxLabel) -> u32 {
+        match label {
+            TxLabel::Funding => self.funding,
+            TxLabel::Lock => self.lock,
+            TxLabel::AccLock => self.accordant_lock,
+            TxLabel::Buy => self.buy,
+            TxLabel::Cancel => self.cancel,
+            TxLabel::Refund => self.refund,
+            TxLabel::Punish => self.punish,
+        }
+    }
+
+    /// Set the depth required on a transaction.
+    pub fn with_depth(mut self, label: TxLabel, depth: u32) -> Self {
+        match label {
+            TxLabel::Funding => self.funding = depth,
+            TxLabel::Lock => self.lock = depth,
+            TxLabel::AccLock => self.accordant_lock = depth,
+            TxLabel::Buy => self.buy = depth,
+            TxLabel::Cancel => self.cancel = depth,
+            TxLabel::Refund => self.refund = depth,
+            TxLabel::Punish => self.punish = depth,
+        }
+        self
+    }
+
+    /// The reorganization policy following the depths of the lock transactions.
+    pub fn reorg_policy(&self) -> ReorgPolicy {
+        ReorgPolicy {
+            arbitrating_depth: self.lock,
+            accordant_depth: self.accordant_lock,
+        }
+    }
+
+    /// Validate the depths against the timelocks, expressed in arbitrating blocks. The lock
+    /// transactions must be final and the buy transaction mined before the cancel timelock
+    /// expires, and the cancel transaction final and the refund transaction mined before the punish
+    /// timelock expires. Returns all the violations found, not only the first one.
+    pub fn validate<Ti>(
+        &self,
+        cancel_timelock: Ti,
+        punish_timelock: Ti,
+    ) -> Result<(), Vec<ConfirmationViolation>>
+    where
+        Ti: Timelock,
+    {
+        let cancel_timelock = cancel_timelock.as_blocks();
+        let punish_timelock = punish_timelock.as_blocks();
+        let mut violations = vec![];
+
+        for label in [
+            TxLabel::Funding,
+            TxLabel::Lock,
+            TxLabel::AccLock,
+            TxLabel::Buy,
+            TxLabel::Cancel,
+            TxLabel::Refund,
+            TxLabel::Punish,
+        ] {
+            if self.depth(label) == 0 {
+                violations.push(ConfirmationViolation::ZeroDepth(label));
+            }
+        }
+
+        // The buy transaction must be mined, i.e. one block, before cancel becomes valid
+        let required = self
+            .lock
+            .saturating_add(self.accordant_lock_blocks())
+            .saturating_add(1);
+        if required >= cancel_timelock {
+            violations.push(ConfirmationViolation::CancelTimelockTooShort {
+                required,
+                cancel_timelock,
+            });
+        }
+        let required = self.cancel.saturating_add(1);
+        if required >= punish_timelock {
+            violations.push(ConfirmationViolation::PunishTimelockTooShort {
+                required,
+                punish_timelock,
+            });
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// The accordant lock depth converted in arbitrating blocks, rounded up.
+    pub fn accordant_lock_blocks(&self) -> u32 {
+        let seconds = self
+            .accordant_lock
+            .saturating_mul(Self::block_interval(self.accordant));
+        let interval = Self::block_interval(self.arbitrating);
+        seconds / interval + (seconds % interval != 0) as u32
+    }
+}
+
+impl Default for ConfirmationPolicy {
+    fn default() -> Self {
+        Self::conservative(Blockchain::Bitcoin, Blockchain::Monero)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bitcoin::timelock::CSVTimelock;
+
+    #[test]
+    fn presets_and_timelocks() {
+        let conservative = ConfirmationPolicy::default();
+        assert_eq!(conservative.depth(TxLabel::Lock), 6);
+        assert_eq!(conservative.depth(TxLabel::AccLock), 10);
+        assert_eq!(conservative.reorg_policy(), ReorgPolicy::default());
+        // 6 blocks for the lock, 10 Monero blocks take 2 Bitcoin blocks, 1 block for the buy
+        assert!(conservative
+            .validate(CSVTimelock::new(10), CSVTimelock::new(8))
+            .is_ok());
+        assert_eq!(
+            conservative.validate(CSVTimelock::new(9), CSVTimelock::new(7)),
+            Err(vec![
+                ConfirmationViolation::CancelTimelockTooShort {
+                    required: 9,
+                    cancel_timelock: 9,
+                },
+                ConfirmationViolation::PunishTimelockTooShort {
+                    required: 7,
+                    punish_timelock: 7,
+                },
+            ])
+        );
+
+        let economic = ConfirmationPolicy::economic(Blockchain::Bitcoin, Blockchain::Monero);
+        assert_eq!(economic.depth(TxLabel::Lock), 6);
+        assert_eq!(economic.depth(TxLabel::Cancel), 1);
+        assert!(economic
+            .validate(CSVTimelock::new(10), CSVTimelock::new(3))
+            .is_ok());
+
+        let policy = economic.with_depth(TxLabel::Funding, 0);
+        assert_eq!(
+            policy.validate(CSVTimelock::new(10), CSVTimelock::new(3)),
+            Err(vec![ConfirmationViolation::ZeroDepth(TxLabel::Funding)])
+        );
+    }
+}
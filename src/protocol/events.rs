@@ -0,0 +1,257 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Auditable log of the lifecycle of a swap. The [`RaceDetector`](crate::protocol::race) emits a
+//! [`SwapEvent`] for each transition of the swap transactions, the participant can add its own
+//! events (e.g. when a secret is recovered) and store them, timestamped, in an [`EventLog`].
+//!
+//! The log is append-only: each [`LoggedEvent`] is serialized as an independent record, so new
+//! records can be appended at the end of a file without rewriting it, and the full log is the
+//! concatenation of the records:
+//!
+//! ```text
+//! timestamp (u64, unix seconds) || event
+//! ```
+
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::consensus::{self, Decodable, Encodable};
+use crate::protocol::race::RecommendedAction;
+use crate::transaction::TxLabel;
+
+/// A transition in the lifecycle of a swap. Transactions are referenced by their label, e.g.
+/// `TxSeen(TxLabel::Funding)` when the funding is seen or `TxSeen(TxLabel::Lock)` when the lock
+/// is broadcast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum SwapEvent {
+    /// The transaction has been seen for the first time, in the mempool or in a block.
+    TxSeen(TxLabel),
+    /// The transaction has been mined at the given height.
+    TxConfirmed { tx: TxLabel, height: u32 },
+    /// The transaction reached the safety depth of the reorg policy.
+    TxFinal { tx: TxLabel, depth: u32 },
+    /// The transaction lost confirmations after a chain reorganization.
+    TxReorged { tx: TxLabel, new_depth: u32 },
+    /// The cancel timelock expired at the given height, `cancel` can be broadcast.
+    CancelBroadcastable { height: u32 },
+    /// The punish timelock expired at the given height, `punish` can be broadcast.
+    PunishBroadcastable { height: u32 },
+    /// An action has been recommended to the participant.
+    ActionRecommended(RecommendedAction),
+    /// The counter-party secret has been recovered from the witness of the transaction.
+    SecretRecovered(TxLabel),
+}
+
+impl Encodable for RecommendedAction {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        match self {
+            Self::BroadcastCancelNow => 0x01u8.consensus_encode(writer),
+            Self::ExtractSecretFromBuy => 0x02u8.consensus_encode(writer),
+            Self::BroadcastRefund => 0x03u8.consensus_encode(writer),
+            Self::ExtractSecretFromRefund => 0x04u8.consensus_encode(writer),
+            Self::BroadcastPunish => 0x05u8.consensus_encode(writer),
+        }
+    }
+}
+
+impl Decodable for RecommendedAction {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match u8::consensus_decode(d)? {
+            0x01u8 => Ok(Self::BroadcastCancelNow),
+            0x02u8 => Ok(Self::ExtractSecretFromBuy),
+            0x03u8 => Ok(Self::BroadcastRefund),
+            0x04u8 => Ok(Self::ExtractSecretFromRefund),
+            0x05u8 => Ok(Self::BroadcastPunish),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl Encodable for SwapEvent {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        match self {
+            Self::TxSeen(tx) => Ok(0x01u8.consensus_encode(writer)? + tx.consensus_encode(writer)?),
+            Self::TxConfirmed { tx, height } => Ok(0x02u8.consensus_encode(writer)?
+                + tx.consensus_encode(writer)?
+                + height.consensus_encode(writer)?),
+            Self::TxFinal { tx, depth } => Ok(0x03u8.consensus_encode(writer)?
+                + tx.consensus_encode(writer)?
+                + depth.consensus_encode(writer)?),
+            Self::TxReorged { tx, new_depth } => Ok(0x04u8.consensus_encode(writer)?
+                + tx.consensus_encode(writer)?
+                + new_depth.consensus_encode(writer)?),
+            Self::CancelBroadcastable { height } => {
+                Ok(0x05u8.consensus_encode(writer)? + height.consensus_encode(writer)?)
+            }
+            Self::PunishBroadcastable { height } => {
+                Ok(0x06u8.consensus_encode(writer)? + height.consensus_encode(writer)?)
+            }
+            Self::ActionRecommended(action) => {
+                Ok(0x07u8.consensus_encode(writer)? + action.consensus_encode(writer)?)
+            }
+            Self::SecretRecovered(tx) => {
+                Ok(0x08u8.consensus_encode(writer)? + tx.consensus_encode(writer)?)
+            }
+        }
+    }
+}
+
+impl Decodable for SwapEvent {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match u8::consensus_decode(d)? {
+            0x01u8 => Ok(Self::TxSeen(Decodable::consensus_decode(d)?)),
+            0x02u8 => Ok(Self::TxConfirmed {
+                tx: Decodable::consensus_decode(d)?,
+                height: Decodable::consensus_decode(d)?,
+            }),
+            0x03u8 => Ok(Self::TxFinal {
+                tx: Decodable::consensus_decode(d)?,
+                depth: Decodable::consensus_decode(d)?,
+            }),
+            0x04u8 => Ok(Self::TxReorged {
+                tx: Decodable::consensus_decode(d)?,
+                new_depth: Decodable::consensus_decode(d)?,
+            }),
+            0x05u8 => Ok(Self::CancelBroadcastable {
+                height: Decodable::consensus_decode(d)?,
+            }),
+            0x06u8 => Ok(Self::PunishBroadcastable {
+                height: Decodable::consensus_decode(d)?,
+            }),
+            0x07u8 => Ok(Self::ActionRecommended(Decodable::consensus_decode(d)?)),
+            0x08u8 => Ok(Self::SecretRecovered(Decodable::consensus_decode(d)?)),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl_strict_encoding!(SwapEvent);
+
+/// A [`SwapEvent`] with the time at which it has been recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LoggedEvent {
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    /// The recorded event.
+    pub event: SwapEvent,
+}
+
+impl Encodable for LoggedEvent {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        Ok(self.timestamp.consensus_encode(writer)? + self.event.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for LoggedEvent {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            timestamp: Decodable::consensus_decode(d)?,
+            event: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(LoggedEvent);
+
+/// Append-only log of the events of a swap, ordered by recording time.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventLog {
+    entries: Vec<LoggedEvent>,
+}
+
+impl EventLog {
+    /// Create an empty log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an event at the given timestamp, in seconds since the unix epoch, and return the
+    /// logged entry.
+    pub fn record(&mut self, timestamp: u64, event: SwapEvent) -> LoggedEvent {
+        let entry = LoggedEvent { timestamp, event };
+        self.entries.push(entry);
+        entry
+    }
+
+    /// Record an event at the current system time.
+    pub fn record_now(&mut self, event: SwapEvent) -> LoggedEvent {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.record(timestamp, event)
+    }
+
+    /// The recorded entries, in recording order.
+    pub fn entries(&self) -> &[LoggedEvent] {
+        &self.entries
+    }
+
+    /// Append the serialized entry at the end of a log, e.g. a file opened in append mode.
+    pub fn append_to<W: io::Write>(
+        entry: &LoggedEvent,
+        writer: &mut W,
+    ) -> Result<usize, io::Error> {
+        entry.consensus_encode(writer)
+    }
+
+    /// Serialize all the entries of the log.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries.iter().flat_map(consensus::serialize).collect()
+    }
+
+    /// Parse a log made of a concatenation of serialized entries.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let mut reader = io::Cursor::new(bytes);
+        let mut entries = vec![];
+        while (reader.position() as usize) < bytes.len() {
+            entries.push(LoggedEvent::consensus_decode(&mut reader)?);
+        }
+        Ok(Self { entries })
+    }
+}
+
+impl Extend<LoggedEvent> for EventLog {
+    fn extend<I: IntoIterator<Item = LoggedEvent>>(&mut self, iter: I) {
+        self.entries.extend(iter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_and_parse_log() {
+        let mut log = EventLog::new();
+        log.record(1_650_000_000, SwapEvent::TxSeen(TxLabel::Funding));
+        log.record(
+            1_650_000_600,
+            SwapEvent::TxFinal {
+                tx: TxLabel::Lock,
+                depth: 6,
+            },
+        );
+        let mut bytes = log.to_bytes();
+
+        let entry = log.record_now(SwapEvent::SecretRecovered(TxLabel::Buy));
+        EventLog::append_to(&entry, &mut bytes).unwrap();
+
+        assert_eq!(EventLog::from_bytes(&bytes).unwrap(), log);
+        assert!(EventLog::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
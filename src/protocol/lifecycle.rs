@@ -0,0 +1,259 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Retention of stored swaps. Given the [`EventLog`] of a swap and the arbitrating chain height,
+//! [`SwapLifecycle`] classifies the swap with a [`Liveness`] and lists the [`CleanupAction`]s to
+//! execute, in order, before forgetting it, so daemons implement their retention policies on
+//! top of the protocol rules:
+//!
+//! - a swap is [`Liveness::Dead`] once its final transaction, the buy, the refund or the punish,
+//!   is at the safety depth of the [`ReorgPolicy`],
+//! - a swap is [`Liveness::Expirable`] if its lock transaction was never seen and the setup
+//!   deadline passed, no funds are locked and the swap can be abandoned,
+//! - any other swap is [`Liveness::Active`] and must be kept.
+
+use std::collections::HashMap;
+
+use crate::protocol::events::{EventLog, SwapEvent};
+use crate::protocol::race::ReorgPolicy;
+use crate::role::SwapRole;
+use crate::transaction::TxLabel;
+
+/// Retention class of a swap, returned by [`SwapLifecycle::liveness`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum Liveness {
+    /// The swap is running or funds may still move, it must be kept.
+    Active,
+    /// The swap never locked funds and its setup deadline passed, it can be abandoned.
+    Expirable,
+    /// The final transaction of the swap is final, nothing is left to watch.
+    Dead,
+}
+
+/// An action to execute before a swap is forgotten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum CleanupAction {
+    /// Sweep the funding output back to the wallet, the lock transaction was never broadcast.
+    SweepFunding,
+    /// Sweep the accordant lock with the counter-party key recovered from the witness of the
+    /// transaction.
+    SweepAccordantLock(TxLabel),
+    /// Delete the checkpoints of the swap, the keys and transactions of the swap are lost.
+    DeleteCheckpoints,
+}
+
+// State of a transaction replayed from the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TxState {
+    Seen,
+    Confirmed(u32),
+    Final,
+}
+
+/// Classifies stored swaps for retention, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SwapLifecycle {
+    role: SwapRole,
+    reorg_policy: ReorgPolicy,
+    setup_deadline: Option<u32>,
+}
+
+impl SwapLifecycle {
+    /// Create the lifecycle of a swap for the participant playing `role`, the swap never
+    /// expires if no setup deadline is set.
+    pub fn new(role: SwapRole) -> Self {
+        Self {
+            role,
+            reorg_policy: ReorgPolicy::default(),
+            setup_deadline: None,
+        }
+    }
+
+    /// Set the safety depths after which the final transaction is considered final.
+    pub fn with_reorg_policy(mut self, policy: ReorgPolicy) -> Self {
+        self.reorg_policy = policy;
+        self
+    }
+
+    /// Set the arbitrating height from which a swap whose lock transaction was never seen can
+    /// be abandoned, e.g. the expiry of the deal.
+    pub fn with_setup_deadline(mut self, height: u32) -> Self {
+        self.setup_deadline = Some(height);
+        self
+    }
+
+    /// Classify the swap given its log and the arbitrating chain `height`.
+    pub fn liveness(&self, log: &EventLog, height: u32) -> Liveness {
+        let states = replay(log);
+        if self.final_tx(&states, height).is_some() {
+            return Liveness::Dead;
+        }
+        let expired = self
+            .setup_deadline
+            .map_or(false, |deadline| height >= deadline);
+        if expired && !states.contains_key(&TxLabel::Lock) {
+            Liveness::Expirable
+        } else {
+            Liveness::Active
+        }
+    }
+
+    /// Return the actions to execute, in order, before forgetting the swap. Active swaps have no
+    /// cleanup action. The accordant lock must be swept by Bob after a buy and by Alice after a
+    /// refund before the checkpoints are deleted.
+    pub fn cleanup_actions(&self, log: &EventLog, height: u32) -> Vec<CleanupAction> {
+        let states = replay(log);
+        let mut actions = vec![];
+        match self.liveness(log, height) {
+            Liveness::Active => return actions,
+            Liveness::Expirable => {
+                if self.role == SwapRole::Bob && states.contains_key(&TxLabel::Funding) {
+                    actions.push(CleanupAction::SweepFunding);
+                }
+            }
+            Liveness::Dead => {
+                let label = self.final_tx(&states, height).expect("final transaction");
+                let sweeps = matches!(
+                    (label, self.role),
+                    (TxLabel::Buy, SwapRole::Bob) | (TxLabel::Refund, SwapRole::Alice)
+                );
+                if sweeps && states.contains_key(&TxLabel::AccLock) {
+                    actions.push(CleanupAction::SweepAccordantLock(label));
+                }
+            }
+        }
+        actions.push(CleanupAction::DeleteCheckpoints);
+        actions
+    }
+
+    // Return the final transaction at safety depth, if any.
+    fn final_tx(&self, states: &HashMap<TxLabel, TxState>, height: u32) -> Option<TxLabel> {
+        [TxLabel::Buy, TxLabel::Refund, TxLabel::Punish]
+            .into_iter()
+            .find(|label| match states.get(label) {
+                Some(TxState::Final) => true,
+                Some(TxState::Confirmed(confirmed_at)) => {
+                    height >= *confirmed_at
+                        && height - confirmed_at + 1 >= self.reorg_policy.safety_depth(*label)
+                }
+                _ => false,
+            })
+    }
+}
+
+// Replay the transaction events of the log, reorged transactions go back to the mempool.
+fn replay(log: &EventLog) -> HashMap<TxLabel, TxState> {
+    let mut states = HashMap::new();
+    for entry in log.entries() {
+        match entry.event {
+            SwapEvent::TxSeen(tx) => {
+                states.entry(tx).or_insert(TxState::Seen);
+            }
+            SwapEvent::TxConfirmed { tx, height } => {
+                states.insert(tx, TxState::Confirmed(height));
+            }
+            SwapEvent::TxFinal { tx, .. } => {
+                states.insert(tx, TxState::Final);
+            }
+            SwapEvent::TxReorged { tx, new_depth: 0 } => {
+                states.insert(tx, TxState::Seen);
+            }
+            _ => (),
+        }
+    }
+    states
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_stale_swaps() {
+        let bob = SwapLifecycle::new(SwapRole::Bob).with_setup_deadline(100);
+        let alice = SwapLifecycle::new(SwapRole::Alice).with_setup_deadline(100);
+        let mut log = EventLog::new();
+        log.record(0, SwapEvent::TxSeen(TxLabel::Funding));
+        assert_eq!(bob.liveness(&log, 99), Liveness::Active);
+        assert!(bob.cleanup_actions(&log, 99).is_empty());
+        assert_eq!(bob.liveness(&log, 100), Liveness::Expirable);
+        assert_eq!(
+            bob.cleanup_actions(&log, 100),
+            vec![
+                CleanupAction::SweepFunding,
+                CleanupAction::DeleteCheckpoints
+            ]
+        );
+        assert_eq!(
+            alice.cleanup_actions(&log, 100),
+            vec![CleanupAction::DeleteCheckpoints]
+        );
+
+        // Locked funds never expire
+        log.record(1, SwapEvent::TxSeen(TxLabel::Lock));
+        log.record(
+            2,
+            SwapEvent::TxConfirmed {
+                tx: TxLabel::Lock,
+                height: 90,
+            },
+        );
+        log.record(3, SwapEvent::TxSeen(TxLabel::AccLock));
+        assert_eq!(bob.liveness(&log, 1_000), Liveness::Active);
+
+        // The buy is dead at safety depth, Bob must sweep the accordant lock first
+        log.record(
+            4,
+            SwapEvent::TxConfirmed {
+                tx: TxLabel::Buy,
+                height: 95,
+            },
+        );
+        assert_eq!(bob.liveness(&log, 99), Liveness::Active);
+        assert_eq!(bob.liveness(&log, 100), Liveness::Dead);
+        assert_eq!(
+            bob.cleanup_actions(&log, 100),
+            vec![
+                CleanupAction::SweepAccordantLock(TxLabel::Buy),
+                CleanupAction::DeleteCheckpoints
+            ]
+        );
+        assert_eq!(
+            alice.cleanup_actions(&log, 100),
+            vec![CleanupAction::DeleteCheckpoints]
+        );
+
+        // A reorged buy brings the swap back to life
+        log.record(
+            5,
+            SwapEvent::TxReorged {
+                tx: TxLabel::Buy,
+                new_depth: 0,
+            },
+        );
+        assert_eq!(bob.liveness(&log, 100), Liveness::Active);
+        log.record(
+            6,
+            SwapEvent::TxFinal {
+                tx: TxLabel::Buy,
+                depth: 6,
+            },
+        );
+        assert_eq!(bob.liveness(&log, 100), Liveness::Dead);
+    }
+}
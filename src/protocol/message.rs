@@ -22,16 +22,97 @@ use std::io;
 
 use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
 use crate::crypto::{Commit, SharedKeyId, TaggedElement};
+use crate::primitives;
 use crate::protocol::Parameters;
 use crate::protocol::{verify_vec_of_commitments, CoreArbitratingTransactions};
-use crate::swap::SwapId;
+use crate::swap::{self, SwapId};
+use crate::transaction::TxLabel;
 use crate::Error;
 
+/// A protocol message belonging to a swap. Used by daemons running multiple swaps to route the
+/// messages to the correct swap and to detect messages replayed from another swap.
+pub trait SwapMessage {
+    /// The identifier of the swap the message belongs to.
+    fn swap_id(&self) -> SwapId;
+
+    /// Check that the message belongs to the swap `swap_id`, return
+    /// [`swap::Error::SwapIdMismatch`] otherwise.
+    fn check_swap_id(&self, swap_id: SwapId) -> Result<(), swap::Error> {
+        if self.swap_id() == swap_id {
+            Ok(())
+        } else {
+            Err(swap::Error::SwapIdMismatch {
+                expected: swap_id,
+                found: self.swap_id(),
+            })
+        }
+    }
+}
+
+/// Version of the protocol messages encoding, prefixed to messages wrapped in [`Versioned`].
+pub const MESSAGE_ENCODING_VERSION: u16 = primitives::encoding::MESSAGE_ENCODING_VERSION;
+
+/// A protocol message prefixed with the [`MESSAGE_ENCODING_VERSION`] when encoded, so peers
+/// running another version of the encoding reject the message instead of misinterpreting it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Versioned<T>(pub T);
+
+impl<T> Versioned<T> {
+    /// Returns the wrapped message.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Encodable for Versioned<T>
+where
+    T: Encodable,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let len = MESSAGE_ENCODING_VERSION.consensus_encode(s)?;
+        Ok(len + self.0.consensus_encode(s)?)
+    }
+}
+
+impl<T> Decodable for Versioned<T>
+where
+    T: Decodable,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match u16::consensus_decode(d)? {
+            MESSAGE_ENCODING_VERSION => Ok(Self(Decodable::consensus_decode(d)?)),
+            _ => Err(consensus::Error::ParseFailed("Unsupported message version")),
+        }
+    }
+}
+
+impl_strict_encoding!(Versioned<T>, T: Encodable + Decodable);
+
+impl<T> SwapMessage for Versioned<T>
+where
+    T: SwapMessage,
+{
+    fn swap_id(&self) -> SwapId {
+        self.0.swap_id()
+    }
+}
+
+macro_rules! impl_swap_message {
+    ($type:ident $(<$($generic:ident),*>)?) => {
+        impl$(<$($generic),*>)? SwapMessage for $type$(<$($generic),*>)? {
+            fn swap_id(&self) -> SwapId {
+                self.swap_id
+            }
+        }
+    };
+}
+
 /// Forces Alice to commit to the result of her cryptographic setup before receiving Bob's setup.
 /// This is done to remove adaptive behavior in the cryptographic parameters.
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CommitAliceParameters<C> {
-    /// The swap identifier related to this message.
+    /// The identifier of the deal, the swap identifier is derived from both commit messages, see
+    /// [`SwapId::derive`].
     pub swap_id: SwapId,
     /// Commitment to the buy public key.
     pub buy: C,
@@ -108,13 +189,21 @@ where
     }
 }
 
-impl<C> Encodable for CommitAliceParameters<C>
+impl<C> CommitAliceParameters<C>
 where
     C: CanonicalBytes,
 {
-    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
-        let mut len = self.swap_id.consensus_encode(s)?;
-        len += self.buy.as_canonical_bytes().consensus_encode(s)?;
+    /// The encoded commitments of the message, without the swap identifier, used to derive the
+    /// swap identifier, see [`SwapId::derive`].
+    pub fn commitments(&self) -> Vec<u8> {
+        let mut writer = vec![];
+        self.encode_commitments(&mut writer)
+            .expect("Encoding in vec never fails");
+        writer
+    }
+
+    fn encode_commitments<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.buy.as_canonical_bytes().consensus_encode(s)?;
         len += self.cancel.as_canonical_bytes().consensus_encode(s)?;
         len += self.refund.as_canonical_bytes().consensus_encode(s)?;
         len += self.punish.as_canonical_bytes().consensus_encode(s)?;
@@ -127,6 +216,16 @@ where
     }
 }
 
+impl<C> Encodable for CommitAliceParameters<C>
+where
+    C: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let len = self.swap_id.consensus_encode(s)?;
+        Ok(len + self.encode_commitments(s)?)
+    }
+}
+
 impl<C> Decodable for CommitAliceParameters<C>
 where
     C: CanonicalBytes,
@@ -150,11 +249,14 @@ where
 
 impl_strict_encoding!(CommitAliceParameters<C>, C: CanonicalBytes);
 
+impl_swap_message!(CommitAliceParameters<C>);
+
 /// Forces Bob to commit to the result of his cryptographic setup before receiving Alice's setup.
 /// This is done to remove adaptive behavior in the cryptographic parameters.
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CommitBobParameters<C> {
-    /// The swap identifier related to this message.
+    /// The identifier of the deal, the swap identifier is derived from both commit messages, see
+    /// [`SwapId::derive`].
     pub swap_id: SwapId,
     /// Commitment to the buy public key.
     pub buy: C,
@@ -228,13 +330,21 @@ where
     }
 }
 
-impl<C> Encodable for CommitBobParameters<C>
+impl<C> CommitBobParameters<C>
 where
     C: CanonicalBytes,
 {
-    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
-        let mut len = self.swap_id.consensus_encode(s)?;
-        len += self.buy.as_canonical_bytes().consensus_encode(s)?;
+    /// The encoded commitments of the message, without the swap identifier, used to derive the
+    /// swap identifier, see [`SwapId::derive`].
+    pub fn commitments(&self) -> Vec<u8> {
+        let mut writer = vec![];
+        self.encode_commitments(&mut writer)
+            .expect("Encoding in vec never fails");
+        writer
+    }
+
+    fn encode_commitments<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.buy.as_canonical_bytes().consensus_encode(s)?;
         len += self.cancel.as_canonical_bytes().consensus_encode(s)?;
         len += self.refund.as_canonical_bytes().consensus_encode(s)?;
         len += self.adaptor.as_canonical_bytes().consensus_encode(s)?;
@@ -246,6 +356,16 @@ where
     }
 }
 
+impl<C> Encodable for CommitBobParameters<C>
+where
+    C: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let len = self.swap_id.consensus_encode(s)?;
+        Ok(len + self.encode_commitments(s)?)
+    }
+}
+
 impl<C> Decodable for CommitBobParameters<C>
 where
     C: CanonicalBytes,
@@ -268,6 +388,8 @@ where
 
 impl_strict_encoding!(CommitBobParameters<C>, C: CanonicalBytes);
 
+impl_swap_message!(CommitBobParameters<C>);
+
 /// Reveals the parameters commited by the [`CommitAliceParameters`] protocol message and the
 /// zero-knowledge proof for the discrete logarithm across curves.
 ///
@@ -399,6 +521,8 @@ where
 
 impl_strict_encoding!(RevealAliceParameters<Pk, Qk, Rk, Sk, Addr, Pr>, Pk: CanonicalBytes, Qk: CanonicalBytes, Rk: CanonicalBytes, Sk: CanonicalBytes, Addr: CanonicalBytes, Pr: CanonicalBytes);
 
+impl_swap_message!(RevealAliceParameters<Pk, Qk, Rk, Sk, Addr, Pr>);
+
 /// Reveals the parameters commited by the [`CommitBobParameters`] protocol message and the
 /// zero-knowledge proof for the discrete logarithm across curves.
 ///
@@ -526,6 +650,8 @@ where
 
 impl_strict_encoding!(RevealBobParameters<Pk, Qk, Rk, Sk, Addr, Pr>, Pk: CanonicalBytes, Qk: CanonicalBytes, Rk: CanonicalBytes, Sk: CanonicalBytes, Addr: CanonicalBytes, Pr: CanonicalBytes);
 
+impl_swap_message!(RevealBobParameters<Pk, Qk, Rk, Sk, Addr, Pr>);
+
 /// Sends the [`Lockable`], [`Cancelable`] and [`Refundable`] arbritrating transactions from
 /// [`SwapRole::Bob`] to [`SwapRole::Alice`], as well as Bob's signature for the [`Cancelable`]
 /// transaction.
@@ -603,6 +729,8 @@ where
 
 impl_strict_encoding!(CoreArbitratingSetup<Px, Sig>, Px: CanonicalBytes, Sig: CanonicalBytes);
 
+impl_swap_message!(CoreArbitratingSetup<Px, Sig>);
+
 /// Protocol message is intended to transmit [`SwapRole::Alice`]'s signature for the [`Cancelable`]
 /// transaction and Alice's adaptor signature for the [`Refundable`] transaction. Uppon reception
 /// [`SwapRole::Bob`] must validate the signatures.
@@ -663,6 +791,8 @@ where
 
 impl_strict_encoding!(RefundProcedureSignatures<Sig, EncSig>, Sig: CanonicalBytes, EncSig: CanonicalBytes);
 
+impl_swap_message!(RefundProcedureSignatures<Sig, EncSig>);
+
 /// Protocol message intended to transmit [`SwapRole::Bob`]'s adaptor signature for the [`Buyable`]
 /// transaction and the transaction itself. Uppon reception Alice must validate the transaction and
 /// the adaptor signature.
@@ -721,6 +851,225 @@ where
 
 impl_strict_encoding!(BuyProcedureSignature<Px, EncSig>, Px: consensus::CanonicalBytes, EncSig: consensus::CanonicalBytes);
 
+impl_swap_message!(BuyProcedureSignature<Px, EncSig>);
+
+/// Protocol message intended to transmit a participant's public nonce for a MuSig2 signing
+/// session over the key path of a Taproot output. Each participant must receive the nonces of all
+/// the others before creating its partial signature.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MuSigNonce<N> {
+    /// The swap identifier related to this message.
+    pub swap_id: SwapId,
+    /// The transaction the signing session is for.
+    pub tx_label: TxLabel,
+    /// The public nonce of the sender.
+    pub nonce: N,
+}
+
+impl<N> fmt::Display for MuSigNonce<N>
+where
+    N: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl<N> Encodable for MuSigNonce<N>
+where
+    N: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.swap_id.consensus_encode(s)?;
+        len += self.tx_label.consensus_encode(s)?;
+        Ok(len + self.nonce.as_canonical_bytes().consensus_encode(s)?)
+    }
+}
+
+impl<N> Decodable for MuSigNonce<N>
+where
+    N: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            swap_id: Decodable::consensus_decode(d)?,
+            tx_label: Decodable::consensus_decode(d)?,
+            nonce: N::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(MuSigNonce<N>, N: CanonicalBytes);
+
+impl_swap_message!(MuSigNonce<N>);
+
+/// Protocol message intended to transmit a participant's MuSig2 partial signature for the key
+/// path of a Taproot output. Uppon reception the partial signature must be verified before being
+/// aggregated into the final signature.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MuSigPartialSignature<Ps> {
+    /// The swap identifier related to this message.
+    pub swap_id: SwapId,
+    /// The transaction the partial signature is for.
+    pub tx_label: TxLabel,
+    /// The partial signature of the sender.
+    pub partial_sig: Ps,
+}
+
+impl<Ps> fmt::Display for MuSigPartialSignature<Ps>
+where
+    Ps: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl<Ps> Encodable for MuSigPartialSignature<Ps>
+where
+    Ps: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.swap_id.consensus_encode(s)?;
+        len += self.tx_label.consensus_encode(s)?;
+        Ok(len + self.partial_sig.as_canonical_bytes().consensus_encode(s)?)
+    }
+}
+
+impl<Ps> Decodable for MuSigPartialSignature<Ps>
+where
+    Ps: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            swap_id: Decodable::consensus_decode(d)?,
+            tx_label: Decodable::consensus_decode(d)?,
+            partial_sig: Ps::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(MuSigPartialSignature<Ps>, Ps: CanonicalBytes);
+
+impl_swap_message!(MuSigPartialSignature<Ps>);
+
+/// Optional protocol message from [`SwapRole::Bob`] proposing to unwind the swap cooperatively,
+/// without waiting for the cancel timelock. Carries the transaction spending the lock following
+/// the split agreed upon by both participants and Bob's signature for it. Upon reception Alice
+/// must validate the transaction against the agreed split and the signature.
+///
+/// [`SwapRole::Bob`]: crate::role::SwapRole::Bob
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollaborativeCancelProposal<Px, Sig> {
+    /// The swap identifier related to this message.
+    pub swap_id: SwapId,
+    /// The arbitrating transaction spending `lock (b)` with the agreed split.
+    pub collaborative_cancel: Px,
+    /// The `Bb` signature of the collaborative cancel.
+    pub collaborative_cancel_sig: Sig,
+}
+
+impl<Px, Sig> fmt::Display for CollaborativeCancelProposal<Px, Sig>
+where
+    Px: fmt::Debug,
+    Sig: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl<Px, Sig> Encodable for CollaborativeCancelProposal<Px, Sig>
+where
+    Px: CanonicalBytes,
+    Sig: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.swap_id.consensus_encode(s)?;
+        len += self
+            .collaborative_cancel
+            .as_canonical_bytes()
+            .consensus_encode(s)?;
+        Ok(len
+            + self
+                .collaborative_cancel_sig
+                .as_canonical_bytes()
+                .consensus_encode(s)?)
+    }
+}
+
+impl<Px, Sig> Decodable for CollaborativeCancelProposal<Px, Sig>
+where
+    Px: CanonicalBytes,
+    Sig: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            swap_id: Decodable::consensus_decode(d)?,
+            collaborative_cancel: Px::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            collaborative_cancel_sig: Sig::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(CollaborativeCancelProposal<Px, Sig>, Px: CanonicalBytes, Sig: CanonicalBytes);
+
+impl_swap_message!(CollaborativeCancelProposal<Px, Sig>);
+
+/// Protocol message from [`SwapRole::Alice`] accepting a [`CollaborativeCancelProposal`] with her
+/// adaptor signature of the collaborative cancel, encrypted with Bob's adaptor key. When Bob
+/// broadcasts the transaction Alice recovers his key share from the witness, as for the refund.
+///
+/// [`SwapRole::Alice`]: crate::role::SwapRole::Alice
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollaborativeCancelSignature<EncSig> {
+    /// The swap identifier related to this message.
+    pub swap_id: SwapId,
+    /// The `Ab(Tb)` collaborative cancel adaptor signature.
+    pub collaborative_cancel_adaptor_sig: EncSig,
+}
+
+impl<EncSig> fmt::Display for CollaborativeCancelSignature<EncSig>
+where
+    EncSig: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl<EncSig> Encodable for CollaborativeCancelSignature<EncSig>
+where
+    EncSig: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let len = self.swap_id.consensus_encode(s)?;
+        Ok(len
+            + self
+                .collaborative_cancel_adaptor_sig
+                .as_canonical_bytes()
+                .consensus_encode(s)?)
+    }
+}
+
+impl<EncSig> Decodable for CollaborativeCancelSignature<EncSig>
+where
+    EncSig: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            swap_id: Decodable::consensus_decode(d)?,
+            collaborative_cancel_adaptor_sig: EncSig::from_canonical_bytes(
+                unwrap_vec_ref!(d).as_ref(),
+            )?,
+        })
+    }
+}
+
+impl_strict_encoding!(CollaborativeCancelSignature<EncSig>, EncSig: CanonicalBytes);
+
+impl_swap_message!(CollaborativeCancelSignature<EncSig>);
+
 /// Optional courtesy message from either [`SwapRole`] to inform the counterparty
 /// that they have aborted the swap with an `OPTIONAL` message body to provide the reason.
 ///
@@ -751,3 +1100,5 @@ impl Decodable for Abort {
 }
 
 impl_strict_encoding!(Abort);
+
+impl_swap_message!(Abort);
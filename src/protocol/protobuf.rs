@@ -0,0 +1,819 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Protocol Buffers representation of the protocol messages and deals, for implementations
+//! exchanging swap messages over gRPC or with other protobuf based stacks.
+//!
+//! The schema is shipped as [`PROTO_SCHEMA`] and can be fed to `protoc` to generate the
+//! messages in other languages. Blockchain specific elements are carried as their canonical
+//! bytes and enumerations as their consensus code, so the protobuf messages stay independent of
+//! the blockchains supported by a swap. The [`Protobuf`] trait converts the messages from this
+//! crate into their protobuf wire format and back.
+
+use std::io;
+
+use bitcoin::secp256k1::PublicKey;
+use inet2_addr::InetSocketAddr;
+
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::{SharedKeyId, TaggedElement};
+use crate::protocol::message::{
+    Abort, BuyProcedureSignature, CollaborativeCancelProposal, CollaborativeCancelSignature,
+    CommitAliceParameters, CommitBobParameters, CoreArbitratingSetup, MuSigNonce,
+    MuSigPartialSignature, RefundProcedureSignatures, RevealAliceParameters, RevealBobParameters,
+};
+use crate::swap::SwapId;
+use crate::trade::{Deal, DealId, DealParameters, DealTerms, Version};
+
+/// The protobuf schema of the protocol messages and deals.
+pub const PROTO_SCHEMA: &str = include_str!("../../proto/farcaster.proto");
+
+/// Conversion of a message from and into its protobuf wire format, as defined in
+/// [`PROTO_SCHEMA`].
+pub trait Protobuf: Sized {
+    /// Encode the message in protobuf wire format.
+    fn to_protobuf(&self) -> Vec<u8>;
+
+    /// Decode a message from its protobuf wire format. Unknown fields are ignored.
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error>;
+}
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.raw_varint(((field as u64) << 3) | wire_type as u64);
+    }
+
+    /// Write a scalar field, omitted when set to its default value.
+    fn varint(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.key(field, WIRE_VARINT);
+            self.raw_varint(value);
+        }
+    }
+
+    /// Write a bytes field, omitted when empty.
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        if !bytes.is_empty() {
+            self.message(field, bytes);
+        }
+    }
+
+    /// Write a length delimited field, even if empty, as needed for optional and repeated fields.
+    fn message(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, WIRE_LEN);
+        self.raw_varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn tagged<T: ProtoTag, E: CanonicalBytes>(&mut self, field: u32, keys: &[TaggedElement<T, E>]) {
+        for key in keys {
+            let mut w = Writer::default();
+            w.varint(1, key.tag().to_code());
+            w.bytes(2, &key.elem().as_canonical_bytes());
+            self.message(field, &w.0);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+/// The fields of a decoded message, in wire order.
+struct Fields<'a>(Vec<(u32, Value<'a>)>);
+
+impl<'a> Fields<'a> {
+    fn parse(mut bytes: &'a [u8]) -> Result<Self, consensus::Error> {
+        let mut fields = vec![];
+        while !bytes.is_empty() {
+            let key = read_varint(&mut bytes)?;
+            let field = u32::try_from(key >> 3)
+                .map_err(|_| consensus::Error::ParseFailed("Invalid protobuf field number"))?;
+            let value = match (key & 0x07) as u8 {
+                WIRE_VARINT => Value::Varint(read_varint(&mut bytes)?),
+                WIRE_LEN => {
+                    let len = usize::try_from(read_varint(&mut bytes)?)
+                        .map_err(|_| consensus::Error::ParseFailed("Protobuf field too long"))?;
+                    Value::Bytes(take(&mut bytes, len)?)
+                }
+                WIRE_FIXED64 => {
+                    take(&mut bytes, 8)?;
+                    continue;
+                }
+                WIRE_FIXED32 => {
+                    take(&mut bytes, 4)?;
+                    continue;
+                }
+                _ => return Err(consensus::Error::ParseFailed("Unknown protobuf wire type")),
+            };
+            fields.push((field, value));
+        }
+        Ok(Self(fields))
+    }
+
+    /// Return the last value of a singular field, as protobuf merges repeated singular fields.
+    fn last(&self, field: u32) -> Option<Value<'a>> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(f, _)| *f == field)
+            .map(|(_, value)| *value)
+    }
+
+    fn varint(&self, field: u32) -> Result<u64, consensus::Error> {
+        match self.last(field) {
+            None => Ok(0),
+            Some(Value::Varint(value)) => Ok(value),
+            Some(Value::Bytes(_)) => Err(consensus::Error::TypeMismatch),
+        }
+    }
+
+    fn optional_bytes(&self, field: u32) -> Result<Option<&'a [u8]>, consensus::Error> {
+        match self.last(field) {
+            None => Ok(None),
+            Some(Value::Bytes(bytes)) => Ok(Some(bytes)),
+            Some(Value::Varint(_)) => Err(consensus::Error::TypeMismatch),
+        }
+    }
+
+    fn bytes(&self, field: u32) -> Result<&'a [u8], consensus::Error> {
+        Ok(self.optional_bytes(field)?.unwrap_or_default())
+    }
+
+    fn element<T: CanonicalBytes>(&self, field: u32) -> Result<T, consensus::Error> {
+        T::from_canonical_bytes(self.bytes(field)?)
+    }
+
+    fn swap_id(&self, field: u32) -> Result<SwapId, consensus::Error> {
+        consensus::deserialize(self.bytes(field)?)
+    }
+
+    fn code<T: Decodable>(&self, field: u32, width: usize) -> Result<T, consensus::Error> {
+        let code = self.varint(field)?;
+        (width == 8 || code >> (8 * width) == 0)
+            .then(|| ())
+            .ok_or(consensus::Error::UnknownType)?;
+        consensus::deserialize(&code.to_le_bytes()[..width])
+    }
+
+    fn tagged<T: ProtoTag, E: CanonicalBytes>(
+        &self,
+        field: u32,
+    ) -> Result<Vec<TaggedElement<T, E>>, consensus::Error> {
+        self.0
+            .iter()
+            .filter(|(f, _)| *f == field)
+            .map(|(_, value)| match value {
+                Value::Bytes(bytes) => {
+                    let key = Fields::parse(bytes)?;
+                    Ok(TaggedElement::new(
+                        T::from_code(key.varint(1)?)?,
+                        key.element(2)?,
+                    ))
+                }
+                Value::Varint(_) => Err(consensus::Error::TypeMismatch),
+            })
+            .collect()
+    }
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, consensus::Error> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *take(bytes, 1)?.first().expect("one byte taken");
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(consensus::Error::ParseFailed("Protobuf varint too long"))
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], consensus::Error> {
+    (bytes.len() >= len)
+        .then(|| ())
+        .ok_or_else(|| consensus::Error::Io(io::ErrorKind::UnexpectedEof.into()))?;
+    let (head, tail) = bytes.split_at(len);
+    *bytes = tail;
+    Ok(head)
+}
+
+/// Return the consensus code of an enumeration, encoded in little endian.
+fn code<T: Encodable>(value: &T) -> u64 {
+    consensus::serialize(value)
+        .iter()
+        .rev()
+        .fold(0, |code, byte| (code << 8) | *byte as u64)
+}
+
+/// Tags of the keys carried in [`TaggedElement`].
+trait ProtoTag: Sized {
+    fn to_code(&self) -> u64;
+    fn from_code(code: u64) -> Result<Self, consensus::Error>;
+}
+
+impl ProtoTag for u16 {
+    fn to_code(&self) -> u64 {
+        *self as u64
+    }
+
+    fn from_code(code: u64) -> Result<Self, consensus::Error> {
+        u16::try_from(code).map_err(|_| consensus::Error::ParseFailed("Invalid key tag"))
+    }
+}
+
+impl ProtoTag for SharedKeyId {
+    fn to_code(&self) -> u64 {
+        self.id() as u64
+    }
+
+    fn from_code(code: u64) -> Result<Self, consensus::Error> {
+        u16::from_code(code).map(SharedKeyId::new)
+    }
+}
+
+impl<C> Protobuf for CommitAliceParameters<C>
+where
+    C: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        w.bytes(2, &self.buy.as_canonical_bytes());
+        w.bytes(3, &self.cancel.as_canonical_bytes());
+        w.bytes(4, &self.refund.as_canonical_bytes());
+        w.bytes(5, &self.punish.as_canonical_bytes());
+        w.bytes(6, &self.adaptor.as_canonical_bytes());
+        w.tagged(7, &self.extra_arbitrating_keys);
+        w.tagged(8, &self.arbitrating_shared_keys);
+        w.bytes(9, &self.spend.as_canonical_bytes());
+        w.tagged(10, &self.extra_accordant_keys);
+        w.tagged(11, &self.accordant_shared_keys);
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            buy: f.element(2)?,
+            cancel: f.element(3)?,
+            refund: f.element(4)?,
+            punish: f.element(5)?,
+            adaptor: f.element(6)?,
+            extra_arbitrating_keys: f.tagged(7)?,
+            arbitrating_shared_keys: f.tagged(8)?,
+            spend: f.element(9)?,
+            extra_accordant_keys: f.tagged(10)?,
+            accordant_shared_keys: f.tagged(11)?,
+        })
+    }
+}
+
+impl<C> Protobuf for CommitBobParameters<C>
+where
+    C: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        w.bytes(2, &self.buy.as_canonical_bytes());
+        w.bytes(3, &self.cancel.as_canonical_bytes());
+        w.bytes(4, &self.refund.as_canonical_bytes());
+        w.bytes(6, &self.adaptor.as_canonical_bytes());
+        w.tagged(7, &self.extra_arbitrating_keys);
+        w.tagged(8, &self.arbitrating_shared_keys);
+        w.bytes(9, &self.spend.as_canonical_bytes());
+        w.tagged(10, &self.extra_accordant_keys);
+        w.tagged(11, &self.accordant_shared_keys);
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            buy: f.element(2)?,
+            cancel: f.element(3)?,
+            refund: f.element(4)?,
+            adaptor: f.element(6)?,
+            extra_arbitrating_keys: f.tagged(7)?,
+            arbitrating_shared_keys: f.tagged(8)?,
+            spend: f.element(9)?,
+            extra_accordant_keys: f.tagged(10)?,
+            accordant_shared_keys: f.tagged(11)?,
+        })
+    }
+}
+
+impl<Pk, Qk, Rk, Sk, Addr, Pr> Protobuf for RevealAliceParameters<Pk, Qk, Rk, Sk, Addr, Pr>
+where
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+    Rk: CanonicalBytes,
+    Sk: CanonicalBytes,
+    Addr: CanonicalBytes,
+    Pr: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        w.bytes(2, &self.buy.as_canonical_bytes());
+        w.bytes(3, &self.cancel.as_canonical_bytes());
+        w.bytes(4, &self.refund.as_canonical_bytes());
+        w.bytes(5, &self.punish.as_canonical_bytes());
+        w.bytes(6, &self.adaptor.as_canonical_bytes());
+        w.tagged(7, &self.extra_arbitrating_keys);
+        w.tagged(8, &self.arbitrating_shared_keys);
+        w.bytes(9, &self.spend.as_canonical_bytes());
+        w.tagged(10, &self.extra_accordant_keys);
+        w.tagged(11, &self.accordant_shared_keys);
+        w.bytes(12, &self.address.as_canonical_bytes());
+        w.bytes(13, &self.proof.as_canonical_bytes());
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            buy: f.element(2)?,
+            cancel: f.element(3)?,
+            refund: f.element(4)?,
+            punish: f.element(5)?,
+            adaptor: f.element(6)?,
+            extra_arbitrating_keys: f.tagged(7)?,
+            arbitrating_shared_keys: f.tagged(8)?,
+            spend: f.element(9)?,
+            extra_accordant_keys: f.tagged(10)?,
+            accordant_shared_keys: f.tagged(11)?,
+            address: f.element(12)?,
+            proof: f.element(13)?,
+        })
+    }
+}
+
+impl<Pk, Qk, Rk, Sk, Addr, Pr> Protobuf for RevealBobParameters<Pk, Qk, Rk, Sk, Addr, Pr>
+where
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+    Rk: CanonicalBytes,
+    Sk: CanonicalBytes,
+    Addr: CanonicalBytes,
+    Pr: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        w.bytes(2, &self.buy.as_canonical_bytes());
+        w.bytes(3, &self.cancel.as_canonical_bytes());
+        w.bytes(4, &self.refund.as_canonical_bytes());
+        w.bytes(6, &self.adaptor.as_canonical_bytes());
+        w.tagged(7, &self.extra_arbitrating_keys);
+        w.tagged(8, &self.arbitrating_shared_keys);
+        w.bytes(9, &self.spend.as_canonical_bytes());
+        w.tagged(10, &self.extra_accordant_keys);
+        w.tagged(11, &self.accordant_shared_keys);
+        w.bytes(12, &self.address.as_canonical_bytes());
+        w.bytes(13, &self.proof.as_canonical_bytes());
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            buy: f.element(2)?,
+            cancel: f.element(3)?,
+            refund: f.element(4)?,
+            adaptor: f.element(6)?,
+            extra_arbitrating_keys: f.tagged(7)?,
+            arbitrating_shared_keys: f.tagged(8)?,
+            spend: f.element(9)?,
+            extra_accordant_keys: f.tagged(10)?,
+            accordant_shared_keys: f.tagged(11)?,
+            address: f.element(12)?,
+            proof: f.element(13)?,
+        })
+    }
+}
+
+impl<Px, Sig> Protobuf for CoreArbitratingSetup<Px, Sig>
+where
+    Px: CanonicalBytes,
+    Sig: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        w.bytes(2, &self.lock.as_canonical_bytes());
+        w.bytes(3, &self.cancel.as_canonical_bytes());
+        w.bytes(4, &self.refund.as_canonical_bytes());
+        w.bytes(5, &self.cancel_sig.as_canonical_bytes());
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            lock: f.element(2)?,
+            cancel: f.element(3)?,
+            refund: f.element(4)?,
+            cancel_sig: f.element(5)?,
+        })
+    }
+}
+
+impl<Sig, EncSig> Protobuf for RefundProcedureSignatures<Sig, EncSig>
+where
+    Sig: CanonicalBytes,
+    EncSig: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        w.bytes(2, &self.cancel_sig.as_canonical_bytes());
+        w.bytes(3, &self.refund_adaptor_sig.as_canonical_bytes());
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            cancel_sig: f.element(2)?,
+            refund_adaptor_sig: f.element(3)?,
+        })
+    }
+}
+
+impl<Px, EncSig> Protobuf for BuyProcedureSignature<Px, EncSig>
+where
+    Px: CanonicalBytes,
+    EncSig: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        w.bytes(2, &self.buy.as_canonical_bytes());
+        w.bytes(3, &self.buy_adaptor_sig.as_canonical_bytes());
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            buy: f.element(2)?,
+            buy_adaptor_sig: f.element(3)?,
+        })
+    }
+}
+
+impl<N> Protobuf for MuSigNonce<N>
+where
+    N: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        w.varint(2, code(&self.tx_label));
+        w.bytes(3, &self.nonce.as_canonical_bytes());
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            tx_label: f.code(2, 2)?,
+            nonce: f.element(3)?,
+        })
+    }
+}
+
+impl<Ps> Protobuf for MuSigPartialSignature<Ps>
+where
+    Ps: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        w.varint(2, code(&self.tx_label));
+        w.bytes(3, &self.partial_sig.as_canonical_bytes());
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            tx_label: f.code(2, 2)?,
+            partial_sig: f.element(3)?,
+        })
+    }
+}
+
+impl<Px, Sig> Protobuf for CollaborativeCancelProposal<Px, Sig>
+where
+    Px: CanonicalBytes,
+    Sig: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        w.bytes(2, &self.collaborative_cancel.as_canonical_bytes());
+        w.bytes(3, &self.collaborative_cancel_sig.as_canonical_bytes());
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            collaborative_cancel: f.element(2)?,
+            collaborative_cancel_sig: f.element(3)?,
+        })
+    }
+}
+
+impl<EncSig> Protobuf for CollaborativeCancelSignature<EncSig>
+where
+    EncSig: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        w.bytes(
+            2,
+            &self.collaborative_cancel_adaptor_sig.as_canonical_bytes(),
+        );
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            collaborative_cancel_adaptor_sig: f.element(2)?,
+        })
+    }
+}
+
+impl Protobuf for Abort {
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.swap_id));
+        if let Some(body) = &self.error_body {
+            w.message(2, body.as_bytes());
+        }
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            swap_id: f.swap_id(1)?,
+            error_body: f
+                .optional_bytes(2)?
+                .map(String::from_canonical_bytes)
+                .transpose()?,
+        })
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Protobuf for DealParameters<Amt, Bmt, Ti, F>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(1, &consensus::serialize(&self.uuid.0));
+        w.varint(2, code(&self.network));
+        w.varint(3, code(&self.arbitrating_blockchain));
+        w.varint(4, code(&self.accordant_blockchain));
+        w.bytes(5, &self.arbitrating_amount.as_canonical_bytes());
+        w.bytes(6, &self.accordant_amount.as_canonical_bytes());
+        w.bytes(7, &self.cancel_timelock.as_canonical_bytes());
+        w.bytes(8, &self.punish_timelock.as_canonical_bytes());
+        w.bytes(9, &consensus::serialize(&self.fee_strategy));
+        w.varint(10, code(&self.maker_role));
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        Ok(Self {
+            uuid: DealId(consensus::deserialize(f.bytes(1)?)?),
+            network: f.code(2, 1)?,
+            arbitrating_blockchain: f.code(3, 4)?,
+            accordant_blockchain: f.code(4, 4)?,
+            arbitrating_amount: f.element(5)?,
+            accordant_amount: f.element(6)?,
+            cancel_timelock: f.element(7)?,
+            punish_timelock: f.element(8)?,
+            fee_strategy: consensus::deserialize(f.bytes(9)?)?,
+            maker_role: f.code(10, 1)?,
+        })
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Protobuf for Deal<Amt, Bmt, Ti, F>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+{
+    fn to_protobuf(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.varint(1, self.version.to_u16() as u64);
+        w.message(2, &self.parameters.to_protobuf());
+        w.bytes(3, &self.node_id.as_canonical_bytes());
+        let mut peer_address = vec![];
+        strict_encoding::StrictEncode::strict_encode(&self.peer_address, &mut peer_address)
+            .expect("in-memory encoding cannot fail");
+        w.bytes(4, &peer_address);
+        if self.version.to_u16() >= 2 {
+            w.bytes(5, &consensus::serialize(&self.terms));
+        }
+        w.0
+    }
+
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, consensus::Error> {
+        let f = Fields::parse(bytes)?;
+        let version = Version::new(
+            u16::try_from(f.varint(1)?)
+                .map_err(|_| consensus::Error::ParseFailed("Invalid deal version"))?,
+        );
+        let peer_address: InetSocketAddr =
+            strict_encoding::StrictDecode::strict_deserialize(f.bytes(4)?)
+                .map_err(consensus::Error::new)?;
+        let terms = match version.to_u16() {
+            v if v >= 2 => consensus::deserialize(f.bytes(5)?)?,
+            _ => DealTerms::default(),
+        };
+        Ok(Self {
+            version,
+            parameters: DealParameters::from_protobuf(f.bytes(2)?)?,
+            node_id: PublicKey::from_canonical_bytes(f.bytes(3)?)?,
+            peer_address,
+            terms,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    use crate::bitcoin::fee::SatPerKvB;
+    use crate::bitcoin::timelock::CSVTimelock;
+    use crate::crypto::KeccakCommitment;
+    use crate::transaction::TxLabel;
+
+    const DEAL: &str = "Deal:Cke4ftrP5A7CRkYdGNd87TRU6sUP1kBKM1LQM2fvVdFMNR4gmBqNCsR11111uMM4pF11111112Lvo11111TBALTh113GTvtvqfD1111114A4TUWxWeBc1WxwGBKaUssrb6pnijjhnb6RAs1HBr1CaX7o1a1111111111111111111111111111111111111111115T1WG8uDoZeAW1q";
+
+    fn swap_id() -> SwapId {
+        SwapId::from_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap()
+    }
+
+    #[test]
+    fn schema_defines_messages() {
+        for message in [
+            "CommitAliceParameters",
+            "CommitBobParameters",
+            "RevealAliceParameters",
+            "RevealBobParameters",
+            "CoreArbitratingSetup",
+            "RefundProcedureSignatures",
+            "BuyProcedureSignature",
+            "MuSigNonce",
+            "MuSigPartialSignature",
+            "CollaborativeCancelProposal",
+            "CollaborativeCancelSignature",
+            "Abort",
+            "DealParameters",
+            "Deal",
+        ] {
+            assert!(PROTO_SCHEMA.contains(&format!("message {} {{", message)));
+        }
+    }
+
+    #[test]
+    fn commit_roundtrip() {
+        let commit = |byte| KeccakCommitment::new([byte; 32]);
+        let msg = CommitAliceParameters {
+            swap_id: swap_id(),
+            buy: commit(1),
+            cancel: commit(2),
+            refund: commit(3),
+            punish: commit(4),
+            adaptor: commit(5),
+            extra_arbitrating_keys: vec![TaggedElement::new(0, commit(6))],
+            arbitrating_shared_keys: vec![],
+            spend: commit(7),
+            extra_accordant_keys: vec![],
+            accordant_shared_keys: vec![
+                TaggedElement::new(SharedKeyId::new(0), commit(8)),
+                TaggedElement::new(SharedKeyId::new(300), commit(9)),
+            ],
+        };
+        let bytes = msg.to_protobuf();
+        assert_eq!(CommitAliceParameters::from_protobuf(&bytes).unwrap(), msg);
+
+        // Unknown fields of all wire types are skipped
+        let mut extended = bytes.clone();
+        extended.extend([0xa0, 0x01, 0x2a]); // field 20, varint
+        extended.extend([0xa9, 0x01, 0, 0, 0, 0, 0, 0, 0, 0]); // field 21, fixed64
+        extended.extend([0xb2, 0x01, 0x01, 0xff]); // field 22, bytes
+        extended.extend([0xbd, 0x01, 0, 0, 0, 0]); // field 23, fixed32
+        assert_eq!(
+            CommitAliceParameters::from_protobuf(&extended).unwrap(),
+            msg
+        );
+
+        // Truncated messages are rejected
+        assert!(CommitAliceParameters::<KeccakCommitment>::from_protobuf(
+            &bytes[..bytes.len() - 1]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn musig_and_abort_roundtrip() {
+        let msg = MuSigPartialSignature {
+            swap_id: swap_id(),
+            tx_label: TxLabel::AccLock,
+            partial_sig: String::from("partial signature"),
+        };
+        assert_eq!(
+            MuSigPartialSignature::from_protobuf(&msg.to_protobuf()).unwrap(),
+            msg
+        );
+
+        let mut bytes = msg.to_protobuf();
+        bytes.extend([0x10, 0x08]); // the last tx label wins, 8 is unknown
+        assert!(MuSigPartialSignature::<String>::from_protobuf(&bytes).is_err());
+
+        for error_body in [None, Some(String::new()), Some(String::from("timeout"))] {
+            let abort = Abort {
+                swap_id: swap_id(),
+                error_body: error_body.clone(),
+            };
+            let decoded = Abort::from_protobuf(&abort.to_protobuf()).unwrap();
+            assert_eq!(decoded.swap_id, abort.swap_id);
+            assert_eq!(decoded.error_body, error_body);
+        }
+    }
+
+    #[test]
+    fn deal_roundtrip() {
+        let deal = Deal::<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB>::from_str(DEAL)
+            .unwrap();
+        let bytes = deal.to_protobuf();
+        assert_eq!(Deal::from_protobuf(&bytes).unwrap(), deal);
+
+        let mut deal = deal;
+        deal.version = Version::new_v2();
+        deal.terms.min_accordant_amount = Some(monero::Amount::from_pico(10));
+        assert_eq!(Deal::from_protobuf(&deal.to_protobuf()).unwrap(), deal);
+    }
+}
@@ -0,0 +1,559 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Detection of the races between the arbitrating transactions of a swap. A [`RaceDetector`] is
+//! fed with the mempool and chain events reported by a syncer and returns the
+//! [`RecommendedAction`] the local participant must take to stay safe.
+//!
+//! The dangerous situations covered are:
+//!
+//! - the cancel timelock expires while the swap did not complete: `cancel` must be broadcast,
+//! - the `buy` transaction appears, even in the mempool and after the cancel timelock expired:
+//!   Bob must extract Alice's secret from it before it is possibly replaced by `cancel`,
+//! - `cancel` is confirmed: Bob must refund before the punish timelock expires, Alice must punish
+//!   once it expires if Bob did not refund,
+//! - the `refund` transaction appears: Alice must extract Bob's secret from it.
+//!
+//! Confirmations can go backwards when a chain reorganizes. The detector follows the
+//! [`ChainEvent::Reorged`] events and, given a [`ReorgPolicy`], tells which [`SwapAction`]s
+//! already taken are no longer backed by a transaction buried deep enough and must be treated as
+//! unsafe.
+//!
+//! Every transition observed by the detector is also reported as a [`SwapEvent`], see
+//! [`RaceDetector::drain_events`], to keep an audit log of the swap.
+
+use std::collections::HashMap;
+
+use crate::blockchain::{Blockchain, Timelock};
+use crate::protocol::events::SwapEvent;
+use crate::protocol::schedule::TimelockSchedule;
+use crate::role::SwapRole;
+use crate::transaction::TxLabel;
+
+/// An event about an arbitrating transaction or the chain tip, reported by a syncer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum ChainEvent {
+    /// The transaction has been seen in the mempool.
+    Mempool(TxLabel),
+    /// The transaction has been mined at the given height.
+    Confirmed(TxLabel, u32),
+    /// A new block has been mined at the given height.
+    Height(u32),
+    /// The transaction lost confirmations after a chain reorganization, it now has `new_depth`
+    /// confirmations, zero if it returned to the mempool.
+    Reorged { tx: TxLabel, new_depth: u32 },
+}
+
+/// Number of confirmations after which a transaction is considered final on each blockchain of
+/// the swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ReorgPolicy {
+    /// Safety depth of the arbitrating transactions.
+    pub arbitrating_depth: u32,
+    /// Safety depth of the accordant lock transaction.
+    pub accordant_depth: u32,
+}
+
+impl ReorgPolicy {
+    /// Create a new policy with the safety depth of each blockchain.
+    pub fn new(arbitrating: Blockchain, accordant: Blockchain) -> Self {
+        Self {
+            arbitrating_depth: Self::default_depth(arbitrating),
+            accordant_depth: Self::default_depth(accordant),
+        }
+    }
+
+    /// The default safety depth of a blockchain.
+    pub fn default_depth(blockchain: Blockchain) -> u32 {
+        match blockchain {
+            Blockchain::Bitcoin => 6,
+            Blockchain::Monero => 10,
+        }
+    }
+
+    /// The safety depth applied to a transaction.
+    pub fn safety_depth(&self, label: TxLabel) -> u32 {
+        match label {
+            TxLabel::AccLock => self.accordant_depth,
+            _ => self.arbitrating_depth,
+        }
+    }
+}
+
+impl Default for ReorgPolicy {
+    fn default() -> Self {
+        Self::new(Blockchain::Bitcoin, Blockchain::Monero)
+    }
+}
+
+/// Irreversible action of a participant that is only safe once a transaction is final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum SwapAction {
+    /// Alice locks the accordant assets, requires the arbitrating lock to be final.
+    LockAccordant,
+    /// Bob reveals the buy procedure signature, requires the accordant lock to be final.
+    RevealBuySignature,
+}
+
+impl SwapAction {
+    /// The transaction the action depends on.
+    pub fn depends_on(&self) -> TxLabel {
+        match self {
+            Self::LockAccordant => TxLabel::Lock,
+            Self::RevealBuySignature => TxLabel::AccLock,
+        }
+    }
+}
+
+/// Action a participant must take in reaction to the current on-chain state of the swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum RecommendedAction {
+    /// The cancel timelock expired, broadcast the `cancel` transaction.
+    BroadcastCancelNow,
+    /// The `buy` transaction has been seen, extract the counter-party secret from its witness.
+    ExtractSecretFromBuy,
+    /// The `cancel` transaction is confirmed, broadcast the `refund` transaction before the punish
+    /// timelock expires.
+    BroadcastRefund,
+    /// The `refund` transaction has been seen, extract the counter-party secret from its
+    /// witness.
+    ExtractSecretFromRefund,
+    /// The punish timelock expired without seeing the `refund`, broadcast the `punish`
+    /// transaction.
+    BroadcastPunish,
+}
+
+/// Where a transaction has been seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Seen {
+    Mempool,
+    Confirmed(u32),
+}
+
+/// Tracks the arbitrating transactions of a swap for one participant and detects the races
+/// between them.
+#[derive(Debug, Clone)]
+pub struct RaceDetector {
+    role: SwapRole,
+    cancel_timelock: u32,
+    punish_timelock: u32,
+    height: u32,
+    seen: HashMap<TxLabel, Seen>,
+    policy: ReorgPolicy,
+    taken: Vec<SwapAction>,
+    events: Vec<SwapEvent>,
+    finalized: Vec<TxLabel>,
+    cancel_expired: bool,
+    punish_expired: bool,
+    last_action: Option<RecommendedAction>,
+}
+
+/// All the transaction labels, in the order their events are reported.
+const LABELS: [TxLabel; 7] = [
+    TxLabel::Funding,
+    TxLabel::Lock,
+    TxLabel::AccLock,
+    TxLabel::Buy,
+    TxLabel::Cancel,
+    TxLabel::Refund,
+    TxLabel::Punish,
+];
+
+impl RaceDetector {
+    /// Create a new detector for the swap `role` with the negotiated timelocks, converted to
+    /// blocks with [`Timelock::as_blocks`].
+    pub fn new<Ti>(role: SwapRole, cancel_timelock: Ti, punish_timelock: Ti) -> Self
+    where
+        Ti: Timelock,
+    {
+        Self {
+            role,
+            cancel_timelock: cancel_timelock.as_blocks(),
+            punish_timelock: punish_timelock.as_blocks(),
+            height: 0,
+            seen: HashMap::new(),
+            policy: ReorgPolicy::default(),
+            taken: vec![],
+            events: vec![],
+            finalized: vec![],
+            cancel_expired: false,
+            punish_expired: false,
+            last_action: None,
+        }
+    }
+
+    /// Set the reorg policy used to decide if a transaction is final.
+    pub fn with_reorg_policy(mut self, policy: ReorgPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Register a new event and return the action to take, if any.
+    pub fn on_event(&mut self, event: ChainEvent) -> Option<RecommendedAction> {
+        match event {
+            ChainEvent::Mempool(label) => {
+                if !self.was_seen(label) {
+                    self.events.push(SwapEvent::TxSeen(label));
+                }
+                self.seen.entry(label).or_insert(Seen::Mempool);
+            }
+            ChainEvent::Confirmed(label, height) => {
+                if !self.was_seen(label) {
+                    self.events.push(SwapEvent::TxSeen(label));
+                }
+                self.events
+                    .push(SwapEvent::TxConfirmed { tx: label, height });
+                self.seen.insert(label, Seen::Confirmed(height));
+                self.height = self.height.max(height);
+            }
+            ChainEvent::Height(height) => self.height = self.height.max(height),
+            ChainEvent::Reorged { tx, new_depth } => {
+                self.events.push(SwapEvent::TxReorged { tx, new_depth });
+                let seen = match new_depth {
+                    0 => Seen::Mempool,
+                    depth => Seen::Confirmed(self.height.saturating_add(1).saturating_sub(depth)),
+                };
+                self.seen.insert(tx, seen);
+            }
+        }
+        self.record_transitions();
+        let action = self.recommended_action();
+        if let Some(recommended) = action.filter(|_| action != self.last_action) {
+            self.events.push(SwapEvent::ActionRecommended(recommended));
+        }
+        self.last_action = action;
+        action
+    }
+
+    /// Return and clear the events emitted since the last call, in the order they happened.
+    pub fn drain_events(&mut self) -> Vec<SwapEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn record_transitions(&mut self) {
+        for label in LABELS {
+            let is_final = self.is_final(label);
+            let was_final = self.finalized.contains(&label);
+            if is_final && !was_final {
+                self.finalized.push(label);
+                self.events.push(SwapEvent::TxFinal {
+                    tx: label,
+                    depth: self.depth(label).unwrap_or_default(),
+                });
+            } else if !is_final && was_final {
+                self.finalized.retain(|l| *l != label);
+            }
+        }
+
+        let cancel_expired = self.expired(TxLabel::Lock, self.cancel_timelock);
+        if cancel_expired && !self.cancel_expired {
+            self.events.push(SwapEvent::CancelBroadcastable {
+                height: self.height,
+            });
+        }
+        self.cancel_expired = cancel_expired;
+
+        let punish_expired = self.expired(TxLabel::Cancel, self.punish_timelock);
+        if punish_expired && !self.punish_expired {
+            self.events.push(SwapEvent::PunishBroadcastable {
+                height: self.height,
+            });
+        }
+        self.punish_expired = punish_expired;
+    }
+
+    /// Return the action to take given the events received so far, if any.
+    pub fn recommended_action(&self) -> Option<RecommendedAction> {
+        match self.role {
+            SwapRole::Alice => self.alice_action(),
+            SwapRole::Bob => self.bob_action(),
+        }
+    }
+
+    /// Return the number of confirmations of a transaction, `Some(0)` if only seen in the
+    /// mempool.
+    pub fn depth(&self, label: TxLabel) -> Option<u32> {
+        self.seen.get(&label).map(|seen| match seen {
+            Seen::Mempool => 0,
+            Seen::Confirmed(height) => self.height.saturating_add(1).saturating_sub(*height),
+        })
+    }
+
+    /// Return the deadlines of the swap once the lock transaction is mined, following the height
+    /// the cancel transaction is mined at if any.
+    pub fn schedule(&self) -> Option<TimelockSchedule> {
+        let lock_height = self.confirmed_height(TxLabel::Lock)?;
+        let schedule =
+            TimelockSchedule::new(lock_height, self.cancel_timelock, self.punish_timelock);
+        Some(match self.confirmed_height(TxLabel::Cancel) {
+            Some(height) => schedule.with_cancel_height(height),
+            None => schedule,
+        })
+    }
+
+    fn confirmed_height(&self, label: TxLabel) -> Option<u32> {
+        match self.seen.get(&label) {
+            Some(Seen::Confirmed(height)) => Some(*height),
+            _ => None,
+        }
+    }
+
+    /// Return true if the transaction reached the safety depth of the reorg policy.
+    pub fn is_final(&self, label: TxLabel) -> bool {
+        self.depth(label)
+            .map_or(false, |depth| depth >= self.policy.safety_depth(label))
+    }
+
+    /// Return true if the transaction the action depends on is final, i.e. the action can be
+    /// taken safely.
+    pub fn is_safe(&self, action: SwapAction) -> bool {
+        self.is_final(action.depends_on())
+    }
+
+    /// Register an action taken by the participant, so it is reported by
+    /// [`RaceDetector::unsafe_actions`] if a reorg invalidates it.
+    pub fn record_action(&mut self, action: SwapAction) {
+        if !self.taken.contains(&action) {
+            self.taken.push(action);
+        }
+    }
+
+    /// Return the actions already taken whose transaction is no longer final, e.g. after a reorg.
+    /// The participant must not rely on them until the transaction is buried again.
+    pub fn unsafe_actions(&self) -> Vec<SwapAction> {
+        self.taken
+            .iter()
+            .copied()
+            .filter(|action| !self.is_safe(*action))
+            .collect()
+    }
+
+    fn alice_action(&self) -> Option<RecommendedAction> {
+        if self.was_seen(TxLabel::Refund) {
+            return Some(RecommendedAction::ExtractSecretFromRefund);
+        }
+        if self.was_seen(TxLabel::Punish) || self.is_confirmed(TxLabel::Buy) {
+            return None;
+        }
+        if self.was_seen(TxLabel::Cancel) {
+            return self
+                .expired(TxLabel::Cancel, self.punish_timelock)
+                .then(|| RecommendedAction::BroadcastPunish);
+        }
+        if self.was_seen(TxLabel::Buy) {
+            // Our own buy is pending, cancelling would only race against it
+            return None;
+        }
+        self.cancel_action()
+    }
+
+    fn bob_action(&self) -> Option<RecommendedAction> {
+        if self.was_seen(TxLabel::Buy) {
+            return Some(RecommendedAction::ExtractSecretFromBuy);
+        }
+        if self.was_seen(TxLabel::Refund) || self.was_seen(TxLabel::Punish) {
+            return None;
+        }
+        if self.was_seen(TxLabel::Cancel) {
+            return self
+                .is_confirmed(TxLabel::Cancel)
+                .then(|| RecommendedAction::BroadcastRefund);
+        }
+        self.cancel_action()
+    }
+
+    fn cancel_action(&self) -> Option<RecommendedAction> {
+        self.expired(TxLabel::Lock, self.cancel_timelock)
+            .then(|| RecommendedAction::BroadcastCancelNow)
+    }
+
+    fn was_seen(&self, label: TxLabel) -> bool {
+        self.seen.contains_key(&label)
+    }
+
+    fn is_confirmed(&self, label: TxLabel) -> bool {
+        matches!(self.seen.get(&label), Some(Seen::Confirmed(_)))
+    }
+
+    /// The timelock of a transaction spending `label` expired, i.e. the spending transaction can
+    /// be mined in the next block.
+    fn expired(&self, label: TxLabel, timelock: u32) -> bool {
+        match self.seen.get(&label) {
+            Some(Seen::Confirmed(height)) => {
+                self.height.saturating_add(1) >= height.saturating_add(timelock)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bob_cancel_then_refund() {
+        let mut detector = RaceDetector::new(SwapRole::Bob, 10u32, 20u32);
+        assert_eq!(detector.schedule(), None);
+        assert_eq!(
+            detector.on_event(ChainEvent::Confirmed(TxLabel::Lock, 100)),
+            None
+        );
+        assert_eq!(detector.schedule().unwrap().cancel_available_at(), 110);
+        assert_eq!(detector.on_event(ChainEvent::Height(108)), None);
+        assert_eq!(
+            detector.on_event(ChainEvent::Height(109)),
+            Some(RecommendedAction::BroadcastCancelNow)
+        );
+        assert_eq!(
+            detector.on_event(ChainEvent::Mempool(TxLabel::Cancel)),
+            None
+        );
+        assert_eq!(
+            detector.on_event(ChainEvent::Confirmed(TxLabel::Cancel, 110)),
+            Some(RecommendedAction::BroadcastRefund)
+        );
+        assert_eq!(detector.schedule().unwrap().punish_available_at(), 130);
+        assert_eq!(
+            detector.on_event(ChainEvent::Mempool(TxLabel::Refund)),
+            None
+        );
+    }
+
+    #[test]
+    fn bob_sees_buy_after_cancel_timelock() {
+        let mut detector = RaceDetector::new(SwapRole::Bob, 10u32, 20u32);
+        detector.on_event(ChainEvent::Confirmed(TxLabel::Lock, 100));
+        assert_eq!(
+            detector.on_event(ChainEvent::Height(115)),
+            Some(RecommendedAction::BroadcastCancelNow)
+        );
+        assert_eq!(
+            detector.on_event(ChainEvent::Mempool(TxLabel::Buy)),
+            Some(RecommendedAction::ExtractSecretFromBuy)
+        );
+        // Even if cancel wins the race the secret must be extracted
+        assert_eq!(
+            detector.on_event(ChainEvent::Confirmed(TxLabel::Cancel, 116)),
+            Some(RecommendedAction::ExtractSecretFromBuy)
+        );
+    }
+
+    #[test]
+    fn alice_punish_or_extract_from_refund() {
+        let mut detector = RaceDetector::new(SwapRole::Alice, 10u32, 20u32);
+        detector.on_event(ChainEvent::Confirmed(TxLabel::Lock, 100));
+        assert_eq!(
+            detector.on_event(ChainEvent::Height(109)),
+            Some(RecommendedAction::BroadcastCancelNow)
+        );
+        assert_eq!(
+            detector.on_event(ChainEvent::Confirmed(TxLabel::Cancel, 110)),
+            None
+        );
+        assert_eq!(detector.on_event(ChainEvent::Height(128)), None);
+        assert_eq!(
+            detector.on_event(ChainEvent::Height(129)),
+            Some(RecommendedAction::BroadcastPunish)
+        );
+
+        let mut punished = detector.clone();
+        assert_eq!(
+            punished.on_event(ChainEvent::Mempool(TxLabel::Punish)),
+            None
+        );
+
+        assert_eq!(
+            detector.on_event(ChainEvent::Mempool(TxLabel::Refund)),
+            Some(RecommendedAction::ExtractSecretFromRefund)
+        );
+    }
+
+    #[test]
+    fn reorg_invalidates_taken_action() {
+        let mut detector = RaceDetector::new(SwapRole::Alice, 10u32, 20u32);
+        detector.on_event(ChainEvent::Confirmed(TxLabel::Lock, 100));
+        detector.on_event(ChainEvent::Height(104));
+        assert_eq!(detector.depth(TxLabel::Lock), Some(5));
+        assert!(!detector.is_safe(SwapAction::LockAccordant));
+
+        detector.on_event(ChainEvent::Height(105));
+        assert!(detector.is_safe(SwapAction::LockAccordant));
+        detector.record_action(SwapAction::LockAccordant);
+        assert!(detector.unsafe_actions().is_empty());
+
+        detector.on_event(ChainEvent::Reorged {
+            tx: TxLabel::Lock,
+            new_depth: 2,
+        });
+        assert_eq!(detector.depth(TxLabel::Lock), Some(2));
+        assert_eq!(detector.unsafe_actions(), vec![SwapAction::LockAccordant]);
+
+        // Back in the mempool the cancel timelock does not run anymore
+        detector.on_event(ChainEvent::Height(120));
+        detector.on_event(ChainEvent::Reorged {
+            tx: TxLabel::Lock,
+            new_depth: 0,
+        });
+        assert_eq!(detector.recommended_action(), None);
+    }
+
+    #[test]
+    fn emit_lifecycle_events() {
+        let mut detector = RaceDetector::new(SwapRole::Bob, 10u32, 20u32);
+        detector.on_event(ChainEvent::Mempool(TxLabel::Lock));
+        detector.on_event(ChainEvent::Confirmed(TxLabel::Lock, 100));
+        detector.on_event(ChainEvent::Height(105));
+        assert_eq!(
+            detector.drain_events(),
+            vec![
+                SwapEvent::TxSeen(TxLabel::Lock),
+                SwapEvent::TxConfirmed {
+                    tx: TxLabel::Lock,
+                    height: 100
+                },
+                SwapEvent::TxFinal {
+                    tx: TxLabel::Lock,
+                    depth: 6
+                },
+            ]
+        );
+
+        detector.on_event(ChainEvent::Height(109));
+        detector.on_event(ChainEvent::Height(110));
+        assert_eq!(
+            detector.drain_events(),
+            vec![
+                SwapEvent::CancelBroadcastable { height: 109 },
+                SwapEvent::ActionRecommended(RecommendedAction::BroadcastCancelNow),
+            ]
+        );
+        assert!(detector.drain_events().is_empty());
+    }
+
+    #[test]
+    fn alice_buy_confirmed() {
+        let mut detector = RaceDetector::new(SwapRole::Alice, 10u32, 20u32);
+        detector.on_event(ChainEvent::Confirmed(TxLabel::Lock, 100));
+        assert_eq!(detector.on_event(ChainEvent::Mempool(TxLabel::Buy)), None);
+        assert_eq!(detector.on_event(ChainEvent::Height(112)), None);
+        detector.on_event(ChainEvent::Confirmed(TxLabel::Buy, 113));
+        assert_eq!(detector.on_event(ChainEvent::Height(200)), None);
+    }
+}
@@ -0,0 +1,467 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Swaps routed through an intermediary, e.g. a liquidity provider, between Bob and Alice.
+//!
+//! A [`Route`] is made of two arbitrating swaps, the legs: on the _Bob leg_ the intermediary
+//! plays Alice against Bob, on the _Alice leg_ the intermediary plays Bob against Alice. Only
+//! Alice locks accordant assets, to a spend key shared between Alice and Bob directly. The
+//! intermediary relays Alice's adaptor and spend keys on the Bob leg and Bob's on the Alice leg,
+//! so it never holds both secrets:
+//!
+//! - Alice buys on the Alice leg, revealing her secret, the intermediary uses it to buy on the
+//!   Bob leg and Bob recovers it from there to spend the accordant assets,
+//! - Bob refunds on the Bob leg, revealing his secret, the intermediary uses it to refund on the
+//!   Alice leg and Alice recovers it from there to get her accordant assets back.
+//!
+//! Each end participant commits to its relayed keys with a [`RouteCommitment`] before the legs
+//! are negotiated, and verifies with [`RouteReveal::verify_relayed`] that the keys used by the
+//! intermediary on its leg are the other end's keys. The legs timelocks must leave the
+//! intermediary time to act on one leg after the other, see [`RouteTimelocks`].
+
+use std::fmt;
+use std::io;
+
+use crate::blockchain::Timelock;
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::Commit;
+use crate::protocol::race::ChainEvent;
+use crate::protocol::{ArbitratingParameters, Parameters};
+use crate::role::SwapRole;
+use crate::swap::{self, SwapId};
+use crate::transaction::TxLabel;
+use crate::Error;
+
+/// The two legs of a swap routed through an intermediary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display("{bob_leg} -> {alice_leg}")]
+pub struct Route {
+    /// The swap between Bob and the intermediary playing Alice.
+    pub bob_leg: SwapId,
+    /// The swap between the intermediary playing Bob and Alice.
+    pub alice_leg: SwapId,
+}
+
+impl Route {
+    /// Create a route, both legs must be distinct swaps.
+    pub fn new(bob_leg: SwapId, alice_leg: SwapId) -> Result<Self, swap::Error> {
+        (bob_leg != alice_leg)
+            .then(|| Self { bob_leg, alice_leg })
+            .ok_or(swap::Error::InvalidRoute("The two legs are the same swap"))
+    }
+
+    /// Return the role played by the intermediary on the `swap_id` leg, if part of the route.
+    pub fn intermediary_role(&self, swap_id: SwapId) -> Option<SwapRole> {
+        match swap_id {
+            id if id == self.bob_leg => Some(SwapRole::Alice),
+            id if id == self.alice_leg => Some(SwapRole::Bob),
+            _ => None,
+        }
+    }
+
+    /// Return the leg an end participant is part of.
+    pub fn leg_of(&self, end: SwapRole) -> SwapId {
+        match end {
+            SwapRole::Alice => self.alice_leg,
+            SwapRole::Bob => self.bob_leg,
+        }
+    }
+}
+
+impl Encodable for Route {
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let len = self.bob_leg.consensus_encode(s)?;
+        Ok(len + self.alice_leg.consensus_encode(s)?)
+    }
+}
+
+impl Decodable for Route {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            bob_leg: Decodable::consensus_decode(d)?,
+            alice_leg: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(Route);
+
+/// The composed timelock constraints of a route, in blocks. With both locks confirmed in the
+/// same block:
+///
+/// - the Bob leg cancel must expire at least `min_delta` blocks after the Alice leg cancel, so the
+///   intermediary can buy on the Bob leg after Alice bought on the Alice leg,
+/// - the Alice leg punish must expire at least `min_delta` blocks after the Bob leg punish, so
+///   the intermediary can refund on the Alice leg after Bob refunded on the Bob leg.
+///
+/// The delta must cover the time for the intermediary to react and a difference in the
+/// confirmation height of the two locks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RouteTimelocks {
+    /// Minimum number of blocks between the deadlines of the two legs.
+    pub min_delta: u32,
+}
+
+impl Default for RouteTimelocks {
+    fn default() -> Self {
+        Self { min_delta: 6 }
+    }
+}
+
+impl RouteTimelocks {
+    /// Verify that the arbitrating parameters of the two legs satisfy the constraints.
+    pub fn verify<Amt, Ti, F>(
+        &self,
+        bob_leg: &ArbitratingParameters<Amt, Ti, F>,
+        alice_leg: &ArbitratingParameters<Amt, Ti, F>,
+    ) -> Result<(), swap::Error>
+    where
+        Ti: Timelock,
+    {
+        let deadlines = |params: &ArbitratingParameters<Amt, Ti, F>| {
+            let cancel = params.cancel_timelock.as_blocks();
+            (
+                cancel,
+                cancel.saturating_add(params.punish_timelock.as_blocks()),
+            )
+        };
+        let (bob_cancel, bob_punish) = deadlines(bob_leg);
+        let (alice_cancel, alice_punish) = deadlines(alice_leg);
+        (bob_cancel >= alice_cancel.saturating_add(self.min_delta))
+            .then(|| ())
+            .ok_or(swap::Error::InvalidRoute(
+                "Bob leg cancel expires too early after Alice leg cancel",
+            ))?;
+        (alice_punish >= bob_punish.saturating_add(self.min_delta))
+            .then(|| ())
+            .ok_or(swap::Error::InvalidRoute(
+                "Alice leg punish expires too early after Bob leg punish",
+            ))
+    }
+}
+
+/// Sent by an end participant, and relayed by the intermediary to the other end, to commit to
+/// the adaptor and spend keys the intermediary must relay on the other leg.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteCommitment<C> {
+    /// The route the commitment belongs to.
+    pub route: Route,
+    /// The end participant committing.
+    pub from: SwapRole,
+    /// Commitment to the adaptor public key.
+    pub adaptor: C,
+    /// Commitment to the spend public key.
+    pub spend: C,
+}
+
+impl<C> RouteCommitment<C>
+where
+    C: Eq + Clone,
+{
+    /// Verify the revealed keys against the commitment.
+    pub fn verify_with_reveal<Pk, Qk>(
+        &self,
+        wallet: &impl Commit<C>,
+        reveal: &RouteReveal<Pk, Qk>,
+    ) -> Result<(), Error>
+    where
+        Pk: CanonicalBytes,
+        Qk: CanonicalBytes,
+    {
+        (self.route == reveal.route && self.from == reveal.from)
+            .then(|| ())
+            .ok_or(swap::Error::InvalidRoute("Reveal of another route"))?;
+        wallet.validate(reveal.adaptor.as_canonical_bytes(), self.adaptor.clone())?;
+        wallet.validate(reveal.spend.as_canonical_bytes(), self.spend.clone())?;
+        Ok(())
+    }
+}
+
+impl<C> fmt::Display for RouteCommitment<C>
+where
+    C: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl<C> Encodable for RouteCommitment<C>
+where
+    C: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.route.consensus_encode(s)?;
+        len += self.from.consensus_encode(s)?;
+        len += self.adaptor.as_canonical_bytes().consensus_encode(s)?;
+        Ok(len + self.spend.as_canonical_bytes().consensus_encode(s)?)
+    }
+}
+
+impl<C> Decodable for RouteCommitment<C>
+where
+    C: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            route: Decodable::consensus_decode(d)?,
+            from: Decodable::consensus_decode(d)?,
+            adaptor: C::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            spend: C::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(RouteCommitment<C>, C: CanonicalBytes);
+
+/// Reveals the keys committed in a [`RouteCommitment`], relayed by the intermediary once both
+/// ends committed.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteReveal<Pk, Qk> {
+    /// The route the reveal belongs to.
+    pub route: Route,
+    /// The end participant revealing.
+    pub from: SwapRole,
+    /// The adaptor public key.
+    pub adaptor: Pk,
+    /// The spend public key.
+    pub spend: Qk,
+}
+
+impl<Pk, Qk> RouteReveal<Pk, Qk>
+where
+    Pk: PartialEq,
+    Qk: PartialEq,
+{
+    /// Verify that the parameters revealed by the intermediary on the leg of the receiving end
+    /// relay the adaptor and spend keys of the revealing end. Otherwise the intermediary would
+    /// hold the secrets of both legs.
+    pub fn verify_relayed<Rk, Sk, Addr, Ti, F, Pr>(
+        &self,
+        intermediary: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+    ) -> Result<(), swap::Error> {
+        (intermediary.adaptor == self.adaptor)
+            .then(|| ())
+            .ok_or(swap::Error::InvalidRoute("Adaptor key is not relayed"))?;
+        (intermediary.spend == self.spend)
+            .then(|| ())
+            .ok_or(swap::Error::InvalidRoute("Spend key is not relayed"))
+    }
+}
+
+impl<Pk, Qk> fmt::Display for RouteReveal<Pk, Qk>
+where
+    Pk: fmt::Debug,
+    Qk: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl<Pk, Qk> Encodable for RouteReveal<Pk, Qk>
+where
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.route.consensus_encode(s)?;
+        len += self.from.consensus_encode(s)?;
+        len += self.adaptor.as_canonical_bytes().consensus_encode(s)?;
+        Ok(len + self.spend.as_canonical_bytes().consensus_encode(s)?)
+    }
+}
+
+impl<Pk, Qk> Decodable for RouteReveal<Pk, Qk>
+where
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            route: Decodable::consensus_decode(d)?,
+            from: Decodable::consensus_decode(d)?,
+            adaptor: Pk::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            spend: Qk::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(RouteReveal<Pk, Qk>, Pk: CanonicalBytes, Qk: CanonicalBytes);
+
+/// Action the intermediary must take on one leg after observing the other leg.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum HopAction {
+    /// Alice bought on the Alice leg: extract her secret from the `buy` and buy on the Bob leg.
+    BuyBobLeg,
+    /// Bob cancelled the Bob leg: cancel the Alice leg to be able to refund it.
+    CancelAliceLeg,
+    /// Bob refunded on the Bob leg: extract his secret from the `refund` and refund on the Alice
+    /// leg.
+    RefundAliceLeg,
+}
+
+/// Follows the chain events of both legs for the intermediary and tells when an action on one
+/// leg is required by the other. Each leg must still be followed by its own
+/// [`RaceDetector`](crate::protocol::race::RaceDetector).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteMonitor {
+    route: Route,
+    done: Vec<HopAction>,
+}
+
+impl RouteMonitor {
+    /// Create a monitor for the route.
+    pub fn new(route: Route) -> Self {
+        Self {
+            route,
+            done: vec![],
+        }
+    }
+
+    /// Return the route followed.
+    pub fn route(&self) -> Route {
+        self.route
+    }
+
+    /// Feed an event of the `swap_id` leg, return the action required on the other leg, each
+    /// action is returned once.
+    pub fn on_event(&mut self, swap_id: SwapId, event: ChainEvent) -> Option<HopAction> {
+        let label = match event {
+            ChainEvent::Mempool(label) | ChainEvent::Confirmed(label, _) => label,
+            _ => return None,
+        };
+        let action = match (self.route.intermediary_role(swap_id)?, label) {
+            (SwapRole::Bob, TxLabel::Buy) => HopAction::BuyBobLeg,
+            (SwapRole::Alice, TxLabel::Cancel) => HopAction::CancelAliceLeg,
+            (SwapRole::Alice, TxLabel::Refund) => HopAction::RefundAliceLeg,
+            _ => return None,
+        };
+        if self.done.contains(&action) {
+            return None;
+        }
+        self.done.push(action);
+        Some(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bitcoin::fee::SatPerKvB;
+    use crate::bitcoin::timelock::CSVTimelock;
+    use crate::blockchain::FeeStrategy;
+    use crate::crypto::CommitmentEngine;
+    use crate::Uuid;
+
+    fn params(cancel: u32, punish: u32) -> ArbitratingParameters<u64, CSVTimelock, SatPerKvB> {
+        ArbitratingParameters {
+            arbitrating_amount: 100_000,
+            cancel_timelock: CSVTimelock::new(cancel),
+            punish_timelock: CSVTimelock::new(punish),
+            fee_strategy: FeeStrategy::Fixed(SatPerKvB::from_sat(1_000)),
+            fee_politics: None,
+            maker_role: SwapRole::Alice,
+        }
+    }
+
+    fn route() -> Route {
+        Route::new(SwapId::from(Uuid::new()), SwapId::from(Uuid::new())).unwrap()
+    }
+
+    #[test]
+    fn compose_leg_timelocks() {
+        let constraints = RouteTimelocks::default();
+        assert!(constraints.verify(&params(20, 10), &params(10, 26)).is_ok());
+        // The intermediary has no time to buy on the Bob leg
+        assert!(constraints
+            .verify(&params(15, 10), &params(10, 26))
+            .is_err());
+        // The intermediary has no time to refund on the Alice leg
+        assert!(constraints
+            .verify(&params(20, 10), &params(10, 25))
+            .is_err());
+
+        let id = SwapId::from(Uuid::new());
+        assert!(Route::new(id, id).is_err());
+    }
+
+    #[test]
+    fn commit_and_reveal_relayed_keys() {
+        let route = route();
+        let reveal = RouteReveal {
+            route,
+            from: SwapRole::Alice,
+            adaptor: String::from("adaptor"),
+            spend: String::from("spend"),
+        };
+        let engine = CommitmentEngine;
+        let commit = RouteCommitment {
+            route,
+            from: SwapRole::Alice,
+            adaptor: engine.commit_to(reveal.adaptor.as_canonical_bytes()),
+            spend: engine.commit_to(reveal.spend.as_canonical_bytes()),
+        };
+        assert!(commit.verify_with_reveal(&engine, &reveal).is_ok());
+        let other = RouteReveal {
+            spend: String::from("other spend"),
+            ..reveal.clone()
+        };
+        assert!(commit.verify_with_reveal(&engine, &other).is_err());
+
+        let decoded: RouteCommitment<_> =
+            consensus::deserialize(&consensus::serialize(&commit)).unwrap();
+        assert_eq!(decoded, commit);
+        let decoded: RouteReveal<String, String> =
+            consensus::deserialize(&consensus::serialize(&reveal)).unwrap();
+        assert_eq!(decoded, reveal);
+    }
+
+    #[test]
+    fn intermediary_actions() {
+        let route = route();
+        let mut monitor = RouteMonitor::new(route);
+        assert_eq!(
+            monitor.on_event(route.bob_leg, ChainEvent::Mempool(TxLabel::Buy)),
+            None
+        );
+        assert_eq!(
+            monitor.on_event(route.alice_leg, ChainEvent::Mempool(TxLabel::Buy)),
+            Some(HopAction::BuyBobLeg)
+        );
+        assert_eq!(
+            monitor.on_event(route.alice_leg, ChainEvent::Confirmed(TxLabel::Buy, 101)),
+            None
+        );
+        assert_eq!(
+            monitor.on_event(route.bob_leg, ChainEvent::Confirmed(TxLabel::Cancel, 120)),
+            Some(HopAction::CancelAliceLeg)
+        );
+        assert_eq!(
+            monitor.on_event(route.bob_leg, ChainEvent::Mempool(TxLabel::Refund)),
+            Some(HopAction::RefundAliceLeg)
+        );
+        assert_eq!(
+            monitor.on_event(
+                SwapId::from(Uuid::new()),
+                ChainEvent::Mempool(TxLabel::Refund)
+            ),
+            None
+        );
+    }
+}
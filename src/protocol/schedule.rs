@@ -0,0 +1,172 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Concrete deadlines of a swap computed from the negotiated timelocks. The cancel timelock is
+//! relative to the height the arbitrating lock transaction, the one funding the swap output, is
+//! mined at, and the punish timelock to the height the cancel transaction is mined at. A
+//! [`TimelockSchedule`] turns them into block heights, e.g. "the refund must be mined before
+//! height H" or "punish is available from height P", and tells with [`ScheduleStatus`] how many
+//! blocks are left and when continuing the swap has become unsafe.
+
+use crate::blockchain::Timelock;
+use crate::protocol::confirmation::ConfirmationPolicy;
+
+/// State of a swap at a given height with respect to its deadlines, returned by
+/// [`TimelockSchedule::status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum ScheduleStatus {
+    /// The swap can continue, `margin` blocks can still include the buy transaction before the
+    /// cancel timelock expires.
+    Safe { margin: u32 },
+    /// Fewer blocks than required to lock the accordant assets and mine the buy transaction are
+    /// left, `margin` blocks can still include it. The accordant assets must not be locked and the
+    /// buy signature must not be revealed anymore.
+    Unsafe { margin: u32 },
+    /// The cancel timelock expired, the cancel transaction can be mined.
+    Cancelable,
+    /// The cancel transaction is mined, `margin` blocks can still include the refund transaction
+    /// before the punish timelock expires.
+    Refundable { margin: u32 },
+    /// The punish timelock expired, the punish transaction can be mined.
+    Punishable,
+}
+
+/// Deadlines of a swap given the height its arbitrating lock is mined at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TimelockSchedule {
+    lock_height: u32,
+    cancel_height: Option<u32>,
+    cancel_timelock: u32,
+    punish_timelock: u32,
+}
+
+impl TimelockSchedule {
+    /// Create the schedule of a swap whose lock transaction is mined at `lock_height`, with the
+    /// negotiated timelocks converted to blocks, see [`Timelock::as_blocks`].
+    pub fn new<Ti>(lock_height: u32, cancel_timelock: Ti, punish_timelock: Ti) -> Self
+    where
+        Ti: Timelock,
+    {
+        Self {
+            lock_height,
+            cancel_height: None,
+            cancel_timelock: cancel_timelock.as_blocks(),
+            punish_timelock: punish_timelock.as_blocks(),
+        }
+    }
+
+    /// Set the height the cancel transaction is mined at.
+    pub fn with_cancel_height(mut self, height: u32) -> Self {
+        self.cancel_height = Some(height);
+        self
+    }
+
+    /// Return the height the lock transaction is mined at.
+    pub fn lock_height(&self) -> u32 {
+        self.lock_height
+    }
+
+    /// Return the height the cancel transaction is mined at, if known.
+    pub fn cancel_height(&self) -> Option<u32> {
+        self.cancel_height
+    }
+
+    /// The first height at which the cancel transaction can be mined. The buy transaction must be
+    /// mined before it.
+    pub fn cancel_available_at(&self) -> u32 {
+        self.lock_height.saturating_add(self.cancel_timelock)
+    }
+
+    /// The first height at which the punish transaction can be mined, the refund transaction must
+    /// be mined before it. Until the cancel transaction is mined this is the earliest possible
+    /// height, assuming cancel is mined as soon as available.
+    pub fn punish_available_at(&self) -> u32 {
+        self.cancel_height
+            .unwrap_or_else(|| self.cancel_available_at())
+            .saturating_add(self.punish_timelock)
+    }
+
+    /// Return the state of the swap when the chain tip is at `height`. The swap becomes unsafe as
+    /// soon as the accordant lock depth of the policy, converted in arbitrating blocks, plus the
+    /// block mining the buy transaction no longer fit before the cancel timelock expires.
+    pub fn status(&self, height: u32, policy: &ConfirmationPolicy) -> ScheduleStatus {
+        let next = height.saturating_add(1);
+        match self.cancel_height {
+            Some(_) if next >= self.punish_available_at() => ScheduleStatus::Punishable,
+            Some(_) => ScheduleStatus::Refundable {
+                margin: self.punish_available_at() - next,
+            },
+            None if next >= self.cancel_available_at() => ScheduleStatus::Cancelable,
+            None => {
+                let margin = self.cancel_available_at() - next;
+                if margin < policy.accordant_lock_blocks().saturating_add(1) {
+                    ScheduleStatus::Unsafe { margin }
+                } else {
+                    ScheduleStatus::Safe { margin }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bitcoin::timelock::CSVTimelock;
+
+    #[test]
+    fn deadlines_and_margins() {
+        let policy = ConfirmationPolicy::default();
+        let schedule = TimelockSchedule::new(100, CSVTimelock::new(10), CSVTimelock::new(8));
+        assert_eq!(schedule.cancel_available_at(), 110);
+        assert_eq!(schedule.punish_available_at(), 118);
+
+        assert_eq!(
+            schedule.status(100, &policy),
+            ScheduleStatus::Safe { margin: 9 }
+        );
+        // The accordant lock needs 2 Bitcoin blocks and the buy one more
+        assert_eq!(
+            schedule.status(106, &policy),
+            ScheduleStatus::Safe { margin: 3 }
+        );
+        assert_eq!(
+            schedule.status(107, &policy),
+            ScheduleStatus::Unsafe { margin: 2 }
+        );
+        assert_eq!(schedule.status(109, &policy), ScheduleStatus::Cancelable);
+
+        let schedule = schedule.with_cancel_height(112);
+        assert_eq!(schedule.punish_available_at(), 120);
+        assert_eq!(
+            schedule.status(112, &policy),
+            ScheduleStatus::Refundable { margin: 7 }
+        );
+        assert_eq!(schedule.status(119, &policy), ScheduleStatus::Punishable);
+    }
+
+    #[test]
+    fn time_based_deadlines_in_blocks() {
+        // A day and two hours, not the raw sequence with the BIP-68 type flag
+        let cancel = CSVTimelock::from_seconds(86_400).unwrap();
+        let punish = CSVTimelock::from_seconds(7_200).unwrap();
+        let schedule = TimelockSchedule::new(100, cancel, punish);
+        assert_eq!(schedule.cancel_available_at(), 244);
+        assert_eq!(schedule.with_cancel_height(250).punish_available_at(), 262);
+    }
+}
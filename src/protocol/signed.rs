@@ -0,0 +1,152 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Authentication of protocol messages with the session identity key of the sender. A
+//! [`SignedMessage`] wraps any protocol message with an ECDSA signature over its consensus
+//! serialization, so messages transported over untrusted relays cannot be tampered with or
+//! spoofed.
+//!
+//! The signed digest is a tagged SHA-256 hash, see [`crate::crypto::commitment::tagged_hash`],
+//! to prevent the signature from being valid in any other context.
+
+use std::io;
+
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey, Signing, Verification};
+
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::{self, commitment::tagged_hash};
+use crate::protocol::message::SwapMessage;
+use crate::swap::SwapId;
+
+/// Tag of the hash signed in [`SignedMessage`].
+pub const SIGNED_MESSAGE_TAG: &[u8] = b"farcaster/signed-message";
+
+/// A protocol message signed with the session identity key of its sender.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedMessage<T> {
+    /// The protocol message.
+    pub message: T,
+    /// The signature of the message with the sender's session identity key.
+    pub signature: Signature,
+}
+
+fn digest<T: Encodable>(message: &T) -> Message {
+    let hash = tagged_hash(SIGNED_MESSAGE_TAG, &consensus::serialize(message));
+    Message::from_slice(&hash[..]).expect("32 bytes hash")
+}
+
+impl<T> SignedMessage<T>
+where
+    T: Encodable,
+{
+    /// Sign the message with the session identity secret key.
+    pub fn sign<C: Signing>(secp: &Secp256k1<C>, message: T, secret_key: &SecretKey) -> Self {
+        let signature = secp.sign_ecdsa(&digest(&message), secret_key);
+        Self { message, signature }
+    }
+
+    /// Verify the signature against the peer's session identity public key. Fails with
+    /// [`crypto::Error::InvalidSignature`] if the message was not signed by the peer or was
+    /// modified.
+    pub fn verify<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        peer_pubkey: &PublicKey,
+    ) -> Result<(), crypto::Error> {
+        secp.verify_ecdsa(&digest(&self.message), &self.signature, peer_pubkey)
+            .map_err(|_| crypto::Error::InvalidSignature)
+    }
+
+    /// Verify the signature against the peer's session identity public key and return the
+    /// authenticated message.
+    pub fn into_verified<C: Verification>(
+        self,
+        secp: &Secp256k1<C>,
+        peer_pubkey: &PublicKey,
+    ) -> Result<T, crypto::Error> {
+        self.verify(secp, peer_pubkey)?;
+        Ok(self.message)
+    }
+}
+
+impl<T> SwapMessage for SignedMessage<T>
+where
+    T: SwapMessage,
+{
+    fn swap_id(&self) -> SwapId {
+        self.message.swap_id()
+    }
+}
+
+impl<T> Encodable for SignedMessage<T>
+where
+    T: Encodable,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let len = self.message.consensus_encode(s)?;
+        Ok(len + self.signature.as_canonical_bytes().consensus_encode(s)?)
+    }
+}
+
+impl<T> Decodable for SignedMessage<T>
+where
+    T: Decodable,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            message: Decodable::consensus_decode(d)?,
+            signature: Signature::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(SignedMessage<T>, T: Encodable + Decodable);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::message::Abort;
+    use crate::Uuid;
+
+    #[test]
+    fn sign_and_verify_message() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let peer = PublicKey::from_secret_key(&secp, &secret_key);
+        let abort = Abort {
+            swap_id: Uuid::random().into(),
+            error_body: Some("timeout".to_string()),
+        };
+
+        let signed = SignedMessage::sign(&secp, abort.clone(), &secret_key);
+        assert!(signed.verify(&secp, &peer).is_ok());
+        let bytes = consensus::serialize(&signed);
+        let decoded: SignedMessage<Abort> = consensus::deserialize(&bytes).unwrap();
+        let verified = decoded.into_verified(&secp, &peer).unwrap();
+        assert_eq!(verified.error_body, abort.error_body);
+
+        // Other key and tampered message are rejected
+        let other = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[2; 32]).unwrap());
+        assert!(signed.verify(&secp, &other).is_err());
+        let mut tampered = signed;
+        tampered.message.error_body = None;
+        assert!(matches!(
+            tampered.verify(&secp, &peer),
+            Err(crypto::Error::InvalidSignature)
+        ));
+    }
+}
@@ -139,11 +139,50 @@ impl FromStr for SwapRole {
 
 /// An accordant is the blockchain which does not need transaction inside the protocol nor
 /// timelocks: it is the blockchain with fewer requirements for an atomic swap.
+///
+/// Any asset controlled by a private key can play the accordant role, e.g. Monero or another UTXO
+/// coin used as the silent leg of the swap. The funds are locked on an address controlled by the
+/// sum of Alice's and Bob's spend keys and the blockchain must provide:
+///
+/// - the aggregation of the public spend keys, and of the private spend key shares once the
+///   counter-party share is recovered from the adaptor signatures on the arbitrating blockchain,
+/// - the derivation of the lock address from the aggregated keys and the shared secret keys, e.g.
+///   the Monero shared view key,
+/// - the validation of the destination addresses and the sweep of the lock address.
 pub trait Accordant<Pk, Sk, Addr> {
-    //: Asset + Address + Clone + Eq + Display + Debug
+    /// Everything needed to move the locked funds to the destination once the full spend key is
+    /// reconstructed, e.g. the keys to restore a wallet or a signed transaction.
+    type Sweep;
+
+    /// Aggregate Alice's and Bob's public spend keys into the public spend key of the lock
+    /// address.
+    fn aggregate_public_keys(alice: &Pk, bob: &Pk) -> Pk;
+
+    /// Reconstruct the private spend key of the lock address from a participant spend key share
+    /// and the share recovered from the counter-party.
+    fn aggregate_secret_keys(share: &Sk, recovered_share: &Sk) -> Sk;
+
     /// Derive the lock address for the accordant blockchain.
     fn derive_lock_address(
         network: Network,
         keys: AccordantKeySet<Pk, Sk>,
     ) -> Result<Addr, crypto::Error>;
+
+    /// Validate an address given by a participant to receive the accordant funds at the end of
+    /// the swap, e.g. when sweeping the lock address. By default any address is accepted.
+    fn validate_destination(_network: Network, _address: &Addr) -> Result<(), consensus::Error> {
+        Ok(())
+    }
+
+    /// Prepare the sweep of the lock address derived from `keys` to `destination` with the
+    /// participant spend key share and the share recovered from the counter-party. The lock is
+    /// searched from `restore_height`.
+    fn sweep(
+        network: Network,
+        keys: AccordantKeySet<Pk, Sk>,
+        share: Sk,
+        recovered_share: Sk,
+        destination: Addr,
+        restore_height: u64,
+    ) -> Result<Self::Sweep, crypto::Error>;
 }
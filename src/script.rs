@@ -85,14 +85,25 @@ where
 /// to create a lock and remove the double spending problem and create an unilateral punishment
 /// mechanisms in [`Cancelable`].
 ///
+/// With a `recovery` key the successful path requires Alice's signature and either Bob's or the
+/// recovery key signature, so if Bob loses his swap keys mid-protocol the refund can still be
+/// spent with the help of the backup service holding the recovery key. Alice's signature stays
+/// mandatory so she always learns Bob's adaptor secret from the refund seen on-chain.
+///
 /// [`Cancelable`]: crate::transaction::Cancelable
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct DataPunishableLock<Ti, Pk> {
     pub timelock: Ti,
     pub success: SwapRoleKeys<Pk>,
     pub failure: Pk,
+    #[serde(default)]
+    pub recovery: Option<Pk>,
 }
 
+/// Tag of the extra arbitrating key a participant reveals in its parameters to add a recovery key
+/// to the punishable lock, see [`DataPunishableLock`].
+pub const RECOVERY_KEY_TAG: u16 = 0x0100;
+
 impl<Ti, Pk> fmt::Display for DataPunishableLock<Ti, Pk>
 where
     Ti: fmt::Display,
@@ -103,7 +114,11 @@ where
             f,
             "Timelock: {}, Success: <{}>, Failure: {}",
             self.timelock, self.success, self.failure
-        )
+        )?;
+        if let Some(recovery) = &self.recovery {
+            write!(f, ", Recovery: {}", recovery)?;
+        }
+        Ok(())
     }
 }
 
@@ -21,13 +21,49 @@ use std::io;
 use std::str::FromStr;
 
 use strict_encoding::{StrictDecode, StrictEncode};
+use thiserror::Error;
 
-use crate::consensus::{self, Decodable, Encodable};
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::commitment;
+use crate::crypto::registry::{AlgorithmId, HashRegistry};
+use crate::protocol::message::{CommitAliceParameters, CommitBobParameters};
 use crate::trade::DealId;
 use crate::Uuid;
 
 pub mod btcxmr;
 
+/// Errors used when routing protocol messages to their swap.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The message belongs to another swap.
+    #[error("The message swap id {found} does not match the swap id {expected}")]
+    SwapIdMismatch {
+        /// The swap identifier of the running swap.
+        expected: SwapId,
+        /// The swap identifier of the message.
+        found: SwapId,
+    },
+    /// The route through an intermediary is not safe, see
+    /// [`route`](crate::protocol::route).
+    #[error("Invalid route: {0}")]
+    InvalidRoute(&'static str),
+    /// The swap cannot be aborted once the accordant lock has been seen, see
+    /// [`abort`](crate::protocol::abort).
+    #[error("The swap cannot be aborted after the accordant lock")]
+    AbortAfterAccordantLock,
+    /// The pre-signing ceremony of the arbitrating transactions is not followed, see
+    /// [`setup`](crate::bitcoin::segwitv0::setup).
+    #[error("Invalid arbitrating setup: {0}")]
+    InvalidSetup(&'static str),
+    /// The recovery key of the punishable lock is not agreed upon by both participants, see
+    /// [`agreed_recovery_key`](crate::protocol::agreed_recovery_key).
+    #[error("Invalid recovery key: {0}")]
+    InvalidRecoveryKey(&'static str),
+    /// None of the final transactions, buy, refund or punish, is confirmed yet.
+    #[error("The swap is not completed")]
+    NotCompleted,
+}
+
 /// The identifier of a swap. This is a wrapper around [`Uuid`] that can be constructed from
 /// [`DealId`].
 #[derive(
@@ -49,6 +85,69 @@ pub mod btcxmr;
 #[display(inner)]
 pub struct SwapId(pub Uuid);
 
+impl SwapId {
+    /// Tag of the hash used to derive swap identifiers.
+    pub const DERIVATION_TAG: &'static [u8] = b"farcaster/swap-id";
+
+    /// Derive the canonical swap identifier from the public deal and Alice's and Bob's commit
+    /// messages. Both parties derive the same identifier, which is bound to the deal and to the
+    /// two participants of the swap, so messages from another swap are detected even when the
+    /// same deal is taken multiple times.
+    ///
+    /// Only the commitments are hashed, not the swap identifier of the commit messages which
+    /// carry the identifier of the deal, see [`CommitAliceParameters::commitments`]. All the
+    /// messages following the commit messages carry the derived identifier.
+    ///
+    /// The identifier is the first 16 bytes of a tagged SHA-256 hash formatted as a version 8
+    /// (custom) UUID.
+    pub fn derive<D, C>(
+        deal: &D,
+        alice: &CommitAliceParameters<C>,
+        bob: &CommitBobParameters<C>,
+    ) -> Self
+    where
+        D: Encodable,
+        C: CanonicalBytes,
+    {
+        Self::derive_with(
+            &HashRegistry::new(),
+            AlgorithmId::TAGGED_SHA256,
+            deal,
+            alice,
+            bob,
+        )
+        .expect("built-in algorithm")
+    }
+
+    /// Derive the swap identifier as in [`SwapId::derive`] with an algorithm of the registry.
+    /// Fails with [`commitment::Error::UnknownAlgorithm`] if the algorithm is not registered.
+    pub fn derive_with<D, C>(
+        registry: &HashRegistry,
+        algorithm: AlgorithmId,
+        deal: &D,
+        alice: &CommitAliceParameters<C>,
+        bob: &CommitBobParameters<C>,
+    ) -> Result<Self, commitment::Error>
+    where
+        D: Encodable,
+        C: CanonicalBytes,
+    {
+        let mut preimage = consensus::serialize(deal);
+        preimage.extend(consensus::serialize(&alice.commitments()));
+        preimage.extend(consensus::serialize(&bob.commitments()));
+        let hash = registry
+            .digest(algorithm, Self::DERIVATION_TAG, &preimage)?
+            .hash;
+
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&hash[..16]);
+        // Version 8 and RFC 4122 variant
+        bytes[6] = (bytes[6] & 0x0f) | 0x80;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Ok(Self::from(uuid::Uuid::from_bytes(bytes)))
+    }
+}
+
 impl From<Uuid> for SwapId {
     fn from(u: Uuid) -> Self {
         SwapId(u)
@@ -67,6 +166,12 @@ impl From<DealId> for SwapId {
     }
 }
 
+impl std::fmt::LowerHex for SwapId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
 impl FromStr for SwapId {
     type Err = uuid::Error;
 
@@ -86,3 +191,73 @@ impl Decodable for SwapId {
         Ok(Self(Decodable::consensus_decode(d)?))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeccakCommitment;
+
+    fn commit_alice(swap_id: SwapId, byte: u8) -> CommitAliceParameters<KeccakCommitment> {
+        let c = KeccakCommitment::new([byte; 32]);
+        CommitAliceParameters {
+            swap_id,
+            buy: c,
+            cancel: c,
+            refund: c,
+            punish: c,
+            adaptor: c,
+            extra_arbitrating_keys: vec![],
+            arbitrating_shared_keys: vec![],
+            spend: c,
+            extra_accordant_keys: vec![],
+            accordant_shared_keys: vec![],
+        }
+    }
+
+    fn commit_bob(swap_id: SwapId, byte: u8) -> CommitBobParameters<KeccakCommitment> {
+        let c = KeccakCommitment::new([byte; 32]);
+        CommitBobParameters {
+            swap_id,
+            buy: c,
+            cancel: c,
+            refund: c,
+            adaptor: c,
+            extra_arbitrating_keys: vec![],
+            arbitrating_shared_keys: vec![],
+            spend: c,
+            extra_accordant_keys: vec![],
+            accordant_shared_keys: vec![],
+        }
+    }
+
+    #[test]
+    fn derive_swap_id() {
+        let deal = Uuid::default();
+        let deal_id = SwapId::from(deal);
+        let alice = commit_alice(deal_id, 1);
+        let bob = commit_bob(deal_id, 2);
+
+        let swap_id = SwapId::derive(&deal, &alice, &bob);
+        assert_eq!(swap_id, SwapId::derive(&deal, &alice, &bob));
+        assert_eq!(swap_id.0 .0.get_version_num(), 8);
+        assert_eq!(swap_id, SwapId::from_str(&swap_id.to_string()).unwrap());
+
+        // Bound to the deal and the commitments of the participants
+        assert_ne!(
+            swap_id,
+            SwapId::derive(&deal, &commit_alice(deal_id, 2), &bob)
+        );
+        assert_ne!(
+            swap_id,
+            SwapId::derive(&deal, &alice, &commit_bob(deal_id, 1))
+        );
+        assert_ne!(swap_id, SwapId::derive(&Uuid::random(), &alice, &bob));
+
+        // Not bound to the identifier carried by the commit messages
+        let other = SwapId::from(Uuid::random());
+        assert_eq!(
+            swap_id,
+            SwapId::derive(&deal, &commit_alice(other, 1), &commit_bob(other, 2))
+        );
+    }
+}
@@ -17,54 +17,72 @@
 //! Concrete implementation of a swap between Bitcoin as the arbitrating blockchain and Monero as the
 //! accordant blockchain.
 
+#[cfg(feature = "experimental")]
+use crate::bitcoin::segwitv0::funding::{AddressUse, FundingAddresses};
+#[cfg(feature = "experimental")]
+use crate::bitcoin::segwitv0::{witness_signatures, BuyTx, FundingTx};
 use crate::bitcoin::{fee::SatPerKvB, timelock::CSVTimelock, BitcoinSegwitV0};
+#[cfg(feature = "experimental")]
+use crate::blockchain::{AccordantAmount, Network};
+#[cfg(feature = "experimental")]
+use crate::consensus::CanonicalBytes;
 use crate::consensus::{self, Decodable, Encodable};
+#[cfg(feature = "experimental")]
+use crate::crypto::cache::{CacheKey, VerificationKind};
 use crate::crypto::{
     self,
+    cache::VerificationCache,
+    context::CryptoContext,
     slip10::{ChildNumber, DerivationPath, Ed25519ExtSecretKey, Secp256k1ExtSecretKey},
     AccordantKeyId, ArbitratingKeyId, GenerateKey, GenerateSharedKey, ProveCrossGroupDleq,
-    SharedKeyId,
+    SecretData, SharedKeyId,
 };
 #[cfg(feature = "experimental")]
 use crate::crypto::{EncSign, RecoverSecret, Sign};
+use crate::instrument::Instrumentation;
+#[cfg(feature = "experimental")]
+use crate::instrument::Step;
 use crate::monero::Monero;
 use crate::protocol;
 use crate::trade;
+#[cfg(feature = "experimental")]
+use crate::transaction::Fundable;
 use crate::{blockchain::Blockchain, crypto::dleq::DLEQProof};
 
 use monero::cryptonote::hash::Hash;
 
+#[cfg(feature = "experimental")]
+use crate::crypto::context::{NonceGen, Transcript};
 #[cfg(feature = "experimental")]
 use ecdsa_fun::{
-    adaptor::{Adaptor, HashTranscript},
+    adaptor::Adaptor,
     fun::{Point, Scalar},
-    nonce, ECDSA,
 };
 // FIXME: when secp256kfun as new crates.io release
 #[cfg(feature = "experimental")]
-use rand::rngs::ThreadRng;
-#[cfg(feature = "experimental")]
-use rand_chacha::ChaCha20Rng;
-#[cfg(feature = "experimental")]
 use secp256kfun::marker::*;
-#[cfg(feature = "experimental")]
-use sha2::Sha256;
 
-use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::secp256k1::{PublicKey, SecretKey};
 use bitcoin::util::psbt::PartiallySignedTransaction;
 #[cfg(feature = "experimental")]
 use bitcoin::{hashes::sha256d::Hash as Sha256dHash, secp256k1::Message};
 
 use std::collections::HashMap;
+use std::fmt;
 use std::str::FromStr;
 
 pub mod message;
-
-#[cfg(feature = "experimental")]
-type Transcript = HashTranscript<Sha256, ChaCha20Rng>;
-
-#[cfg(feature = "experimental")]
-type NonceGen = nonce::Synthetic<Sha256, nonce::GlobalRng<ThreadRng>>;
+pub mod parameters;
+pub mod quote;
+pub mod receipt;
+#[cfg(feature = "jsonrpc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "jsonrpc")))]
+pub mod rpc;
+#[cfg(feature = "vectors")]
+#[cfg_attr(docsrs, doc(cfg(feature = "vectors")))]
+pub mod vectors;
+
+pub use parameters::{ParameterViolation, SwapParameters};
 
 /// Fully defined type for Bitcoin-Monero atomic swap sets of parameters.
 pub type Parameters = protocol::Parameters<
@@ -88,10 +106,30 @@ impl_strict_encoding!(Bob);
 
 /// Fully defined type for Bitcoin-Monero atomic swap trade.
 pub type DealParameters =
-    trade::DealParameters<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB>;
+    trade::DealParameters<bitcoin::Amount, AccordantAmount, CSVTimelock, SatPerKvB>;
 
 /// Fully defined type for Bitcoin-Monero atomic swap public trade.
-pub type Deal = trade::Deal<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB>;
+pub type Deal = trade::Deal<bitcoin::Amount, AccordantAmount, CSVTimelock, SatPerKvB>;
+
+/// Accept the `deal` for the `accordant_amount` picked by the taker, given the current bitcoin
+/// block `height` and UNIX `timestamp` in seconds, and return the parameters of the swap. The
+/// arbitrating amount is scaled to keep the deal price, rounded down to the satoshi, see
+/// [`Quotation`](quote::Quotation). See [`trade::Deal::validate_acceptance`] for the errors.
+pub fn accept_deal(
+    deal: &Deal,
+    accordant_amount: AccordantAmount,
+    height: u32,
+    timestamp: u64,
+) -> Result<DealParameters, trade::Error> {
+    deal.validate_acceptance(&accordant_amount, height, timestamp)?;
+    let mut parameters = deal.parameters.clone();
+    if let Ok(rate) = quote::Rate::from_deal(&parameters) {
+        parameters.arbitrating_amount = quote::Quotation::new(rate, quote::Rounding::Down)
+            .to_arbitrating(accordant_amount.into())?;
+    }
+    parameters.accordant_amount = accordant_amount;
+    Ok(parameters)
+}
 
 /// Fully defined type for Bitcoin-Monero atomic swap arbitrating parameters.
 pub type ArbitratingParameters =
@@ -188,7 +226,9 @@ impl Derivation for SharedKeyId {
 
 /// Manager responsible for handling key operations (secret and public). Implements traits for
 /// handling [`GenerateKey`], [`GenerateSharedKey`] and [`Sign`].
-#[derive(Clone, Debug)]
+///
+/// Derived keys are wiped from memory when the key manager is dropped and are never displayed.
+#[derive(Clone)]
 pub struct KeyManager {
     /// The swap identifier used in the derivation.
     swap_index: ChildNumber,
@@ -197,9 +237,23 @@ pub struct KeyManager {
     /// The ed25519 account key as derived from swap_index.
     monero_account_key: Ed25519ExtSecretKey,
     /// A list of already derived keys for secp256k1 by derivation path.
-    bitcoin_derivations: HashMap<DerivationPath, SecretKey>,
+    bitcoin_derivations: HashMap<DerivationPath, SecretData<[u8; 32]>>,
     /// A list of already derived monero keys for ed25519 by derivation path.
-    monero_derivations: HashMap<DerivationPath, monero::PrivateKey>,
+    monero_derivations: HashMap<DerivationPath, SecretData<[u8; 32]>>,
+    /// The cryptographic contexts used for all key operations.
+    ctx: CryptoContext,
+    /// The counter-party proofs and adaptor signatures already verified.
+    cache: VerificationCache,
+    /// Reports the signing and proof operations.
+    instrumentation: Instrumentation,
+}
+
+impl fmt::Debug for KeyManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeyManager")
+            .field("swap_index", &self.swap_index)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Encodable for KeyManager {
@@ -222,6 +276,9 @@ impl Decodable for KeyManager {
             monero_account_key,
             bitcoin_derivations: HashMap::new(),
             monero_derivations: HashMap::new(),
+            ctx: CryptoContext::new(),
+            cache: VerificationCache::new(),
+            instrumentation: Instrumentation::new(),
         })
     }
 }
@@ -249,19 +306,19 @@ impl KeyManager {
         self.bitcoin_derivations
             .get(&path)
             // Option<Result<SecretKey, _>>
-            .map(|key| Ok(*key))
+            .map(|key| SecretKey::from_slice(key.expose_secret()).map_err(crypto::Error::new))
             // Some(Ok(_)) => Ok(_)
             // None => || { ... } => Result<SecretKey, crypto::Error>
-            .unwrap_or_else(|| {
-                let secp = Secp256k1::new();
-                match self.bitcoin_account_key.derive_priv(&secp, &path) {
+            .unwrap_or_else(
+                || match self.bitcoin_account_key.derive_priv(self.ctx.secp(), &path) {
                     Ok(key) => {
-                        self.bitcoin_derivations.insert(path, key.secret_key);
+                        self.bitcoin_derivations
+                            .insert(path, key.secret_key.secret_bytes().into());
                         Ok(key.secret_key)
                     }
                     Err(e) => Err(e.into()),
-                }
-            })
+                },
+            )
     }
 
     /// Try to retreive the secret key internally if already generated, if the key is not found
@@ -274,18 +331,22 @@ impl KeyManager {
         self.monero_derivations
             .get(&path)
             // Option<Result<PrivateKey, _>>
-            .map(|key| Ok(*key))
+            .map(|key| {
+                monero::PrivateKey::from_slice(key.expose_secret()).map_err(crypto::Error::new)
+            })
             // Some(Ok(_)) => Ok(_)
             // None => || { ... } => Result<PrivateKey, crypto::Error>
             .unwrap_or_else(|| {
-                let key_seed = self
-                    .monero_account_key
-                    .derive_priv(&path)
-                    .expect("Path does not contain non-hardened derivation")
-                    .secret_key;
-                let secret_key = Hash::from_slice(&key_seed).as_scalar();
-
-                self.monero_derivations.insert(path, secret_key);
+                let key_seed = SecretData::new(
+                    self.monero_account_key
+                        .derive_priv(&path)
+                        .expect("Path does not contain non-hardened derivation")
+                        .secret_key,
+                );
+                let secret_key = Hash::from_slice(key_seed.expose_secret()).as_scalar();
+
+                self.monero_derivations
+                    .insert(path, secret_key.to_bytes().into());
                 Ok(secret_key)
             })
     }
@@ -293,23 +354,35 @@ impl KeyManager {
     /// Get the monero accordant spend secret key. The key is derived from the master seed like all
     /// other keys but clamped to only 252 bits.
     pub fn get_or_derive_monero_spend_key(&mut self) -> Result<monero::PrivateKey, crypto::Error> {
-        let mut little_endian_bytes = self
-            .get_or_derive_monero_key(AccordantKeyId::Spend)?
-            .to_bytes();
-        little_endian_bytes[31] &= CLAMPING_TO_252_BITS_MASK;
-        Ok(monero::PrivateKey::from_slice(little_endian_bytes.as_ref())
-            .expect("Valid canonical bytes"))
+        let mut little_endian_bytes = SecretData::new(
+            self.get_or_derive_monero_key(AccordantKeyId::Spend)?
+                .to_bytes(),
+        );
+        little_endian_bytes.expose_secret_mut()[31] &= CLAMPING_TO_252_BITS_MASK;
+        Ok(
+            monero::PrivateKey::from_slice(little_endian_bytes.expose_secret())
+                .expect("Valid canonical bytes"),
+        )
     }
 
     /// Create a new key manager with the provided master seed, returns an error if the swap index is
     /// not within `[0, 2^31 - 1]`.
     pub fn new(seed: [u8; 32], swap_index: u32) -> Result<Self, crypto::Error> {
+        Self::with_context(seed, swap_index, CryptoContext::new())
+    }
+
+    /// Create a new key manager reusing already created cryptographic contexts, see
+    /// [`KeyManager::new`].
+    pub fn with_context(
+        seed: [u8; 32],
+        swap_index: u32,
+        ctx: CryptoContext,
+    ) -> Result<Self, crypto::Error> {
         let swap_index = ChildNumber::from_hardened_idx(swap_index).map_err(crypto::Error::new)?;
-        let secp = Secp256k1::new();
         Ok(Self {
             swap_index,
             bitcoin_account_key: Secp256k1ExtSecretKey::new_master(seed.as_ref()).derive_priv(
-                &secp,
+                ctx.secp(),
                 &Self::get_account_derivation_path(Blockchain::Bitcoin, swap_index)?,
             )?,
             monero_account_key: Ed25519ExtSecretKey::new_master(seed.as_ref()).derive_priv(
@@ -317,8 +390,66 @@ impl KeyManager {
             )?,
             bitcoin_derivations: HashMap::new(),
             monero_derivations: HashMap::new(),
+            ctx,
+            cache: VerificationCache::new(),
+            instrumentation: Instrumentation::new(),
         })
     }
+
+    /// The cryptographic contexts used by the key manager.
+    pub fn context(&self) -> &CryptoContext {
+        &self.ctx
+    }
+
+    /// Reuse the verifications of a previous run, e.g. a cache persisted with the swap state
+    /// before a restart.
+    pub fn with_verification_cache(mut self, cache: VerificationCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// The cache of the DLEQ proofs and adaptor signatures already verified. Entries can be
+    /// invalidated, e.g. when the counter-party sends new parameters.
+    pub fn verification_cache(&self) -> &VerificationCache {
+        &self.cache
+    }
+
+    /// Report the signing, proof generation and verification operations of the key manager.
+    pub fn with_instrumentation(mut self, instrumentation: Instrumentation) -> Self {
+        self.instrumentation = instrumentation;
+        self
+    }
+
+    /// Return the funding transaction paying to the [`ArbitratingKeyId::Lock`] key of the swap
+    /// index.
+    #[cfg(feature = "experimental")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+    pub fn funding(&mut self, network: Network) -> crate::Res<FundingTx> {
+        let pubkey = self.get_pubkey(ArbitratingKeyId::Lock)?;
+        Ok(FundingTx::initialize(pubkey, network)?)
+    }
+
+    /// Create the key manager of the first swap index, starting at `swap_index`, whose funding
+    /// address was never handed out in `addresses`, so each swap attempt is funded on a fresh
+    /// address. Fails if no swap index is left.
+    #[cfg(feature = "experimental")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+    pub fn with_fresh_funding(
+        seed: [u8; 32],
+        swap_index: u32,
+        network: Network,
+        addresses: &FundingAddresses,
+    ) -> crate::Res<Self> {
+        let mut swap_index = swap_index;
+        loop {
+            let mut key_manager = Self::new(seed, swap_index)?;
+            let address = key_manager.funding(network)?.get_address()?;
+            if addresses.check(&address) == AddressUse::Fresh {
+                return Ok(key_manager);
+            }
+            swap_index += 1;
+        }
+    }
 }
 
 impl GenerateKey<monero::PublicKey, AccordantKeyId> for KeyManager {
@@ -340,10 +471,9 @@ impl GenerateSharedKey<monero::PrivateKey> for KeyManager {
 
 impl GenerateKey<PublicKey, ArbitratingKeyId> for KeyManager {
     fn get_pubkey(&mut self, key_id: ArbitratingKeyId) -> Result<PublicKey, crypto::Error> {
-        let secp = Secp256k1::new();
         let secret_key = self.get_or_derive_bitcoin_key(key_id)?;
 
-        Ok(PublicKey::from_secret_key(&secp, &secret_key))
+        Ok(PublicKey::from_secret_key(self.ctx.secp(), &secret_key))
     }
 }
 
@@ -361,28 +491,32 @@ impl Sign<PublicKey, Sha256dHash, Signature> for KeyManager {
         key: ArbitratingKeyId,
         msg: Sha256dHash,
     ) -> Result<Signature, crypto::Error> {
-        let secret_key = self.get_or_derive_bitcoin_key(key)?;
+        let instrumentation = self.instrumentation.clone();
+        instrumentation.record(Step::Sign, || {
+            let secret_key = self.get_or_derive_bitcoin_key(key)?;
+
+            // FIXME: when new version is released on crates.io
+            // let secret_key = Scalar::from(secret_key);
+            let secret_key = Scalar::from_slice(&secret_key[..])
+                .unwrap()
+                .mark::<NonZero>()
+                .expect("SecretKey is never zero");
+            let message_hash: &[u8; 32] = {
+                use bitcoin::hashes::Hash;
+                msg.as_inner()
+            };
 
-        // FIXME: when new version is released on crates.io
-        // let secret_key = Scalar::from(secret_key);
-        let secret_key = Scalar::from_slice(&secret_key[..])
-            .unwrap()
-            .mark::<NonZero>()
-            .expect("SecretKey is never zero");
-        let message_hash: &[u8; 32] = {
-            use bitcoin::hashes::Hash;
-            msg.as_inner()
-        };
-
-        let nonce_gen = nonce::Synthetic::<Sha256, nonce::GlobalRng<ThreadRng>>::default();
-        let ecdsa = ECDSA::new(nonce_gen);
-
-        // FIXME
-        // Ok(ecdsa.sign(&secret_key, message_hash).into())
-        Ok(
-            Signature::from_compact(ecdsa.sign(&secret_key, message_hash).to_bytes().as_ref())
-                .unwrap(),
-        )
+            // FIXME
+            // Ok(ecdsa.sign(&secret_key, message_hash).into())
+            Ok(Signature::from_compact(
+                self.ctx
+                    .ecdsa()
+                    .sign(&secret_key, message_hash)
+                    .to_bytes()
+                    .as_ref(),
+            )
+            .unwrap())
+        })
     }
 
     fn verify_signature(
@@ -391,9 +525,10 @@ impl Sign<PublicKey, Sha256dHash, Signature> for KeyManager {
         msg: Sha256dHash,
         sig: &Signature,
     ) -> Result<(), crypto::Error> {
-        let secp = Secp256k1::new();
         let message = Message::from_slice(&msg).expect("Hash is always ok");
-        secp.verify_ecdsa(&message, sig, key)
+        self.ctx
+            .secp()
+            .verify_ecdsa(&message, sig, key)
             .map_err(crypto::Error::new)
     }
 }
@@ -407,24 +542,27 @@ impl EncSign<PublicKey, Sha256dHash, Signature, EncryptedSignature> for KeyManag
         encryption_key: &PublicKey,
         msg: Sha256dHash,
     ) -> Result<EncryptedSignature, crypto::Error> {
-        let secret_key = self.get_or_derive_bitcoin_key(signing_key)?;
-
-        let engine = Adaptor::<Transcript, NonceGen>::default();
-        // FIXME
-        // let secret_signing_key = Scalar::from(secret_key);
-        let secret_signing_key = Scalar::from_slice(&secret_key[..])
-            .unwrap()
-            .mark::<NonZero>()
-            .expect("SecretKey is never zero");
-        // FIXME
-        // let encryption_key = Point::from(*encryption_key);
-        let encryption_key = Point::from_bytes(encryption_key.serialize()).unwrap();
-        let message_hash: &[u8; 32] = {
-            use bitcoin::hashes::Hash;
-            msg.as_inner()
-        };
+        let instrumentation = self.instrumentation.clone();
+        instrumentation.record(Step::EncryptSign, || {
+            let secret_key = self.get_or_derive_bitcoin_key(signing_key)?;
 
-        Ok(engine.encrypted_sign(&secret_signing_key, &encryption_key, message_hash))
+            let engine = self.ctx.adaptor();
+            // FIXME
+            // let secret_signing_key = Scalar::from(secret_key);
+            let secret_signing_key = Scalar::from_slice(&secret_key[..])
+                .unwrap()
+                .mark::<NonZero>()
+                .expect("SecretKey is never zero");
+            // FIXME
+            // let encryption_key = Point::from(*encryption_key);
+            let encryption_key = Point::from_bytes(encryption_key.serialize()).unwrap();
+            let message_hash: &[u8; 32] = {
+                use bitcoin::hashes::Hash;
+                msg.as_inner()
+            };
+
+            Ok(engine.encrypted_sign(&secret_signing_key, &encryption_key, message_hash))
+        })
     }
 
     fn verify_encrypted_signature(
@@ -434,26 +572,41 @@ impl EncSign<PublicKey, Sha256dHash, Signature, EncryptedSignature> for KeyManag
         msg: Sha256dHash,
         sig: &EncryptedSignature,
     ) -> Result<(), crypto::Error> {
-        let engine = Adaptor::<Transcript, NonceGen>::default();
-        // FIXME
-        // let verification_key = Point::from(*signing_key);
-        // let encryption_key = Point::from(*encryption_key);
-        let verification_key = Point::from_bytes(signing_key.serialize()).unwrap();
-        let encryption_key = Point::from_bytes(encryption_key.serialize()).unwrap();
-        let message_hash: &[u8; 32] = {
-            use bitcoin::hashes::Hash;
-            msg.as_inner()
+        let key = CacheKey::new(
+            VerificationKind::EncryptedSignature,
+            &[
+                &signing_key.serialize(),
+                &encryption_key.serialize(),
+                &msg[..],
+                &sig.as_canonical_bytes(),
+            ],
+        );
+        let verify = || {
+            let engine = self.ctx.adaptor();
+            // FIXME
+            // let verification_key = Point::from(*signing_key);
+            // let encryption_key = Point::from(*encryption_key);
+            let verification_key = Point::from_bytes(signing_key.serialize()).unwrap();
+            let encryption_key = Point::from_bytes(encryption_key.serialize()).unwrap();
+            let message_hash: &[u8; 32] = {
+                use bitcoin::hashes::Hash;
+                msg.as_inner()
+            };
+
+            match engine.verify_encrypted_signature(
+                &verification_key,
+                &encryption_key,
+                message_hash,
+                sig,
+            ) {
+                true => Ok(()),
+                false => Err(crypto::Error::InvalidEncryptedSignature),
+            }
         };
-
-        match engine.verify_encrypted_signature(
-            &verification_key,
-            &encryption_key,
-            message_hash,
-            sig,
-        ) {
-            true => Ok(()),
-            false => Err(crypto::Error::InvalidEncryptedSignature),
-        }
+        self.instrumentation
+            .record(Step::VerifyEncryptedSignature, || {
+                self.cache.verify(key, verify)
+            })
     }
 
     fn decrypt_signature(
@@ -470,7 +623,7 @@ impl EncSign<PublicKey, Sha256dHash, Signature, EncryptedSignature> for KeyManag
         let secret_key = SecretKey::from_slice(little_endian_secret_bytes.as_ref())
             .map_err(crypto::Error::new)?;
 
-        let adaptor = Adaptor::<Transcript, NonceGen>::default();
+        let adaptor = self.ctx.adaptor();
         // FIXME
         // let decryption_key = Scalar::from(secret_key);
         let decryption_key = Scalar::from_slice(&secret_key[..])
@@ -498,23 +651,101 @@ impl RecoverSecret<PublicKey, SecretKey, Signature, EncryptedSignature> for KeyM
         encrypted_sig: EncryptedSignature,
         encryption_key: &PublicKey,
         sig: Signature,
-    ) -> SecretKey {
-        let adaptor = Adaptor::<Transcript, NonceGen>::default();
-        // FIXME
-        // let encryption_key = Point::from(*encryption_key);
-        //let signature = ecdsa_fun::Signature::from(sig);
-        let encryption_key = Point::from_bytes(encryption_key.serialize()).unwrap();
-        let signature = ecdsa_fun::Signature::from_bytes(sig.serialize_compact()).unwrap();
+    ) -> Result<SecretKey, crypto::Error> {
+        recover_secret(sig, &encrypted_sig, encryption_key)
+    }
+}
 
-        match adaptor.recover_decryption_key(&encryption_key, &signature, &encrypted_sig) {
-            // FIXME
-            // Some(decryption_key) => decryption_key.into(),
-            Some(decryption_key) => {
-                SecretKey::from_slice(decryption_key.to_bytes().as_ref()).unwrap()
-            }
-            None => panic!("signature is not the decryption of our original encrypted signature"),
-        }
+/// Recover the secret key used to encrypt `encrypted_sig` given its decrypted version
+/// `full_sig` and the `encryption_key`. Fails with [`crypto::Error::InvalidEncryptedSignature`]
+/// if `full_sig` is not the decryption of `encrypted_sig`.
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub fn recover_secret(
+    full_sig: Signature,
+    encrypted_sig: &EncryptedSignature,
+    encryption_key: &PublicKey,
+) -> Result<SecretKey, crypto::Error> {
+    let adaptor = Adaptor::<Transcript, NonceGen>::default();
+    let encryption_key =
+        Point::from_bytes(encryption_key.serialize()).ok_or(crypto::Error::InvalidAdaptorKey)?;
+    let signature = ecdsa_fun::Signature::from_bytes(full_sig.serialize_compact())
+        .ok_or(crypto::Error::InvalidSignature)?;
+
+    let decryption_key = adaptor
+        .recover_decryption_key(&encryption_key, &signature, encrypted_sig)
+        .ok_or(crypto::Error::InvalidEncryptedSignature)?;
+    let bytes = SecretData::new(decryption_key.to_bytes());
+    SecretKey::from_slice(bytes.expose_secret()).map_err(crypto::Error::new)
+}
+
+/// Convert a secret recovered from an adaptor signature into the Monero spend key share it
+/// represents. The DLEQ proof exchanged during the swap guarantees both are the same scalar.
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub fn accordant_key_share(secret: &SecretKey) -> Result<monero::PrivateKey, crypto::Error> {
+    let mut bytes = SecretData::new(secret.secret_bytes());
+    bytes.expose_secret_mut().reverse();
+    monero::PrivateKey::from_slice(bytes.expose_secret()).map_err(crypto::Error::new)
+}
+
+/// Recover Alice's Monero spend key share from a buy transaction seen on-chain and the buy
+/// adaptor signature encrypted with Alice's adaptor key. If the witness does not follow the buy
+/// template, e.g. the signatures are swapped or an extra element is pushed, the whole witness is
+/// searched for the decryption of `encrypted_sig`, see [`recover_secret_from_witness`].
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub fn recover_secret_from_buy(
+    buy_tx: &bitcoin::Transaction,
+    encrypted_sig: &EncryptedSignature,
+    encryption_key: &PublicKey,
+) -> Result<monero::PrivateKey, crate::Error> {
+    let secret = BuyTx::extract_witness_signature(buy_tx)
+        .ok()
+        .and_then(|full_sig| recover_secret(full_sig, encrypted_sig, encryption_key).ok());
+    let secret = match secret {
+        Some(secret) => secret,
+        None => recover_secret_from_witness(buy_tx, encrypted_sig, encryption_key)?,
+    };
+    Ok(accordant_key_share(&secret)?)
+}
+
+/// Recover Bob's Monero spend key share from a refund transaction seen on-chain and the refund
+/// adaptor signature encrypted with Bob's adaptor key. The whole witness is searched, see
+/// [`recover_secret_from_witness`], so a non-standard witness from the counterparty does not
+/// prevent the recovery.
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub fn recover_secret_from_refund(
+    refund_tx: &bitcoin::Transaction,
+    encrypted_sig: &EncryptedSignature,
+    encryption_key: &PublicKey,
+) -> Result<monero::PrivateKey, crate::Error> {
+    let secret = recover_secret_from_witness(refund_tx, encrypted_sig, encryption_key)?;
+    Ok(accordant_key_share(&secret)?)
+}
+
+/// Recover the secret key used to encrypt `encrypted_sig` from any signature in the witness of
+/// `tx`, regardless of its position in the stack. Only the decryption of `encrypted_sig` yields
+/// the secret, the other signatures are skipped. Fails with [`FError::MissingWitness`] if the
+/// witness holds no signature and [`crypto::Error::InvalidEncryptedSignature`] if none matches.
+///
+/// [`FError::MissingWitness`]: crate::transaction::Error::MissingWitness
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub fn recover_secret_from_witness(
+    tx: &bitcoin::Transaction,
+    encrypted_sig: &EncryptedSignature,
+    encryption_key: &PublicKey,
+) -> Result<SecretKey, crate::Error> {
+    let signatures = witness_signatures(tx);
+    if signatures.is_empty() {
+        return Err(crate::transaction::Error::MissingWitness.into());
     }
+    signatures
+        .into_iter()
+        .find_map(|full_sig| recover_secret(full_sig, encrypted_sig, encryption_key).ok())
+        .ok_or_else(|| crypto::Error::InvalidEncryptedSignature.into())
 }
 
 #[cfg(feature = "experimental")]
@@ -523,23 +754,30 @@ impl ProveCrossGroupDleq<PublicKey, monero::PublicKey, DLEQProof> for KeyManager
     fn generate_proof(
         &mut self,
     ) -> Result<(monero::PublicKey, PublicKey, DLEQProof), crypto::Error> {
-        let spend = self.get_pubkey(AccordantKeyId::Spend)?;
-        let encryption_key = self.get_encryption_key()?;
+        let instrumentation = self.instrumentation.clone();
+        instrumentation.record(Step::GenerateProof, || {
+            let spend = self.get_pubkey(AccordantKeyId::Spend)?;
+            let encryption_key = self.get_encryption_key()?;
 
-        let x = self.get_or_derive_monero_spend_key()?.to_bytes();
-        let proof = crypto::dleq::DLEQProof::generate(x);
+            let x = SecretData::new(self.get_or_derive_monero_spend_key()?.to_bytes());
+            let mut rng = self.ctx.ecdsa().nonce_gen.proof_rng(x.expose_secret());
+            let proof = crypto::dleq::DLEQProof::generate_with(*x.expose_secret(), &mut rng);
 
-        Ok((spend, encryption_key, proof))
+            Ok((spend, encryption_key, proof))
+        })
     }
 
     fn get_encryption_key(&mut self) -> Result<PublicKey, crypto::Error> {
-        let secp = Secp256k1::new();
         let secret = self.get_or_derive_monero_spend_key()?;
-        let mut little_endian_secret_bytes = secret.to_bytes();
-        little_endian_secret_bytes.reverse();
+        let mut little_endian_secret_bytes = SecretData::new(secret.to_bytes());
+        little_endian_secret_bytes.expose_secret_mut().reverse();
         let encryption_secret_key =
-            SecretKey::from_slice(&little_endian_secret_bytes).map_err(crypto::Error::new)?;
-        Ok(PublicKey::from_secret_key(&secp, &encryption_secret_key))
+            SecretKey::from_slice(little_endian_secret_bytes.expose_secret())
+                .map_err(crypto::Error::new)?;
+        Ok(PublicKey::from_secret_key(
+            self.ctx.secp(),
+            &encryption_secret_key,
+        ))
     }
 
     fn verify_proof(
@@ -548,18 +786,92 @@ impl ProveCrossGroupDleq<PublicKey, monero::PublicKey, DLEQProof> for KeyManager
         encryption_key: &PublicKey,
         proof: DLEQProof,
     ) -> Result<(), crypto::Error> {
-        proof.verify(
-            public_spend
-                .point
-                .decompress()
-                .expect("Valid point to decompress"),
-            // FIXME
-            //ecdsa_fun::fun::Point::from(*encryption_key),
-            Point::from_bytes(encryption_key.serialize()).unwrap(),
-        )
+        let key = CacheKey::new(
+            VerificationKind::DleqProof,
+            &[
+                public_spend.as_bytes(),
+                &encryption_key.serialize(),
+                &proof.as_canonical_bytes(),
+            ],
+        );
+        self.instrumentation.record(Step::VerifyProof, || {
+            self.cache.verify(key, || {
+                proof.verify(
+                    public_spend
+                        .point
+                        .decompress()
+                        .expect("Valid point to decompress"),
+                    // FIXME
+                    //ecdsa_fun::fun::Point::from(*encryption_key),
+                    Point::from_bytes(encryption_key.serialize()).unwrap(),
+                )
+            })
+        })
     }
 }
 
+#[test]
+fn test_accept_deal_within_bounds() {
+    use crate::blockchain::FeePolitics;
+    use crate::trade::{DealExpiry, DealTerms};
+
+    let hex = "46435357415001004450e567b1106f429247bb680e5fe0c80200000080800000800800a08601000\
+               00000000800c80000000000000004000a00000004000a0000000108001400000000000000022100\
+               03b31a0a70343bb46f3db3768296ac5027f9873921b37f852860c690063ff9e4c90000000000000\
+               0000000000000000000000000000000000000000000000000000000260700";
+    let deal: Deal = consensus::deserialize(&hex::decode(hex).unwrap()).unwrap();
+    let terms = DealTerms {
+        min_accordant_amount: Some(AccordantAmount::from_atomic_units(50)),
+        expiry: Some(DealExpiry::Height(800_000)),
+        fee_politics: Some(FeePolitics::Split),
+    };
+    let deal = deal
+        .parameters
+        .to_v2(deal.node_id, deal.peer_address, terms);
+    let decoded: Deal = consensus::deserialize(&consensus::serialize(&deal)).unwrap();
+    assert_eq!(decoded, deal);
+
+    // Half the maximum amount for half the bitcoins
+    let params = accept_deal(&deal, AccordantAmount::from_atomic_units(100), 799_999, 0).unwrap();
+    assert_eq!(
+        params.accordant_amount,
+        AccordantAmount::from_atomic_units(100)
+    );
+    assert_eq!(params.arbitrating_amount, bitcoin::Amount::from_sat(50_000));
+
+    assert!(matches!(
+        accept_deal(&deal, AccordantAmount::from_atomic_units(49), 0, 0),
+        Err(trade::Error::AmountOutOfRange)
+    ));
+    assert!(matches!(
+        accept_deal(&deal, AccordantAmount::from_atomic_units(201), 0, 0),
+        Err(trade::Error::AmountOutOfRange)
+    ));
+    assert!(matches!(
+        accept_deal(&deal, AccordantAmount::from_atomic_units(100), 800_000, 0),
+        Err(trade::Error::Expired)
+    ));
+}
+
+#[test]
+fn test_keymanager_hides_secrets() {
+    let mut key_manager = KeyManager::new([0; 32], 1).unwrap();
+    let key = key_manager
+        .get_or_derive_bitcoin_key(ArbitratingKeyId::Lock)
+        .unwrap();
+    // Derived keys are cached
+    assert_eq!(
+        key_manager
+            .get_or_derive_bitcoin_key(ArbitratingKeyId::Lock)
+            .unwrap(),
+        key
+    );
+    let debug = format!("{:?}", key_manager);
+    assert!(!debug.contains(&hex::encode(key.secret_bytes())));
+    assert!(!debug.contains(&hex::encode(key_manager.monero_account_key.secret_key)));
+    assert!(!format!("{:?}", SecretData::new([7u8; 32])).contains('7'));
+}
+
 #[test]
 fn test_keymanager_consensus_encoding() {
     let key_manager = KeyManager::new([0; 32], 1).unwrap();
@@ -609,3 +921,117 @@ fn test_keymanager_restore_monero_key() {
         .unwrap();
     assert_eq!(orig_key, restored_key);
 }
+
+#[cfg(feature = "experimental")]
+#[test]
+fn test_keymanager_shared_context() {
+    let ctx = CryptoContext::new();
+    let mut alice = KeyManager::with_context([1; 32], 1, ctx.clone()).unwrap();
+    let bob = KeyManager::with_context([2; 32], 1, ctx).unwrap();
+    let msg = Sha256dHash::default();
+
+    let sig = alice.sign(ArbitratingKeyId::Lock, msg).unwrap();
+    let pubkey = alice.get_pubkey(ArbitratingKeyId::Lock).unwrap();
+    assert!(bob.verify_signature(&pubkey, msg, &sig).is_ok());
+    assert_eq!(
+        pubkey,
+        KeyManager::new([1; 32], 1)
+            .unwrap()
+            .get_pubkey(ArbitratingKeyId::Lock)
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_keymanager_caches_verifications() {
+    let mut alice = KeyManager::new([1; 32], 1).unwrap();
+    let mut bob = KeyManager::new([2; 32], 1).unwrap();
+    let msg = Sha256dHash::default();
+
+    let (spend, encryption_key, proof) = alice.generate_proof().unwrap();
+    assert!(bob.verify_proof(&spend, &encryption_key, proof).is_ok());
+    let pubkey = alice.get_pubkey(ArbitratingKeyId::Lock).unwrap();
+    let sig = alice
+        .encrypt_sign(ArbitratingKeyId::Lock, &encryption_key, msg)
+        .unwrap();
+    assert!(bob
+        .verify_encrypted_signature(&pubkey, &encryption_key, msg, &sig)
+        .is_ok());
+    assert_eq!(bob.verification_cache().len(), 2);
+
+    // Failed verifications are not cached
+    assert!(bob
+        .verify_encrypted_signature(&encryption_key, &pubkey, msg, &sig)
+        .is_err());
+    assert_eq!(bob.verification_cache().len(), 2);
+
+    // The cache survives a restart
+    let cache = consensus::deserialize(&consensus::serialize(bob.verification_cache())).unwrap();
+    let restored = KeyManager::new([2; 32], 1)
+        .unwrap()
+        .with_verification_cache(cache);
+    assert_eq!(restored.verification_cache(), bob.verification_cache());
+    bob.verification_cache().clear();
+    assert!(bob.verification_cache().is_empty());
+}
+
+#[cfg(feature = "experimental")]
+#[test]
+fn rotate_used_funding_addresses() {
+    use crate::swap::SwapId;
+
+    let seed = [1; 32];
+    let mut addresses = FundingAddresses::new();
+    let first_swap = SwapId::from(crate::Uuid::random());
+    let mut key_manager =
+        KeyManager::with_fresh_funding(seed, 0, Network::Local, &addresses).unwrap();
+    let funding = key_manager.funding(Network::Local).unwrap();
+    let address = addresses.register(first_swap, &funding).unwrap();
+    assert_eq!(addresses.check(&address), AddressUse::Used(first_swap));
+    // Registering the same swap again is allowed
+    assert!(addresses.register(first_swap, &funding).is_ok());
+
+    // Another swap attempt on the same index would reuse the address
+    let second_swap = SwapId::from(crate::Uuid::random());
+    assert!(matches!(
+        addresses.register(second_swap, &funding),
+        Err(crate::transaction::Error::AddressReuse(swap_id)) if swap_id == first_swap
+    ));
+
+    // A fresh address is derived from the next index
+    let mut key_manager =
+        KeyManager::with_fresh_funding(seed, 0, Network::Local, &addresses).unwrap();
+    let fresh = addresses
+        .register(second_swap, &key_manager.funding(Network::Local).unwrap())
+        .unwrap();
+    assert_ne!(fresh, address);
+    assert_eq!(addresses.len(), 2);
+
+    // Funding detection attributes transactions to their swap
+    let tx = |script_pubkeys: Vec<bitcoin::Script>| bitcoin::Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![],
+        output: script_pubkeys
+            .into_iter()
+            .map(|script_pubkey| bitcoin::TxOut {
+                value: 1000,
+                script_pubkey,
+            })
+            .collect(),
+    };
+    assert_eq!(
+        addresses
+            .funded_swap(&tx(vec![fresh.script_pubkey()]))
+            .unwrap(),
+        Some(second_swap)
+    );
+    assert!(addresses
+        .funded_swap(&tx(vec![address.script_pubkey(), fresh.script_pubkey()]))
+        .is_err());
+    assert_eq!(addresses.funded_swap(&tx(vec![])).unwrap(), None);
+
+    let decoded: FundingAddresses =
+        consensus::deserialize(&consensus::serialize(&addresses)).unwrap();
+    assert_eq!(decoded, addresses);
+}
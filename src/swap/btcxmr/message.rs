@@ -29,6 +29,10 @@ use ecdsa_fun::adaptor::EncryptedSignature;
 pub type BuyProcedureSignature =
     message::BuyProcedureSignature<PartiallySignedTransaction, EncryptedSignature>;
 
+pub type CollaborativeCancelProposal =
+    message::CollaborativeCancelProposal<PartiallySignedTransaction, Signature>;
+pub type CollaborativeCancelSignature = message::CollaborativeCancelSignature<EncryptedSignature>;
+
 pub type CommitAliceParameters = message::CommitAliceParameters<KeccakCommitment>;
 pub type CommitBobParameters = message::CommitBobParameters<KeccakCommitment>;
 
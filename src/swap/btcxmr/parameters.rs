@@ -0,0 +1,408 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Negotiated values of a Bitcoin-Monero swap and their validation. [`SwapParameters::validate`]
+//! checks the invariants spanning multiple fields and reports all the violations at once, so a
+//! user interface can show everything that must be fixed in a deal.
+
+use std::io;
+
+use bitcoin::hashes::Hash;
+use bitcoin::{Address, Script, WScriptHash};
+use thiserror::Error;
+
+use crate::bitcoin::address::{validate_destination, DestinationError};
+use crate::bitcoin::fee::SatPerKvB;
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::blockchain::{AccordantAmount, FeeStrategy, Network};
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::protocol::confirmation::{ConfirmationPolicy, ConfirmationViolation};
+use crate::swap::btcxmr::DealParameters;
+
+/// Violation of a swap parameters invariant, returned by [`SwapParameters::validate`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParameterViolation {
+    /// The cancel timelock is not strictly lower than the punish timelock.
+    #[error("Cancel timelock {cancel} must be lower than punish timelock {punish}")]
+    TimelockOrder {
+        cancel: CSVTimelock,
+        punish: CSVTimelock,
+    },
+    /// A timelock is zero, the transaction would be valid immediately.
+    #[error("Timelocks must be greater than zero")]
+    ZeroTimelock,
+    /// A timelock sets bits other than the BIP-68 type flag and value, e.g. the disable flag.
+    #[error("Invalid timelock {0}")]
+    InvalidTimelock(CSVTimelock),
+    /// The arbitrating amount would create a dust output.
+    #[error("Arbitrating amount {amount} is below the dust limit {dust}")]
+    ArbitratingAmountBelowDust {
+        amount: bitcoin::Amount,
+        dust: bitcoin::Amount,
+    },
+    /// The arbitrating amount exceeds the total supply of bitcoins.
+    #[error("Arbitrating amount {0} is above the maximum supply")]
+    ArbitratingAmountAboveCap(bitcoin::Amount),
+    /// The accordant amount is zero.
+    #[error("Accordant amount must be greater than zero")]
+    ZeroAccordantAmount,
+    /// The accordant amount exceeds the maximum amount of Monero, see [`crate::monero::MAX_AMOUNT`].
+    #[error("Accordant amount {0} is above the maximum amount")]
+    AccordantAmountAboveCap(AccordantAmount),
+    /// The fee strategy has a zero or inverted rate.
+    #[error("Invalid fee strategy")]
+    InvalidFeeStrategy,
+    /// The destination address is not valid for the swap network.
+    #[error("Destination address {0} does not match the swap network")]
+    DestinationNetworkMismatch(Address),
+    /// The refund address is not valid for the swap network.
+    #[error("Refund address {0} does not match the swap network")]
+    RefundNetworkMismatch(Address),
+    /// The destination address is not P2WPKH, P2WSH, or P2TR.
+    #[error("Destination address {0} is not of a supported type")]
+    UnsupportedDestinationType(Address),
+    /// The refund address is not P2WPKH, P2WSH, or P2TR.
+    #[error("Refund address {0} is not of a supported type")]
+    UnsupportedRefundType(Address),
+}
+
+/// All the values negotiated for a Bitcoin-Monero swap. Unlike the deal, it includes the
+/// addresses provided by the participant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapParameters {
+    /// The network the swap is executed on.
+    pub network: Network,
+    /// Amount of bitcoins locked in the swap.
+    pub arbitrating_amount: bitcoin::Amount,
+    /// Amount of moneroj locked in the swap.
+    pub accordant_amount: AccordantAmount,
+    /// The cancel timelock, relative to the lock confirmation.
+    pub cancel_timelock: CSVTimelock,
+    /// The punish timelock, relative to the cancel confirmation.
+    pub punish_timelock: CSVTimelock,
+    /// The fee strategy of the arbitrating transactions.
+    pub fee_strategy: FeeStrategy<SatPerKvB>,
+    /// The address receiving the bitcoins if the swap succeeds or is punished, P2WPKH, P2WSH, or
+    /// P2TR.
+    pub destination_address: Address,
+    /// The address receiving the bitcoins if the swap is refunded, P2WPKH, P2WSH, or P2TR.
+    pub refund_address: Address,
+}
+
+impl Encodable for SwapParameters {
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.network.consensus_encode(s)?;
+        len += self
+            .arbitrating_amount
+            .as_canonical_bytes()
+            .consensus_encode(s)?;
+        len += self
+            .accordant_amount
+            .as_canonical_bytes()
+            .consensus_encode(s)?;
+        len += self
+            .cancel_timelock
+            .as_canonical_bytes()
+            .consensus_encode(s)?;
+        len += self
+            .punish_timelock
+            .as_canonical_bytes()
+            .consensus_encode(s)?;
+        len += self.fee_strategy.consensus_encode(s)?;
+        len += self
+            .destination_address
+            .as_canonical_bytes()
+            .consensus_encode(s)?;
+        Ok(len
+            + self
+                .refund_address
+                .as_canonical_bytes()
+                .consensus_encode(s)?)
+    }
+}
+
+impl Decodable for SwapParameters {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            network: Decodable::consensus_decode(d)?,
+            arbitrating_amount: bitcoin::Amount::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            accordant_amount: AccordantAmount::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            cancel_timelock: CSVTimelock::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            punish_timelock: CSVTimelock::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            fee_strategy: Decodable::consensus_decode(d)?,
+            destination_address: Address::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            refund_address: Address::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(SwapParameters);
+
+impl SwapParameters {
+    /// Create the swap parameters from the deal parameters and the participant addresses.
+    pub fn from_deal(
+        deal: &DealParameters,
+        destination_address: Address,
+        refund_address: Address,
+    ) -> Self {
+        Self {
+            network: deal.network,
+            arbitrating_amount: deal.arbitrating_amount,
+            accordant_amount: deal.accordant_amount,
+            cancel_timelock: deal.cancel_timelock,
+            punish_timelock: deal.punish_timelock,
+            fee_strategy: deal.fee_strategy,
+            destination_address,
+            refund_address,
+        }
+    }
+
+    /// Validate the invariants between the parameters. Returns all the violations found, not only
+    /// the first one.
+    pub fn validate(&self) -> Result<(), Vec<ParameterViolation>> {
+        let mut violations = vec![];
+
+        if self.cancel_timelock.is_zero() || self.punish_timelock.is_zero() {
+            violations.push(ParameterViolation::ZeroTimelock);
+        }
+        for timelock in [self.cancel_timelock, self.punish_timelock] {
+            if !timelock.is_valid() {
+                violations.push(ParameterViolation::InvalidTimelock(timelock));
+            }
+        }
+        // Time-based timelocks are compared in blocks
+        if self.cancel_timelock.as_blocks() >= self.punish_timelock.as_blocks() {
+            violations.push(ParameterViolation::TimelockOrder {
+                cancel: self.cancel_timelock,
+                punish: self.punish_timelock,
+            });
+        }
+
+        let dust = self.dust_limit();
+        if self.arbitrating_amount <= dust {
+            violations.push(ParameterViolation::ArbitratingAmountBelowDust {
+                amount: self.arbitrating_amount,
+                dust,
+            });
+        }
+        if self.arbitrating_amount > bitcoin::Amount::MAX_MONEY {
+            violations.push(ParameterViolation::ArbitratingAmountAboveCap(
+                self.arbitrating_amount,
+            ));
+        }
+        if self.accordant_amount == AccordantAmount::ZERO {
+            violations.push(ParameterViolation::ZeroAccordantAmount);
+        }
+        if self.accordant_amount > crate::monero::MAX_AMOUNT {
+            violations.push(ParameterViolation::AccordantAmountAboveCap(
+                self.accordant_amount,
+            ));
+        }
+
+        let valid_fee = match self.fee_strategy {
+            FeeStrategy::Fixed(rate) => rate.as_sat() > 0,
+            #[cfg(feature = "fee_range")]
+            FeeStrategy::Range { min_inc, max_inc } => min_inc.as_sat() > 0 && min_inc <= max_inc,
+        };
+        if !valid_fee {
+            violations.push(ParameterViolation::InvalidFeeStrategy);
+        }
+
+        let network = bitcoin::Network::from(self.network);
+        match validate_destination(&self.destination_address, network) {
+            Ok(()) => (),
+            Err(DestinationError::NetworkMismatch { address, .. }) => {
+                violations.push(ParameterViolation::DestinationNetworkMismatch(address))
+            }
+            Err(DestinationError::UnsupportedType(address)) => {
+                violations.push(ParameterViolation::UnsupportedDestinationType(address))
+            }
+        }
+        match validate_destination(&self.refund_address, network) {
+            Ok(()) => (),
+            Err(DestinationError::NetworkMismatch { address, .. }) => {
+                violations.push(ParameterViolation::RefundNetworkMismatch(address))
+            }
+            Err(DestinationError::UnsupportedType(address)) => {
+                violations.push(ParameterViolation::UnsupportedRefundType(address))
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Validate the timelocks against the confirmations expected on both chains, see
+    /// [`ConfirmationPolicy::validate`]. Time-based timelocks are converted in blocks with
+    /// [`CSVTimelock::as_blocks`].
+    pub fn validate_confirmations(
+        &self,
+        policy: &ConfirmationPolicy,
+    ) -> Result<(), Vec<ConfirmationViolation>> {
+        policy.validate(
+            self.cancel_timelock.as_blocks(),
+            self.punish_timelock.as_blocks(),
+        )
+    }
+
+    /// The highest dust limit among the outputs created with the arbitrating amount: the lock
+    /// output and the outputs paying the destination and refund addresses.
+    fn dust_limit(&self) -> bitcoin::Amount {
+        let lock = Script::new_v0_p2wsh(&WScriptHash::from_inner([0; 32]));
+        [
+            lock.dust_value(),
+            self.destination_address.script_pubkey().dust_value(),
+            self.refund_address.script_pubkey().dust_value(),
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(bitcoin::Amount::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    fn address(network: bitcoin::Network) -> Address {
+        let key = PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1; 32]).unwrap(),
+        );
+        Address::p2wpkh(&bitcoin::PublicKey::new(key), network).unwrap()
+    }
+
+    fn parameters() -> SwapParameters {
+        SwapParameters {
+            network: Network::Testnet,
+            arbitrating_amount: bitcoin::Amount::from_sat(100_000),
+            accordant_amount: AccordantAmount::from_atomic_units(1_000_000),
+            cancel_timelock: CSVTimelock::new(10),
+            punish_timelock: CSVTimelock::new(20),
+            fee_strategy: FeeStrategy::Fixed(SatPerKvB::from_sat(1000)),
+            destination_address: address(bitcoin::Network::Testnet),
+            refund_address: address(bitcoin::Network::Testnet),
+        }
+    }
+
+    #[test]
+    fn validate_swap_parameters() {
+        assert!(parameters().validate().is_ok());
+
+        let mut params = parameters();
+        params.cancel_timelock = CSVTimelock::new(20);
+        params.arbitrating_amount = bitcoin::Amount::from_sat(100);
+        params.refund_address = address(bitcoin::Network::Bitcoin);
+        let violations = params.validate().unwrap_err();
+        assert_eq!(violations.len(), 3);
+        assert!(matches!(
+            violations[0],
+            ParameterViolation::TimelockOrder { .. }
+        ));
+        assert!(matches!(
+            violations[1],
+            ParameterViolation::ArbitratingAmountBelowDust { .. }
+        ));
+        assert!(matches!(
+            violations[2],
+            ParameterViolation::RefundNetworkMismatch(_)
+        ));
+
+        let mut params = parameters();
+        params.arbitrating_amount = bitcoin::Amount::MAX_MONEY + bitcoin::Amount::from_sat(1);
+        params.accordant_amount = AccordantAmount::ZERO;
+        params.fee_strategy = FeeStrategy::Fixed(SatPerKvB::from_sat(0));
+        assert_eq!(
+            params.validate().unwrap_err(),
+            vec![
+                ParameterViolation::ArbitratingAmountAboveCap(params.arbitrating_amount),
+                ParameterViolation::ZeroAccordantAmount,
+                ParameterViolation::InvalidFeeStrategy,
+            ]
+        );
+
+        let mut params = parameters();
+        params.accordant_amount = crate::monero::MAX_AMOUNT;
+        assert!(params.validate().is_ok());
+        params.accordant_amount = crate::monero::MAX_AMOUNT
+            .checked_add(AccordantAmount::from_atomic_units(1))
+            .unwrap();
+        assert_eq!(
+            params.validate().unwrap_err(),
+            vec![ParameterViolation::AccordantAmountAboveCap(
+                params.accordant_amount
+            )]
+        );
+
+        let key = PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1; 32]).unwrap(),
+        );
+        let mut params = parameters();
+        params.destination_address = Address::p2tr(
+            &Secp256k1::new(),
+            key.into(),
+            None,
+            bitcoin::Network::Testnet,
+        );
+        assert!(params.validate().is_ok());
+        params.refund_address =
+            Address::p2pkh(&bitcoin::PublicKey::new(key), bitcoin::Network::Testnet);
+        assert_eq!(
+            params.validate().unwrap_err(),
+            vec![ParameterViolation::UnsupportedRefundType(
+                params.refund_address.clone()
+            )]
+        );
+    }
+
+    #[test]
+    fn validate_time_based_timelocks() {
+        use crate::blockchain::Blockchain;
+
+        // Four hours then a day, about 24 and 144 blocks
+        let mut params = parameters();
+        params.cancel_timelock = CSVTimelock::from_seconds(4 * 3600).unwrap();
+        params.punish_timelock = CSVTimelock::from_seconds(24 * 3600).unwrap();
+        assert!(params.validate().is_ok());
+        let policy = ConfirmationPolicy::default();
+        assert!(params.validate_confirmations(&policy).is_ok());
+
+        // Mixed units are compared in blocks
+        params.punish_timelock = CSVTimelock::from_blocks(20);
+        assert!(matches!(
+            params.validate().unwrap_err()[..],
+            [ParameterViolation::TimelockOrder { .. }]
+        ));
+
+        // Too short for the confirmations expected on Monero
+        params.cancel_timelock = CSVTimelock::from_seconds(3600).unwrap();
+        params.punish_timelock = CSVTimelock::from_seconds(24 * 3600).unwrap();
+        let policy = ConfirmationPolicy::conservative(Blockchain::Bitcoin, Blockchain::Monero);
+        assert!(params.validate_confirmations(&policy).is_err());
+
+        params.cancel_timelock = CSVTimelock::new(CSVTimelock::disable() | 10);
+        assert!(params
+            .validate()
+            .unwrap_err()
+            .contains(&ParameterViolation::InvalidTimelock(params.cancel_timelock)));
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Conversion between arbitrating and accordant amounts at an agreed price. Bitcoin amounts are
+//! counted in satoshis, 8 decimals, and Monero amounts in piconeros, 12 decimals. A [`Rate`] is
+//! kept as the exact ratio between two amounts, e.g. the amounts of a deal, and a [`Quotation`]
+//! converts amounts at that rate with an explicit [`Rounding`] policy, so both parties derive the
+//! same amounts from the same deal.
+//!
+//! Conversions are computed on 128 bits integers and fail with [`Error::AmountOverflow`] if the
+//! result does not fit in an amount.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::swap::btcxmr::DealParameters;
+use crate::trade::Error;
+
+/// Rounding policy applied when a converted amount falls between two units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rounding {
+    /// Round toward zero, the default.
+    Down,
+    /// Round away from zero.
+    Up,
+    /// Round to the nearest unit, half units away from zero.
+    Nearest,
+}
+
+impl Default for Rounding {
+    fn default() -> Self {
+        Self::Down
+    }
+}
+
+/// An exchange rate between satoshis and piconeros, defined as `arbitrating` satoshis for
+/// `accordant` piconeros.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rate {
+    arbitrating: bitcoin::Amount,
+    accordant: monero::Amount,
+}
+
+impl Rate {
+    /// Create the rate of `arbitrating` for `accordant`. Fails with [`Error::InvalidRate`] if one
+    /// of the amounts is zero.
+    pub fn new(arbitrating: bitcoin::Amount, accordant: monero::Amount) -> Result<Self, Error> {
+        (arbitrating.as_sat() != 0 && accordant.as_pico() != 0)
+            .then(|| Self {
+                arbitrating,
+                accordant,
+            })
+            .ok_or(Error::InvalidRate)
+    }
+
+    /// Create the rate from the price of one monero.
+    pub fn from_price(price: bitcoin::Amount) -> Result<Self, Error> {
+        Self::new(price, monero::Amount::ONE_XMR)
+    }
+
+    /// Create the rate defined by the amounts of a deal.
+    pub fn from_deal(parameters: &DealParameters) -> Result<Self, Error> {
+        Self::new(
+            parameters.arbitrating_amount,
+            parameters.accordant_amount.into(),
+        )
+    }
+
+    /// Return the arbitrating side of the rate.
+    pub fn arbitrating_amount(&self) -> bitcoin::Amount {
+        self.arbitrating
+    }
+
+    /// Return the accordant side of the rate.
+    pub fn accordant_amount(&self) -> monero::Amount {
+        self.accordant
+    }
+
+    /// Return the price of one monero, rounded with `rounding`.
+    pub fn price(&self, rounding: Rounding) -> Result<bitcoin::Amount, Error> {
+        Quotation::new(*self, rounding).to_arbitrating(monero::Amount::ONE_XMR)
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} sat for {} piconero",
+            self.arbitrating.as_sat(),
+            self.accordant.as_pico()
+        )
+    }
+}
+
+/// Converts amounts at an agreed [`Rate`] with a [`Rounding`] policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Quotation {
+    /// The agreed exchange rate.
+    pub rate: Rate,
+    /// The rounding policy applied to converted amounts.
+    pub rounding: Rounding,
+}
+
+impl Quotation {
+    /// Create a quotation at `rate`.
+    pub fn new(rate: Rate, rounding: Rounding) -> Self {
+        Self { rate, rounding }
+    }
+
+    /// Return the arbitrating amount exchanged for `accordant`.
+    pub fn to_arbitrating(&self, accordant: monero::Amount) -> Result<bitcoin::Amount, Error> {
+        mul_div(
+            accordant.as_pico(),
+            self.rate.arbitrating.as_sat(),
+            self.rate.accordant.as_pico(),
+            self.rounding,
+        )
+        .map(bitcoin::Amount::from_sat)
+    }
+
+    /// Return the accordant amount exchanged for `arbitrating`.
+    pub fn to_accordant(&self, arbitrating: bitcoin::Amount) -> Result<monero::Amount, Error> {
+        mul_div(
+            arbitrating.as_sat(),
+            self.rate.accordant.as_pico(),
+            self.rate.arbitrating.as_sat(),
+            self.rounding,
+        )
+        .map(monero::Amount::from_pico)
+    }
+}
+
+// Compute `value * numerator / denominator` rounded with `rounding`, the denominator is never
+// zero as rates have non-zero amounts.
+fn mul_div(value: u64, numerator: u64, denominator: u64, rounding: Rounding) -> Result<u64, Error> {
+    let product = value as u128 * numerator as u128;
+    let denominator = denominator as u128;
+    let (quotient, remainder) = (product / denominator, product % denominator);
+    let quotient = match rounding {
+        Rounding::Down => quotient,
+        Rounding::Up => quotient + (remainder != 0) as u128,
+        Rounding::Nearest => quotient + (2 * remainder >= denominator) as u128,
+    };
+    u64::try_from(quotient).map_err(|_| Error::AmountOverflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_amounts_at_rate() {
+        // 0.007 BTC per XMR
+        let rate = Rate::from_price(bitcoin::Amount::from_sat(700_000)).unwrap();
+        let quote = Quotation::new(rate, Rounding::Down);
+        assert_eq!(
+            quote
+                .to_arbitrating(monero::Amount::from_pico(2_500_000_000_000))
+                .unwrap(),
+            bitcoin::Amount::from_sat(1_750_000)
+        );
+        assert_eq!(
+            quote
+                .to_accordant(bitcoin::Amount::from_sat(1_750_000))
+                .unwrap(),
+            monero::Amount::from_pico(2_500_000_000_000)
+        );
+
+        // One piconero is worth 7e-7 satoshi
+        let one_pico = monero::Amount::from_pico(1);
+        assert_eq!(
+            quote.to_arbitrating(one_pico).unwrap(),
+            bitcoin::Amount::ZERO
+        );
+        let up = Quotation::new(rate, Rounding::Up);
+        assert_eq!(
+            up.to_arbitrating(one_pico).unwrap(),
+            bitcoin::Amount::from_sat(1)
+        );
+        let nearest = Quotation::new(rate, Rounding::Nearest);
+        assert_eq!(
+            nearest
+                .to_arbitrating(monero::Amount::from_pico(714_286))
+                .unwrap(),
+            bitcoin::Amount::from_sat(1)
+        );
+        assert_eq!(
+            nearest
+                .to_arbitrating(monero::Amount::from_pico(714_285))
+                .unwrap(),
+            bitcoin::Amount::ZERO
+        );
+        assert_eq!(
+            rate.price(Rounding::Down).unwrap(),
+            bitcoin::Amount::from_sat(700_000)
+        );
+
+        assert!(matches!(
+            Rate::new(bitcoin::Amount::ZERO, monero::Amount::ONE_XMR),
+            Err(Error::InvalidRate)
+        ));
+        let rate = Rate::new(
+            bitcoin::Amount::from_sat(u64::MAX),
+            monero::Amount::from_pico(1),
+        )
+        .unwrap();
+        assert!(matches!(
+            Quotation::new(rate, Rounding::Down).to_arbitrating(monero::Amount::from_pico(2)),
+            Err(Error::AmountOverflow)
+        ));
+    }
+}
@@ -0,0 +1,431 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Receipts of completed swaps. Once the swap is over, by a buy, a refund or a punish, the
+//! [`SwapReceipt`] assembles the arbitrating transactions with their fees, the amounts, the
+//! timestamps and the final outcome in a document kept for accounting or as evidence in a
+//! dispute. The receipt can be signed with the identity key of the participant, see
+//! [`SignedReceipt`].
+//!
+//! The transaction timestamps and heights are taken from the [`EventLog`] of the swap.
+
+use std::io;
+
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey, Signing, Verification};
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::{Amount, Txid};
+
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::{self, commitment::tagged_hash};
+use crate::protocol::events::{EventLog, SwapEvent};
+use crate::role::SwapRole;
+use crate::swap::{self, SwapId};
+use crate::transaction::TxLabel;
+
+/// Tag of the hash signed in [`SignedReceipt`].
+pub const SIGNED_RECEIPT_TAG: &[u8] = b"farcaster/swap-receipt";
+
+/// The final outcome of a swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum SwapOutcome {
+    /// Alice bought the bitcoins, Bob got the moneros.
+    Success,
+    /// Bob refunded his bitcoins, Alice recovers her moneros.
+    Refunded,
+    /// Alice punished Bob, the moneros are lost for Bob.
+    Punished,
+}
+
+impl SwapOutcome {
+    /// Return the transaction completing the swap with this outcome.
+    pub fn final_tx(&self) -> TxLabel {
+        match self {
+            Self::Success => TxLabel::Buy,
+            Self::Refunded => TxLabel::Refund,
+            Self::Punished => TxLabel::Punish,
+        }
+    }
+
+    /// Return the outcome of the swap completed by the transaction, if final.
+    pub fn from_final_tx(label: TxLabel) -> Option<Self> {
+        match label {
+            TxLabel::Buy => Some(Self::Success),
+            TxLabel::Refund => Some(Self::Refunded),
+            TxLabel::Punish => Some(Self::Punished),
+            _ => None,
+        }
+    }
+}
+
+impl Encodable for SwapOutcome {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        match self {
+            Self::Success => 0x01u8.consensus_encode(writer),
+            Self::Refunded => 0x02u8.consensus_encode(writer),
+            Self::Punished => 0x03u8.consensus_encode(writer),
+        }
+    }
+}
+
+impl Decodable for SwapOutcome {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match u8::consensus_decode(d)? {
+            0x01u8 => Ok(Self::Success),
+            0x02u8 => Ok(Self::Refunded),
+            0x03u8 => Ok(Self::Punished),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl_strict_encoding!(SwapOutcome);
+
+/// An arbitrating transaction of the swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TxRecord {
+    /// The label of the transaction.
+    pub label: TxLabel,
+    /// The id of the transaction.
+    pub txid: Txid,
+    /// The fee paid by the transaction.
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub fee: Amount,
+    /// When the transaction was first seen, in seconds since the unix epoch.
+    pub seen_at: Option<u64>,
+    /// The height the transaction was mined at.
+    pub confirmed_at: Option<u32>,
+}
+
+impl TxRecord {
+    /// Create the record of a transaction not seen yet.
+    pub fn new(label: TxLabel, txid: Txid, fee: Amount) -> Self {
+        Self {
+            label,
+            txid,
+            fee,
+            seen_at: None,
+            confirmed_at: None,
+        }
+    }
+
+    /// Create the record of a partial transaction, the fee is computed from the previous outputs.
+    /// Returns `None` if a previous output is missing.
+    pub fn from_psbt(label: TxLabel, psbt: &PartiallySignedTransaction) -> Option<Self> {
+        let spent = psbt
+            .inputs
+            .iter()
+            .map(|input| input.witness_utxo.as_ref().map(|txout| txout.value))
+            .sum::<Option<u64>>()?;
+        let output: u64 = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|txout| txout.value)
+            .sum();
+        let fee = Amount::from_sat(spent.checked_sub(output)?);
+        Some(Self::new(label, psbt.unsigned_tx.txid(), fee))
+    }
+}
+
+impl Encodable for TxRecord {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.label.consensus_encode(writer)?;
+        len += self.txid.into_inner().consensus_encode(writer)?;
+        len += self.fee.as_sat().consensus_encode(writer)?;
+        len += self.seen_at.consensus_encode(writer)?;
+        Ok(len + self.confirmed_at.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for TxRecord {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            label: Decodable::consensus_decode(d)?,
+            txid: Txid::from_inner(Decodable::consensus_decode(d)?),
+            fee: Amount::from_sat(Decodable::consensus_decode(d)?),
+            seen_at: Decodable::consensus_decode(d)?,
+            confirmed_at: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(TxRecord);
+
+/// The summary of a completed swap.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapReceipt {
+    /// The swap identifier.
+    pub swap_id: SwapId,
+    /// The role of the participant issuing the receipt.
+    pub role: SwapRole,
+    /// The final outcome of the swap.
+    pub outcome: SwapOutcome,
+    /// The bitcoins swapped.
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub arbitrating_amount: Amount,
+    /// The moneros swapped.
+    #[serde(with = "monero::util::amount::serde::as_pico")]
+    pub accordant_amount: monero::Amount,
+    /// The arbitrating transactions of the swap, in the order given at generation.
+    pub transactions: Vec<TxRecord>,
+    /// When the first event of the swap was recorded, in seconds since the unix epoch.
+    pub started_at: u64,
+    /// When the final transaction was confirmed, in seconds since the unix epoch.
+    pub completed_at: u64,
+}
+
+impl SwapReceipt {
+    /// Generate the receipt of a swap from its arbitrating `transactions` and its event `log`.
+    /// The records are completed with the time they were first seen and the height they were
+    /// mined at. The outcome is given by the first final transaction confirmed in the log, fails
+    /// with [`swap::Error::NotCompleted`] if there is none.
+    pub fn generate(
+        swap_id: SwapId,
+        role: SwapRole,
+        arbitrating_amount: Amount,
+        accordant_amount: monero::Amount,
+        transactions: Vec<TxRecord>,
+        log: &EventLog,
+    ) -> Result<Self, swap::Error> {
+        let (outcome, completed_at) = log
+            .entries()
+            .iter()
+            .find_map(|entry| match entry.event {
+                SwapEvent::TxConfirmed { tx, .. } | SwapEvent::TxFinal { tx, .. } => {
+                    SwapOutcome::from_final_tx(tx).map(|outcome| (outcome, entry.timestamp))
+                }
+                _ => None,
+            })
+            .ok_or(swap::Error::NotCompleted)?;
+        let started_at = log.entries().first().map_or(0, |entry| entry.timestamp);
+        let transactions = transactions
+            .into_iter()
+            .map(|mut record| {
+                for entry in log.entries() {
+                    match entry.event {
+                        SwapEvent::TxSeen(tx) if tx == record.label => {
+                            record.seen_at.get_or_insert(entry.timestamp);
+                        }
+                        // The last confirmation wins after a reorg
+                        SwapEvent::TxConfirmed { tx, height } if tx == record.label => {
+                            record.confirmed_at = Some(height);
+                        }
+                        _ => (),
+                    }
+                }
+                record
+            })
+            .collect();
+        Ok(Self {
+            swap_id,
+            role,
+            outcome,
+            arbitrating_amount,
+            accordant_amount,
+            transactions,
+            started_at,
+            completed_at,
+        })
+    }
+
+    /// Return the record of the transaction, if part of the receipt.
+    pub fn transaction(&self, label: TxLabel) -> Option<&TxRecord> {
+        self.transactions
+            .iter()
+            .find(|record| record.label == label)
+    }
+
+    /// Return the sum of the fees paid by the transactions of the receipt.
+    pub fn total_fees(&self) -> Amount {
+        self.transactions
+            .iter()
+            .fold(Amount::ZERO, |total, record| total + record.fee)
+    }
+
+    /// Sign the receipt with the identity secret key of the participant.
+    pub fn sign<C: Signing>(self, secp: &Secp256k1<C>, secret_key: &SecretKey) -> SignedReceipt {
+        let signature = secp.sign_ecdsa(&digest(&self), secret_key);
+        SignedReceipt {
+            receipt: self,
+            signature,
+        }
+    }
+}
+
+impl Encodable for SwapReceipt {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.swap_id.consensus_encode(writer)?;
+        len += self.role.consensus_encode(writer)?;
+        len += self.outcome.consensus_encode(writer)?;
+        len += self.arbitrating_amount.as_sat().consensus_encode(writer)?;
+        len += self.accordant_amount.as_pico().consensus_encode(writer)?;
+        len += self.transactions.consensus_encode(writer)?;
+        len += self.started_at.consensus_encode(writer)?;
+        Ok(len + self.completed_at.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for SwapReceipt {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            swap_id: Decodable::consensus_decode(d)?,
+            role: Decodable::consensus_decode(d)?,
+            outcome: Decodable::consensus_decode(d)?,
+            arbitrating_amount: Amount::from_sat(Decodable::consensus_decode(d)?),
+            accordant_amount: monero::Amount::from_pico(Decodable::consensus_decode(d)?),
+            transactions: Decodable::consensus_decode(d)?,
+            started_at: Decodable::consensus_decode(d)?,
+            completed_at: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(SwapReceipt);
+
+fn digest(receipt: &SwapReceipt) -> Message {
+    let hash = tagged_hash(SIGNED_RECEIPT_TAG, &consensus::serialize(receipt));
+    Message::from_slice(&hash[..]).expect("32 bytes hash")
+}
+
+/// A receipt signed with the identity key of the participant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedReceipt {
+    /// The receipt.
+    pub receipt: SwapReceipt,
+    /// The signature of the receipt.
+    pub signature: Signature,
+}
+
+impl SignedReceipt {
+    /// Verify the signature against the identity public key of the participant. Fails with
+    /// [`crypto::Error::InvalidSignature`] if the receipt was not signed with the key or was
+    /// modified.
+    pub fn verify<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        pubkey: &PublicKey,
+    ) -> Result<(), crypto::Error> {
+        secp.verify_ecdsa(&digest(&self.receipt), &self.signature, pubkey)
+            .map_err(|_| crypto::Error::InvalidSignature)
+    }
+}
+
+impl Encodable for SignedReceipt {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let len = self.receipt.consensus_encode(writer)?;
+        Ok(len
+            + self
+                .signature
+                .as_canonical_bytes()
+                .consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for SignedReceipt {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            receipt: Decodable::consensus_decode(d)?,
+            signature: Signature::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(SignedReceipt);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Uuid;
+
+    fn txid(byte: u8) -> Txid {
+        Txid::from_inner([byte; 32])
+    }
+
+    fn log() -> EventLog {
+        let mut log = EventLog::new();
+        log.record(100, SwapEvent::TxSeen(TxLabel::Lock));
+        log.record(
+            200,
+            SwapEvent::TxConfirmed {
+                tx: TxLabel::Lock,
+                height: 10,
+            },
+        );
+        log.record(300, SwapEvent::TxSeen(TxLabel::Buy));
+        log
+    }
+
+    #[test]
+    fn generate_and_sign_receipt() {
+        let swap_id = SwapId::from(Uuid::random());
+        let transactions = vec![
+            TxRecord::new(TxLabel::Lock, txid(1), Amount::from_sat(300)),
+            TxRecord::new(TxLabel::Buy, txid(2), Amount::from_sat(200)),
+        ];
+        let mut log = log();
+        let generate = |log: &EventLog| {
+            SwapReceipt::generate(
+                swap_id,
+                SwapRole::Alice,
+                Amount::from_sat(100_000),
+                monero::Amount::from_pico(1_000_000),
+                transactions.clone(),
+                log,
+            )
+        };
+        // The buy is not confirmed yet
+        assert!(matches!(generate(&log), Err(swap::Error::NotCompleted)));
+
+        log.record(
+            400,
+            SwapEvent::TxConfirmed {
+                tx: TxLabel::Buy,
+                height: 12,
+            },
+        );
+        let receipt = generate(&log).unwrap();
+        assert_eq!(receipt.outcome, SwapOutcome::Success);
+        assert_eq!(receipt.started_at, 100);
+        assert_eq!(receipt.completed_at, 400);
+        assert_eq!(receipt.total_fees(), Amount::from_sat(500));
+        let lock = receipt.transaction(TxLabel::Lock).unwrap();
+        assert_eq!((lock.seen_at, lock.confirmed_at), (Some(100), Some(10)));
+        let buy = receipt.transaction(TxLabel::Buy).unwrap();
+        assert_eq!((buy.seen_at, buy.confirmed_at), (Some(300), Some(12)));
+        assert!(receipt.transaction(TxLabel::Cancel).is_none());
+
+        let yaml = serde_yaml::to_string(&receipt).unwrap();
+        assert_eq!(serde_yaml::from_str::<SwapReceipt>(&yaml).unwrap(), receipt);
+
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&secp, &secret_key);
+        let signed = receipt.sign(&secp, &secret_key);
+        assert!(signed.verify(&secp, &pubkey).is_ok());
+        let decoded: SignedReceipt =
+            consensus::deserialize(&consensus::serialize(&signed)).unwrap();
+        assert_eq!(decoded, signed);
+
+        // Tampering with the receipt invalidates the signature
+        let mut tampered = signed;
+        tampered.receipt.outcome = SwapOutcome::Refunded;
+        assert!(tampered.verify(&secp, &pubkey).is_err());
+    }
+}
@@ -0,0 +1,895 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! JSON-RPC 2.0 facade over the Bitcoin-Monero swap, for frontends not written in Rust.
+//!
+//! The [`SwapServer`] is transport agnostic: it takes JSON-RPC requests as strings and returns
+//! the responses, so it can be served over HTTP, a websocket or a unix socket. It exposes the
+//! following methods:
+//!
+//! - `create_deal` with `{"parameters": <DealParameters>}`, make a deal and open its swap.
+//! - `take_deal` with `{"deal": "Deal:..."}`, take a deal and open its swap.
+//! - `advance` with `{"swap_id": ..., "message": {"type": ..., "data": <hex>}}` for an incoming
+//!   protocol message, consensus encoded, or `{"swap_id": ..., "event": <ChainEvent>}` for an
+//!   event reported by a syncer.
+//! - `swap_status` with `{"swap_id": ...}`, the state of the swap.
+//! - `export_checkpoint` with `{"swap_id": ...}`, the checkpoint of the swap.
+//! - `import_checkpoint` with `{"checkpoint": <checkpoint>}`, restore a swap from its checkpoint.
+//!
+//! Swaps are opened under the identifier of their deal, carried by the commit messages. The
+//! following messages carry the swap identifier derived from both commit messages, see
+//! [`SwapId::derive`], so the application reports its own commit message with `advance`.
+//!
+//! Checkpoints stored at rest are encrypted with [`Checkpoint::seal`], under a key derived from
+//! the wallet seed with [`Checkpoint::sealing_key`].
+//!
+//! The server tracks the messages received and the arbitrating transactions of each swap, it does
+//! not hold any key: the cryptographic steps are left to the [`Alice`](super::Alice) and
+//! [`Bob`](super::Bob) roles of the application.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use bitcoin::secp256k1::PublicKey;
+use inet2_addr::InetSocketAddr;
+use rand::{CryptoRng, RngCore};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::consensus::{self, Decodable};
+use crate::crypto::encryption::{self, SealingKey};
+use crate::crypto::SecretData;
+use crate::instrument::{Instrumentation, Step};
+use crate::protocol::confirmation::ConfirmationPolicy;
+use crate::protocol::events::{EventLog, SwapEvent};
+use crate::protocol::message::{Abort, SwapMessage};
+use crate::protocol::race::{ChainEvent, RaceDetector};
+use crate::role::{SwapRole, TradeRole};
+use crate::swap::btcxmr::message::{
+    BuyProcedureSignature, CommitAliceParameters, CommitBobParameters, CoreArbitratingSetup,
+    RefundProcedureSignatures, RevealAliceParameters, RevealBobParameters,
+};
+use crate::swap::btcxmr::{Deal, DealParameters};
+use crate::swap::{self, SwapId};
+
+use thiserror::Error;
+
+/// Errors returned by the [`SwapServer`], sent back as JSON-RPC errors with their
+/// [`Error::code`].
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The request is not valid JSON.
+    #[error("Parse error: {0}")]
+    Parse(String),
+    /// The request is not a valid JSON-RPC request.
+    #[error("Invalid request")]
+    InvalidRequest,
+    /// The method does not exist.
+    #[error("Method not found: {0}")]
+    MethodNotFound(String),
+    /// The parameters of the method are invalid.
+    #[error("Invalid params: {0}")]
+    InvalidParams(String),
+    /// No swap is open with this identifier.
+    #[error("Unknown swap {0}")]
+    UnknownSwap(SwapId),
+    /// A swap is already open with this identifier.
+    #[error("Swap {0} already exists")]
+    SwapExists(SwapId),
+    /// The message is not expected by the swap role or has already been received.
+    #[error("Unexpected message {0}")]
+    UnexpectedMessage(MessageType),
+    /// The swap has been aborted by the counter-party.
+    #[error("Swap {0} has been aborted")]
+    Aborted(SwapId),
+    /// The encrypted checkpoint is malformed, of an unknown version, or not sealed with the key.
+    #[error("Invalid encrypted checkpoint")]
+    InvalidCheckpoint,
+    /// A consensus error, e.g. a message or deal that cannot be decoded.
+    #[error("Consensus error: {0}")]
+    Consensus(#[from] consensus::Error),
+    /// A swap error, e.g. a message from another swap.
+    #[error("Swap error: {0}")]
+    Swap(#[from] swap::Error),
+}
+
+impl Error {
+    /// The JSON-RPC error code, server errors are in the `-32000` to `-32099` range.
+    pub fn code(&self) -> i64 {
+        match self {
+            Self::Parse(_) => -32700,
+            Self::InvalidRequest => -32600,
+            Self::MethodNotFound(_) => -32601,
+            Self::InvalidParams(_) => -32602,
+            Self::UnknownSwap(_) => -32000,
+            Self::SwapExists(_) => -32001,
+            Self::UnexpectedMessage(_) | Self::Aborted(_) => -32002,
+            Self::Consensus(_) | Self::Swap(_) => -32003,
+            Self::InvalidCheckpoint => -32004,
+        }
+    }
+}
+
+/// The protocol messages accepted by `advance`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum MessageType {
+    /// The [`CommitAliceParameters`] message.
+    CommitAliceParameters,
+    /// The [`CommitBobParameters`] message.
+    CommitBobParameters,
+    /// The [`RevealAliceParameters`] message.
+    RevealAliceParameters,
+    /// The [`RevealBobParameters`] message.
+    RevealBobParameters,
+    /// The [`CoreArbitratingSetup`] message.
+    CoreArbitratingSetup,
+    /// The [`RefundProcedureSignatures`] message.
+    RefundProcedureSignatures,
+    /// The [`BuyProcedureSignature`] message.
+    BuyProcedureSignature,
+    /// The [`Abort`] message.
+    Abort,
+}
+
+impl MessageType {
+    /// The swap role sending the message, `None` if sent by both.
+    pub fn sender(&self) -> Option<SwapRole> {
+        match self {
+            Self::CommitAliceParameters
+            | Self::RevealAliceParameters
+            | Self::RefundProcedureSignatures => Some(SwapRole::Alice),
+            Self::CommitBobParameters
+            | Self::RevealBobParameters
+            | Self::CoreArbitratingSetup
+            | Self::BuyProcedureSignature => Some(SwapRole::Bob),
+            Self::Abort => None,
+        }
+    }
+
+    /// Return true for the commit messages, sent before the swap identifier is derived.
+    pub fn is_commit(&self) -> bool {
+        matches!(
+            self,
+            Self::CommitAliceParameters | Self::CommitBobParameters
+        )
+    }
+
+    /// Decode the message and check that it belongs to the swap `swap_id`.
+    fn check(&self, data: &[u8], swap_id: SwapId) -> Result<(), Error> {
+        fn check<T: Decodable + SwapMessage>(data: &[u8], swap_id: SwapId) -> Result<(), Error> {
+            Ok(consensus::deserialize::<T>(data)?.check_swap_id(swap_id)?)
+        }
+        match self {
+            Self::CommitAliceParameters => check::<CommitAliceParameters>(data, swap_id),
+            Self::CommitBobParameters => check::<CommitBobParameters>(data, swap_id),
+            Self::RevealAliceParameters => check::<RevealAliceParameters>(data, swap_id),
+            Self::RevealBobParameters => check::<RevealBobParameters>(data, swap_id),
+            Self::CoreArbitratingSetup => check::<CoreArbitratingSetup>(data, swap_id),
+            Self::RefundProcedureSignatures => check::<RefundProcedureSignatures>(data, swap_id),
+            Self::BuyProcedureSignature => check::<BuyProcedureSignature>(data, swap_id),
+            Self::Abort => check::<Abort>(data, swap_id),
+        }
+    }
+}
+
+/// A protocol message received by a swap, consensus encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncomingMessage {
+    /// The type of the message.
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    /// The consensus encoding of the message, hex encoded.
+    #[serde(with = "hex_bytes")]
+    pub data: Vec<u8>,
+}
+
+mod hex_bytes {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        hex::decode(String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+/// Everything needed to restore a swap in a [`SwapServer`]: the chain events and messages are
+/// replayed on import.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The deal of the swap, in its string format.
+    pub deal: String,
+    /// The trade role of the server in the swap.
+    pub trade_role: TradeRole,
+    /// The chain events received, in order.
+    pub chain_events: Vec<ChainEvent>,
+    /// The protocol messages received, in order.
+    pub messages: Vec<IncomingMessage>,
+}
+
+/// Magic bytes starting an encrypted checkpoint.
+pub const ENCRYPTED_CHECKPOINT_MAGIC: [u8; 4] = *b"FCCP";
+
+/// Version of the encrypted checkpoint format.
+pub const ENCRYPTED_CHECKPOINT_VERSION: u8 = 1;
+
+impl Checkpoint {
+    /// Derive the key sealing the checkpoints from the wallet seed.
+    pub fn sealing_key(seed: &[u8; 32]) -> SealingKey {
+        encryption::derive_sealing_key(seed, b"farcaster-checkpoint")
+    }
+
+    /// Serialize and encrypt the checkpoint, so the adaptor signatures and key shares of the
+    /// messages do not leak if the storage is exfiltrated. The blob is serialized as:
+    ///
+    /// ```text
+    /// magic (4 bytes) || version (1 byte) || sealed checkpoint
+    /// ```
+    ///
+    /// The checkpoint is sealed with XChaCha20-Poly1305 and the header is passed as associated
+    /// data, so the header is authenticated.
+    pub fn seal<R: RngCore + CryptoRng>(&self, rng: &mut R, key: &SealingKey) -> Vec<u8> {
+        let header = Self::header();
+        let plaintext =
+            SecretData::new(serde_json::to_vec(self).expect("checkpoint serializes to JSON"));
+        let mut blob = header.to_vec();
+        blob.extend(encryption::seal_with_associated_data(
+            rng,
+            key,
+            &header,
+            plaintext.expose_secret(),
+        ));
+        blob
+    }
+
+    /// Decrypt and deserialize a checkpoint from a blob created with [`Checkpoint::seal`].
+    pub fn open(key: &SealingKey, blob: &[u8]) -> Result<Self, Error> {
+        let header = Self::header();
+        (blob.len() > header.len() && blob[..header.len()] == header)
+            .then(|| ())
+            .ok_or(Error::InvalidCheckpoint)?;
+        let plaintext = SecretData::new(
+            encryption::open_with_associated_data(key, &header, &blob[header.len()..])
+                .map_err(|_| Error::InvalidCheckpoint)?,
+        );
+        serde_json::from_slice(plaintext.expose_secret()).map_err(|_| Error::InvalidCheckpoint)
+    }
+
+    fn header() -> [u8; 5] {
+        let mut header = [ENCRYPTED_CHECKPOINT_VERSION; 5];
+        header[..4].copy_from_slice(&ENCRYPTED_CHECKPOINT_MAGIC);
+        header
+    }
+}
+
+struct Session {
+    deal: Deal,
+    trade_role: TradeRole,
+    detector: RaceDetector,
+    height: u32,
+    log: EventLog,
+    chain_events: Vec<ChainEvent>,
+    messages: Vec<IncomingMessage>,
+    aborted: bool,
+}
+
+impl Session {
+    fn new(deal: Deal, trade_role: TradeRole, policy: &ConfirmationPolicy) -> Self {
+        let params = &deal.parameters;
+        let detector = RaceDetector::new(
+            params.swap_role(&trade_role),
+            params.cancel_timelock.as_blocks(),
+            params.punish_timelock.as_blocks(),
+        )
+        .with_reorg_policy(policy.reorg_policy());
+        Self {
+            deal,
+            trade_role,
+            detector,
+            height: 0,
+            log: EventLog::new(),
+            chain_events: vec![],
+            messages: vec![],
+            aborted: false,
+        }
+    }
+
+    fn swap_id(&self) -> SwapId {
+        self.deal.id().into()
+    }
+
+    fn swap_role(&self) -> SwapRole {
+        self.deal.swap_role(&self.trade_role)
+    }
+
+    fn message(&self, message_type: MessageType) -> Option<&[u8]> {
+        self.messages
+            .iter()
+            .find(|msg| msg.message_type == message_type)
+            .map(|msg| msg.data.as_slice())
+    }
+
+    // The swap identifier carried by the message: the identifier of the deal for the commit
+    // messages, and for an abort before the commit messages are exchanged, the identifier derived
+    // from both commit messages otherwise.
+    fn expected_swap_id(&self, message_type: MessageType) -> Result<SwapId, Error> {
+        let commits = (
+            self.message(MessageType::CommitAliceParameters),
+            self.message(MessageType::CommitBobParameters),
+        );
+        match commits {
+            (Some(alice), Some(bob)) if !message_type.is_commit() => Ok(SwapId::derive(
+                &self.deal,
+                &consensus::deserialize::<CommitAliceParameters>(alice)?,
+                &consensus::deserialize::<CommitBobParameters>(bob)?,
+            )),
+            _ if message_type.is_commit() || message_type == MessageType::Abort => {
+                Ok(self.swap_id())
+            }
+            _ => Err(Error::UnexpectedMessage(message_type)),
+        }
+    }
+
+    fn on_event(&mut self, event: ChainEvent) {
+        if let ChainEvent::Height(height) | ChainEvent::Confirmed(_, height) = event {
+            self.height = self.height.max(height);
+        }
+        self.detector.on_event(event);
+        for event in self.detector.drain_events() {
+            self.log.record_now(event);
+        }
+        self.chain_events.push(event);
+    }
+
+    fn on_message(
+        &mut self,
+        message: IncomingMessage,
+        instrumentation: &Instrumentation,
+    ) -> Result<(), Error> {
+        (!self.aborted)
+            .then(|| ())
+            .ok_or(Error::Aborted(self.swap_id()))?;
+        let message_type = message.message_type;
+        // The own commit message is needed to derive the swap identifier
+        let from_counterparty = message_type.is_commit()
+            || message_type
+                .sender()
+                .map_or(true, |sender| sender == self.swap_role().other());
+        (from_counterparty && self.message(message_type).is_none())
+            .then(|| ())
+            .ok_or(Error::UnexpectedMessage(message_type))?;
+        let swap_id = self.expected_swap_id(message_type)?;
+        instrumentation.record(Step::DecodeMessage, || {
+            message_type.check(&message.data, swap_id)
+        })?;
+        self.aborted = message_type == MessageType::Abort;
+        self.messages.push(message);
+        Ok(())
+    }
+
+    fn status(&self, policy: &ConfirmationPolicy) -> Value {
+        json!({
+            "swap_id": self.swap_id(),
+            "deal": self.deal.to_string(),
+            "trade_role": self.trade_role,
+            "swap_role": self.swap_role(),
+            "height": self.height,
+            "aborted": self.aborted,
+            "messages": self.messages.iter().map(|msg| msg.message_type).collect::<Vec<_>>(),
+            "recommended_action": self.detector.recommended_action(),
+            "timelocks": self.detector.schedule().map(|s| s.status(self.height, policy)),
+            "events": self.log.entries().iter().map(|e| e.event).collect::<Vec<SwapEvent>>(),
+        })
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            deal: self.deal.to_string(),
+            trade_role: self.trade_role,
+            chain_events: self.chain_events.clone(),
+            messages: self.messages.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateDealParams {
+    parameters: DealParameters,
+}
+
+#[derive(Deserialize)]
+struct TakeDealParams {
+    deal: String,
+}
+
+#[derive(Deserialize)]
+struct AdvanceParams {
+    swap_id: SwapId,
+    message: Option<IncomingMessage>,
+    event: Option<ChainEvent>,
+}
+
+#[derive(Deserialize)]
+struct SwapParams {
+    swap_id: SwapId,
+}
+
+#[derive(Deserialize)]
+struct ImportParams {
+    checkpoint: Checkpoint,
+}
+
+/// Serves the swaps of a node over JSON-RPC.
+pub struct SwapServer {
+    node_id: PublicKey,
+    peer_address: InetSocketAddr,
+    policy: ConfirmationPolicy,
+    swaps: HashMap<SwapId, Session>,
+    instrumentation: Instrumentation,
+}
+
+impl SwapServer {
+    /// Create a server for the node, the node identity and address are advertised in the deals
+    /// it makes.
+    pub fn new(node_id: PublicKey, peer_address: InetSocketAddr) -> Self {
+        Self {
+            node_id,
+            peer_address,
+            policy: ConfirmationPolicy::default(),
+            swaps: HashMap::new(),
+            instrumentation: Instrumentation::new(),
+        }
+    }
+
+    /// Set the confirmation policy used to follow the arbitrating transactions.
+    pub fn with_confirmation_policy(mut self, policy: ConfirmationPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Report the decoding of the incoming messages and the state transitions of the swaps.
+    pub fn with_instrumentation(mut self, instrumentation: Instrumentation) -> Self {
+        self.instrumentation = instrumentation;
+        self
+    }
+
+    /// Handle a JSON-RPC request, or a batch of requests, and return the response. Notifications
+    /// have no response.
+    pub fn handle(&mut self, request: &str) -> Option<String> {
+        let response = match serde_json::from_str::<Value>(request) {
+            Err(e) => Some(error_response(Value::Null, Error::Parse(e.to_string()))),
+            Ok(Value::Array(batch)) if !batch.is_empty() => {
+                let responses: Vec<_> = batch
+                    .into_iter()
+                    .filter_map(|request| self.handle_value(request))
+                    .collect();
+                (!responses.is_empty()).then(|| Value::Array(responses))
+            }
+            Ok(request) => self.handle_value(request),
+        };
+        response.map(|response| response.to_string())
+    }
+
+    fn handle_value(&mut self, request: Value) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let method = request
+            .get("method")
+            .and_then(Value::as_str)
+            .filter(|_| request.get("jsonrpc") == Some(&json!("2.0")));
+        let method = match method {
+            Some(method) => method.to_string(),
+            None => return Some(error_response(Value::Null, Error::InvalidRequest)),
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let result = self.call(&method, params);
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "result": result, "id": id}),
+            Err(e) => error_response(id, e),
+        })
+    }
+
+    /// Call a method with its parameters and return its result.
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value, Error> {
+        match method {
+            "create_deal" => {
+                let CreateDealParams { parameters } = from_params(params)?;
+                let deal = parameters.to_v1(self.node_id, self.peer_address);
+                let swap_id = self.open(deal.clone(), TradeRole::Maker)?;
+                Ok(json!({"swap_id": swap_id, "deal": deal.to_string()}))
+            }
+            "take_deal" => {
+                let TakeDealParams { deal } = from_params(params)?;
+                let deal = Deal::from_str(&deal)?;
+                let swap_role = deal.swap_role(&TradeRole::Taker);
+                let swap_id = self.open(deal, TradeRole::Taker)?;
+                Ok(json!({"swap_id": swap_id, "swap_role": swap_role}))
+            }
+            "advance" => {
+                let AdvanceParams {
+                    swap_id,
+                    message,
+                    event,
+                } = from_params(params)?;
+                let policy = self.policy;
+                let instrumentation = self.instrumentation.clone();
+                let session = self.session(swap_id)?;
+                instrumentation.record(Step::StateTransition, || match (message, event) {
+                    (Some(message), None) => session.on_message(message, &instrumentation),
+                    (None, Some(event)) => {
+                        session.on_event(event);
+                        Ok(())
+                    }
+                    _ => Err(Error::InvalidParams(
+                        "expected either a message or an event".into(),
+                    )),
+                })?;
+                Ok(session.status(&policy))
+            }
+            "swap_status" => {
+                let SwapParams { swap_id } = from_params(params)?;
+                let policy = self.policy;
+                Ok(self.session(swap_id)?.status(&policy))
+            }
+            "export_checkpoint" => {
+                let SwapParams { swap_id } = from_params(params)?;
+                serde_json::to_value(self.export_checkpoint(swap_id)?)
+                    .map_err(|e| Error::InvalidParams(e.to_string()))
+            }
+            "import_checkpoint" => {
+                let ImportParams { checkpoint } = from_params(params)?;
+                let swap_id = self.import_checkpoint(checkpoint)?;
+                Ok(json!({ "swap_id": swap_id }))
+            }
+            _ => Err(Error::MethodNotFound(method.to_string())),
+        }
+    }
+
+    /// Return the checkpoint of the swap.
+    pub fn export_checkpoint(&mut self, swap_id: SwapId) -> Result<Checkpoint, Error> {
+        Ok(self.session(swap_id)?.checkpoint())
+    }
+
+    /// Restore a swap from its checkpoint and return its identifier.
+    pub fn import_checkpoint(&mut self, checkpoint: Checkpoint) -> Result<SwapId, Error> {
+        let deal = Deal::from_str(&checkpoint.deal)?;
+        let mut session = Session::new(deal, checkpoint.trade_role, &self.policy);
+        for event in checkpoint.chain_events {
+            session.on_event(event);
+        }
+        for message in checkpoint.messages {
+            session.on_message(message, &self.instrumentation)?;
+        }
+        let swap_id = session.swap_id();
+        self.insert(session)?;
+        Ok(swap_id)
+    }
+
+    fn open(&mut self, deal: Deal, trade_role: TradeRole) -> Result<SwapId, Error> {
+        let session = Session::new(deal, trade_role, &self.policy);
+        let swap_id = session.swap_id();
+        self.insert(session)?;
+        Ok(swap_id)
+    }
+
+    fn insert(&mut self, session: Session) -> Result<(), Error> {
+        let swap_id = session.swap_id();
+        (!self.swaps.contains_key(&swap_id))
+            .then(|| ())
+            .ok_or(Error::SwapExists(swap_id))?;
+        self.swaps.insert(swap_id, session);
+        Ok(())
+    }
+
+    fn session(&mut self, swap_id: SwapId) -> Result<&mut Session, Error> {
+        self.swaps
+            .get_mut(&swap_id)
+            .ok_or(Error::UnknownSwap(swap_id))
+    }
+}
+
+fn from_params<T: DeserializeOwned>(params: Value) -> Result<T, Error> {
+    serde_json::from_value(params).map_err(|e| Error::InvalidParams(e.to_string()))
+}
+
+fn error_response(id: Value, error: Error) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "error": {"code": error.code(), "message": error.to_string()},
+        "id": id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bitcoin::fee::SatPerKvB;
+    use crate::bitcoin::timelock::CSVTimelock;
+    use crate::blockchain::{AccordantAmount, Blockchain, FeeStrategy, Network};
+    use crate::protocol::race::RecommendedAction;
+    use crate::transaction::TxLabel;
+
+    fn server() -> SwapServer {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let secret = bitcoin::secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let peer_address = InetSocketAddr::socket(
+            FromStr::from_str("1.2.3.4").unwrap(),
+            FromStr::from_str("9735").unwrap(),
+        );
+        SwapServer::new(PublicKey::from_secret_key(&secp, &secret), peer_address)
+    }
+
+    fn parameters() -> DealParameters {
+        DealParameters {
+            uuid: uuid::uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8").into(),
+            network: Network::Testnet,
+            arbitrating_blockchain: Blockchain::Bitcoin,
+            accordant_blockchain: Blockchain::Monero,
+            arbitrating_amount: bitcoin::Amount::from_sat(1350),
+            accordant_amount: AccordantAmount::from_atomic_units(10000),
+            cancel_timelock: CSVTimelock::new(4),
+            punish_timelock: CSVTimelock::new(6),
+            fee_strategy: FeeStrategy::Fixed(SatPerKvB::from_sat(1)),
+            maker_role: SwapRole::Bob,
+        }
+    }
+
+    fn request(server: &mut SwapServer, method: &str, params: Value) -> Value {
+        let request = json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1});
+        serde_json::from_str(&server.handle(&request.to_string()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn make_and_take_deal() {
+        let mut maker = server();
+        let response = request(
+            &mut maker,
+            "create_deal",
+            json!({ "parameters": parameters() }),
+        );
+        let deal = response["result"]["deal"].clone();
+        let swap_id = response["result"]["swap_id"].clone();
+
+        let mut taker = server();
+        let response = request(&mut taker, "take_deal", json!({ "deal": deal }));
+        assert_eq!(response["result"]["swap_id"], swap_id);
+        assert_eq!(response["result"]["swap_role"], json!(SwapRole::Alice));
+
+        // The same deal cannot be taken twice
+        let response = request(&mut taker, "take_deal", json!({ "deal": deal }));
+        assert_eq!(response["error"]["code"], json!(-32001));
+    }
+
+    #[test]
+    fn advance_and_checkpoint_swap() {
+        let mut alice = server();
+        let deal = parameters().to_v1(alice.node_id, alice.peer_address);
+        let response = request(&mut alice, "take_deal", json!({"deal": deal.to_string()}));
+        let swap_id: SwapId =
+            serde_json::from_value(response["result"]["swap_id"].clone()).unwrap();
+
+        for event in [
+            ChainEvent::Confirmed(TxLabel::Lock, 100),
+            ChainEvent::Height(104),
+        ] {
+            request(
+                &mut alice,
+                "advance",
+                json!({"swap_id": swap_id, "event": event}),
+            );
+        }
+
+        // Alice does not accept her own messages, nor messages from another swap
+        let abort = |swap_id| IncomingMessage {
+            message_type: MessageType::Abort,
+            data: consensus::serialize(&Abort {
+                swap_id,
+                error_body: Some("timeout".into()),
+            }),
+        };
+        let refund = IncomingMessage {
+            message_type: MessageType::RefundProcedureSignatures,
+            data: vec![],
+        };
+        let response = request(
+            &mut alice,
+            "advance",
+            json!({"swap_id": swap_id, "message": refund}),
+        );
+        assert_eq!(response["error"]["code"], json!(-32002));
+        let response = request(
+            &mut alice,
+            "advance",
+            json!({"swap_id": swap_id, "message": abort(SwapId::from(uuid::Uuid::new_v4()))}),
+        );
+        assert_eq!(response["error"]["code"], json!(-32003));
+
+        let status = request(
+            &mut alice,
+            "advance",
+            json!({"swap_id": swap_id, "message": abort(swap_id)}),
+        )["result"]
+            .clone();
+        assert_eq!(status["height"], json!(104));
+        assert_eq!(status["aborted"], json!(true));
+        assert_eq!(status["messages"], json!([MessageType::Abort]));
+        assert_eq!(
+            status["recommended_action"],
+            json!(RecommendedAction::BroadcastCancelNow)
+        );
+
+        // The swap is restored from its checkpoint on another server
+        let checkpoint = request(
+            &mut alice,
+            "export_checkpoint",
+            json!({ "swap_id": swap_id }),
+        );
+        let mut restored = server();
+        request(
+            &mut restored,
+            "import_checkpoint",
+            json!({"checkpoint": checkpoint["result"]}),
+        );
+        let restored_status = request(&mut restored, "swap_status", json!({ "swap_id": swap_id }));
+        assert_eq!(restored_status["result"], status);
+    }
+
+    #[test]
+    fn check_derived_swap_id() {
+        use crate::crypto::KeccakCommitment;
+
+        let mut alice = server();
+        let deal = parameters().to_v1(alice.node_id, alice.peer_address);
+        let response = request(&mut alice, "take_deal", json!({"deal": deal.to_string()}));
+        let deal_id: SwapId =
+            serde_json::from_value(response["result"]["swap_id"].clone()).unwrap();
+        let advance = |server: &mut SwapServer, message_type, data| {
+            request(
+                server,
+                "advance",
+                json!({"swap_id": deal_id, "message": IncomingMessage { message_type, data }}),
+            )
+        };
+
+        let c = KeccakCommitment::new([1; 32]);
+        let commit_alice = CommitAliceParameters {
+            swap_id: deal_id,
+            buy: c,
+            cancel: c,
+            refund: c,
+            punish: c,
+            adaptor: c,
+            extra_arbitrating_keys: vec![],
+            arbitrating_shared_keys: vec![],
+            spend: c,
+            extra_accordant_keys: vec![],
+            accordant_shared_keys: vec![],
+        };
+        let commit_bob = CommitBobParameters {
+            swap_id: deal_id,
+            buy: c,
+            cancel: c,
+            refund: c,
+            adaptor: c,
+            extra_arbitrating_keys: vec![],
+            arbitrating_shared_keys: vec![],
+            spend: c,
+            extra_accordant_keys: vec![],
+            accordant_shared_keys: vec![],
+        };
+        let swap_id = SwapId::derive(&deal, &commit_alice, &commit_bob);
+        let abort = |swap_id| {
+            consensus::serialize(&Abort {
+                swap_id,
+                error_body: None,
+            })
+        };
+
+        // The swap identifier is not derived before both commit messages are known
+        let response = advance(&mut alice, MessageType::RevealBobParameters, vec![]);
+        assert_eq!(response["error"]["code"], json!(-32002));
+        let response = advance(
+            &mut alice,
+            MessageType::CommitAliceParameters,
+            consensus::serialize(&commit_alice),
+        );
+        assert!(response["error"].is_null());
+        let mut other = commit_bob.clone();
+        other.swap_id = swap_id;
+        let response = advance(
+            &mut alice,
+            MessageType::CommitBobParameters,
+            consensus::serialize(&other),
+        );
+        assert_eq!(response["error"]["code"], json!(-32003));
+        let response = advance(
+            &mut alice,
+            MessageType::CommitBobParameters,
+            consensus::serialize(&commit_bob),
+        );
+        assert!(response["error"].is_null());
+
+        // The following messages carry the derived identifier
+        let response = advance(&mut alice, MessageType::Abort, abort(deal_id));
+        assert_eq!(response["error"]["code"], json!(-32003));
+        let status = advance(&mut alice, MessageType::Abort, abort(swap_id));
+        assert_eq!(status["result"]["aborted"], json!(true));
+    }
+
+    #[test]
+    fn seal_and_open_checkpoint() {
+        let mut alice = server();
+        let deal = parameters().to_v1(alice.node_id, alice.peer_address);
+        let response = request(&mut alice, "take_deal", json!({"deal": deal.to_string()}));
+        let swap_id: SwapId =
+            serde_json::from_value(response["result"]["swap_id"].clone()).unwrap();
+        request(
+            &mut alice,
+            "advance",
+            json!({"swap_id": swap_id, "event": ChainEvent::Confirmed(TxLabel::Lock, 100)}),
+        );
+        let checkpoint = alice.export_checkpoint(swap_id).unwrap();
+
+        let key = Checkpoint::sealing_key(&[3; 32]);
+        let blob = checkpoint.seal(&mut rand::thread_rng(), &key);
+        assert_eq!(blob[..4], ENCRYPTED_CHECKPOINT_MAGIC);
+        assert_eq!(blob[4], ENCRYPTED_CHECKPOINT_VERSION);
+        assert!(!String::from_utf8_lossy(&blob).contains(&checkpoint.deal));
+
+        let opened = Checkpoint::open(&key, &blob).unwrap();
+        assert_eq!(opened, checkpoint);
+        let mut restored = server();
+        assert_eq!(restored.import_checkpoint(opened).unwrap(), swap_id);
+
+        // Wrong key, unknown version and tampered blobs are rejected
+        assert!(matches!(
+            Checkpoint::open(&Checkpoint::sealing_key(&[4; 32]), &blob),
+            Err(Error::InvalidCheckpoint)
+        ));
+        let mut tampered = blob.clone();
+        tampered[4] = 2;
+        assert!(Checkpoint::open(&key, &tampered).is_err());
+        let mut tampered = blob.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(Checkpoint::open(&key, &tampered).is_err());
+        assert!(Checkpoint::open(&key, &blob[..5]).is_err());
+    }
+
+    #[test]
+    fn reject_invalid_requests() {
+        let mut server = server();
+        let response: Value = serde_json::from_str(&server.handle("{").unwrap()).unwrap();
+        assert_eq!(response["error"]["code"], json!(-32700));
+
+        let response = request(&mut server, "unknown", Value::Null);
+        assert_eq!(response["error"]["code"], json!(-32601));
+
+        let response = request(
+            &mut server,
+            "swap_status",
+            json!({ "swap_id": SwapId::from(uuid::Uuid::new_v4()) }),
+        );
+        assert_eq!(response["error"]["code"], json!(-32000));
+
+        // Notifications have no response
+        let notification = json!({"jsonrpc": "2.0", "method": "unknown"});
+        assert_eq!(server.handle(&notification.to_string()), None);
+    }
+}
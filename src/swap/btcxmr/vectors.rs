@@ -0,0 +1,566 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Canonical test vectors of the Bitcoin-Monero swap, exported to JSON so alternative
+//! implementations can validate against this crate byte-for-byte.
+//!
+//! The vectors are generated from fixed seeds with deterministic nonces, see
+//! [`NonceProvider::deterministic`], and grouped in [`VectorFile`]s:
+//!
+//! - `keys`: the arbitrating and accordant keys derived by Alice and Bob,
+//! - `commitments`: the commitments to the keys,
+//! - `proofs`: the cross-group DLEQ proofs,
+//! - `signatures`: the signatures and adaptor signatures,
+//! - `messages`: the consensus encoded protocol messages,
+//! - `transactions`: the PSBTs of the arbitrating transactions at each stage, from unsigned to
+//!   final.
+//!
+//! DLEQ proofs draw their blinders and nonces from a stream seeded with the secret, so the
+//! vectors containing them are [`reproducible`](Vector::reproducible) like the others. Run `cargo run --features vectors --bin farcaster-vectors <dir>` to
+//! write the files.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Amount, Witness};
+use inet2_addr::InetSocketAddr;
+
+use crate::bitcoin::fee::SatPerKvB;
+use crate::bitcoin::segwitv0::setup::{ArbitratingSetup, SetupParameters};
+use crate::bitcoin::segwitv0::{FundingTx, PunishPolicy};
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::transaction::{SubTransaction, Tx};
+use crate::bitcoin::truc::BroadcastMode;
+use crate::bitcoin::BitcoinSegwitV0;
+use crate::blockchain::{
+    AccordantAmount, Blockchain, FeePriority, FeeStrategy, Network, Transactions,
+};
+use crate::consensus::{self, CanonicalBytes};
+use crate::crypto::context::{CryptoContext, NonceProvider};
+use crate::crypto::{
+    AccordantKeyId, ArbitratingKeyId, Commit, CommitmentEngine, EncSign, GenerateKey,
+    GenerateSharedKey, ProveCrossGroupDleq, SharedKeyId, Sign,
+};
+use crate::monero::{Monero, SHARED_VIEW_KEY_ID};
+use crate::role::SwapRole;
+use crate::script::{DataLock, DataPunishableLock, ScriptPath, SwapRoleKeys};
+use crate::swap::btcxmr::{Alice, Bob, Deal, DealParameters, KeyManager};
+use crate::swap::SwapId;
+use crate::transaction::{Broadcastable, Fundable, Transaction, TxLabel, Witnessable};
+use crate::Res;
+
+/// The seed of Alice's key manager.
+pub const ALICE_SEED: [u8; 32] = [0x01; 32];
+
+/// The seed of Bob's key manager.
+pub const BOB_SEED: [u8; 32] = [0x02; 32];
+
+/// The swap index of both key managers.
+pub const SWAP_INDEX: u32 = 0;
+
+/// The swap id used in the protocol messages and transactions.
+pub const SWAP_ID: [u8; 16] = [
+    0x67, 0xe5, 0x50, 0x44, 0x10, 0xb1, 0x42, 0x6f, 0x92, 0x47, 0xbb, 0x68, 0x0e, 0x5f, 0xe0, 0xc8,
+];
+
+/// The message signed in the signature vectors.
+pub const MESSAGE: &[u8] = b"Farcaster test vector";
+
+/// A test vector, the inputs and outputs of an operation encoded in hexadecimal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vector {
+    /// What the vector tests.
+    pub description: String,
+    /// False if the outputs are randomized and must be verified instead of compared.
+    pub reproducible: bool,
+    /// The inputs of the operation.
+    pub input: BTreeMap<String, String>,
+    /// The outputs of the operation.
+    pub output: BTreeMap<String, String>,
+}
+
+impl Vector {
+    /// Create an empty reproducible vector.
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            reproducible: true,
+            input: BTreeMap::new(),
+            output: BTreeMap::new(),
+        }
+    }
+
+    /// Mark the vector as randomized.
+    pub fn randomized(mut self) -> Self {
+        self.reproducible = false;
+        self
+    }
+
+    /// Add an input to the vector.
+    pub fn input(mut self, name: impl Into<String>, bytes: impl AsRef<[u8]>) -> Self {
+        self.input.insert(name.into(), hex::encode(bytes));
+        self
+    }
+
+    /// Add an output to the vector.
+    pub fn output(mut self, name: impl Into<String>, bytes: impl AsRef<[u8]>) -> Self {
+        self.output.insert(name.into(), hex::encode(bytes));
+        self
+    }
+}
+
+/// A set of test vectors, written to `<name>.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VectorFile {
+    /// The name of the set.
+    pub name: String,
+    /// The vectors of the set.
+    pub vectors: Vec<Vector>,
+}
+
+impl VectorFile {
+    fn new(name: &str, vectors: Vec<Vector>) -> Self {
+        Self {
+            name: name.into(),
+            vectors,
+        }
+    }
+
+    /// Return the file name of the set.
+    pub fn file_name(&self) -> String {
+        format!("{}.json", self.name)
+    }
+
+    /// Serialize the set to pretty printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Vectors are always serializable")
+    }
+}
+
+/// Generate all the test vectors.
+pub fn generate() -> Res<Vec<VectorFile>> {
+    let mut keys = Keys::new()?;
+    Ok(vec![
+        VectorFile::new("keys", keys.key_vectors()?),
+        VectorFile::new("commitments", keys.commitment_vectors()?),
+        VectorFile::new("proofs", keys.proof_vectors()?),
+        VectorFile::new("signatures", keys.signature_vectors()?),
+        VectorFile::new("messages", keys.message_vectors()?),
+        VectorFile::new("transactions", keys.transaction_vectors()?),
+    ])
+}
+
+/// Generate all the test vectors and write them in `dir`, return the paths of the files.
+pub fn write_to(dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    generate()
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .iter()
+        .map(|file| {
+            let path = dir.join(file.file_name());
+            fs::write(&path, file.to_json())?;
+            Ok(path)
+        })
+        .collect()
+}
+
+const ARBITRATING_KEYS: [ArbitratingKeyId; 5] = [
+    ArbitratingKeyId::Lock,
+    ArbitratingKeyId::Buy,
+    ArbitratingKeyId::Cancel,
+    ArbitratingKeyId::Refund,
+    ArbitratingKeyId::Punish,
+];
+
+// A signature required to finalize a transaction: the signer, its key, and whether it is an
+// adaptor signature decrypted by the counter-party
+type Signer = (SwapRole, ArbitratingKeyId, bool);
+
+struct Keys {
+    alice: KeyManager,
+    bob: KeyManager,
+}
+
+impl Keys {
+    fn new() -> Res<Self> {
+        let key_manager = |seed| {
+            let ctx = CryptoContext::with_nonce_provider(NonceProvider::deterministic());
+            KeyManager::with_context(seed, SWAP_INDEX, ctx)
+        };
+        Ok(Self {
+            alice: key_manager(ALICE_SEED)?,
+            bob: key_manager(BOB_SEED)?,
+        })
+    }
+
+    fn get(&mut self, role: SwapRole) -> &mut KeyManager {
+        match role {
+            SwapRole::Alice => &mut self.alice,
+            SwapRole::Bob => &mut self.bob,
+        }
+    }
+
+    fn seed(role: SwapRole) -> [u8; 32] {
+        match role {
+            SwapRole::Alice => ALICE_SEED,
+            SwapRole::Bob => BOB_SEED,
+        }
+    }
+
+    fn pubkey(&mut self, role: SwapRole, key_id: ArbitratingKeyId) -> Res<PublicKey> {
+        Ok(self.get(role).get_pubkey(key_id)?)
+    }
+
+    fn key_vectors(&mut self) -> Res<Vec<Vector>> {
+        let mut vectors = vec![];
+        for role in [SwapRole::Alice, SwapRole::Bob] {
+            let seed = Self::seed(role);
+            let index = SWAP_INDEX.to_le_bytes();
+            let key_manager = self.get(role);
+            let mut vector = Vector::new(format!("{} keys", role))
+                .input("seed", seed)
+                .input("swap_index", index);
+            for key_id in ARBITRATING_KEYS {
+                let pubkey = key_manager.get_pubkey(key_id)?;
+                vector = vector.output(
+                    format!("{}_pubkey", key_id).to_lowercase(),
+                    pubkey.serialize(),
+                );
+            }
+            let spend: monero::PublicKey = key_manager.get_pubkey(AccordantKeyId::Spend)?;
+            let view: monero::PrivateKey =
+                key_manager.get_shared_key(SharedKeyId::new(SHARED_VIEW_KEY_ID))?;
+            let encryption_key = key_manager.get_encryption_key()?;
+            vectors.push(
+                vector
+                    .output("spend_pubkey", spend.as_bytes())
+                    .output("view_secret_key", view.as_bytes())
+                    .output("encryption_key", encryption_key.serialize()),
+            );
+        }
+        Ok(vectors)
+    }
+
+    fn commitment_vectors(&mut self) -> Res<Vec<Vector>> {
+        let mut vectors = vec![];
+        for role in [SwapRole::Alice, SwapRole::Bob] {
+            for key_id in ARBITRATING_KEYS {
+                let pubkey = self.pubkey(role, key_id)?.serialize();
+                vectors.push(
+                    Vector::new(format!("Commitment to {} {} key", role, key_id))
+                        .input("value", pubkey)
+                        .output(
+                            "commitment",
+                            CommitmentEngine.commit_to(pubkey).as_canonical_bytes(),
+                        ),
+                );
+            }
+        }
+        Ok(vectors)
+    }
+
+    fn proof_vectors(&mut self) -> Res<Vec<Vector>> {
+        let mut vectors = vec![];
+        for role in [SwapRole::Alice, SwapRole::Bob] {
+            let (spend, encryption_key, proof) = self.get(role).generate_proof()?;
+            self.get(role.other())
+                .verify_proof(&spend, &encryption_key, proof.clone())?;
+            vectors.push(
+                Vector::new(format!("{} DLEQ proof", role))
+                    .input("spend_pubkey", spend.as_bytes())
+                    .input("encryption_key", encryption_key.serialize())
+                    .output("proof", proof.as_canonical_bytes()),
+            );
+        }
+        Ok(vectors)
+    }
+
+    fn signature_vectors(&mut self) -> Res<Vec<Vector>> {
+        let msg = sha256d::Hash::hash(MESSAGE);
+        let mut vectors = vec![];
+        for role in [SwapRole::Alice, SwapRole::Bob] {
+            let encryption_key = self.get(role.other()).get_encryption_key()?;
+            let key_manager = self.get(role);
+            let pubkey = key_manager.get_pubkey(ArbitratingKeyId::Buy)?;
+            let sig = key_manager.sign(ArbitratingKeyId::Buy, msg)?;
+            let encrypted_sig =
+                key_manager.encrypt_sign(ArbitratingKeyId::Buy, &encryption_key, msg)?;
+            key_manager.verify_encrypted_signature(
+                &pubkey,
+                &encryption_key,
+                msg,
+                &encrypted_sig,
+            )?;
+            let decrypted_sig = self
+                .get(role.other())
+                .decrypt_signature(AccordantKeyId::Spend, encrypted_sig.clone())?;
+            vectors.push(
+                Vector::new(format!("{} signature and adaptor signature", role))
+                    .input("pubkey", pubkey.serialize())
+                    .input("encryption_key", encryption_key.serialize())
+                    .input("message_hash", msg)
+                    .output("signature", sig.as_canonical_bytes())
+                    .output("encrypted_signature", encrypted_sig.as_canonical_bytes())
+                    .output("decrypted_signature", decrypted_sig.as_canonical_bytes()),
+            );
+        }
+        Ok(vectors)
+    }
+
+    fn message_vectors(&mut self) -> Res<Vec<Vector>> {
+        let deal = deal(self.pubkey(SwapRole::Bob, ArbitratingKeyId::Lock)?);
+        let swap_id = swap_id();
+        let alice = Alice::new(
+            BitcoinSegwitV0::new(),
+            Monero,
+            address(self.pubkey(SwapRole::Alice, ArbitratingKeyId::Punish)?),
+            FeePriority::Low,
+        );
+        let bob = Bob::new(
+            BitcoinSegwitV0::new(),
+            Monero,
+            address(self.pubkey(SwapRole::Bob, ArbitratingKeyId::Refund)?),
+            FeePriority::Low,
+        );
+        let alice_params = alice.generate_parameters(&mut self.alice, &deal)?;
+        let bob_params = bob.generate_parameters(&mut self.bob, &deal)?;
+        let input = |vector: Vector, seed| {
+            vector
+                .input("deal", consensus::serialize(&deal))
+                .input("swap_id", SWAP_ID)
+                .input("seed", seed)
+        };
+        Ok(vec![
+            input(Vector::new("CommitAliceParameters message"), ALICE_SEED).output(
+                "message",
+                consensus::serialize(&alice_params.commit_alice(swap_id, &CommitmentEngine)),
+            ),
+            input(Vector::new("CommitBobParameters message"), BOB_SEED).output(
+                "message",
+                consensus::serialize(&bob_params.commit_bob(swap_id, &CommitmentEngine)),
+            ),
+            input(Vector::new("RevealAliceParameters message"), ALICE_SEED).output(
+                "message",
+                consensus::serialize(&alice_params.reveal_alice(swap_id)),
+            ),
+            input(Vector::new("RevealBobParameters message"), BOB_SEED).output(
+                "message",
+                consensus::serialize(&bob_params.reveal_bob(swap_id)),
+            ),
+        ])
+    }
+
+    fn transaction_vectors(&mut self) -> Res<Vec<Vector>> {
+        let alice = |keys: &mut Self, key_id| keys.pubkey(SwapRole::Alice, key_id);
+        let bob = |keys: &mut Self, key_id| keys.pubkey(SwapRole::Bob, key_id);
+        let arb_params = deal(bob(self, ArbitratingKeyId::Lock)?).to_arbitrating_params();
+        let alice_address = address(alice(self, ArbitratingKeyId::Punish)?);
+        // Fund the lock with its fee, as the lock is rejected out of the fee strategy
+        let funding_amount = BitcoinSegwitV0::funding_amount(&arb_params, &alice_address)?;
+        let mut funding =
+            FundingTx::initialize(bob(self, ArbitratingKeyId::Lock)?, Network::Local)?;
+        funding.update(bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: funding_amount.as_sat(),
+                script_pubkey: funding.get_address()?.script_pubkey(),
+            }],
+        })?;
+        let params = SetupParameters {
+            lock: DataLock {
+                timelock: arb_params.cancel_timelock,
+                success: SwapRoleKeys::new(
+                    alice(self, ArbitratingKeyId::Buy)?,
+                    bob(self, ArbitratingKeyId::Buy)?,
+                ),
+                failure: SwapRoleKeys::new(
+                    alice(self, ArbitratingKeyId::Cancel)?,
+                    bob(self, ArbitratingKeyId::Cancel)?,
+                ),
+            },
+            punish_lock: DataPunishableLock {
+                timelock: arb_params.punish_timelock,
+                success: SwapRoleKeys::new(
+                    alice(self, ArbitratingKeyId::Refund)?,
+                    bob(self, ArbitratingKeyId::Refund)?,
+                ),
+                failure: alice(self, ArbitratingKeyId::Punish)?,
+                recovery: None,
+            },
+            target_amount: BitcoinSegwitV0::lock_amount(&arb_params, &alice_address)?,
+            alice_address,
+            bob_address: address(bob(self, ArbitratingKeyId::Refund)?),
+            fee_strategy: arb_params.fee_strategy,
+            fee_politic: FeePriority::Low,
+            punish_policy: PunishPolicy::new(),
+            alice_adaptor: self.get(SwapRole::Alice).get_encryption_key()?,
+            bob_adaptor: self.get(SwapRole::Bob).get_encryption_key()?,
+            broadcast_mode: BroadcastMode::Standard,
+        };
+        let setup = ArbitratingSetup::new(swap_id(), &funding, params)?;
+
+        let regular = |role, key_id| (role, key_id, false);
+        let adaptor = |role, key_id| (role, key_id, true);
+        Ok(vec![
+            self.transaction_vector(
+                TxLabel::Lock,
+                setup.lock(),
+                ScriptPath::Success,
+                &[regular(SwapRole::Bob, ArbitratingKeyId::Lock)],
+            )?,
+            self.transaction_vector(
+                TxLabel::Cancel,
+                setup.cancel(),
+                ScriptPath::Failure,
+                &[
+                    regular(SwapRole::Alice, ArbitratingKeyId::Cancel),
+                    regular(SwapRole::Bob, ArbitratingKeyId::Cancel),
+                ],
+            )?,
+            self.transaction_vector(
+                TxLabel::Refund,
+                setup.refund(),
+                ScriptPath::Success,
+                &[
+                    adaptor(SwapRole::Alice, ArbitratingKeyId::Refund),
+                    regular(SwapRole::Bob, ArbitratingKeyId::Refund),
+                ],
+            )?,
+            self.transaction_vector(
+                TxLabel::Buy,
+                setup.buy(),
+                ScriptPath::Success,
+                &[
+                    regular(SwapRole::Alice, ArbitratingKeyId::Buy),
+                    adaptor(SwapRole::Bob, ArbitratingKeyId::Buy),
+                ],
+            )?,
+            self.transaction_vector(
+                TxLabel::Punish,
+                setup.punish(),
+                ScriptPath::Failure,
+                &[regular(SwapRole::Alice, ArbitratingKeyId::Punish)],
+            )?,
+        ])
+    }
+
+    // Sign a copy of `tx` with all the `signers` and record the PSBT at each stage
+    fn transaction_vector<T: SubTransaction>(
+        &mut self,
+        label: TxLabel,
+        tx: &Tx<T>,
+        path: ScriptPath,
+        signers: &[Signer],
+    ) -> Res<Vector> {
+        let mut tx = Tx::<T>::from_partial(tx.as_psbt().clone());
+        let msg = tx.generate_witness_message(path)?;
+        let mut vector = Vector::new(format!("{} transaction", label))
+            .output("unsigned_psbt", tx.as_psbt().as_canonical_bytes())
+            .output("sighash", msg);
+        for &(role, key_id, is_adaptor) in signers {
+            let name = format!("{}_{}", role, key_id).to_lowercase();
+            let pubkey = self.pubkey(role, key_id)?;
+            let sig = if is_adaptor {
+                let encryption_key = self.get(role.other()).get_encryption_key()?;
+                let encrypted_sig = self.get(role).encrypt_sign(key_id, &encryption_key, msg)?;
+                vector = vector.output(
+                    format!("{}_encrypted_signature", name),
+                    encrypted_sig.as_canonical_bytes(),
+                );
+                self.get(role.other())
+                    .decrypt_signature(AccordantKeyId::Spend, encrypted_sig)?
+            } else {
+                self.get(role).sign(key_id, msg)?
+            };
+            tx.add_witness(pubkey, sig)?;
+            vector = vector
+                .input(format!("{}_pubkey", name), pubkey.serialize())
+                .output(format!("{}_signature", name), sig.as_canonical_bytes());
+        }
+        let signed_psbt = tx.as_psbt().as_canonical_bytes();
+        let final_tx = Broadcastable::<bitcoin::Transaction>::finalize_and_extract(&mut tx)?;
+        Ok(vector
+            .output("signed_psbt", signed_psbt)
+            .output("final_tx", final_tx.as_canonical_bytes()))
+    }
+}
+
+fn swap_id() -> SwapId {
+    uuid::Uuid::from_bytes(SWAP_ID).into()
+}
+
+fn address(pubkey: PublicKey) -> bitcoin::Address {
+    bitcoin::Address::p2wpkh(&bitcoin::PublicKey::new(pubkey), bitcoin::Network::Regtest)
+        .expect("Compressed keys are valid")
+}
+
+fn deal(node_id: PublicKey) -> Deal {
+    DealParameters {
+        uuid: uuid::Uuid::from_bytes(SWAP_ID).into(),
+        network: Network::Local,
+        arbitrating_blockchain: Blockchain::Bitcoin,
+        accordant_blockchain: Blockchain::Monero,
+        arbitrating_amount: Amount::from_sat(100_000),
+        accordant_amount: AccordantAmount::from_atomic_units(1_000_000_000),
+        cancel_timelock: CSVTimelock::new(10),
+        punish_timelock: CSVTimelock::new(10),
+        fee_strategy: FeeStrategy::Fixed(SatPerKvB::from_sat(1000)),
+        maker_role: SwapRole::Bob,
+    }
+    .to_v1(
+        node_id,
+        InetSocketAddr::socket(FromStr::from_str("127.0.0.1").expect("Valid address"), 9735),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_reproducible_vectors() {
+        let files = generate().unwrap();
+        let again = generate().unwrap();
+        assert_eq!(files.len(), 6);
+        for (file, other) in files.iter().zip(again.iter()) {
+            assert!(!file.vectors.is_empty());
+            for (vector, other) in file.vectors.iter().zip(other.vectors.iter()) {
+                assert!(
+                    vector.reproducible,
+                    "{} is not reproducible",
+                    vector.description
+                );
+                assert_eq!(vector, other);
+            }
+            let decoded: VectorFile = serde_json::from_str(&file.to_json()).unwrap();
+            assert_eq!(&decoded, file);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Abstraction of the blockchain backend a swap daemon relies on to broadcast transactions and
+//! follow their confirmations. The events reported by a [`Syncer`] feed the
+//! [`RaceDetector`](crate::protocol::race::RaceDetector).
+
+use thiserror::Error;
+
+use crate::protocol::race::ChainEvent;
+use crate::transaction::TxLabel;
+
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub mod asynchronous;
+#[cfg(any(test, feature = "test-utils"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-utils")))]
+pub mod mock;
+
+/// Error returned by the default [`Syncer::broadcast_package`] of backends without package relay.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("The backend does not support package relay")]
+pub struct PackageRelayUnsupported;
+
+/// A blockchain backend broadcasting transactions of type `Tx` and tracking the arbitrating
+/// transactions of a swap.
+pub trait Syncer<Tx> {
+    /// Identifier of a transaction.
+    type Txid;
+
+    /// Error returned when a backend operation fails.
+    type Error: From<PackageRelayUnsupported>;
+
+    /// Broadcast a transaction and return its identifier.
+    fn broadcast(&mut self, tx: Tx) -> Result<Self::Txid, Self::Error>;
+
+    /// Broadcast a package of transactions, parents first, evaluated together by the mempool,
+    /// e.g. with Bitcoin Core `submitpackage`. Return the identifiers in the same order.
+    ///
+    /// The default implementation fails with [`PackageRelayUnsupported`]: broadcast one by one a
+    /// TRUC parent paying a low fee would not enter the mempool, backends supporting package relay
+    /// must override it.
+    fn broadcast_package(&mut self, _package: Vec<Tx>) -> Result<Vec<Self::Txid>, Self::Error> {
+        Err(PackageRelayUnsupported.into())
+    }
+
+    /// Return the current chain height.
+    fn height(&self) -> u32;
+
+    /// Return the number of confirmations of a transaction, `Some(0)` if it is in the mempool and
+    /// `None` if it is unknown.
+    fn confirmations(&self, txid: &Self::Txid) -> Option<u32>;
+
+    /// Start tracking a transaction of the swap, its state changes are reported as
+    /// [`ChainEvent`]s with the given label.
+    fn watch(&mut self, label: TxLabel, txid: Self::Txid);
+
+    /// Return the events occurred since the last call.
+    fn poll_events(&mut self) -> Vec<ChainEvent>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Broadcaster(Vec<u32>);
+
+    impl Syncer<u32> for Broadcaster {
+        type Txid = u32;
+        type Error = PackageRelayUnsupported;
+
+        fn broadcast(&mut self, tx: u32) -> Result<u32, PackageRelayUnsupported> {
+            self.0.push(tx);
+            Ok(tx)
+        }
+
+        fn height(&self) -> u32 {
+            0
+        }
+
+        fn confirmations(&self, _txid: &u32) -> Option<u32> {
+            None
+        }
+
+        fn watch(&mut self, _label: TxLabel, _txid: u32) {}
+
+        fn poll_events(&mut self) -> Vec<ChainEvent> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn package_relay_is_not_emulated() {
+        let mut syncer = Broadcaster::default();
+        assert_eq!(
+            syncer.broadcast_package(vec![1, 2]),
+            Err(PackageRelayUnsupported)
+        );
+        // Nothing is broadcast one by one
+        assert!(syncer.0.is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Asynchronous version of the [`Syncer`](crate::syncer::Syncer) trait for daemons running on an
+//! async runtime, e.g. tokio, and helpers to wait for confirmations before acting without
+//! blocking a thread.
+
+use async_trait::async_trait;
+
+use crate::protocol::race::ChainEvent;
+use crate::syncer::PackageRelayUnsupported;
+use crate::transaction::TxLabel;
+
+/// An asynchronous blockchain backend broadcasting transactions of type `Tx` and tracking the
+/// arbitrating transactions of a swap.
+#[async_trait]
+pub trait AsyncSyncer<Tx>: Send
+where
+    Tx: Send + 'static,
+{
+    /// Identifier of a transaction.
+    type Txid: Send + Sync;
+
+    /// Error returned when a backend operation fails.
+    type Error: Send + From<PackageRelayUnsupported>;
+
+    /// Broadcast a transaction and return its identifier.
+    async fn broadcast(&mut self, tx: Tx) -> Result<Self::Txid, Self::Error>;
+
+    /// Broadcast a package of transactions, parents first, evaluated together by the mempool.
+    /// See [`Syncer::broadcast_package`](crate::syncer::Syncer::broadcast_package), the default
+    /// implementation fails with [`PackageRelayUnsupported`].
+    async fn broadcast_package(
+        &mut self,
+        _package: Vec<Tx>,
+    ) -> Result<Vec<Self::Txid>, Self::Error> {
+        Err(PackageRelayUnsupported.into())
+    }
+
+    /// Return the current chain height.
+    async fn height(&self) -> Result<u32, Self::Error>;
+
+    /// Return the number of confirmations of a transaction, `Some(0)` if it is in the mempool and
+    /// `None` if it is unknown.
+    async fn confirmations(&self, txid: &Self::Txid) -> Result<Option<u32>, Self::Error>;
+
+    /// Start tracking a transaction of the swap, its state changes are reported as
+    /// [`ChainEvent`]s with the given label.
+    async fn watch(&mut self, label: TxLabel, txid: Self::Txid) -> Result<(), Self::Error>;
+
+    /// Return the events occurred since the last call.
+    async fn poll_events(&mut self) -> Result<Vec<ChainEvent>, Self::Error>;
+
+    /// Wait until a new block is mined and return the new chain height.
+    async fn next_block(&mut self) -> Result<u32, Self::Error>;
+}
+
+/// Wait until the transaction has at least `depth` confirmations and return its number of
+/// confirmations. A new block is awaited each time the transaction is not deep enough.
+pub async fn wait_for_confirmations<Tx, S>(
+    syncer: &mut S,
+    txid: &S::Txid,
+    depth: u32,
+) -> Result<u32, S::Error>
+where
+    Tx: Send + 'static,
+    S: AsyncSyncer<Tx> + ?Sized,
+{
+    loop {
+        match syncer.confirmations(txid).await? {
+            Some(confirmations) if confirmations >= depth => return Ok(confirmations),
+            _ => {
+                syncer.next_block().await?;
+            }
+        }
+    }
+}
+
+/// Wait until `dependency` has at least `depth` confirmations, then broadcast `tx`. E.g. Bob
+/// broadcasting `refund` once `cancel` is final.
+pub async fn broadcast_after_confirmations<Tx, S>(
+    syncer: &mut S,
+    dependency: &S::Txid,
+    depth: u32,
+    tx: Tx,
+) -> Result<S::Txid, S::Error>
+where
+    Tx: Send + 'static,
+    S: AsyncSyncer<Tx> + ?Sized,
+{
+    wait_for_confirmations(syncer, dependency, depth).await?;
+    syncer.broadcast(tx).await
+}
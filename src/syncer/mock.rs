@@ -0,0 +1,342 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! An in-memory [`Syncer`] with scriptable behavior for deterministic tests: blocks are mined
+//! on demand, broadcasts can be forced to fail, transactions can be injected as if broadcast by
+//! the counterparty, and reorgs can be triggered, reported with [`ChainEvent::Reorged`].
+
+use std::collections::hash_map::{Entry, HashMap};
+
+use bitcoin::{Transaction, Txid};
+use thiserror::Error;
+
+use crate::protocol::race::ChainEvent;
+use crate::syncer::{PackageRelayUnsupported, Syncer};
+use crate::transaction::TxLabel;
+
+/// Errors returned by the [`MockSyncer`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MockError {
+    /// The broadcast was forced to fail.
+    #[error("Broadcast of {0} rejected")]
+    BroadcastRejected(Txid),
+    /// The backend does not support package relay.
+    #[error(transparent)]
+    PackageRelay(#[from] PackageRelayUnsupported),
+}
+
+/// In-memory Bitcoin backend. Transactions broadcast or injected enter the mempool and are
+/// confirmed in the next mined block.
+#[derive(Debug, Clone, Default)]
+pub struct MockSyncer {
+    height: u32,
+    transactions: HashMap<Txid, Transaction>,
+    mempool: Vec<Txid>,
+    confirmed: HashMap<Txid, u32>,
+    watched: HashMap<Txid, TxLabel>,
+    failing_broadcasts: usize,
+    events: Vec<ChainEvent>,
+}
+
+impl MockSyncer {
+    /// Create a new backend with the chain tip at height zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new backend with the chain tip at `height`.
+    pub fn with_height(height: u32) -> Self {
+        Self {
+            height,
+            ..Self::default()
+        }
+    }
+
+    /// Force the next `count` broadcasts to fail.
+    pub fn fail_broadcasts(&mut self, count: usize) {
+        self.failing_broadcasts = count;
+    }
+
+    /// Add a transaction to the mempool as if broadcast by someone else, never fails.
+    pub fn inject(&mut self, tx: Transaction) -> Txid {
+        let txid = tx.txid();
+        if let Entry::Vacant(entry) = self.transactions.entry(txid) {
+            entry.insert(tx);
+            self.enter_mempool(txid);
+        }
+        txid
+    }
+
+    /// Return a transaction known by the backend.
+    pub fn transaction(&self, txid: &Txid) -> Option<&Transaction> {
+        self.transactions.get(txid)
+    }
+
+    /// Mine `blocks` blocks, the current mempool is confirmed in the first one.
+    pub fn mine(&mut self, blocks: u32) {
+        if blocks == 0 {
+            return;
+        }
+        self.height += 1;
+        for txid in std::mem::take(&mut self.mempool) {
+            self.confirmed.insert(txid, self.height);
+            if let Some(label) = self.watched.get(&txid) {
+                self.events.push(ChainEvent::Confirmed(*label, self.height));
+            }
+        }
+        self.height += blocks - 1;
+        self.events.push(ChainEvent::Height(self.height));
+    }
+
+    /// Remove the last `depth` blocks and mine the same number of empty blocks, transactions
+    /// confirmed in the removed blocks go back to the mempool.
+    pub fn reorg(&mut self, depth: u32) {
+        let fork = self.height.saturating_sub(depth);
+        let mut reorged: Vec<(Txid, u32)> = self
+            .confirmed
+            .iter()
+            .filter(|(_, height)| **height > fork)
+            .map(|(txid, height)| (*txid, *height))
+            .collect();
+        // Keep the original confirmation order in the mempool
+        reorged.sort_by_key(|(_, height)| *height);
+        for (txid, _) in reorged {
+            self.confirmed.remove(&txid);
+            self.mempool.push(txid);
+            if let Some(label) = self.watched.get(&txid) {
+                self.events.push(ChainEvent::Reorged {
+                    tx: *label,
+                    new_depth: 0,
+                });
+            }
+        }
+        self.events.push(ChainEvent::Height(self.height));
+    }
+
+    fn enter_mempool(&mut self, txid: Txid) {
+        self.mempool.push(txid);
+        if let Some(label) = self.watched.get(&txid) {
+            self.events.push(ChainEvent::Mempool(*label));
+        }
+    }
+}
+
+impl Syncer<Transaction> for MockSyncer {
+    type Txid = Txid;
+    type Error = MockError;
+
+    fn broadcast(&mut self, tx: Transaction) -> Result<Txid, MockError> {
+        if self.failing_broadcasts > 0 {
+            self.failing_broadcasts -= 1;
+            return Err(MockError::BroadcastRejected(tx.txid()));
+        }
+        Ok(self.inject(tx))
+    }
+
+    /// The package is accepted or rejected as a whole, a forced failure rejects it entirely.
+    fn broadcast_package(&mut self, package: Vec<Transaction>) -> Result<Vec<Txid>, MockError> {
+        if self.failing_broadcasts > 0 {
+            self.failing_broadcasts -= 1;
+            let txid = package.first().map(Transaction::txid).unwrap_or_default();
+            return Err(MockError::BroadcastRejected(txid));
+        }
+        Ok(package.into_iter().map(|tx| self.inject(tx)).collect())
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn confirmations(&self, txid: &Txid) -> Option<u32> {
+        match self.confirmed.get(txid) {
+            Some(height) => Some(self.height - height + 1),
+            None => self.mempool.contains(txid).then(|| 0),
+        }
+    }
+
+    fn watch(&mut self, label: TxLabel, txid: Txid) {
+        self.watched.insert(txid, label);
+        match self.confirmed.get(&txid) {
+            Some(height) => self.events.push(ChainEvent::Confirmed(label, *height)),
+            None if self.mempool.contains(&txid) => self.events.push(ChainEvent::Mempool(label)),
+            None => (),
+        }
+    }
+
+    fn poll_events(&mut self) -> Vec<ChainEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// The mock has no notion of time, waiting for the next block mines it.
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[async_trait::async_trait]
+impl crate::syncer::asynchronous::AsyncSyncer<Transaction> for MockSyncer {
+    type Txid = Txid;
+    type Error = MockError;
+
+    async fn broadcast(&mut self, tx: Transaction) -> Result<Txid, MockError> {
+        Syncer::broadcast(self, tx)
+    }
+
+    async fn broadcast_package(
+        &mut self,
+        package: Vec<Transaction>,
+    ) -> Result<Vec<Txid>, MockError> {
+        Syncer::broadcast_package(self, package)
+    }
+
+    async fn height(&self) -> Result<u32, MockError> {
+        Ok(Syncer::height(self))
+    }
+
+    async fn confirmations(&self, txid: &Txid) -> Result<Option<u32>, MockError> {
+        Ok(Syncer::confirmations(self, txid))
+    }
+
+    async fn watch(&mut self, label: TxLabel, txid: Txid) -> Result<(), MockError> {
+        Syncer::watch(self, label, txid);
+        Ok(())
+    }
+
+    async fn poll_events(&mut self) -> Result<Vec<ChainEvent>, MockError> {
+        Ok(Syncer::poll_events(self))
+    }
+
+    async fn next_block(&mut self) -> Result<u32, MockError> {
+        self.mine(1);
+        Ok(self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::protocol::race::{RaceDetector, RecommendedAction};
+    use crate::role::SwapRole;
+
+    fn tx(id: u32) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: id,
+            input: vec![],
+            output: vec![],
+        }
+    }
+
+    fn feed(detector: &mut RaceDetector, syncer: &mut MockSyncer) -> Option<RecommendedAction> {
+        syncer
+            .poll_events()
+            .into_iter()
+            .fold(None, |_, event| detector.on_event(event))
+    }
+
+    #[test]
+    fn confirmations_advance_and_reorg() {
+        let mut syncer = MockSyncer::with_height(100);
+        let lock = syncer.broadcast(tx(1)).unwrap();
+        syncer.watch(TxLabel::Lock, lock);
+        assert_eq!(syncer.confirmations(&lock), Some(0));
+        assert_eq!(
+            syncer.poll_events(),
+            vec![ChainEvent::Mempool(TxLabel::Lock)]
+        );
+
+        syncer.mine(3);
+        assert_eq!(syncer.height(), 103);
+        assert_eq!(syncer.confirmations(&lock), Some(3));
+        assert_eq!(
+            syncer.poll_events(),
+            vec![
+                ChainEvent::Confirmed(TxLabel::Lock, 101),
+                ChainEvent::Height(103)
+            ]
+        );
+
+        syncer.reorg(3);
+        assert_eq!(syncer.confirmations(&lock), Some(0));
+        assert_eq!(
+            syncer.poll_events(),
+            vec![
+                ChainEvent::Reorged {
+                    tx: TxLabel::Lock,
+                    new_depth: 0
+                },
+                ChainEvent::Height(103)
+            ]
+        );
+        syncer.mine(1);
+        assert_eq!(syncer.confirmations(&lock), Some(1));
+        assert_eq!(syncer.confirmations(&tx(2).txid()), None);
+    }
+
+    #[test]
+    fn forced_broadcast_failure() {
+        let mut syncer = MockSyncer::new();
+        syncer.fail_broadcasts(1);
+        assert_eq!(
+            syncer.broadcast(tx(1)),
+            Err(MockError::BroadcastRejected(tx(1).txid()))
+        );
+        assert!(syncer.broadcast(tx(1)).is_ok());
+    }
+
+    #[test]
+    fn drive_race_detector() {
+        let mut syncer = MockSyncer::with_height(100);
+        let mut detector = RaceDetector::new(SwapRole::Bob, 10u32, 20u32);
+
+        let lock = syncer.broadcast(tx(1)).unwrap();
+        syncer.watch(TxLabel::Lock, lock);
+        syncer.mine(1);
+        assert_eq!(feed(&mut detector, &mut syncer), None);
+
+        syncer.mine(9);
+        assert_eq!(
+            feed(&mut detector, &mut syncer),
+            Some(RecommendedAction::BroadcastCancelNow)
+        );
+
+        // Alice's buy shows up in the mempool before our cancel
+        let buy = syncer.inject(tx(2));
+        syncer.watch(TxLabel::Buy, buy);
+        assert_eq!(
+            feed(&mut detector, &mut syncer),
+            Some(RecommendedAction::ExtractSecretFromBuy)
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn wait_then_broadcast() {
+        use crate::syncer::asynchronous::broadcast_after_confirmations;
+
+        let mut syncer = MockSyncer::with_height(100);
+        let cancel = syncer.broadcast(tx(1)).unwrap();
+        let refund = futures::executor::block_on(broadcast_after_confirmations(
+            &mut syncer,
+            &cancel,
+            3,
+            tx(2),
+        ))
+        .unwrap();
+        assert_eq!(Syncer::height(&syncer), 103);
+        assert_eq!(syncer.confirmations(&cancel), Some(3));
+        assert_eq!(syncer.confirmations(&refund), Some(0));
+    }
+}
@@ -33,11 +33,13 @@
 //! - The deal parameters, containing the asset types, amounts, timings, etc.
 //! - A node identifier, used to secure the communication with the other peer
 //! - A peer address, used to connect to the other peer
+//! - Since version 2, the [`DealTerms`]: the minimum accordant amount a taker can pick and the
+//!   deal expiry
 
 use bitcoin::secp256k1::PublicKey;
 use inet2_addr::InetSocketAddr;
-use serde::ser::{Serialize, Serializer};
-use serde::{de, Deserialize, Deserializer};
+use serde::ser::Serialize;
+use serde::Deserialize;
 use std::fmt::Display;
 use std::str::FromStr;
 use strict_encoding::{StrictDecode, StrictEncode};
@@ -47,13 +49,14 @@ use tiny_keccak::{Hasher, Keccak};
 use std::fmt;
 use std::io;
 
-use crate::blockchain::{Blockchain, FeeStrategy, Network};
+use crate::blockchain::{Blockchain, FeePolitics, FeeStrategy, Network};
 use crate::consensus::{self, serialize, serialize_hex, CanonicalBytes, Decodable, Encodable};
-use crate::hash::HashString;
 use crate::protocol::ArbitratingParameters;
 use crate::role::{SwapRole, TradeRole};
 use crate::Uuid;
 
+pub mod listing;
+
 /// First six magic bytes of a deal. Bytes are included inside the base58 encoded part.
 pub const DEAL_MAGIC_BYTES: &[u8; 6] = b"FCSWAP";
 
@@ -71,6 +74,11 @@ impl Version {
         Self::new(1)
     }
 
+    /// Create a new version 2 deal, with [`DealTerms`].
+    pub fn new_v2() -> Self {
+        Self::new(2)
+    }
+
     /// Create a deal from a raw version and feature `u16`.
     pub fn new(version: u16) -> Self {
         Version(version)
@@ -103,6 +111,21 @@ pub enum Error {
     /// The deal signature does not pass the validation tests.
     #[error("Invalid signature")]
     InvalidSignature,
+    /// The signing key is not the node identity key of the deal.
+    #[error("The signing key does not match the deal node identifier")]
+    NodeIdMismatch,
+    /// The deal or its listing is expired.
+    #[error("The deal is expired")]
+    Expired,
+    /// The accordant amount picked by the taker is not within the deal bounds.
+    #[error("The accordant amount is out of the deal bounds")]
+    AmountOutOfRange,
+    /// The exchange rate is not defined, one of its amounts is zero.
+    #[error("The exchange rate is invalid")]
+    InvalidRate,
+    /// The converted amount does not fit in the amount type.
+    #[error("The converted amount overflows")]
+    AmountOverflow,
 }
 
 /// The identifier of a trade. This is a wrapper around [`Uuid`] that can be transformed into a
@@ -138,6 +161,12 @@ impl From<uuid::Uuid> for DealId {
     }
 }
 
+impl fmt::LowerHex for DealId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
 impl FromStr for DealId {
     type Err = uuid::Error;
 
@@ -152,24 +181,7 @@ fixed_hash::construct_fixed_hash!(
     pub struct DealFingerprint(32);
 );
 
-impl Serialize for DealFingerprint {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        serializer.serialize_str(format!("{:#x}", self).as_ref())
-    }
-}
-
-impl<'de> Deserialize<'de> for DealFingerprint {
-    fn deserialize<D>(deserializer: D) -> Result<DealFingerprint, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        DealFingerprint::from_str(&deserializer.deserialize_string(HashString)?)
-            .map_err(de::Error::custom)
-    }
-}
+impl_serde_hex!(DealFingerprint);
 
 /// `DealParameters` is created by a [`TradeRole::Maker`] before the start of his daemon, it
 /// references all the data needed to parametrize a deal and be validated from a
@@ -217,6 +229,35 @@ mod string {
 
     use serde::{de, Deserialize, Deserializer, Serializer};
 
+    pub mod option {
+        use std::fmt::Display;
+        use std::str::FromStr;
+
+        use serde::{de, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            T: Display,
+            S: Serializer,
+        {
+            match value {
+                Some(value) => serializer.collect_str(value),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+        where
+            T: FromStr,
+            T::Err: Display,
+            D: Deserializer<'de>,
+        {
+            Option::<String>::deserialize(deserializer)?
+                .map(|s| s.parse().map_err(de::Error::custom))
+                .transpose()
+        }
+    }
+
     pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
     where
         T: Display,
@@ -269,6 +310,23 @@ impl<Amt, Bmt, Ti, F> DealParameters<Amt, Bmt, Ti, F> {
             parameters: self,
             node_id,
             peer_address,
+            terms: DealTerms::default(),
+        }
+    }
+
+    /// Transform the deal parameters in a deal of [`Version`] 2 with the given `terms`.
+    pub fn to_v2(
+        self,
+        node_id: PublicKey,
+        peer_address: InetSocketAddr,
+        terms: DealTerms<Bmt>,
+    ) -> Deal<Amt, Bmt, Ti, F> {
+        Deal {
+            version: Version::new_v2(),
+            parameters: self,
+            node_id,
+            peer_address,
+            terms,
         }
     }
 
@@ -372,6 +430,115 @@ where
 
 impl_strict_encoding!(DealParameters<Amt, Bmt, Ti, F>, Amt: CanonicalBytes, Bmt: CanonicalBytes, Ti: CanonicalBytes, F: CanonicalBytes,);
 
+/// Expiry of a deal, takers must not accept a deal once expired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+pub enum DealExpiry {
+    /// The deal expires once the arbitrating blockchain reaches this block height.
+    #[display("block height {0}")]
+    Height(u32),
+    /// The deal expires at this UNIX timestamp, in seconds.
+    #[display("timestamp {0}")]
+    Timestamp(u64),
+}
+
+impl DealExpiry {
+    /// Returns `true` if the deal is expired given the current arbitrating blockchain `height`
+    /// and UNIX `timestamp` in seconds.
+    pub fn is_expired(&self, height: u32, timestamp: u64) -> bool {
+        match self {
+            Self::Height(expiry) => height >= *expiry,
+            Self::Timestamp(expiry) => timestamp >= *expiry,
+        }
+    }
+}
+
+impl Encodable for DealExpiry {
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        match self {
+            Self::Height(height) => Ok(0x01u8.consensus_encode(s)? + height.consensus_encode(s)?),
+            Self::Timestamp(timestamp) => {
+                Ok(0x02u8.consensus_encode(s)? + timestamp.consensus_encode(s)?)
+            }
+        }
+    }
+}
+
+impl Decodable for DealExpiry {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match u8::consensus_decode(d)? {
+            0x01u8 => Ok(Self::Height(Decodable::consensus_decode(d)?)),
+            0x02u8 => Ok(Self::Timestamp(Decodable::consensus_decode(d)?)),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl_strict_encoding!(DealExpiry);
+
+/// Terms of a deal added in [`Version`] 2. Without terms a deal is for the exact amounts of its
+/// parameters and never expires.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct DealTerms<Bmt> {
+    /// The minimum accordant amount a taker can pick, the deal accordant amount is the maximum.
+    /// The arbitrating amount is scaled accordingly.
+    #[serde(with = "string::option", default)]
+    #[serde(bound(serialize = "Bmt: Display"))]
+    #[serde(bound(deserialize = "Bmt: FromStr, Bmt::Err: Display"))]
+    pub min_accordant_amount: Option<Bmt>,
+    /// The deal expiry.
+    #[serde(default)]
+    pub expiry: Option<DealExpiry>,
+    /// The party bearing the arbitrating fees, see [`FeePolitics`].
+    #[serde(default)]
+    pub fee_politics: Option<FeePolitics>,
+}
+
+impl<Bmt> Default for DealTerms<Bmt> {
+    fn default() -> Self {
+        Self {
+            min_accordant_amount: None,
+            expiry: None,
+            fee_politics: None,
+        }
+    }
+}
+
+impl<Bmt> DealTerms<Bmt> {
+    /// Returns `true` if no term is set.
+    pub fn is_empty(&self) -> bool {
+        self.min_accordant_amount.is_none() && self.expiry.is_none() && self.fee_politics.is_none()
+    }
+}
+
+impl<Bmt> Encodable for DealTerms<Bmt>
+where
+    Bmt: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let len = self
+            .min_accordant_amount
+            .as_canonical_bytes()
+            .consensus_encode(s)?;
+        let len = len + self.expiry.consensus_encode(s)?;
+        Ok(len + self.fee_politics.consensus_encode(s)?)
+    }
+}
+
+impl<Bmt> Decodable for DealTerms<Bmt>
+where
+    Bmt: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            min_accordant_amount: Option::<Bmt>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            expiry: Decodable::consensus_decode(d)?,
+            fee_politics: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(DealTerms<Bmt>, Bmt: CanonicalBytes);
+
 /// A deal is shared across [`TradeRole::Maker`]'s prefered network to signal is willing of trading
 /// some assets at some conditions. The assets and condition are defined in the [`DealParameters`],
 /// maker peer connection information are contained in the deal.
@@ -390,6 +557,11 @@ pub struct Deal<Amt, Bmt, Ti, F> {
     /// Address of the listening daemon's peer. An internet socket address, which consists of an IP
     /// or Tor address and a port number.
     pub peer_address: InetSocketAddr,
+    /// The deal terms, only encoded since [`Version`] 2.
+    #[serde(default, skip_serializing_if = "DealTerms::is_empty")]
+    #[serde(bound(serialize = "Bmt: Display"))]
+    #[serde(bound(deserialize = "Bmt: FromStr, Bmt::Err: Display"))]
+    pub terms: DealTerms<Bmt>,
 }
 
 impl<Amt, Bmt, Ti, F> Deal<Amt, Bmt, Ti, F>
@@ -404,6 +576,8 @@ where
             cancel_timelock: self.parameters.cancel_timelock,
             punish_timelock: self.parameters.punish_timelock,
             fee_strategy: self.parameters.fee_strategy,
+            fee_politics: self.terms.fee_politics,
+            maker_role: self.parameters.maker_role,
         }
     }
 }
@@ -450,6 +624,40 @@ impl<Amt, Bmt, Ti, F> Deal<Amt, Bmt, Ti, F> {
     pub fn swap_role(&self, trade_role: &TradeRole) -> SwapRole {
         self.parameters.swap_role(trade_role)
     }
+
+    /// Returns `true` if the deal is expired given the current arbitrating blockchain `height`
+    /// and UNIX `timestamp` in seconds.
+    pub fn is_expired(&self, height: u32, timestamp: u64) -> bool {
+        self.terms
+            .expiry
+            .map_or(false, |expiry| expiry.is_expired(height, timestamp))
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Deal<Amt, Bmt, Ti, F>
+where
+    Bmt: PartialOrd,
+{
+    /// Validate the acceptance of the deal by a taker picking `accordant_amount`, given the
+    /// current arbitrating blockchain `height` and UNIX `timestamp` in seconds. Fails with
+    /// [`Error::Expired`] if the deal is expired and with [`Error::AmountOutOfRange`] if the
+    /// amount is not within the deal bounds.
+    pub fn validate_acceptance(
+        &self,
+        accordant_amount: &Bmt,
+        height: u32,
+        timestamp: u64,
+    ) -> Result<(), Error> {
+        if self.is_expired(height, timestamp) {
+            return Err(Error::Expired);
+        }
+        let max = &self.parameters.accordant_amount;
+        let min = self.terms.min_accordant_amount.as_ref().unwrap_or(max);
+        if accordant_amount < min || accordant_amount > max {
+            return Err(Error::AmountOutOfRange);
+        }
+        Ok(())
+    }
 }
 
 impl<Amt, Bmt, Ti, F> Display for Deal<Amt, Bmt, Ti, F>
@@ -497,13 +705,17 @@ where
         len += self.version.consensus_encode(s)?;
         len += self.parameters.consensus_encode(s)?;
         len += self.node_id.as_canonical_bytes().consensus_encode(s)?;
-        len +=
-            strict_encoding::StrictEncode::strict_encode(&self.peer_address, s).map_err(|_| {
+        len += strict_encoding::StrictEncode::strict_encode(&self.peer_address, &mut *s).map_err(
+            |_| {
                 io::Error::new(
                     io::ErrorKind::InvalidData,
                     "Failed to encode InetSocketAddr",
                 )
-            })?;
+            },
+        )?;
+        if self.version.to_u16() >= 2 {
+            len += self.terms.consensus_encode(s)?;
+        }
         Ok(len)
     }
 }
@@ -520,12 +732,21 @@ where
         if magic_bytes != *DEAL_MAGIC_BYTES {
             return Err(consensus::Error::IncorrectMagicBytes);
         }
+        let version: Version = Decodable::consensus_decode(d)?;
+        let parameters = Decodable::consensus_decode(d)?;
+        let node_id = PublicKey::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?;
+        let peer_address =
+            strict_encoding::StrictDecode::strict_decode(&mut *d).map_err(consensus::Error::new)?;
+        let terms = match version.to_u16() {
+            v if v >= 2 => Decodable::consensus_decode(d)?,
+            _ => DealTerms::default(),
+        };
         Ok(Deal {
-            version: Decodable::consensus_decode(d)?,
-            parameters: Decodable::consensus_decode(d)?,
-            node_id: PublicKey::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
-            peer_address: strict_encoding::StrictDecode::strict_decode(d)
-                .map_err(consensus::Error::new)?,
+            version,
+            parameters,
+            node_id,
+            peer_address,
+            terms,
         })
     }
 }
@@ -649,7 +870,7 @@ mod tests {
     #[test]
     fn deserialize_deal_params_from_yaml() {
         let s = "---\nuuid: 67e55044-10b1-426f-9247-bb680e5fe0c8\nnetwork: Testnet\narbitrating_blockchain: Bitcoin\naccordant_blockchain: Monero\narbitrating_amount: 0.00000005 BTC\naccordant_amount: 0.000000000006 XMR\ncancel_timelock: 7\npunish_timelock: 8\nfee_strategy:\n  Fixed: 9 satoshi/kvB\nmaker_role: Bob\n";
-        let deal_params = serde_yaml::from_str(&s).expect("Decode deal from yaml");
+        let deal_params = serde_yaml::from_str(s).expect("Decode deal from yaml");
         assert_eq!(
             DealParameters {
                 uuid: uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8").into(),
@@ -682,7 +903,7 @@ mod tests {
     #[test]
     fn deserialize_deal_from_yaml() {
         let s = "---\nversion: 1\nparameters:\n  uuid: 67e55044-10b1-426f-9247-bb680e5fe0c8\n  network: Local\n  arbitrating_blockchain: Bitcoin\n  accordant_blockchain: Monero\n  arbitrating_amount: 0.00001350 BTC\n  accordant_amount: 1000000.001000000000 XMR\n  cancel_timelock: 4\n  punish_timelock: 6\n  fee_strategy:\n    Fixed: 1 satoshi/kvB\n  maker_role: Bob\nnode_id: 02e77b779cdc2c713823f7a19147a67e4209c74d77e2cb5045bce0584a6be064d4\npeer_address:\n  IPv4: \"127.0.0.1:9735\"\n";
-        let deal = serde_yaml::from_str(&s).expect("Decode deal from yaml");
+        let deal = serde_yaml::from_str(s).expect("Decode deal from yaml");
         assert_eq!(
             Deal::<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB>::from_str("Deal:Cke4ftrP5A7CRkYdGNd87TRU6sUP1kBKM1W723UjzEWsNR4gmBqNCsR11111uMFubBevJ2E5fp6ZR11111TBALTh113GTvtvqfD1111114A4TTfifktDH7QZD71vpdfo6EVo2ds7KviHz7vYbLZDkgsMNb11111111111111111111111111111111111111111AfZ113XRBuL3QS1m")
                 .expect("Valid deal"),
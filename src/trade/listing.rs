@@ -0,0 +1,376 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Signed deal listings for orderbook services. A [`SignedDeal`] is a deal signed by the maker
+//! with its node identity key, the `node_id` of the deal, and valid until an expiry timestamp.
+//! Takers can verify the listing was published by the node they will connect to and is still
+//! valid.
+//!
+//! An [`OfferSet`] collects verified listings and filters them by asset pair, network, and
+//! amounts with a [`DealFilter`].
+
+use std::io;
+use std::ops::RangeInclusive;
+
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey, Signing, Verification};
+
+use crate::blockchain::{Blockchain, Network};
+use crate::consensus::{self, serialize, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::commitment::tagged_hash;
+use crate::trade::{Deal, DealId, Error};
+
+/// Tag of the hash signed in [`SignedDeal`].
+pub const DEAL_LISTING_TAG: &[u8] = b"farcaster/deal-listing";
+
+/// A deal signed by its maker, valid until `expires_at`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedDeal<Amt, Bmt, Ti, F> {
+    /// The listed deal.
+    pub deal: Deal<Amt, Bmt, Ti, F>,
+    /// The UNIX timestamp, in seconds, after which the listing is no longer valid.
+    pub expires_at: u64,
+    /// The signature of the deal and its expiry with the maker node identity key.
+    pub signature: Signature,
+}
+
+fn digest<T: Encodable>(deal: &T, expires_at: u64) -> Message {
+    let mut data = serialize(deal);
+    data.extend(expires_at.to_le_bytes());
+    let hash = tagged_hash(DEAL_LISTING_TAG, &data);
+    Message::from_slice(&hash[..]).expect("32 bytes hash")
+}
+
+impl<Amt, Bmt, Ti, F> SignedDeal<Amt, Bmt, Ti, F>
+where
+    Deal<Amt, Bmt, Ti, F>: Encodable,
+{
+    /// Sign the deal with the maker node identity secret key. Fails with
+    /// [`Error::NodeIdMismatch`] if the key does not match the deal `node_id`.
+    pub fn sign<C: Signing>(
+        secp: &Secp256k1<C>,
+        deal: Deal<Amt, Bmt, Ti, F>,
+        expires_at: u64,
+        secret_key: &SecretKey,
+    ) -> Result<Self, Error> {
+        if PublicKey::from_secret_key(secp, secret_key) != deal.node_id {
+            return Err(Error::NodeIdMismatch);
+        }
+        let signature = secp.sign_ecdsa(&digest(&deal, expires_at), secret_key);
+        Ok(Self {
+            deal,
+            expires_at,
+            signature,
+        })
+    }
+
+    /// Verify the listing is signed by the deal `node_id` and not expired at `now`, a UNIX
+    /// timestamp in seconds. Fails with [`Error::InvalidSignature`] or [`Error::Expired`].
+    pub fn verify<C: Verification>(&self, secp: &Secp256k1<C>, now: u64) -> Result<(), Error> {
+        secp.verify_ecdsa(
+            &digest(&self.deal, self.expires_at),
+            &self.signature,
+            &self.deal.node_id,
+        )
+        .map_err(|_| Error::InvalidSignature)?;
+        if self.is_expired(now) {
+            return Err(Error::Expired);
+        }
+        Ok(())
+    }
+}
+
+impl<Amt, Bmt, Ti, F> SignedDeal<Amt, Bmt, Ti, F> {
+    /// Returns `true` if the listing is expired at `now`, a UNIX timestamp in seconds.
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.expires_at
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Encodable for SignedDeal<Amt, Bmt, Ti, F>
+where
+    Deal<Amt, Bmt, Ti, F>: Encodable,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.deal.consensus_encode(s)?;
+        len += self.expires_at.consensus_encode(s)?;
+        Ok(len + self.signature.as_canonical_bytes().consensus_encode(s)?)
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Decodable for SignedDeal<Amt, Bmt, Ti, F>
+where
+    Deal<Amt, Bmt, Ti, F>: Decodable,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            deal: Decodable::consensus_decode(d)?,
+            expires_at: Decodable::consensus_decode(d)?,
+            signature: Signature::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(SignedDeal<Amt, Bmt, Ti, F>, Amt: CanonicalBytes, Bmt: CanonicalBytes, Ti: CanonicalBytes, F: CanonicalBytes,);
+
+/// Criteria to select deals in an [`OfferSet`], unset criteria match all the deals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DealFilter<Amt, Bmt> {
+    /// The arbitrating blockchain of the deal.
+    pub arbitrating_blockchain: Option<Blockchain>,
+    /// The accordant blockchain of the deal.
+    pub accordant_blockchain: Option<Blockchain>,
+    /// The network of the deal.
+    pub network: Option<Network>,
+    /// The accepted range of arbitrating amounts.
+    pub arbitrating_amount: Option<RangeInclusive<Amt>>,
+    /// The accepted range of accordant amounts.
+    pub accordant_amount: Option<RangeInclusive<Bmt>>,
+}
+
+impl<Amt, Bmt> Default for DealFilter<Amt, Bmt> {
+    fn default() -> Self {
+        Self {
+            arbitrating_blockchain: None,
+            accordant_blockchain: None,
+            network: None,
+            arbitrating_amount: None,
+            accordant_amount: None,
+        }
+    }
+}
+
+impl<Amt, Bmt> DealFilter<Amt, Bmt>
+where
+    Amt: PartialOrd,
+    Bmt: PartialOrd,
+{
+    /// Returns `true` if the deal matches all the criteria.
+    pub fn matches<Ti, F>(&self, deal: &Deal<Amt, Bmt, Ti, F>) -> bool {
+        let params = &deal.parameters;
+        self.arbitrating_blockchain
+            .map_or(true, |b| b == params.arbitrating_blockchain)
+            && self
+                .accordant_blockchain
+                .map_or(true, |b| b == params.accordant_blockchain)
+            && self.network.map_or(true, |n| n == params.network)
+            && self
+                .arbitrating_amount
+                .as_ref()
+                .map_or(true, |r| r.contains(&params.arbitrating_amount))
+            && self
+                .accordant_amount
+                .as_ref()
+                .map_or(true, |r| r.contains(&params.accordant_amount))
+    }
+}
+
+/// A set of verified deal listings, at most one per deal identifier.
+#[derive(Debug, Clone)]
+pub struct OfferSet<Amt, Bmt, Ti, F> {
+    listings: Vec<SignedDeal<Amt, Bmt, Ti, F>>,
+}
+
+impl<Amt, Bmt, Ti, F> Default for OfferSet<Amt, Bmt, Ti, F> {
+    fn default() -> Self {
+        Self { listings: vec![] }
+    }
+}
+
+impl<Amt, Bmt, Ti, F> OfferSet<Amt, Bmt, Ti, F> {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove the listing of the deal `id`, if any.
+    pub fn remove(&mut self, id: DealId) -> Option<SignedDeal<Amt, Bmt, Ti, F>> {
+        let index = self.listings.iter().position(|l| l.deal.id() == id)?;
+        Some(self.listings.remove(index))
+    }
+
+    /// Remove all the listings expired at `now`, a UNIX timestamp in seconds.
+    pub fn prune_expired(&mut self, now: u64) {
+        self.listings.retain(|l| !l.is_expired(now));
+    }
+
+    /// Returns the listing of the deal `id`, if any.
+    pub fn get(&self, id: DealId) -> Option<&SignedDeal<Amt, Bmt, Ti, F>> {
+        self.listings.iter().find(|l| l.deal.id() == id)
+    }
+
+    /// Returns an iterator over all the listings.
+    pub fn iter(&self) -> impl Iterator<Item = &SignedDeal<Amt, Bmt, Ti, F>> {
+        self.listings.iter()
+    }
+
+    /// The number of listings in the set.
+    pub fn len(&self) -> usize {
+        self.listings.len()
+    }
+
+    /// Returns `true` if the set contains no listing.
+    pub fn is_empty(&self) -> bool {
+        self.listings.is_empty()
+    }
+}
+
+impl<Amt, Bmt, Ti, F> OfferSet<Amt, Bmt, Ti, F>
+where
+    Amt: PartialOrd,
+    Bmt: PartialOrd,
+{
+    /// Returns the listings matching the `filter`.
+    pub fn filter<'a>(
+        &'a self,
+        filter: &'a DealFilter<Amt, Bmt>,
+    ) -> impl Iterator<Item = &'a SignedDeal<Amt, Bmt, Ti, F>> {
+        self.listings
+            .iter()
+            .filter(move |l| filter.matches(&l.deal))
+    }
+}
+
+impl<Amt, Bmt, Ti, F> OfferSet<Amt, Bmt, Ti, F>
+where
+    Deal<Amt, Bmt, Ti, F>: Encodable,
+{
+    /// Verify the listing at `now`, see [`SignedDeal::verify`], and insert it in the set. A
+    /// listing of the same deal is replaced and returned.
+    pub fn insert<C: Verification>(
+        &mut self,
+        secp: &Secp256k1<C>,
+        listing: SignedDeal<Amt, Bmt, Ti, F>,
+        now: u64,
+    ) -> Result<Option<SignedDeal<Amt, Bmt, Ti, F>>, Error> {
+        listing.verify(secp, now)?;
+        let previous = self.remove(listing.deal.id());
+        self.listings.push(listing);
+        Ok(previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::{fee::SatPerKvB, timelock::CSVTimelock};
+    use crate::blockchain::FeeStrategy;
+    use crate::role::SwapRole;
+    use crate::trade::DealParameters;
+    use crate::Uuid;
+
+    use bitcoin::Amount;
+    use inet2_addr::InetSocketAddr;
+    use std::str::FromStr;
+
+    type TestDeal = Deal<Amount, monero::Amount, CSVTimelock, SatPerKvB>;
+
+    fn deal(secp: &Secp256k1<secp256k1::All>, sats: u64, network: Network) -> TestDeal {
+        DealParameters {
+            uuid: Uuid::random().into(),
+            network,
+            arbitrating_blockchain: Blockchain::Bitcoin,
+            accordant_blockchain: Blockchain::Monero,
+            arbitrating_amount: Amount::from_sat(sats),
+            accordant_amount: monero::Amount::from_pico(10000),
+            cancel_timelock: CSVTimelock::new(4),
+            punish_timelock: CSVTimelock::new(6),
+            fee_strategy: FeeStrategy::Fixed(SatPerKvB::from_sat(1)),
+            maker_role: SwapRole::Bob,
+        }
+        .to_v1(
+            PublicKey::from_secret_key(secp, &SecretKey::from_slice(&[1; 32]).unwrap()),
+            InetSocketAddr::socket(
+                FromStr::from_str("1.2.3.4").unwrap(),
+                FromStr::from_str("9735").unwrap(),
+            ),
+        )
+    }
+
+    #[test]
+    fn sign_and_verify_listing() {
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let listing =
+            SignedDeal::sign(&secp, deal(&secp, 1350, Network::Testnet), 100, &key).unwrap();
+        assert!(listing.verify(&secp, 99).is_ok());
+        assert!(matches!(listing.verify(&secp, 100), Err(Error::Expired)));
+
+        let bytes = consensus::serialize(&listing);
+        let decoded: SignedDeal<Amount, monero::Amount, CSVTimelock, SatPerKvB> =
+            consensus::deserialize(&bytes).unwrap();
+        assert_eq!(decoded, listing);
+
+        // Extending the expiry invalidates the signature
+        let mut tampered = listing;
+        tampered.expires_at = 200;
+        assert!(matches!(
+            tampered.verify(&secp, 99),
+            Err(Error::InvalidSignature)
+        ));
+
+        // Only the node identity key can sign the listing
+        let other = SecretKey::from_slice(&[2; 32]).unwrap();
+        assert!(matches!(
+            SignedDeal::sign(&secp, deal(&secp, 1350, Network::Testnet), 100, &other),
+            Err(Error::NodeIdMismatch)
+        ));
+    }
+
+    #[test]
+    fn filter_offer_set() {
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let mut offers = OfferSet::new();
+        for (sats, network) in [
+            (1000, Network::Testnet),
+            (5000, Network::Testnet),
+            (5000, Network::Mainnet),
+        ] {
+            let listing = SignedDeal::sign(&secp, deal(&secp, sats, network), 100, &key).unwrap();
+            offers.insert(&secp, listing, 0).unwrap();
+        }
+        let expired = SignedDeal::sign(&secp, deal(&secp, 1, Network::Testnet), 10, &key).unwrap();
+        assert!(offers.insert(&secp, expired, 10).is_err());
+        assert_eq!(offers.len(), 3);
+
+        let filter = DealFilter {
+            arbitrating_blockchain: Some(Blockchain::Bitcoin),
+            accordant_blockchain: Some(Blockchain::Monero),
+            network: Some(Network::Testnet),
+            arbitrating_amount: Some(Amount::from_sat(2000)..=Amount::from_sat(10000)),
+            ..Default::default()
+        };
+        let found: Vec<_> = offers.filter(&filter).collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].deal.parameters.arbitrating_amount,
+            Amount::from_sat(5000)
+        );
+        assert_eq!(offers.filter(&DealFilter::default()).count(), 3);
+
+        // Re-inserting a deal replaces its listing
+        let id = found[0].deal.id();
+        let mut relisted = offers.get(id).unwrap().clone();
+        relisted = SignedDeal::sign(&secp, relisted.deal, 200, &key).unwrap();
+        assert!(offers.insert(&secp, relisted, 0).unwrap().is_some());
+        assert_eq!(offers.len(), 3);
+
+        offers.prune_expired(100);
+        assert_eq!(offers.len(), 1);
+        assert_eq!(offers.get(id).unwrap().expires_at, 200);
+    }
+}
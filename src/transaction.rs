@@ -26,6 +26,7 @@ use thiserror::Error;
 use crate::blockchain::Network;
 use crate::consensus::{self, Decodable, Encodable};
 use crate::script::{DataLock, DataPunishableLock, ScriptPath};
+use crate::swap::SwapId;
 
 /// A list specifying general categories of transaction error.
 #[derive(Error, Debug)]
@@ -54,15 +55,25 @@ pub enum Error {
     /// Not enough assets to create the transaction.
     #[error("Not enough assets to create the transaction")]
     NotEnoughAssets,
+    /// More assets than expected are available to create the transaction.
+    #[error("Too many assets available, the funding is overfunded")]
+    Overfunded,
+    /// The signature is not strictly DER encoded or its `s` value is not in the lower half of the
+    /// curve order, the transaction would not be relayed.
+    #[error("The signature is not canonical")]
+    NonCanonicalSignature,
     /// Wrong transaction template.
     #[error("Wrong transaction template: {0}")]
     WrongTemplate(&'static str),
     /// The transaction chain validation failed
     #[error("The transaction chain validation failed")]
     InvalidTransactionChain,
+    /// The funding address is already used by another swap.
+    #[error("The funding address is already used by swap {0}")]
+    AddressReuse(SwapId),
     /// Any transaction error not part of this list.
     #[error("Transaction error: {0}")]
-    Other(Box<dyn error::Error + Send + Sync>),
+    Other(#[source] Box<dyn error::Error + Send + Sync>),
 }
 
 impl Error {
@@ -90,7 +101,8 @@ impl Error {
 }
 
 /// Base trait for arbitrating transactions. Defines methods to generate a partial arbitrating
-/// transaction used over the network.
+/// transaction used over the network. The partial format is chosen by the blockchain, e.g. a PSBT
+/// on Bitcoin.
 ///
 /// ```
 /// use bitcoin::Amount;
@@ -100,7 +112,9 @@ impl Error {
 ///
 /// pub struct MyTx(PartiallySignedTransaction);
 ///
-/// impl Transaction<PartiallySignedTransaction, TxIn, Amount> for MyTx {
+/// impl Transaction<TxIn, Amount> for MyTx {
+///     type Partial = PartiallySignedTransaction;
+///
 ///     fn as_partial(&self) -> &PartiallySignedTransaction {
 ///         todo!()
 ///     }
@@ -126,19 +140,22 @@ impl Error {
 ///     }
 /// }
 /// ```
-pub trait Transaction<Px, Out, Amt> {
+pub trait Transaction<Out, Amt> {
+    /// The partial format used to exchange unsigned or partially signed transactions.
+    type Partial;
+
     /// Returns a reference to the inner partial transaction data.
-    fn as_partial(&self) -> &Px;
+    fn as_partial(&self) -> &Self::Partial;
 
     /// Returns a mutable reference to the inner partial transaction data.
-    fn as_partial_mut(&mut self) -> &mut Px;
+    fn as_partial_mut(&mut self) -> &mut Self::Partial;
 
     /// Extract the transaction in the defined partial format on the arbitrating blockchain. The
     /// partial format is used to exchange unsigned or patially signed transactions.
-    fn to_partial(self) -> Px;
+    fn to_partial(self) -> Self::Partial;
 
     /// Construct the transaction type from a deserialized partial transaction.
-    fn from_partial(partial: Px) -> Self;
+    fn from_partial(partial: Self::Partial) -> Self;
 
     /// Returns the metadata that identifies the transaction this transaction is build on top.
     fn based_on(&self) -> Out;
@@ -253,8 +270,8 @@ pub trait Linkable<Out> {
 }
 
 /// Implemented by transactions based on another transaction. This trait is auto implemented for
-/// all type `T` that implements `Transaction<Px, Out, Amt>` when and `Out` is `Eq`.
-pub trait Chainable<Px, Out, Amt>: Transaction<Px, Out, Amt>
+/// all type `T` that implements `Transaction<Out, Amt>` when and `Out` is `Eq`.
+pub trait Chainable<Out, Amt>: Transaction<Out, Amt>
 where
     Out: Eq,
 {
@@ -262,10 +279,10 @@ where
     fn is_build_on_top_of(&self, prev: &impl Linkable<Out>) -> Result<(), Error>;
 }
 
-impl<T, Px, Out, Amt> Chainable<Px, Out, Amt> for T
+impl<T, Out, Amt> Chainable<Out, Amt> for T
 where
     Out: Eq,
-    T: Transaction<Px, Out, Amt>,
+    T: Transaction<Out, Amt>,
 {
     fn is_build_on_top_of(&self, prev: &impl Linkable<Out>) -> Result<(), Error> {
         match self.based_on() == prev.get_consumable_output()? {
@@ -312,7 +329,7 @@ pub trait Fundable<Tx, Out, Addr, Pk>: Linkable<Out> {
 /// Represent a lockable transaction such as the `lock (b)` transaction that consumes the `funding
 /// (a)` transaction and creates the scripts used by `buy (c)` and `cancel (d)` transactions.
 pub trait Lockable<Addr, Tx, Px, Out, Amt, Ti, Ms, Pk, Si>:
-    Transaction<Px, Out, Amt> + Broadcastable<Tx> + Linkable<Out> + Witnessable<Ms, Pk, Si>
+    Transaction<Out, Amt, Partial = Px> + Broadcastable<Tx> + Linkable<Out> + Witnessable<Ms, Pk, Si>
 {
     /// Creates a new `lock (b)` transaction based on the `funding (a)` transaction and the data
     /// needed for creating the lock primitive (i.e. the timelock and the keys). Return a new `lock
@@ -362,11 +379,11 @@ pub trait Lockable<Addr, Tx, Px, Out, Amt, Ti, Ms, Pk, Si>:
 /// to take ownership of the counter-party funds. This transaction becomes available directly after
 /// `lock (b)` but should be broadcasted only when `lock (b)` is finalized on-chain.
 pub trait Buyable<Addr, Tx, Px, Out, Amt, Ti, Ms, Pk, Si>:
-    Transaction<Px, Out, Amt>
+    Transaction<Out, Amt, Partial = Px>
     + Broadcastable<Tx>
     + Linkable<Out>
     + Witnessable<Ms, Pk, Si>
-    + Chainable<Px, Out, Amt>
+    + Chainable<Out, Amt>
 where
     Out: Eq,
 {
@@ -388,8 +405,10 @@ where
     /// the correct conditions of the [`DataLock`] and the destination address.
     fn verify_template(&self, destination_target: Addr) -> Result<(), Error>;
 
-    /// Extract the valuable witness from a transaction.
-    fn extract_witness(tx: Tx) -> Si;
+    /// Extract the valuable witness from this transaction seen on-chain. The whole witness is
+    /// searched so a non-standard witness from the counterparty does not prevent the extraction,
+    /// fails if no valid witness is found.
+    fn extract_witness(&self, tx: Tx) -> Result<Si, Error>;
 
     /// Return the Farcaster transaction identifier.
     fn get_label(&self) -> TxLabel {
@@ -402,11 +421,11 @@ where
 /// unilateral path available after some defined timelaps. This transaction becomes available after
 /// the define timelock in `lock (b)`.
 pub trait Cancelable<Addr, Tx, Px, Out, Amt, Ti, Ms, Pk, Si>:
-    Transaction<Px, Out, Amt>
+    Transaction<Out, Amt, Partial = Px>
     + Broadcastable<Tx>
     + Linkable<Out>
     + Witnessable<Ms, Pk, Si>
-    + Chainable<Px, Out, Amt>
+    + Chainable<Out, Amt>
 where
     Out: Eq,
 {
@@ -442,11 +461,11 @@ where
 /// `cancel (d)` transaction and send the money to its original owner. This transaction is directly
 /// available but should be broadcasted only after 'finalization' of `cancel (d)` on-chain.
 pub trait Refundable<Addr, Tx, Px, Out, Amt, Ti, Ms, Pk, Si>:
-    Transaction<Px, Out, Amt>
+    Transaction<Out, Amt, Partial = Px>
     + Broadcastable<Tx>
     + Linkable<Out>
     + Witnessable<Ms, Pk, Si>
-    + Chainable<Px, Out, Amt>
+    + Chainable<Out, Amt>
 where
     Out: Eq,
 {
@@ -467,8 +486,9 @@ where
     /// the correct conditions of the [`DataPunishableLock`] and the refund address.
     fn verify_template(&self, refund_target: Addr) -> Result<(), Error>;
 
-    /// Extract the valuable witness from a transaction.
-    fn extract_witness(tx: Tx) -> Si;
+    /// Extract the valuable witness from this transaction seen on-chain. Fails if the witness
+    /// does not follow the template, e.g. it has been malleated.
+    fn extract_witness(&self, tx: Tx) -> Result<Si, Error>;
 
     /// Return the Farcaster transaction identifier.
     fn get_label(&self) -> TxLabel {
@@ -487,11 +507,11 @@ where
 /// This transaction does not have a `verify_template` function as it is created unilaterally and
 /// thus is fully trusted by the creator.
 pub trait Punishable<Addr, Tx, Px, Out, Amt, Ti, Ms, Pk, Si>:
-    Transaction<Px, Out, Amt>
+    Transaction<Out, Amt, Partial = Px>
     + Broadcastable<Tx>
     + Linkable<Out>
     + Witnessable<Ms, Pk, Si>
-    + Chainable<Px, Out, Amt>
+    + Chainable<Out, Amt>
 where
     Out: Eq,
 {
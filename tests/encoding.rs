@@ -0,0 +1,237 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Golden vectors of the wire encodings. The vectors in `tests/vectors` are part of the
+//! specification: a failure here means the encoding changed and breaks interoperability with
+//! other implementations and previous releases.
+
+use farcaster_core::bitcoin::BitcoinSegwitV0 as Btc;
+use farcaster_core::blockchain::FeePriority;
+use farcaster_core::consensus::{self, deserialize, serialize, serialize_hex};
+use farcaster_core::crypto::CommitmentEngine;
+use farcaster_core::monero::Monero as Xmr;
+use farcaster_core::protocol::message::*;
+use farcaster_core::swap::btcxmr::{
+    message as btcxmr_message, Alice, Bob, Deal, KeyManager, Parameters, SwapParameters,
+};
+use farcaster_core::swap::SwapId;
+
+use bitcoin::Address;
+use uuid::uuid;
+
+use std::fmt::Debug;
+use std::str::FromStr;
+
+macro_rules! vector {
+    ($name:literal) => {
+        hex::decode(include_str!(concat!("vectors/", $name, ".hex")).trim()).unwrap()
+    };
+}
+
+// Decode the vector and check it encodes back byte-for-byte, with both consensus and strict
+// encoding
+fn check_vector<T>(bytes: &[u8]) -> T
+where
+    T: consensus::Encodable
+        + consensus::Decodable
+        + strict_encoding::StrictEncode
+        + strict_encoding::StrictDecode
+        + PartialEq
+        + Debug,
+{
+    let value: T = deserialize(bytes).unwrap();
+    assert_eq!(serialize(&value), bytes);
+    assert_eq!(strict_encoding::strict_serialize(&value).unwrap(), bytes);
+    assert_eq!(
+        strict_encoding::strict_deserialize::<T>(bytes).unwrap(),
+        value
+    );
+    value
+}
+
+fn swap_id() -> SwapId {
+    uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8").into()
+}
+
+fn destination() -> Address {
+    Address::from_str("bc1qesgvtyx9y6lax0x34napc2m7t5zdq6s7xxwpvk").unwrap()
+}
+
+fn parameters() -> (Deal, Parameters, Parameters) {
+    let deal: Deal = check_vector(&vector!("deal"));
+    let alice = Alice::new(Btc::new(), Xmr, destination(), FeePriority::Low);
+    let bob = Bob::new(Btc::new(), Xmr, destination(), FeePriority::Low);
+    let alice_params = alice
+        .generate_parameters(&mut KeyManager::new([32; 32], 1).unwrap(), &deal)
+        .unwrap();
+    let bob_params = bob
+        .generate_parameters(&mut KeyManager::new([1; 32], 1).unwrap(), &deal)
+        .unwrap();
+    (deal, alice_params, bob_params)
+}
+
+#[test]
+fn commit_messages_vectors() {
+    let (_, alice_params, bob_params) = parameters();
+
+    let commit_alice: btcxmr_message::CommitAliceParameters =
+        check_vector(&vector!("commit_alice_parameters"));
+    assert_eq!(
+        commit_alice,
+        alice_params.commit_alice(swap_id(), &CommitmentEngine)
+    );
+
+    let commit_bob: btcxmr_message::CommitBobParameters =
+        check_vector(&vector!("commit_bob_parameters"));
+    assert_eq!(
+        commit_bob,
+        bob_params.commit_bob(swap_id(), &CommitmentEngine)
+    );
+}
+
+#[test]
+fn reveal_messages_vectors() {
+    let (_, alice_params, bob_params) = parameters();
+
+    // Proofs are randomized, only compare the keys
+    let reveal_alice: btcxmr_message::RevealAliceParameters =
+        check_vector(&vector!("reveal_alice_parameters"));
+    let expected = alice_params.reveal_alice(swap_id());
+    assert_eq!(reveal_alice.buy, expected.buy);
+    assert_eq!(reveal_alice.spend, expected.spend);
+    assert_eq!(reveal_alice.address, expected.address);
+
+    let reveal_bob: btcxmr_message::RevealBobParameters =
+        check_vector(&vector!("reveal_bob_parameters"));
+    let expected = bob_params.reveal_bob(swap_id());
+    assert_eq!(reveal_bob.buy, expected.buy);
+    assert_eq!(reveal_bob.spend, expected.spend);
+    assert_eq!(reveal_bob.address, expected.address);
+}
+
+#[test]
+fn abort_vectors() {
+    let abort = Abort {
+        swap_id: swap_id(),
+        error_body: Some("timeout".into()),
+    };
+    assert_eq!(serialize(&abort), vector!("abort"));
+
+    let versioned = Versioned(Abort {
+        swap_id: swap_id(),
+        error_body: None,
+    });
+    let bytes = vector!("versioned_abort");
+    assert_eq!(serialize(&versioned), bytes);
+    let decoded: Versioned<Abort> = deserialize(&bytes).unwrap();
+    assert_eq!(decoded.into_inner().swap_id, swap_id());
+
+    // Unknown versions are rejected
+    let mut bytes = bytes;
+    bytes[0] = 0x02;
+    assert!(deserialize::<Versioned<Abort>>(&bytes).is_err());
+}
+
+#[test]
+fn swap_parameters_vectors() {
+    let (deal, _, _) = parameters();
+    let swap_params: SwapParameters = check_vector(&vector!("swap_parameters"));
+    assert_eq!(
+        serialize_hex(&SwapParameters::from_deal(
+            &deal.parameters,
+            destination(),
+            destination()
+        )),
+        serialize_hex(&swap_params)
+    );
+}
+
+// The no_std primitives decode the same messages, open the commitments and verify the proofs
+#[test]
+fn primitives_decode_messages_vectors() {
+    use farcaster_core::primitives::{commitment, dleq, encoding};
+
+    let commit_bytes = vector!("commit_alice_parameters");
+    let commit: encoding::CommitAliceParameters = encoding::decode(&commit_bytes).unwrap();
+    let mut out = vec![];
+    encoding::Message::encode(&commit, &mut out).unwrap();
+    assert_eq!(out, commit_bytes);
+    assert_eq!(commit.swap_id, serialize(&swap_id())[..]);
+
+    let reveal_bytes = vector!("reveal_alice_parameters");
+    let reveal: encoding::RevealAliceParameters = encoding::decode(&reveal_bytes).unwrap();
+    let mut out = vec![];
+    encoding::Message::encode(&reveal, &mut out).unwrap();
+    assert_eq!(out, reveal_bytes);
+
+    for (commitment, revealed) in [
+        (commit.buy, reveal.buy),
+        (commit.adaptor, reveal.adaptor),
+        (commit.spend, reveal.spend),
+    ] {
+        assert!(commitment::verify_commitment(commitment, revealed));
+    }
+    assert!(!commitment::verify_commitment(commit.buy, reveal.adaptor));
+
+    assert!(dleq::verify(
+        reveal.proof,
+        reveal.spend.try_into().unwrap(),
+        reveal.adaptor.try_into().unwrap(),
+    )
+    .is_ok());
+    assert_eq!(
+        dleq::verify(
+            reveal.proof,
+            reveal.spend.try_into().unwrap(),
+            reveal.buy.try_into().unwrap(),
+        ),
+        Err(dleq::Error::InvalidPedersenCommitment)
+    );
+
+    let commit_bytes = vector!("commit_bob_parameters");
+    let commit: encoding::CommitBobParameters = encoding::decode(&commit_bytes).unwrap();
+    let reveal_bytes = vector!("reveal_bob_parameters");
+    let reveal: encoding::RevealBobParameters = encoding::decode(&reveal_bytes).unwrap();
+    assert!(commitment::verify_commitment(
+        commit.adaptor,
+        reveal.adaptor
+    ));
+    assert!(dleq::verify(
+        reveal.proof,
+        reveal.spend.try_into().unwrap(),
+        reveal.adaptor.try_into().unwrap(),
+    )
+    .is_ok());
+
+    // Versioned messages
+    let bytes = vector!("versioned_abort");
+    assert_eq!(bytes[..2], encoding::MESSAGE_ENCODING_VERSION.to_le_bytes());
+    let versioned = encoding::encode_versioned(&commit).unwrap();
+    assert_eq!(
+        encoding::decode_versioned::<encoding::CommitBobParameters>(&versioned).unwrap(),
+        commit
+    );
+    let mut versioned = versioned;
+    versioned[0] = 0x02;
+    assert_eq!(
+        encoding::decode_versioned::<encoding::CommitBobParameters>(&versioned),
+        Err(encoding::Error::UnsupportedVersion(2))
+    );
+    assert_eq!(
+        encoding::decode::<encoding::CommitBobParameters>(&commit_bytes[..commit_bytes.len() - 1]),
+        Err(encoding::Error::UnexpectedEnd)
+    );
+}
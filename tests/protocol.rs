@@ -14,26 +14,37 @@
 // License along with this library; if not, write to the Free Software
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
 
+use farcaster_core::bitcoin::segwitv0::watchtower::WatchtowerPackage;
 use farcaster_core::bitcoin::segwitv0::{BuyTx, CancelTx, FundingTx, LockTx, PunishTx, RefundTx};
 use farcaster_core::bitcoin::BitcoinSegwitV0 as Btc;
 use farcaster_core::crypto::dleq::DLEQProof;
+use farcaster_core::crypto::musig2::{
+    KeyAggContext, PartialSignature, PublicNonce, SecretNonce, Session,
+};
 use farcaster_core::monero::Monero as Xmr;
 use farcaster_core::swap::btcxmr::KeyManager;
+use farcaster_core::swap::SwapId;
 
-use farcaster_core::blockchain::{FeePriority, Network};
+use farcaster_core::blockchain::{FeePriority, Network, Transactions};
 use farcaster_core::consensus::deserialize;
+use farcaster_core::crypto::commitment::{self, SessionCommitment};
 use farcaster_core::crypto::KeccakCommitment;
 use farcaster_core::crypto::{
     ArbitratingKeyId, CommitmentEngine, GenerateKey, ProveCrossGroupDleq,
 };
 use farcaster_core::protocol::message::*;
-use farcaster_core::swap::btcxmr::{Alice, Bob, Deal, Parameters};
+use farcaster_core::role::SwapRole;
+use farcaster_core::swap::btcxmr::{
+    recover_secret_from_buy, recover_secret_from_refund, Alice, Bob, Deal, Parameters,
+};
 use farcaster_core::transaction::*;
 use farcaster_core::Uuid;
 
 use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
 use bitcoin::blockdata::witness::Witness;
-use bitcoin::secp256k1::{ecdsa::Signature, PublicKey as BPub, Secp256k1, SecretKey as BPriv};
+use bitcoin::secp256k1::{
+    ecdsa::Signature, Message, PublicKey as BPub, Secp256k1, SecretKey as BPriv,
+};
 use bitcoin::util::psbt::PartiallySignedTransaction;
 use bitcoin::Address;
 
@@ -91,7 +102,8 @@ fn execute_offline_protocol() {
     )
     .unwrap();
 
-    let swap_id = Uuid::random();
+    // The commit messages carry the deal identifier, the swap identifier is derived from them
+    let deal_id = SwapId::from(deal.id());
 
     //
     // Commit/Reveal round
@@ -100,28 +112,90 @@ fn execute_offline_protocol() {
         .generate_parameters(&mut alice_key_manager, &deal)
         .unwrap();
 
-    let commit_alice_params = alice_params.commit_alice(swap_id, &commitment_engine);
+    let commit_alice_params = alice_params.commit_alice(deal_id, &commitment_engine);
     test_strict_ser!(commit_alice_params, CommitAliceParameters<KeccakCommitment>);
 
     let bob_params: Parameters = bob
         .generate_parameters(&mut bob_key_manager, &deal)
         .unwrap();
-    let commit_bob_params = bob_params.commit_bob(swap_id, &commitment_engine);
+    let commit_bob_params = bob_params.commit_bob(deal_id, &commitment_engine);
     test_strict_ser!(commit_bob_params, CommitBobParameters<KeccakCommitment>);
 
+    // Both derive the same swap identifier from the commitments
+    let swap_id = alice
+        .derive_swap_id(&deal, &commit_alice_params, &commit_bob_params)
+        .unwrap();
+    assert_eq!(
+        bob.derive_swap_id(&deal, &commit_alice_params, &commit_bob_params)
+            .unwrap(),
+        swap_id
+    );
+    let mut replayed_commit = commit_bob_params.clone();
+    replayed_commit.swap_id = Uuid::random().into();
+    assert!(matches!(
+        alice.derive_swap_id(&deal, &commit_alice_params, &replayed_commit),
+        Err(farcaster_core::Error::Swap(
+            farcaster_core::swap::Error::SwapIdMismatch { .. }
+        ))
+    ));
+
     // Reveal
     let reveal_alice_params = alice_params.clone().reveal_alice(swap_id);
     test_strict_ser!(reveal_alice_params, RevealAliceParameters<BPub, MPub, BPriv, MPriv, Address, DLEQProof>);
     let reveal_bob_params = bob_params.clone().reveal_bob(swap_id);
     test_strict_ser!(reveal_bob_params, RevealBobParameters<BPub, MPub, BPriv, MPriv, Address, DLEQProof>);
 
-    assert!(commit_alice_params
-        .verify_with_reveal(&commitment_engine, reveal_alice_params.clone())
+    assert!(bob
+        .verify_reveal(
+            swap_id,
+            &commitment_engine,
+            &commit_alice_params,
+            reveal_alice_params.clone()
+        )
         .is_ok());
-    assert!(commit_bob_params
-        .verify_with_reveal(&commitment_engine, reveal_bob_params.clone())
+    assert!(alice
+        .verify_reveal(
+            swap_id,
+            &commitment_engine,
+            &commit_bob_params,
+            reveal_bob_params.clone()
+        )
         .is_ok());
 
+    // Messages are bound to the swap
+    assert!(reveal_alice_params.check_swap_id(swap_id).is_ok());
+    assert!(commit_bob_params
+        .check_swap_id(Uuid::random().into())
+        .is_err());
+    assert!(alice
+        .verify_reveal(
+            deal_id,
+            &commitment_engine,
+            &commit_bob_params,
+            reveal_bob_params.clone()
+        )
+        .is_err());
+
+    // Session commitments over the full reveals
+    let alice_session = SessionCommitment::commit(&reveal_alice_params);
+    test_strict_ser!(alice_session, SessionCommitment);
+    let bob_session = SessionCommitment::commit(&reveal_bob_params);
+    assert!(commitment::verify_reveal(&alice_session, &reveal_alice_params).is_ok());
+    assert!(commitment::verify_reveal(&bob_session, &reveal_bob_params).is_ok());
+    assert_eq!(
+        commitment::verify_reveal(&alice_session, &reveal_bob_params),
+        Err(commitment::Error::RoleMismatch {
+            expected: SwapRole::Alice,
+            found: SwapRole::Bob
+        })
+    );
+    let mut tampered = reveal_bob_params.clone();
+    tampered.proof = reveal_alice_params.proof.clone();
+    assert_eq!(
+        commitment::verify_reveal(&bob_session, &tampered),
+        Err(commitment::Error::InvalidOpening)
+    );
+
     //
     // Get Funding Address and Transaction
     //
@@ -139,11 +213,33 @@ fn execute_offline_protocol() {
             witness: Witness::new(),
         }],
         output: vec![TxOut {
-            value: 123456789,
+            value: Btc::funding_amount(
+                &deal.to_arbitrating_params(),
+                &alice_params.destination_address,
+            )
+            .unwrap()
+            .as_sat(),
             script_pubkey: funding_address.script_pubkey(),
         }],
     };
 
+    // The lock fee must follow the strategy, an overfunded lock is rejected
+    let mut overfunding_tx = funding_tx.clone();
+    overfunding_tx.output[0].value += 1_000;
+    let mut overfunding = FundingTx::initialize(funding_key, Network::Local).unwrap();
+    overfunding.update(overfunding_tx).unwrap();
+    assert!(matches!(
+        bob.core_arbitrating_transactions(
+            &alice_params,
+            &bob_params,
+            overfunding,
+            deal.to_arbitrating_params(),
+        ),
+        Err(farcaster_core::Error::FeeStrategy(
+            farcaster_core::blockchain::FeeStrategyError::OutOfStrategy
+        ))
+    ));
+
     funding.update(funding_tx).unwrap();
 
     //
@@ -189,7 +285,7 @@ fn execute_offline_protocol() {
         .unwrap();
 
     let refund_proc_sig = RefundProcedureSignatures {
-        swap_id: swap_id.into(),
+        swap_id,
         cancel_sig,
         refund_adaptor_sig: refund_adaptor_sig.clone(),
     };
@@ -239,6 +335,7 @@ fn execute_offline_protocol() {
     //
     alice
         .validate_adaptor_buy(
+            swap_id,
             &mut alice_key_manager,
             &alice_params,
             &bob_params,
@@ -247,8 +344,21 @@ fn execute_offline_protocol() {
             &adaptor_buy,
         )
         .unwrap();
+    // A buy procedure from another swap is rejected
+    assert!(alice
+        .validate_adaptor_buy(
+            deal_id,
+            &mut alice_key_manager,
+            &alice_params,
+            &bob_params,
+            &core,
+            deal.to_arbitrating_params(),
+            &adaptor_buy,
+        )
+        .is_err());
     let fully_sign_buy = alice
         .fully_sign_buy(
+            swap_id,
             &mut alice_key_manager,
             &alice_params,
             &bob_params,
@@ -271,13 +381,58 @@ fn execute_offline_protocol() {
         .generate_proof()
         .expect("Considered valid in tests");
 
+    let recovered =
+        recover_secret_from_buy(&buy_tx, &adaptor_buy.buy_adaptor_sig, &alice_params.adaptor)
+            .unwrap();
+    assert_eq!(MPub::from_private_key(&recovered), xmr_public_spend);
+
+    // A malleated witness does not follow the template but the signature is still found
+    let mut malleated_buy = buy_tx.clone();
+    let mut stack = malleated_buy.input[0].witness.to_vec();
+    stack.swap(0, 1);
+    stack.insert(0, vec![]);
+    malleated_buy.input[0].witness = Witness::from_vec(stack.clone());
+    assert!(BuyTx::extract_witness_signature(&malleated_buy).is_err());
+    let recovered = recover_secret_from_buy(
+        &malleated_buy,
+        &adaptor_buy.buy_adaptor_sig,
+        &alice_params.adaptor,
+    )
+    .unwrap();
+    assert_eq!(MPub::from_private_key(&recovered), xmr_public_spend);
+    let reordered_buy = malleated_buy.clone();
+    // Alice's signature alone does not reveal the secret
+    stack.remove(2);
+    malleated_buy.input[0].witness = Witness::from_vec(stack);
+    assert!(recover_secret_from_buy(
+        &malleated_buy,
+        &adaptor_buy.buy_adaptor_sig,
+        &alice_params.adaptor
+    )
+    .is_err());
+
     let secp = Secp256k1::new();
-    let btc_adaptor_priv = bob.recover_accordant_key(
-        &mut bob_key_manager,
-        &alice_params,
-        adaptor_buy.buy_adaptor_sig,
-        buy_tx,
-    );
+    // Bob searches the whole witness, a malleated witness does not prevent the recovery
+    assert!(bob
+        .recover_accordant_key(
+            &mut bob_key_manager,
+            &alice_params,
+            &adaptor_buy,
+            malleated_buy
+        )
+        .is_err());
+    let recovered = bob
+        .recover_accordant_key(
+            &mut bob_key_manager,
+            &alice_params,
+            &adaptor_buy,
+            reordered_buy,
+        )
+        .unwrap();
+    let btc_adaptor_priv = bob
+        .recover_accordant_key(&mut bob_key_manager, &alice_params, &adaptor_buy, buy_tx)
+        .unwrap();
+    assert_eq!(recovered, btc_adaptor_priv);
     let mut secret_bits: Vec<u8> = (*btc_adaptor_priv.as_ref()).into();
     secret_bits.reverse();
     let xmr_spend_priv = MPriv::from_slice(secret_bits.as_ref()).expect("Valid Monero Private Key");
@@ -328,12 +483,19 @@ fn execute_offline_protocol() {
         .generate_proof()
         .expect("Considered valid in tests");
 
-    let btc_adaptor_priv = alice.recover_accordant_key(
-        &mut alice_key_manager,
-        &bob_params,
-        refund_adaptor_sig,
-        refund_tx,
-    );
+    let recovered =
+        recover_secret_from_refund(&refund_tx, &refund_adaptor_sig, &bob_params.adaptor).unwrap();
+    assert_eq!(MPub::from_private_key(&recovered), xmr_public_spend);
+
+    let btc_adaptor_priv = alice
+        .recover_accordant_key(
+            &mut alice_key_manager,
+            &bob_params,
+            &core,
+            refund_adaptor_sig,
+            refund_tx,
+        )
+        .unwrap();
     let mut secret_bits: Vec<u8> = (*btc_adaptor_priv.as_ref()).into();
     secret_bits.reverse();
     let xmr_spend_priv = MPriv::from_slice(secret_bits.as_ref()).expect("Valid Monero Private Key");
@@ -369,4 +531,128 @@ fn execute_offline_protocol() {
         )
         .unwrap();
     let _ = Broadcastable::<bitcoin::Transaction>::finalize_and_extract(&mut refund).unwrap();
+    punish.finalize().unwrap();
+
+    //
+    // EXPORT CANCEL AND PUNISH TO A WATCHTOWER:
+    //
+
+    let package = WatchtowerPackage::new(swap_id, &cancel, Some(&punish)).unwrap();
+    assert_eq!(
+        package.cancel_trigger.outpoint,
+        core.cancel.unsigned_tx.input[0].previous_output
+    );
+    assert_eq!(
+        package.punish_trigger.unwrap().timelock,
+        deal.parameters.punish_timelock
+    );
+    let key = [42u8; 32];
+    let blob = package.seal(&mut rand::thread_rng(), &key);
+    assert_eq!(WatchtowerPackage::open(&key, &blob).unwrap(), package);
+    assert!(WatchtowerPackage::open(&[0u8; 32], &blob).is_err());
+}
+
+#[test]
+fn exchange_musig2_messages() {
+    let secp = Secp256k1::new();
+    let swap_id = SwapId(Uuid::random());
+    let alice_key = BPriv::from_slice(&[3u8; 32]).unwrap();
+    let bob_key = BPriv::from_slice(&[4u8; 32]).unwrap();
+    let pubkeys = vec![
+        BPub::from_secret_key(&secp, &alice_key),
+        BPub::from_secret_key(&secp, &bob_key),
+    ];
+    let key_agg = KeyAggContext::new(pubkeys.clone())
+        .unwrap()
+        .with_taproot_tweak(None)
+        .unwrap();
+    let message = Message::from_slice(&[7u8; 32]).unwrap();
+
+    let alice_nonce = SecretNonce::generate(&mut rand::thread_rng());
+    let bob_nonce = SecretNonce::generate(&mut rand::thread_rng());
+    let bob_nonce_msg = MuSigNonce {
+        swap_id,
+        tx_label: TxLabel::Buy,
+        nonce: bob_nonce.public_nonce(),
+    };
+    test_strict_ser!(bob_nonce_msg, MuSigNonce<PublicNonce>);
+
+    let nonces = vec![alice_nonce.public_nonce(), bob_nonce_msg.nonce.clone()];
+    let session = Session::new(&key_agg, &nonces, &message).unwrap();
+    let bob_sig_msg = MuSigPartialSignature {
+        swap_id,
+        tx_label: TxLabel::Buy,
+        partial_sig: session.sign(&secp, bob_nonce, &bob_key).unwrap(),
+    };
+    test_strict_ser!(bob_sig_msg, MuSigPartialSignature<PartialSignature>);
+
+    // Alice validates Bob's partial signature and completes the signature
+    assert!(session
+        .verify_partial(&bob_sig_msg.partial_sig, &nonces[1], &pubkeys[1])
+        .is_ok());
+    let alice_sig = session.sign(&secp, alice_nonce, &alice_key).unwrap();
+    assert!(session
+        .aggregate(&secp, &[alice_sig, bob_sig_msg.partial_sig])
+        .is_ok());
+}
+
+#[test]
+fn agree_on_recovery_key() {
+    use farcaster_core::crypto::TaggedElement;
+    use farcaster_core::protocol::agreed_recovery_key;
+    use farcaster_core::script::RECOVERY_KEY_TAG;
+    use farcaster_core::swap;
+
+    let (alice, bob, deal) = init();
+    let mut alice_key_manager = KeyManager::new([1u8; 32], 1).unwrap();
+    let mut bob_key_manager = KeyManager::new([2u8; 32], 1).unwrap();
+    let alice_params: Parameters = alice
+        .generate_parameters(&mut alice_key_manager, &deal)
+        .unwrap();
+    let bob_params: Parameters = bob
+        .generate_parameters(&mut bob_key_manager, &deal)
+        .unwrap();
+    assert_eq!(agreed_recovery_key(&alice_params, &bob_params), Ok(None));
+
+    let secp = Secp256k1::new();
+    let recovery = BPub::from_secret_key(&secp, &BPriv::from_slice(&[9u8; 32]).unwrap());
+    let with_recovery = |params: &Parameters, key: BPub| {
+        let mut params = params.clone();
+        params
+            .extra_arbitrating_keys
+            .push(TaggedElement::new(RECOVERY_KEY_TAG, key));
+        params
+    };
+    let invalid = |res| matches!(res, Err(swap::Error::InvalidRecoveryKey(_)));
+
+    // Both parties must explicitly agree on the same key
+    assert!(invalid(agreed_recovery_key(
+        &with_recovery(&alice_params, recovery),
+        &bob_params
+    )));
+    assert!(invalid(agreed_recovery_key(
+        &alice_params,
+        &with_recovery(&bob_params, recovery)
+    )));
+    let other = BPub::from_secret_key(&secp, &BPriv::from_slice(&[10u8; 32]).unwrap());
+    assert!(invalid(agreed_recovery_key(
+        &with_recovery(&alice_params, recovery),
+        &with_recovery(&bob_params, other)
+    )));
+    // The recovery key must be a third-party key
+    assert!(invalid(agreed_recovery_key(
+        &with_recovery(&alice_params, bob_params.refund),
+        &with_recovery(&bob_params, bob_params.refund)
+    )));
+    assert!(invalid(agreed_recovery_key(
+        &with_recovery(&alice_params, alice_params.adaptor),
+        &with_recovery(&bob_params, alice_params.adaptor)
+    )));
+    assert_eq!(
+        agreed_recovery_key(
+            &with_recovery(&alice_params, recovery),
+            &with_recovery(&bob_params, recovery)
+        ),
+        Ok(Some(recovery))
+    );
 }
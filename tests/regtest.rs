@@ -0,0 +1,75 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+#![cfg(feature = "rpc-tests")]
+
+use farcaster_core::blockchain::AccordantAmount;
+use farcaster_core_testutils::swap::AccordantSetup;
+use farcaster_core_testutils::{Bitcoind, MoneroWallet, Monerod, SignedTransactions, SwapSetup};
+
+#[test]
+fn happy_path_swap() {
+    let node = Bitcoind::spawn().unwrap();
+    let txs = SignedTransactions::prepare(&node, SwapSetup::default()).unwrap();
+    txs.run_happy_path(&node).unwrap();
+}
+
+#[test]
+#[ignore = "requires monerod and monero-wallet-rpc, run with --include-ignored"]
+fn happy_path_swap_with_accordant_lock() {
+    let monerod = Monerod::spawn().expect("monerod is required");
+    let wallet = MoneroWallet::spawn(&monerod).expect("monero-wallet-rpc is required");
+    wallet.fund(&monerod).unwrap();
+    let node = Bitcoind::spawn().unwrap();
+    let txs = SignedTransactions::prepare(&node, SwapSetup::default()).unwrap();
+    node.broadcast(&txs.lock).unwrap();
+    node.mine(1).unwrap();
+    // Alice locks the accordant funds once the arbitrating lock is final, Bob verifies the lock
+    // with the view key before signing the buy
+    let accordant = AccordantSetup::random();
+    let amount = AccordantAmount::from_atomic_units(1_000_000_000_000);
+    let outputs = accordant.lock(&monerod, &wallet, amount).unwrap();
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[0].amount, amount);
+    node.broadcast(&txs.buy).unwrap();
+    node.mine(1).unwrap();
+    assert!(node.confirmations(&txs.buy.txid()).unwrap() > 0);
+}
+
+#[test]
+fn refund_path_swap() {
+    let node = Bitcoind::spawn().unwrap();
+    let txs = SignedTransactions::prepare(&node, SwapSetup::default()).unwrap();
+    txs.run_refund_path(&node).unwrap();
+}
+
+#[test]
+fn punish_path_swap() {
+    let node = Bitcoind::spawn().unwrap();
+    let txs = SignedTransactions::prepare(&node, SwapSetup::default()).unwrap();
+    txs.run_punish_path(&node).unwrap();
+}
+
+#[test]
+fn punish_before_timelock_is_rejected() {
+    let node = Bitcoind::spawn().unwrap();
+    let txs = SignedTransactions::prepare(&node, SwapSetup::default()).unwrap();
+    node.broadcast(&txs.lock).unwrap();
+    node.mine(txs.setup.cancel_timelock.into()).unwrap();
+    node.broadcast(&txs.cancel).unwrap();
+    node.mine(1).unwrap();
+    assert!(node.broadcast(&txs.punish).is_err());
+}
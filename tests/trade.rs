@@ -60,7 +60,7 @@ fn get_deal_parameters_fingerprint() {
     let hex = "4450e567b1106f429247bb680e5fe0c802000000808000008008000500000000000000080006000\
                00000000000040007000000040008000000010800090000000000000002";
     let res: DealParameters<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB> =
-        strict_encoding::strict_deserialize(&hex::decode(hex).unwrap()).unwrap();
+        strict_encoding::strict_deserialize(hex::decode(hex).unwrap()).unwrap();
     let id = DealFingerprint::from_str(
         "f79b29ccb233b37cea3aa35b94c5ece25c58a8098afc18f046810a3c04591599",
     )
@@ -70,7 +70,7 @@ fn get_deal_parameters_fingerprint() {
     let hex = "4351e567b1106f429247bb680e5fe0c802000000808000008008000500000000000000080006000\
                00000000000040007000000040008000000010800090000000000000002";
     let res: DealParameters<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB> =
-        strict_encoding::strict_deserialize(&hex::decode(hex).unwrap()).unwrap();
+        strict_encoding::strict_deserialize(hex::decode(hex).unwrap()).unwrap();
     // same fingerprint
     assert_eq!(id, res.fingerprint());
 }
@@ -80,7 +80,7 @@ fn get_deal_parameters_uuid() {
     let hex = "4450e567b1106f429247bb680e5fe0c802000000808000008008000500000000000000080006000\
                00000000000040007000000040008000000010800090000000000000002";
     let res: DealParameters<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB> =
-        strict_encoding::strict_deserialize(&hex::decode(hex).unwrap()).unwrap();
+        strict_encoding::strict_deserialize(hex::decode(hex).unwrap()).unwrap();
     assert_eq!(
         res.uuid(),
         uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8").into(),
@@ -134,7 +134,7 @@ fn get_deal_fingerprint() {
                03b31a0a70343bb46f3db3768296ac5027f9873921b37f852860c690063ff9e4c90000000000000\
                0000000000000000000000000000000000000000000000000000000260700";
     let res: Deal<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB> =
-        strict_encoding::strict_deserialize(&hex::decode(hex).unwrap()).unwrap();
+        strict_encoding::strict_deserialize(hex::decode(hex).unwrap()).unwrap();
     let id = DealFingerprint::from_str(
         "3a466a0a0cff7bf800808653460076549621d07db78e697b9dfaebaba0ab8b33",
     )
@@ -146,7 +146,7 @@ fn get_deal_fingerprint() {
                03b31a0a70343bb46f3db3768296ac5027f9873921b37f852860c690063ff9e4c90000000000000\
                0000000000000000000000000000000000000000000000000000000260700";
     let res: Deal<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB> =
-        strict_encoding::strict_deserialize(&hex::decode(hex).unwrap()).unwrap();
+        strict_encoding::strict_deserialize(hex::decode(hex).unwrap()).unwrap();
     // same fingerprint
     assert_eq!(id, res.fingerprint());
 }
@@ -158,7 +158,7 @@ fn get_deal_uuid() {
                03b31a0a70343bb46f3db3768296ac5027f9873921b37f852860c690063ff9e4c90000000000000\
                0000000000000000000000000000000000000000000000000000000260700";
     let res: Deal<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB> =
-        strict_encoding::strict_deserialize(&hex::decode(hex).unwrap()).unwrap();
+        strict_encoding::strict_deserialize(hex::decode(hex).unwrap()).unwrap();
     assert_eq!(
         res.uuid(),
         uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8").into(),
@@ -190,17 +190,12 @@ fn check_deal_magic_bytes() {
 
 #[test]
 fn parse_deal() {
-    for hex in [
-        "46435357415001004450e567b1106f429247bb680e5fe0c80200000080800000800800a08601000\
-         00000000800c80000000000000004000a00000004000a0000000108001400000000000000022100\
-         03b31a0a70343bb46f3db3768296ac5027f9873921b37f852860c690063ff9e4c90000000000000\
-         0000000000000000000000000000000000000000000000000000000260700",
-    ]
-    .iter_mut()
-    {
-        let bytes = hex::decode(hex).expect("hex");
-        let res: Result<Deal<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB>, _> =
-            strict_encoding::strict_deserialize(&bytes);
-        assert!(res.is_ok());
-    }
+    let hex = "46435357415001004450e567b1106f429247bb680e5fe0c80200000080800000800800a08601000\
+               00000000800c80000000000000004000a00000004000a0000000108001400000000000000022100\
+               03b31a0a70343bb46f3db3768296ac5027f9873921b37f852860c690063ff9e4c90000000000000\
+               0000000000000000000000000000000000000000000000000000000260700";
+    let bytes = hex::decode(hex).expect("hex");
+    let res: Result<Deal<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerKvB>, _> =
+        strict_encoding::strict_deserialize(bytes);
+    assert!(res.is_ok());
 }
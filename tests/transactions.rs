@@ -32,6 +32,7 @@ mod rpc;
 
 macro_rules! setup_txs {
     () => {{
+        let secp = bitcoin::secp256k1::Secp256k1::signing_only();
         let (_, pubkey_a1, secret_a1) = new_address!();
         //let (_, pubkey_a2, secret_a2) = new_address!();
 
@@ -95,14 +96,15 @@ macro_rules! setup_txs {
             timelock: timelock::CSVTimelock::new(10),
             success: SwapRoleKeys::new(pubkey_a1, pubkey_b1),
             failure: pubkey_a1,
+            recovery: None,
         };
 
         let mut cancel =
             CancelTx::initialize(&lock, datalock.clone(), datapunishablelock.clone()).unwrap();
 
         // Set the fees according to the given strategy
-        cancel.as_partial_mut().set_fee(&fee, politic).unwrap();
-        assert!(cancel.as_partial_mut().validate_fee(&fee).unwrap());
+        cancel.set_fee(&fee, politic).unwrap();
+        assert!(cancel.validate_fee(&fee).unwrap());
 
         //
         // Create refund tx
@@ -111,8 +113,8 @@ macro_rules! setup_txs {
         let mut refund = RefundTx::initialize(&cancel, new_address.clone()).unwrap();
 
         // Set the fees according to the given strategy
-        refund.as_partial_mut().set_fee(&fee, politic).unwrap();
-        assert!(refund.as_partial_mut().validate_fee(&fee).unwrap());
+        refund.set_fee(&fee, politic).unwrap();
+        assert!(refund.validate_fee(&fee).unwrap());
 
         lock.verify_template(datalock.clone()).unwrap();
         cancel
@@ -126,12 +128,12 @@ macro_rules! setup_txs {
         let msg = refund
             .generate_witness_message(ScriptPath::Success)
             .unwrap();
-        let sig = sign_hash(msg, &secret_a1).unwrap();
+        let sig = sign_hash(&secp, msg, &secret_a1).unwrap();
         refund.add_witness(pubkey_a1, sig).unwrap();
         let msg = refund
             .generate_witness_message(ScriptPath::Success)
             .unwrap();
-        let sig = sign_hash(msg, &secret_b1).unwrap();
+        let sig = sign_hash(&secp, msg, &secret_b1).unwrap();
         refund.add_witness(pubkey_b1, sig).unwrap();
 
         //
@@ -146,12 +148,12 @@ macro_rules! setup_txs {
         let msg = cancel
             .generate_witness_message(ScriptPath::Failure)
             .unwrap();
-        let sig = sign_hash(msg, &secret_a1).unwrap();
+        let sig = sign_hash(&secp, msg, &secret_a1).unwrap();
         cancel.add_witness(pubkey_a1, sig).unwrap();
         let msg = cancel
             .generate_witness_message(ScriptPath::Failure)
             .unwrap();
-        let sig = sign_hash(msg, &secret_b1).unwrap();
+        let sig = sign_hash(&secp, msg, &secret_b1).unwrap();
         cancel.add_witness(pubkey_b1, sig).unwrap();
 
         //
@@ -167,8 +169,8 @@ macro_rules! setup_txs {
         let mut buy = BuyTx::initialize(&lock, datalock.clone(), new_address.clone()).unwrap();
 
         // Set the fees according to the given strategy
-        buy.as_partial_mut().set_fee(&fee, politic).unwrap();
-        assert!(buy.as_partial_mut().validate_fee(&fee).unwrap());
+        buy.set_fee(&fee, politic).unwrap();
+        assert!(buy.validate_fee(&fee).unwrap());
 
         buy.verify_template(new_address.clone()).unwrap();
 
@@ -176,10 +178,10 @@ macro_rules! setup_txs {
         // Co-Sign buy
         //
         let msg = buy.generate_witness_message(ScriptPath::Success).unwrap();
-        let sig = sign_hash(msg, &secret_a1).unwrap();
+        let sig = sign_hash(&secp, msg, &secret_a1).unwrap();
         buy.add_witness(pubkey_a1, sig).unwrap();
         let msg = buy.generate_witness_message(ScriptPath::Success).unwrap();
-        let sig = sign_hash(msg, &secret_b1).unwrap();
+        let sig = sign_hash(&secp, msg, &secret_b1).unwrap();
         buy.add_witness(pubkey_b1, sig).unwrap();
 
         //
@@ -192,7 +194,7 @@ macro_rules! setup_txs {
         // Sign lock tx
         //
         let msg = lock.generate_witness_message(ScriptPath::Success).unwrap();
-        let sig = sign_hash(msg, &secret_a1).unwrap();
+        let sig = sign_hash(&secp, msg, &secret_a1).unwrap();
         lock.add_witness(pubkey_a1, sig).unwrap();
         let lock_finalized =
             Broadcastable::<bitcoin::Transaction>::finalize_and_extract(&mut lock).unwrap();
@@ -205,8 +207,8 @@ macro_rules! setup_txs {
             PunishTx::initialize(&cancel, datapunishablelock, new_address.into()).unwrap();
 
         // Set the fees according to the given strategy
-        punish.as_partial_mut().set_fee(&fee, politic).unwrap();
-        assert!(punish.as_partial_mut().validate_fee(&fee).unwrap());
+        punish.set_fee(&fee, politic).unwrap();
+        assert!(punish.validate_fee(&fee).unwrap());
 
         //
         // Sign punish
@@ -214,7 +216,7 @@ macro_rules! setup_txs {
         let msg = punish
             .generate_witness_message(ScriptPath::Failure)
             .unwrap();
-        let sig = sign_hash(msg, &secret_a1).unwrap();
+        let sig = sign_hash(&secp, msg, &secret_a1).unwrap();
         punish.add_witness(pubkey_a1, sig).unwrap();
 
         //
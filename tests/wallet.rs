@@ -19,11 +19,13 @@ use rand::prelude::*;
 use std::convert::TryInto;
 use std::str::FromStr;
 
+use farcaster_core::crypto::context::{CryptoContext, EcdsaSigner, NonceProvider};
 use farcaster_core::crypto::{
     AccordantKeyId, ArbitratingKeyId, EncSign, GenerateKey, GenerateSharedKey, ProveCrossGroupDleq,
     RecoverSecret, SharedKeyId, Sign,
 };
 use farcaster_core::monero::SHARED_VIEW_KEY_ID;
+use farcaster_core::primitives;
 use farcaster_core::swap::btcxmr::*;
 use farcaster_core::{consensus::CanonicalBytes, crypto::AccordantKeySet};
 
@@ -64,7 +66,6 @@ fn key_manager_can_derive_arbitrating_bitcoin_keys() {
     assert!(std_keys.is_ok());
 
     let extra_keys: Result<Vec<bitcoin::secp256k1::PublicKey>, _> = (0..50)
-        .into_iter()
         .map(|extra_id| {
             let key_id = ArbitratingKeyId::Extra(extra_id);
             GenerateKey::<bitcoin::secp256k1::PublicKey, _>::get_pubkey(&mut key_manager, key_id)
@@ -84,7 +85,6 @@ fn key_manager_can_derive_accordant_monero_keys() {
     assert!(std_key.is_ok());
 
     let extra_keys: Result<Vec<monero::PublicKey>, _> = (0..50)
-        .into_iter()
         .map(|extra_id| {
             let key_id = AccordantKeyId::Extra(extra_id);
             GenerateKey::<monero::PublicKey, _>::get_pubkey(&mut key_manager, key_id)
@@ -151,6 +151,33 @@ fn key_manager_can_encrypt_sign_and_verify() {
     assert!(key_manager
         .verify_encrypted_signature(&pubkey, &encryption_key, bytes, &enc_sig)
         .is_ok());
+    // The no_std primitives verify the same encrypted signature
+    let enc_sig_bytes = enc_sig.as_canonical_bytes();
+    assert!(primitives::adaptor::verify_encrypted_signature(
+        &pubkey.serialize(),
+        &encryption_key.serialize(),
+        &bytes.into_inner(),
+        &enc_sig_bytes,
+    )
+    .is_ok());
+    assert_eq!(
+        primitives::adaptor::verify_encrypted_signature(
+            &encryption_key.serialize(),
+            &pubkey.serialize(),
+            &bytes.into_inner(),
+            &enc_sig_bytes,
+        ),
+        Err(primitives::adaptor::Error::InvalidProof)
+    );
+    assert_eq!(
+        primitives::adaptor::verify_encrypted_signature(
+            &pubkey.serialize(),
+            &encryption_key.serialize(),
+            &[0; 32],
+            &enc_sig_bytes,
+        ),
+        Err(primitives::adaptor::Error::InvalidEncryptedSignature)
+    );
 
     let wrong_pubkey = key_manager
         .get_pubkey(ArbitratingKeyId::Punish)
@@ -191,13 +218,21 @@ fn key_manager_can_recover_secret() {
         .verify_signature(&pubkey, bytes, &decrypt_sig)
         .is_ok());
 
-    let recovered_secret: bitcoin::secp256k1::SecretKey =
-        key_manager.recover_secret_key(enc_sig, &encryption_key, decrypt_sig);
+    let recovered_secret: bitcoin::secp256k1::SecretKey = key_manager
+        .recover_secret_key(enc_sig.clone(), &encryption_key, decrypt_sig)
+        .unwrap();
 
     // check equality on canonical bytes
     let mut secret = secret.as_canonical_bytes();
     secret.reverse();
     assert_eq!(secret, recovered_secret.as_canonical_bytes());
+
+    // A valid signature that is not the decryption of the encrypted signature is rejected
+    let other_sig = key_manager.sign(ArbitratingKeyId::Buy, bytes).unwrap();
+    assert!(matches!(
+        key_manager.recover_secret_key(enc_sig, &encryption_key, other_sig),
+        Err(farcaster_core::crypto::Error::InvalidEncryptedSignature)
+    ));
 }
 
 #[test]
@@ -256,3 +291,58 @@ fn can_create_accordant_address() {
         accordant_address
     );
 }
+
+fn deterministic_key_manager() -> KeyManager {
+    let seed =
+        hex::decode("000102030405060708090a0b0c0d0e0f000102030405060708090a0b0c0d0e0f").unwrap();
+    let ctx = CryptoContext::with_nonce_provider(NonceProvider::deterministic());
+    KeyManager::with_context(seed.try_into().unwrap(), 0, ctx).unwrap()
+}
+
+// Signatures produced with deterministic nonces are reproducible across runs and
+// implementations, they are published as vectors
+#[test]
+fn key_manager_with_deterministic_nonces_matches_vectors() {
+    let bytes = sha256d::Hash::hash("The message to sign!".as_bytes());
+    let mut key_manager = deterministic_key_manager();
+    let encryption_key = key_manager.get_encryption_key().unwrap();
+    let pubkey = key_manager.get_pubkey(ArbitratingKeyId::Buy).unwrap();
+
+    let sig = key_manager.sign(ArbitratingKeyId::Buy, bytes).unwrap();
+    assert_eq!(
+        hex::encode(sig.as_canonical_bytes()),
+        include_str!("vectors/deterministic_signature.hex").trim()
+    );
+    assert!(key_manager.verify_signature(&pubkey, bytes, &sig).is_ok());
+
+    let enc_sig = key_manager
+        .encrypt_sign(ArbitratingKeyId::Buy, &encryption_key, bytes)
+        .unwrap();
+    assert_eq!(
+        hex::encode(enc_sig.as_canonical_bytes()),
+        include_str!("vectors/deterministic_encrypted_signature.hex").trim()
+    );
+    assert!(key_manager
+        .verify_encrypted_signature(&pubkey, &encryption_key, bytes, &enc_sig)
+        .is_ok());
+
+    // A second key manager with the same seed produces the same signatures
+    let mut other = deterministic_key_manager();
+    assert_eq!(other.sign(ArbitratingKeyId::Buy, bytes).unwrap(), sig);
+    assert_eq!(
+        other
+            .encrypt_sign(ArbitratingKeyId::Buy, &encryption_key, bytes)
+            .unwrap()
+            .as_canonical_bytes(),
+        enc_sig.as_canonical_bytes()
+    );
+
+    // The secp256k1 context signs with RFC6979 nonces
+    let secp = bitcoin::secp256k1::Secp256k1::new();
+    let msg = bitcoin::secp256k1::Message::from_slice(&bytes[..]).unwrap();
+    let secret_key = bitcoin::secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+    assert_eq!(
+        hex::encode(secp.sign_low_s(&msg, &secret_key).as_canonical_bytes()),
+        include_str!("vectors/rfc6979_signature.hex").trim()
+    );
+}
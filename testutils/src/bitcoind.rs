@@ -0,0 +1,159 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! A throw-away `bitcoind` node in regtest with a funded wallet.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bitcoin::{Address, Amount, BlockHash, Transaction, Txid};
+use bitcoincore_rpc::{Auth, Client, RpcApi};
+
+use crate::{free_port, temp_dir, Error, Res};
+
+/// Maximum time to wait for the node to accept RPC calls.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Name of the wallet created on the node.
+const WALLET_NAME: &str = "farcaster";
+
+/// A running `bitcoind` regtest node, killed and cleaned up when dropped.
+pub struct Bitcoind {
+    process: Child,
+    client: Client,
+    datadir: PathBuf,
+    rpc_port: u16,
+}
+
+impl Bitcoind {
+    /// Start a new node with a fresh data directory and a wallet holding mature coins. The binary
+    /// is taken from `BITCOIND_EXE` if set, otherwise from `PATH`.
+    pub fn spawn() -> Res<Self> {
+        let exe = env::var("BITCOIND_EXE").unwrap_or_else(|_| "bitcoind".into());
+        let datadir = temp_dir("farcaster-bitcoind");
+        std::fs::create_dir_all(&datadir)?;
+        let rpc_port = free_port()?;
+
+        let process = Command::new(exe)
+            .arg("-regtest")
+            .arg("-server")
+            .arg("-listen=0")
+            .arg("-txindex")
+            .arg("-fallbackfee=0.00001")
+            .arg(format!("-datadir={}", datadir.display()))
+            .arg(format!("-rpcport={}", rpc_port))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(Error::Spawn)?;
+
+        let cookie = datadir.join("regtest").join(".cookie");
+        let url = format!("http://127.0.0.1:{}", rpc_port);
+        let start = Instant::now();
+        let client = loop {
+            if start.elapsed() > STARTUP_TIMEOUT {
+                return Err(Error::Timeout);
+            }
+            if cookie.exists() {
+                let client = Client::new(&url, Auth::CookieFile(cookie.clone()))?;
+                if client.get_blockchain_info().is_ok() {
+                    break client;
+                }
+            }
+            thread::sleep(Duration::from_millis(100));
+        };
+
+        let node = Self {
+            process,
+            client,
+            datadir,
+            rpc_port,
+        };
+        node.client
+            .create_wallet(WALLET_NAME, None, None, None, None)?;
+        // Coinbase outputs are spendable after 100 confirmations
+        node.mine(101)?;
+        Ok(node)
+    }
+
+    /// The RPC client connected to the node.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// The RPC port the node listens on.
+    pub fn rpc_port(&self) -> u16 {
+        self.rpc_port
+    }
+
+    /// Return a new address of the node wallet.
+    pub fn new_address(&self) -> Res<Address> {
+        Ok(self.client.get_new_address(None, None)?)
+    }
+
+    /// Mine `blocks` blocks, rewards are paid to the node wallet.
+    pub fn mine(&self, blocks: u64) -> Res<Vec<BlockHash>> {
+        let address = self.new_address()?;
+        Ok(self.client.generate_to_address(blocks, &address)?)
+    }
+
+    /// Return the current chain height.
+    pub fn height(&self) -> Res<u64> {
+        Ok(self.client.get_block_count()?)
+    }
+
+    /// Send `amount` from the node wallet to `address` and return the unconfirmed transaction.
+    pub fn fund(&self, address: &Address, amount: Amount) -> Res<Transaction> {
+        let txid = self
+            .client
+            .send_to_address(address, amount, None, None, None, None, None, None)?;
+        self.transaction(&txid)
+    }
+
+    /// Broadcast a fully signed transaction.
+    pub fn broadcast(&self, tx: &Transaction) -> Res<Txid> {
+        Ok(self.client.send_raw_transaction(tx)?)
+    }
+
+    /// Return a transaction known by the node, confirmed or in the mempool.
+    pub fn transaction(&self, txid: &Txid) -> Res<Transaction> {
+        Ok(self.client.get_raw_transaction(txid, None)?)
+    }
+
+    /// Return the number of confirmations of a transaction, zero if it is only in the mempool.
+    pub fn confirmations(&self, txid: &Txid) -> Res<u32> {
+        Ok(self
+            .client
+            .get_raw_transaction_info(txid, None)?
+            .confirmations
+            .unwrap_or(0))
+    }
+}
+
+impl Drop for Bitcoind {
+    fn drop(&mut self) {
+        let _ = self.client.stop();
+        if self.process.try_wait().ok().flatten().is_none() {
+            thread::sleep(Duration::from_millis(500));
+            let _ = self.process.kill();
+        }
+        let _ = self.process.wait();
+        let _ = std::fs::remove_dir_all(&self.datadir);
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Regtest harness for [`farcaster_core`]. Spins up throw-away `bitcoind` and, optionally,
+//! `monerod` nodes in regtest, funds addresses, mines blocks on demand, and drives the
+//! arbitrating transactions of a swap through the public API of the core library. The protocol
+//! branches can also be run without nodes against a mock backend, see [`scenario`].
+//!
+//! Node binaries are looked up in `PATH` or with the `BITCOIND_EXE`, `MONEROD_EXE` and
+//! `MONERO_WALLET_RPC_EXE` environment variables. The end to end swap tests of the core library
+//! run against the nodes from the root of the repository with:
+//!
+//! ```text
+//! cargo test --features rpc-tests
+//! ```
+//!
+//! The tests requiring Monero are ignored by default and fail if the Monero binaries are missing
+//! when run with `-- --include-ignored`.
+
+use thiserror::Error;
+
+pub mod bitcoind;
+pub mod monerod;
+pub mod scenario;
+pub mod swap;
+
+pub use bitcoind::Bitcoind;
+pub use monerod::{MoneroWallet, Monerod};
+pub use scenario::Scenario;
+pub use swap::{SignedTransactions, SwapSetup};
+
+/// Errors returned by the harness.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The node binary cannot be found or started.
+    #[error("Cannot start node: {0}")]
+    Spawn(std::io::Error),
+    /// The node did not become ready in time.
+    #[error("Node did not start in time")]
+    Timeout,
+    /// An I/O error while talking to a node.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// A Bitcoin Core RPC error.
+    #[error("Bitcoin RPC error: {0}")]
+    BitcoinRpc(#[from] bitcoincore_rpc::Error),
+    /// A Monero daemon RPC error.
+    #[error("Monero RPC error: {0}")]
+    MoneroRpc(String),
+    /// An error from the core library.
+    #[error("Farcaster error: {0}")]
+    Farcaster(#[from] farcaster_core::Error),
+    /// The swap did not reach the expected on-chain state.
+    #[error("Unexpected swap state: {0}")]
+    Swap(String),
+}
+
+impl From<farcaster_core::transaction::Error> for Error {
+    fn from(e: farcaster_core::transaction::Error) -> Self {
+        Self::Farcaster(e.into())
+    }
+}
+
+impl From<farcaster_core::blockchain::FeeStrategyError> for Error {
+    fn from(e: farcaster_core::blockchain::FeeStrategyError) -> Self {
+        Self::Farcaster(e.into())
+    }
+}
+
+/// Result type of the harness.
+pub type Res<T> = Result<T, Error>;
+
+/// Return a fresh unique directory under the system temporary directory.
+pub(crate) fn temp_dir(prefix: &str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "{}-{}-{}",
+        prefix,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ))
+}
+
+/// Return a free local TCP port.
+pub(crate) fn free_port() -> Res<u16> {
+    Ok(std::net::TcpListener::bind("127.0.0.1:0")?
+        .local_addr()?
+        .port())
+}
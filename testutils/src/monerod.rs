@@ -0,0 +1,273 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! A throw-away `monerod` node in regtest and a `monero-wallet-rpc` wallet connected to it.
+//! Monero is optional in the harness, when both binaries are available the accordant lock of a
+//! swap is sent from the wallet to the swap address and verified with the view key, see
+//! [`crate::swap::AccordantSetup`].
+
+use std::env;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bitcoin::hashes::hex::FromHex;
+use serde_json::{json, Value};
+
+use crate::{free_port, temp_dir, Error, Res};
+
+/// Maximum time to wait for the node to accept RPC calls.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Confirmations required before coinbase outputs can be spent.
+const COINBASE_MATURITY: u64 = 60;
+
+/// A running `monerod` regtest node, killed and cleaned up when dropped.
+pub struct Monerod {
+    process: Child,
+    datadir: PathBuf,
+    rpc_port: u16,
+}
+
+impl Monerod {
+    /// Start a new offline node with a fixed difficulty. The binary is taken from `MONEROD_EXE`
+    /// if set, otherwise from `PATH`.
+    pub fn spawn() -> Res<Self> {
+        let exe = env::var("MONEROD_EXE").unwrap_or_else(|_| "monerod".into());
+        let datadir = temp_dir("farcaster-monerod");
+        std::fs::create_dir_all(&datadir)?;
+        let rpc_port = free_port()?;
+        let p2p_port = free_port()?;
+
+        let process = Command::new(exe)
+            .arg("--regtest")
+            .arg("--offline")
+            .arg("--fixed-difficulty=1")
+            .arg("--non-interactive")
+            .arg("--no-igd")
+            .arg("--no-zmq")
+            .arg(format!("--data-dir={}", datadir.display()))
+            .arg(format!("--rpc-bind-port={}", rpc_port))
+            .arg(format!("--p2p-bind-port={}", p2p_port))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(Error::Spawn)?;
+
+        let node = Self {
+            process,
+            datadir,
+            rpc_port,
+        };
+        let start = Instant::now();
+        while node.height().is_err() {
+            if start.elapsed() > STARTUP_TIMEOUT {
+                return Err(Error::Timeout);
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+        Ok(node)
+    }
+
+    /// The RPC port the node listens on.
+    pub fn rpc_port(&self) -> u16 {
+        self.rpc_port
+    }
+
+    /// Return the current chain height.
+    pub fn height(&self) -> Res<u64> {
+        self.call("get_block_count", json!({}))?["count"]
+            .as_u64()
+            .ok_or_else(|| Error::MoneroRpc("missing block count".into()))
+    }
+
+    /// Mine `blocks` blocks, rewards are paid to `address`.
+    pub fn mine(&self, blocks: u64, address: &monero::Address) -> Res<u64> {
+        self.call(
+            "generateblocks",
+            json!({ "amount_of_blocks": blocks, "wallet_address": address.to_string() }),
+        )?["height"]
+            .as_u64()
+            .ok_or_else(|| Error::MoneroRpc("missing height".into()))
+    }
+
+    /// Return the transaction `hash` from the pool or the chain.
+    pub fn transaction(&self, hash: &monero::Hash) -> Res<monero::Transaction> {
+        let blob = post(
+            self.rpc_port,
+            "/get_transactions",
+            json!({ "txs_hashes": [format!("{:x}", hash)] }),
+        )?["txs"][0]["as_hex"]
+            .as_str()
+            .map(Vec::<u8>::from_hex)
+            .ok_or_else(|| Error::MoneroRpc(format!("transaction {:x} not found", hash)))?
+            .map_err(|e| Error::MoneroRpc(e.to_string()))?;
+        monero::consensus::deserialize(&blob).map_err(|e| Error::MoneroRpc(e.to_string()))
+    }
+
+    /// Return the coinbase transaction of the block at `height`.
+    pub fn miner_transaction(&self, height: u64) -> Res<monero::Transaction> {
+        let blob = self.call("get_block", json!({ "height": height }))?["blob"]
+            .as_str()
+            .map(Vec::<u8>::from_hex)
+            .ok_or_else(|| Error::MoneroRpc("missing block blob".into()))?
+            .map_err(|e| Error::MoneroRpc(e.to_string()))?;
+        let block: monero::Block =
+            monero::consensus::deserialize(&blob).map_err(|e| Error::MoneroRpc(e.to_string()))?;
+        Ok(block.miner_tx)
+    }
+
+    /// Issue a JSON-RPC call on the daemon and return its result.
+    fn call(&self, method: &str, params: Value) -> Res<Value> {
+        json_rpc(self.rpc_port, method, params)
+    }
+}
+
+impl Drop for Monerod {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = std::fs::remove_dir_all(&self.datadir);
+    }
+}
+
+/// A running `monero-wallet-rpc` wallet connected to a [`Monerod`] node, killed and cleaned up
+/// when dropped.
+pub struct MoneroWallet {
+    process: Child,
+    wallet_dir: PathBuf,
+    rpc_port: u16,
+}
+
+impl MoneroWallet {
+    /// Start a wallet RPC server on `node` and create a new wallet. The binary is taken from
+    /// `MONERO_WALLET_RPC_EXE` if set, otherwise from `PATH`.
+    pub fn spawn(node: &Monerod) -> Res<Self> {
+        let exe = env::var("MONERO_WALLET_RPC_EXE").unwrap_or_else(|_| "monero-wallet-rpc".into());
+        let wallet_dir = temp_dir("farcaster-monero-wallet");
+        std::fs::create_dir_all(&wallet_dir)?;
+        let rpc_port = free_port()?;
+
+        let process = Command::new(exe)
+            .arg(format!("--daemon-address=127.0.0.1:{}", node.rpc_port()))
+            .arg("--trusted-daemon")
+            .arg("--allow-mismatched-daemon-version")
+            .arg("--disable-rpc-login")
+            .arg("--non-interactive")
+            .arg(format!("--wallet-dir={}", wallet_dir.display()))
+            .arg(format!("--rpc-bind-port={}", rpc_port))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(Error::Spawn)?;
+
+        let wallet = Self {
+            process,
+            wallet_dir,
+            rpc_port,
+        };
+        let start = Instant::now();
+        while wallet.call("get_version", json!({})).is_err() {
+            if start.elapsed() > STARTUP_TIMEOUT {
+                return Err(Error::Timeout);
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+        wallet.call(
+            "create_wallet",
+            json!({ "filename": "farcaster", "language": "English" }),
+        )?;
+        Ok(wallet)
+    }
+
+    /// Return the primary address of the wallet.
+    pub fn address(&self) -> Res<monero::Address> {
+        self.call("get_address", json!({ "account_index": 0 }))?["address"]
+            .as_str()
+            .ok_or_else(|| Error::MoneroRpc("missing address".into()))?
+            .parse()
+            .map_err(|e: monero::util::address::Error| Error::MoneroRpc(e.to_string()))
+    }
+
+    /// Mine blocks paying the wallet until the first reward is spendable, and refresh the wallet.
+    pub fn fund(&self, node: &Monerod) -> Res<()> {
+        node.mine(COINBASE_MATURITY + 1, &self.address()?)?;
+        self.call("refresh", json!({}))?;
+        Ok(())
+    }
+
+    /// Send `amount` atomic units to `address` and return the hash of the transaction.
+    pub fn transfer(&self, address: &monero::Address, amount: u64) -> Res<monero::Hash> {
+        self.call(
+            "transfer",
+            json!({ "destinations": [{ "amount": amount, "address": address.to_string() }] }),
+        )?["tx_hash"]
+            .as_str()
+            .map(<[u8; 32]>::from_hex)
+            .ok_or_else(|| Error::MoneroRpc("missing transaction hash".into()))?
+            .map(monero::Hash)
+            .map_err(|e| Error::MoneroRpc(e.to_string()))
+    }
+
+    /// Issue a JSON-RPC call on the wallet and return its result.
+    fn call(&self, method: &str, params: Value) -> Res<Value> {
+        json_rpc(self.rpc_port, method, params)
+    }
+}
+
+impl Drop for MoneroWallet {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = std::fs::remove_dir_all(&self.wallet_dir);
+    }
+}
+
+/// Issue a JSON-RPC call on the server listening on `port` and return its result.
+fn json_rpc(port: u16, method: &str, params: Value) -> Res<Value> {
+    let mut value = post(
+        port,
+        "/json_rpc",
+        json!({ "jsonrpc": "2.0", "id": "0", "method": method, "params": params }),
+    )?;
+    if let Some(error) = value.get("error") {
+        return Err(Error::MoneroRpc(error.to_string()));
+    }
+    Ok(value["result"].take())
+}
+
+/// Post `body` on the `path` endpoint of the server listening on `port` and return the response.
+fn post(port: u16, path: &str, body: Value) -> Res<Value> {
+    let body = body.to_string();
+    let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        body.len(),
+        body
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let payload = response
+        .split_once("\r\n\r\n")
+        .map(|(_, payload)| payload)
+        .ok_or_else(|| Error::MoneroRpc("malformed HTTP response".into()))?;
+    serde_json::from_str(payload).map_err(|e| Error::MoneroRpc(e.to_string()))
+}
@@ -0,0 +1,291 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Deterministic end to end scenarios of the arbitrating transaction graph run against the
+//! in-memory [`MockSyncer`], no node required. Each [`Scenario`] follows one protocol branch,
+//! enforces the relative timelocks and the spending rules of the transactions it broadcasts, and
+//! reports the final balances of the participants.
+//!
+//! ```
+//! use farcaster_core_testutils::scenario::Scenario;
+//!
+//! let report = Scenario::alice_never_buys().run().unwrap();
+//! assert!(report.alice.as_sat() == 0 && report.bob.as_sat() > 0);
+//! ```
+
+use std::collections::HashMap;
+
+use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+use bitcoin::{Address, Amount, Script, Transaction, Txid, Witness};
+
+use farcaster_core::syncer::mock::MockSyncer;
+use farcaster_core::syncer::Syncer;
+use farcaster_core::transaction::TxLabel;
+
+use crate::swap::{Participant, Payouts, SignedTransactions, SwapSetup};
+use crate::{Error, Res};
+
+/// The final protocol branch reached by a scenario.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Alice bought the locked funds.
+    Bought,
+    /// Bob got refunded after the cancel.
+    Refunded,
+    /// Alice punished Bob after the cancel.
+    Punished,
+}
+
+/// The final state of a scenario.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// The branch reached.
+    pub outcome: Outcome,
+    /// The funds received by Alice.
+    pub alice: Amount,
+    /// The funds received by Bob.
+    pub bob: Amount,
+    /// The fees paid by all the broadcast transactions.
+    pub fees: Amount,
+    /// The backend at the end of the scenario.
+    pub syncer: MockSyncer,
+}
+
+/// A protocol branch run end to end against the mock backend.
+#[derive(Debug, Clone, Copy)]
+pub struct Scenario {
+    setup: SwapSetup,
+    outcome: Outcome,
+}
+
+impl Scenario {
+    /// Bob locks, Alice buys.
+    pub fn happy_path() -> Self {
+        Self::new(Outcome::Bought)
+    }
+
+    /// Bob locks and goes offline: the cancel is broadcast once the cancel timelock expires and,
+    /// since Bob never refunds, Alice punishes once the punish timelock expires.
+    pub fn bob_aborts_after_lock() -> Self {
+        Self::new(Outcome::Punished)
+    }
+
+    /// Bob locks and Alice never buys: Bob cancels once the cancel timelock expires and refunds.
+    pub fn alice_never_buys() -> Self {
+        Self::new(Outcome::Refunded)
+    }
+
+    fn new(outcome: Outcome) -> Self {
+        Self {
+            setup: SwapSetup::default(),
+            outcome,
+        }
+    }
+
+    /// Run the scenario with other swap values.
+    pub fn with_setup(mut self, setup: SwapSetup) -> Self {
+        self.setup = setup;
+        self
+    }
+
+    /// Create and sign the transactions, drive them on a fresh backend, and check that the funds
+    /// are conserved.
+    pub fn run(&self) -> Res<Report> {
+        let alice = address(&Participant::random());
+        let bob = address(&Participant::random());
+        let payouts = Payouts {
+            buy: alice.clone(),
+            refund: bob.clone(),
+            punish: alice.clone(),
+        };
+        let amount = self.setup.amount;
+        let txs = SignedTransactions::prepare_with(self.setup, payouts, |funding| {
+            Ok(funding_transaction(funding, amount))
+        })?;
+
+        let mut chain = MockChain::new(&txs.funding);
+        chain.broadcast(TxLabel::Lock, &txs.lock)?;
+        chain.syncer.mine(1);
+        match self.outcome {
+            Outcome::Bought => {
+                chain.broadcast(TxLabel::Buy, &txs.buy)?;
+            }
+            Outcome::Refunded | Outcome::Punished => {
+                // The cancel is not valid before the cancel timelock expires
+                expect_rejected(chain.broadcast(TxLabel::Cancel, &txs.cancel))?;
+                chain.mine_until_mature(&txs.cancel)?;
+                chain.broadcast(TxLabel::Cancel, &txs.cancel)?;
+                chain.syncer.mine(1);
+                if self.outcome == Outcome::Refunded {
+                    chain.broadcast(TxLabel::Refund, &txs.refund)?;
+                } else {
+                    expect_rejected(chain.broadcast(TxLabel::Punish, &txs.punish))?;
+                    chain.mine_until_mature(&txs.punish)?;
+                    chain.broadcast(TxLabel::Punish, &txs.punish)?;
+                }
+            }
+        }
+        chain.syncer.mine(1);
+
+        let report = Report {
+            outcome: self.outcome,
+            alice: chain.balance(&alice.script_pubkey()),
+            bob: chain.balance(&bob.script_pubkey()),
+            fees: chain.fees,
+            syncer: chain.syncer,
+        };
+        if report.alice + report.bob + report.fees != amount {
+            return Err(Error::Swap(format!(
+                "funds are not conserved: {} + {} + {} fees != {}",
+                report.alice, report.bob, report.fees, amount
+            )));
+        }
+        Ok(report)
+    }
+}
+
+fn address(participant: &Participant) -> Address {
+    Address::p2wpkh(
+        &bitcoin::PublicKey::new(participant.public),
+        bitcoin::Network::Regtest,
+    )
+    .expect("compressed key")
+}
+
+/// Create a transaction paying `amount` to `address` from an outpoint unknown to the backend.
+fn funding_transaction(address: &Address, amount: Amount) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::default(), 0),
+            script_sig: Script::new(),
+            sequence: u32::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: amount.as_sat(),
+            script_pubkey: address.script_pubkey(),
+        }],
+    }
+}
+
+fn expect_rejected(res: Res<()>) -> Res<()> {
+    match res {
+        Err(_) => Ok(()),
+        Ok(()) => Err(Error::Swap("premature transaction accepted".into())),
+    }
+}
+
+/// Mock backend with a set of unspent outputs, broadcast transactions must spend existing
+/// outputs once their relative timelock expired.
+struct MockChain {
+    syncer: MockSyncer,
+    utxos: HashMap<OutPoint, TxOut>,
+    fees: Amount,
+}
+
+impl MockChain {
+    fn new(funding: &Transaction) -> Self {
+        let mut syncer = MockSyncer::new();
+        syncer.inject(funding.clone());
+        syncer.mine(1);
+        let mut chain = Self {
+            syncer,
+            utxos: HashMap::new(),
+            fees: Amount::ZERO,
+        };
+        chain.add_outputs(funding);
+        chain
+    }
+
+    fn add_outputs(&mut self, tx: &Transaction) {
+        let txid = tx.txid();
+        for (vout, output) in tx.output.iter().enumerate() {
+            self.utxos
+                .insert(OutPoint::new(txid, vout as u32), output.clone());
+        }
+    }
+
+    /// Return the number of blocks left before the relative timelocks of `tx` expire.
+    fn blocks_to_maturity(&self, tx: &Transaction) -> Res<u32> {
+        tx.input.iter().try_fold(0, |blocks, input| {
+            let confirmations = self
+                .syncer
+                .confirmations(&input.previous_output.txid)
+                .ok_or_else(|| Error::Swap(format!("unknown input {}", input.previous_output)))?;
+            // Relative timelocks in blocks, see BIP 68
+            let timelock = match input.sequence {
+                s if s & (1 << 31) == 0 && s & (1 << 22) == 0 => s & 0xffff,
+                _ => 0,
+            };
+            Ok(blocks.max(timelock.saturating_sub(confirmations)))
+        })
+    }
+
+    fn mine_until_mature(&mut self, tx: &Transaction) -> Res<()> {
+        let blocks = self.blocks_to_maturity(tx)?;
+        self.syncer.mine(blocks);
+        Ok(())
+    }
+
+    fn broadcast(&mut self, label: TxLabel, tx: &Transaction) -> Res<()> {
+        let inputs = tx
+            .input
+            .iter()
+            .map(|input| {
+                self.utxos
+                    .get(&input.previous_output)
+                    .map(|output| output.value)
+                    .ok_or_else(|| Error::Swap(format!("{} spends a missing output", label)))
+            })
+            .collect::<Res<Vec<u64>>>()?;
+        if self.blocks_to_maturity(tx)? > 0 {
+            return Err(Error::Swap(format!("{} is not final", label)));
+        }
+        let fee = inputs
+            .iter()
+            .sum::<u64>()
+            .checked_sub(tx.output.iter().map(|o| o.value).sum())
+            .ok_or_else(|| Error::Swap(format!("{} spends more than its inputs", label)))?;
+
+        let txid = self
+            .syncer
+            .broadcast(tx.clone())
+            .map_err(|e| Error::Swap(e.to_string()))?;
+        self.syncer.watch(label, txid);
+        for input in &tx.input {
+            self.utxos.remove(&input.previous_output);
+        }
+        self.add_outputs(tx);
+        self.fees += Amount::from_sat(fee);
+        Ok(())
+    }
+
+    /// Sum the unspent confirmed outputs paying to `script`.
+    fn balance(&self, script: &Script) -> Amount {
+        Amount::from_sat(
+            self.utxos
+                .iter()
+                .filter(|(outpoint, output)| {
+                    &output.script_pubkey == script
+                        && self.syncer.confirmations(&outpoint.txid).unwrap_or(0) > 0
+                })
+                .map(|(_, output)| output.value)
+                .sum(),
+        )
+    }
+}
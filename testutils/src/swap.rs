@@ -0,0 +1,310 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Drive the arbitrating transactions of a swap on a regtest [`Bitcoind`] node. A swap is
+//! prepared with [`SignedTransactions::prepare`], which funds and fully signs all the
+//! transactions, and then executed along one of the protocol paths. When a [`Monerod`] node and a
+//! [`MoneroWallet`] are available the accordant lock is sent and verified with
+//! [`AccordantSetup::lock`].
+
+use bitcoin::secp256k1::rand::thread_rng;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey, SignOnly};
+use bitcoin::{Address, Amount, Transaction};
+
+use farcaster_core::bitcoin::fee::SatPerKvB;
+use farcaster_core::bitcoin::segwitv0::{
+    sign_hash, BuyTx, CancelTx, FundingTx, LockTx, PunishTx, RefundTx,
+};
+use farcaster_core::bitcoin::timelock::CSVTimelock;
+use farcaster_core::blockchain::{AccordantAmount, Fee, FeePriority, FeeStrategy, Network};
+use farcaster_core::script::{DataLock, DataPunishableLock, ScriptPath, SwapRoleKeys};
+use farcaster_core::transaction::{
+    Broadcastable, Buyable, Cancelable, Fundable, Lockable, Punishable, Refundable, Witnessable,
+};
+
+use crate::{Bitcoind, Error, MoneroWallet, Monerod, Res};
+
+/// A participant key pair used to sign the arbitrating transactions.
+#[derive(Debug, Clone, Copy)]
+pub struct Participant {
+    pub secret: SecretKey,
+    pub public: PublicKey,
+}
+
+impl Participant {
+    /// Generate a new random key pair.
+    pub fn random() -> Self {
+        let (secret, public) = Secp256k1::new().generate_keypair(&mut thread_rng());
+        Self { secret, public }
+    }
+}
+
+/// The negotiated values of the swap run on regtest.
+#[derive(Debug, Clone, Copy)]
+pub struct SwapSetup {
+    /// Amount sent to the funding address.
+    pub amount: Amount,
+    /// The cancel timelock, in blocks.
+    pub cancel_timelock: u32,
+    /// The punish timelock, in blocks.
+    pub punish_timelock: u32,
+    /// The fee strategy applied on all transactions but the lock.
+    pub fee_strategy: FeeStrategy<SatPerKvB>,
+}
+
+impl Default for SwapSetup {
+    fn default() -> Self {
+        Self {
+            amount: Amount::from_btc(1.0).expect("valid amount"),
+            cancel_timelock: 10,
+            punish_timelock: 10,
+            fee_strategy: FeeStrategy::Fixed(SatPerKvB::from_sat(1500)),
+        }
+    }
+}
+
+/// Fee paid by the lock transaction, the lock consumes the funding output.
+const LOCK_FEE_SAT: u64 = 1000;
+
+fn sign<T>(
+    secp: &Secp256k1<SignOnly>,
+    tx: &mut T,
+    path: ScriptPath,
+    signers: &[Participant],
+) -> Res<()>
+where
+    T: Witnessable<bitcoin::hashes::sha256d::Hash, PublicKey, bitcoin::secp256k1::ecdsa::Signature>,
+{
+    for signer in signers {
+        let msg = tx.generate_witness_message(path)?;
+        let sig = sign_hash(secp, msg, &signer.secret).map_err(|e| Error::Swap(e.to_string()))?;
+        tx.add_witness(signer.public, sig)?;
+    }
+    Ok(())
+}
+
+fn extract<T>(tx: &mut T) -> Res<Transaction>
+where
+    T: Broadcastable<Transaction>,
+{
+    Ok(tx.finalize_and_extract()?)
+}
+
+fn apply_fee<T>(tx: &mut T, strategy: &FeeStrategy<SatPerKvB>) -> Res<()>
+where
+    T: Fee<FeeUnit = SatPerKvB>,
+{
+    tx.set_fee(strategy, FeePriority::Low)?;
+    Ok(())
+}
+
+/// The fully signed arbitrating transactions of a swap, ready to be broadcast.
+#[derive(Debug, Clone)]
+pub struct SignedTransactions {
+    pub funding: Transaction,
+    pub lock: Transaction,
+    pub cancel: Transaction,
+    pub refund: Transaction,
+    pub buy: Transaction,
+    pub punish: Transaction,
+    /// The setup used to create the transactions.
+    pub setup: SwapSetup,
+}
+
+/// The addresses receiving the funds at the end of each protocol path.
+#[derive(Debug, Clone)]
+pub struct Payouts {
+    /// The address of Alice receiving the buy output.
+    pub buy: Address,
+    /// The address of Bob receiving the refund output.
+    pub refund: Address,
+    /// The address of Alice receiving the punish output.
+    pub punish: Address,
+}
+
+impl SignedTransactions {
+    /// Fund a new swap from the node wallet and create all the arbitrating transactions, signed
+    /// by fresh Alice and Bob keys.
+    pub fn prepare(node: &Bitcoind, setup: SwapSetup) -> Res<Self> {
+        let payouts = Payouts {
+            buy: node.new_address()?,
+            refund: node.new_address()?,
+            punish: node.new_address()?,
+        };
+        Self::prepare_with(setup, payouts, |address| node.fund(address, setup.amount))
+    }
+
+    /// Create all the arbitrating transactions, signed by fresh Alice and Bob keys, paying to
+    /// `payouts`. The funding transaction paying `setup.amount` to the given funding address is
+    /// created by `fund`.
+    pub fn prepare_with<F>(setup: SwapSetup, payouts: Payouts, fund: F) -> Res<Self>
+    where
+        F: FnOnce(&Address) -> Res<Transaction>,
+    {
+        let alice = Participant::random();
+        let bob = Participant::random();
+
+        let mut funding = FundingTx::initialize(bob.public, Network::Local)?;
+        let funding_address = funding.get_address()?;
+        let funding_tx = fund(&funding_address)?;
+        funding.update(funding_tx.clone())?;
+
+        let data_lock = DataLock {
+            timelock: CSVTimelock::new(setup.cancel_timelock),
+            success: SwapRoleKeys::new(alice.public, bob.public),
+            failure: SwapRoleKeys::new(alice.public, bob.public),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(setup.punish_timelock),
+            success: SwapRoleKeys::new(alice.public, bob.public),
+            failure: alice.public,
+            recovery: None,
+        };
+
+        let lock_amount = setup
+            .amount
+            .checked_sub(Amount::from_sat(LOCK_FEE_SAT))
+            .ok_or_else(|| Error::Swap("amount does not cover the lock fee".into()))?;
+        let mut lock = LockTx::initialize(&funding, data_lock, lock_amount)?;
+
+        let mut cancel = CancelTx::initialize(&lock, data_lock, punish_lock)?;
+        apply_fee(&mut cancel, &setup.fee_strategy)?;
+
+        let mut refund = RefundTx::initialize(&cancel, payouts.refund)?;
+        apply_fee(&mut refund, &setup.fee_strategy)?;
+
+        let mut buy = BuyTx::initialize(&lock, data_lock, payouts.buy)?;
+        apply_fee(&mut buy, &setup.fee_strategy)?;
+
+        let mut punish = PunishTx::initialize(&cancel, punish_lock, payouts.punish)?;
+        apply_fee(&mut punish, &setup.fee_strategy)?;
+
+        let secp = Secp256k1::signing_only();
+        sign(&secp, &mut refund, ScriptPath::Success, &[alice, bob])?;
+        sign(&secp, &mut cancel, ScriptPath::Failure, &[alice, bob])?;
+        sign(&secp, &mut buy, ScriptPath::Success, &[alice, bob])?;
+        sign(&secp, &mut punish, ScriptPath::Failure, &[alice])?;
+        sign(&secp, &mut lock, ScriptPath::Success, &[bob])?;
+
+        Ok(Self {
+            funding: funding_tx,
+            lock: extract(&mut lock)?,
+            cancel: extract(&mut cancel)?,
+            refund: extract(&mut refund)?,
+            buy: extract(&mut buy)?,
+            punish: extract(&mut punish)?,
+            setup,
+        })
+    }
+
+    /// Broadcast the lock and the buy, the swap completes.
+    pub fn run_happy_path(&self, node: &Bitcoind) -> Res<()> {
+        node.broadcast(&self.lock)?;
+        node.mine(1)?;
+        node.broadcast(&self.buy)?;
+        node.mine(1)?;
+        expect_confirmed(node, &self.buy)
+    }
+
+    /// Broadcast the lock, the cancel once the cancel timelock expires, and the refund.
+    pub fn run_refund_path(&self, node: &Bitcoind) -> Res<()> {
+        node.broadcast(&self.lock)?;
+        node.mine(self.setup.cancel_timelock.into())?;
+        node.broadcast(&self.cancel)?;
+        node.mine(1)?;
+        node.broadcast(&self.refund)?;
+        node.mine(1)?;
+        expect_confirmed(node, &self.refund)
+    }
+
+    /// Broadcast the lock, the cancel once the cancel timelock expires, and the punish once the
+    /// punish timelock expires.
+    pub fn run_punish_path(&self, node: &Bitcoind) -> Res<()> {
+        node.broadcast(&self.lock)?;
+        node.mine(self.setup.cancel_timelock.into())?;
+        node.broadcast(&self.cancel)?;
+        node.mine(self.setup.punish_timelock.into())?;
+        node.broadcast(&self.punish)?;
+        node.mine(1)?;
+        expect_confirmed(node, &self.punish)
+    }
+}
+
+/// The accordant side of a swap: the spend key shares of Alice and Bob and the shared view
+/// key controlling the swap address.
+#[derive(Debug, Clone, Copy)]
+pub struct AccordantSetup {
+    pub alice_spend: monero::PrivateKey,
+    pub bob_spend: monero::PrivateKey,
+    pub view: monero::PrivateKey,
+}
+
+fn random_monero_key() -> monero::PrivateKey {
+    use bitcoin::secp256k1::rand::RngCore;
+    let mut bytes = [0u8; 32];
+    thread_rng().fill_bytes(&mut bytes);
+    // Below the group order, thus a canonical scalar
+    bytes[31] &= 0x0f;
+    monero::PrivateKey::from_slice(&bytes).expect("canonical scalar")
+}
+
+impl AccordantSetup {
+    /// Generate new random key shares and view key.
+    pub fn random() -> Self {
+        Self {
+            alice_spend: random_monero_key(),
+            bob_spend: random_monero_key(),
+            view: random_monero_key(),
+        }
+    }
+
+    /// The aggregated public spend key of the swap address.
+    pub fn spend(&self) -> monero::PublicKey {
+        monero::PublicKey::from_private_key(&(self.alice_spend + self.bob_spend))
+    }
+
+    /// The swap address, regtest nodes use mainnet addresses.
+    pub fn address(&self) -> monero::Address {
+        monero::Address::standard(
+            monero::Network::Mainnet,
+            self.spend(),
+            monero::PublicKey::from_private_key(&self.view),
+        )
+    }
+
+    /// Lock `amount` on the swap address from the funded `wallet`, mine the lock, and verify it
+    /// with the shared view key as the counterparty does before signing the buy. Return the
+    /// outputs of the lock.
+    pub fn lock(
+        &self,
+        node: &Monerod,
+        wallet: &MoneroWallet,
+        amount: AccordantAmount,
+    ) -> Res<Vec<farcaster_core::monero::LockOutput>> {
+        let tx_hash = wallet.transfer(&self.address(), amount.as_atomic_units())?;
+        node.mine(1, &wallet.address()?)?;
+        let tx = node.transaction(&tx_hash)?;
+        farcaster_core::monero::verify_lock(&[tx], self.view, self.spend(), amount)
+            .map_err(|e| Error::Swap(e.to_string()))
+    }
+}
+
+fn expect_confirmed(node: &Bitcoind, tx: &Transaction) -> Res<()> {
+    match node.confirmations(&tx.txid())? {
+        0 => Err(Error::Swap(format!("{} is not confirmed", tx.txid()))),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,44 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+use farcaster_core::syncer::Syncer;
+use farcaster_core_testutils::scenario::{Outcome, Scenario};
+
+#[test]
+fn happy_path_pays_alice() {
+    let report = Scenario::happy_path().run().unwrap();
+    assert_eq!(report.outcome, Outcome::Bought);
+    assert!(report.alice.as_sat() > 0);
+    assert_eq!(report.bob.as_sat(), 0);
+    // Funding, lock, and buy
+    assert_eq!(report.syncer.height(), 3);
+}
+
+#[test]
+fn bob_aborts_after_lock_is_punished() {
+    let report = Scenario::bob_aborts_after_lock().run().unwrap();
+    assert_eq!(report.outcome, Outcome::Punished);
+    assert!(report.alice.as_sat() > 0);
+    assert_eq!(report.bob.as_sat(), 0);
+}
+
+#[test]
+fn alice_never_buys_refunds_bob() {
+    let report = Scenario::alice_never_buys().run().unwrap();
+    assert_eq!(report.outcome, Outcome::Refunded);
+    assert_eq!(report.alice.as_sat(), 0);
+    assert!(report.bob.as_sat() > 0);
+}